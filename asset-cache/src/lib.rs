@@ -0,0 +1,150 @@
+use std::path::{Path, PathBuf};
+
+/// Directory under the platform cache directory holding the artifacts of every viewer.
+const CACHE_DIR: &str = "silly-cat-engine";
+
+const FNV_OFFSET_BASIS: u128 = 0x6c62272e07bb014262b821756295c58d;
+const FNV_PRIME: u128 = 0x0000000001000000000000000000013b;
+
+/// Names an artifact by what it was made from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Key(u128);
+
+impl std::fmt::Display for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+/// Hashes the inputs of an artifact into its `Key`, with 128 bit FNV-1a. Fast and good enough to
+/// tell assets apart, not to resist collisions made on purpose.
+pub struct KeyBuilder {
+    hash: u128,
+}
+
+impl KeyBuilder {
+    /// `kind` names the artifact and how it is made, like `texture-rgba8-v1`. Change it when the
+    /// processing changes, so artifacts made the old way are no longer found.
+    pub fn new(kind: &str) -> Self {
+        Self {
+            hash: FNV_OFFSET_BASIS,
+        }
+        .bytes(kind.as_bytes())
+    }
+
+    pub fn bytes(mut self, bytes: &[u8]) -> Self {
+        // The length keeps ["ab", "c"] and ["a", "bc"] apart.
+        for byte in (bytes.len() as u64).to_le_bytes().iter().chain(bytes) {
+            self.hash ^= *byte as u128;
+            self.hash = self.hash.wrapping_mul(FNV_PRIME);
+        }
+        self
+    }
+
+    /// Hashes the content of the file at `path`, not its name or modification time.
+    pub fn file<P: AsRef<Path>>(self, path: P) -> std::io::Result<Self> {
+        Ok(self.bytes(&std::fs::read(path)?))
+    }
+
+    pub fn finish(&self) -> Key {
+        Key(self.hash)
+    }
+}
+
+/// Processed assets, like decoded textures, stored under the hash of their sources so later runs
+/// load them instead of processing the sources again. An edited source gets a new key, artifacts
+/// of old versions stay until `clear`.
+pub struct AssetCache {
+    dir: PathBuf,
+}
+
+impl AssetCache {
+    pub fn open<P: Into<PathBuf>>(dir: P) -> std::io::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    /// `<cache dir>/silly-cat-engine`, `None` on platforms without a cache directory.
+    pub fn default_dir() -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| dir.join(CACHE_DIR))
+    }
+
+    /// The cache in `default_dir`, `None` if there is none or it can't be created.
+    pub fn open_default() -> Option<Self> {
+        let dir = Self::default_dir()?;
+        match Self::open(&dir) {
+            Ok(cache) => Some(cache),
+            Err(e) => {
+                log::warn!("failed to open asset cache {}: {}", dir.display(), e);
+                None
+            }
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Artifacts are spread over 256 directories by the first byte of their key.
+    fn path(&self, key: Key) -> PathBuf {
+        let name = key.to_string();
+        self.dir.join(&name[..2]).join(name)
+    }
+
+    pub fn get(&self, key: Key) -> Option<Vec<u8>> {
+        match std::fs::read(self.path(key)) {
+            Ok(data) => Some(data),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => {
+                log::warn!("failed to read cached {}: {}", key, e);
+                None
+            }
+        }
+    }
+
+    /// Stores `data` under `key`. Written to a temporary file first, so other processes never
+    /// read half an artifact.
+    pub fn put(&self, key: Key, data: &[u8]) -> std::io::Result<()> {
+        let path = self.path(key);
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let temporary = path.with_extension(format!("tmp-{}", std::process::id()));
+        std::fs::write(&temporary, data)?;
+        std::fs::rename(&temporary, &path)
+    }
+
+    /// The artifact under `key`, made by `make` and stored if there is none. A failure to store
+    /// it is only logged.
+    pub fn get_or_insert_with<E, F>(&self, key: Key, make: F) -> Result<Vec<u8>, E>
+    where
+        F: FnOnce() -> Result<Vec<u8>, E>,
+    {
+        if let Some(data) = self.get(key) {
+            return Ok(data);
+        }
+        let data = make()?;
+        if let Err(e) = self.put(key, &data) {
+            log::warn!("failed to cache {}: {}", key, e);
+        }
+        Ok(data)
+    }
+
+    /// Total size of the artifacts in bytes.
+    pub fn size(&self) -> std::io::Result<u64> {
+        let mut size = 0;
+        for dir in std::fs::read_dir(&self.dir)? {
+            for entry in std::fs::read_dir(dir?.path())? {
+                size += entry?.metadata()?.len();
+            }
+        }
+        Ok(size)
+    }
+
+    /// Removes every artifact.
+    pub fn clear(&self) -> std::io::Result<()> {
+        for dir in std::fs::read_dir(&self.dir)? {
+            std::fs::remove_dir_all(dir?.path())?;
+        }
+        Ok(())
+    }
+}
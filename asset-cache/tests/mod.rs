@@ -0,0 +1,58 @@
+use std::path::PathBuf;
+
+use asset_cache::{AssetCache, KeyBuilder};
+
+fn temp_dir(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("silly-cat-engine-asset-cache-{}", name))
+}
+
+#[test]
+fn test_keys_depend_on_kind_and_content() {
+    let key = KeyBuilder::new("texture-v1").bytes(b"pixels").finish();
+    assert_eq!(key, KeyBuilder::new("texture-v1").bytes(b"pixels").finish());
+    assert_ne!(key, KeyBuilder::new("texture-v2").bytes(b"pixels").finish());
+    assert_ne!(key, KeyBuilder::new("texture-v1").bytes(b"pixel").finish());
+    assert_ne!(
+        KeyBuilder::new("").bytes(b"ab").bytes(b"c").finish(),
+        KeyBuilder::new("").bytes(b"a").bytes(b"bc").finish()
+    );
+}
+
+#[test]
+fn test_put_get_round_trip() {
+    let dir = temp_dir("round-trip");
+    let cache = AssetCache::open(&dir).unwrap();
+    let key = KeyBuilder::new("test").bytes(b"source").finish();
+    assert_eq!(cache.get(key), None);
+
+    cache.put(key, b"artifact").unwrap();
+    let data = cache.get(key);
+    let size = cache.size().unwrap();
+    cache.clear().unwrap();
+    let cleared = cache.get(key);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(data.as_deref(), Some(&b"artifact"[..]));
+    assert_eq!(size, 8);
+    assert_eq!(cleared, None);
+}
+
+#[test]
+fn test_get_or_insert_with_makes_once() {
+    let dir = temp_dir("get-or-insert");
+    let cache = AssetCache::open(&dir).unwrap();
+    let key = KeyBuilder::new("test").bytes(b"source").finish();
+    let mut made = 0;
+    for _ in 0..2 {
+        let data = cache
+            .get_or_insert_with(key, || -> Result<_, ()> {
+                made += 1;
+                Ok(b"artifact".to_vec())
+            })
+            .unwrap();
+        assert_eq!(data, b"artifact");
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(made, 1);
+}
@@ -0,0 +1,226 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Key, MouseButton};
+
+/// Something a key or mouse button does. Held actions last while their binding is down, the
+/// others happen when it is pressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    MoveDown,
+    MoveUp,
+    RollLeft,
+    RollRight,
+    /// Held, multiplies the move speed by the sprint multiplier.
+    Sprint,
+    /// Held, mouse motion turns the camera.
+    Look,
+    /// Held, mouse motion pans an orbit camera.
+    Pan,
+    ApertureDown,
+    ApertureUp,
+    FocusNearer,
+    FocusFarther,
+    ExposureDown,
+    ExposureUp,
+    CycleBookmark,
+    /// Held, a digit key stores the bookmark instead of recalling it.
+    StoreBookmark,
+    /// Handled by the engines, the camera ignores it.
+    Screenshot,
+}
+
+impl Action {
+    pub const ALL: [Action; 20] = [
+        Action::MoveForward,
+        Action::MoveBackward,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::MoveDown,
+        Action::MoveUp,
+        Action::RollLeft,
+        Action::RollRight,
+        Action::Sprint,
+        Action::Look,
+        Action::Pan,
+        Action::ApertureDown,
+        Action::ApertureUp,
+        Action::FocusNearer,
+        Action::FocusFarther,
+        Action::ExposureDown,
+        Action::ExposureUp,
+        Action::CycleBookmark,
+        Action::StoreBookmark,
+        Action::Screenshot,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::MoveForward => "Move Forward",
+            Action::MoveBackward => "Move Backward",
+            Action::MoveLeft => "Move Left",
+            Action::MoveRight => "Move Right",
+            Action::MoveDown => "Move Down",
+            Action::MoveUp => "Move Up",
+            Action::RollLeft => "Roll Left",
+            Action::RollRight => "Roll Right",
+            Action::Sprint => "Sprint",
+            Action::Look => "Look",
+            Action::Pan => "Pan",
+            Action::ApertureDown => "Aperture Down",
+            Action::ApertureUp => "Aperture Up",
+            Action::FocusNearer => "Focus Nearer",
+            Action::FocusFarther => "Focus Farther",
+            Action::ExposureDown => "Exposure Down",
+            Action::ExposureUp => "Exposure Up",
+            Action::CycleBookmark => "Cycle Bookmark",
+            Action::StoreBookmark => "Store Bookmark",
+            Action::Screenshot => "Screenshot",
+        }
+    }
+
+    /// Whether the action lasts while its binding is down, rather than happening once.
+    pub fn is_held(self) -> bool {
+        matches!(
+            self,
+            Action::MoveForward
+                | Action::MoveBackward
+                | Action::MoveLeft
+                | Action::MoveRight
+                | Action::MoveDown
+                | Action::MoveUp
+                | Action::RollLeft
+                | Action::RollRight
+                | Action::Sprint
+                | Action::Look
+                | Action::Pan
+                | Action::StoreBookmark
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Binding {
+    Key(Key),
+    Button(MouseButton),
+}
+
+impl std::fmt::Display for Binding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Binding::Key(Key::Digit(n)) => write!(f, "{}", n),
+            Binding::Key(Key::Function(n)) => write!(f, "F{}", n),
+            Binding::Key(key) => write!(f, "{:?}", key),
+            Binding::Button(button) => write!(f, "{:?} Mouse", button),
+        }
+    }
+}
+
+/// The bindings of every action. A binding triggers one action at most, the digit keys always
+/// recall and store bookmarks.
+///
+/// Saved with the viewer settings. Actions missing from a saved map keep their default bindings,
+/// so actions added later get bound.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(
+    from = "BTreeMap<Action, Vec<Binding>>",
+    into = "BTreeMap<Action, Vec<Binding>>"
+)]
+pub struct InputBindings {
+    bindings: BTreeMap<Action, Vec<Binding>>,
+}
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        let defaults = [
+            (Action::MoveForward, Binding::Key(Key::W)),
+            (Action::MoveBackward, Binding::Key(Key::S)),
+            (Action::MoveLeft, Binding::Key(Key::A)),
+            (Action::MoveRight, Binding::Key(Key::D)),
+            (Action::MoveDown, Binding::Key(Key::Q)),
+            (Action::MoveUp, Binding::Key(Key::E)),
+            (Action::RollLeft, Binding::Key(Key::Z)),
+            (Action::RollRight, Binding::Key(Key::C)),
+            (Action::Sprint, Binding::Key(Key::Shift)),
+            (Action::Look, Binding::Button(MouseButton::Right)),
+            (Action::Pan, Binding::Button(MouseButton::Middle)),
+            (Action::ApertureDown, Binding::Key(Key::LBracket)),
+            (Action::ApertureUp, Binding::Key(Key::RBracket)),
+            (Action::FocusNearer, Binding::Key(Key::Minus)),
+            (Action::FocusFarther, Binding::Key(Key::Equals)),
+            (Action::ExposureDown, Binding::Key(Key::Comma)),
+            (Action::ExposureUp, Binding::Key(Key::Period)),
+            (Action::CycleBookmark, Binding::Key(Key::Tab)),
+            (Action::StoreBookmark, Binding::Key(Key::Ctrl)),
+            (Action::Screenshot, Binding::Key(Key::Function(12))),
+        ];
+        Self {
+            bindings: defaults
+                .iter()
+                .map(|(action, binding)| (*action, vec![*binding]))
+                .collect(),
+        }
+    }
+}
+
+impl From<BTreeMap<Action, Vec<Binding>>> for InputBindings {
+    fn from(saved: BTreeMap<Action, Vec<Binding>>) -> Self {
+        let mut bindings = Self::default();
+        for (action, saved) in saved {
+            bindings.bindings.insert(action, Vec::new());
+            for binding in saved {
+                bindings.bind(action, binding);
+            }
+        }
+        bindings
+    }
+}
+
+impl From<InputBindings> for BTreeMap<Action, Vec<Binding>> {
+    fn from(bindings: InputBindings) -> Self {
+        bindings.bindings
+    }
+}
+
+impl InputBindings {
+    pub fn bindings(&self, action: Action) -> &[Binding] {
+        self.bindings
+            .get(&action)
+            .map_or(&[], |bindings| bindings.as_slice())
+    }
+
+    /// The action `binding` triggers.
+    pub fn action(&self, binding: Binding) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(_, bindings)| bindings.contains(&binding))
+            .map(|(action, _)| *action)
+    }
+
+    pub fn is_bound(&self, action: Action, binding: Binding) -> bool {
+        self.bindings(action).contains(&binding)
+    }
+
+    /// Adds `binding` to `action`, taking it from the action it triggered before. Digit keys
+    /// can't be bound.
+    pub fn bind(&mut self, action: Action, binding: Binding) {
+        if let Binding::Key(Key::Digit(_)) = binding {
+            return;
+        }
+        for bindings in self.bindings.values_mut() {
+            bindings.retain(|bound| *bound != binding);
+        }
+        self.bindings.entry(action).or_default().push(binding);
+    }
+
+    pub fn unbind(&mut self, action: Action, binding: Binding) {
+        if let Some(bindings) = self.bindings.get_mut(&action) {
+            bindings.retain(|bound| *bound != binding);
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use glam::{Mat4, Vec3, Vec4};
+
+/// A view frustum in world space.
+///
+/// Planes are stored as `(normal, d)` with normals pointing inwards, so a point `p` is inside a
+/// plane when `normal.dot(p) + d >= 0`. The order is left, right, bottom, top, near, far.
+#[derive(Debug, Clone, Copy)]
+pub struct Frustum {
+    pub planes: [Vec4; 6],
+    /// Near plane corners followed by far plane corners, each in the order
+    /// bottom left, bottom right, top left, top right.
+    pub corners: [Vec3; 8],
+}
+
+impl Frustum {
+    /// Extracts the frustum of a view-projection matrix with Vulkan's [0, 1] depth range.
+    pub fn from_view_projection(view_projection: Mat4) -> Self {
+        let m = view_projection.transpose();
+        let (r0, r1, r2, r3) = (m.x_axis, m.y_axis, m.z_axis, m.w_axis);
+        let normalize = |p: Vec4| p / p.truncate().length();
+        let planes = [
+            normalize(r3 + r0),
+            normalize(r3 - r0),
+            normalize(r3 + r1),
+            normalize(r3 - r1),
+            normalize(r2),
+            normalize(r3 - r2),
+        ];
+
+        let inverse = view_projection.inverse();
+        let mut corners = [Vec3::ZERO; 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            let x = if i & 1 == 0 { -1.0 } else { 1.0 };
+            let y = if i & 2 == 0 { -1.0 } else { 1.0 };
+            let z = if i & 4 == 0 { 0.0 } else { 1.0 };
+            let p = inverse * Vec4::new(x, y, z, 1.0);
+            *corner = p.truncate() / p.w;
+        }
+
+        Self { planes, corners }
+    }
+
+    pub fn contains_point(&self, point: Vec3) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.truncate().dot(point) + plane.w >= 0.0)
+    }
+
+    pub fn intersects_sphere(&self, center: Vec3, radius: f32) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.truncate().dot(center) + plane.w >= -radius)
+    }
+
+    /// Conservative test, may report boxes near frustum edges as visible.
+    pub fn intersects_aabb(&self, min: Vec3, max: Vec3) -> bool {
+        self.planes.iter().all(|plane| {
+            let normal = plane.truncate();
+            let positive = Vec3::new(
+                if normal.x >= 0.0 { max.x } else { min.x },
+                if normal.y >= 0.0 { max.y } else { min.y },
+                if normal.z >= 0.0 { max.z } else { min.z },
+            );
+            normal.dot(positive) + plane.w >= 0.0
+        })
+    }
+}
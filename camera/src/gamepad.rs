@@ -0,0 +1,63 @@
+use gilrs::{Axis, Button, GamepadId, Gilrs};
+
+const DEAD_ZONE: f32 = 0.15;
+
+/// Stick and trigger values of the most recently used gamepad, with the dead zone applied.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct GamepadState {
+    pub left_stick: (f32, f32),
+    pub right_stick: (f32, f32),
+    pub left_trigger: f32,
+    pub right_trigger: f32,
+}
+
+#[derive(Default)]
+pub(crate) struct Gamepad {
+    gilrs: Option<Gilrs>,
+    active: Option<GamepadId>,
+    initialized: bool,
+}
+
+impl std::fmt::Debug for Gamepad {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Gamepad")
+            .field("active", &self.active)
+            .finish()
+    }
+}
+
+impl Gamepad {
+    /// Drains pending gamepad events and returns the current state, if a gamepad is in use.
+    pub fn poll(&mut self) -> Option<GamepadState> {
+        if !self.initialized {
+            self.initialized = true;
+            self.gilrs = Gilrs::new().ok();
+        }
+        let gilrs = self.gilrs.as_mut()?;
+        while let Some(event) = gilrs.next_event() {
+            self.active = Some(event.id);
+        }
+        let gamepad = gilrs.connected_gamepad(self.active?)?;
+
+        let axis = |axis| dead_zone(gamepad.value(axis));
+        let trigger = |button| {
+            gamepad
+                .button_data(button)
+                .map_or(0.0, |data| dead_zone(data.value()))
+        };
+        Some(GamepadState {
+            left_stick: (axis(Axis::LeftStickX), axis(Axis::LeftStickY)),
+            right_stick: (axis(Axis::RightStickX), axis(Axis::RightStickY)),
+            left_trigger: trigger(Button::LeftTrigger2),
+            right_trigger: trigger(Button::RightTrigger2),
+        })
+    }
+}
+
+fn dead_zone(value: f32) -> f32 {
+    if value.abs() < DEAD_ZONE {
+        0.0
+    } else {
+        (value - DEAD_ZONE * value.signum()) / (1.0 - DEAD_ZONE)
+    }
+}
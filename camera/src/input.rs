@@ -0,0 +1,191 @@
+use serde::{Deserialize, Serialize};
+
+/// Window-system independent input understood by [`crate::Camera::handle_input`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CameraInput {
+    /// Raw relative mouse motion in pixels.
+    MouseDelta { x: f32, y: f32 },
+    Key { key: Key, pressed: bool },
+    /// Wheel movement in lines, positive when scrolling up.
+    Wheel { steps: f32 },
+    Button { button: MouseButton, pressed: bool },
+    Touch {
+        id: u64,
+        phase: TouchPhase,
+        location: (f32, f32),
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Key {
+    W,
+    A,
+    S,
+    D,
+    Q,
+    E,
+    Z,
+    C,
+    R,
+    F,
+    G,
+    V,
+    X,
+    Space,
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    Shift,
+    Ctrl,
+    Tab,
+    LBracket,
+    RBracket,
+    Minus,
+    Equals,
+    Comma,
+    Period,
+    /// A number key, 0 to 9.
+    Digit(u8),
+    /// A function key, 1 to 12.
+    Function(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TouchPhase {
+    Started,
+    Moved,
+    Ended,
+}
+
+impl CameraInput {
+    /// Translates the winit events the camera cares about.
+    pub fn from_winit(event: &winit::event::Event<()>) -> Option<Self> {
+        use winit::event::{DeviceEvent, ElementState, Event, MouseScrollDelta, WindowEvent};
+
+        match event {
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::MouseWheel { delta, .. } => {
+                    let steps = match delta {
+                        MouseScrollDelta::LineDelta(_, y) => *y,
+                        MouseScrollDelta::PixelDelta(p) => (p.y / 20.0) as f32,
+                    };
+                    Some(CameraInput::Wheel { steps })
+                }
+                WindowEvent::MouseInput { state, button, .. } => {
+                    let button = match button {
+                        winit::event::MouseButton::Left => MouseButton::Left,
+                        winit::event::MouseButton::Right => MouseButton::Right,
+                        winit::event::MouseButton::Middle => MouseButton::Middle,
+                        winit::event::MouseButton::Other(_) => return None,
+                    };
+                    Some(CameraInput::Button {
+                        button,
+                        pressed: *state == ElementState::Pressed,
+                    })
+                }
+                WindowEvent::Touch(touch) => {
+                    let phase = match touch.phase {
+                        winit::event::TouchPhase::Started => TouchPhase::Started,
+                        winit::event::TouchPhase::Moved => TouchPhase::Moved,
+                        winit::event::TouchPhase::Ended | winit::event::TouchPhase::Cancelled => {
+                            TouchPhase::Ended
+                        }
+                    };
+                    Some(CameraInput::Touch {
+                        id: touch.id,
+                        phase,
+                        location: (touch.location.x as f32, touch.location.y as f32),
+                    })
+                }
+                _ => None,
+            },
+            Event::DeviceEvent { event, .. } => match event {
+                DeviceEvent::MouseMotion { delta: (x, y) } => Some(CameraInput::MouseDelta {
+                    x: *x as f32,
+                    y: *y as f32,
+                }),
+                DeviceEvent::Key(input) => {
+                    let key = Key::from_winit(input.virtual_keycode?)?;
+                    Some(CameraInput::Key {
+                        key,
+                        pressed: input.state == ElementState::Pressed,
+                    })
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+impl Key {
+    pub fn from_winit(keycode: winit::event::VirtualKeyCode) -> Option<Self> {
+        use winit::event::VirtualKeyCode;
+
+        let key = match keycode {
+            VirtualKeyCode::W => Key::W,
+            VirtualKeyCode::A => Key::A,
+            VirtualKeyCode::S => Key::S,
+            VirtualKeyCode::D => Key::D,
+            VirtualKeyCode::Q => Key::Q,
+            VirtualKeyCode::E => Key::E,
+            VirtualKeyCode::Z => Key::Z,
+            VirtualKeyCode::C => Key::C,
+            VirtualKeyCode::R => Key::R,
+            VirtualKeyCode::F => Key::F,
+            VirtualKeyCode::G => Key::G,
+            VirtualKeyCode::V => Key::V,
+            VirtualKeyCode::X => Key::X,
+            VirtualKeyCode::Space => Key::Space,
+            VirtualKeyCode::Up => Key::Up,
+            VirtualKeyCode::Down => Key::Down,
+            VirtualKeyCode::Left => Key::Left,
+            VirtualKeyCode::Right => Key::Right,
+            VirtualKeyCode::PageUp => Key::PageUp,
+            VirtualKeyCode::PageDown => Key::PageDown,
+            VirtualKeyCode::LShift | VirtualKeyCode::RShift => Key::Shift,
+            VirtualKeyCode::LControl | VirtualKeyCode::RControl => Key::Ctrl,
+            VirtualKeyCode::Tab => Key::Tab,
+            VirtualKeyCode::LBracket => Key::LBracket,
+            VirtualKeyCode::RBracket => Key::RBracket,
+            VirtualKeyCode::Minus => Key::Minus,
+            VirtualKeyCode::Equals => Key::Equals,
+            VirtualKeyCode::Comma => Key::Comma,
+            VirtualKeyCode::Period => Key::Period,
+            VirtualKeyCode::Key0 => Key::Digit(0),
+            VirtualKeyCode::Key1 => Key::Digit(1),
+            VirtualKeyCode::Key2 => Key::Digit(2),
+            VirtualKeyCode::Key3 => Key::Digit(3),
+            VirtualKeyCode::Key4 => Key::Digit(4),
+            VirtualKeyCode::Key5 => Key::Digit(5),
+            VirtualKeyCode::Key6 => Key::Digit(6),
+            VirtualKeyCode::Key7 => Key::Digit(7),
+            VirtualKeyCode::Key8 => Key::Digit(8),
+            VirtualKeyCode::Key9 => Key::Digit(9),
+            VirtualKeyCode::F1 => Key::Function(1),
+            VirtualKeyCode::F2 => Key::Function(2),
+            VirtualKeyCode::F3 => Key::Function(3),
+            VirtualKeyCode::F4 => Key::Function(4),
+            VirtualKeyCode::F5 => Key::Function(5),
+            VirtualKeyCode::F6 => Key::Function(6),
+            VirtualKeyCode::F7 => Key::Function(7),
+            VirtualKeyCode::F8 => Key::Function(8),
+            VirtualKeyCode::F9 => Key::Function(9),
+            VirtualKeyCode::F10 => Key::Function(10),
+            VirtualKeyCode::F11 => Key::Function(11),
+            VirtualKeyCode::F12 => Key::Function(12),
+            _ => return None,
+        };
+        Some(key)
+    }
+}
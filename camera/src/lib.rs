@@ -1,5 +1,22 @@
+mod bindings;
+mod frustum;
+mod gamepad;
+mod input;
+mod path;
+mod state;
+
+use std::collections::BTreeSet;
+
 use bytemuck::{Pod, Zeroable};
+use glam::Mat4;
 use glam::Vec3A as Vec3;
+use serde::{Deserialize, Serialize};
+
+pub use bindings::{Action, Binding, InputBindings};
+pub use frustum::Frustum;
+pub use input::{CameraInput, Key, MouseButton, TouchPhase};
+pub use path::{CameraPath, Keyframe};
+pub use state::{CameraPresets, CameraState};
 
 #[derive(Debug, Default)]
 pub struct Camera {
@@ -7,23 +24,55 @@ pub struct Camera {
     front: Vec3,
     yaw: f32,
     pitch: f32,
+    roll: f32,
     world_up: Vec3,
     right: Vec3,
     up: Vec3,
-    right_button_pressed: bool,
+    mode: CameraMode,
+    target: Vec3,
+    distance: f32,
     camera_uniform: CameraUniform,
-    key_pressed: KeyPressed,
+    bindings: InputBindings,
+    /// Keys and buttons that are down.
+    held: BTreeSet<Binding>,
+    fov: f32,
+    aspect_ratio: f32,
+    z_near: f32,
+    z_far: f32,
+    fov_sensitivity: f32,
+    dolly_sensitivity: f32,
+    speed: f32,
+    sprint_multiplier: f32,
+    axis_speed_scale: Vec3,
+    bookmarks: Vec<Option<CameraState>>,
+    last_bookmark: usize,
+    transition: Option<Transition>,
+    transition_duration: f32,
+    aperture: f32,
+    focus_distance: f32,
+    exposure: f32,
+    gamepad: gamepad::Gamepad,
+    gamepad_state: gamepad::GamepadState,
+    gamepad_look_speed: f32,
+    touches: Vec<(u64, (f32, f32))>,
+    roll_speed: f32,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug)]
+struct Transition {
+    from: CameraState,
+    to: CameraState,
+    elapsed: f32,
+}
 
-struct KeyPressed {
-    w: bool,
-    s: bool,
-    a: bool,
-    d: bool,
-    q: bool,
-    e: bool,
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CameraMode {
+    /// Free flight with the move actions, WASD/QE by default, looking around while `Look` is
+    /// held.
+    #[default]
+    Fly,
+    /// Rotates around a target point. `Look` drag orbits, `Pan` drag pans and the wheel dollies.
+    Orbit,
 }
 
 enum Direction {
@@ -35,31 +84,112 @@ enum Direction {
     Down,
 }
 
+/// Camera data laid out for both std140 and std430 uniform blocks.
 #[repr(C)]
 #[derive(Copy, Clone, Default, Debug, Pod, Zeroable)]
 pub struct CameraUniform {
+    pub view: Mat4,
+    pub projection: Mat4,
+    pub inverse_view_projection: Mat4,
     pub origin: glam::Vec3,
+    /// Vertical field of view in degrees.
+    pub fov: f32,
+    /// Lens radius in world units, zero for a pinhole camera.
+    pub aperture: f32,
+    /// Distance from the origin to the plane in perfect focus.
+    pub focus_distance: f32,
+    /// Exposure compensation in stops.
+    pub exposure: f32,
+    _padding: f32,
+}
+
+// Returns the centroid of the touch points and their mean distance from it.
+fn touch_gesture(touches: &[(u64, (f32, f32))]) -> ((f32, f32), f32) {
+    let count = touches.len().max(1) as f32;
+    let (sum_x, sum_y) = touches
+        .iter()
+        .fold((0.0, 0.0), |(x, y), (_, p)| (x + p.0, y + p.1));
+    let center = (sum_x / count, sum_y / count);
+    let spread = touches
+        .iter()
+        .map(|(_, p)| ((p.0 - center.0).powi(2) + (p.1 - center.1).powi(2)).sqrt())
+        .sum::<f32>()
+        / count;
+    (center, spread)
+}
+
+// Two axes spanning the plane perpendicular to `up`. For +Y up these are +X and +Z.
+fn horizontal_axes(up: Vec3) -> (Vec3, Vec3) {
+    let reference = if up.x.abs() < 0.9 {
+        Vec3::new(1.0, 0.0, 0.0)
+    } else {
+        Vec3::new(0.0, 0.0, 1.0)
+    };
+    let a = (reference - up * up.dot(reference)).normalize();
+    (a, a.cross(up))
+}
+
+// Front, right and up of a camera turned by yaw, pitch and roll in degrees.
+fn view_vectors(world_up: Vec3, yaw: f32, pitch: f32, roll: f32) -> (Vec3, Vec3, Vec3) {
+    let (a, b) = horizontal_axes(world_up);
+    let (yaw_sin, yaw_cos) = yaw.to_radians().sin_cos();
+    let (pitch_sin, pitch_cos) = pitch.to_radians().sin_cos();
+    let front =
+        (a * yaw_cos * pitch_cos + world_up * pitch_sin + b * yaw_sin * pitch_cos).normalize();
+
+    // The pitch clamp keeps this away from zero, but guard against it anyway since the
+    // cross product loses all precision when looking straight along the up axis.
+    let mut right = front.cross(world_up);
+    if right.length_squared() < 1e-6 {
+        right = front.cross(b);
+    }
+    let right = right.normalize();
+    let up = right.cross(front).normalize();
+
+    let (roll_sin, roll_cos) = roll.to_radians().sin_cos();
+    (
+        front,
+        right * roll_cos + up * roll_sin,
+        up * roll_cos - right * roll_sin,
+    )
+}
+
+// Yaw and pitch in degrees that make the camera look along `direction`.
+fn look_angles(up: Vec3, direction: Vec3) -> (f32, f32) {
+    let (a, b) = horizontal_axes(up);
+    let direction = direction.normalize();
+    let pitch = direction.dot(up).asin().to_degrees().clamp(-89.0, 89.0);
+    let yaw = direction.dot(b).atan2(direction.dot(a)).to_degrees();
+    (yaw, pitch)
 }
 
 impl Camera {
     pub fn new(position: Vec3, look_at: Vec3) -> Self {
         let front: Vec3 = look_at - position;
-        let pitch = (front.y / front.length())
-            .asin()
-            .to_degrees()
-            .clamp(-89.0, 89.0);
-        let mut yaw = (front.z / front.length()).asin().to_degrees();
-
-        if front.z >= 0.0 && front.x < 0.0 {
-            yaw = 180.0 - yaw;
-        }
+        let world_up = Vec3::new(0.0, 1.0, 0.0);
+        let (yaw, pitch) = look_angles(world_up, front);
 
         let mut camera = Self {
             position,
             front,
             yaw,
             pitch,
-            world_up: Vec3::new(0.0, 1.0, 0.0),
+            world_up,
+            target: look_at,
+            distance: front.length(),
+            fov: 45.0,
+            aspect_ratio: 1.0,
+            z_near: 0.01,
+            z_far: 10000.0,
+            fov_sensitivity: 2.0,
+            dolly_sensitivity: 0.1,
+            speed: 10.0,
+            sprint_multiplier: 4.0,
+            axis_speed_scale: Vec3::ONE,
+            transition_duration: 0.5,
+            focus_distance: front.length(),
+            gamepad_look_speed: 120.0,
+            roll_speed: 45.0,
             ..Default::default()
         };
 
@@ -68,163 +198,235 @@ impl Camera {
         camera
     }
 
+    /// Feeds a winit event to the camera. Other windowing backends can use `handle_input` and
+    /// `poll_gamepad` directly.
     pub fn input(&mut self, event: &winit::event::Event<()>) {
-        match event {
-            winit::event::Event::NewEvents(_) => {}
-            winit::event::Event::WindowEvent { window_id, event } => {
-                match event {
-                    winit::event::WindowEvent::Resized(_) => {}
-                    winit::event::WindowEvent::Moved(_) => {}
-                    winit::event::WindowEvent::ReceivedCharacter(_) => {}
-                    winit::event::WindowEvent::Focused(_) => {}
-                    winit::event::WindowEvent::KeyboardInput {
-                        device_id,
-                        input,
-                        is_synthetic,
-                    } => {}
-                    winit::event::WindowEvent::ModifiersChanged(_) => {}
-                    winit::event::WindowEvent::CursorMoved {
-                        device_id,
-                        position,
-                        ..
-                    } => {}
-                    winit::event::WindowEvent::CursorEntered { device_id } => {}
-                    winit::event::WindowEvent::CursorLeft { device_id } => {}
-                    winit::event::WindowEvent::MouseWheel {
-                        device_id,
-                        delta,
-                        phase,
-                        ..
-                    } => {}
-                    winit::event::WindowEvent::MouseInput {
-                        device_id,
-                        state,
-                        button,
-                        ..
-                    } => {
-                        match button {
-                            winit::event::MouseButton::Left => {}
-                            winit::event::MouseButton::Right => {
-                                match state {
-                                    winit::event::ElementState::Pressed => {
-                                        self.right_button_pressed = true;
-                                    }
-                                    winit::event::ElementState::Released => {
-                                        self.right_button_pressed = false;
-                                    }
-                                }
-                            }
-                            winit::event::MouseButton::Middle => {}
-                            winit::event::MouseButton::Other(_) => {}
-                        }
-                    }
-                    winit::event::WindowEvent::AxisMotion {
-                        device_id,
-                        axis,
-                        value,
-                    } => {}
-                    winit::event::WindowEvent::ScaleFactorChanged {
-                        scale_factor,
-                        new_inner_size,
-                    } => {}
-                    winit::event::WindowEvent::ThemeChanged(_) => {}
-                    _ => {}
+        if let winit::event::Event::MainEventsCleared = event {
+            self.poll_gamepad();
+        }
+        if let Some(input) = CameraInput::from_winit(event) {
+            self.handle_input(input);
+        }
+    }
+
+    /// Reads the current gamepad state. Call once per frame before `update`.
+    pub fn poll_gamepad(&mut self) {
+        self.gamepad_state = self.gamepad.poll().unwrap_or_default();
+    }
+
+    pub fn handle_input(&mut self, input: CameraInput) {
+        match input {
+            CameraInput::MouseDelta { x, y } => {
+                if self.is_held(Action::Look) {
+                    self.process_mouse_movement(x * 0.08, y * 0.08);
+                }
+                if self.is_held(Action::Pan) && self.mode == CameraMode::Orbit {
+                    self.pan(x, y);
                 }
             }
-            winit::event::Event::DeviceEvent { device_id, event } => {
-                match event {
-                    winit::event::DeviceEvent::Added => {}
-                    winit::event::DeviceEvent::Removed => {}
-                    winit::event::DeviceEvent::MouseMotion { delta: (x, y) } => {
-                        if self.right_button_pressed {
-                            self.process_mouse_movement((x * 0.08) as f32, (y * 0.08) as f32);
-                        }
-                    }
-                    winit::event::DeviceEvent::MouseWheel { delta } => {}
-                    winit::event::DeviceEvent::Motion { axis, value } => {}
-                    winit::event::DeviceEvent::Button { button, state } => {}
-                    winit::event::DeviceEvent::Key(input) => {
-                        if let Some(keycode) = input.virtual_keycode {
-                            match keycode {
-                                winit::event::VirtualKeyCode::W => {
-                                    self.key_pressed.w = match input.state {
-                                        winit::event::ElementState::Pressed => true,
-                                        winit::event::ElementState::Released => false,
-                                    }
-                                }
-                                winit::event::VirtualKeyCode::S => {
-                                    self.key_pressed.s = match input.state {
-                                        winit::event::ElementState::Pressed => true,
-                                        winit::event::ElementState::Released => false,
-                                    }
-                                }
-                                winit::event::VirtualKeyCode::A => {
-                                    self.key_pressed.a = match input.state {
-                                        winit::event::ElementState::Pressed => true,
-                                        winit::event::ElementState::Released => false,
-                                    }
-                                }
-                                winit::event::VirtualKeyCode::D => {
-                                    self.key_pressed.d = match input.state {
-                                        winit::event::ElementState::Pressed => true,
-                                        winit::event::ElementState::Released => false,
-                                    }
-                                }
-                                winit::event::VirtualKeyCode::Q => {
-                                    self.key_pressed.q = match input.state {
-                                        winit::event::ElementState::Pressed => true,
-                                        winit::event::ElementState::Released => false,
-                                    }
-                                }
-                                winit::event::VirtualKeyCode::E => {
-                                    self.key_pressed.e = match input.state {
-                                        winit::event::ElementState::Pressed => true,
-                                        winit::event::ElementState::Released => false,
-                                    }
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
-                    winit::event::DeviceEvent::Text { codepoint } => {}
+            CameraInput::Wheel { steps } => self.process_wheel(steps),
+            CameraInput::Button { button, pressed } => {
+                self.process_binding(Binding::Button(button), pressed)
+            }
+            CameraInput::Touch {
+                id,
+                phase,
+                location,
+            } => self.process_touch(id, phase, location),
+            CameraInput::Key {
+                key: Key::Digit(n),
+                pressed: true,
+            } => {
+                if self.is_held(Action::StoreBookmark) {
+                    self.store_bookmark(n as usize);
+                } else {
+                    self.recall_bookmark(n as usize);
                 }
             }
-            winit::event::Event::UserEvent(_) => {}
-            winit::event::Event::MainEventsCleared => {}
-            winit::event::Event::RedrawRequested(_) => {}
-            winit::event::Event::RedrawEventsCleared => {}
-            winit::event::Event::LoopDestroyed => {}
+            CameraInput::Key { key, pressed } => self.process_binding(Binding::Key(key), pressed),
+        }
+    }
+
+    fn process_binding(&mut self, binding: Binding, pressed: bool) {
+        if !pressed {
+            self.held.remove(&binding);
+            return;
+        }
+        // Key repeat sends more presses while the key is down.
+        if !self.held.insert(binding) {
+            return;
+        }
+        match self.bindings.action(binding) {
+            Some(Action::ApertureDown) => self.set_aperture(self.aperture - 0.05),
+            Some(Action::ApertureUp) => self.set_aperture(self.aperture + 0.05),
+            Some(Action::FocusNearer) => self.set_focus_distance(self.focus_distance / 1.1),
+            Some(Action::FocusFarther) => self.set_focus_distance(self.focus_distance * 1.1),
+            Some(Action::ExposureDown) => self.set_exposure(self.exposure - 0.25),
+            Some(Action::ExposureUp) => self.set_exposure(self.exposure + 0.25),
+            Some(Action::CycleBookmark) => self.cycle_bookmark(),
             _ => {}
         }
-        self.update();
     }
 
-    fn update(&mut self) {
-        let speed = 0.01;
-        if self.key_pressed.w {
-            self.process_keyboard(Direction::Forward, speed);
+    /// Whether a binding of `action` is down.
+    pub fn is_held(&self, action: Action) -> bool {
+        self.bindings
+            .bindings(action)
+            .iter()
+            .any(|binding| self.held.contains(binding))
+    }
+
+    pub fn bindings(&self) -> &InputBindings {
+        &self.bindings
+    }
+
+    pub fn bindings_mut(&mut self) -> &mut InputBindings {
+        &mut self.bindings
+    }
+
+    pub fn set_bindings(&mut self, bindings: InputBindings) {
+        self.bindings = bindings;
+    }
+
+    /// Applies held movement keys for the `dt` seconds elapsed since the last call.
+    pub fn update(&mut self, dt: f32) {
+        if let Some(transition) = &mut self.transition {
+            transition.elapsed += dt;
+            let duration = self.transition_duration.max(f32::EPSILON);
+            let t = (transition.elapsed / duration).min(1.0);
+            let state = transition.from.lerp(&transition.to, t * t * (3.0 - 2.0 * t));
+            if t >= 1.0 {
+                self.transition = None;
+            }
+            self.set_state(&state);
+            return;
+        }
+
+        let roll_left = self.is_held(Action::RollLeft);
+        if roll_left != self.is_held(Action::RollRight) {
+            let direction = if roll_left { -1.0 } else { 1.0 };
+            self.set_roll(self.roll + direction * self.roll_speed * dt);
+        }
+
+        let pad = self.gamepad_state;
+        let look = self.gamepad_look_speed * dt;
+        if pad.right_stick != (0.0, 0.0) {
+            self.process_mouse_movement(pad.right_stick.0 * look, pad.right_stick.1 * look);
         }
-        if self.key_pressed.s {
-            self.process_keyboard(Direction::Backward, speed);
+
+        if self.mode == CameraMode::Orbit {
+            if pad.left_stick.1 != 0.0 {
+                self.dolly((1.0 - self.dolly_sensitivity).powf(pad.left_stick.1 * dt * 10.0));
+            }
+            return;
+        }
+        let mut distance = self.speed * dt;
+        if self.is_held(Action::Sprint) {
+            distance *= self.sprint_multiplier;
         }
-        if self.key_pressed.a {
-            self.process_keyboard(Direction::Left, speed);
+        let scale = self.axis_speed_scale;
+        if self.is_held(Action::MoveForward) {
+            self.process_keyboard(Direction::Forward, distance * scale.z);
         }
-        if self.key_pressed.d {
-            self.process_keyboard(Direction::Right, speed);
+        if self.is_held(Action::MoveBackward) {
+            self.process_keyboard(Direction::Backward, distance * scale.z);
         }
-        if self.key_pressed.q {
-            self.process_keyboard(Direction::Down, speed);
+        if self.is_held(Action::MoveLeft) {
+            self.process_keyboard(Direction::Left, distance * scale.x);
         }
-        if self.key_pressed.e {
-            self.process_keyboard(Direction::Up, speed);
+        if self.is_held(Action::MoveRight) {
+            self.process_keyboard(Direction::Right, distance * scale.x);
         }
+        if self.is_held(Action::MoveDown) {
+            self.process_keyboard(Direction::Down, distance * scale.y);
+        }
+        if self.is_held(Action::MoveUp) {
+            self.process_keyboard(Direction::Up, distance * scale.y);
+        }
+
+        self.process_keyboard(Direction::Forward, pad.left_stick.1 * distance * scale.z);
+        self.process_keyboard(Direction::Right, pad.left_stick.0 * distance * scale.x);
+        self.process_keyboard(
+            Direction::Up,
+            (pad.right_trigger - pad.left_trigger) * distance * scale.y,
+        );
     }
 
     fn process_mouse_movement(&mut self, yaw_offset: f32, pitch_offset: f32) {
         self.yaw += yaw_offset;
         self.pitch = (self.pitch + pitch_offset).clamp(-89.0, 89.0);
         self.update_vectors();
+        if self.mode == CameraMode::Orbit {
+            self.position = self.target - self.front * self.distance;
+        }
+    }
+
+    // One finger looks around (or orbits), two fingers pinch to zoom and drag to pan. Trackpad
+    // pinches are not reported by winit, but two-finger scrolling arrives as `MouseWheel`.
+    fn process_touch(&mut self, id: u64, phase: TouchPhase, location: (f32, f32)) {
+        let index = self.touches.iter().position(|(touch_id, _)| *touch_id == id);
+        match phase {
+            TouchPhase::Started => {
+                self.touches.push((id, location));
+            }
+            TouchPhase::Moved => {
+                let index = match index {
+                    Some(index) => index,
+                    None => return,
+                };
+                let (old_center, old_spread) = touch_gesture(&self.touches);
+                self.touches[index].1 = location;
+                let (center, spread) = touch_gesture(&self.touches);
+                let delta = (center.0 - old_center.0, center.1 - old_center.1);
+
+                match self.touches.len() {
+                    1 => self.process_mouse_movement(delta.0 * 0.2, delta.1 * 0.2),
+                    2 => {
+                        if spread > 0.0 && old_spread > 0.0 {
+                            match self.mode {
+                                CameraMode::Fly => {
+                                    self.fov = (self.fov * old_spread / spread).clamp(1.0, 120.0);
+                                }
+                                CameraMode::Orbit => self.dolly(old_spread / spread),
+                            }
+                        }
+                        if self.mode == CameraMode::Orbit {
+                            self.pan(delta.0, delta.1);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            TouchPhase::Ended => {
+                if let Some(index) = index {
+                    self.touches.remove(index);
+                }
+            }
+        }
+    }
+
+    // Moves the target in the view plane, scaled so the target roughly follows the cursor.
+    fn pan(&mut self, x: f32, y: f32) {
+        let scale = self.distance * 0.001;
+        self.target += (self.up * y - self.right * x) * scale;
+        self.position = self.target - self.front * self.distance;
+    }
+
+    // Scrolling up zooms in: narrows the FOV in fly mode, moves towards the target in orbit mode.
+    fn process_wheel(&mut self, steps: f32) {
+        match self.mode {
+            CameraMode::Fly => {
+                self.fov = (self.fov - steps * self.fov_sensitivity).clamp(1.0, 120.0);
+            }
+            CameraMode::Orbit => {
+                self.dolly((1.0 - self.dolly_sensitivity).powf(steps));
+            }
+        }
+    }
+
+    fn dolly(&mut self, factor: f32) {
+        self.distance = (self.distance * factor).max(self.z_near);
+        self.position = self.target - self.front * self.distance;
     }
 
     fn process_keyboard(&mut self, direction: Direction, distance: f32) {
@@ -251,23 +453,289 @@ impl Camera {
     }
 
     pub fn camera_uniform(&self) -> CameraUniform {
+        self.uniform(self.position, self.view_matrix(), self.fov)
+    }
+
+    /// The uniform of the pose `alpha` of the way from `previous` to the current one. Renders
+    /// between fixed timestep updates draw this, so the camera moves smoothly at any frame rate.
+    pub fn interpolated_uniform(&self, previous: &CameraState, alpha: f32) -> CameraUniform {
+        let state = previous.lerp(&self.state(), alpha);
+        let position: Vec3 = state.position.into();
+        let pitch = state.pitch.clamp(-89.0, 89.0);
+        let (front, _, up) = view_vectors(self.world_up, state.yaw, pitch, state.roll);
+        let view = Mat4::look_at_rh(position.into(), (position + front).into(), up.into());
+        self.uniform(position, view, state.fov)
+    }
+
+    fn uniform(&self, position: Vec3, view: Mat4, fov: f32) -> CameraUniform {
+        let projection = Self::projection_matrix(self.aspect_ratio, fov, self.z_near, self.z_far);
         CameraUniform {
-            origin: self.position.into(),
+            view,
+            projection,
+            inverse_view_projection: (projection * view).inverse(),
+            origin: position.into(),
+            fov,
+            aperture: self.aperture,
+            focus_distance: self.focus_distance,
+            exposure: self.exposure,
+            _padding: 0.0,
         }
     }
 
-    fn update_vectors(&mut self) {
-        self.front = Vec3::new(
-            self.yaw.to_radians().cos() * self.pitch.to_radians().cos(),
-            self.pitch.to_radians().sin(),
-            self.yaw.to_radians().sin() * self.pitch.to_radians().cos(),
+    pub fn view_projection_matrix(&self) -> Mat4 {
+        Self::projection_matrix(self.aspect_ratio, self.fov, self.z_near, self.z_far)
+            * self.view_matrix()
+    }
+
+    pub fn frustum(&self) -> Frustum {
+        let mut frustum = Frustum::from_view_projection(self.view_projection_matrix());
+        // Unprojecting through the inverse matrix loses too much precision at the far plane.
+        let half_height = (self.fov.to_radians() * 0.5).tan();
+        let half_width = half_height * self.aspect_ratio;
+        for (i, corner) in frustum.corners.iter_mut().enumerate() {
+            let x = if i & 1 == 0 { -half_width } else { half_width };
+            let y = if i & 2 == 0 { -half_height } else { half_height };
+            let z = if i & 4 == 0 { self.z_near } else { self.z_far };
+            *corner = (self.position + (self.front + self.right * x + self.up * y) * z).into();
+        }
+        frustum
+    }
+
+    pub fn view_matrix(&self) -> Mat4 {
+        Mat4::look_at_rh(
+            self.position.into(),
+            (self.position + self.front).into(),
+            self.up.into(),
         )
-        .normalize();
-        self.right = self.front.cross(self.world_up).normalize();
-        self.up = self.right.cross(self.front).normalize();
+    }
+
+    /// `fov` is the vertical field of view in degrees. Depth maps to [0, 1] as Vulkan expects.
+    pub fn projection_matrix(aspect_ratio: f32, fov: f32, z_near: f32, z_far: f32) -> Mat4 {
+        Mat4::perspective_rh(fov.to_radians(), aspect_ratio, z_near, z_far)
+    }
+
+    pub fn mode(&self) -> CameraMode {
+        self.mode
+    }
+
+    /// Switching to orbit mode keeps the current view and orbits the point `distance` ahead.
+    pub fn set_mode(&mut self, mode: CameraMode) {
+        if mode == CameraMode::Orbit && self.mode != CameraMode::Orbit {
+            self.target = self.position + self.front * self.distance;
+        }
+        self.mode = mode;
+    }
+
+    pub fn target(&self) -> Vec3 {
+        self.target
+    }
+
+    /// Points the camera at `target` from its current position and orbits around it from now on.
+    pub fn set_target(&mut self, target: Vec3) {
+        let front = target - self.position;
+        self.distance = front.length().max(self.z_near);
+        let (yaw, pitch) = look_angles(self.world_up, front);
+        self.yaw = yaw;
+        self.pitch = pitch;
+        self.target = target;
+        self.update_vectors();
+        self.position = self.target - self.front * self.distance;
+    }
+
+    pub fn distance(&self) -> f32 {
+        self.distance
+    }
+
+    pub fn set_distance(&mut self, distance: f32) {
+        self.distance = distance.max(self.z_near);
+        if self.mode == CameraMode::Orbit {
+            self.position = self.target - self.front * self.distance;
+        }
+    }
+
+    /// Returns the orbit azimuth and elevation in degrees.
+    pub fn orbit_angles(&self) -> (f32, f32) {
+        (self.yaw, self.pitch)
+    }
+
+    pub fn set_orbit_angles(&mut self, azimuth: f32, elevation: f32) {
+        self.yaw = azimuth;
+        self.pitch = elevation.clamp(-89.0, 89.0);
+        self.update_vectors();
+        if self.mode == CameraMode::Orbit {
+            self.position = self.target - self.front * self.distance;
+        }
+    }
+
+    /// Movement speed in world units per second.
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Speed multiplier applied while shift is held.
+    pub fn set_sprint_multiplier(&mut self, multiplier: f32) {
+        self.sprint_multiplier = multiplier;
+    }
+
+    /// Per-axis speed scale as (right, up, forward).
+    pub fn set_axis_speed_scale(&mut self, scale: Vec3) {
+        self.axis_speed_scale = scale;
+    }
+
+    /// Lens radius, adjusted with `[` and `]`. Zero disables depth of field.
+    pub fn set_aperture(&mut self, aperture: f32) {
+        self.aperture = aperture.max(0.0);
+    }
+
+    pub fn aperture(&self) -> f32 {
+        self.aperture
+    }
+
+    /// Adjusted with `-` and `=`.
+    pub fn set_focus_distance(&mut self, focus_distance: f32) {
+        self.focus_distance = focus_distance.max(self.z_near);
+    }
+
+    pub fn focus_distance(&self) -> f32 {
+        self.focus_distance
+    }
+
+    /// Exposure compensation in stops, adjusted with `,` and `.`.
+    pub fn set_exposure(&mut self, exposure: f32) {
+        self.exposure = exposure;
+    }
+
+    pub fn exposure(&self) -> f32 {
+        self.exposure
+    }
+
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: f32) {
+        self.aspect_ratio = aspect_ratio;
+    }
+
+    pub fn set_fov(&mut self, fov: f32) {
+        self.fov = fov;
+    }
+
+    pub fn fov(&self) -> f32 {
+        self.fov
+    }
+
+    /// Degrees of FOV change per wheel step in fly mode.
+    pub fn set_fov_sensitivity(&mut self, sensitivity: f32) {
+        self.fov_sensitivity = sensitivity;
+    }
+
+    /// Fraction of the orbit distance covered per wheel step in orbit mode.
+    pub fn set_dolly_sensitivity(&mut self, sensitivity: f32) {
+        self.dolly_sensitivity = sensitivity.clamp(0.0, 0.99);
+    }
+
+    pub fn set_clip_planes(&mut self, z_near: f32, z_far: f32) {
+        self.z_near = z_near;
+        self.z_far = z_far;
+    }
+
+    /// Sets the world up axis, e.g. +Z for Z-up scenes, keeping the current view direction.
+    pub fn set_world_up(&mut self, world_up: Vec3) {
+        self.world_up = world_up.normalize();
+        let (yaw, pitch) = look_angles(self.world_up, self.front);
+        self.yaw = yaw;
+        self.pitch = pitch;
+        self.update_vectors();
+    }
+
+    pub fn world_up(&self) -> Vec3 {
+        self.world_up
+    }
+
+    /// Rotation around the view direction in degrees, adjusted by holding Z and C.
+    pub fn set_roll(&mut self, roll: f32) {
+        self.roll = roll;
+        self.update_vectors();
+    }
+
+    pub fn roll(&self) -> f32 {
+        self.roll
+    }
+
+    fn update_vectors(&mut self) {
+        let (front, right, up) = view_vectors(self.world_up, self.yaw, self.pitch, self.roll);
+        self.front = front;
+        self.right = right;
+        self.up = up;
     }
 
     pub fn position(&self) -> glam::Vec3A {
         self.position
     }
+
+    pub fn state(&self) -> CameraState {
+        CameraState {
+            position: self.position.into(),
+            yaw: self.yaw,
+            pitch: self.pitch,
+            roll: self.roll,
+            fov: self.fov,
+            mode: self.mode,
+            target: self.target.into(),
+            distance: self.distance,
+        }
+    }
+
+    /// Remembers the current pose in slot `n` (Ctrl + number key).
+    pub fn store_bookmark(&mut self, n: usize) {
+        if self.bookmarks.len() <= n {
+            self.bookmarks.resize(n + 1, None);
+        }
+        self.bookmarks[n] = Some(self.state());
+        self.last_bookmark = n;
+    }
+
+    /// Moves smoothly to the pose in slot `n` (number key). Returns false if the slot is empty.
+    pub fn recall_bookmark(&mut self, n: usize) -> bool {
+        match self.bookmarks.get(n).copied().flatten() {
+            Some(to) => {
+                self.transition = Some(Transition {
+                    from: self.state(),
+                    to,
+                    elapsed: 0.0,
+                });
+                self.last_bookmark = n;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Recalls the next stored bookmark after the last one used (Tab).
+    pub fn cycle_bookmark(&mut self) {
+        let count = self.bookmarks.len();
+        for i in 1..=count {
+            if self.recall_bookmark((self.last_bookmark + i) % count) {
+                return;
+            }
+        }
+    }
+
+    /// Seconds taken to move to a recalled bookmark.
+    pub fn set_transition_duration(&mut self, duration: f32) {
+        self.transition_duration = duration;
+    }
+
+    pub fn set_state(&mut self, state: &CameraState) {
+        self.position = state.position.into();
+        self.yaw = state.yaw;
+        self.pitch = state.pitch.clamp(-89.0, 89.0);
+        self.roll = state.roll;
+        self.fov = state.fov;
+        self.mode = state.mode;
+        self.target = state.target.into();
+        self.distance = state.distance;
+        self.update_vectors();
+    }
 }
@@ -0,0 +1,190 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::state::wrap_degrees;
+use crate::CameraState;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Keyframe {
+    /// Seconds from the start of the path.
+    pub time: f32,
+    pub state: CameraState,
+}
+
+/// Keyframed camera poses played back with Catmull-Rom interpolation.
+///
+/// Playback only advances through [`CameraPath::advance`], so feeding it a fixed time step
+/// replays a flythrough identically every run.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CameraPath {
+    keyframes: Vec<Keyframe>,
+    #[serde(skip)]
+    time: f32,
+    #[serde(skip)]
+    playing: bool,
+    pub looping: bool,
+}
+
+impl CameraPath {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inserts a keyframe, replacing one already at exactly `time`. Keyframes at a non-finite
+    /// time are skipped.
+    pub fn add_keyframe(&mut self, time: f32, state: CameraState) {
+        debug_assert!(time.is_finite(), "keyframe at {} s", time);
+        if !time.is_finite() {
+            return;
+        }
+        match self
+            .keyframes
+            .binary_search_by(|k| k.time.partial_cmp(&time).unwrap())
+        {
+            Ok(i) => self.keyframes[i].state = state,
+            Err(i) => self.keyframes.insert(i, Keyframe { time, state }),
+        }
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    pub fn clear(&mut self) {
+        self.keyframes.clear();
+        self.time = 0.0;
+        self.playing = false;
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |k| k.time)
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// Starts playback from the current time, rewinding first if the end was reached.
+    pub fn play(&mut self) {
+        if self.time >= self.duration() {
+            self.time = 0.0;
+        }
+        self.playing = !self.keyframes.is_empty();
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    pub fn seek(&mut self, time: f32) {
+        self.time = time.clamp(0.0, self.duration());
+    }
+
+    /// Advances playback by `dt` seconds and returns the pose to apply, or `None` when paused.
+    pub fn advance(&mut self, dt: f32) -> Option<CameraState> {
+        if !self.playing {
+            return None;
+        }
+        let duration = self.duration();
+        self.time += dt;
+        if self.time >= duration {
+            if self.looping && duration > 0.0 {
+                self.time %= duration;
+            } else {
+                self.time = duration;
+                self.playing = false;
+            }
+        }
+        self.sample(self.time)
+    }
+
+    pub fn sample(&self, time: f32) -> Option<CameraState> {
+        let last = self.keyframes.len().checked_sub(1)?;
+        let i = self
+            .keyframes
+            .iter()
+            .rposition(|k| k.time <= time)
+            .unwrap_or(0)
+            .min(last.saturating_sub(1));
+        let k1 = &self.keyframes[i];
+        let k2 = &self.keyframes[(i + 1).min(last)];
+        let k0 = &self.keyframes[i.saturating_sub(1)];
+        let k3 = &self.keyframes[(i + 2).min(last)];
+
+        let span = k2.time - k1.time;
+        let t = if span > 0.0 {
+            ((time - k1.time) / span).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+
+        let (s0, s1, s2, s3) = (&k0.state, &k1.state, &k2.state, &k3.state);
+        let spline = |p0: f32, p1: f32, p2: f32, p3: f32| catmull_rom(p0, p1, p2, p3, t);
+        let spline3 = |p0: [f32; 3], p1: [f32; 3], p2: [f32; 3], p3: [f32; 3]| {
+            [
+                spline(p0[0], p1[0], p2[0], p3[0]),
+                spline(p0[1], p1[1], p2[1], p3[1]),
+                spline(p0[2], p1[2], p2[2], p3[2]),
+            ]
+        };
+        // Unwrap yaw and roll so the spline never turns the long way round.
+        let unwrap = |a0: f32, a1: f32, a2: f32, a3: f32| {
+            let a2 = a1 + wrap_degrees(a2 - a1);
+            (
+                a1 - wrap_degrees(a1 - a0),
+                a1,
+                a2,
+                a2 + wrap_degrees(a3 - a2),
+            )
+        };
+        let (y0, y1, y2, y3) = unwrap(s0.yaw, s1.yaw, s2.yaw, s3.yaw);
+        let (r0, r1, r2, r3) = unwrap(s0.roll, s1.roll, s2.roll, s3.roll);
+
+        Some(CameraState {
+            position: spline3(s0.position, s1.position, s2.position, s3.position),
+            yaw: spline(y0, y1, y2, y3),
+            pitch: spline(s0.pitch, s1.pitch, s2.pitch, s3.pitch),
+            roll: spline(r0, r1, r2, r3),
+            fov: spline(s0.fov, s1.fov, s2.fov, s3.fov),
+            mode: if t < 0.5 { s1.mode } else { s2.mode },
+            target: spline3(s0.target, s1.target, s2.target, s3.target),
+            distance: spline(s0.distance, s1.distance, s2.distance, s3.distance),
+        })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)
+    }
+
+    /// Loads a path saved by `save`. Keyframes are sorted by time, a later one replacing an
+    /// earlier one at the same time. Fails with `InvalidData` on a non-finite time.
+    pub fn load<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let json = std::fs::read_to_string(path)?;
+        let mut path: Self = serde_json::from_str(&json)?;
+        if let Some(keyframe) = path.keyframes.iter().find(|k| !k.time.is_finite()) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("keyframe at {} s", keyframe.time),
+            ));
+        }
+        for keyframe in std::mem::take(&mut path.keyframes) {
+            path.add_keyframe(keyframe.time, keyframe.state);
+        }
+        Ok(path)
+    }
+}
+
+fn catmull_rom(p0: f32, p1: f32, p2: f32, p3: f32, t: f32) -> f32 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    0.5 * (2.0 * p1
+        + (p2 - p0) * t
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+        + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3)
+}
@@ -0,0 +1,100 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::CameraMode;
+
+/// Everything needed to restore a viewpoint exactly.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CameraState {
+    pub position: [f32; 3],
+    pub yaw: f32,
+    pub pitch: f32,
+    #[serde(default)]
+    pub roll: f32,
+    pub fov: f32,
+    pub mode: CameraMode,
+    pub target: [f32; 3],
+    pub distance: f32,
+}
+
+impl CameraState {
+    /// Interpolates towards `other`, turning the short way round in yaw. The mode switches halfway.
+    pub fn lerp(&self, other: &CameraState, t: f32) -> CameraState {
+        let lerp = |a: f32, b: f32| a + (b - a) * t;
+        let lerp3 = |a: [f32; 3], b: [f32; 3]| {
+            [lerp(a[0], b[0]), lerp(a[1], b[1]), lerp(a[2], b[2])]
+        };
+        CameraState {
+            position: lerp3(self.position, other.position),
+            yaw: self.yaw + wrap_degrees(other.yaw - self.yaw) * t,
+            pitch: lerp(self.pitch, other.pitch),
+            roll: self.roll + wrap_degrees(other.roll - self.roll) * t,
+            fov: lerp(self.fov, other.fov),
+            mode: if t < 0.5 { self.mode } else { other.mode },
+            target: lerp3(self.target, other.target),
+            distance: lerp(self.distance, other.distance),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let json = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+}
+
+/// Named camera states, stored together in a single file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CameraPresets {
+    presets: BTreeMap<String, CameraState>,
+}
+
+impl CameraPresets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, name: &str, state: CameraState) {
+        self.presets.insert(name.to_owned(), state);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&CameraState> {
+        self.presets.get(name)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<CameraState> {
+        self.presets.remove(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.presets.keys().map(|name| name.as_str())
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let json = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+}
+
+/// Wraps an angle difference into [-180, 180] degrees.
+pub(crate) fn wrap_degrees(angle: f32) -> f32 {
+    let angle = angle % 360.0;
+    if angle > 180.0 {
+        angle - 360.0
+    } else if angle < -180.0 {
+        angle + 360.0
+    } else {
+        angle
+    }
+}
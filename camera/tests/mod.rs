@@ -0,0 +1,285 @@
+use camera::*;
+use glam::Vec3A;
+
+fn create_camera() -> Camera {
+    Camera::new(Vec3A::new(0.0, 0.0, 10.0), Vec3A::new(0.0, 0.0, 0.0))
+}
+
+#[test]
+fn test_key_moves_forward() {
+    let mut camera = create_camera();
+    camera.set_speed(2.0);
+    camera.handle_input(CameraInput::Key {
+        key: Key::W,
+        pressed: true,
+    });
+    camera.update(0.5);
+    assert!(camera.position().abs_diff_eq(Vec3A::new(0.0, 0.0, 9.0), 1e-4));
+
+    camera.handle_input(CameraInput::Key {
+        key: Key::W,
+        pressed: false,
+    });
+    camera.update(0.5);
+    assert!(camera.position().abs_diff_eq(Vec3A::new(0.0, 0.0, 9.0), 1e-4));
+}
+
+#[test]
+fn test_mouse_looks_only_while_right_button_held() {
+    let mut camera = create_camera();
+    let view = camera.view_matrix();
+    camera.handle_input(CameraInput::MouseDelta { x: 100.0, y: 0.0 });
+    assert_eq!(camera.view_matrix(), view);
+
+    camera.handle_input(CameraInput::Button {
+        button: MouseButton::Right,
+        pressed: true,
+    });
+    camera.handle_input(CameraInput::MouseDelta { x: 100.0, y: 0.0 });
+    assert_ne!(camera.view_matrix(), view);
+    assert!(camera.position().abs_diff_eq(Vec3A::new(0.0, 0.0, 10.0), 1e-4));
+}
+
+#[test]
+fn test_wheel_zooms_fov_in_fly_mode() {
+    let mut camera = create_camera();
+    let fov = camera.fov();
+    camera.handle_input(CameraInput::Wheel { steps: 1.0 });
+    assert!(camera.fov() < fov);
+}
+
+#[test]
+fn test_wheel_dollies_in_orbit_mode() {
+    let mut camera = create_camera();
+    camera.set_mode(CameraMode::Orbit);
+    let fov = camera.fov();
+    camera.handle_input(CameraInput::Wheel { steps: 1.0 });
+    assert_eq!(camera.fov(), fov);
+    assert!(camera.distance() < 10.0);
+    assert!(camera.target().abs_diff_eq(Vec3A::new(0.0, 0.0, 0.0), 1e-4));
+}
+
+#[test]
+fn test_bookmark_recall_interpolates() {
+    let mut camera = create_camera();
+    camera.handle_input(CameraInput::Key {
+        key: Key::Ctrl,
+        pressed: true,
+    });
+    camera.handle_input(CameraInput::Key {
+        key: Key::Digit(1),
+        pressed: true,
+    });
+    camera.handle_input(CameraInput::Key {
+        key: Key::Ctrl,
+        pressed: false,
+    });
+
+    camera.set_state(&CameraState {
+        position: [0.0, 0.0, 20.0],
+        ..camera.state()
+    });
+    camera.set_transition_duration(1.0);
+    camera.handle_input(CameraInput::Key {
+        key: Key::Digit(1),
+        pressed: true,
+    });
+    camera.update(0.5);
+    assert!(camera.position().abs_diff_eq(Vec3A::new(0.0, 0.0, 15.0), 1e-4));
+    camera.update(0.5);
+    assert!(camera.position().abs_diff_eq(Vec3A::new(0.0, 0.0, 10.0), 1e-4));
+}
+
+#[test]
+fn test_frustum_contains_target() {
+    let camera = create_camera();
+    let frustum = camera.frustum();
+    assert!(frustum.contains_point(glam::Vec3::new(0.0, 0.0, 0.0)));
+    assert!(!frustum.contains_point(glam::Vec3::new(0.0, 0.0, 20.0)));
+    assert!(!frustum.contains_point(glam::Vec3::new(100.0, 0.0, 0.0)));
+}
+
+#[test]
+fn test_rebound_key_moves_forward() {
+    let mut camera = create_camera();
+    camera.set_speed(2.0);
+    camera
+        .bindings_mut()
+        .bind(Action::MoveForward, Binding::Key(Key::Up));
+    assert!(camera
+        .bindings()
+        .is_bound(Action::MoveForward, Binding::Key(Key::W)));
+
+    camera.handle_input(CameraInput::Key {
+        key: Key::Up,
+        pressed: true,
+    });
+    camera.update(0.5);
+    assert!(camera
+        .position()
+        .abs_diff_eq(Vec3A::new(0.0, 0.0, 9.0), 1e-4));
+}
+
+#[test]
+fn test_binding_moves_between_actions() {
+    let mut bindings = InputBindings::default();
+    bindings.bind(Action::Look, Binding::Button(MouseButton::Middle));
+    assert_eq!(
+        bindings.action(Binding::Button(MouseButton::Middle)),
+        Some(Action::Look)
+    );
+    assert!(bindings.bindings(Action::Pan).is_empty());
+
+    bindings.unbind(Action::Look, Binding::Button(MouseButton::Right));
+    let mut camera = create_camera();
+    camera.set_bindings(bindings);
+    let view = camera.view_matrix();
+    camera.handle_input(CameraInput::Button {
+        button: MouseButton::Right,
+        pressed: true,
+    });
+    camera.handle_input(CameraInput::MouseDelta { x: 100.0, y: 0.0 });
+    assert_eq!(camera.view_matrix(), view);
+}
+
+#[test]
+fn test_interpolated_uniform_blends_poses() {
+    let mut camera = create_camera();
+    let previous = camera.state();
+    camera.set_state(&CameraState {
+        position: [0.0, 0.0, 20.0],
+        ..previous
+    });
+
+    let halfway = camera.interpolated_uniform(&previous, 0.5);
+    assert!(halfway
+        .origin
+        .abs_diff_eq(glam::Vec3::new(0.0, 0.0, 15.0), 1e-4));
+    let current = camera.interpolated_uniform(&camera.state(), 0.5);
+    assert_eq!(current.view, camera.camera_uniform().view);
+}
+
+fn keyframe_state(position: [f32; 3], yaw: f32) -> CameraState {
+    CameraState {
+        position,
+        yaw,
+        ..create_camera().state()
+    }
+}
+
+fn assert_state_near(actual: CameraState, expected: CameraState) {
+    let position = Vec3A::from(actual.position);
+    assert!(
+        position.abs_diff_eq(Vec3A::from(expected.position), 1e-4),
+        "{:?} != {:?}",
+        actual,
+        expected
+    );
+    assert!((actual.yaw - expected.yaw).abs() < 1e-4);
+    assert!((actual.fov - expected.fov).abs() < 1e-4);
+}
+
+#[test]
+fn test_path_passes_through_keyframes() {
+    let mut path = CameraPath::new();
+    let keyframes = [
+        (0.0, keyframe_state([0.0, 0.0, 10.0], 0.0)),
+        (1.0, keyframe_state([5.0, 2.0, 10.0], 45.0)),
+        (3.0, keyframe_state([5.0, 0.0, -4.0], 90.0)),
+        (3.5, keyframe_state([0.0, 1.0, -8.0], 80.0)),
+    ];
+    // Out of order, the path sorts them.
+    for &(time, state) in keyframes.iter().rev() {
+        path.add_keyframe(time, state);
+    }
+    assert_eq!(path.duration(), 3.5);
+
+    for &(time, state) in &keyframes {
+        assert_state_near(path.sample(time).unwrap(), state);
+    }
+    // Before the first and after the last keyframe the path holds still.
+    assert_state_near(path.sample(-1.0).unwrap(), keyframes[0].1);
+    assert_state_near(path.sample(10.0).unwrap(), keyframes[3].1);
+}
+
+#[test]
+fn test_path_seek_clamps() {
+    let mut path = CameraPath::new();
+    path.add_keyframe(0.0, keyframe_state([0.0, 0.0, 10.0], 0.0));
+    path.add_keyframe(2.0, keyframe_state([4.0, 0.0, 10.0], 30.0));
+
+    path.seek(1.0);
+    assert_eq!(path.time(), 1.0);
+    path.seek(-1.0);
+    assert_eq!(path.time(), 0.0);
+    path.seek(5.0);
+    assert_eq!(path.time(), 2.0);
+}
+
+#[test]
+fn test_single_keyframe_path_holds_its_pose() {
+    let state = keyframe_state([1.0, 2.0, 3.0], 60.0);
+    let mut path = CameraPath::new();
+    path.add_keyframe(1.0, state);
+
+    assert_eq!(path.sample(0.0), Some(state));
+    assert_eq!(path.sample(1.0), Some(state));
+    assert_eq!(path.sample(2.0), Some(state));
+
+    path.seek(0.0);
+    path.play();
+    assert_eq!(path.advance(0.5), Some(state));
+}
+
+#[test]
+fn test_empty_path() {
+    let mut path = CameraPath::new();
+    assert_eq!(path.duration(), 0.0);
+    assert_eq!(path.sample(0.0), None);
+
+    path.play();
+    assert!(!path.is_playing());
+    assert_eq!(path.advance(1.0), None);
+}
+
+#[test]
+fn test_path_rolls_the_short_way() {
+    let mut path = CameraPath::new();
+    for &(time, roll) in &[(0.0, 170.0), (1.0, -170.0)] {
+        path.add_keyframe(
+            time,
+            CameraState {
+                roll,
+                ..create_camera().state()
+            },
+        );
+    }
+    // Halfway through the 20° turn across ±180°, not through 0°.
+    let roll = path.sample(0.5).unwrap().roll;
+    assert!((roll.rem_euclid(360.0) - 180.0).abs() < 1e-3, "{}", roll);
+}
+
+#[test]
+fn test_loaded_path_is_sorted_and_finite() {
+    let mut path = CameraPath::new();
+    path.add_keyframe(0.0, keyframe_state([0.0, 0.0, 10.0], 0.0));
+    path.add_keyframe(1.0, keyframe_state([4.0, 0.0, 10.0], 30.0));
+    let file = std::env::temp_dir().join(format!("camera-path-{}.json", std::process::id()));
+    path.save(&file).unwrap();
+    let json = std::fs::read_to_string(&file).unwrap();
+
+    std::fs::write(&file, json.replace("\"time\": 0.0", "\"time\": 2.0")).unwrap();
+    let loaded = CameraPath::load(&file).unwrap();
+    let times = loaded
+        .keyframes()
+        .iter()
+        .map(|k| k.time)
+        .collect::<Vec<_>>();
+    assert_eq!(times, [1.0, 2.0]);
+
+    // Too large for an f32, so it loads as infinity.
+    std::fs::write(&file, json.replace("\"time\": 0.0", "\"time\": 1e300")).unwrap();
+    let error = CameraPath::load(&file).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    std::fs::remove_file(&file).unwrap();
+}
@@ -6,12 +6,16 @@ use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Instant;
 
-use bytemuck::cast_slice;
 use camera::Camera;
 use image::ImageBuffer;
 use safe_vk::{vk, PipelineRecorder};
+use settings::Settings;
 use vk::CommandBuffer;
 
+use crate::Args;
+
+const DEFAULT_SCENE: &str = "./cornell-box/models/CornellBox.glb";
+
 const WIDTH: u32 = 800;
 const HEIGHT: u32 = 600;
 
@@ -19,81 +23,67 @@ const WORKGROUP_WIDTH: u32 = 16;
 const WORKGROUP_HEIGHT: u32 = 8;
 
 pub struct Engine {
-    ui_platform: egui_winit_platform::Platform,
+    ui_platform: egui_backend::Platform,
+    ui_textures_delta: egui::TexturesDelta,
     size: winit::dpi::PhysicalSize<u32>,
     scale_factor: f64,
-    swapchain: Arc<safe_vk::Swapchain>,
+    target: engine_core::FrameTarget,
     queue: safe_vk::Queue,
     ui_pass: egui_backend::UiPass,
     command_pool: Arc<safe_vk::CommandPool>,
     time: Instant,
-    swapchain_images: Vec<Arc<safe_vk::Image>>,
-    render_finish_semaphore: safe_vk::BinarySemaphore,
     render_finish_fence: Arc<safe_vk::Fence>,
     allocator: Arc<safe_vk::Allocator>,
+    /// Transient images of the render graphs of earlier frames.
+    transient_images: safe_vk::TransientImagePool,
     pipeline: Arc<safe_vk::ComputePipeline>,
     descriptor_set: Arc<safe_vk::DescriptorSet>,
     result_image: Arc<safe_vk::Image>,
     uniform_buffer: Arc<safe_vk::Buffer>,
     camera: Camera,
     scene: gltf_wrapper::Scene,
+    /// The file `scene` was loaded from.
+    scene_path: PathBuf,
+    /// Written back on exit.
+    settings: Settings,
 }
 
 impl Engine {
-    pub fn new(window: &winit::window::Window) -> Self {
+    pub fn new(window: &winit::window::Window, args: &Args, settings: Settings) -> Self {
         let size = window.inner_size();
         let scale_factor = window.scale_factor();
-        let ui_platform =
-            egui_winit_platform::Platform::new(egui_winit_platform::PlatformDescriptor {
-                physical_width: size.width,
-                physical_height: size.height,
-                scale_factor,
-                font_definitions: Default::default(),
-                style: Default::default(),
-            });
-        let entry = Arc::new(safe_vk::Entry::new().unwrap());
-        let instance = Arc::new(safe_vk::Instance::new(
-            entry,
-            &[
-                safe_vk::name::instance::Layer::KhronosValidation,
-                safe_vk::name::instance::Layer::LunargMonitor,
-            ],
-            &[
-                safe_vk::name::instance::Extension::KhrWin32Surface,
-                safe_vk::name::instance::Extension::KhrSurface,
-                safe_vk::name::instance::Extension::ExtDebugUtils,
-            ],
-        ));
-        let surface = Arc::new(safe_vk::Surface::new(instance.clone(), window));
-
-        let pdevice = Arc::new(safe_vk::PhysicalDevice::new(instance, Some(surface)));
-        let device = Arc::new(safe_vk::Device::new(
-            pdevice,
-            &vk::PhysicalDeviceFeatures {
-                fragment_stores_and_atomics: vk::TRUE,
-                vertex_pipeline_stores_and_atomics: vk::TRUE,
-                ..Default::default()
+        let ui_platform = engine_core::ui_platform(size, scale_factor);
+        let engine_core::Gpu {
+            device,
+            allocator,
+            queue,
+            command_pool,
+            target,
+            render_finish_fence,
+        } = engine_core::Gpu::new(
+            window,
+            &engine_core::GpuDescriptor {
+                device_index: args.device,
+                validation: !args.no_validation,
+                present_mode: engine_core::present_mode(settings.vsync),
             },
-            &[
-                safe_vk::name::device::Extension::KhrSwapchain,
-                safe_vk::name::device::Extension::KhrAccelerationStructure,
-                safe_vk::name::device::Extension::KhrDeferredHostOperations,
-                safe_vk::name::device::Extension::KhrShaderNonSemanticInfo,
-                safe_vk::name::device::Extension::KhrRayQuery,
-            ],
-        ));
-        let swapchain = Arc::new(safe_vk::Swapchain::new(device.clone()));
-        let mut queue = safe_vk::Queue::new(device.clone());
-        let allocator = Arc::new(safe_vk::Allocator::new(device.clone()));
-        let ui_pass = egui_backend::UiPass::new(allocator.clone());
-        let command_pool = Arc::new(safe_vk::CommandPool::new(device.clone()));
+            |_| engine_core::DeviceRequest {
+                extensions: vec![
+                    safe_vk::name::device::Extension::KhrAccelerationStructure,
+                    safe_vk::name::device::Extension::KhrDeferredHostOperations,
+                    safe_vk::name::device::Extension::KhrShaderNonSemanticInfo,
+                    safe_vk::name::device::Extension::KhrRayQuery,
+                ],
+                features: vk::PhysicalDeviceFeatures {
+                    fragment_stores_and_atomics: vk::TRUE,
+                    vertex_pipeline_stores_and_atomics: vk::TRUE,
+                    ..Default::default()
+                },
+            },
+        )
+        .unwrap_or_else(|e| panic!("failed to create the device: {}", e));
+        let ui_pass = engine_core::ui_pass(allocator.clone(), &target, size, scale_factor);
         let time = Instant::now();
-        let swapchain_images = safe_vk::Image::from_swapchain(swapchain.clone())
-            .into_iter()
-            .map(Arc::new)
-            .collect::<Vec<_>>();
-        let render_finish_semaphore = safe_vk::BinarySemaphore::new(device.clone());
-        let render_finish_fence = Arc::new(safe_vk::Fence::new(device.clone(), true));
 
         let descriptor_set_layout = Arc::new(safe_vk::DescriptorSetLayout::new(
             device.clone(),
@@ -126,9 +116,10 @@ impl Engine {
             device.clone(),
             Some("compute pipeline layout"),
             &[&descriptor_set_layout],
+            &[],
         ));
 
-        let mut result_image = safe_vk::Image::new(
+        let result_image = Arc::new(safe_vk::Image::new(
             Some("result image"),
             allocator.clone(),
             vk::Format::R32G32B32A32_SFLOAT,
@@ -139,31 +130,20 @@ impl Engine {
                 | vk::ImageUsageFlags::TRANSFER_DST
                 | vk::ImageUsageFlags::TRANSFER_SRC,
             safe_vk::MemoryUsage::GpuOnly,
-        );
-
-        result_image.set_layout(vk::ImageLayout::GENERAL, &mut queue, command_pool.clone());
-
-        let result_image = Arc::new(result_image);
+        ));
 
         let result_image_view = Arc::new(safe_vk::ImageView::new(result_image.clone()));
 
-        let mut descriptor_set = safe_vk::DescriptorSet::new(
-            Some("Main descriptor set"),
-            Arc::new(safe_vk::DescriptorPool::new(
-                device.clone(),
-                &[vk::DescriptorPoolSize::builder()
-                    .ty(vk::DescriptorType::STORAGE_IMAGE)
-                    .descriptor_count(1)
-                    .build()],
-                1,
-            )),
-            descriptor_set_layout.clone(),
-        );
+        let descriptor_set = safe_vk::DescriptorAllocator::new(descriptor_set_layout.clone(), 1)
+            .allocate(Some("Main descriptor set"));
 
-        let scene = gltf_wrapper::Scene::from_file(
-            allocator.clone(),
-            "./cornell-box/models/CornellBox.glb",
-        );
+        // A last scene that has been moved or deleted since falls back to the default.
+        let scene_path = args
+            .scene
+            .clone()
+            .or_else(|| settings.last_scene.clone().filter(|path| path.exists()))
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_SCENE));
+        let scene = gltf_wrapper::Scene::from_file(allocator.clone(), &scene_path);
         // let scene = gltf_wrapper::Scene::from_file(
         //     allocator.clone(),
         //     "./models/2.0/DamagedHelmet/glTF-Binary/DamagedHelmet.glb",
@@ -226,27 +206,43 @@ impl Engine {
             glam::Vec3A::new(0.0, 0.0, 0.0),
         );
 
+        let transient_images = safe_vk::TransientImagePool::new(allocator.clone());
+
         log::info!("pipeline created");
 
         Self {
             ui_platform,
+            ui_textures_delta: Default::default(),
             size,
             scale_factor,
-            swapchain,
+            target,
             queue,
             ui_pass,
             command_pool,
             time,
-            swapchain_images,
-            render_finish_semaphore,
             render_finish_fence,
             allocator,
+            transient_images,
             pipeline,
             descriptor_set,
             result_image,
             uniform_buffer,
             camera,
             scene,
+            scene_path,
+            settings,
+        }
+    }
+
+    /// Writes the window size and the scene to the settings file.
+    pub fn save_settings(&mut self) {
+        // A minimized window reopens at its size before.
+        if self.size.width > 0 && self.size.height > 0 {
+            self.settings.window_size = [self.size.width, self.size.height];
+        }
+        self.settings.last_scene = Some(self.scene_path.clone());
+        if let Err(e) = self.settings.save(crate::SETTINGS_APP) {
+            log::warn!("failed to save settings: {}", e);
         }
     }
 
@@ -279,9 +275,36 @@ impl Engine {
     //     self.storage_buffer.unmap();
     // }
 
+    /// The result image keeps its size, the blit scales it to the window.
+    fn resize(&mut self, new_size: &winit::dpi::PhysicalSize<u32>) {
+        self.size = *new_size;
+        self.ui_pass
+            .set_screen_descriptor(engine_core::screen_descriptor(self.size, self.scale_factor));
+        // Minimized, keep everything as is until the window comes back.
+        if !self.target.resize(self.size) {
+            return;
+        }
+        self.transient_images.clear();
+    }
+
     pub fn handle_event(&mut self, event: &winit::event::Event<()>) {
         self.ui_platform.handle_event(event);
         self.camera.input(event);
+        if let winit::event::Event::WindowEvent { event, .. } = event {
+            match event {
+                winit::event::WindowEvent::Resized(size) => {
+                    self.resize(size);
+                }
+                winit::event::WindowEvent::ScaleFactorChanged {
+                    scale_factor,
+                    new_inner_size,
+                } => {
+                    self.scale_factor = *scale_factor;
+                    self.resize(new_inner_size);
+                }
+                _ => {}
+            }
+        }
     }
 
     pub fn update(&mut self) {
@@ -292,10 +315,10 @@ impl Engine {
             .update_time(self.time.elapsed().as_secs_f64());
         self.ui_platform.begin_frame();
 
-        egui::TopPanel::top(egui::Id::new("menu bar")).show(&self.ui_platform.context(), |ui| {
+        egui::TopBottomPanel::top("menu bar").show(&self.ui_platform.context(), |ui| {
             egui::menu::bar(ui, |ui| {
-                egui::menu::menu(ui, "File", |ui| {
-                    if ui.button("Open").clicked {
+                ui.menu_button("File", |ui| {
+                    if ui.button("Open").clicked() {
                         match nfd2::open_file_dialog(Some("gltf,glb"), Some(current_dir.as_ref()))
                             .unwrap()
                         {
@@ -308,138 +331,119 @@ impl Engine {
             });
         });
 
-        let (_, shapes) = self.ui_platform.end_frame();
-        let paint_jobs = self.ui_platform.context().tessellate(shapes);
-        self.ui_pass.update_buffers(
-            &paint_jobs,
-            &egui_backend::ScreenDescriptor {
-                physical_width: self.size.width,
-                physical_height: self.size.height,
-                scale_factor: self.scale_factor as f32,
-            },
-        );
-        self.ui_pass
-            .update_texture(&self.ui_platform.context().texture());
+        let full_output = self.ui_platform.end_frame();
+        let paint_jobs = self.ui_platform.context().tessellate(full_output.shapes);
+        self.ui_pass.update_buffers(&paint_jobs);
+        // Frames skipped while minimized leave their texture changes for the next render.
+        self.ui_textures_delta.append(full_output.textures_delta);
 
-        self.uniform_buffer.copy_from(bytemuck::cast_slice(
-            self.camera.camera_uniform().origin.as_ref(),
-        ));
+        self.uniform_buffer
+            .write(0, self.camera.camera_uniform().origin.as_ref());
     }
 
     pub fn render(&mut self) {
-        let (index, _) = self.swapchain.acquire_next_image();
+        if self.target.is_zero_sized() {
+            return;
+        }
+        let (index, target_image) = match self.target.acquire() {
+            Some(acquired) => acquired,
+            None => {
+                self.resize(&self.size.clone());
+                return;
+            }
+        };
         let mut command_buffer = safe_vk::CommandBuffer::new(self.command_pool.clone());
 
-        let target_image = self.swapchain_images[index as usize].clone();
-
-        command_buffer.encode(|recorder| {
-            recorder.set_image_layout(
-                self.result_image.clone(),
-                Some(vk::ImageLayout::UNDEFINED),
-                vk::ImageLayout::GENERAL,
-            );
-            recorder.bind_compute_pipeline(self.pipeline.clone(), |rec, pipeline| {
-                rec.bind_descriptor_sets(vec![self.descriptor_set.clone()], pipeline.layout(), 0);
-
-                rec.dispatch(
-                    (WIDTH as f32 / WORKGROUP_WIDTH as f32).ceil() as u32,
-                    (HEIGHT as f32 / WORKGROUP_HEIGHT as f32).ceil() as u32,
-                    1,
+        let Self {
+            ref mut ui_pass,
+            ref ui_textures_delta,
+            ref pipeline,
+            ref descriptor_set,
+            ref result_image,
+            ref mut transient_images,
+            ..
+        } = *self;
+        let mut graph = safe_vk::RenderGraph::new(transient_images);
+        // The result is traced anew every frame.
+        let result = graph.import_image(result_image.clone(), Some(vk::ImageLayout::UNDEFINED));
+        let target = graph.import_image(target_image, Some(vk::ImageLayout::UNDEFINED));
+
+        graph.add_pass(
+            "trace",
+            |pass| {
+                pass.write(
+                    result,
+                    safe_vk::ImageAccess::Storage(vk::PipelineStageFlags::COMPUTE_SHADER),
                 );
-            });
-
-            recorder.set_image_layout(
-                self.result_image.clone(),
-                Some(vk::ImageLayout::GENERAL),
-                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
-            );
-            recorder.set_image_layout(
-                target_image.clone(),
-                Some(vk::ImageLayout::UNDEFINED),
-                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-            );
-            // recorder.copy_buffer_to_image(
-            //     self.storage_buffer.clone(),
-            //     self.result_image.clone(),
-            //     &[vk::BufferImageCopy::builder()
-            //         .image_extent(vk::Extent3D {
-            //             width: self.result_image.width(),
-            //             height: self.result_image.height(),
-            //             depth: 1,
-            //         })
-            //         .image_subresource(
-            //             vk::ImageSubresourceLayers::builder()
-            //                 .aspect_mask(vk::ImageAspectFlags::COLOR)
-            //                 .layer_count(1)
-            //                 .base_array_layer(0)
-            //                 .mip_level(0)
-            //                 .build(),
-            //         )
-            //         .build()],
-            // );
-
-            recorder.blit_image(
-                self.result_image.clone(),
-                target_image.clone(),
-                &[vk::ImageBlit::builder()
-                    .src_subresource(
-                        vk::ImageSubresourceLayers::builder()
-                            .aspect_mask(vk::ImageAspectFlags::COLOR)
-                            .layer_count(1)
-                            .base_array_layer(0)
-                            .mip_level(0)
-                            .build(),
-                    )
-                    .src_offsets([
-                        vk::Offset3D { x: 0, y: 0, z: 0 },
-                        vk::Offset3D {
-                            x: self.result_image.width() as i32,
-                            y: self.result_image.height() as i32,
-                            z: 1,
-                        },
-                    ])
-                    .dst_offsets([
-                        vk::Offset3D { x: 0, y: 0, z: 0 },
-                        vk::Offset3D {
-                            x: target_image.width() as i32,
-                            y: target_image.height() as i32,
-                            z: 1,
-                        },
-                    ])
-                    .dst_subresource(
-                        vk::ImageSubresourceLayers::builder()
-                            .aspect_mask(vk::ImageAspectFlags::COLOR)
-                            .layer_count(1)
-                            .base_array_layer(0)
-                            .mip_level(0)
-                            .build(),
-                    )
-                    .build()],
-                vk::Filter::NEAREST,
-            );
-            recorder.set_image_layout(
-                target_image.clone(),
-                None,
-                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-            );
-            self.ui_pass.execute(
-                recorder,
-                target_image,
-                &egui_backend::ScreenDescriptor {
-                    physical_width: self.size.width,
-                    physical_height: self.size.height,
-                    scale_factor: self.scale_factor as f32,
-                },
-            );
-        });
-        self.render_finish_fence.wait();
-        self.render_finish_fence = self.queue.submit_binary(
-            command_buffer,
-            &[&self.swapchain.image_available_semaphore()],
-            &[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT],
-            &[&self.render_finish_semaphore],
+            },
+            |recorder, _| {
+                recorder.bind_compute_pipeline(pipeline.clone(), |rec, pipeline| {
+                    rec.bind_descriptor_sets(vec![descriptor_set.clone()], pipeline.layout(), 0);
+
+                    rec.dispatch(
+                        (WIDTH as f32 / WORKGROUP_WIDTH as f32).ceil() as u32,
+                        (HEIGHT as f32 / WORKGROUP_HEIGHT as f32).ceil() as u32,
+                        1,
+                    );
+                });
+            },
         );
-        self.queue
-            .present(&self.swapchain, index, &[&self.render_finish_semaphore])
+        graph.add_pass(
+            "blit",
+            |pass| {
+                pass.read(result, safe_vk::ImageAccess::TransferSrc)
+                    .write(target, safe_vk::ImageAccess::TransferDst);
+            },
+            |recorder, images| {
+                let (result_image, target_image) = (images.get(result), images.get(target));
+                recorder.blit_image(
+                    result_image.clone(),
+                    target_image.clone(),
+                    &[vk::ImageBlit::builder()
+                        .src_subresource(
+                            vk::ImageSubresourceLayers::builder()
+                                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                                .layer_count(1)
+                                .base_array_layer(0)
+                                .mip_level(0)
+                                .build(),
+                        )
+                        .src_offsets([
+                            vk::Offset3D { x: 0, y: 0, z: 0 },
+                            vk::Offset3D {
+                                x: result_image.width() as i32,
+                                y: result_image.height() as i32,
+                                z: 1,
+                            },
+                        ])
+                        .dst_offsets([
+                            vk::Offset3D { x: 0, y: 0, z: 0 },
+                            vk::Offset3D {
+                                x: target_image.width() as i32,
+                                y: target_image.height() as i32,
+                                z: 1,
+                            },
+                        ])
+                        .dst_subresource(
+                            vk::ImageSubresourceLayers::builder()
+                                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                                .layer_count(1)
+                                .base_array_layer(0)
+                                .mip_level(0)
+                                .build(),
+                        )
+                        .build()],
+                    vk::Filter::NEAREST,
+                );
+            },
+        );
+        ui_pass.add_to_graph(&mut graph, target, &[], ui_textures_delta, |_, _| {});
+        command_buffer.encode(|recorder| graph.execute(recorder));
+        self.render_finish_fence.wait();
+        self.ui_pass.frame_finished();
+        self.render_finish_fence = self.target.submit(&mut self.queue, command_buffer);
+        self.ui_pass
+            .free_textures(&std::mem::take(&mut self.ui_textures_delta));
+        self.target.present(&self.queue, index);
     }
 }
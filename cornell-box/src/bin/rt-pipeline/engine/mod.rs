@@ -7,17 +7,59 @@ use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use bytemuck::cast_slice;
-use camera::{Camera, CameraUniform};
+use camera::{
+    Action, Binding, Camera, CameraMode, CameraPath, CameraPresets, CameraState, CameraUniform,
+};
 use image::ImageBuffer;
-use safe_vk::{vk, PipelineRecorder};
+use render_pass::tone_map::{ToneMap, ToneMapOperator};
+use safe_vk::{vk, Pipeline, PipelineRecorder};
+use settings::Settings;
 use vk::CommandBuffer;
 
 use bytemuck::{Pod, Zeroable};
 
 mod scene;
 
+use engine_core::adaptive::AdaptiveSampling;
+use engine_core::benchmark::{Benchmark, DeviceInfo};
+use engine_core::capture;
+use engine_core::environment::Environment;
+use engine_core::offline::{CompletionAction, OfflineRender};
 use scene::Scene;
 
+use crate::Args;
+
+// Matches the vertical slope of 1/5 the scenes were originally framed with.
+const CAMERA_FOV: f32 = 22.62;
+
+/// Camera updates per second, renders in between interpolate.
+const UPDATE_RATE: f64 = 120.0;
+
+/// Frame rate cap offered when the cap is turned on.
+const DEFAULT_MAX_FPS: u32 = 60;
+
+/// Where camera presets were saved before they moved to the settings. Imported when the settings
+/// have none.
+const CAMERA_PRESETS_PATH: &str = "./cornell-box/camera-presets.json";
+
+const DEFAULT_SCENE: &str = "./cornell-box/models/CornellBox.glb";
+
+const SCREENSHOT_DIR: &str = "./cornell-box/screenshots";
+
+const CAPTURE_DIR: &str = "./cornell-box/captures";
+
+/// Where the Camera menu saves the camera path, for `--benchmark`.
+const CAMERA_PATH_FILE: &str = "./cornell-box/camera-path.json";
+
+/// Where benchmark reports go unless `--benchmark-report` says otherwise.
+const BENCHMARK_DIR: &str = "./cornell-box/benchmarks";
+
+/// The GLSL sources of `shaders::Shaders`, watched for changes in debug builds.
+const SHADER_DIR: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/src/bin/rt-pipeline/engine/shaders"
+);
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Pod, Zeroable)]
 struct PushConstants {
@@ -25,6 +67,20 @@ struct PushConstants {
     render_height: u32,
     sample_count: u32,
     batch_sample_count: u32,
+    /// Samples a light at every diffuse vertex when non-zero.
+    nee_enabled: u32,
+    environment_rotation: f32,
+    environment_intensity: f32,
+    /// The pixel raytrace.rgen probes the focus distance at, `u32::MAX` when not picking.
+    focus_probe_x: u32,
+    focus_probe_y: u32,
+    adaptive_enabled: u32,
+    adaptive_threshold: f32,
+    adaptive_min_samples: u32,
+    /// Paths end after this many bounces off surfaces.
+    max_bounces: u32,
+    /// Bounce from which paths are terminated at random, based on the light they carry.
+    russian_roulette_start: u32,
 }
 
 #[derive(Debug, Clone)]
@@ -35,102 +91,119 @@ struct FpsCounter {
 }
 
 pub struct Engine {
-    ui_platform: egui_winit_platform::Platform,
+    ui_platform: egui_backend::Platform,
+    ui_textures_delta: egui::TexturesDelta,
     size: winit::dpi::PhysicalSize<u32>,
     scale_factor: f64,
-    swapchain: Arc<safe_vk::Swapchain>,
+    target: engine_core::FrameTarget,
     queue: safe_vk::Queue,
     ui_pass: egui_backend::UiPass,
+    hdr_inspector: egui_backend::HdrInspector,
+    show_hdr_inspector: bool,
+    show_render_settings: bool,
     command_pool: Arc<safe_vk::CommandPool>,
     time: Instant,
-    swapchain_images: Vec<Arc<safe_vk::Image>>,
-    render_finish_semaphore: safe_vk::BinarySemaphore,
+    timestep: frame_loop::FixedTimestep,
+    /// The camera before the last update, renders interpolate from it.
+    previous_camera_state: CameraState,
+    frame_limiter: frame_loop::FrameLimiter,
     render_finish_fence: Arc<safe_vk::Fence>,
     allocator: Arc<safe_vk::Allocator>,
     pipeline: Arc<safe_vk::RayTracingPipeline>,
+    descriptor_allocator: safe_vk::DescriptorAllocator,
     descriptor_set: Arc<safe_vk::DescriptorSet>,
     result_image: Arc<safe_vk::Image>,
     tone_mapped_image: Arc<safe_vk::Image>,
+    tone_map: ToneMap,
+    adaptive_sampling: AdaptiveSampling,
+    /// Transient images of the render graphs of earlier frames.
+    transient_images: safe_vk::TransientImagePool,
+    offline_render: OfflineRender,
+    screenshot_requested: bool,
+    toasts: egui_backend::Toasts,
+    frame_capture: capture::FrameCapture,
     uniform_buffer: Arc<safe_vk::Buffer>,
+    /// Distance written by raytrace.rgen at the probed pixel.
+    focus_probe_buffer: Arc<safe_vk::Buffer>,
+    /// The next click on the image sets the focus distance.
+    picking_focus: bool,
+    cursor_position: winit::dpi::PhysicalPosition<f64>,
     camera: Camera,
+    camera_presets: CameraPresets,
+    camera_path: CameraPath,
     scene: Scene,
+    /// The file `scene` was loaded from.
+    scene_path: PathBuf,
+    /// Plays the camera path of `--benchmark`, the UI is hidden while it runs.
+    benchmark: Option<Benchmark>,
+    /// Written back on exit, unless headless.
+    settings: Settings,
+    headless: bool,
+    environment: Environment,
+    /// Replaced scenes, dropped once the frame that last used them has finished.
+    retired_scenes: Vec<(Arc<safe_vk::Fence>, Scene)>,
     push_constants: PushConstants,
     fps_counter: FpsCounter,
+    frame_stats: egui_backend::FrameStats,
+    show_frame_stats: bool,
+    memory_panel: egui_backend::MemoryPanel,
+    show_memory_panel: bool,
+    log_console: egui_backend::LogConsole,
+    show_log_console: bool,
+    bindings_editor: egui_backend::BindingsEditor,
+    show_input_bindings: bool,
+    /// Recompiles the shaders when their sources change. Only in debug builds, where
+    /// `shaders::Shaders` reads the SPIR-V from disk instead of embedding it.
+    shader_watcher: Option<safe_vk::ShaderWatcher>,
+    /// Compiler output of the last shaders that failed to reload, shown until they compile.
+    shader_errors: Vec<String>,
     sample_speed: f64,
-    old_camera_position: glam::Vec3A,
+    old_camera_uniform: CameraUniform,
 }
 
 impl Engine {
-    pub fn new(window: &winit::window::Window) -> Self {
-        let size = window.inner_size();
-        let scale_factor = window.scale_factor();
-        let ui_platform =
-            egui_winit_platform::Platform::new(egui_winit_platform::PlatformDescriptor {
-                physical_width: size.width,
-                physical_height: size.height,
-                scale_factor,
-                font_definitions: Default::default(),
-                style: Default::default(),
-            });
-        let entry = Arc::new(safe_vk::Entry::new().unwrap());
-        #[cfg(target_os = "linux")]
-        let extensions = vec![
-            safe_vk::name::instance::Extension::KhrSurface,
-            safe_vk::name::instance::Extension::ExtDebugUtils,
-            safe_vk::name::instance::Extension::KhrXcbSurface,
-            safe_vk::name::instance::Extension::KhrXlibSurface,
-        ];
-        #[cfg(target_os = "windows")]
-        let extensions = vec![
-            safe_vk::name::instance::Extension::KhrSurface,
-            safe_vk::name::instance::Extension::ExtDebugUtils,
-            safe_vk::name::instance::Extension::KhrWin32Surface,
-        ];
-        let instance = Arc::new(safe_vk::Instance::new(
-            entry,
-            &[
-                safe_vk::name::instance::Layer::KhronosValidation,
-                safe_vk::name::instance::Layer::LunargMonitor,
-            ],
-            extensions.as_slice(),
-        ));
-        let surface = Arc::new(safe_vk::Surface::new(instance.clone(), window));
-
-        let pdevice = Arc::new(safe_vk::PhysicalDevice::new(
-            instance,
-            Some(surface.as_ref()),
-        ));
-        let device = Arc::new(safe_vk::Device::new(
-            pdevice,
-            &vk::PhysicalDeviceFeatures {
-                fragment_stores_and_atomics: vk::TRUE,
-                vertex_pipeline_stores_and_atomics: vk::TRUE,
-                ..Default::default()
-            },
-            &[
-                safe_vk::name::device::Extension::KhrSwapchain,
+    /// Renders to `window`, or offscreen at `size` without one.
+    pub fn new(
+        window: Option<&winit::window::Window>,
+        size: winit::dpi::PhysicalSize<u32>,
+        args: &Args,
+        settings: Settings,
+    ) -> Self {
+        let scale_factor = window.map_or(1.0, |window| window.scale_factor());
+        let ui_platform = engine_core::ui_platform(size, scale_factor);
+        let descriptor = engine_core::GpuDescriptor {
+            device_index: args.device,
+            validation: !args.no_validation,
+            // Benchmarks measure the frame rate the device reaches, not the display.
+            present_mode: engine_core::present_mode(settings.vsync && args.benchmark.is_none()),
+        };
+        let request = |_: &safe_vk::PhysicalDevice| engine_core::DeviceRequest {
+            extensions: vec![
                 safe_vk::name::device::Extension::KhrAccelerationStructure,
                 safe_vk::name::device::Extension::KhrDeferredHostOperations,
                 safe_vk::name::device::Extension::KhrShaderNonSemanticInfo,
                 safe_vk::name::device::Extension::KhrRayTracingPipeline,
             ],
-        ));
-        let swapchain = Arc::new(safe_vk::Swapchain::new(
-            device.clone(),
-            surface.clone(),
-            vk::PresentModeKHR::IMMEDIATE,
-        ));
-        let mut queue = safe_vk::Queue::new(device.clone());
-        let allocator = Arc::new(safe_vk::Allocator::new(device.clone()));
-        let ui_pass = egui_backend::UiPass::new(allocator.clone());
-        let command_pool = Arc::new(safe_vk::CommandPool::new(device.clone()));
+            features: vk::PhysicalDeviceFeatures {
+                fragment_stores_and_atomics: vk::TRUE,
+                vertex_pipeline_stores_and_atomics: vk::TRUE,
+                ..Default::default()
+            },
+        };
+        let engine_core::Gpu {
+            device,
+            allocator,
+            mut queue,
+            command_pool,
+            target,
+            render_finish_fence,
+        } = match window {
+            Some(window) => engine_core::Gpu::new(window, &descriptor, request),
+            None => engine_core::Gpu::headless(&descriptor, size, request),
+        }
+        .unwrap_or_else(|e| panic!("failed to create the device: {}", e));
+        let ui_pass = engine_core::ui_pass(allocator.clone(), &target, size, scale_factor);
         let time = Instant::now();
-        let swapchain_images = safe_vk::Image::from_swapchain(swapchain.clone())
-            .into_iter()
-            .map(Arc::new)
-            .collect::<Vec<_>>();
-        let render_finish_semaphore = safe_vk::BinarySemaphore::new(device.clone());
-        let render_finish_fence = Arc::new(safe_vk::Fence::new(device.clone(), true));
 
         let descriptor_set_layout = Arc::new(safe_vk::DescriptorSetLayout::new(
             device.clone(),
@@ -158,7 +231,7 @@ impl Engine {
                 },
                 safe_vk::DescriptorSetLayoutBinding {
                     binding: 4,
-                    descriptor_type: safe_vk::DescriptorType::StorageImage,
+                    descriptor_type: safe_vk::DescriptorType::StorageBuffer,
                     stage_flags: vk::ShaderStageFlags::RAYGEN_KHR,
                 },
                 safe_vk::DescriptorSetLayoutBinding {
@@ -166,6 +239,36 @@ impl Engine {
                     descriptor_type: safe_vk::DescriptorType::UniformBuffer,
                     stage_flags: vk::ShaderStageFlags::RAYGEN_KHR,
                 },
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 6,
+                    descriptor_type: safe_vk::DescriptorType::StorageBuffer,
+                    stage_flags: vk::ShaderStageFlags::RAYGEN_KHR,
+                },
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 7,
+                    descriptor_type: safe_vk::DescriptorType::StorageBuffer,
+                    stage_flags: vk::ShaderStageFlags::CLOSEST_HIT_KHR,
+                },
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 8,
+                    descriptor_type: safe_vk::DescriptorType::StorageBuffer,
+                    stage_flags: vk::ShaderStageFlags::CLOSEST_HIT_KHR,
+                },
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 9,
+                    descriptor_type: safe_vk::DescriptorType::StorageImage,
+                    stage_flags: vk::ShaderStageFlags::RAYGEN_KHR,
+                },
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 10,
+                    descriptor_type: safe_vk::DescriptorType::StorageBuffer,
+                    stage_flags: vk::ShaderStageFlags::RAYGEN_KHR,
+                },
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 14,
+                    descriptor_type: safe_vk::DescriptorType::StorageBuffer,
+                    stage_flags: vk::ShaderStageFlags::RAYGEN_KHR,
+                },
             ],
         ));
 
@@ -184,8 +287,8 @@ impl Engine {
             Some("result image"),
             allocator.clone(),
             vk::Format::R32G32B32A32_SFLOAT,
-            swapchain.width(),
-            swapchain.height(),
+            target.width(),
+            target.height(),
             vk::ImageTiling::OPTIMAL,
             vk::ImageUsageFlags::STORAGE
                 | vk::ImageUsageFlags::TRANSFER_DST
@@ -197,8 +300,8 @@ impl Engine {
             Some("tone mapped image"),
             allocator.clone(),
             vk::Format::R32G32B32A32_SFLOAT,
-            swapchain.width(),
-            swapchain.height(),
+            target.width(),
+            target.height(),
             vk::ImageTiling::OPTIMAL,
             vk::ImageUsageFlags::STORAGE
                 | vk::ImageUsageFlags::TRANSFER_DST
@@ -210,152 +313,96 @@ impl Engine {
         tone_mapped_image.set_layout(vk::ImageLayout::GENERAL, &mut queue, command_pool.clone());
 
         let result_image = Arc::new(result_image);
+        let mut hdr_inspector = egui_backend::HdrInspector::new(allocator.clone());
+        hdr_inspector.set_source(result_image.clone());
         let tone_mapped_image = Arc::new(tone_mapped_image);
+        let mut tone_map = ToneMap::new(device.clone());
+        tone_map.set_images(result_image.clone(), tone_mapped_image.clone());
+        if let Some(operator) = settings
+            .tone_map_operator
+            .as_deref()
+            .and_then(ToneMapOperator::from_name)
+        {
+            tone_map.operator = operator;
+        }
+        let adaptive_sampling = AdaptiveSampling::new(allocator.clone(), tone_mapped_image.clone());
 
-        let result_image_view = Arc::new(safe_vk::ImageView::new(result_image.clone()));
-        let tone_mapped_image_view = Arc::new(safe_vk::ImageView::new(tone_mapped_image.clone()));
-
-        let mut descriptor_set = safe_vk::DescriptorSet::new(
-            Some("Main descriptor set"),
-            Arc::new(safe_vk::DescriptorPool::new(
-                device.clone(),
-                &[vk::DescriptorPoolSize::builder()
-                    .ty(vk::DescriptorType::STORAGE_IMAGE)
-                    .descriptor_count(1)
-                    .build()],
-                1,
-            )),
-            descriptor_set_layout.clone(),
-        );
-
-        let scene = Scene::from_file(allocator.clone(), "./cornell-box/models/CornellBox.glb");
+        // A last scene that has been moved or deleted since falls back to the default.
+        let scene_path = args
+            .scene
+            .clone()
+            .or_else(|| settings.last_scene.clone().filter(|path| path.exists()))
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_SCENE));
+        let scene = Scene::from_file(allocator.clone(), &scene_path).unwrap_or_else(|e| {
+            panic!("failed to load {}: {}", scene_path.display(), e);
+        });
+        let environment = match &args.environment {
+            Some(path) => {
+                Environment::from_hdr(allocator.clone(), &mut queue, command_pool.clone(), path)
+                    .unwrap_or_else(|e| panic!("failed to load {}: {}", path.display(), e))
+            }
+            None => Environment::gradient_sky(allocator.clone(), &mut queue, command_pool.clone()),
+        };
 
         let uniform_buffer = Arc::new(safe_vk::Buffer::new(
             Some("camera buffer"),
             allocator.clone(),
-            std::mem::size_of::<f32>() * 3,
+            std::mem::size_of::<CameraUniform>(),
             vk::BufferUsageFlags::UNIFORM_BUFFER,
             safe_vk::MemoryUsage::CpuToGpu,
         ));
-
-        descriptor_set.update(&[
-            safe_vk::DescriptorSetUpdateInfo {
-                binding: 0,
-                detail: safe_vk::DescriptorSetUpdateDetail::Image(result_image_view.clone()),
-            },
-            safe_vk::DescriptorSetUpdateInfo {
-                binding: 1,
-                detail: safe_vk::DescriptorSetUpdateDetail::AccelerationStructure(
-                    scene.tlas().clone(),
-                ),
-            },
-            safe_vk::DescriptorSetUpdateInfo {
-                binding: 2,
-                detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
-                    buffer: scene.sole_buffer().clone(),
-                    offset: scene.sole_geometry_index_buffer_offset(),
-                },
-            },
-            safe_vk::DescriptorSetUpdateInfo {
-                binding: 3,
-                detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
-                    buffer: scene.sole_buffer().clone(),
-                    offset: scene.sole_geometry_vertex_buffer_offset(),
-                },
-            },
-            safe_vk::DescriptorSetUpdateInfo {
-                binding: 4,
-                detail: safe_vk::DescriptorSetUpdateDetail::Image(tone_mapped_image_view.clone()),
-            },
-            safe_vk::DescriptorSetUpdateInfo {
-                binding: 5,
-                detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
-                    buffer: uniform_buffer.clone(),
-                    offset: 0,
-                },
-            },
-        ]);
-
-        let descriptor_set = Arc::new(descriptor_set);
-
-        let shader_stages = vec![
-            Arc::new(safe_vk::ShaderStage::new(
-                Arc::new(safe_vk::ShaderModule::new(
-                    device.clone(),
-                    shaders::Shaders::get("raytrace.rgen.spv").unwrap(),
-                )),
-                vk::ShaderStageFlags::RAYGEN_KHR,
-                "main",
-            )),
-            Arc::new(safe_vk::ShaderStage::new(
-                Arc::new(safe_vk::ShaderModule::new(
-                    device.clone(),
-                    shaders::Shaders::get("miss.rmiss.spv").unwrap(),
-                )),
-                vk::ShaderStageFlags::MISS_KHR,
-                "main",
-            )),
-            Arc::new(safe_vk::ShaderStage::new(
-                Arc::new(safe_vk::ShaderModule::new(
-                    device.clone(),
-                    shaders::Shaders::get("closest_hit_0.rchit.spv").unwrap(),
-                )),
-                vk::ShaderStageFlags::CLOSEST_HIT_KHR,
-                "main",
-            )),
-            Arc::new(safe_vk::ShaderStage::new(
-                Arc::new(safe_vk::ShaderModule::new(
-                    device.clone(),
-                    shaders::Shaders::get("closest_hit_1.rchit.spv").unwrap(),
-                )),
-                vk::ShaderStageFlags::CLOSEST_HIT_KHR,
-                "main",
-            )),
-            Arc::new(safe_vk::ShaderStage::new(
-                Arc::new(safe_vk::ShaderModule::new(
-                    device.clone(),
-                    shaders::Shaders::get("closest_hit_2.rchit.spv").unwrap(),
-                )),
-                vk::ShaderStageFlags::CLOSEST_HIT_KHR,
-                "main",
-            )),
-            Arc::new(safe_vk::ShaderStage::new(
-                Arc::new(safe_vk::ShaderModule::new(
-                    device.clone(),
-                    shaders::Shaders::get("closest_hit_3.rchit.spv").unwrap(),
-                )),
-                vk::ShaderStageFlags::CLOSEST_HIT_KHR,
-                "main",
-            )),
-            Arc::new(safe_vk::ShaderStage::new(
-                Arc::new(safe_vk::ShaderModule::new(
-                    device.clone(),
-                    shaders::Shaders::get("closest_hit_4.rchit.spv").unwrap(),
-                )),
-                vk::ShaderStageFlags::CLOSEST_HIT_KHR,
-                "main",
-            )),
-        ];
-
-        let pipeline = Arc::new(safe_vk::RayTracingPipeline::new(
-            Some("rt pipeline"),
+        let focus_probe_buffer = Arc::new(safe_vk::Buffer::new_typed::<f32>(
+            Some("focus probe buffer"),
             allocator.clone(),
-            pipeline_layout,
-            shader_stages,
-            31,
-            &mut queue,
+            1,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            safe_vk::MemoryUsage::GpuToCpu,
         ));
 
-        let camera = camera::Camera::new(
+        // A new scene gets a new set while the frame in flight still uses the old one.
+        let mut descriptor_allocator =
+            safe_vk::DescriptorAllocator::new(descriptor_set_layout.clone(), 2);
+        let descriptor_set = create_descriptor_set(
+            &mut descriptor_allocator,
+            &result_image,
+            &scene,
+            &environment,
+            &uniform_buffer,
+            &focus_probe_buffer,
+            adaptive_sampling.moments_buffer(),
+        );
+
+        let pipeline = create_pipeline(&allocator, &mut queue, pipeline_layout);
+
+        let mut camera = camera::Camera::new(
             glam::Vec3A::new(-0.001, 0.0, 53.0),
             glam::Vec3A::new(0.0, 0.0, 0.0),
         );
+        camera.set_fov(CAMERA_FOV);
+        camera.set_aspect_ratio(size.width as f32 / size.height as f32);
+        camera.set_bindings(settings.input_bindings.clone());
+
+        let camera_presets = if settings.camera_presets.names().next().is_some() {
+            settings.camera_presets.clone()
+        } else {
+            CameraPresets::load(CAMERA_PRESETS_PATH).unwrap_or_default()
+        };
 
         let push_constants = PushConstants {
             render_width: size.width,
             render_height: size.height,
             sample_count: 0,
             batch_sample_count: 1,
+            nee_enabled: 1,
+            environment_rotation: environment.rotation,
+            environment_intensity: environment.intensity,
+            focus_probe_x: u32::MAX,
+            focus_probe_y: u32::MAX,
+            adaptive_enabled: adaptive_sampling.enabled as u32,
+            adaptive_threshold: adaptive_sampling.threshold,
+            adaptive_min_samples: adaptive_sampling.min_samples,
+            max_bounces: 31,
+            russian_roulette_start: 3,
         };
 
         log::info!("pipeline created");
@@ -366,32 +413,101 @@ impl Engine {
             sampled_frames: 0,
         };
 
-        let old_camera_position = camera.position();
+        let old_camera_uniform = camera.camera_uniform();
+        let previous_camera_state = camera.state();
+
+        let shader_watcher = if cfg!(debug_assertions) && !args.headless {
+            engine_core::watch_shaders(SHADER_DIR)
+        } else {
+            None
+        };
+
+        let benchmark = args.benchmark.as_ref().map(|camera_path| {
+            let report_path = args.benchmark_report.clone().unwrap_or_else(|| {
+                PathBuf::from(BENCHMARK_DIR)
+                    .join(format!("benchmark-{}.json", capture::timestamp()))
+            });
+            Benchmark::new(camera_path, args.benchmark_duration, report_path)
+                .unwrap_or_else(|e| panic!("failed to load {}: {}", camera_path.display(), e))
+        });
+
+        let mut offline_render = OfflineRender::new();
+        if let Some(samples) = args.samples {
+            offline_render.target_sample_count = samples;
+        }
+        if let Some(output) = &args.output {
+            offline_render.file_template = output.clone();
+        }
+        if args.headless {
+            offline_render.completion_action = CompletionAction::SaveAndExit;
+            offline_render.start();
+        }
+        let transient_images = safe_vk::TransientImagePool::new(allocator.clone());
 
         Self {
             ui_platform,
+            ui_textures_delta: Default::default(),
             size,
             scale_factor,
-            swapchain,
+            target,
             queue,
             ui_pass,
+            hdr_inspector,
+            show_hdr_inspector: settings.window_open("HDR Inspector"),
+            show_render_settings: settings.window_open("Render Settings"),
             command_pool,
             time,
-            swapchain_images,
-            render_finish_semaphore,
+            timestep: frame_loop::FixedTimestep::new(UPDATE_RATE),
+            previous_camera_state,
+            frame_limiter: frame_loop::FrameLimiter::new(
+                if args.headless || args.benchmark.is_some() {
+                    None
+                } else {
+                    settings.max_fps.map(f64::from)
+                },
+            ),
             render_finish_fence,
             allocator,
             pipeline,
+            descriptor_allocator,
             descriptor_set,
             result_image,
             tone_mapped_image,
+            tone_map,
+            adaptive_sampling,
+            transient_images,
+            offline_render,
+            screenshot_requested: false,
+            toasts: egui_backend::Toasts::new(),
+            frame_capture: capture::FrameCapture::new(CAPTURE_DIR),
             uniform_buffer,
+            focus_probe_buffer,
+            picking_focus: false,
+            cursor_position: Default::default(),
             camera,
+            camera_presets,
+            camera_path: CameraPath::new(),
             scene,
+            scene_path,
+            settings,
+            headless: args.headless,
+            benchmark,
+            environment,
+            retired_scenes: Vec::new(),
             push_constants,
             fps_counter,
+            frame_stats: egui_backend::FrameStats::new(),
+            show_frame_stats: settings.window_open("Frame Times"),
+            memory_panel: egui_backend::MemoryPanel::new(),
+            show_memory_panel: settings.window_open("GPU Memory"),
+            log_console: egui_backend::LogConsole::new(),
+            show_log_console: settings.window_open("Log Console"),
+            bindings_editor: egui_backend::BindingsEditor::new(),
+            show_input_bindings: settings.window_open("Input Bindings"),
+            shader_watcher,
+            shader_errors: Vec::new(),
             sample_speed: 0.0,
-            old_camera_position,
+            old_camera_uniform,
         }
     }
 
@@ -427,17 +543,19 @@ impl Engine {
     fn resize(&mut self, new_size: &winit::dpi::PhysicalSize<u32>) {
         log::debug!("resizing");
         self.size = new_size.clone();
-        self.swapchain.renew();
-        self.swapchain_images = safe_vk::Image::from_swapchain(self.swapchain.clone())
-            .into_iter()
-            .map(Arc::new)
-            .collect::<Vec<_>>();
+        self.ui_pass
+            .set_screen_descriptor(engine_core::screen_descriptor(self.size, self.scale_factor));
+        // Minimized, keep everything as is until the window comes back.
+        if !self.target.resize(self.size) {
+            return;
+        }
+        self.transient_images.clear();
         let mut result_image = safe_vk::Image::new(
             Some("result image"),
             self.allocator.clone(),
             vk::Format::R32G32B32A32_SFLOAT,
-            self.swapchain.width(),
-            self.swapchain.height(),
+            self.target.width(),
+            self.target.height(),
             vk::ImageTiling::OPTIMAL,
             vk::ImageUsageFlags::STORAGE
                 | vk::ImageUsageFlags::TRANSFER_DST
@@ -449,8 +567,8 @@ impl Engine {
             Some("result image"),
             self.allocator.clone(),
             vk::Format::R32G32B32A32_SFLOAT,
-            self.swapchain.width(),
-            self.swapchain.height(),
+            self.target.width(),
+            self.target.height(),
             vk::ImageTiling::OPTIMAL,
             vk::ImageUsageFlags::STORAGE
                 | vk::ImageUsageFlags::TRANSFER_DST
@@ -471,28 +589,222 @@ impl Engine {
         );
 
         self.result_image = Arc::new(result_image);
+        self.hdr_inspector.set_source(self.result_image.clone());
         self.tone_mapped_image = Arc::new(tone_mapped_image);
+        self.tone_map
+            .set_images(self.result_image.clone(), self.tone_mapped_image.clone());
+        self.adaptive_sampling
+            .resize(&self.allocator, self.tone_mapped_image.clone());
 
         let result_image_view = Arc::new(safe_vk::ImageView::new(self.result_image.clone()));
-        let tone_mapped_image_view =
-            Arc::new(safe_vk::ImageView::new(self.tone_mapped_image.clone()));
         self.descriptor_set.update(&[
             safe_vk::DescriptorSetUpdateInfo {
                 binding: 0,
                 detail: safe_vk::DescriptorSetUpdateDetail::Image(result_image_view.clone()),
             },
             safe_vk::DescriptorSetUpdateInfo {
-                binding: 4,
-                detail: safe_vk::DescriptorSetUpdateDetail::Image(tone_mapped_image_view.clone()),
+                binding: 14,
+                detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
+                    buffer: self.adaptive_sampling.moments_buffer().clone(),
+                    offset: 0,
+                },
             },
         ]);
 
+        self.camera
+            .set_aspect_ratio(new_size.width as f32 / new_size.height as f32);
+
+        self.push_constants.sample_count = 0;
+    }
+
+    /// Replaces the scene. The old one is kept until the frame in flight is done with it.
+    fn load_scene(&mut self, path: PathBuf) {
+        let scene = match Scene::from_file(self.allocator.clone(), &path) {
+            Ok(scene) => scene,
+            Err(e) => {
+                log::warn!("failed to load {}: {}", path.display(), e);
+                self.toasts.add(format!("Failed to load {}: {}", path.display(), e));
+                return;
+            }
+        };
+        self.descriptor_set = create_descriptor_set(
+            &mut self.descriptor_allocator,
+            &self.result_image,
+            &scene,
+            &self.environment,
+            &self.uniform_buffer,
+            &self.focus_probe_buffer,
+            self.adaptive_sampling.moments_buffer(),
+        );
+        let old_scene = std::mem::replace(&mut self.scene, scene);
+        self.retired_scenes
+            .push((self.render_finish_fence.clone(), old_scene));
+        self.push_constants.sample_count = 0;
+        self.toasts.add(format!("Loaded {}", path.display()));
+        self.scene_path = path;
+        // Loading stalled the frame, the camera doesn't catch up on it.
+        self.timestep.reset();
+    }
+
+    /// Rebuilds the ray tracing pipeline from the shaders the watcher recompiled, between two
+    /// frames. Shaders that fail to compile keep their last SPIR-V and show their errors instead.
+    fn reload_shaders(&mut self) {
+        let reload = match self
+            .shader_watcher
+            .as_mut()
+            .and_then(|watcher| watcher.poll())
+        {
+            Some(reload) => reload,
+            None => return,
+        };
+        for error in reload.errors.iter() {
+            log::error!("{}", error);
+        }
+        if !reload.errors.is_empty() {
+            self.toasts
+                .add("Shaders failed to compile, see Shader Errors");
+        }
+        self.shader_errors = reload.errors;
+        if reload.compiled.is_empty() {
+            return;
+        }
+
+        self.render_finish_fence.wait();
+        self.pipeline = create_pipeline(
+            &self.allocator,
+            &mut self.queue,
+            self.pipeline.layout().clone(),
+        );
+
+        log::info!("reloaded {}", reload.compiled.join(", "));
+        self.toasts
+            .add(format!("Reloaded {}", reload.compiled.join(", ")));
         self.push_constants.sample_count = 0;
     }
 
+    /// Replaces the environment map, keeping the rotation and intensity.
+    fn load_environment(&mut self, path: PathBuf) {
+        let mut environment = match Environment::from_hdr(
+            self.allocator.clone(),
+            &mut self.queue,
+            self.command_pool.clone(),
+            &path,
+        ) {
+            Ok(environment) => environment,
+            Err(e) => {
+                log::warn!("failed to load {}: {}", path.display(), e);
+                self.toasts
+                    .add(format!("Failed to load {}: {}", path.display(), e));
+                return;
+            }
+        };
+        environment.rotation = self.environment.rotation;
+        environment.intensity = self.environment.intensity;
+        self.environment = environment;
+        // The set of the frame in flight keeps the old map alive.
+        self.descriptor_set = create_descriptor_set(
+            &mut self.descriptor_allocator,
+            &self.result_image,
+            &self.scene,
+            &self.environment,
+            &self.uniform_buffer,
+            &self.focus_probe_buffer,
+            self.adaptive_sampling.moments_buffer(),
+        );
+        self.push_constants.sample_count = 0;
+        self.toasts.add(format!("Loaded {}", path.display()));
+    }
+
+    fn benchmark_device_info(&self) -> DeviceInfo {
+        let pdevice = self.allocator.device().pdevice();
+        let properties = pdevice.properties();
+        DeviceInfo {
+            name: pdevice.name(),
+            driver_version: properties.driver_version,
+            api_version: properties.api_version,
+            renderer: "Path Tracer",
+            width: self.size.width,
+            height: self.size.height,
+        }
+    }
+
+    /// The code to exit with, once an offline render that exits when done has finished, or a
+    /// benchmark.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.offline_render
+            .exit_code()
+            .or_else(|| self.benchmark.as_ref().and_then(Benchmark::exit_code))
+    }
+
+    /// Writes the window size, the scene, the tone map operator, the camera presets, the input
+    /// bindings and the open windows to the settings file. Headless runs and benchmarks leave it
+    /// as is.
+    pub fn save_settings(&mut self) {
+        if self.headless || self.benchmark.is_some() {
+            return;
+        }
+        // A minimized window reopens at its size before.
+        if self.size.width > 0 && self.size.height > 0 {
+            self.settings.window_size = [self.size.width, self.size.height];
+        }
+        self.settings.last_scene = Some(self.scene_path.clone());
+        self.settings.tone_map_operator = Some(self.tone_map.operator.name().to_owned());
+        self.settings.camera_presets = self.camera_presets.clone();
+        self.settings.input_bindings = self.camera.bindings().clone();
+        self.settings
+            .set_window_open("HDR Inspector", self.show_hdr_inspector);
+        self.settings
+            .set_window_open("Render Settings", self.show_render_settings);
+        self.settings
+            .set_window_open("Frame Times", self.show_frame_stats);
+        self.settings
+            .set_window_open("GPU Memory", self.show_memory_panel);
+        self.settings
+            .set_window_open("Log Console", self.show_log_console);
+        self.settings
+            .set_window_open("Input Bindings", self.show_input_bindings);
+        if let Err(e) = self.settings.save(crate::SETTINGS_APP) {
+            log::warn!("failed to save settings: {}", e);
+        }
+    }
+
+    /// Focuses on the surface probed by the last frame. The sky leaves the focus as is.
+    fn read_focus_probe(&mut self) {
+        let distance = self.focus_probe_buffer.read::<f32>(0, 1)[0];
+        if distance > 0.0 {
+            self.camera.set_focus_distance(distance);
+        }
+        self.push_constants.focus_probe_x = u32::MAX;
+        self.push_constants.focus_probe_y = u32::MAX;
+    }
+
+    fn save_screenshot(&mut self) {
+        let result = capture::save_screenshot(
+            SCREENSHOT_DIR,
+            &mut self.queue,
+            self.command_pool.clone(),
+            self.allocator.clone(),
+            self.tone_mapped_image.clone(),
+        );
+        match result {
+            Ok(path) => self.toasts.add(format!("Saved {}", path.display())),
+            Err(e) => {
+                log::warn!("failed to save screenshot: {}", e);
+                self.toasts.add(format!("Failed to save screenshot: {}", e));
+            }
+        }
+    }
+
     pub fn handle_event(&mut self, event: &winit::event::Event<()>) {
         self.ui_platform.handle_event(event);
-        self.camera.input(event);
+        // A key or button bound in the bindings editor does nothing else.
+        let captured = camera::CameraInput::from_winit(event).map_or(false, |input| {
+            self.bindings_editor
+                .capture(self.camera.bindings_mut(), &input)
+        });
+        if !captured {
+            self.camera.input(event);
+        }
         match event {
             winit::event::Event::NewEvents(_) => {}
             winit::event::Event::WindowEvent { window_id, event } => {
@@ -512,13 +824,27 @@ impl Engine {
                         device_id,
                         input,
                         is_synthetic,
-                    } => {}
+                    } => {
+                        let key = input.virtual_keycode.and_then(camera::Key::from_winit);
+                        if input.state == winit::event::ElementState::Pressed
+                            && !self.bindings_editor.is_capturing()
+                            && key.map_or(false, |key| {
+                                self.camera
+                                    .bindings()
+                                    .is_bound(Action::Screenshot, Binding::Key(key))
+                            })
+                        {
+                            self.screenshot_requested = true;
+                        }
+                    }
                     winit::event::WindowEvent::ModifiersChanged(_) => {}
                     winit::event::WindowEvent::CursorMoved {
                         device_id,
                         position,
                         modifiers,
-                    } => {}
+                    } => {
+                        self.cursor_position = *position;
+                    }
                     winit::event::WindowEvent::CursorEntered { device_id } => {}
                     winit::event::WindowEvent::CursorLeft { device_id } => {}
                     winit::event::WindowEvent::MouseWheel {
@@ -532,7 +858,17 @@ impl Engine {
                         state,
                         button,
                         modifiers,
-                    } => {}
+                    } => {
+                        if self.picking_focus
+                            && *state == winit::event::ElementState::Pressed
+                            && *button == winit::event::MouseButton::Left
+                            && !self.ui_platform.context().wants_pointer_input()
+                        {
+                            self.picking_focus = false;
+                            self.push_constants.focus_probe_x = self.cursor_position.x as u32;
+                            self.push_constants.focus_probe_y = self.cursor_position.y as u32;
+                        }
+                    }
                     winit::event::WindowEvent::TouchpadPressure {
                         device_id,
                         pressure,
@@ -547,7 +883,10 @@ impl Engine {
                     winit::event::WindowEvent::ScaleFactorChanged {
                         scale_factor,
                         new_inner_size,
-                    } => {}
+                    } => {
+                        self.scale_factor = *scale_factor;
+                        self.resize(new_inner_size);
+                    }
                     winit::event::WindowEvent::ThemeChanged(_) => {}
                 }
             }
@@ -563,6 +902,8 @@ impl Engine {
     }
 
     pub fn update(&mut self) {
+        self.frame_stats.begin_frame();
+        self.reload_shaders();
         let current_dir = PathBuf::from_str(std::env::current_dir().unwrap().to_str().unwrap())
             .unwrap()
             .join("models\\2.0\\Box\\glTF");
@@ -570,58 +911,351 @@ impl Engine {
             .update_time(self.time.elapsed().as_secs_f64());
         self.ui_platform.begin_frame();
 
-        egui::TopPanel::top(egui::Id::new("menu bar")).show(&self.ui_platform.context(), |ui| {
+        egui::TopBottomPanel::top("menu bar").show(&self.ui_platform.context(), |ui| {
             egui::menu::bar(ui, |ui| {
-                egui::menu::menu(ui, "File", |ui| {
-                    if ui.button("Open").clicked {
+                ui.menu_button("File", |ui| {
+                    if ui.button("Open").clicked() {
                         match nfd2::open_file_dialog(Some("gltf,glb"), Some(current_dir.as_ref()))
                             .unwrap()
                         {
-                            nfd2::Response::Okay(p) => {}
+                            nfd2::Response::Okay(p) => self.load_scene(p),
                             nfd2::Response::OkayMultiple(_) => {}
                             nfd2::Response::Cancel => {}
                         }
                     }
+                    if ui.button("Screenshot").clicked() {
+                        self.screenshot_requested = true;
+                    }
                 });
-                ui.label(format!("FPS: {:.1}", self.fps_counter.fps));
-                ui.label(format!("Samples: {}", self.push_constants.sample_count));
+                ui.menu_button("View", |ui| {
+                    let mut orbit = self.camera.mode() == CameraMode::Orbit;
+                    if ui.checkbox(&mut orbit, "Orbit Camera").clicked() {
+                        self.camera.set_mode(if orbit {
+                            CameraMode::Orbit
+                        } else {
+                            CameraMode::Fly
+                        });
+                    }
+                    ui.checkbox(&mut self.show_hdr_inspector, "HDR Inspector");
+                    ui.checkbox(&mut self.show_memory_panel, "GPU Memory");
+                    ui.checkbox(&mut self.show_log_console, "Log Console");
+                    ui.checkbox(&mut self.show_input_bindings, "Input Bindings");
+                    ui.checkbox(&mut self.settings.vsync, "VSync")
+                        .on_hover_text("Applies on the next start");
+                    ui.horizontal(|ui| {
+                        let mut capped = self.settings.max_fps.is_some();
+                        ui.checkbox(&mut capped, "Frame Rate Cap");
+                        let mut max_fps = self.settings.max_fps.unwrap_or(DEFAULT_MAX_FPS);
+                        ui.add_enabled(
+                            capped,
+                            egui::DragValue::new(&mut max_fps)
+                                .clamp_range(10..=1000)
+                                .suffix(" FPS"),
+                        );
+                        let max_fps = Some(max_fps).filter(|_| capped);
+                        if max_fps != self.settings.max_fps {
+                            self.settings.max_fps = max_fps;
+                            self.frame_limiter.set_max_fps(max_fps.map(f64::from));
+                        }
+                    });
+                    if ui.button("Save Camera").clicked() {
+                        let name = format!("View {}", self.camera_presets.names().count() + 1);
+                        self.camera_presets.insert(&name, self.camera.state());
+                    }
+                    let mut aperture = self.camera.aperture();
+                    ui.add(egui::Slider::new(&mut aperture, 0.0..=5.0).text("Aperture"));
+                    self.camera.set_aperture(aperture);
+                    let mut focus_distance = self.camera.focus_distance();
+                    ui.add(
+                        egui::Slider::new(&mut focus_distance, 0.1..=200.0).text("Focus Distance"),
+                    );
+                    self.camera.set_focus_distance(focus_distance);
+                    if ui.button("Pick Focus").clicked() {
+                        self.picking_focus = true;
+                        self.toasts.add("Click the image to focus on it");
+                    }
+                    ui.separator();
+                    if ui.button("Add Path Keyframe").clicked() {
+                        let time = if self.camera_path.keyframes().is_empty() {
+                            0.0
+                        } else {
+                            self.camera_path.duration() + 2.0
+                        };
+                        self.camera_path.add_keyframe(time, self.camera.state());
+                    }
+                    let play_label = if self.camera_path.is_playing() {
+                        "Pause Path"
+                    } else {
+                        "Play Path"
+                    };
+                    if ui.button(play_label).clicked() {
+                        if self.camera_path.is_playing() {
+                            self.camera_path.pause();
+                        } else {
+                            self.camera_path.play();
+                        }
+                    }
+                    if ui.button("Clear Path").clicked() {
+                        self.camera_path.clear();
+                    }
+                    if ui.button("Save Path").clicked() {
+                        match self.camera_path.save(CAMERA_PATH_FILE) {
+                            Ok(()) => self.toasts.add(format!("Saved {}", CAMERA_PATH_FILE)),
+                            Err(e) => {
+                                log::warn!("failed to save {}: {}", CAMERA_PATH_FILE, e);
+                                self.toasts
+                                    .add(format!("Failed to save {}: {}", CAMERA_PATH_FILE, e));
+                            }
+                        }
+                    }
+                    if ui.button("Load Path").clicked() {
+                        match CameraPath::load(CAMERA_PATH_FILE) {
+                            Ok(camera_path) => self.camera_path = camera_path,
+                            Err(e) => {
+                                log::warn!("failed to load {}: {}", CAMERA_PATH_FILE, e);
+                                self.toasts
+                                    .add(format!("Failed to load {}: {}", CAMERA_PATH_FILE, e));
+                            }
+                        }
+                    }
+                    ui.separator();
+                    let names: Vec<String> =
+                        self.camera_presets.names().map(str::to_owned).collect();
+                    for name in names {
+                        if ui.button(&name).clicked() {
+                            let state = *self.camera_presets.get(&name).unwrap();
+                            self.camera.set_state(&state);
+                        }
+                    }
+                });
+                ui.menu_button("Tone Mapping", |ui| {
+                    for operator in ToneMapOperator::ALL.iter() {
+                        ui.radio_value(&mut self.tone_map.operator, *operator, operator.name());
+                    }
+                    ui.separator();
+                    let mut exposure = self.camera.exposure();
+                    ui.add(egui::Slider::new(&mut exposure, -8.0..=8.0).text("Exposure"));
+                    self.camera.set_exposure(exposure);
+                    ui.add(
+                        egui::Slider::new(&mut self.tone_map.white_point, 1.0..=20.0)
+                            .text("White Point"),
+                    );
+                });
+                ui.menu_button("Environment", |ui| {
+                    if ui.button("Open").clicked() {
+                        match nfd2::open_file_dialog(Some("hdr"), None).unwrap() {
+                            nfd2::Response::Okay(p) => self.load_environment(p),
+                            nfd2::Response::OkayMultiple(_) => {}
+                            nfd2::Response::Cancel => {}
+                        }
+                    }
+                    let mut rotation = self.environment.rotation.to_degrees();
+                    let rotation_changed = ui
+                        .add(egui::Slider::new(&mut rotation, -180.0..=180.0).text("Rotation"))
+                        .changed();
+                    let intensity_changed = ui
+                        .add(
+                            egui::Slider::new(&mut self.environment.intensity, 0.0..=10.0)
+                                .text("Intensity"),
+                        )
+                        .changed();
+                    if rotation_changed || intensity_changed {
+                        self.environment.rotation = rotation.to_radians();
+                        self.push_constants.sample_count = 0;
+                    }
+                });
+                ui.menu_button("Render", |ui| {
+                    ui.checkbox(&mut self.show_render_settings, "Render Settings");
+                    let mut nee_enabled = self.push_constants.nee_enabled != 0;
+                    if ui
+                        .checkbox(&mut nee_enabled, "Next-Event Estimation")
+                        .changed()
+                    {
+                        self.push_constants.nee_enabled = nee_enabled as u32;
+                        self.push_constants.sample_count = 0;
+                    }
+                    ui.separator();
+                    ui.checkbox(&mut self.adaptive_sampling.enabled, "Adaptive Sampling");
+                    ui.add_enabled_ui(self.adaptive_sampling.enabled, |ui| {
+                        ui.add(
+                            egui::Slider::new(&mut self.adaptive_sampling.threshold, 0.001..=0.2)
+                                .logarithmic(true)
+                                .text("Error Threshold"),
+                        );
+                        ui.add(
+                            egui::Slider::new(&mut self.adaptive_sampling.min_samples, 2..=1024)
+                                .logarithmic(true)
+                                .text("Min Samples"),
+                        );
+                    });
+                    ui.checkbox(&mut self.adaptive_sampling.show_heatmap, "Sample Heatmap");
+                    ui.separator();
+                    self.offline_render.ui(ui, self.push_constants.sample_count);
+                    ui.separator();
+                    self.frame_capture.ui(ui);
+                });
+                let fps = format!("FPS: {:.1}", self.fps_counter.fps);
+                if ui
+                    .selectable_label(self.show_frame_stats, fps)
+                    .on_hover_text("Frame Times")
+                    .clicked()
+                {
+                    self.show_frame_stats = !self.show_frame_stats;
+                }
+                if self.offline_render.is_active() {
+                    ui.label(format!(
+                        "Rendering: {} / {}",
+                        self.push_constants.sample_count, self.offline_render.target_sample_count
+                    ));
+                } else {
+                    ui.label(format!("Samples: {}", self.push_constants.sample_count));
+                }
                 ui.label(format!("Sample Speed: {:.1}", self.sample_speed));
+                let ui_stats = self.ui_pass.stats();
+                ui.label(format!(
+                    "UI: {} draws, {} vertices, {} texture uploads",
+                    ui_stats.draw_calls, ui_stats.vertices, ui_stats.texture_uploads
+                ));
+                if let Some(gpu_time) = ui_stats.gpu_time {
+                    ui.label(format!(
+                        "UI GPU time: {:.2} ms",
+                        gpu_time.as_secs_f64() * 1e3
+                    ));
+                }
             });
         });
 
-        let (_, shapes) = self.ui_platform.end_frame();
-        let paint_jobs = self.ui_platform.context().tessellate(shapes);
-        self.ui_pass.update_buffers(
-            &paint_jobs,
-            &egui_backend::ScreenDescriptor {
-                physical_width: self.size.width,
-                physical_height: self.size.height,
-                scale_factor: self.scale_factor as f32,
-            },
-        );
-        self.ui_pass
-            .update_texture(&self.ui_platform.context().texture());
+        let hdr_inspector = &mut self.hdr_inspector;
+        egui::Window::new("HDR Inspector")
+            .open(&mut self.show_hdr_inspector)
+            .show(&self.ui_platform.context(), |ui| hdr_inspector.ui(ui));
+        let push_constants = &mut self.push_constants;
+        egui::Window::new("Render Settings")
+            .open(&mut self.show_render_settings)
+            .show(&self.ui_platform.context(), |ui| {
+                let mut changed = ui
+                    .add(
+                        egui::Slider::new(&mut push_constants.max_bounces, 0..=64)
+                            .text("Max Bounces"),
+                    )
+                    .changed();
+                changed |= ui
+                    .add(
+                        egui::Slider::new(&mut push_constants.russian_roulette_start, 1..=64)
+                            .text("Russian Roulette Start"),
+                    )
+                    .on_hover_text("The first bounce that may terminate paths at random")
+                    .changed();
+                if changed {
+                    push_constants.sample_count = 0;
+                }
+            });
+        let frame_stats = &self.frame_stats;
+        egui::Window::new("Frame Times")
+            .open(&mut self.show_frame_stats)
+            .show(&self.ui_platform.context(), |ui| frame_stats.ui(ui));
+        let memory_panel = &mut self.memory_panel;
+        let allocator = &self.allocator;
+        egui::Window::new("GPU Memory")
+            .open(&mut self.show_memory_panel)
+            .show(&self.ui_platform.context(), |ui| {
+                memory_panel.ui(ui, allocator)
+            });
+        // Drained while hidden too, so it shows the latest records when opened.
+        self.log_console.update();
+        let log_console = &mut self.log_console;
+        egui::Window::new("Log Console")
+            .open(&mut self.show_log_console)
+            .default_size([600.0, 300.0])
+            .show(&self.ui_platform.context(), |ui| log_console.ui(ui));
+        let bindings_editor = &mut self.bindings_editor;
+        let bindings = self.camera.bindings_mut();
+        egui::Window::new("Input Bindings")
+            .open(&mut self.show_input_bindings)
+            .show(&self.ui_platform.context(), |ui| {
+                bindings_editor.ui(ui, bindings)
+            });
+        let mut show_shader_errors = !self.shader_errors.is_empty();
+        let shader_errors = &self.shader_errors;
+        egui::Window::new("Shader Errors")
+            .open(&mut show_shader_errors)
+            .default_size([600.0, 300.0])
+            .show(&self.ui_platform.context(), |ui| {
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    for error in shader_errors.iter() {
+                        ui.label(
+                            egui::RichText::new(error)
+                                .monospace()
+                                .color(egui::Color32::RED),
+                        );
+                    }
+                });
+            });
+        if !show_shader_errors {
+            self.shader_errors.clear();
+        }
+        self.toasts.show(&self.ui_platform.context());
+
+        let dt = self.timestep.step_secs();
+        for _ in 0..self.timestep.tick() {
+            self.previous_camera_state = self.camera.state();
+            if let Some(state) = self.camera_path.advance(dt) {
+                self.camera.set_state(&state);
+            }
+            self.camera.update(dt);
+        }
+        if let Some(state) = self.benchmark.as_mut().and_then(Benchmark::camera_state) {
+            self.camera.set_state(&state);
+            self.previous_camera_state = state;
+        }
+        self.tone_map.exposure = self.camera.exposure();
+        self.push_constants.environment_rotation = self.environment.rotation;
+        self.push_constants.environment_intensity = self.environment.intensity;
+        self.push_constants.adaptive_enabled = self.adaptive_sampling.enabled as u32;
+        self.push_constants.adaptive_threshold = self.adaptive_sampling.threshold;
+        self.push_constants.adaptive_min_samples = self.adaptive_sampling.min_samples;
+
+        let full_output = self.ui_platform.end_frame();
+        let paint_jobs = if self.benchmark.is_some() {
+            Vec::new()
+        } else {
+            self.ui_platform.context().tessellate(full_output.shapes)
+        };
+        self.ui_pass.update_buffers(&paint_jobs);
+        // Frames skipped while minimized leave their texture changes for the next render.
+        self.ui_textures_delta.append(full_output.textures_delta);
+        self.frame_stats.span("Update");
 
         // self.uniform_buffer.copy_from(bytemuck::cast_slice(
         //     self.camera.camera_uniform().origin.as_ref(),
         // ));
 
-        if !self
-            .old_camera_position
-            .abs_diff_eq(self.camera.position(), std::f32::EPSILON)
-        {
-            println!("here");
+        let camera_uniform = self.camera_uniform();
+        if bytemuck::bytes_of(&camera_uniform) != bytemuck::bytes_of(&self.old_camera_uniform) {
             self.push_constants.sample_count = 0;
-            self.old_camera_position = self.camera.position();
+            self.old_camera_uniform = camera_uniform;
         }
     }
 
     pub fn render(&mut self) {
-        let (index, _) = self.swapchain.acquire_next_image();
+        if self.target.is_zero_sized() {
+            return;
+        }
+        if self.offline_render.is_active() {
+            self.push_constants.batch_sample_count = self
+                .offline_render
+                .next_batch_sample_count(self.push_constants.sample_count);
+        }
+        let (index, target_image) = match self.target.acquire() {
+            Some(acquired) => acquired,
+            None => {
+                self.resize(&self.size.clone());
+                return;
+            }
+        };
+        self.frame_stats.span("Acquire");
         let mut command_buffer = safe_vk::CommandBuffer::new(self.command_pool.clone());
 
-        let target_image = self.swapchain_images[index as usize].clone();
-
         let start_address = self.pipeline.sbt_buffer().device_address();
         let stride = self.pipeline.sbt_stride() as u64;
         let sbt_ray_gen_region = vk::StridedDeviceAddressRegionKHR::builder()
@@ -629,149 +1263,235 @@ impl Engine {
             .stride(stride)
             .size(stride)
             .build();
+        // Groups are in stage order: raygen, the sky and shadow misses, then the hit group.
         let mut sbt_hit_region = sbt_ray_gen_region;
         sbt_hit_region.size = stride;
-        sbt_hit_region.device_address = start_address + 2 * stride;
+        sbt_hit_region.device_address = start_address + 3 * stride;
         let mut sbt_miss_region = sbt_ray_gen_region;
-        sbt_miss_region.size = stride;
+        sbt_miss_region.size = 2 * stride;
         sbt_miss_region.device_address = start_address + stride;
 
         let mut sbt_callable_region = sbt_ray_gen_region;
         sbt_callable_region.size = 0;
 
-        command_buffer.encode(|recorder| {
-            recorder.update_buffer(
-                self.uniform_buffer.clone(),
-                0,
-                bytemuck::cast_slice(&[self.camera.camera_uniform()]),
-            );
-            // recorder.bind_compute_pipeline(self.pipeline.clone(), |rec, pipeline| {
-            //     rec.bind_descriptor_sets(vec![self.descriptor_set.clone()], pipeline.layout(), 0);
-
-            //     rec.dispatch(
-            //         (WIDTH as f32 / WORKGROUP_WIDTH as f32).ceil() as u32,
-            //         (HEIGHT as f32 / WORKGROUP_HEIGHT as f32).ceil() as u32,
-            //         1,
-            //     );
-            // });
-            recorder.set_image_layout(
-                self.result_image.clone(),
-                Some(vk::ImageLayout::UNDEFINED),
-                vk::ImageLayout::GENERAL,
-            );
-            recorder.bind_ray_tracing_pipeline(self.pipeline.clone(), |rec, pipeline| {
-                rec.bind_descriptor_sets(vec![self.descriptor_set.clone()], pipeline.layout(), 0);
-                rec.push_constants(
-                    pipeline.layout(),
-                    vk::ShaderStageFlags::RAYGEN_KHR,
+        let camera_uniform = self.camera_uniform();
+        let Self {
+            ref mut ui_pass,
+            ref ui_textures_delta,
+            ref mut hdr_inspector,
+            show_hdr_inspector,
+            ref uniform_buffer,
+            ref pipeline,
+            ref descriptor_set,
+            ref result_image,
+            ref tone_mapped_image,
+            ref tone_map,
+            ref adaptive_sampling,
+            ref mut transient_images,
+            ref push_constants,
+            ..
+        } = *self;
+        let mut graph = safe_vk::RenderGraph::new(transient_images);
+        // Both images are written anew every frame.
+        let result = graph.import_image(result_image.clone(), Some(vk::ImageLayout::UNDEFINED));
+        let tone_mapped =
+            graph.import_image(tone_mapped_image.clone(), Some(vk::ImageLayout::UNDEFINED));
+        let target = graph.import_image(target_image, Some(vk::ImageLayout::UNDEFINED));
+
+        graph.add_pass(
+            "upload",
+            |_| {},
+            |recorder, _| {
+                recorder.update_buffer(
+                    uniform_buffer.clone(),
                     0,
-                    bytemuck::cast_slice(&[self.push_constants]),
+                    bytemuck::cast_slice(&[camera_uniform]),
                 );
-                rec.trace_ray(
-                    &sbt_ray_gen_region,
-                    &sbt_miss_region,
-                    &sbt_hit_region,
-                    &sbt_callable_region,
-                    self.result_image.width(),
-                    self.result_image.height(),
-                    1,
+            },
+        );
+        graph.add_pass(
+            "trace",
+            |pass| {
+                pass.write(
+                    result,
+                    safe_vk::ImageAccess::Storage(vk::PipelineStageFlags::RAY_TRACING_SHADER_KHR),
                 );
-            });
-            recorder.set_image_layout(
+            },
+            |recorder, images| {
+                let result_image = images.get(result);
+                recorder.bind_ray_tracing_pipeline(pipeline.clone(), |rec, pipeline| {
+                    rec.bind_descriptor_sets(vec![descriptor_set.clone()], pipeline.layout(), 0);
+                    rec.push_constants(
+                        pipeline.layout(),
+                        vk::ShaderStageFlags::RAYGEN_KHR,
+                        0,
+                        bytemuck::cast_slice(&[*push_constants]),
+                    );
+                    rec.trace_ray(
+                        &sbt_ray_gen_region,
+                        &sbt_miss_region,
+                        &sbt_hit_region,
+                        &sbt_callable_region,
+                        result_image.width(),
+                        result_image.height(),
+                        1,
+                    );
+                });
+            },
+        );
+        graph.add_pass(
+            "post",
+            |pass| {
+                pass.read(
+                    result,
+                    safe_vk::ImageAccess::Storage(vk::PipelineStageFlags::COMPUTE_SHADER),
+                )
+                .write(
+                    tone_mapped,
+                    safe_vk::ImageAccess::Storage(vk::PipelineStageFlags::COMPUTE_SHADER),
+                );
+            },
+            |recorder, _| {
+                tone_map.record(recorder);
+                adaptive_sampling.record_heatmap(
+                    recorder,
+                    push_constants.sample_count + push_constants.batch_sample_count,
+                );
+            },
+        );
+        graph.add_pass(
+            "blit",
+            |pass| {
+                pass.read(tone_mapped, safe_vk::ImageAccess::TransferSrc)
+                    .write(target, safe_vk::ImageAccess::TransferDst);
+            },
+            |recorder, images| {
+                let (tone_mapped_image, target_image) =
+                    (images.get(tone_mapped), images.get(target));
+                recorder.blit_image(
+                    tone_mapped_image.clone(),
+                    target_image.clone(),
+                    &[vk::ImageBlit::builder()
+                        .src_subresource(
+                            vk::ImageSubresourceLayers::builder()
+                                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                                .layer_count(1)
+                                .base_array_layer(0)
+                                .mip_level(0)
+                                .build(),
+                        )
+                        .src_offsets([
+                            vk::Offset3D { x: 0, y: 0, z: 0 },
+                            vk::Offset3D {
+                                x: tone_mapped_image.width() as i32,
+                                y: tone_mapped_image.height() as i32,
+                                z: 1,
+                            },
+                        ])
+                        .dst_offsets([
+                            vk::Offset3D { x: 0, y: 0, z: 0 },
+                            vk::Offset3D {
+                                x: target_image.width() as i32,
+                                y: target_image.height() as i32,
+                                z: 1,
+                            },
+                        ])
+                        .dst_subresource(
+                            vk::ImageSubresourceLayers::builder()
+                                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                                .layer_count(1)
+                                .base_array_layer(0)
+                                .mip_level(0)
+                                .build(),
+                        )
+                        .build()],
+                    vk::Filter::NEAREST,
+                );
+            },
+        );
+        // The HDR inspector converts the result for display before the UI draws it.
+        let ui_reads = if show_hdr_inspector {
+            vec![(
+                result,
+                safe_vk::ImageAccess::Storage(vk::PipelineStageFlags::COMPUTE_SHADER),
+            )]
+        } else {
+            Vec::new()
+        };
+        ui_pass.add_to_graph(
+            &mut graph,
+            target,
+            &ui_reads,
+            ui_textures_delta,
+            |recorder, ui_pass| {
+                if show_hdr_inspector {
+                    hdr_inspector.record(recorder, ui_pass);
+                }
+            },
+        );
+        command_buffer.encode(|recorder| graph.execute(recorder));
+        self.frame_stats.span("Record");
+        self.render_finish_fence.wait();
+        self.ui_pass.frame_finished();
+        self.frame_stats.span("GPU Wait");
+        self.render_finish_fence = self.target.submit(&mut self.queue, command_buffer);
+        self.ui_pass.free_textures(&std::mem::take(&mut self.ui_textures_delta));
+        self.retired_scenes
+            .retain(|(fence, _)| !fence.is_signaled());
+        self.target.present(&self.queue, index);
+        self.frame_stats.span("Submit");
+
+        self.push_constants.sample_count += self.push_constants.batch_sample_count;
+        let benchmark_done = match &mut self.benchmark {
+            Some(benchmark) if benchmark.exit_code().is_none() => {
+                benchmark.record_frame(self.push_constants.batch_sample_count, &self.allocator);
+                benchmark.is_done()
+            }
+            _ => false,
+        };
+        if benchmark_done {
+            let device = self.benchmark_device_info();
+            if let Some(benchmark) = &mut self.benchmark {
+                benchmark.finish(device, &self.allocator);
+            }
+        }
+        if self.offline_render.is_due(self.push_constants.sample_count) {
+            self.render_finish_fence.wait();
+            self.offline_render.write(
+                &mut self.queue,
+                self.command_pool.clone(),
+                self.allocator.clone(),
                 self.result_image.clone(),
-                Some(vk::ImageLayout::GENERAL),
-                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
-            );
-            recorder.set_image_layout(
-                target_image.clone(),
-                Some(vk::ImageLayout::UNDEFINED),
-                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-            );
-            // recorder.copy_buffer_to_image(
-            //     self.storage_buffer.clone(),
-            //     self.result_image.clone(),
-            //     &[vk::BufferImageCopy::builder()
-            //         .image_extent(vk::Extent3D {
-            //             width: self.result_image.width(),
-            //             height: self.result_image.height(),
-            //             depth: 1,
-            //         })
-            //         .image_subresource(
-            //             vk::ImageSubresourceLayers::builder()
-            //                 .aspect_mask(vk::ImageAspectFlags::COLOR)
-            //                 .layer_count(1)
-            //                 .base_array_layer(0)
-            //                 .mip_level(0)
-            //                 .build(),
-            //         )
-            //         .build()],
-            // );
-
-            recorder.blit_image(
                 self.tone_mapped_image.clone(),
-                target_image.clone(),
-                &[vk::ImageBlit::builder()
-                    .src_subresource(
-                        vk::ImageSubresourceLayers::builder()
-                            .aspect_mask(vk::ImageAspectFlags::COLOR)
-                            .layer_count(1)
-                            .base_array_layer(0)
-                            .mip_level(0)
-                            .build(),
-                    )
-                    .src_offsets([
-                        vk::Offset3D { x: 0, y: 0, z: 0 },
-                        vk::Offset3D {
-                            x: self.result_image.width() as i32,
-                            y: self.result_image.height() as i32,
-                            z: 1,
-                        },
-                    ])
-                    .dst_offsets([
-                        vk::Offset3D { x: 0, y: 0, z: 0 },
-                        vk::Offset3D {
-                            x: target_image.width() as i32,
-                            y: target_image.height() as i32,
-                            z: 1,
-                        },
-                    ])
-                    .dst_subresource(
-                        vk::ImageSubresourceLayers::builder()
-                            .aspect_mask(vk::ImageAspectFlags::COLOR)
-                            .layer_count(1)
-                            .base_array_layer(0)
-                            .mip_level(0)
-                            .build(),
-                    )
-                    .build()],
-                vk::Filter::NEAREST,
+                self.push_constants.sample_count,
             );
-            recorder.set_image_layout(
-                target_image.clone(),
-                None,
-                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        }
+        if self.push_constants.focus_probe_x != u32::MAX {
+            self.render_finish_fence.wait();
+            self.read_focus_probe();
+        }
+        if self.screenshot_requested {
+            self.screenshot_requested = false;
+            self.render_finish_fence.wait();
+            self.save_screenshot();
+        }
+        if self.frame_capture.should_capture() {
+            self.render_finish_fence.wait();
+            let pixels = capture::read_image(
+                &mut self.queue,
+                self.command_pool.clone(),
+                self.allocator.clone(),
+                self.tone_mapped_image.clone(),
             );
-            self.ui_pass.execute(
-                recorder,
-                target_image,
-                &egui_backend::ScreenDescriptor {
-                    physical_width: self.size.width,
-                    physical_height: self.size.height,
-                    scale_factor: self.scale_factor as f32,
-                },
+            self.frame_capture.write_frame(
+                self.tone_mapped_image.width(),
+                self.tone_mapped_image.height(),
+                &pixels,
             );
-        });
-        self.render_finish_fence.wait();
-        self.render_finish_fence = self.queue.submit_binary(
-            command_buffer,
-            &[&self.swapchain.image_available_semaphore()],
-            &[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT],
-            &[&self.render_finish_semaphore],
-        );
-        self.queue
-            .present(&self.swapchain, index, &[&self.render_finish_semaphore]);
+        }
 
-        self.push_constants.sample_count += self.push_constants.batch_sample_count;
+        // Offline renders, picks, screenshots and captures read back after the frame.
+        self.frame_stats.span("Readback");
 
         let now = Instant::now();
         let frame_time = now - self.fps_counter.update_time;
@@ -783,11 +1503,168 @@ impl Engine {
             self.fps_counter.sampled_frames = 0;
             self.sample_speed =
                 self.fps_counter.fps * self.push_constants.batch_sample_count as f64;
-            if self.fps_counter.fps > 140.0 {
-                self.push_constants.batch_sample_count *= 2;
-            } else if self.fps_counter.fps < 70.0 && self.push_constants.batch_sample_count > 1 {
-                self.push_constants.batch_sample_count /= 2;
+            // Offline renders trace a fixed batch.
+            if !self.offline_render.is_active() {
+                if self.fps_counter.fps > 140.0 {
+                    self.push_constants.batch_sample_count *= 2;
+                } else if self.fps_counter.fps < 70.0 && self.push_constants.batch_sample_count > 1
+                {
+                    self.push_constants.batch_sample_count /= 2;
+                }
             }
         }
+        self.frame_limiter.wait();
+    }
+
+    /// The camera between the last two updates, as far as the time since the last one.
+    fn camera_uniform(&self) -> CameraUniform {
+        self.camera
+            .interpolated_uniform(&self.previous_camera_state, self.timestep.alpha())
     }
 }
+
+/// Built from the SPIR-V `shaders::Shaders` returns, again when the shaders are reloaded.
+fn create_pipeline(
+    allocator: &Arc<safe_vk::Allocator>,
+    queue: &mut safe_vk::Queue,
+    pipeline_layout: Arc<safe_vk::PipelineLayout>,
+) -> Arc<safe_vk::RayTracingPipeline> {
+    let shader_stages = vec![
+        Arc::new(safe_vk::ShaderStage::new(
+            Arc::new(safe_vk::ShaderModule::new(
+                allocator.device().clone(),
+                shaders::Shaders::get("raytrace.rgen.spv").unwrap(),
+            )),
+            vk::ShaderStageFlags::RAYGEN_KHR,
+            "main",
+        )),
+        Arc::new(safe_vk::ShaderStage::new(
+            Arc::new(safe_vk::ShaderModule::new(
+                allocator.device().clone(),
+                shaders::Shaders::get("miss.rmiss.spv").unwrap(),
+            )),
+            vk::ShaderStageFlags::MISS_KHR,
+            "main",
+        )),
+        Arc::new(safe_vk::ShaderStage::new(
+            Arc::new(safe_vk::ShaderModule::new(
+                allocator.device().clone(),
+                engine_core::shaders::Shaders::get("shadow.rmiss.spv").unwrap(),
+            )),
+            vk::ShaderStageFlags::MISS_KHR,
+            "main",
+        )),
+        Arc::new(safe_vk::ShaderStage::new(
+            Arc::new(safe_vk::ShaderModule::new(
+                allocator.device().clone(),
+                shaders::Shaders::get("closest_hit.rchit.spv").unwrap(),
+            )),
+            vk::ShaderStageFlags::CLOSEST_HIT_KHR,
+            "main",
+        )),
+    ];
+
+    Arc::new(safe_vk::RayTracingPipeline::new(
+        Some("rt pipeline"),
+        allocator.clone(),
+        pipeline_layout,
+        shader_stages,
+        // Bounces are traced in a loop in the ray generation shader, not recursively.
+        1,
+        queue,
+    ))
+}
+
+fn create_descriptor_set(
+    descriptor_allocator: &mut safe_vk::DescriptorAllocator,
+    result_image: &Arc<safe_vk::Image>,
+    scene: &Scene,
+    environment: &Environment,
+    uniform_buffer: &Arc<safe_vk::Buffer>,
+    focus_probe_buffer: &Arc<safe_vk::Buffer>,
+    moments_buffer: &Arc<safe_vk::Buffer>,
+) -> Arc<safe_vk::DescriptorSet> {
+    let descriptor_set = descriptor_allocator.allocate(Some("Main descriptor set"));
+    descriptor_set.update(&[
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 0,
+            detail: safe_vk::DescriptorSetUpdateDetail::Image(Arc::new(safe_vk::ImageView::new(
+                result_image.clone(),
+            ))),
+        },
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 1,
+            detail: safe_vk::DescriptorSetUpdateDetail::AccelerationStructure(scene.tlas().clone()),
+        },
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 2,
+            detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
+                buffer: scene.sole_buffer().clone(),
+                offset: scene.sole_geometry_index_buffer_offset(),
+            },
+        },
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 3,
+            detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
+                buffer: scene.sole_buffer().clone(),
+                offset: scene.sole_geometry_vertex_buffer_offset(),
+            },
+        },
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 4,
+            detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
+                buffer: focus_probe_buffer.clone(),
+                offset: 0,
+            },
+        },
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 5,
+            detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
+                buffer: uniform_buffer.clone(),
+                offset: 0,
+            },
+        },
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 6,
+            detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
+                buffer: scene.light_buffer().clone(),
+                offset: 0,
+            },
+        },
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 7,
+            detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
+                buffer: scene.material_buffer().clone(),
+                offset: 0,
+            },
+        },
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 8,
+            detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
+                buffer: scene.geometry_material_buffer().clone(),
+                offset: 0,
+            },
+        },
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 9,
+            detail: safe_vk::DescriptorSetUpdateDetail::Image(Arc::new(safe_vk::ImageView::new(
+                environment.image().clone(),
+            ))),
+        },
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 10,
+            detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
+                buffer: environment.cdf_buffer().clone(),
+                offset: 0,
+            },
+        },
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 14,
+            detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
+                buffer: moments_buffer.clone(),
+                offset: 0,
+            },
+        },
+    ]);
+    Arc::new(descriptor_set)
+}
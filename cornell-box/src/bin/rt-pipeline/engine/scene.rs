@@ -3,6 +3,7 @@ use std::f32::consts::FRAC_PI_6;
 use std::path::Path;
 use std::sync::Arc;
 
+use bytemuck::{Pod, Zeroable};
 use glam::{vec3, Mat4, Vec3};
 use rand::{Rng, SeedableRng};
 use safe_vk::vk;
@@ -21,6 +22,92 @@ struct Geometry {
 struct Mesh {
     geometries: Vec<Geometry>,
     blas: safe_vk::AccelerationStructure,
+    /// Where the material indices of the geometries start in the geometry material buffer.
+    first_geometry_material: u32,
+}
+
+/// Matches `Material` in closest_hit_common.glsl.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct Material {
+    base_color: [f32; 4],
+    emissive: [f32; 3],
+    metallic: f32,
+    roughness: f32,
+}
+
+impl Material {
+    fn from_gltf(material: gltf::Material) -> Self {
+        let pbr = material.pbr_metallic_roughness();
+        Self {
+            base_color: pbr.base_color_factor(),
+            emissive: material.emissive_factor(),
+            metallic: pbr.metallic_factor(),
+            roughness: pbr.roughness_factor(),
+        }
+    }
+}
+
+const LIGHT_SUN: u32 = 0;
+const LIGHT_DIRECTIONAL: u32 = 1;
+const LIGHT_POINT: u32 = 2;
+const LIGHT_SPOT: u32 = 3;
+
+/// Sky light every scene gets, towards the camera side and above the horizon.
+const SUN_DIRECTION: Vec3 = glam::const_vec3!([0.3, 0.6, 1.0]);
+/// Larger than the real sun so that paths hitting it by chance converge in reasonable time.
+const SUN_ANGULAR_RADIUS: f32 = 0.02;
+const SUN_IRRADIANCE: f32 = 3.0;
+
+/// Matches `Light` in raytrace.rgen.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct Light {
+    position: [f32; 3],
+    kind: u32,
+    /// The direction the light travels in.
+    direction: [f32; 3],
+    cos_outer: f32,
+    /// Radiance for suns, intensity for point and spot lights, illuminance for directional
+    /// lights.
+    radiance: [f32; 3],
+    cos_inner: f32,
+}
+
+impl Light {
+    fn sun(towards_sun: Vec3, angular_radius: f32, irradiance: f32) -> Self {
+        let cos_outer = angular_radius.cos();
+        let solid_angle = 2.0 * std::f32::consts::PI * (1.0 - cos_outer);
+        Self {
+            position: [0.0; 3],
+            kind: LIGHT_SUN,
+            direction: (-towards_sun.normalize()).into(),
+            cos_outer,
+            radiance: [irradiance / solid_angle; 3],
+            cos_inner: cos_outer,
+        }
+    }
+
+    fn from_gltf(light: gltf::khr_lights_punctual::Light, transform: Mat4) -> Self {
+        let radiance = Vec3::from(light.color()) * light.intensity();
+        let (kind, cos_outer, cos_inner) = match light.kind() {
+            gltf::khr_lights_punctual::Kind::Directional => (LIGHT_DIRECTIONAL, -1.0, -1.0),
+            gltf::khr_lights_punctual::Kind::Point => (LIGHT_POINT, -1.0, -1.0),
+            gltf::khr_lights_punctual::Kind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => (LIGHT_SPOT, outer_cone_angle.cos(), inner_cone_angle.cos()),
+        };
+        Self {
+            position: transform.transform_point3(Vec3::ZERO).into(),
+            kind,
+            // Punctual lights point down their local -Z axis.
+            direction: transform.transform_vector3(-Vec3::Z).normalize().into(),
+            cos_outer,
+            radiance: radiance.into(),
+            cos_inner,
+        }
+    }
 }
 
 pub struct Scene {
@@ -34,13 +121,19 @@ pub struct Scene {
     command_pool: Arc<safe_vk::CommandPool>,
     pointer_buffer: safe_vk::Buffer,
     meshes: Vec<Mesh>,
+    light_buffer: Arc<safe_vk::Buffer>,
+    material_buffer: Arc<safe_vk::Buffer>,
+    geometry_material_buffer: Arc<safe_vk::Buffer>,
 }
 
 impl Scene {
-    pub fn from_file<I: AsRef<Path>>(allocator: Arc<safe_vk::Allocator>, path: I) -> Self {
+    pub fn from_file<I: AsRef<Path>>(
+        allocator: Arc<safe_vk::Allocator>,
+        path: I,
+    ) -> Result<Self, gltf::Error> {
         let mut queue = safe_vk::Queue::new(allocator.device().clone());
         let command_pool = Arc::new(safe_vk::CommandPool::new(allocator.device().clone()));
-        let (doc, gltf_buffers, gltf_images) = gltf::import(path).unwrap();
+        let (doc, gltf_buffers, gltf_images) = gltf::import(path)?;
 
         let buffers = gltf_buffers
             .iter()
@@ -91,10 +184,28 @@ impl Scene {
 
         let scene = doc.scenes().next().unwrap();
 
+        let mut materials = doc.materials().map(Material::from_gltf).collect::<Vec<_>>();
+        // Primitives without a material get the glTF default one at the end.
+        let default_material = materials.len() as u32;
+        materials.push(Material {
+            base_color: [1.0; 4],
+            emissive: [0.0; 3],
+            metallic: 1.0,
+            roughness: 1.0,
+        });
+        let mut geometry_materials = Vec::new();
+
         let mut meshes = Vec::with_capacity(doc.meshes().count());
         for mesh in doc.meshes() {
             let mut geometries = Vec::with_capacity(mesh.primitives().count());
+            let first_geometry_material = geometry_materials.len() as u32;
             for primitive in mesh.primitives() {
+                geometry_materials.push(
+                    primitive
+                        .material()
+                        .index()
+                        .map_or(default_material, |index| index as u32),
+                );
                 let index_accessor = primitive.indices().expect("unsupported");
                 let index_type = match index_accessor.data_type() {
                     gltf::accessor::DataType::U16 => vk::IndexType::UINT16,
@@ -188,7 +299,11 @@ impl Scene {
                     .as_slice(),
                 vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL,
             );
-            meshes.push(Mesh { geometries, blas });
+            meshes.push(Mesh {
+                geometries,
+                blas,
+                first_geometry_material,
+            });
         }
 
         let instance_buffers: Vec<safe_vk::Buffer> = scene
@@ -242,7 +357,39 @@ impl Scene {
             vk::AccelerationStructureTypeKHR::TOP_LEVEL,
         ));
 
-        Self {
+        // Lights use their glTF transforms, there's always at least the sun.
+        let mut lights = vec![Light::sun(
+            SUN_DIRECTION,
+            SUN_ANGULAR_RADIUS,
+            SUN_IRRADIANCE,
+        )];
+        for node in scene.nodes() {
+            Self::collect_lights(node, Mat4::IDENTITY, &mut lights);
+        }
+        log::info!("{} lights", lights.len());
+        let light_buffer = Arc::new(safe_vk::Buffer::new_init_host(
+            Some("light buffer"),
+            allocator.clone(),
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            safe_vk::MemoryUsage::CpuToGpu,
+            bytemuck::cast_slice(&lights),
+        ));
+        let material_buffer = Arc::new(safe_vk::Buffer::new_init_host(
+            Some("material buffer"),
+            allocator.clone(),
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            safe_vk::MemoryUsage::CpuToGpu,
+            bytemuck::cast_slice(&materials),
+        ));
+        let geometry_material_buffer = Arc::new(safe_vk::Buffer::new_init_host(
+            Some("geometry material buffer"),
+            allocator.clone(),
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            safe_vk::MemoryUsage::CpuToGpu,
+            bytemuck::cast_slice(&geometry_materials),
+        ));
+
+        Ok(Self {
             doc,
             buffers,
             // images,
@@ -253,6 +400,19 @@ impl Scene {
             top_level_acceleration_structure,
             pointer_buffer,
             meshes,
+            light_buffer,
+            material_buffer,
+            geometry_material_buffer,
+        })
+    }
+
+    fn collect_lights(node: gltf::Node, parent_transform: Mat4, lights: &mut Vec<Light>) {
+        let transform = parent_transform * Mat4::from_cols_array_2d(&node.transform().matrix());
+        if let Some(light) = node.light() {
+            lights.push(Light::from_gltf(light, transform));
+        }
+        for child in node.children() {
+            Self::collect_lights(child, transform, lights);
         }
     }
 
@@ -286,10 +446,12 @@ impl Scene {
                         transform: vk::TransformMatrixKHR {
                             matrix: transform.transpose().as_ref()[..12].try_into().unwrap(),
                         },
-                        instance_custom_index_and_mask: 0 | (0xFF << 24),
-                        instance_shader_binding_table_record_offset_and_flags: rng.gen_range(0..=4)
-                            | (vk::GeometryInstanceFlagsKHR::TRIANGLE_FACING_CULL_DISABLE.as_raw()
-                                << 24),
+                        instance_custom_index_and_mask: meshes[mesh.index()]
+                            .first_geometry_material
+                            | (0xFF << 24),
+                        instance_shader_binding_table_record_offset_and_flags:
+                            vk::GeometryInstanceFlagsKHR::TRIANGLE_FACING_CULL_DISABLE.as_raw()
+                                << 24,
                         acceleration_structure_reference: vk::AccelerationStructureReferenceKHR {
                             device_handle: meshes[mesh.index()].blas.device_address(),
                         },
@@ -321,6 +483,21 @@ impl Scene {
         &self.top_level_acceleration_structure
     }
 
+    /// Lights for next-event estimation, see `Light` in raytrace.rgen.
+    pub fn light_buffer(&self) -> &Arc<safe_vk::Buffer> {
+        &self.light_buffer
+    }
+
+    /// Material factors, see `Material` in closest_hit_common.glsl.
+    pub fn material_buffer(&self) -> &Arc<safe_vk::Buffer> {
+        &self.material_buffer
+    }
+
+    /// Material index of every geometry, indexed by instance custom index plus geometry index.
+    pub fn geometry_material_buffer(&self) -> &Arc<safe_vk::Buffer> {
+        &self.geometry_material_buffer
+    }
+
     pub fn sole_buffer(&self) -> &Arc<safe_vk::Buffer> {
         assert_eq!(self.buffers.len(), 1);
         &self.buffers[0]
@@ -1,4 +1,5 @@
 #include "common.glsl"
+#include "brdf.glsl"
 
 layout(location = 0) rayPayloadInEXT PassableInfo payload;
 
@@ -13,6 +14,24 @@ layout(binding = 3, set = 0, scalar) buffer Vertices
     vec3 vertices[];
 };
 
+// glTF material factors, textures aren't loaded.
+struct Material {
+    vec4 base_color;
+    vec3 emissive;
+    float metallic;
+    float roughness;
+};
+
+layout(binding = 7, set = 0, scalar) buffer Materials
+{
+    Material materials[];
+};
+// Material index of every geometry, starting at the instance custom index for each mesh.
+layout(binding = 8, set = 0, scalar) buffer GeometryMaterials
+{
+    uint geometry_materials[];
+};
+
 struct HitInfo {
     vec3 object_position;
     vec3 world_normal;
@@ -66,22 +85,3 @@ HitInfo get_object_hit_info()
 
     return result;
 }
-
-// Returns a random diffuse (Lambertian) reflection for a surface with the
-// given normal, using the given random number generator state. This is
-// cosine-weighted, so directions closer to the normal are more likely to
-// be chosen.
-vec3 diffuseReflection(vec3 normal, inout uint rngState)
-{
-    // For a random diffuse bounce direction, we follow the approach of
-    // Ray Tracing in One Weekend, and generate a random point on a sphere
-    // of radius 1 centered at the normal. This uses the random_unit_vector
-    // function from chapter 8.5:
-    const float theta = 2.0 * k_pi * stepAndOutputRNGFloat(rngState); // Random in [0, 2pi]
-    const float u = 2.0 * stepAndOutputRNGFloat(rngState) - 1.0; // Random in [-1, 1]
-    const float r = sqrt(1.0 - u * u);
-    const vec3 direction = normal + vec3(r * cos(theta), r * sin(theta), u);
-
-    // Then normalize the ray direction:
-    return normalize(direction);
-}
@@ -0,0 +1,88 @@
+use camera::{Action, Binding, CameraInput, InputBindings, Key, MouseButton};
+
+/// A table of the actions and their bindings, adding a binding takes the next key or button
+/// pressed.
+#[derive(Default)]
+pub struct BindingsEditor {
+    /// The action the next key or button pressed is bound to.
+    capturing: Option<Action>,
+}
+
+impl BindingsEditor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_capturing(&self) -> bool {
+        self.capturing.is_some()
+    }
+
+    /// Binds a press to the action waiting for one. Returns whether the input was taken, the
+    /// camera and the engine should not see it then. The left button clicks the UI and the digit
+    /// keys belong to the bookmarks, neither is taken.
+    pub fn capture(&mut self, bindings: &mut InputBindings, input: &CameraInput) -> bool {
+        let action = match self.capturing {
+            Some(action) => action,
+            None => return false,
+        };
+        let binding = match *input {
+            CameraInput::Key {
+                key: Key::Digit(_), ..
+            } => return false,
+            CameraInput::Key { key, pressed } => {
+                if !pressed {
+                    return false;
+                }
+                Binding::Key(key)
+            }
+            CameraInput::Button {
+                button: MouseButton::Left,
+                ..
+            } => return false,
+            CameraInput::Button { button, pressed } => {
+                if !pressed {
+                    return false;
+                }
+                Binding::Button(button)
+            }
+            _ => return false,
+        };
+        bindings.bind(action, binding);
+        self.capturing = None;
+        true
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, bindings: &mut InputBindings) {
+        egui::Grid::new("input bindings").show(ui, |ui| {
+            for action in Action::ALL.iter().copied() {
+                ui.label(action.name());
+                ui.horizontal(|ui| {
+                    for binding in bindings.bindings(action).to_vec() {
+                        if ui
+                            .button(binding.to_string())
+                            .on_hover_text("Remove")
+                            .clicked()
+                        {
+                            bindings.unbind(action, binding);
+                        }
+                    }
+                    if self.capturing == Some(action) {
+                        ui.label("Press a key or button");
+                        if ui.button("Cancel").clicked() {
+                            self.capturing = None;
+                        }
+                    } else if ui.button("+").clicked() {
+                        self.capturing = Some(action);
+                    }
+                });
+                ui.end_row();
+            }
+        });
+        ui.separator();
+        ui.label("Digits recall bookmarks, with Store Bookmark held they store them");
+        if ui.button("Reset to Defaults").clicked() {
+            *bindings = InputBindings::default();
+            self.capturing = None;
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use std::collections::VecDeque;
+use std::time::Instant;
+
+/// Frames the graph and the lows cover, ten seconds at 60 FPS.
+const HISTORY_LEN: usize = 600;
+
+/// How fast the breakdown follows the spans of new frames, so it is readable while it changes.
+const BREAKDOWN_SMOOTHING: f64 = 0.05;
+
+/// Frame times of the last frames, as a graph with the average and the 1% and 0.1% lows, and
+/// where the time of a frame goes.
+///
+/// An average FPS hides stutter, the lows don't: they are the frame rates of the slowest 1% and
+/// 0.1% of the frames.
+pub struct FrameStats {
+    /// Milliseconds from the start of a frame to the start of the next, oldest first.
+    frame_times: VecDeque<f64>,
+    frame_start: Option<Instant>,
+    span_start: Instant,
+    /// Milliseconds of the spans of the current frame, in order.
+    spans: Vec<(&'static str, f64)>,
+    /// The spans of the last frames, smoothed.
+    breakdown: Vec<(&'static str, f64)>,
+}
+
+impl Default for FrameStats {
+    fn default() -> Self {
+        Self {
+            frame_times: VecDeque::with_capacity(HISTORY_LEN),
+            frame_start: None,
+            span_start: Instant::now(),
+            spans: Vec::new(),
+            breakdown: Vec::new(),
+        }
+    }
+}
+
+impl FrameStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ends the last frame and starts the next one. Call once per frame, before any `span`.
+    pub fn begin_frame(&mut self) {
+        let now = Instant::now();
+        if let Some(frame_start) = self.frame_start {
+            self.push_frame_time((now - frame_start).as_secs_f64() * 1000.0);
+        }
+        self.frame_start = Some(now);
+        self.span_start = now;
+
+        let spans = std::mem::take(&mut self.spans);
+        let breakdown = &self.breakdown;
+        self.breakdown = spans
+            .into_iter()
+            .map(|(name, milliseconds)| {
+                let smoothed = match breakdown.iter().find(|(previous, _)| *previous == name) {
+                    Some((_, previous)) => {
+                        previous + (milliseconds - previous) * BREAKDOWN_SMOOTHING
+                    }
+                    None => milliseconds,
+                };
+                (name, smoothed)
+            })
+            .collect();
+    }
+
+    /// Adds the time of a frame measured elsewhere, in milliseconds.
+    pub fn push_frame_time(&mut self, milliseconds: f64) {
+        if self.frame_times.len() == HISTORY_LEN {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(milliseconds);
+    }
+
+    /// Ends the span of the frame that started at the end of the last span, or at
+    /// `begin_frame`, under `name`. The next span starts now.
+    pub fn span(&mut self, name: &'static str) {
+        let now = Instant::now();
+        self.spans
+            .push((name, (now - self.span_start).as_secs_f64() * 1000.0));
+        self.span_start = now;
+    }
+
+    /// Average frame time in milliseconds, `None` before the first frame ended.
+    pub fn average(&self) -> Option<f64> {
+        if self.frame_times.is_empty() {
+            return None;
+        }
+        Some(self.frame_times.iter().sum::<f64>() / self.frame_times.len() as f64)
+    }
+
+    /// Average time in milliseconds of the slowest `fraction` of the frames, at least one.
+    pub fn low(&self, fraction: f64) -> Option<f64> {
+        if self.frame_times.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<f64> = self.frame_times.iter().copied().collect();
+        sorted.sort_by(|a, b| b.partial_cmp(a).unwrap());
+        let count = ((sorted.len() as f64 * fraction).ceil() as usize).max(1);
+        Some(sorted[..count].iter().sum::<f64>() / count as f64)
+    }
+
+    pub fn ui(&self, ui: &mut egui::Ui) {
+        let (average, low_1, low_01) = match (self.average(), self.low(0.01), self.low(0.001)) {
+            (Some(average), Some(low_1), Some(low_01)) => (average, low_1, low_01),
+            _ => {
+                ui.label("No frames yet");
+                return;
+            }
+        };
+        egui::Grid::new("frame stats").show(ui, |ui| {
+            for (label, milliseconds) in [
+                ("Average", average),
+                ("1% Low", low_1),
+                ("0.1% Low", low_01),
+            ]
+            .iter()
+            {
+                ui.label(*label);
+                ui.label(format!("{:.1} FPS", 1000.0 / milliseconds));
+                ui.label(format!("{:.2} ms", milliseconds));
+                ui.end_row();
+            }
+        });
+
+        let frame_times = egui::plot::Line::new(egui::plot::Values::from_values_iter(
+            self.frame_times
+                .iter()
+                .enumerate()
+                .map(|(i, milliseconds)| egui::plot::Value::new(i as f64, *milliseconds)),
+        ))
+        .name("Frame Time (ms)");
+        egui::plot::Plot::new("frame times")
+            .height(120.0)
+            .include_x(0.0)
+            .include_x(HISTORY_LEN as f64)
+            .include_y(0.0)
+            .allow_drag(false)
+            .allow_zoom(false)
+            .legend(egui::plot::Legend::default())
+            .show(ui, |plot_ui| {
+                plot_ui.line(frame_times);
+                plot_ui.hline(egui::plot::HLine::new(1000.0 / 60.0).name("60 FPS"));
+            });
+
+        if self.breakdown.is_empty() {
+            return;
+        }
+        ui.separator();
+        egui::Grid::new("frame breakdown").show(ui, |ui| {
+            for (name, milliseconds) in self.breakdown.iter() {
+                ui.label(*name);
+                ui.label(format!("{:.2} ms", milliseconds));
+                ui.end_row();
+            }
+        });
+    }
+}
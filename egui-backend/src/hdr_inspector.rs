@@ -0,0 +1,339 @@
+use std::sync::Arc;
+
+use safe_vk::{vk, Buffer, CommandRecorder, DescriptorSet, Image, ImageView, MemoryUsage};
+use safe_vk::{ComputePipelineRecorder, Pipeline, PipelineRecorder};
+
+use crate::shaders::Shaders;
+use crate::UiPass;
+
+const WORKGROUP_SIZE: u32 = 16;
+
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct PushConstants {
+    exposure: f32,
+    gamma: f32,
+}
+
+/// The display copy of the source image and the UI texture showing it.
+struct Display {
+    image: Arc<Image>,
+    descriptor_set: Arc<DescriptorSet>,
+    texture_id: egui::TextureId,
+}
+
+/// An egui widget showing a floating point `safe_vk::Image` before any tone mapping, e.g. the
+/// accumulated path tracer output.
+///
+/// The source must have `STORAGE` and `TRANSFER_SRC` usage and be in `GENERAL` layout when
+/// `record` is called. Pixel values under the cursor are read back with one frame of delay.
+pub struct HdrInspector {
+    allocator: Arc<safe_vk::Allocator>,
+    pipeline: Arc<safe_vk::ComputePipeline>,
+    descriptor_set_layout: Arc<safe_vk::DescriptorSetLayout>,
+    descriptor_pool: Arc<safe_vk::DescriptorPool>,
+    readback_buffer: Arc<Buffer>,
+    source: Option<Arc<Image>>,
+    display: Option<Display>,
+    stale_texture: Option<egui::TextureId>,
+    /// Exposure in stops applied before display.
+    pub exposure: f32,
+    /// Gamma applied on top of the sRGB encoding of the UI.
+    pub gamma: f32,
+    zoom: f32,
+    center: egui::Pos2,
+    hovered_pixel: Option<[u32; 2]>,
+    readback_pixel: Option<[u32; 2]>,
+    pixel_value: Option<([u32; 2], [f32; 4])>,
+}
+
+impl HdrInspector {
+    pub fn new(allocator: Arc<safe_vk::Allocator>) -> Self {
+        let device = allocator.device();
+        let module = safe_vk::ShaderModule::new(
+            device.clone(),
+            Shaders::get("hdr_inspector.comp.spv").unwrap(),
+        );
+
+        let descriptor_set_layout = Arc::new(safe_vk::DescriptorSetLayout::new(
+            device.clone(),
+            Some("hdr inspector"),
+            &[
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 0,
+                    descriptor_type: safe_vk::DescriptorType::StorageImage,
+                    stage_flags: vk::ShaderStageFlags::COMPUTE,
+                },
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 1,
+                    descriptor_type: safe_vk::DescriptorType::StorageImage,
+                    stage_flags: vk::ShaderStageFlags::COMPUTE,
+                },
+            ],
+        ));
+
+        let pipeline_layout = Arc::new(safe_vk::PipelineLayout::new(
+            device.clone(),
+            Some("hdr inspector pipeline layout"),
+            &[&descriptor_set_layout],
+            &[vk::PushConstantRange::builder()
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .offset(0)
+                .size(std::mem::size_of::<PushConstants>() as u32)
+                .build()],
+        ));
+
+        let pipeline = Arc::new(safe_vk::ComputePipeline::new(
+            Some("hdr inspector pipeline"),
+            pipeline_layout,
+            Arc::new(safe_vk::ShaderStage::new(
+                Arc::new(module),
+                vk::ShaderStageFlags::COMPUTE,
+                "main",
+            )),
+        ));
+
+        let descriptor_pool = Arc::new(safe_vk::DescriptorPool::new(
+            device.clone(),
+            &[vk::DescriptorPoolSize::builder()
+                .ty(vk::DescriptorType::STORAGE_IMAGE)
+                .descriptor_count(4)
+                .build()],
+            2,
+        ));
+
+        let readback_buffer = Arc::new(Buffer::new_typed::<[f32; 4]>(
+            Some("hdr inspector readback"),
+            allocator.clone(),
+            1,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryUsage::GpuToCpu,
+        ));
+
+        Self {
+            allocator,
+            pipeline,
+            descriptor_set_layout,
+            descriptor_pool,
+            readback_buffer,
+            source: None,
+            display: None,
+            stale_texture: None,
+            exposure: 0.0,
+            gamma: 1.0,
+            zoom: 1.0,
+            center: egui::pos2(0.5, 0.5),
+            hovered_pixel: None,
+            readback_pixel: None,
+            pixel_value: None,
+        }
+    }
+
+    /// Sets the image to inspect. Call again whenever the image is recreated, e.g. on resize.
+    pub fn set_source(&mut self, source: Arc<Image>) {
+        if let Some(display) = self.display.take() {
+            self.stale_texture = Some(display.texture_id);
+        }
+        self.source = Some(source);
+        self.readback_pixel = None;
+        self.pixel_value = None;
+    }
+
+    /// Shows the controls and the image. Scroll over the image to zoom and drag to pan.
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.add(egui::Slider::new(&mut self.exposure, -10.0..=10.0).text("Exposure"));
+            ui.add(egui::Slider::new(&mut self.gamma, 0.2..=5.0).text("Gamma"));
+            ui.add(
+                egui::Slider::new(&mut self.zoom, 1.0..=64.0)
+                    .logarithmic(true)
+                    .text("Zoom"),
+            );
+        });
+
+        let (texture_id, width, height) = match (&self.display, &self.source) {
+            (Some(display), Some(source)) => {
+                (display.texture_id, source.width() as f32, source.height() as f32)
+            }
+            _ => {
+                ui.label("No image to inspect yet.");
+                return;
+            }
+        };
+
+        let half_extent = 0.5 / self.zoom;
+        self.center.x = self.center.x.clamp(half_extent, 1.0 - half_extent);
+        self.center.y = self.center.y.clamp(half_extent, 1.0 - half_extent);
+        let uv = egui::Rect::from_center_size(self.center, egui::vec2(1.0, 1.0) / self.zoom);
+
+        let available = ui.available_size() - egui::vec2(0.0, ui.spacing().interact_size.y);
+        let scale = (available.x / width).min(available.y / height).max(0.0);
+        let size = egui::vec2(width, height) * scale;
+        let response = ui.add(
+            egui::Image::new(texture_id, size)
+                .uv(uv)
+                .sense(egui::Sense::click_and_drag()),
+        );
+
+        if response.hovered() {
+            let scroll = ui.input().scroll_delta.y;
+            if scroll != 0.0 {
+                self.zoom = (self.zoom * (scroll / 200.0).exp()).clamp(1.0, 64.0);
+            }
+        }
+        if response.dragged() {
+            self.center -= response.drag_delta() / size * uv.size();
+        }
+
+        self.hovered_pixel = response.hover_pos().map(|pos| {
+            let t = (pos - response.rect.min) / response.rect.size();
+            let uv = uv.min + t * uv.size();
+            [
+                ((uv.x * width) as u32).min(width as u32 - 1),
+                ((uv.y * height) as u32).min(height as u32 - 1),
+            ]
+        });
+
+        match (self.hovered_pixel, self.pixel_value) {
+            (Some(hovered), Some((pixel, [r, g, b, a]))) if hovered == pixel => {
+                ui.label(format!(
+                    "({}, {}): {:.5} {:.5} {:.5} {:.5}",
+                    pixel[0], pixel[1], r, g, b, a
+                ));
+            }
+            (Some(hovered), _) => {
+                ui.label(format!("({}, {})", hovered[0], hovered[1]));
+            }
+            (None, _) => {
+                ui.label("Hover the image to read a pixel.");
+            }
+        }
+    }
+
+    /// Records the conversion of the source into the displayed texture and the readback of the
+    /// hovered pixel. Call after the source has been written and before `UiPass::execute`.
+    pub fn record(&mut self, recorder: &mut CommandRecorder, ui_pass: &mut UiPass) {
+        if let Some(texture_id) = self.stale_texture.take() {
+            ui_pass.free_native_texture(texture_id);
+        }
+        let source = match &self.source {
+            Some(source) => source.clone(),
+            None => return,
+        };
+
+        if let Some(pixel) = self.readback_pixel.take() {
+            let value = self.readback_buffer.read::<[f32; 4]>(0, 1)[0];
+            self.pixel_value = Some((pixel, value));
+        }
+
+        if self.display.is_none() {
+            self.display = Some(self.create_display(recorder, ui_pass, &source));
+        }
+        let display = self.display.as_ref().unwrap();
+
+        // Wait for whatever wrote the source and for the UI that sampled the last result.
+        recorder.set_image_layout(source.clone(), None, vk::ImageLayout::GENERAL);
+        recorder.set_image_layout(display.image.clone(), None, vk::ImageLayout::GENERAL);
+        let push_constants = PushConstants {
+            exposure: self.exposure,
+            gamma: self.gamma,
+        };
+        recorder.bind_compute_pipeline(self.pipeline.clone(), |recorder, pipeline| {
+            recorder.bind_descriptor_sets(
+                vec![display.descriptor_set.clone()],
+                pipeline.layout(),
+                0,
+            );
+            recorder.push_constants(
+                pipeline.layout(),
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                bytemuck::bytes_of(&push_constants),
+            );
+            recorder.dispatch(
+                (source.width() + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE,
+                (source.height() + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE,
+                1,
+            );
+        });
+        recorder.set_image_layout(display.image.clone(), None, vk::ImageLayout::GENERAL);
+
+        if let Some([x, y]) = self.hovered_pixel {
+            recorder.copy_image_to_buffer(
+                source,
+                self.readback_buffer.clone(),
+                &[vk::BufferImageCopy::builder()
+                    .image_offset(vk::Offset3D {
+                        x: x as i32,
+                        y: y as i32,
+                        z: 0,
+                    })
+                    .image_extent(vk::Extent3D {
+                        width: 1,
+                        height: 1,
+                        depth: 1,
+                    })
+                    .image_subresource(
+                        vk::ImageSubresourceLayers::builder()
+                            .aspect_mask(vk::ImageAspectFlags::COLOR)
+                            .mip_level(0)
+                            .base_array_layer(0)
+                            .layer_count(1)
+                            .build(),
+                    )
+                    .build()],
+            );
+            self.readback_pixel = Some([x, y]);
+        }
+    }
+
+    fn create_display(
+        &self,
+        recorder: &mut CommandRecorder,
+        ui_pass: &mut UiPass,
+        source: &Arc<Image>,
+    ) -> Display {
+        let image = Arc::new(Image::new(
+            Some("hdr inspector display"),
+            self.allocator.clone(),
+            vk::Format::R16G16B16A16_SFLOAT,
+            source.width(),
+            source.height(),
+            vk::ImageTiling::OPTIMAL,
+            vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED,
+            MemoryUsage::GpuOnly,
+        ));
+        // The display image stays in GENERAL so it can be written and sampled without
+        // transitions; descriptors pick up the layout recorded here.
+        recorder.set_image_layout(
+            image.clone(),
+            Some(vk::ImageLayout::UNDEFINED),
+            vk::ImageLayout::GENERAL,
+        );
+        let image_view = Arc::new(ImageView::new(image.clone()));
+
+        let mut descriptor_set = DescriptorSet::new(
+            Some("hdr inspector descriptor set"),
+            self.descriptor_pool.clone(),
+            self.descriptor_set_layout.clone(),
+        );
+        descriptor_set.update(&[
+            safe_vk::DescriptorSetUpdateInfo {
+                binding: 0,
+                detail: safe_vk::DescriptorSetUpdateDetail::Image(Arc::new(ImageView::new(
+                    source.clone(),
+                ))),
+            },
+            safe_vk::DescriptorSetUpdateInfo {
+                binding: 1,
+                detail: safe_vk::DescriptorSetUpdateDetail::Image(image_view.clone()),
+            },
+        ]);
+
+        Display {
+            texture_id: ui_pass.register_native_texture(image_view),
+            image,
+            descriptor_set: Arc::new(descriptor_set),
+        }
+    }
+}
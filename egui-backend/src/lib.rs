@@ -1,22 +1,46 @@
 #![allow(unused)]
 
+mod bindings_editor;
+mod frame_stats;
+mod hdr_inspector;
+mod log_console;
+mod memory_panel;
+mod platform;
 mod shaders;
+mod toasts;
 
-use epi::egui;
-use std::sync::{Arc, Mutex};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
 use std::unimplemented;
 
 use bytemuck::{Pod, Zeroable};
 
 use shaders::Shaders;
 
-use safe_vk::{
-    vk, Buffer, CommandBuffer, CommandPool, CommandRecorder, DescriptorPool, DescriptorSet,
-    Framebuffer, ImageView, Queue,
-};
-use safe_vk::{Image, MemoryUsage};
+use safe_vk::{vk, Buffer, CommandRecorder, DescriptorSet, Framebuffer, GpuProfiler, ImageView};
+use safe_vk::{Image, ImageAccess, ImageHandle, MemoryUsage, RenderGraph};
 
-use safe_vk::Pipeline;
+use safe_vk::{GraphicsPipelineRecorder, Pipeline, PipelineRecorder};
+
+pub use bindings_editor::BindingsEditor;
+pub use frame_stats::FrameStats;
+pub use hdr_inspector::HdrInspector;
+pub use log_console::{init_logger, LogConsole, LogRecord};
+pub use memory_panel::MemoryPanel;
+pub use platform::{Platform, PlatformDescriptor};
+pub use toasts::Toasts;
+
+/// Number of textures (the font texture plus user textures) per descriptor pool, more pools are
+/// added as needed.
+const TEXTURES_PER_POOL: u32 = 64;
+
+/// Initial size in bytes of the vertex and index buffers, which grow by powers of two.
+const INITIAL_BUFFER_SIZE: usize = 1 << 16;
+
+/// Meshes with more triangles than this are drawn in several calls, so a single huge plot or
+/// table doesn't turn into one long draw.
+const MAX_TRIANGLES_PER_DRAW: usize = 1 << 16;
 
 /// Enum for selecting the right buffer type.
 #[derive(Debug)]
@@ -27,6 +51,7 @@ enum BufferType {
 }
 
 /// Information about the screen used for rendering.
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct ScreenDescriptor {
     /// Width of the window in physical pixel.
     pub physical_width: u32,
@@ -44,52 +69,177 @@ impl ScreenDescriptor {
     }
 }
 
+/// What happens to the contents of the target before the UI is drawn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoadOp {
+    /// Draws over the existing contents.
+    Load,
+    /// Clears the target to a color first, e.g. for UI-only frames.
+    Clear(egui::Rgba),
+}
+
+/// How egui textures, most importantly the font atlas, are filtered.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextureFilter {
+    Linear,
+    /// Crisper text at fractional scale factors, at the cost of smoother edges.
+    Nearest,
+}
+
+/// What the UI cost in the last frame, counted from `update_buffers` on.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct UiStats {
+    /// Meshes drawn, one draw call each.
+    pub draw_calls: u32,
+    /// Paint callbacks invoked.
+    pub paint_callbacks: u32,
+    /// Vertices drawn.
+    pub vertices: u32,
+    /// Indices drawn.
+    pub indices: u32,
+    /// Vertex or index buffers that had to be recreated to fit the UI.
+    pub buffer_reallocations: u32,
+    /// Textures created or partially updated.
+    pub texture_uploads: u32,
+    /// Bytes of texture data uploaded.
+    pub texture_upload_bytes: usize,
+    /// The UI didn't change, the buffers of the previous frame were reused without an upload.
+    pub upload_skipped: bool,
+    /// GPU time of the draws `execute` recorded for the last frame `frame_finished` was called
+    /// for. `None` if the device can't write timestamps.
+    pub gpu_time: Option<Duration>,
+}
+
 /// Uniform buffer used when rendering.
 #[derive(Clone, Copy, Debug, Pod, Zeroable)]
 #[repr(C)]
 struct UniformBuffer {
     screen_size: [f32; 2],
+    /// Non-zero when the color attachment is not sRGB and the shader has to encode the output.
+    gamma_encode: u32,
+    nearest_filter: u32,
+    pixel_snapping: u32,
+    pixels_per_point: f32,
+}
+
+/// A piece of the current frame, in painting order.
+enum Draw {
+    Mesh(MeshDraw),
+    Callback {
+        clip_rect: egui::Rect,
+        callback: egui::PaintCallback,
+    },
+}
+
+/// Where a mesh of the current frame lives in the shared vertex and index buffers.
+struct MeshDraw {
+    clip_rect: egui::Rect,
+    texture_id: egui::TextureId,
+    vertex_offset: u64,
+    index_offset: u64,
+    index_count: u32,
+}
+
+/// Passed to the callbacks of `egui::PaintCallback`s as the `&mut dyn Any` argument.
+///
+/// Callbacks run while the UI is being recorded and queue their commands here; `UiPass` records
+/// them inside its render pass with the viewport set to the callback rect and the scissor set to
+/// its clip rect.
+pub struct CallbackContext {
+    render_pass: Arc<safe_vk::RenderPass>,
+    commands: Vec<Box<dyn FnOnce(&mut CommandRecorder)>>,
+}
+
+impl CallbackContext {
+    /// The render pass the commands are recorded in.
+    pub fn render_pass(&self) -> &Arc<safe_vk::RenderPass> {
+        &self.render_pass
+    }
+
+    /// Queues commands to record once the callback returns. Pipelines bound here replace the
+    /// UI pipeline, which is bound again for the meshes painted after the callback.
+    pub fn record<F>(&mut self, f: F)
+    where
+        F: FnOnce(&mut CommandRecorder) + 'static,
+    {
+        self.commands.push(Box::new(f));
+    }
+}
+
+/// The buffers one frame in flight draws from, written by `update_buffers`.
+struct Frame {
+    vertex_buffer: Arc<Buffer>,
+    index_buffer: Arc<Buffer>,
+    uniform_buffer: Arc<Buffer>,
+    uniform_descriptor_set: Arc<DescriptorSet>,
+}
+
+/// What was last written to the buffers of the current frame.
+struct Upload {
+    uniform: UniformBuffer,
+    vertices: Vec<u8>,
+    indices: Vec<u8>,
+}
+
+/// A texture managed by egui, kept around so partial updates can be written into it.
+struct Texture {
+    image: Arc<Image>,
+    descriptor_set: Arc<DescriptorSet>,
 }
 
 /// RenderPass to render a egui based GUI.
 pub struct UiPass {
     graphics_pipeline: Arc<safe_vk::GraphicsPipeline>,
-    index_buffers: Vec<Arc<safe_vk::Buffer>>,
-    vertex_buffers: Vec<Arc<safe_vk::Buffer>>,
-    uniform_buffer: Arc<safe_vk::Buffer>,
-    uniform_descriptor_set: Arc<safe_vk::DescriptorSet>,
-    texture_descriptor_set_layout: Arc<safe_vk::DescriptorSetLayout>,
-    texture_descriptor_set: Option<Arc<safe_vk::DescriptorSet>>,
-    texture_version: Option<u64>,
+    frames: Vec<Frame>,
+    frame_index: usize,
+    last_upload: Option<Upload>,
+    textures: HashMap<u64, Texture>,
     next_user_texture_id: u64,
-    pending_user_textures: Vec<(u64, egui::Texture)>,
-    user_textures: Vec<Option<Arc<safe_vk::DescriptorSet>>>,
+    user_textures: HashMap<u64, Arc<safe_vk::DescriptorSet>>,
+    freed_user_textures: Vec<u64>,
     allocator: Arc<safe_vk::Allocator>,
     render_pass: Arc<safe_vk::RenderPass>,
-    descriptor_pool: Arc<safe_vk::DescriptorPool>,
-    command_pool: Arc<safe_vk::CommandPool>,
-    queue: Arc<Mutex<safe_vk::Queue>>,
-    paint_jobs: egui::PaintJobs,
+    texture_descriptor_allocator: safe_vk::DescriptorAllocator,
+    draws: Vec<Draw>,
+    output_format: vk::Format,
+    final_layout: vk::ImageLayout,
+    load_op: LoadOp,
+    texture_filter: TextureFilter,
+    pixel_snapping: bool,
+    screen_descriptor: ScreenDescriptor,
+    stats: UiStats,
+    /// Times the draws of `execute`, taken while they're recorded.
+    gpu_profiler: Option<GpuProfiler>,
 }
 
 impl UiPass {
-    /// Creates a new render pass to render a egui UI. `output_format` needs to be either `wgpu::TextureFormat::Rgba8UnormSrgb` or `wgpu::TextureFormat::Bgra8UnormSrgb`. Panics if it's not a Srgb format.
-    pub fn new(allocator: Arc<safe_vk::Allocator>) -> Self {
+    /// Creates a new render pass to render a egui UI onto images of `output_format`, usually the
+    /// swapchain format. sRGB formats are encoded by the hardware, UNORM formats in the shader.
+    ///
+    /// The target is left in `final_layout`, `PRESENT_SRC_KHR` for swapchain images or e.g.
+    /// `SHADER_READ_ONLY_OPTIMAL` for offscreen targets.
+    ///
+    /// `frames_in_flight` buffers are kept apart, so the one being written isn't still read by
+    /// the GPU: usually the swapchain image count.
+    pub fn new(
+        allocator: Arc<safe_vk::Allocator>,
+        output_format: vk::Format,
+        final_layout: vk::ImageLayout,
+        screen_descriptor: ScreenDescriptor,
+        frames_in_flight: usize,
+    ) -> Self {
+        assert!(frames_in_flight > 0, "a UI pass needs at least one frame");
         let device = allocator.device();
         let vs_module =
             safe_vk::ShaderModule::new(device.clone(), Shaders::get("egui.vert.spv").unwrap());
         let fs_module =
             safe_vk::ShaderModule::new(device.clone(), Shaders::get("egui.frag.spv").unwrap());
 
-        let uniform_buffer = Arc::new(safe_vk::Buffer::new(
-            Some("uniform buffer"),
-            allocator.clone(),
-            std::mem::size_of::<UniformBuffer>(),
-            vk::BufferUsageFlags::UNIFORM_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
-            MemoryUsage::CpuToGpu,
-        ));
-
         let sampler = Arc::new(safe_vk::Sampler::new(device.clone()));
+        let nearest_sampler = Arc::new(safe_vk::Sampler::new_with_filter(
+            device.clone(),
+            vk::Filter::NEAREST,
+        ));
 
         let uniform_descriptor_set_layout = Arc::new(safe_vk::DescriptorSetLayout::new(
             device.clone(),
@@ -98,13 +248,18 @@ impl UiPass {
                 safe_vk::DescriptorSetLayoutBinding {
                     binding: 0,
                     descriptor_type: safe_vk::DescriptorType::UniformBuffer,
-                    stage_flags: vk::ShaderStageFlags::VERTEX,
+                    stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
                 },
                 safe_vk::DescriptorSetLayoutBinding {
                     binding: 1,
                     descriptor_type: safe_vk::DescriptorType::Sampler(None),
                     stage_flags: vk::ShaderStageFlags::FRAGMENT,
                 },
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 2,
+                    descriptor_type: safe_vk::DescriptorType::Sampler(None),
+                    stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                },
             ],
         ));
 
@@ -128,26 +283,13 @@ impl UiPass {
             &[],
         ));
 
-        let render_pass = Arc::new(safe_vk::RenderPass::new(
+        let render_pass = create_render_pass(
             device.clone(),
-            &vk::RenderPassCreateInfo::builder()
-                .attachments(&[vk::AttachmentDescription::builder()
-                    .format(vk::Format::B8G8R8A8_UNORM)
-                    .samples(vk::SampleCountFlags::TYPE_1)
-                    .load_op(vk::AttachmentLoadOp::LOAD)
-                    .store_op(vk::AttachmentStoreOp::STORE)
-                    .initial_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
-                    .final_layout(vk::ImageLayout::PRESENT_SRC_KHR)
-                    .build()])
-                .subpasses(&[vk::SubpassDescription::builder()
-                    .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
-                    .color_attachments(&[vk::AttachmentReference::builder()
-                        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
-                        .attachment(0)
-                        .build()])
-                    .build()])
-                .build(),
-        ));
+            output_format,
+            LoadOp::Load,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            final_layout,
+        );
 
         let graphics_pipeline = Arc::new(safe_vk::GraphicsPipeline::new(
             Some("egui pipeline"),
@@ -227,73 +369,171 @@ impl UiPass {
 
         let descriptor_pool = Arc::new(safe_vk::DescriptorPool::new(
             device.clone(),
-            &[vk::DescriptorPoolSize::builder()
-                .ty(vk::DescriptorType::UNIFORM_BUFFER)
-                .descriptor_count(1)
-                .build()],
-            1,
+            &[
+                vk::DescriptorPoolSize::builder()
+                    .ty(vk::DescriptorType::UNIFORM_BUFFER)
+                    .descriptor_count(frames_in_flight as u32)
+                    .build(),
+                vk::DescriptorPoolSize::builder()
+                    .ty(vk::DescriptorType::SAMPLER)
+                    .descriptor_count(2 * frames_in_flight as u32)
+                    .build(),
+            ],
+            frames_in_flight as u32,
         ));
 
-        let mut uniform_descriptor_set = safe_vk::DescriptorSet::new(
-            Some("uniform descriptor set"),
-            descriptor_pool.clone(),
-            uniform_descriptor_set_layout.clone(),
-        );
-        uniform_descriptor_set.update(&[
-            safe_vk::DescriptorSetUpdateInfo {
-                binding: 0,
-                detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
-                    buffer: uniform_buffer.clone(),
-                    offset: 0,
-                },
-            },
-            safe_vk::DescriptorSetUpdateInfo {
-                binding: 1,
-                detail: safe_vk::DescriptorSetUpdateDetail::Sampler(sampler.clone()),
-            },
-        ]);
+        let frames = (0..frames_in_flight)
+            .map(|_| {
+                let uniform_buffer = Arc::new(safe_vk::Buffer::new(
+                    Some("uniform buffer"),
+                    allocator.clone(),
+                    std::mem::size_of::<UniformBuffer>(),
+                    vk::BufferUsageFlags::UNIFORM_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+                    MemoryUsage::CpuToGpu,
+                ));
 
-        let uniform_descriptor_set = Arc::new(uniform_descriptor_set);
+                let mut uniform_descriptor_set = safe_vk::DescriptorSet::new(
+                    Some("uniform descriptor set"),
+                    descriptor_pool.clone(),
+                    uniform_descriptor_set_layout.clone(),
+                );
+                uniform_descriptor_set.update(&[
+                    safe_vk::DescriptorSetUpdateInfo {
+                        binding: 0,
+                        detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
+                            buffer: uniform_buffer.clone(),
+                            offset: 0,
+                        },
+                    },
+                    safe_vk::DescriptorSetUpdateInfo {
+                        binding: 1,
+                        detail: safe_vk::DescriptorSetUpdateDetail::Sampler(sampler.clone()),
+                    },
+                    safe_vk::DescriptorSetUpdateInfo {
+                        binding: 2,
+                        detail: safe_vk::DescriptorSetUpdateDetail::Sampler(
+                            nearest_sampler.clone(),
+                        ),
+                    },
+                ]);
 
-        let descriptor_pool = Arc::new(DescriptorPool::new(
-            device.clone(),
-            &[vk::DescriptorPoolSize::builder()
-                .ty(vk::DescriptorType::SAMPLED_IMAGE)
-                .descriptor_count(1)
-                .build()],
-            2,
-        ));
+                Frame {
+                    vertex_buffer: Arc::new(Buffer::new(
+                        Some("vertex buffer"),
+                        allocator.clone(),
+                        INITIAL_BUFFER_SIZE,
+                        vk::BufferUsageFlags::VERTEX_BUFFER,
+                        MemoryUsage::CpuToGpu,
+                    )),
+                    index_buffer: Arc::new(Buffer::new(
+                        Some("index buffer"),
+                        allocator.clone(),
+                        INITIAL_BUFFER_SIZE,
+                        vk::BufferUsageFlags::INDEX_BUFFER,
+                        MemoryUsage::CpuToGpu,
+                    )),
+                    uniform_buffer,
+                    uniform_descriptor_set: Arc::new(uniform_descriptor_set),
+                }
+            })
+            .collect();
 
-        let command_pool = Arc::new(safe_vk::CommandPool::new(device.clone()));
-        let queue = Arc::new(Mutex::new(safe_vk::Queue::new(device.clone())));
+        let texture_descriptor_allocator =
+            safe_vk::DescriptorAllocator::new(texture_descriptor_set_layout, TEXTURES_PER_POOL);
+        let gpu_profiler = GpuProfiler::new(device.clone(), 1);
 
         Self {
             graphics_pipeline,
-            vertex_buffers: Vec::with_capacity(64),
-            index_buffers: Vec::with_capacity(64),
-            uniform_buffer,
-            uniform_descriptor_set,
-            texture_descriptor_set_layout,
-            texture_version: None,
-            texture_descriptor_set: None,
+            frames,
+            frame_index: 0,
+            last_upload: None,
+            textures: HashMap::new(),
             next_user_texture_id: 0,
-            pending_user_textures: Vec::new(),
-            user_textures: Vec::new(),
+            user_textures: HashMap::new(),
+            freed_user_textures: Vec::new(),
             render_pass,
             allocator,
-            descriptor_pool,
-            queue,
-            command_pool,
-            paint_jobs: Vec::new(),
+            texture_descriptor_allocator,
+            draws: Vec::new(),
+            output_format,
+            final_layout,
+            load_op: LoadOp::Load,
+            texture_filter: TextureFilter::Linear,
+            pixel_snapping: false,
+            screen_descriptor,
+            stats: UiStats::default(),
+            gpu_profiler: Some(gpu_profiler),
         }
     }
 
-    pub fn execute(
-        &mut self,
-        recorder: &mut CommandRecorder,
-        color_attachment: Arc<Image>,
-        screen_descriptor: &ScreenDescriptor,
-    ) {
+    /// Updates the size and scale factor the UI is laid out for. Call on `Resized` and
+    /// `ScaleFactorChanged`; egui re-rasterizes the fonts for the new scale factor by itself and
+    /// hands the new atlas over through `update_textures`.
+    pub fn set_screen_descriptor(&mut self, screen_descriptor: ScreenDescriptor) {
+        self.screen_descriptor = screen_descriptor;
+    }
+
+    pub fn screen_descriptor(&self) -> ScreenDescriptor {
+        self.screen_descriptor
+    }
+
+    pub fn stats(&self) -> UiStats {
+        self.stats
+    }
+
+    /// Reads back the GPU time of the frame last submitted into `UiStats::gpu_time`. Call once
+    /// that frame has finished, before the one recorded since is submitted.
+    pub fn frame_finished(&mut self) {
+        let gpu_profiler = self.gpu_profiler.as_mut().unwrap();
+        gpu_profiler.frame_finished();
+        self.stats.gpu_time = gpu_profiler
+            .timings()
+            .first()
+            .map(|(_, milliseconds)| Duration::from_secs_f64(milliseconds / 1e3));
+    }
+
+    /// Sets how many textures each descriptor pool created from now on has room for. Pools are
+    /// added as textures are created, a larger capacity means fewer pools.
+    pub fn set_textures_per_pool(&mut self, textures_per_pool: u32) {
+        self.texture_descriptor_allocator.set_sets_per_pool(textures_per_pool);
+    }
+
+    /// Takes effect with the next `update_buffers`, like `set_pixel_snapping`.
+    pub fn set_texture_filter(&mut self, texture_filter: TextureFilter) {
+        self.texture_filter = texture_filter;
+    }
+
+    /// Rounds vertex positions to whole physical pixels, which keeps text and lines sharp at
+    /// fractional scale factors.
+    pub fn set_pixel_snapping(&mut self, pixel_snapping: bool) {
+        self.pixel_snapping = pixel_snapping;
+    }
+
+    /// Sets how the target is loaded and the layout it is in when `execute` is recorded.
+    ///
+    /// The default is `LoadOp::Load` from `COLOR_ATTACHMENT_OPTIMAL`. When clearing,
+    /// `UNDEFINED` avoids transitioning the target beforehand.
+    pub fn set_load_op(&mut self, load_op: LoadOp, initial_layout: vk::ImageLayout) {
+        // Load ops and layouts don't affect render pass compatibility, the pipeline is kept.
+        self.render_pass = create_render_pass(
+            self.allocator.device().clone(),
+            self.output_format,
+            load_op,
+            initial_layout,
+            self.final_layout,
+        );
+        self.load_op = load_op;
+    }
+
+    /// Records the UI of the last `update_buffers` into `color_attachment`. Paint callbacks are
+    /// invoked in painting order with a `CallbackContext`, see its documentation.
+    pub fn execute(&mut self, recorder: &mut CommandRecorder, color_attachment: Arc<Image>) {
+        let screen_descriptor = self.screen_descriptor;
+        // A minimized window has nothing to draw to, and a framebuffer can't be empty.
+        if screen_descriptor.physical_width == 0 || screen_descriptor.physical_height == 0 {
+            self.release_freed_textures();
+            return;
+        }
         let image_view = Arc::new(ImageView::new(color_attachment.clone()));
         let framebuffer = Arc::new(Framebuffer::new(
             self.render_pass.clone(),
@@ -302,285 +542,647 @@ impl UiPass {
             vec![image_view.clone()],
         ));
 
-        let scale_factor = screen_descriptor.scale_factor;
-        let physical_width = screen_descriptor.physical_width;
-        let physical_height = screen_descriptor.physical_height;
-
-        recorder.begin_render_pass(self.render_pass.clone(), framebuffer.clone(), |recorder| {
-            recorder.bind_graphics_pipeline(
-                self.graphics_pipeline.clone(),
-                |recorder, pipeline| {
-                    recorder.bind_descriptor_sets(
-                        vec![self.uniform_descriptor_set.clone()],
-                        pipeline.layout(),
-                        0,
-                    );
-                    for (((clip_rect, triangles), vertex_buffer), index_buffer) in self
-                        .paint_jobs
-                        .iter()
-                        .zip(self.vertex_buffers.iter())
-                        .zip(self.index_buffers.iter())
-                    {
-                        // Transform clip rect to physical pixels.
-                        let clip_min_x = scale_factor * clip_rect.min.x;
-                        let clip_min_y = scale_factor * clip_rect.min.y;
-                        let clip_max_x = scale_factor * clip_rect.max.x;
-                        let clip_max_y = scale_factor * clip_rect.max.y;
-
-                        // Make sure clip rect can fit within an `u32`.
-                        let clip_min_x = egui::clamp(clip_min_x, 0.0..=physical_width as f32);
-                        let clip_min_y = egui::clamp(clip_min_y, 0.0..=physical_height as f32);
-                        let clip_max_x =
-                            egui::clamp(clip_max_x, clip_min_x..=physical_width as f32);
-                        let clip_max_y =
-                            egui::clamp(clip_max_y, clip_min_y..=physical_height as f32);
-
-                        let clip_min_x = clip_min_x.round() as u32;
-                        let clip_min_y = clip_min_y.round() as u32;
-                        let clip_max_x = clip_max_x.round() as u32;
-                        let clip_max_y = clip_max_y.round() as u32;
-
-                        let width = (clip_max_x - clip_min_x).max(1);
-                        let height = (clip_max_y - clip_min_y).max(1);
-
-                        {
-                            // clip scissor rectangle to target size
-                            let x = clip_min_x.min(physical_width);
-                            let y = clip_min_y.min(physical_height);
-                            let width = width.min(physical_width - x);
-                            let height = height.min(physical_height - y);
-
-                            // skip rendering with zero-sized clip areas
-                            if width == 0 || height == 0 {
-                                continue;
-                            }
+        let clear_values = match self.load_op {
+            LoadOp::Load => vec![],
+            LoadOp::Clear(color) => {
+                // UNORM targets get sRGB values, like the ones the shader writes.
+                let float32 = if is_srgb(self.output_format) {
+                    color.to_array()
+                } else {
+                    let [r, g, b, a] = egui::Color32::from(color).to_array();
+                    [
+                        r as f32 / 255.0,
+                        g as f32 / 255.0,
+                        b as f32 / 255.0,
+                        a as f32 / 255.0,
+                    ]
+                };
+                vec![vk::ClearValue {
+                    color: vk::ClearColorValue { float32 },
+                }]
+            }
+        };
 
-                            recorder.set_scissor(&[vk::Rect2D {
-                                offset: vk::Offset2D {
-                                    x: x as i32,
-                                    y: y as i32,
-                                },
-                                extent: vk::Extent2D { width, height },
-                            }]);
-                            recorder.set_viewport(vk::Viewport {
-                                x: 0.0,
-                                y: physical_height as f32,
-                                width: physical_width as f32,
-                                height: -(physical_height as f32),
-                                min_depth: 0.1,
-                                max_depth: 1.0,
-                            });
+        let mut gpu_profiler = self.gpu_profiler.take().unwrap();
+        recorder.gpu_scope(&mut gpu_profiler, "ui", |recorder| {
+            recorder.begin_render_pass(
+                self.render_pass.clone(),
+                framebuffer.clone(),
+                &clear_values,
+                |recorder| {
+                    let mut draws = &self.draws[..];
+                    while !draws.is_empty() {
+                        // Consecutive meshes share one pipeline bind; callbacks may bind their
+                        // own.
+                        let mesh_count = draws
+                            .iter()
+                            .take_while(|draw| matches!(draw, Draw::Mesh(_)))
+                            .count();
+                        if mesh_count > 0 {
+                            self.draw_meshes(recorder, &draws[..mesh_count]);
+                            draws = &draws[mesh_count..];
+                        } else {
+                            if let Draw::Callback {
+                                clip_rect,
+                                callback,
+                            } = &draws[0]
+                            {
+                                self.draw_callback(recorder, *clip_rect, callback);
+                            }
+                            draws = &draws[1..];
                         }
-                        recorder.bind_descriptor_sets(
-                            vec![self
-                                .get_texture_descriptor_set(triangles.texture_id)
-                                .clone()],
-                            pipeline.layout(),
-                            1,
-                        );
-
-                        recorder.bind_index_buffer(index_buffer.clone(), 0, vk::IndexType::UINT32);
-                        recorder.bind_vertex_buffer(vec![vertex_buffer.clone()], &[0]);
-                        recorder.draw_indexed(triangles.indices.len() as u32, 1);
                     }
                 },
             );
         });
+        self.gpu_profiler = Some(gpu_profiler);
+
+        self.release_freed_textures();
+    }
+
+    /// Adds the pass drawing the UI into `target` to `graph`: the uploads of `textures_delta`,
+    /// then `before`, e.g. `HdrInspector::record`, then `execute`. The pass writes `target` as
+    /// its color attachment and reads `reads` besides, e.g. native textures registered in
+    /// `SHADER_READ_ONLY_OPTIMAL` with `ImageAccess::Sampled(FRAGMENT_SHADER)`, so the graph
+    /// places the barriers around the UI.
+    pub fn add_to_graph<'a, F>(
+        &'a mut self,
+        graph: &mut RenderGraph<'a>,
+        target: ImageHandle,
+        reads: &[(ImageHandle, ImageAccess)],
+        textures_delta: &'a egui::TexturesDelta,
+        before: F,
+    ) where
+        F: FnOnce(&mut CommandRecorder, &mut UiPass) + 'a,
+    {
+        graph.add_pass(
+            "ui",
+            |pass| {
+                pass.write(target, ImageAccess::ColorAttachment);
+                for &(image, access) in reads {
+                    pass.read(image, access);
+                }
+            },
+            move |recorder, images| {
+                self.update_textures(recorder, textures_delta);
+                before(recorder, self);
+                self.execute(recorder, images.get(target).clone());
+            },
+        );
+    }
+
+    // The command buffer holds on to the descriptor sets it uses, they return to the pool once
+    // the GPU is done with them.
+    fn release_freed_textures(&mut self) {
+        for id in self.freed_user_textures.drain(..) {
+            self.user_textures.remove(&id);
+        }
+    }
+
+    /// Renders the UI of the last `update_buffers` into `target` and reads it back, without a
+    /// swapchain, e.g. for screenshot tests. Blocks until the GPU is done.
+    ///
+    /// `target` needs `COLOR_ATTACHMENT` and `TRANSFER_SRC` usage and the four bytes per pixel
+    /// format the pass was created for. Returns the pixels as tightly packed rows.
+    pub fn render_offscreen(
+        &mut self,
+        queue: &mut safe_vk::Queue,
+        command_pool: Arc<safe_vk::CommandPool>,
+        textures_delta: &egui::TexturesDelta,
+        target: Arc<Image>,
+    ) -> Vec<u8> {
+        let size = target.width() as usize * target.height() as usize * 4;
+        let readback_buffer = Arc::new(Buffer::new(
+            Some("ui readback"),
+            self.allocator.clone(),
+            size,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryUsage::GpuToCpu,
+        ));
+
+        let mut command_buffer = safe_vk::CommandBuffer::new(command_pool);
+        command_buffer.encode(|recorder| {
+            self.update_textures(recorder, textures_delta);
+            recorder.set_image_layout(
+                target.clone(),
+                None,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            );
+            self.execute(recorder, target.clone());
+            recorder.set_image_layout(target.clone(), None, vk::ImageLayout::TRANSFER_SRC_OPTIMAL);
+            recorder.copy_image_to_buffer(
+                target.clone(),
+                readback_buffer.clone(),
+                &[vk::BufferImageCopy::builder()
+                    .image_extent(vk::Extent3D {
+                        width: target.width(),
+                        height: target.height(),
+                        depth: 1,
+                    })
+                    .image_subresource(
+                        vk::ImageSubresourceLayers::builder()
+                            .aspect_mask(vk::ImageAspectFlags::COLOR)
+                            .mip_level(0)
+                            .base_array_layer(0)
+                            .layer_count(1)
+                            .build(),
+                    )
+                    .build()],
+            );
+        });
+        // Nothing else is in flight: the first call only hands the draws over to be timed.
+        self.frame_finished();
+        queue.submit_binary(command_buffer, &[], &[], &[]).wait();
+        self.frame_finished();
+        self.free_textures(textures_delta);
+
+        let mut pixels = vec![0u8; size];
+        let mapped = readback_buffer.map();
+        unsafe {
+            std::ptr::copy_nonoverlapping(mapped, pixels.as_mut_ptr(), size);
+        }
+        readback_buffer.unmap();
+        pixels
+    }
+
+    /// The render pass the UI is drawn in. Pipelines used by paint callbacks must be compatible
+    /// with it.
+    pub fn render_pass(&self) -> &Arc<safe_vk::RenderPass> {
+        &self.render_pass
+    }
+
+    fn draw_meshes(&self, recorder: &mut CommandRecorder, draws: &[Draw]) {
+        let frame = &self.frames[self.frame_index];
+        let ScreenDescriptor {
+            physical_width,
+            physical_height,
+            ..
+        } = self.screen_descriptor;
+
+        recorder.bind_graphics_pipeline(self.graphics_pipeline.clone(), |recorder, pipeline| {
+            recorder.bind_descriptor_sets(
+                vec![frame.uniform_descriptor_set.clone()],
+                pipeline.layout(),
+                0,
+            );
+            recorder.set_viewport(vk::Viewport {
+                x: 0.0,
+                y: physical_height as f32,
+                width: physical_width as f32,
+                height: -(physical_height as f32),
+                min_depth: 0.1,
+                max_depth: 1.0,
+            });
+            for draw in draws {
+                let mesh = match draw {
+                    Draw::Mesh(mesh) => mesh,
+                    Draw::Callback { .. } => unreachable!(),
+                };
+                // skip rendering with zero-sized clip areas
+                let scissor = match self.scissor(mesh.clip_rect) {
+                    Some(scissor) => scissor,
+                    None => continue,
+                };
+                recorder.set_scissor(&[scissor]);
+                recorder.bind_descriptor_sets(
+                    vec![self.get_texture_descriptor_set(mesh.texture_id).clone()],
+                    pipeline.layout(),
+                    1,
+                );
+
+                recorder.bind_index_buffer(
+                    frame.index_buffer.clone(),
+                    mesh.index_offset,
+                    vk::IndexType::UINT32,
+                );
+                recorder
+                    .bind_vertex_buffer(vec![frame.vertex_buffer.clone()], &[mesh.vertex_offset]);
+                recorder.draw_indexed(mesh.index_count, 1);
+            }
+        });
+    }
+
+    fn draw_callback(
+        &self,
+        recorder: &mut CommandRecorder,
+        clip_rect: egui::Rect,
+        callback: &egui::PaintCallback,
+    ) {
+        let ScreenDescriptor {
+            physical_width,
+            physical_height,
+            scale_factor,
+        } = self.screen_descriptor;
+        let scissor = match self.scissor(clip_rect) {
+            Some(scissor) => scissor,
+            None => return,
+        };
+        let viewport = callback.rect;
+        if viewport.width() <= 0.0 || viewport.height() <= 0.0 {
+            return;
+        }
+
+        recorder.set_scissor(&[scissor]);
+        recorder.set_viewport(vk::Viewport {
+            x: scale_factor * viewport.min.x,
+            y: scale_factor * viewport.min.y,
+            width: scale_factor * viewport.width(),
+            height: scale_factor * viewport.height(),
+            min_depth: 0.0,
+            max_depth: 1.0,
+        });
+
+        let info = egui::PaintCallbackInfo {
+            viewport,
+            clip_rect,
+            pixels_per_point: scale_factor,
+            screen_size_px: [physical_width, physical_height],
+        };
+        let mut context = CallbackContext {
+            render_pass: self.render_pass.clone(),
+            commands: Vec::new(),
+        };
+        callback.call(&info, &mut context);
+        for command in context.commands {
+            command(recorder);
+        }
+    }
+
+    // Transforms a clip rect in points to a scissor in physical pixels, `None` if it is empty.
+    fn scissor(&self, clip_rect: egui::Rect) -> Option<vk::Rect2D> {
+        let ScreenDescriptor {
+            physical_width,
+            physical_height,
+            scale_factor,
+        } = self.screen_descriptor;
+
+        // Transform clip rect to physical pixels.
+        let clip_min_x = scale_factor * clip_rect.min.x;
+        let clip_min_y = scale_factor * clip_rect.min.y;
+        let clip_max_x = scale_factor * clip_rect.max.x;
+        let clip_max_y = scale_factor * clip_rect.max.y;
+
+        // Make sure clip rect can fit within an `u32`.
+        let clip_min_x = clip_min_x.clamp(0.0, physical_width as f32);
+        let clip_min_y = clip_min_y.clamp(0.0, physical_height as f32);
+        let clip_max_x = clip_max_x.clamp(clip_min_x, physical_width as f32);
+        let clip_max_y = clip_max_y.clamp(clip_min_y, physical_height as f32);
+
+        let clip_min_x = clip_min_x.round() as u32;
+        let clip_min_y = clip_min_y.round() as u32;
+        let clip_max_x = clip_max_x.round() as u32;
+        let clip_max_y = clip_max_y.round() as u32;
+
+        let width = (clip_max_x - clip_min_x).max(1);
+        let height = (clip_max_y - clip_min_y).max(1);
+
+        // clip scissor rectangle to target size
+        let x = clip_min_x.min(physical_width);
+        let y = clip_min_y.min(physical_height);
+        let width = width.min(physical_width - x);
+        let height = height.min(physical_height - y);
+
+        if width == 0 || height == 0 {
+            return None;
+        }
+        Some(vk::Rect2D {
+            offset: vk::Offset2D {
+                x: x as i32,
+                y: y as i32,
+            },
+            extent: vk::Extent2D { width, height },
+        })
     }
 
     fn get_texture_descriptor_set(&self, texture_id: egui::TextureId) -> &Arc<DescriptorSet> {
         match texture_id {
-            egui::TextureId::Egui => {
-                self.texture_descriptor_set
-                    .as_ref()
-                    .expect("egui texture was not set before the first draw")
-            }
-            egui::TextureId::User(id) => {
-                let id = id as usize;
-                assert!(id < self.user_textures.len());
-                self.user_textures
-                    .get(id)
-                    .unwrap_or_else(|| panic!("user texture {} not found", id))
-                    .as_ref()
-                    .unwrap_or_else(|| panic!("user texture {} freed", id))
+            egui::TextureId::Managed(id) => {
+                &self
+                    .textures
+                    .get(&id)
+                    .unwrap_or_else(|| panic!("egui texture {} was not set before drawing", id))
+                    .descriptor_set
             }
+            egui::TextureId::User(id) => self
+                .user_textures
+                .get(&id)
+                .unwrap_or_else(|| panic!("user texture {} not found", id)),
         }
     }
 
-    pub fn update_texture(&mut self, egui_texture: &egui::Texture) {
-        // Don't update the texture if it hasn't changed.
-        if self.texture_version == Some(egui_texture.version) {
-            return;
+    /// Records the uploads of new and changed textures from `egui::FullOutput::textures_delta`.
+    /// Call before `execute` with the same recorder, and `free_textures` once the frame has been
+    /// submitted.
+    pub fn update_textures(
+        &mut self,
+        recorder: &mut CommandRecorder,
+        textures_delta: &egui::TexturesDelta,
+    ) {
+        for (texture_id, image_delta) in textures_delta.set.iter() {
+            let id = match texture_id {
+                egui::TextureId::Managed(id) => *id,
+                egui::TextureId::User(_) => panic!("user textures are not managed by egui"),
+            };
+            self.update_texture(recorder, id, image_delta);
         }
-        // we need to convert the texture into rgba format
-        let egui_texture = egui::Texture {
-            version: egui_texture.version,
-            width: egui_texture.width,
-            height: egui_texture.height,
-            pixels: egui_texture
+    }
+
+    /// Releases the textures egui no longer needs.
+    pub fn free_textures(&mut self, textures_delta: &egui::TexturesDelta) {
+        for texture_id in textures_delta.free.iter() {
+            if let egui::TextureId::Managed(id) = texture_id {
+                self.textures.remove(id);
+            }
+        }
+    }
+
+    fn update_texture(
+        &mut self,
+        recorder: &mut CommandRecorder,
+        id: u64,
+        image_delta: &egui::epaint::ImageDelta,
+    ) {
+        // Font and color images both arrive as premultiplied sRGB and are sampled as linear.
+        let pixels: Vec<u8> = match &image_delta.image {
+            egui::ImageData::Color(image) => image
                 .pixels
                 .iter()
-                .flat_map(|p| std::iter::repeat(*p).take(4))
+                .flat_map(|color| color.to_array())
+                .collect(),
+            egui::ImageData::Font(image) => image
+                .srgba_pixels(1.0)
+                .flat_map(|color| color.to_array())
                 .collect(),
         };
-        let descriptor_set = self.egui_texture_to_gpu(&egui_texture);
+        let [width, height] = image_delta.image.size();
+        self.stats.texture_uploads += 1;
+        self.stats.texture_upload_bytes += pixels.len();
 
-        self.texture_version = Some(egui_texture.version);
-        self.texture_descriptor_set = Some(Arc::new(descriptor_set));
+        match image_delta.pos {
+            Some([x, y]) => {
+                let image = self
+                    .textures
+                    .get(&id)
+                    .unwrap_or_else(|| panic!("partial update of missing texture {}", id))
+                    .image
+                    .clone();
+                self.upload_pixels(recorder, image, &pixels, [x, y], [width, height]);
+            }
+            None => {
+                // Drop the old texture first so the pool has room for the new set.
+                self.textures.remove(&id);
+                let image = Arc::new(Image::new(
+                    Some("egui texture"),
+                    self.allocator.clone(),
+                    vk::Format::R8G8B8A8_SRGB,
+                    width as u32,
+                    height as u32,
+                    vk::ImageTiling::OPTIMAL,
+                    vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+                    MemoryUsage::GpuOnly,
+                ));
+                self.upload_pixels(recorder, image.clone(), &pixels, [0, 0], [width, height]);
+                let descriptor_set = self.create_texture_descriptor_set(Arc::new(
+                    ImageView::new(image.clone()),
+                ));
+                self.textures.insert(
+                    id,
+                    Texture {
+                        image,
+                        descriptor_set: Arc::new(descriptor_set),
+                    },
+                );
+            }
+        }
     }
 
-    fn egui_texture_to_gpu(&mut self, egui_texture: &egui::Texture) -> DescriptorSet {
-        let mut image = Image::new(
-            Some("egui texture"),
-            self.allocator.clone(),
-            vk::Format::B8G8R8A8_UNORM,
-            egui_texture.width as u32,
-            egui_texture.height as u32,
-            vk::ImageTiling::OPTIMAL,
-            vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
-            MemoryUsage::GpuOnly,
-        );
-        let staging_buffer = Buffer::new_init_host(
+    /// Registers an image the application renders itself, e.g. to show it in an `egui::Image`.
+    /// The image must be in the layout it had when registered whenever the UI is drawn.
+    pub fn register_native_texture(&mut self, image_view: Arc<ImageView>) -> egui::TextureId {
+        let id = self.next_user_texture_id;
+        self.next_user_texture_id += 1;
+        let descriptor_set = self.create_texture_descriptor_set(image_view);
+        self.user_textures.insert(id, Arc::new(descriptor_set));
+        egui::TextureId::User(id)
+    }
+
+    /// Frees a texture registered with `register_native_texture`. It stays usable until the end
+    /// of the next `execute`, so a UI built before the call can still show it.
+    pub fn free_native_texture(&mut self, texture_id: egui::TextureId) {
+        if let egui::TextureId::User(id) = texture_id {
+            self.freed_user_textures.push(id);
+        }
+    }
+
+    // Records writing `pixels` into the `size` region of `image` at `offset`, leaving the image
+    // ready for sampling.
+    fn upload_pixels(
+        &mut self,
+        recorder: &mut CommandRecorder,
+        image: Arc<Image>,
+        pixels: &[u8],
+        offset: [usize; 2],
+        size: [usize; 2],
+    ) {
+        let staging_buffer = Arc::new(Buffer::new_init_host(
             Some("staging buffer"),
             self.allocator.clone(),
             vk::BufferUsageFlags::TRANSFER_SRC,
             MemoryUsage::CpuToGpu,
-            egui_texture.pixels.as_slice(),
-        );
-
-        image.copy_from_buffer(
-            &staging_buffer,
-            &mut self.queue.lock().unwrap(),
-            self.command_pool.clone(),
-        );
+            pixels,
+        ));
 
-        image.set_layout(
-            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-            &mut self.queue.lock().unwrap(),
-            self.command_pool.clone(),
+        // The recorder keeps the staging buffer alive until the command buffer is done with it.
+        recorder.set_image_layout(image.clone(), None, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+        recorder.copy_buffer_to_image(
+            staging_buffer,
+            image.clone(),
+            &[vk::BufferImageCopy::builder()
+                .image_offset(vk::Offset3D {
+                    x: offset[0] as i32,
+                    y: offset[1] as i32,
+                    z: 0,
+                })
+                .image_extent(vk::Extent3D {
+                    width: size[0] as u32,
+                    height: size[1] as u32,
+                    depth: 1,
+                })
+                .image_subresource(
+                    vk::ImageSubresourceLayers::builder()
+                        .aspect_mask(vk::ImageAspectFlags::COLOR)
+                        .mip_level(0)
+                        .base_array_layer(0)
+                        .layer_count(1)
+                        .build(),
+                )
+                .build()],
         );
+        recorder.set_image_layout(image, None, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+    }
 
-        let mut descriptor_set = DescriptorSet::new(
-            Some("texture descriptor set"),
-            self.descriptor_pool.clone(),
-            self.texture_descriptor_set_layout.clone(),
-        );
+    fn create_texture_descriptor_set(&mut self, image_view: Arc<ImageView>) -> DescriptorSet {
+        let mut descriptor_set = self
+            .texture_descriptor_allocator
+            .allocate(Some("texture descriptor set"));
 
         descriptor_set.update(&[safe_vk::DescriptorSetUpdateInfo {
             binding: 0,
-            detail: safe_vk::DescriptorSetUpdateDetail::Image(Arc::new(ImageView::new(Arc::new(
-                image,
-            )))),
+            detail: safe_vk::DescriptorSetUpdateDetail::Image(image_view),
         }]);
 
         descriptor_set
     }
 
-    pub fn update_buffers(
-        &mut self,
-        paint_jobs: &[egui::paint::PaintJob],
-        screen_descriptor: &ScreenDescriptor,
-    ) {
-        self.paint_jobs = paint_jobs.to_owned();
-        let index_size = self.index_buffers.len();
-        let vertex_size = self.vertex_buffers.len();
-
-        let (logical_width, logical_height) = screen_descriptor.logical_size();
-
-        self.uniform_buffer
-            .copy_from(bytemuck::cast_slice(&[UniformBuffer {
-                screen_size: [logical_width as f32, logical_height as f32],
-            }]));
-
-        for (i, (_, triangles)) in paint_jobs.iter().enumerate() {
-            let data: &[u8] = bytemuck::cast_slice(&triangles.indices);
-            if i < index_size {
-                if self.index_buffers[i].size() != data.len() {
-                    self.index_buffers[i] = Arc::new(Buffer::new_init_host(
-                        Some("index buffer"),
-                        self.allocator.clone(),
-                        vk::BufferUsageFlags::INDEX_BUFFER,
-                        MemoryUsage::CpuToGpu,
-                        data,
-                    ));
-                } else {
-                    self.index_buffers[i].copy_from(data);
-                }
-            } else {
-                let buffer = Buffer::new_init_host(
-                    Some("index buffer"),
-                    self.allocator.clone(),
-                    vk::BufferUsageFlags::INDEX_BUFFER,
-                    MemoryUsage::CpuToGpu,
-                    data,
-                );
-                self.index_buffers.push(Arc::new(buffer));
-            }
+    /// Uploads the tessellated UI of a new frame. Call once per frame before `execute`; the
+    /// buffers of the previous frame are left alone while the GPU may still read them.
+    pub fn update_buffers(&mut self, paint_jobs: &[egui::ClippedPrimitive]) {
+        let (logical_width, logical_height) = self.screen_descriptor.logical_size();
+        let uniform = UniformBuffer {
+            screen_size: [logical_width as f32, logical_height as f32],
+            gamma_encode: !is_srgb(self.output_format) as u32,
+            nearest_filter: (self.texture_filter == TextureFilter::Nearest) as u32,
+            pixel_snapping: self.pixel_snapping as u32,
+            pixels_per_point: self.screen_descriptor.scale_factor,
+        };
 
-            let data: &[u8] = as_byte_slice(&triangles.vertices);
-            if i < vertex_size {
-                if self.vertex_buffers[i].size() != data.len() {
-                    self.vertex_buffers[i] = Arc::new(Buffer::new_init_host(
-                        Some("vertex buffer"),
-                        self.allocator.clone(),
-                        vk::BufferUsageFlags::VERTEX_BUFFER,
-                        MemoryUsage::CpuToGpu,
-                        data,
-                    ));
-                } else {
-                    self.vertex_buffers[i].copy_from(data);
+        // Pack every mesh into one vertex and one index stream and remember where each one went.
+        self.draws.clear();
+        self.stats = UiStats {
+            gpu_time: self.stats.gpu_time,
+            ..Default::default()
+        };
+        let mut vertices: Vec<u8> = Vec::new();
+        let mut indices: Vec<u8> = Vec::new();
+        for job in paint_jobs {
+            let mesh = match &job.primitive {
+                egui::epaint::Primitive::Mesh(mesh) => mesh,
+                egui::epaint::Primitive::Callback(callback) => {
+                    self.stats.paint_callbacks += 1;
+                    self.draws.push(Draw::Callback {
+                        clip_rect: job.clip_rect,
+                        callback: callback.clone(),
+                    });
+                    continue;
                 }
-            } else {
-                let buffer = Buffer::new_init_host(
-                    Some("vertex buffer"),
-                    self.allocator.clone(),
-                    vk::BufferUsageFlags::VERTEX_BUFFER,
-                    MemoryUsage::CpuToGpu,
-                    data,
-                );
-                self.vertex_buffers.push(Arc::new(buffer));
+            };
+            // Indices stay u32 and relative to the mesh, chunks only move the index offset.
+            let vertex_offset = vertices.len() as u64;
+            let index_offset = indices.len() as u64;
+            let index_size = std::mem::size_of::<u32>() as u64;
+            for (i, chunk) in mesh.indices.chunks(3 * MAX_TRIANGLES_PER_DRAW).enumerate() {
+                self.draws.push(Draw::Mesh(MeshDraw {
+                    clip_rect: job.clip_rect,
+                    texture_id: mesh.texture_id,
+                    vertex_offset,
+                    index_offset: index_offset
+                        + (i * 3 * MAX_TRIANGLES_PER_DRAW) as u64 * index_size,
+                    index_count: chunk.len() as u32,
+                }));
+                self.stats.draw_calls += 1;
             }
+            vertices.extend_from_slice(as_byte_slice(&mesh.vertices));
+            indices.extend_from_slice(bytemuck::cast_slice::<u32, u8>(&mesh.indices));
+            self.stats.vertices += mesh.vertices.len() as u32;
+            self.stats.indices += mesh.indices.len() as u32;
         }
-    }
-}
 
-impl epi::TextureAllocator for UiPass {
-    fn alloc_srgba_premultiplied(
-        &mut self,
-        size: (usize, usize),
-        srgba_pixels: &[egui::Color32],
-    ) -> egui::TextureId {
-        let id = self.next_user_texture_id;
-        self.next_user_texture_id += 1;
-
-        let mut pixels = vec![0u8; srgba_pixels.len() * 4];
-        for (target, given) in pixels.chunks_exact_mut(4).zip(srgba_pixels.iter()) {
-            target.copy_from_slice(&given.to_array());
+        // An idle UI tessellates to the same data, draw the buffers of the last frame again.
+        if let Some(upload) = &self.last_upload {
+            if bytemuck::bytes_of(&upload.uniform) == bytemuck::bytes_of(&uniform)
+                && upload.vertices == vertices
+                && upload.indices == indices
+            {
+                self.stats.upload_skipped = true;
+                return;
+            }
         }
 
-        let (width, height) = size;
-        self.pending_user_textures.push((
-            id,
-            egui::Texture {
-                version: 0,
-                width,
-                height,
-                pixels,
-            },
-        ));
-
-        egui::TextureId::User(id)
+        // Write into the buffers of the oldest frame, which the GPU is done with by now.
+        self.frame_index = (self.frame_index + 1) % self.frames.len();
+        self.reserve_buffers(vertices.len(), indices.len());
+        let frame = &self.frames[self.frame_index];
+        frame.uniform_buffer.write(0, &[uniform]);
+        frame.vertex_buffer.copy_from_offset(0, &vertices);
+        frame.index_buffer.copy_from_offset(0, &indices);
+        self.last_upload = Some(Upload {
+            uniform,
+            vertices,
+            indices,
+        });
     }
 
-    fn free(&mut self, id: egui::TextureId) {
-        if let egui::TextureId::User(id) = id {
-            self.user_textures
-                .get_mut(id as usize)
-                .and_then(|option| option.take());
+    // Grows the vertex and index buffers of the current frame to the next power of two that
+    // fits, keeping them otherwise so a changing UI doesn't reallocate every frame.
+    fn reserve_buffers(&mut self, vertex_size: usize, index_size: usize) {
+        let frame = &mut self.frames[self.frame_index];
+        if frame.vertex_buffer.size() < vertex_size {
+            frame.vertex_buffer = Arc::new(Buffer::new(
+                Some("vertex buffer"),
+                self.allocator.clone(),
+                vertex_size.next_power_of_two(),
+                vk::BufferUsageFlags::VERTEX_BUFFER,
+                MemoryUsage::CpuToGpu,
+            ));
+            self.stats.buffer_reallocations += 1;
+        }
+        if frame.index_buffer.size() < index_size {
+            frame.index_buffer = Arc::new(Buffer::new(
+                Some("index buffer"),
+                self.allocator.clone(),
+                index_size.next_power_of_two(),
+                vk::BufferUsageFlags::INDEX_BUFFER,
+                MemoryUsage::CpuToGpu,
+            ));
+            self.stats.buffer_reallocations += 1;
         }
     }
 }
 
+fn create_render_pass(
+    device: Arc<safe_vk::Device>,
+    output_format: vk::Format,
+    load_op: LoadOp,
+    initial_layout: vk::ImageLayout,
+    final_layout: vk::ImageLayout,
+) -> Arc<safe_vk::RenderPass> {
+    let load_op = match load_op {
+        LoadOp::Load => vk::AttachmentLoadOp::LOAD,
+        LoadOp::Clear(_) => vk::AttachmentLoadOp::CLEAR,
+    };
+    Arc::new(safe_vk::RenderPass::new(
+        device,
+        &vk::RenderPassCreateInfo::builder()
+            .attachments(&[vk::AttachmentDescription::builder()
+                .format(output_format)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .load_op(load_op)
+                .store_op(vk::AttachmentStoreOp::STORE)
+                .initial_layout(initial_layout)
+                .final_layout(final_layout)
+                .build()])
+            .subpasses(&[vk::SubpassDescription::builder()
+                .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+                .color_attachments(&[vk::AttachmentReference::builder()
+                    .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                    .attachment(0)
+                    .build()])
+                .build()])
+            .build(),
+    ))
+}
+
+fn is_srgb(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::R8G8B8A8_SRGB
+            | vk::Format::B8G8R8A8_SRGB
+            | vk::Format::A8B8G8R8_SRGB_PACK32
+            | vk::Format::R8G8B8_SRGB
+            | vk::Format::B8G8R8_SRGB
+    )
+}
+
 // Needed since we can't use bytemuck for external types.
 fn as_byte_slice<T>(slice: &[T]) -> &[u8] {
     let len = slice.len() * std::mem::size_of::<T>();
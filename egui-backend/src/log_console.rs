@@ -0,0 +1,186 @@
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use crossbeam_channel::{Receiver, Sender};
+use once_cell::sync::OnceCell;
+
+/// Records buffered until the next frame drains them, more are dropped.
+const CHANNEL_CAPACITY: usize = 4096;
+
+/// Records a console keeps, the oldest are dropped first.
+const HISTORY_LEN: usize = 2000;
+
+/// Least severe level kept for the console, whatever `RUST_LOG` prints.
+const CONSOLE_LEVEL: log::LevelFilter = log::LevelFilter::Debug;
+
+static RECORDS: OnceCell<Receiver<LogRecord>> = OnceCell::new();
+
+#[derive(Debug, Clone)]
+pub struct LogRecord {
+    pub level: log::Level,
+    pub target: String,
+    pub message: String,
+    /// Seconds since `init_logger`.
+    pub time: f64,
+}
+
+impl LogRecord {
+    fn line(&self) -> String {
+        format!(
+            "{:9.3} {:5} [{}] {}",
+            self.time, self.level, self.target, self.message
+        )
+    }
+}
+
+/// Prints like `env_logger` and sends the records to the consoles.
+struct ConsoleLogger {
+    inner: env_logger::Logger,
+    sender: Sender<LogRecord>,
+    start: Instant,
+}
+
+impl log::Log for ConsoleLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= CONSOLE_LEVEL || self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if record.level() <= CONSOLE_LEVEL {
+            // A full channel means nobody shows a console.
+            let _ = self.sender.try_send(LogRecord {
+                level: record.level(),
+                target: record.target().to_owned(),
+                message: record.args().to_string(),
+                time: self.start.elapsed().as_secs_f64(),
+            });
+        }
+        self.inner.log(record);
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Installs the global logger, in place of `env_logger::init`. It prints what `RUST_LOG` selects
+/// as before, and keeps every record up to debug level for `LogConsole`.
+pub fn init_logger() {
+    let inner = env_logger::Builder::from_default_env().build();
+    let max_level = inner.filter().max(CONSOLE_LEVEL);
+    let (sender, receiver) = crossbeam_channel::bounded(CHANNEL_CAPACITY);
+    RECORDS.set(receiver).expect("logger already initialized");
+    log::set_boxed_logger(Box::new(ConsoleLogger {
+        inner,
+        sender,
+        start: Instant::now(),
+    }))
+    .expect("logger already initialized");
+    log::set_max_level(max_level);
+}
+
+/// The records of the logger installed by `init_logger`, filtered by level and target.
+pub struct LogConsole {
+    records: VecDeque<LogRecord>,
+    /// Least severe level shown.
+    pub level: log::LevelFilter,
+    /// Shows only records whose target contains this.
+    pub target_filter: String,
+    /// Keeps the newest record in view.
+    pub auto_scroll: bool,
+}
+
+impl Default for LogConsole {
+    fn default() -> Self {
+        Self {
+            records: VecDeque::with_capacity(HISTORY_LEN),
+            level: log::LevelFilter::Info,
+            target_filter: String::new(),
+            auto_scroll: true,
+        }
+    }
+}
+
+impl LogConsole {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Moves the records logged since the last call into the history. `ui` calls it, call it
+    /// every frame while the console is hidden too so no records are dropped.
+    pub fn update(&mut self) {
+        let receiver = match RECORDS.get() {
+            Some(receiver) => receiver,
+            None => return,
+        };
+        for record in receiver.try_iter() {
+            if self.records.len() == HISTORY_LEN {
+                self.records.pop_front();
+            }
+            self.records.push_back(record);
+        }
+    }
+
+    fn filtered(&self) -> impl Iterator<Item = &LogRecord> {
+        let level = self.level;
+        let target_filter = self.target_filter.as_str();
+        self.records
+            .iter()
+            .filter(move |record| record.level <= level && record.target.contains(target_filter))
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        self.update();
+        if RECORDS.get().is_none() {
+            ui.label("The logger isn't installed, see egui_backend::init_logger");
+            return;
+        }
+
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_source("log level")
+                .selected_text(self.level.to_string())
+                .show_ui(ui, |ui| {
+                    for level in log::LevelFilter::iter().skip(1) {
+                        ui.selectable_value(&mut self.level, level, level.to_string());
+                    }
+                });
+            ui.add(
+                egui::TextEdit::singleline(&mut self.target_filter)
+                    .hint_text("Target")
+                    .desired_width(120.0),
+            );
+            ui.checkbox(&mut self.auto_scroll, "Auto-scroll");
+            if ui.button("Copy").clicked() {
+                let text = self
+                    .filtered()
+                    .map(LogRecord::line)
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                ui.output().copied_text = text;
+            }
+            if ui.button("Clear").clicked() {
+                self.records.clear();
+            }
+        });
+        ui.separator();
+
+        let auto_scroll = self.auto_scroll;
+        egui::ScrollArea::vertical()
+            .auto_shrink([false, false])
+            .show(ui, |ui| {
+                for record in self.filtered() {
+                    let text = egui::RichText::new(record.line()).monospace();
+                    let text = match record.level {
+                        log::Level::Error => text.color(egui::Color32::RED),
+                        log::Level::Warn => text.color(egui::Color32::YELLOW),
+                        log::Level::Info => text,
+                        log::Level::Debug | log::Level::Trace => text.weak(),
+                    };
+                    ui.label(text);
+                }
+                if auto_scroll {
+                    ui.scroll_to_cursor(Some(egui::Align::BOTTOM));
+                }
+            });
+    }
+}
@@ -0,0 +1,112 @@
+use safe_vk::vk;
+
+/// Seconds between refreshes, the stats walk every allocation.
+const REFRESH_INTERVAL: f64 = 1.0;
+
+/// Number of allocations listed, largest first.
+const LARGEST_ALLOCATIONS: usize = 10;
+
+/// Usage and budget of each memory heap, allocation counts and the largest buffers and images of
+/// an allocator, with a report of every live one logged on demand.
+#[derive(Default)]
+pub struct MemoryPanel {
+    heaps: Vec<safe_vk::HeapUsage>,
+    largest_allocations: Vec<safe_vk::AllocationRecord>,
+    /// `egui::InputState::time` of the last refresh.
+    refreshed_at: Option<f64>,
+}
+
+impl MemoryPanel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, allocator: &safe_vk::Allocator) {
+        let now = ui.input().time;
+        if self
+            .refreshed_at
+            .map_or(true, |refreshed_at| now - refreshed_at >= REFRESH_INTERVAL)
+        {
+            self.heaps = allocator.heap_usage();
+            self.largest_allocations = allocator.largest_allocations(LARGEST_ALLOCATIONS);
+            self.refreshed_at = Some(now);
+        }
+
+        for (index, heap) in self.heaps.iter().enumerate() {
+            let kind = if heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL) {
+                "Device Local"
+            } else {
+                "Host"
+            };
+            ui.label(format!(
+                "Heap {}: {}, {}",
+                index,
+                kind,
+                format_bytes(heap.size)
+            ));
+            ui.add(
+                egui::ProgressBar::new(heap.usage as f32 / heap.budget.max(1) as f32).text(
+                    format!(
+                        "{} of {} budget",
+                        format_bytes(heap.usage),
+                        format_bytes(heap.budget)
+                    ),
+                ),
+            );
+            ui.label(format!(
+                "{} allocations, {} in {} blocks of {}",
+                heap.allocation_count,
+                format_bytes(heap.allocation_bytes),
+                heap.block_count,
+                format_bytes(heap.block_bytes)
+            ));
+        }
+
+        ui.separator();
+        ui.label("Largest Allocations");
+        egui::Grid::new("largest allocations").show(ui, |ui| {
+            for allocation in self.largest_allocations.iter() {
+                ui.label(allocation.name.as_deref().unwrap_or("unnamed"));
+                ui.label(if allocation.object_type == vk::ObjectType::IMAGE {
+                    "Image"
+                } else {
+                    "Buffer"
+                });
+                ui.label(format_bytes(allocation.size));
+                ui.end_row();
+            }
+        });
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            let mut tracking = allocator.is_tracking();
+            if ui
+                .checkbox(&mut tracking, "Track Backtraces")
+                .on_hover_text("Of the buffers and images created from now on")
+                .changed()
+            {
+                allocator.set_tracking(tracking);
+            }
+            if ui.button("Log Report").clicked() {
+                log::info!(
+                    "GPU allocations\n{}",
+                    allocator.allocation_report(LARGEST_ALLOCATIONS)
+                );
+            }
+        });
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const KIB: f64 = 1024.0;
+    const MIB: f64 = KIB * 1024.0;
+    const GIB: f64 = MIB * 1024.0;
+    let bytes = bytes as f64;
+    if bytes >= GIB {
+        format!("{:.2} GiB", bytes / GIB)
+    } else if bytes >= MIB {
+        format!("{:.1} MiB", bytes / MIB)
+    } else {
+        format!("{:.1} KiB", bytes / KIB)
+    }
+}
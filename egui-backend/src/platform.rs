@@ -0,0 +1,290 @@
+use egui::{pos2, vec2, Pos2};
+use winit::event::{Event, ModifiersState, VirtualKeyCode, WindowEvent};
+
+/// Configures the creation of the `Platform`.
+pub struct PlatformDescriptor {
+    /// Width of the window in physical pixel.
+    pub physical_width: u32,
+    /// Height of the window in physical pixel.
+    pub physical_height: u32,
+    /// HiDPI scale factor.
+    pub scale_factor: f64,
+    /// Egui font configuration.
+    pub font_definitions: egui::FontDefinitions,
+    /// Egui style configuration.
+    pub style: egui::Style,
+}
+
+/// Feeds winit events into an egui context.
+///
+/// The published winit integrations for newer egui releases require a newer winit than the
+/// engines use, so this covers the small subset of the translation the viewers need.
+pub struct Platform {
+    scale_factor: f64,
+    context: egui::Context,
+    raw_input: egui::RawInput,
+    modifier_state: ModifiersState,
+    pointer_pos: Option<Pos2>,
+}
+
+impl Platform {
+    /// Creates a new `Platform`.
+    pub fn new(descriptor: PlatformDescriptor) -> Self {
+        let context = egui::Context::default();
+
+        context.set_fonts(descriptor.font_definitions.clone());
+        context.set_style(descriptor.style);
+        let raw_input = egui::RawInput {
+            pixels_per_point: Some(descriptor.scale_factor as f32),
+            screen_rect: Some(egui::Rect::from_min_size(
+                Pos2::default(),
+                vec2(
+                    descriptor.physical_width as f32,
+                    descriptor.physical_height as f32,
+                ) / descriptor.scale_factor as f32,
+            )),
+            ..Default::default()
+        };
+
+        Self {
+            scale_factor: descriptor.scale_factor,
+            context,
+            raw_input,
+            modifier_state: ModifiersState::empty(),
+            pointer_pos: Some(Pos2::default()),
+        }
+    }
+
+    /// Handles the given winit event and updates the egui context. Should be called before
+    /// starting a new frame with `begin_frame()`.
+    pub fn handle_event<T>(&mut self, winit_event: &Event<T>) {
+        if let Event::WindowEvent { event, .. } = winit_event {
+            match event {
+                WindowEvent::Resized(physical_size) => {
+                    self.raw_input.screen_rect = Some(egui::Rect::from_min_size(
+                        Default::default(),
+                        vec2(physical_size.width as f32, physical_size.height as f32)
+                            / self.scale_factor as f32,
+                    ));
+                }
+                WindowEvent::ScaleFactorChanged {
+                    scale_factor,
+                    new_inner_size,
+                } => {
+                    self.scale_factor = *scale_factor;
+                    self.raw_input.pixels_per_point = Some(*scale_factor as f32);
+                    self.raw_input.screen_rect = Some(egui::Rect::from_min_size(
+                        Default::default(),
+                        vec2(new_inner_size.width as f32, new_inner_size.height as f32)
+                            / self.scale_factor as f32,
+                    ));
+                }
+                WindowEvent::MouseInput { state, button, .. } => {
+                    if let winit::event::MouseButton::Other(..) = button {
+                        return;
+                    }
+                    if let Some(pointer_pos) = self.pointer_pos {
+                        self.raw_input.events.push(egui::Event::PointerButton {
+                            pos: pointer_pos,
+                            button: match button {
+                                winit::event::MouseButton::Left => egui::PointerButton::Primary,
+                                winit::event::MouseButton::Right => {
+                                    egui::PointerButton::Secondary
+                                }
+                                winit::event::MouseButton::Middle => egui::PointerButton::Middle,
+                                winit::event::MouseButton::Other(_) => unreachable!(),
+                            },
+                            pressed: *state == winit::event::ElementState::Pressed,
+                            modifiers: self.raw_input.modifiers,
+                        });
+                    }
+                }
+                WindowEvent::MouseWheel { delta, .. } => {
+                    let delta = match delta {
+                        winit::event::MouseScrollDelta::LineDelta(x, y) => {
+                            let line_height = 24.0;
+                            vec2(*x, *y) * line_height
+                        }
+                        winit::event::MouseScrollDelta::PixelDelta(delta) => {
+                            vec2(delta.x as f32, delta.y as f32) / self.scale_factor as f32
+                        }
+                    };
+                    if self.raw_input.modifiers.ctrl || self.raw_input.modifiers.command {
+                        self.raw_input
+                            .events
+                            .push(egui::Event::Zoom((delta.y / 200.0).exp()));
+                    } else {
+                        self.raw_input.events.push(egui::Event::Scroll(delta));
+                    }
+                }
+                WindowEvent::CursorMoved { position, .. } => {
+                    let pointer_pos = pos2(
+                        position.x as f32 / self.scale_factor as f32,
+                        position.y as f32 / self.scale_factor as f32,
+                    );
+                    self.pointer_pos = Some(pointer_pos);
+                    self.raw_input
+                        .events
+                        .push(egui::Event::PointerMoved(pointer_pos));
+                }
+                WindowEvent::CursorLeft { .. } => {
+                    self.pointer_pos = None;
+                    self.raw_input.events.push(egui::Event::PointerGone);
+                }
+                WindowEvent::ModifiersChanged(input) => {
+                    self.modifier_state = *input;
+                    self.raw_input.modifiers = winit_to_egui_modifiers(*input);
+                }
+                WindowEvent::KeyboardInput { input, .. } => {
+                    if let Some(virtual_keycode) = input.virtual_keycode {
+                        let pressed = input.state == winit::event::ElementState::Pressed;
+                        let ctrl = self.modifier_state.ctrl();
+                        let logo = self.modifier_state.logo();
+
+                        match (pressed, ctrl || logo, virtual_keycode) {
+                            (true, true, VirtualKeyCode::C) => {
+                                self.raw_input.events.push(egui::Event::Copy)
+                            }
+                            (true, true, VirtualKeyCode::X) => {
+                                self.raw_input.events.push(egui::Event::Cut)
+                            }
+                            _ => {
+                                if let Some(key) = winit_to_egui_key_code(virtual_keycode) {
+                                    self.raw_input.events.push(egui::Event::Key {
+                                        key,
+                                        pressed,
+                                        modifiers: winit_to_egui_modifiers(self.modifier_state),
+                                    });
+                                }
+                            }
+                        }
+                    }
+                }
+                WindowEvent::ReceivedCharacter(ch) => {
+                    // Control characters arrive as key events instead.
+                    if is_printable(*ch)
+                        && !self.modifier_state.ctrl()
+                        && !self.modifier_state.logo()
+                    {
+                        self.raw_input.events.push(egui::Event::Text(ch.to_string()));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Returns `true` if egui should handle the event exclusively. Check this to
+    /// avoid unexpected interactions, e.g. a mouse click registering "behind" the UI.
+    pub fn captures_event<T>(&self, winit_event: &Event<T>) -> bool {
+        match winit_event {
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::ReceivedCharacter(_)
+                | WindowEvent::KeyboardInput { .. }
+                | WindowEvent::ModifiersChanged(_) => self.context().wants_keyboard_input(),
+
+                WindowEvent::MouseWheel { .. } | WindowEvent::MouseInput { .. } => {
+                    self.context().wants_pointer_input()
+                }
+
+                WindowEvent::CursorMoved { .. } => self.context().is_using_pointer(),
+
+                _ => false,
+            },
+
+            _ => false,
+        }
+    }
+
+    /// Updates the internal time for egui used for animations. `elapsed_seconds` should be the
+    /// seconds since some point in time (for example application start).
+    pub fn update_time(&mut self, elapsed_seconds: f64) {
+        self.raw_input.time = Some(elapsed_seconds);
+    }
+
+    /// Starts a new frame by providing a new `Ui` instance to write into.
+    pub fn begin_frame(&mut self) {
+        self.context.begin_frame(self.raw_input.take());
+    }
+
+    /// Ends the frame. Returns the shapes to tessellate and the texture changes to upload.
+    pub fn end_frame(&mut self) -> egui::FullOutput {
+        self.context.end_frame()
+    }
+
+    /// Returns the internal egui context.
+    pub fn context(&self) -> egui::Context {
+        self.context.clone()
+    }
+
+    /// Returns a mutable reference to the raw input that will be passed to egui
+    /// the next time `begin_frame` is called.
+    pub fn raw_input_mut(&mut self) -> &mut egui::RawInput {
+        &mut self.raw_input
+    }
+
+    /// Returns the scale factor of the last `ScaleFactorChanged` event.
+    pub fn scale_factor(&self) -> f64 {
+        self.scale_factor
+    }
+}
+
+/// Translates winit to egui keycodes.
+#[inline]
+fn winit_to_egui_key_code(key: VirtualKeyCode) -> Option<egui::Key> {
+    Some(match key {
+        VirtualKeyCode::Escape => egui::Key::Escape,
+        VirtualKeyCode::Insert => egui::Key::Insert,
+        VirtualKeyCode::Home => egui::Key::Home,
+        VirtualKeyCode::Delete => egui::Key::Delete,
+        VirtualKeyCode::End => egui::Key::End,
+        VirtualKeyCode::PageDown => egui::Key::PageDown,
+        VirtualKeyCode::PageUp => egui::Key::PageUp,
+        VirtualKeyCode::Left => egui::Key::ArrowLeft,
+        VirtualKeyCode::Up => egui::Key::ArrowUp,
+        VirtualKeyCode::Right => egui::Key::ArrowRight,
+        VirtualKeyCode::Down => egui::Key::ArrowDown,
+        VirtualKeyCode::Back => egui::Key::Backspace,
+        VirtualKeyCode::Return => egui::Key::Enter,
+        VirtualKeyCode::Tab => egui::Key::Tab,
+        VirtualKeyCode::Space => egui::Key::Space,
+
+        VirtualKeyCode::A => egui::Key::A,
+        VirtualKeyCode::K => egui::Key::K,
+        VirtualKeyCode::U => egui::Key::U,
+        VirtualKeyCode::W => egui::Key::W,
+        VirtualKeyCode::Z => egui::Key::Z,
+
+        _ => {
+            return None;
+        }
+    })
+}
+
+/// Translates winit to egui modifier keys.
+#[inline]
+fn winit_to_egui_modifiers(modifiers: ModifiersState) -> egui::Modifiers {
+    egui::Modifiers {
+        alt: modifiers.alt(),
+        ctrl: modifiers.ctrl(),
+        shift: modifiers.shift(),
+        #[cfg(target_os = "macos")]
+        mac_cmd: modifiers.logo(),
+        #[cfg(target_os = "macos")]
+        command: modifiers.logo(),
+        #[cfg(not(target_os = "macos"))]
+        mac_cmd: false,
+        #[cfg(not(target_os = "macos"))]
+        command: modifiers.ctrl(),
+    }
+}
+
+/// We only want printable characters and ignore all special keys.
+#[inline]
+fn is_printable(chr: char) -> bool {
+    let is_in_private_use_area = ('\u{e000}'..='\u{f8ff}').contains(&chr)
+        || ('\u{f0000}'..='\u{ffffd}').contains(&chr)
+        || ('\u{100000}'..='\u{10fffd}').contains(&chr);
+
+    !is_in_private_use_area && !chr.is_ascii_control()
+}
@@ -4,10 +4,36 @@ layout(location = 0) in vec2 v_tex_coord;
 layout(location = 1) in vec4 v_color;
 layout(location = 0) out vec4 f_color;
 
+layout(set = 0, binding = 0) uniform UniformBuffer
+{
+    vec2 u_screen_size;
+    uint u_gamma_encode;
+    uint u_nearest_filter;
+    uint u_pixel_snapping;
+    float u_pixels_per_point;
+};
 layout(set = 1, binding = 0) uniform texture2D t_texture;
 layout(set = 0, binding = 1) uniform sampler s_texture;
+layout(set = 0, binding = 2) uniform sampler s_texture_nearest;
+
+vec3 srgb_from_linear(vec3 linear)
+{
+    bvec3 cutoff = lessThan(linear, vec3(0.0031308));
+    vec3 lower = linear * vec3(12.92);
+    vec3 higher = vec3(1.055) * pow(linear, vec3(1.0 / 2.4)) - vec3(0.055);
+    return mix(higher, lower, cutoff);
+}
 
 void main()
 {
-    f_color = v_color * texture(sampler2D(t_texture, s_texture), v_tex_coord);
+    if (u_nearest_filter != 0) {
+        f_color = v_color * texture(sampler2D(t_texture, s_texture_nearest), v_tex_coord);
+    } else {
+        f_color = v_color * texture(sampler2D(t_texture, s_texture), v_tex_coord);
+    }
+    if (u_gamma_encode != 0) {
+        // Colors are premultiplied, so encoding the color keeps the blend in gamma space like
+        // egui expects.
+        f_color.rgb = srgb_from_linear(f_color.rgb);
+    }
 }
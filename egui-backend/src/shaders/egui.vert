@@ -3,6 +3,10 @@
 layout(set = 0, binding = 0) uniform UniformBuffer
 {
     vec2 u_screen_size;
+    uint u_gamma_encode;
+    uint u_nearest_filter;
+    uint u_pixel_snapping;
+    float u_pixels_per_point;
 };
 
 layout(location = 0) in vec2 a_pos;
@@ -25,5 +29,9 @@ void main()
     // [u8; 4] SRGB as u32 -> [r, g, b, a]
     vec4 color = vec4(a_color & 0xFFu, (a_color >> 8) & 0xFFu, (a_color >> 16) & 0xFFu, (a_color >> 24) & 0xFFu);
     v_color = vec4(linear_from_srgb(color.rgb), color.a / 255.0);
-    gl_Position = vec4(2.0 * a_pos.x / u_screen_size.x - 1.0, 1.0 - 2.0 * a_pos.y / u_screen_size.y, 0.0, 1.0);
+    vec2 pos = a_pos;
+    if (u_pixel_snapping != 0) {
+        pos = round(pos * u_pixels_per_point) / u_pixels_per_point;
+    }
+    gl_Position = vec4(2.0 * pos.x / u_screen_size.x - 1.0, 1.0 - 2.0 * pos.y / u_screen_size.y, 0.0, 1.0);
 }
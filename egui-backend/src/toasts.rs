@@ -0,0 +1,68 @@
+use std::collections::VecDeque;
+
+const MAX_TOASTS: usize = 5;
+
+struct Toast {
+    text: String,
+    /// `egui::InputState::time` of the first frame the toast was shown in.
+    shown_at: Option<f64>,
+}
+
+/// Short notifications stacked in the bottom right corner that disappear on their own.
+pub struct Toasts {
+    toasts: VecDeque<Toast>,
+    /// Seconds a toast stays on screen.
+    pub duration: f64,
+}
+
+impl Default for Toasts {
+    fn default() -> Self {
+        Self {
+            toasts: VecDeque::new(),
+            duration: 3.0,
+        }
+    }
+}
+
+impl Toasts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues a notification, shown from the next `show` on.
+    pub fn add(&mut self, text: impl Into<String>) {
+        if self.toasts.len() == MAX_TOASTS {
+            self.toasts.pop_front();
+        }
+        self.toasts.push_back(Toast {
+            text: text.into(),
+            shown_at: None,
+        });
+    }
+
+    /// Call once per frame between `begin_frame` and `end_frame`.
+    pub fn show(&mut self, ctx: &egui::Context) {
+        let now = ctx.input().time;
+        let duration = self.duration;
+        self.toasts.retain(|toast| match toast.shown_at {
+            Some(shown_at) => now - shown_at < duration,
+            None => true,
+        });
+        if self.toasts.is_empty() {
+            return;
+        }
+
+        egui::Area::new("toasts")
+            .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-10.0, -10.0))
+            .interactable(false)
+            .show(ctx, |ui| {
+                for toast in self.toasts.iter_mut() {
+                    toast.shown_at.get_or_insert(now);
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.label(&toast.text);
+                    });
+                }
+            });
+        ctx.request_repaint();
+    }
+}
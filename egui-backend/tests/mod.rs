@@ -2,7 +2,6 @@ use std::sync::Arc;
 use std::time::Instant;
 
 use egui_backend::*;
-use epi::egui;
 use safe_vk::{
     vk, Allocator, BinarySemaphore, CommandBuffer, CommandPool, Device, Entry, Fence, Instance,
     PhysicalDevice, Surface, Swapchain,
@@ -22,46 +21,67 @@ fn test_all() {
 
     rt.block_on(async {
         let entry = Arc::new(Entry::new().unwrap());
-        let surface_extensions = ash_window::enumerate_required_extensions(&window)
-            .unwrap()
-            .iter()
-            .map(|s| s.to_str().unwrap())
-            .collect::<Vec<_>>();
-        let mut extensions = surface_extensions;
-        extensions.push(safe_vk::name::instance::extension::ext::DEBUG_UTILS);
+        #[cfg(target_os = "linux")]
+        let extensions = [
+            safe_vk::name::instance::Extension::KhrSurface,
+            safe_vk::name::instance::Extension::ExtDebugUtils,
+            safe_vk::name::instance::Extension::KhrXcbSurface,
+            safe_vk::name::instance::Extension::KhrXlibSurface,
+        ];
+        #[cfg(target_os = "windows")]
+        let extensions = [
+            safe_vk::name::instance::Extension::KhrSurface,
+            safe_vk::name::instance::Extension::ExtDebugUtils,
+            safe_vk::name::instance::Extension::KhrWin32Surface,
+        ];
         let instance = Arc::new(Instance::new(
             entry.clone(),
             &[
-                safe_vk::name::instance::layer::khronos::VALIDATION,
-                safe_vk::name::instance::layer::lunarg::MONITOR,
+                safe_vk::name::instance::Layer::KhronosValidation,
+                safe_vk::name::instance::Layer::LunargMonitor,
             ],
-            extensions.as_slice(),
+            &extensions,
         ));
 
         let surface = Arc::new(Surface::new(instance.clone(), &window));
-        let pdevice = Arc::new(PhysicalDevice::new(instance.clone(), Some(surface)));
+        let pdevice = Arc::new(PhysicalDevice::new(
+            instance.clone(),
+            Some(surface.as_ref()),
+        ));
         let device = Arc::new(Device::new(
             pdevice.clone(),
             &vk::PhysicalDeviceFeatures::default(),
-            &[safe_vk::name::device::extension::khr::SWAPCHAIN],
+            &[safe_vk::name::device::Extension::KhrSwapchain],
         ));
         println!("swapchain images created");
 
         let allocator = Arc::new(Allocator::new(device.clone()));
 
-        let mut ui_pass = UiPass::new(allocator.clone());
+        let mut platform = Platform::new(PlatformDescriptor {
+            physical_width: window.inner_size().width,
+            physical_height: window.inner_size().height,
+            scale_factor: window.scale_factor(),
+            font_definitions: Default::default(),
+            style: Default::default(),
+        });
 
-        let mut platform =
-            egui_winit_platform::Platform::new(egui_winit_platform::PlatformDescriptor {
+        let render_finish_semaphore = Arc::new(BinarySemaphore::new(device.clone()));
+        let swapchain = Arc::new(Swapchain::new(
+            device.clone(),
+            surface.clone(),
+            vk::PresentModeKHR::FIFO,
+        ));
+        let mut ui_pass = UiPass::new(
+            allocator.clone(),
+            swapchain.format(),
+            vk::ImageLayout::PRESENT_SRC_KHR,
+            ScreenDescriptor {
                 physical_width: window.inner_size().width,
                 physical_height: window.inner_size().height,
-                scale_factor: window.scale_factor(),
-                font_definitions: Default::default(),
-                style: Default::default(),
-            });
-
-        let render_finish_semaphore = Arc::new(BinarySemaphore::new(device.clone()));
-        let swapchain = Arc::new(Swapchain::new(device.clone()));
+                scale_factor: window.scale_factor() as f32,
+            },
+            swapchain.image_count() as usize,
+        );
         let command_pool = Arc::new(CommandPool::new(device.clone()));
         let swapchain_images = safe_vk::Image::from_swapchain(swapchain.clone())
             .into_iter()
@@ -141,32 +161,23 @@ fn test_all() {
                 winit::event::Event::RedrawRequested(_) => {
                     platform.update_time(start_time.elapsed().as_secs_f64());
                     platform.begin_frame();
-                    egui::TopPanel::top(egui::Id::new("menu bar"))
-                        .show(&platform.context().clone(), |ui| ui.button("fuck"));
-
-                    let (_output, paint_commands) = platform.end_frame();
-                    let paint_jobs = platform.context().tessellate(paint_commands);
-                    ui_pass.update_texture(&platform.context().texture());
-                    let screen_descriptor = ScreenDescriptor {
-                        physical_width: window.inner_size().width,
-                        physical_height: window.inner_size().height,
-                        scale_factor: window.scale_factor() as f32,
-                    };
-                    ui_pass.update_buffers(&paint_jobs, &screen_descriptor);
-
-                    let (index, _) = swapchain.acquire_next_image();
+                    egui::TopBottomPanel::top("menu bar")
+                        .show(&platform.context(), |ui| ui.button("fuck"));
+
+                    let full_output = platform.end_frame();
+                    let paint_jobs = platform.context().tessellate(full_output.shapes);
+                    ui_pass.update_buffers(&paint_jobs);
+
+                    let index = swapchain.acquire_next_image().unwrap().index().unwrap();
                     let mut command_buffer = CommandBuffer::new(command_pool.clone());
                     command_buffer.encode(|recorder| {
+                        ui_pass.update_textures(recorder, &full_output.textures_delta);
                         recorder.set_image_layout(
                             swapchain_images[index as usize].clone(),
+                            None,
                             vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
                         );
-                        ui_pass.execute(
-                            recorder,
-                            swapchain_images[index as usize].clone(),
-                            &paint_jobs,
-                            &screen_descriptor,
-                        );
+                        ui_pass.execute(recorder, swapchain_images[index as usize].clone());
                     });
                     fence.wait();
                     fence = queue.submit_binary(
@@ -175,6 +186,7 @@ fn test_all() {
                         &[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT],
                         &[&render_finish_semaphore],
                     );
+                    ui_pass.free_textures(&full_output.textures_delta);
                     queue.present(&swapchain, index, &[&render_finish_semaphore]);
                 }
                 winit::event::Event::RedrawEventsCleared => {}
@@ -183,3 +195,82 @@ fn test_all() {
         });
     });
 }
+
+#[test]
+fn test_offscreen() {
+    let entry = Arc::new(Entry::new().unwrap());
+    let instance = Arc::new(Instance::new(
+        entry.clone(),
+        &[safe_vk::name::instance::Layer::KhronosValidation],
+        &[safe_vk::name::instance::Extension::ExtDebugUtils],
+    ));
+    let pdevice = Arc::new(PhysicalDevice::new(instance.clone(), None));
+    let device = Arc::new(Device::new(
+        pdevice.clone(),
+        &vk::PhysicalDeviceFeatures::default(),
+        &[],
+    ));
+    let allocator = Arc::new(Allocator::new(device.clone()));
+    let mut queue = safe_vk::Queue::new(device.clone());
+    let command_pool = Arc::new(CommandPool::new(device.clone()));
+
+    let (width, height) = (320, 240);
+    let mut ui_pass = UiPass::new(
+        allocator.clone(),
+        vk::Format::R8G8B8A8_UNORM,
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        ScreenDescriptor {
+            physical_width: width,
+            physical_height: height,
+            scale_factor: 1.0,
+        },
+        1,
+    );
+    ui_pass.set_load_op(LoadOp::Clear(egui::Rgba::BLACK), vk::ImageLayout::UNDEFINED);
+    let target = Arc::new(safe_vk::Image::new(
+        Some("offscreen target"),
+        allocator.clone(),
+        vk::Format::R8G8B8A8_UNORM,
+        width,
+        height,
+        vk::ImageTiling::OPTIMAL,
+        vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+        safe_vk::MemoryUsage::GpuOnly,
+    ));
+
+    let context = egui::Context::default();
+    context.begin_frame(egui::RawInput {
+        screen_rect: Some(egui::Rect::from_min_size(
+            Default::default(),
+            egui::vec2(width as f32, height as f32),
+        )),
+        ..Default::default()
+    });
+    egui::CentralPanel::default().show(&context, |ui| ui.label("Box of Chocolates"));
+    let full_output = context.end_frame();
+    let paint_jobs = context.tessellate(full_output.shapes);
+    ui_pass.update_buffers(&paint_jobs);
+
+    let pixels =
+        ui_pass.render_offscreen(&mut queue, command_pool, &full_output.textures_delta, target);
+    assert_eq!(pixels.len(), (width * height * 4) as usize);
+    // The panel covers the black clear color.
+    assert!(pixels.chunks(4).any(|pixel| pixel != [0, 0, 0, 255]));
+}
+
+#[test]
+fn test_frame_stats_lows() {
+    let mut frame_stats = FrameStats::new();
+    assert_eq!(frame_stats.average(), None);
+    assert_eq!(frame_stats.low(0.01), None);
+
+    for i in 0..500 {
+        frame_stats.push_frame_time(if i % 100 == 0 { 50.0 } else { 10.0 });
+    }
+    assert!((frame_stats.average().unwrap() - 10.4).abs() < 1e-9);
+    // The slowest 1% are the five 50 ms frames.
+    assert_eq!(frame_stats.low(0.01), Some(50.0));
+    // 0.1% of 500 frames rounds up to the single slowest one.
+    assert_eq!(frame_stats.low(0.001), Some(50.0));
+    assert_eq!(frame_stats.low(0.02), Some(30.0));
+}
@@ -0,0 +1,200 @@
+use std::sync::Arc;
+
+use bytemuck::{Pod, Zeroable};
+use safe_vk::{vk, ComputePipelineRecorder, Pipeline, PipelineRecorder};
+
+use crate::shaders;
+
+const WORKGROUP_SIZE: u32 = 16;
+
+/// Size of `PixelMoments` in adaptive.glsl.
+const PIXEL_MOMENTS_SIZE: u64 = 16;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct HeatmapPushConstants {
+    sample_count: u32,
+}
+
+/// Stops sampling pixels once their noise is low enough, so that the samples go where noise
+/// remains.
+///
+/// raytrace.rgen keeps the mean and mean square of the luminance of every pixel in the moments
+/// buffer, along with how many samples it has. Pixels whose standard error falls below
+/// `threshold` times their mean are skipped from then on.
+pub struct AdaptiveSampling {
+    moments_buffer: Arc<safe_vk::Buffer>,
+    heatmap_pipeline: Arc<safe_vk::ComputePipeline>,
+    heatmap_descriptor_set: Arc<safe_vk::DescriptorSet>,
+    extent: (u32, u32),
+    pub enabled: bool,
+    /// Largest standard error relative to the mean a converged pixel has.
+    pub threshold: f32,
+    /// Samples every pixel gets before it can be skipped.
+    pub min_samples: u32,
+    /// Shows the share of the samples every pixel got instead of the render.
+    pub show_heatmap: bool,
+}
+
+impl AdaptiveSampling {
+    pub fn new(allocator: Arc<safe_vk::Allocator>, target_image: Arc<safe_vk::Image>) -> Self {
+        let device = allocator.device().clone();
+        let descriptor_set_layout = Arc::new(safe_vk::DescriptorSetLayout::new(
+            device.clone(),
+            Some("sample heatmap set layout"),
+            &[
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 0,
+                    descriptor_type: safe_vk::DescriptorType::StorageImage,
+                    stage_flags: vk::ShaderStageFlags::COMPUTE,
+                },
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 14,
+                    descriptor_type: safe_vk::DescriptorType::StorageBuffer,
+                    stage_flags: vk::ShaderStageFlags::COMPUTE,
+                },
+            ],
+        ));
+
+        let pipeline_layout = Arc::new(safe_vk::PipelineLayout::new(
+            device.clone(),
+            Some("sample heatmap pipeline layout"),
+            &[&descriptor_set_layout],
+            &[vk::PushConstantRange::builder()
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .offset(0)
+                .size(std::mem::size_of::<HeatmapPushConstants>() as u32)
+                .build()],
+        ));
+
+        let heatmap_pipeline = Arc::new(safe_vk::ComputePipeline::new(
+            Some("sample heatmap pipeline"),
+            pipeline_layout,
+            Arc::new(safe_vk::ShaderStage::new(
+                Arc::new(safe_vk::ShaderModule::new(
+                    device.clone(),
+                    shaders::Shaders::get("sample_heatmap.comp.spv").unwrap(),
+                )),
+                vk::ShaderStageFlags::COMPUTE,
+                "main",
+            )),
+        ));
+
+        let descriptor_pool = Arc::new(safe_vk::DescriptorPool::new(
+            device,
+            &[
+                vk::DescriptorPoolSize::builder()
+                    .ty(vk::DescriptorType::STORAGE_IMAGE)
+                    .descriptor_count(1)
+                    .build(),
+                vk::DescriptorPoolSize::builder()
+                    .ty(vk::DescriptorType::STORAGE_BUFFER)
+                    .descriptor_count(1)
+                    .build(),
+            ],
+            1,
+        ));
+
+        let heatmap_descriptor_set = Arc::new(safe_vk::DescriptorSet::new(
+            Some("sample heatmap descriptor set"),
+            descriptor_pool,
+            descriptor_set_layout,
+        ));
+
+        let adaptive_sampling = Self {
+            moments_buffer: create_moments_buffer(&allocator, &target_image),
+            heatmap_pipeline,
+            heatmap_descriptor_set,
+            extent: (target_image.width(), target_image.height()),
+            enabled: false,
+            threshold: 0.02,
+            min_samples: 64,
+            show_heatmap: false,
+        };
+        adaptive_sampling.update_descriptor_set(target_image);
+        adaptive_sampling
+    }
+
+    /// Recreates the moments buffer for a new render size. Binding 14 of the ray tracing set
+    /// must be updated with the new `moments_buffer`.
+    pub fn resize(
+        &mut self,
+        allocator: &Arc<safe_vk::Allocator>,
+        target_image: Arc<safe_vk::Image>,
+    ) {
+        self.moments_buffer = create_moments_buffer(allocator, &target_image);
+        self.extent = (target_image.width(), target_image.height());
+        self.update_descriptor_set(target_image);
+    }
+
+    /// Binding 14 of adaptive.glsl, only valid where the accumulated sample count is non-zero.
+    pub fn moments_buffer(&self) -> &Arc<safe_vk::Buffer> {
+        &self.moments_buffer
+    }
+
+    /// Draws the heatmap over the target image when `show_heatmap` is set. The image must be in
+    /// `GENERAL` layout.
+    pub fn record_heatmap(&self, recorder: &mut safe_vk::CommandRecorder, sample_count: u32) {
+        if !self.show_heatmap {
+            return;
+        }
+        let (width, height) = self.extent;
+        let push_constants = HeatmapPushConstants { sample_count };
+        // The heatmap overwrites what the previous pass wrote to the image.
+        recorder.memory_barrier(
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::AccessFlags::SHADER_WRITE,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::SHADER_WRITE,
+        );
+        recorder.bind_compute_pipeline(self.heatmap_pipeline.clone(), |recorder, pipeline| {
+            recorder.bind_descriptor_sets(
+                vec![self.heatmap_descriptor_set.clone()],
+                pipeline.layout(),
+                0,
+            );
+            recorder.push_constants(
+                pipeline.layout(),
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                bytemuck::bytes_of(&push_constants),
+            );
+            recorder.dispatch(
+                (width + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE,
+                (height + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE,
+                1,
+            );
+        });
+    }
+
+    fn update_descriptor_set(&self, target_image: Arc<safe_vk::Image>) {
+        self.heatmap_descriptor_set.update(&[
+            safe_vk::DescriptorSetUpdateInfo {
+                binding: 0,
+                detail: safe_vk::DescriptorSetUpdateDetail::Image(Arc::new(
+                    safe_vk::ImageView::new(target_image),
+                )),
+            },
+            safe_vk::DescriptorSetUpdateInfo {
+                binding: 14,
+                detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
+                    buffer: self.moments_buffer.clone(),
+                    offset: 0,
+                },
+            },
+        ]);
+    }
+}
+
+fn create_moments_buffer(
+    allocator: &Arc<safe_vk::Allocator>,
+    target_image: &safe_vk::Image,
+) -> Arc<safe_vk::Buffer> {
+    Arc::new(safe_vk::Buffer::new(
+        Some("pixel moments"),
+        allocator.clone(),
+        target_image.width() as u64 * target_image.height() as u64 * PIXEL_MOMENTS_SIZE,
+        vk::BufferUsageFlags::STORAGE_BUFFER,
+        safe_vk::MemoryUsage::GpuOnly,
+    ))
+}
@@ -0,0 +1,256 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use camera::{CameraPath, CameraState};
+use safe_vk::vk;
+use serde::Serialize;
+
+/// Seconds between memory samples, the stats walk every allocation.
+const MEMORY_SAMPLE_INTERVAL: f64 = 1.0;
+
+/// Plays a camera path over a fixed duration and records every frame, then writes a report and
+/// exits. Runs of the same path compare drivers and code changes.
+///
+/// The report is CSV with a row per frame if its extension is `csv`, else JSON with the device,
+/// a summary, the memory heaps and the frames.
+pub struct Benchmark {
+    camera_path: CameraPath,
+    /// Seconds the path plays over.
+    duration: f64,
+    report_path: PathBuf,
+    /// When the first frame started.
+    start: Option<Instant>,
+    last_frame: Option<Instant>,
+    frames: Vec<FrameRecord>,
+    heaps: Vec<HeapRecord>,
+    memory_sampled_at: Option<Instant>,
+    exit_code: Option<i32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct FrameRecord {
+    /// Seconds from the start of the benchmark to the end of the frame.
+    time: f64,
+    frame_time_ms: f64,
+    /// Samples per pixel the frame traced.
+    samples: u32,
+    /// Bytes of device local memory in use at the last memory sample.
+    device_memory: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+struct HeapRecord {
+    device_local: bool,
+    size: u64,
+    budget: u64,
+    peak_usage: u64,
+    final_usage: u64,
+    allocation_count: u32,
+}
+
+/// What the report says about the machine, so runs on different drivers can be told apart.
+pub struct DeviceInfo {
+    pub name: String,
+    pub driver_version: u32,
+    pub api_version: u32,
+    pub renderer: &'static str,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Serialize)]
+struct Report<'a> {
+    device: String,
+    /// Vendor specific encoding, as reported by Vulkan.
+    driver_version: u32,
+    api_version: String,
+    renderer: &'static str,
+    width: u32,
+    height: u32,
+    duration: f64,
+    frame_count: usize,
+    average_frame_time_ms: f64,
+    low_1_percent_ms: f64,
+    low_01_percent_ms: f64,
+    /// Pixel samples traced per second.
+    samples_per_second: f64,
+    heaps: &'a [HeapRecord],
+    frames: &'a [FrameRecord],
+}
+
+impl Benchmark {
+    /// Loads the camera path saved at `camera_path`. `duration` defaults to the length of the
+    /// path.
+    pub fn new(
+        camera_path: &Path,
+        duration: Option<f32>,
+        report_path: PathBuf,
+    ) -> std::io::Result<Self> {
+        let camera_path = CameraPath::load(camera_path)?;
+        if camera_path.keyframes().is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "the camera path has no keyframes",
+            ));
+        }
+        let duration = duration.unwrap_or_else(|| camera_path.duration()).max(1.0) as f64;
+        Ok(Self {
+            camera_path,
+            duration,
+            report_path,
+            start: None,
+            last_frame: None,
+            frames: Vec::new(),
+            heaps: Vec::new(),
+            memory_sampled_at: None,
+            exit_code: None,
+        })
+    }
+
+    /// The pose of the frame starting now. The first call starts the benchmark.
+    pub fn camera_state(&mut self) -> Option<CameraState> {
+        let now = Instant::now();
+        let start = *self.start.get_or_insert(now);
+        if self.last_frame.is_none() {
+            self.last_frame = Some(now);
+        }
+        let progress = ((now - start).as_secs_f64() / self.duration).min(1.0);
+        self.camera_path
+            .sample(progress as f32 * self.camera_path.duration())
+    }
+
+    /// Records the frame that just ended, which traced `samples` samples per pixel.
+    pub fn record_frame(&mut self, samples: u32, allocator: &safe_vk::Allocator) {
+        let (start, last_frame) = match (self.start, self.last_frame) {
+            (Some(start), Some(last_frame)) => (start, last_frame),
+            _ => return,
+        };
+        let now = Instant::now();
+        if self.memory_sampled_at.map_or(true, |sampled_at| {
+            (now - sampled_at).as_secs_f64() >= MEMORY_SAMPLE_INTERVAL
+        }) {
+            self.sample_memory(allocator);
+            self.memory_sampled_at = Some(now);
+        }
+        let device_memory = self
+            .heaps
+            .iter()
+            .filter(|heap| heap.device_local)
+            .map(|heap| heap.final_usage)
+            .sum();
+        self.frames.push(FrameRecord {
+            time: (now - start).as_secs_f64(),
+            frame_time_ms: (now - last_frame).as_secs_f64() * 1000.0,
+            samples,
+            device_memory,
+        });
+        self.last_frame = Some(now);
+    }
+
+    fn sample_memory(&mut self, allocator: &safe_vk::Allocator) {
+        let heaps = allocator.heap_usage();
+        self.heaps.resize_with(heaps.len(), HeapRecord::default);
+        for (record, heap) in self.heaps.iter_mut().zip(heaps.iter()) {
+            record.device_local = heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL);
+            record.size = heap.size;
+            record.budget = heap.budget;
+            record.peak_usage = record.peak_usage.max(heap.usage);
+            record.final_usage = heap.usage;
+            record.allocation_count = heap.allocation_count;
+        }
+    }
+
+    /// Whether the path has played to the end.
+    pub fn is_done(&self) -> bool {
+        self.frames
+            .last()
+            .map_or(false, |frame| frame.time >= self.duration)
+    }
+
+    /// Writes the report and sets the exit code, 0 if it was written and 1 otherwise.
+    pub fn finish(&mut self, device: DeviceInfo, allocator: &safe_vk::Allocator) {
+        self.sample_memory(allocator);
+        let result = self.write_report(&device);
+        match &result {
+            Ok(()) => log::info!("benchmark report written to {}", self.report_path.display()),
+            Err(e) => log::error!("failed to write {}: {}", self.report_path.display(), e),
+        }
+        self.exit_code = Some(if result.is_ok() { 0 } else { 1 });
+    }
+
+    /// Set once the report has been written or has failed.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    fn write_report(&self, device: &DeviceInfo) -> std::io::Result<()> {
+        if let Some(parent) = self.report_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let is_csv = self
+            .report_path
+            .extension()
+            .map_or(false, |extension| extension == "csv");
+        if is_csv {
+            return self.write_csv();
+        }
+
+        let frame_times: Vec<f64> = self
+            .frames
+            .iter()
+            .map(|frame| frame.frame_time_ms)
+            .collect();
+        let elapsed = self.frames.last().map_or(0.0, |frame| frame.time);
+        let pixels = device.width as f64 * device.height as f64;
+        let samples: f64 = self.frames.iter().map(|frame| frame.samples as f64).sum();
+        let report = Report {
+            device: device.name.clone(),
+            driver_version: device.driver_version,
+            api_version: format!(
+                "{}.{}.{}",
+                vk::version_major(device.api_version),
+                vk::version_minor(device.api_version),
+                vk::version_patch(device.api_version)
+            ),
+            renderer: device.renderer,
+            width: device.width,
+            height: device.height,
+            duration: self.duration,
+            frame_count: self.frames.len(),
+            average_frame_time_ms: average(&frame_times),
+            low_1_percent_ms: low(&frame_times, 0.01),
+            low_01_percent_ms: low(&frame_times, 0.001),
+            samples_per_second: samples * pixels / elapsed.max(f64::EPSILON),
+            heaps: &self.heaps,
+            frames: &self.frames,
+        };
+        let json = serde_json::to_string_pretty(&report)?;
+        std::fs::write(&self.report_path, json)
+    }
+
+    fn write_csv(&self) -> std::io::Result<()> {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(&self.report_path)?);
+        writeln!(file, "frame,time,frame_time_ms,samples,device_memory")?;
+        for (index, frame) in self.frames.iter().enumerate() {
+            writeln!(
+                file,
+                "{},{:.6},{:.3},{},{}",
+                index, frame.time, frame.frame_time_ms, frame.samples, frame.device_memory
+            )?;
+        }
+        file.flush()
+    }
+}
+
+fn average(frame_times: &[f64]) -> f64 {
+    frame_times.iter().sum::<f64>() / frame_times.len().max(1) as f64
+}
+
+/// Average of the slowest `fraction` of the frame times, at least one.
+fn low(frame_times: &[f64], fraction: f64) -> f64 {
+    let mut sorted = frame_times.to_vec();
+    sorted.sort_by(|a, b| b.partial_cmp(a).unwrap());
+    let count = ((sorted.len() as f64 * fraction).ceil() as usize).max(1);
+    average(&sorted[..count.min(sorted.len())])
+}
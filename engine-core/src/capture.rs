@@ -0,0 +1,286 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Copies a four channel float image back to the host. Blocks until the GPU is done.
+pub fn read_image(
+    queue: &mut safe_vk::Queue,
+    command_pool: Arc<safe_vk::CommandPool>,
+    allocator: Arc<safe_vk::Allocator>,
+    image: Arc<safe_vk::Image>,
+) -> Vec<f32> {
+    match image.read_to_vec(allocator, queue, command_pool).unwrap() {
+        safe_vk::Pixels::Rgba32F(pixels) => pixels,
+        safe_vk::Pixels::Rgba8(_) => panic!("{:?} is no float format", image.format()),
+    }
+}
+
+fn srgb_from_linear(linear: f32) -> u8 {
+    let linear = linear.clamp(0.0, 1.0);
+    let srgb = if linear < 0.0031308 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    };
+    (srgb * 255.0).round() as u8
+}
+
+/// Converts four channel linear pixels to opaque sRGB encoded bytes.
+fn encode_srgb(pixels: &[f32]) -> Vec<u8> {
+    pixels
+        .chunks_exact(4)
+        .flat_map(|pixel| {
+            vec![
+                srgb_from_linear(pixel[0]),
+                srgb_from_linear(pixel[1]),
+                srgb_from_linear(pixel[2]),
+                255,
+            ]
+        })
+        .collect()
+}
+
+/// Writes four channel linear pixels as an sRGB encoded PNG.
+pub fn save_png(path: &Path, width: u32, height: u32, pixels: &[f32]) -> image::ImageResult<()> {
+    image::save_buffer(
+        path,
+        &encode_srgb(pixels),
+        width,
+        height,
+        image::ColorType::Rgba8,
+    )
+}
+
+/// Reads `image` back and writes it to a new timestamped PNG in `directory`, created if missing.
+/// Returns the path written.
+pub fn save_screenshot(
+    directory: &str,
+    queue: &mut safe_vk::Queue,
+    command_pool: Arc<safe_vk::CommandPool>,
+    allocator: Arc<safe_vk::Allocator>,
+    image: Arc<safe_vk::Image>,
+) -> image::ImageResult<PathBuf> {
+    let pixels = read_image(queue, command_pool, allocator, image.clone());
+    let path = PathBuf::from(directory).join(format!("screenshot-{}.png", timestamp()));
+    std::fs::create_dir_all(directory).map_err(image::ImageError::IoError)?;
+    save_png(&path, image.width(), image.height(), &pixels)?;
+    Ok(path)
+}
+
+/// Seconds since the Unix epoch, for output file names.
+pub fn timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureOutput {
+    /// Numbered PNGs in a directory per capture.
+    Png,
+    /// Raw frames piped to an `ffmpeg` on the `PATH`, encoded to H.264.
+    Ffmpeg,
+}
+
+enum Sink {
+    Png {
+        directory: PathBuf,
+    },
+    Ffmpeg {
+        path: PathBuf,
+        /// Started with the first frame, once the frame size is known.
+        process: Option<(Child, (u32, u32))>,
+    },
+}
+
+/// Writes every Nth rendered frame to disk, e.g. to make a turntable video with the camera path.
+pub struct FrameCapture {
+    /// Capture one of this many frames.
+    pub frame_interval: u32,
+    pub output: CaptureOutput,
+    /// Frame rate of the encoded video.
+    pub frame_rate: u32,
+    /// Directory captures are written to.
+    pub directory: String,
+    sink: Option<Sink>,
+    frame_count: u64,
+    captured_count: u64,
+    status: Option<String>,
+}
+
+impl FrameCapture {
+    pub fn new(directory: &str) -> Self {
+        Self {
+            frame_interval: 1,
+            output: CaptureOutput::Png,
+            frame_rate: 30,
+            directory: directory.to_owned(),
+            sink: None,
+            frame_count: 0,
+            captured_count: 0,
+            status: None,
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.add_enabled_ui(self.sink.is_none(), |ui| {
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut self.output, CaptureOutput::Png, "PNG Sequence");
+                ui.radio_value(&mut self.output, CaptureOutput::Ffmpeg, "ffmpeg");
+            });
+            ui.add(
+                egui::DragValue::new(&mut self.frame_interval)
+                    .clamp_range(1..=1000)
+                    .prefix("Every Nth Frame: "),
+            );
+            if self.output == CaptureOutput::Ffmpeg {
+                ui.add(
+                    egui::DragValue::new(&mut self.frame_rate)
+                        .clamp_range(1..=240)
+                        .prefix("Frame Rate: "),
+                );
+            }
+            ui.horizontal(|ui| {
+                ui.label("Directory");
+                ui.text_edit_singleline(&mut self.directory);
+            });
+        });
+        if self.sink.is_some() {
+            ui.label(format!("Captured {} frames", self.captured_count));
+            if ui.button("Stop Capture").clicked() {
+                self.stop();
+            }
+        } else if ui.button("Start Capture").clicked() {
+            self.start();
+        }
+        if let Some(status) = &self.status {
+            ui.label(status);
+        }
+    }
+
+    pub fn start(&mut self) {
+        self.stop();
+        let directory = PathBuf::from(&self.directory);
+        if let Err(e) = std::fs::create_dir_all(&directory) {
+            self.status = Some(format!("Failed to create {}: {}", directory.display(), e));
+            return;
+        }
+        self.sink = Some(match self.output {
+            CaptureOutput::Png => Sink::Png {
+                directory: directory.join(format!("capture-{}", timestamp())),
+            },
+            CaptureOutput::Ffmpeg => Sink::Ffmpeg {
+                path: directory.join(format!("capture-{}.mp4", timestamp())),
+                process: None,
+            },
+        });
+        self.frame_count = 0;
+        self.captured_count = 0;
+        self.status = None;
+    }
+
+    /// Finishes the capture. Waits for ffmpeg to finish encoding.
+    pub fn stop(&mut self) {
+        match self.sink.take() {
+            Some(Sink::Ffmpeg {
+                path,
+                process: Some((mut process, _)),
+            }) => {
+                // Closing stdin ends the input stream.
+                drop(process.stdin.take());
+                match process.wait() {
+                    Ok(status) if status.success() => {
+                        self.status = Some(format!(
+                            "Encoded {} frames to {}",
+                            self.captured_count,
+                            path.display()
+                        ));
+                    }
+                    Ok(status) => self.status = Some(format!("ffmpeg exited with {}", status)),
+                    Err(e) => self.status = Some(format!("Failed to wait for ffmpeg: {}", e)),
+                }
+            }
+            Some(Sink::Png { directory }) => {
+                self.status = Some(format!(
+                    "Wrote {} frames to {}",
+                    self.captured_count,
+                    directory.display()
+                ));
+            }
+            Some(Sink::Ffmpeg { process: None, .. }) | None => {}
+        }
+    }
+
+    /// Counts a rendered frame and returns whether it should be passed to `write_frame`.
+    pub fn should_capture(&mut self) -> bool {
+        if self.sink.is_none() {
+            return false;
+        }
+        let capture = self.frame_count % self.frame_interval as u64 == 0;
+        self.frame_count += 1;
+        capture
+    }
+
+    /// Writes four channel linear pixels of a frame. The frame size must not change during a
+    /// capture.
+    pub fn write_frame(&mut self, width: u32, height: u32, pixels: &[f32]) {
+        let index = self.captured_count;
+        let frame_rate = self.frame_rate;
+        let result = match self.sink.as_mut() {
+            Some(Sink::Png { directory }) => std::fs::create_dir_all(&*directory)
+                .map_err(image::ImageError::IoError)
+                .and_then(|_| {
+                    let path = directory.join(format!("{:06}.png", index));
+                    save_png(&path, width, height, pixels)
+                })
+                .map_err(|e| e.to_string()),
+            Some(Sink::Ffmpeg { path, process }) => {
+                let started = match process {
+                    Some(_) => Ok(()),
+                    None => spawn_ffmpeg(path, width, height, frame_rate)
+                        .map(|child| *process = Some((child, (width, height))))
+                        .map_err(|e| format!("failed to start ffmpeg: {}", e)),
+                };
+                started.and_then(|()| match process.as_mut().unwrap() {
+                    (_, size) if *size != (width, height) => {
+                        Err("the frame size changed".to_owned())
+                    }
+                    (child, _) => child
+                        .stdin
+                        .as_mut()
+                        .unwrap()
+                        .write_all(&encode_srgb(pixels))
+                        .map_err(|e| e.to_string()),
+                })
+            }
+            None => return,
+        };
+        match result {
+            Ok(()) => self.captured_count += 1,
+            Err(e) => {
+                log::warn!("frame capture failed: {}", e);
+                self.stop();
+                self.status = Some(format!("Capture stopped: {}", e));
+            }
+        }
+    }
+}
+
+fn spawn_ffmpeg(path: &Path, width: u32, height: u32, frame_rate: u32) -> std::io::Result<Child> {
+    Command::new("ffmpeg")
+        .args(&["-y", "-loglevel", "error"])
+        .args(&["-f", "rawvideo", "-pixel_format", "rgba"])
+        .arg("-video_size")
+        .arg(format!("{}x{}", width, height))
+        .arg("-framerate")
+        .arg(frame_rate.to_string())
+        .args(&["-i", "-", "-c:v", "libx264", "-pix_fmt", "yuv420p"])
+        // yuv420p needs even dimensions.
+        .args(&["-vf", "crop=trunc(iw/2)*2:trunc(ih/2)*2"])
+        .arg(path)
+        .stdin(Stdio::piped())
+        .spawn()
+}
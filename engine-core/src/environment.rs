@@ -0,0 +1,170 @@
+use std::f32::consts::PI;
+use std::path::Path;
+use std::sync::Arc;
+
+use glam::{vec3, Vec3};
+use safe_vk::vk;
+
+/// Size of the gradient sky used until an environment map is loaded.
+const SKY_WIDTH: u32 = 256;
+const SKY_HEIGHT: u32 = 128;
+
+/// An equirectangular environment map lighting the scene, with the tables to importance sample
+/// it. See environment.glsl.
+pub struct Environment {
+    /// Radiance in rgb and the density of sampling each texel per unit uv area in alpha.
+    image: Arc<safe_vk::Image>,
+    /// The CDF over rows, followed by the CDF within each row. Texels are weighted by luminance
+    /// and by the solid angle they cover.
+    cdf_buffer: Arc<safe_vk::Buffer>,
+    /// Rotation around the up axis in radians.
+    pub rotation: f32,
+    /// Multiplier on the radiance of the map.
+    pub intensity: f32,
+}
+
+impl Environment {
+    /// White at the horizon, blending to blue at the zenith, over a dark ground.
+    pub fn gradient_sky(
+        allocator: Arc<safe_vk::Allocator>,
+        queue: &mut safe_vk::Queue,
+        command_pool: Arc<safe_vk::CommandPool>,
+    ) -> Self {
+        let mut texels: Vec<[f32; 3]> = Vec::with_capacity((SKY_WIDTH * SKY_HEIGHT) as usize);
+        for y in 0..SKY_HEIGHT {
+            let up = ((y as f32 + 0.5) / SKY_HEIGHT as f32 * PI).cos();
+            let color = if up > 0.0 {
+                Vec3::ONE.lerp(vec3(0.25, 0.5, 1.0), up)
+            } else {
+                Vec3::splat(0.03)
+            };
+            texels.extend(std::iter::repeat(color.into()).take(SKY_WIDTH as usize));
+        }
+        Self::from_texels(
+            allocator,
+            queue,
+            command_pool,
+            SKY_WIDTH,
+            SKY_HEIGHT,
+            &texels,
+        )
+    }
+
+    /// Loads an equirectangular Radiance HDR file.
+    pub fn from_hdr<P: AsRef<Path>>(
+        allocator: Arc<safe_vk::Allocator>,
+        queue: &mut safe_vk::Queue,
+        command_pool: Arc<safe_vk::CommandPool>,
+        path: P,
+    ) -> image::ImageResult<Self> {
+        let file = std::fs::File::open(path).map_err(image::ImageError::IoError)?;
+        let decoder = image::codecs::hdr::HdrDecoder::new(std::io::BufReader::new(file))?;
+        let metadata = decoder.metadata();
+        let texels = decoder
+            .read_image_hdr()?
+            .iter()
+            .map(|pixel| pixel.0)
+            .collect::<Vec<_>>();
+        Ok(Self::from_texels(
+            allocator,
+            queue,
+            command_pool,
+            metadata.width,
+            metadata.height,
+            &texels,
+        ))
+    }
+
+    fn from_texels(
+        allocator: Arc<safe_vk::Allocator>,
+        queue: &mut safe_vk::Queue,
+        command_pool: Arc<safe_vk::CommandPool>,
+        width: u32,
+        height: u32,
+        texels: &[[f32; 3]],
+    ) -> Self {
+        let row_length = width as usize;
+        let row_count = height as usize;
+        let weights = texels
+            .iter()
+            .enumerate()
+            .map(|(i, [r, g, b])| {
+                let theta = ((i / row_length) as f32 + 0.5) / height as f32 * PI;
+                (0.2126 * r + 0.7152 * g + 0.0722 * b) * theta.sin()
+            })
+            .collect::<Vec<_>>();
+
+        let mut cdf = vec![0.0; row_count + row_count * row_length];
+        let (row_cdf, column_cdfs) = cdf.split_at_mut(row_count);
+        let row_sums = weights
+            .chunks(row_length)
+            .zip(column_cdfs.chunks_mut(row_length))
+            .map(|(row, column_cdf)| cumulative_distribution(row, column_cdf))
+            .collect::<Vec<_>>();
+        let total = cumulative_distribution(&row_sums, row_cdf);
+
+        let mut pixels = Vec::with_capacity(texels.len() * 4);
+        for (texel, weight) in texels.iter().zip(&weights) {
+            pixels.extend_from_slice(texel);
+            pixels.push(if total > 0.0 {
+                weight / total * (row_length * row_count) as f32
+            } else {
+                0.0
+            });
+        }
+
+        let mut image = safe_vk::Image::new_init_host(
+            Some("environment map"),
+            allocator.clone(),
+            vk::Format::R32G32B32A32_SFLOAT,
+            width,
+            height,
+            vk::ImageTiling::OPTIMAL,
+            vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::TRANSFER_DST,
+            safe_vk::MemoryUsage::GpuOnly,
+            queue,
+            command_pool.clone(),
+            bytemuck::cast_slice(&pixels),
+        );
+        image.set_layout(vk::ImageLayout::GENERAL, queue, command_pool);
+
+        let cdf_buffer = Arc::new(safe_vk::Buffer::new_init_host(
+            Some("environment cdf buffer"),
+            allocator,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            safe_vk::MemoryUsage::CpuToGpu,
+            bytemuck::cast_slice(&cdf),
+        ));
+
+        Self {
+            image: Arc::new(image),
+            cdf_buffer,
+            rotation: 0.0,
+            intensity: 1.0,
+        }
+    }
+
+    pub fn image(&self) -> &Arc<safe_vk::Image> {
+        &self.image
+    }
+
+    pub fn cdf_buffer(&self) -> &Arc<safe_vk::Buffer> {
+        &self.cdf_buffer
+    }
+}
+
+/// Writes the normalized running sum of `weights` to `cdf` and returns the sum. All zero weights
+/// give a uniform distribution.
+fn cumulative_distribution(weights: &[f32], cdf: &mut [f32]) -> f32 {
+    let sum = weights.iter().sum::<f32>();
+    let mut running_sum = 0.0;
+    for (i, (weight, value)) in weights.iter().zip(cdf.iter_mut()).enumerate() {
+        running_sum += weight;
+        *value = if sum > 0.0 {
+            running_sum / sum
+        } else {
+            (i + 1) as f32 / weights.len() as f32
+        };
+    }
+    sum
+}
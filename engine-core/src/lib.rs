@@ -0,0 +1,511 @@
+use std::sync::Arc;
+
+use safe_vk::vk;
+use winit::event::{Event, WindowEvent};
+use winit::event_loop::{ControlFlow, EventLoop};
+use winit::window::Window;
+
+pub mod adaptive;
+pub mod benchmark;
+pub mod capture;
+pub mod environment;
+pub mod offline;
+pub mod shaders;
+
+/// The shaders every engine shares, an include directory of their shaders. The engines' build
+/// scripts pass it to `shader_compiler::build_with_include_dirs`, their shader watchers to
+/// `safe_vk::ShaderWatcher::add_include_dir`.
+pub const SHADER_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shaders");
+
+/// An engine `run` drives, one frame per redraw of its window, or `run_headless` one frame after
+/// another.
+pub trait Renderer {
+    /// Called with every event of the event loop, before `run` handles it.
+    fn handle_event(&mut self, event: &Event<()>);
+
+    /// Advances the engine to the frame about to be rendered.
+    fn update(&mut self);
+
+    fn render(&mut self);
+
+    /// The code to exit with once the engine is done, checked after every frame. `None` keeps
+    /// running until the window is closed, headless until it isn't.
+    fn exit_code(&self) -> Option<i32> {
+        None
+    }
+
+    /// Called once as the event loop exits, e.g. to save settings.
+    fn exit(&mut self) {}
+}
+
+/// What `Gpu::new` and `Gpu::headless` create the device and frame target with.
+#[derive(Debug, Clone, Copy)]
+pub struct GpuDescriptor {
+    /// Index of the Vulkan device to use, in enumeration order. The first discrete GPU if
+    /// `None`.
+    pub device_index: Option<usize>,
+    /// Enables the Vulkan validation layer, whose messages are logged.
+    pub validation: bool,
+    /// Ignored headless.
+    pub present_mode: vk::PresentModeKHR,
+}
+
+/// The extensions and core features an engine enables on the physical device chosen, picked
+/// from those it supports.
+#[derive(Debug, Clone, Default)]
+pub struct DeviceRequest {
+    pub extensions: Vec<safe_vk::name::device::Extension>,
+    pub features: vk::PhysicalDeviceFeatures,
+}
+
+/// The device rendering to a window, or offscreen, and what every frame submits and presents
+/// with.
+pub struct Gpu {
+    pub device: Arc<safe_vk::Device>,
+    pub allocator: Arc<safe_vk::Allocator>,
+    pub queue: safe_vk::Queue,
+    pub command_pool: Arc<safe_vk::CommandPool>,
+    pub target: FrameTarget,
+    /// Created signaled, so the first frame doesn't wait for one before it.
+    pub render_finish_fence: Arc<safe_vk::Fence>,
+}
+
+impl Gpu {
+    /// `request` picks the device extensions to enable besides `KhrSwapchain`, and the features,
+    /// from those the physical device chosen supports. Fails with `safe_vk::Error::Unsupported`
+    /// if it asks for one the device lacks.
+    pub fn new<F>(
+        window: &Window,
+        descriptor: &GpuDescriptor,
+        request: F,
+    ) -> Result<Self, safe_vk::Error>
+    where
+        F: FnOnce(&safe_vk::PhysicalDevice) -> DeviceRequest,
+    {
+        let entry = Arc::new(safe_vk::Entry::new().unwrap());
+        #[cfg(target_os = "linux")]
+        let instance_extensions = vec![
+            safe_vk::name::instance::Extension::KhrSurface,
+            safe_vk::name::instance::Extension::ExtDebugUtils,
+            safe_vk::name::instance::Extension::KhrXcbSurface,
+            safe_vk::name::instance::Extension::KhrXlibSurface,
+        ];
+        #[cfg(target_os = "windows")]
+        let instance_extensions = vec![
+            safe_vk::name::instance::Extension::KhrSurface,
+            safe_vk::name::instance::Extension::ExtDebugUtils,
+            safe_vk::name::instance::Extension::KhrWin32Surface,
+        ];
+        let instance = Arc::new(create_instance(entry, descriptor, &instance_extensions));
+        let surface = Arc::new(safe_vk::Surface::new(instance.clone(), window));
+
+        let pdevice = Arc::new(safe_vk::PhysicalDevice::with_index(
+            instance,
+            Some(surface.as_ref()),
+            descriptor.device_index,
+        ));
+        let request = request(&pdevice);
+        let mut device_extensions = vec![safe_vk::name::device::Extension::KhrSwapchain];
+        device_extensions.extend(request.extensions);
+        let device = Arc::new(safe_vk::Device::try_new(
+            pdevice,
+            &request.features,
+            &device_extensions,
+        )?);
+        let swapchain = Arc::new(safe_vk::Swapchain::new(
+            device.clone(),
+            surface,
+            descriptor.present_mode,
+        ));
+        let queue = safe_vk::Queue::new(device.clone());
+        let allocator = Arc::new(safe_vk::Allocator::new(device.clone()));
+        let command_pool = Arc::new(safe_vk::CommandPool::new(device.clone()));
+        let target = FrameTarget::Swapchain {
+            images: swapchain_images(&swapchain),
+            swapchain,
+            render_finish_semaphore: safe_vk::BinarySemaphore::new(device.clone()),
+        };
+        let render_finish_fence = Arc::new(safe_vk::Fence::new(device.clone(), true));
+
+        Ok(Self {
+            device,
+            allocator,
+            queue,
+            command_pool,
+            target,
+            render_finish_fence,
+        })
+    }
+
+    /// Renders to a `safe_vk::OffscreenTarget` of `size` on a `HeadlessGpu`, without a window,
+    /// surface or swapchain.
+    pub fn headless<F>(
+        descriptor: &GpuDescriptor,
+        size: winit::dpi::PhysicalSize<u32>,
+        request: F,
+    ) -> Result<Self, safe_vk::Error>
+    where
+        F: FnOnce(&safe_vk::PhysicalDevice) -> DeviceRequest,
+    {
+        let HeadlessGpu {
+            device,
+            allocator,
+            queue,
+            command_pool,
+        } = HeadlessGpu::new(descriptor, request)?;
+        // UNORM rather than SRGB, which devices rarely support as storage images.
+        let target = FrameTarget::Offscreen(safe_vk::OffscreenTarget::try_new(
+            allocator.clone(),
+            vk::Format::R8G8B8A8_UNORM,
+            size.width,
+            size.height,
+            2,
+        )?);
+        let render_finish_fence = Arc::new(safe_vk::Fence::new(device.clone(), true));
+
+        Ok(Self {
+            device,
+            allocator,
+            queue,
+            command_pool,
+            target,
+            render_finish_fence,
+        })
+    }
+}
+
+/// The images frames are rendered to, those of the swapchain of a window or offscreen ones.
+pub enum FrameTarget {
+    Swapchain {
+        swapchain: Arc<safe_vk::Swapchain>,
+        images: Vec<Arc<safe_vk::Image>>,
+        /// Signaled by the frame, waited for by its presentation.
+        render_finish_semaphore: safe_vk::BinarySemaphore,
+    },
+    Offscreen(safe_vk::OffscreenTarget),
+}
+
+impl FrameTarget {
+    pub fn width(&self) -> u32 {
+        match self {
+            Self::Swapchain { swapchain, .. } => swapchain.width(),
+            Self::Offscreen(target) => target.width(),
+        }
+    }
+
+    pub fn height(&self) -> u32 {
+        match self {
+            Self::Swapchain { swapchain, .. } => swapchain.height(),
+            Self::Offscreen(target) => target.height(),
+        }
+    }
+
+    pub fn format(&self) -> vk::Format {
+        match self {
+            Self::Swapchain { swapchain, .. } => swapchain.format(),
+            Self::Offscreen(target) => target.format(),
+        }
+    }
+
+    pub fn image_count(&self) -> usize {
+        match self {
+            Self::Swapchain { images, .. } => images.len(),
+            Self::Offscreen(target) => target.images().len(),
+        }
+    }
+
+    /// The layout frames leave the images in, to be presented or read back.
+    pub fn final_layout(&self) -> vk::ImageLayout {
+        match self {
+            Self::Swapchain { .. } => vk::ImageLayout::PRESENT_SRC_KHR,
+            Self::Offscreen(_) => vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        }
+    }
+
+    /// Whether the window is minimized, when no frames should be rendered.
+    pub fn is_zero_sized(&self) -> bool {
+        match self {
+            Self::Swapchain { swapchain, .. } => swapchain.is_zero_sized(),
+            Self::Offscreen(_) => false,
+        }
+    }
+
+    /// The index and image to render the next frame to, `None` if the swapchain is out of date
+    /// and has to be resized first. Suboptimal swapchains are rendered to anyway, the resize
+    /// event of the window recreates them.
+    pub fn acquire(&mut self) -> Option<(u32, Arc<safe_vk::Image>)> {
+        match self {
+            Self::Swapchain {
+                swapchain, images, ..
+            } => {
+                let index = swapchain.acquire_next_image().unwrap().index()?;
+                Some((index, images[index as usize].clone()))
+            }
+            Self::Offscreen(target) => {
+                let index = target.acquire_next_image();
+                Some((index, target.images()[index as usize].clone()))
+            }
+        }
+    }
+
+    /// Submits the frame rendering to the image acquired, after the swapchain has released it.
+    pub fn submit(
+        &self,
+        queue: &mut safe_vk::Queue,
+        command_buffer: safe_vk::CommandBuffer,
+    ) -> Arc<safe_vk::Fence> {
+        match self {
+            Self::Swapchain {
+                swapchain,
+                render_finish_semaphore,
+                ..
+            } => queue.submit_binary(
+                command_buffer,
+                &[swapchain.image_available_semaphore()],
+                &[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT],
+                &[render_finish_semaphore],
+            ),
+            Self::Offscreen(_) => queue.submit_binary(command_buffer, &[], &[], &[]),
+        }
+    }
+
+    /// Presents image `index` once its frame finishes, nothing to do offscreen.
+    pub fn present(&self, queue: &safe_vk::Queue, index: u32) {
+        if let Self::Swapchain {
+            swapchain,
+            render_finish_semaphore,
+            ..
+        } = self
+        {
+            queue.present(swapchain, index, &[render_finish_semaphore]);
+        }
+    }
+
+    /// Recreates the swapchain at the size of its surface, `size` where that's up to the
+    /// swapchain. `false` while the window is minimized, when everything sized after the target
+    /// should be kept as is until the window comes back. Offscreen targets keep their size.
+    pub fn resize(&mut self, size: winit::dpi::PhysicalSize<u32>) -> bool {
+        match self {
+            Self::Swapchain {
+                swapchain, images, ..
+            } => {
+                swapchain.renew_to(vk::Extent2D {
+                    width: size.width,
+                    height: size.height,
+                });
+                if swapchain.is_zero_sized() {
+                    return false;
+                }
+                *images = swapchain_images(swapchain);
+                true
+            }
+            Self::Offscreen(_) => true,
+        }
+    }
+}
+
+/// The device of renders without a window, to a `safe_vk::OffscreenTarget` in place of a
+/// swapchain. Neither surface nor swapchain extensions are enabled, so it runs on machines without
+/// a display.
+pub struct HeadlessGpu {
+    pub device: Arc<safe_vk::Device>,
+    pub allocator: Arc<safe_vk::Allocator>,
+    pub queue: safe_vk::Queue,
+    pub command_pool: Arc<safe_vk::CommandPool>,
+}
+
+impl HeadlessGpu {
+    /// Like `Gpu::new`, `descriptor.present_mode` aside. `request` picks the device extensions
+    /// and features to enable.
+    pub fn new<F>(descriptor: &GpuDescriptor, request: F) -> Result<Self, safe_vk::Error>
+    where
+        F: FnOnce(&safe_vk::PhysicalDevice) -> DeviceRequest,
+    {
+        let entry = Arc::new(safe_vk::Entry::new().unwrap());
+        let instance = Arc::new(create_instance(
+            entry,
+            descriptor,
+            &[safe_vk::name::instance::Extension::ExtDebugUtils],
+        ));
+        let pdevice = Arc::new(safe_vk::PhysicalDevice::with_index(
+            instance,
+            None,
+            descriptor.device_index,
+        ));
+        let request = request(&pdevice);
+        let device = Arc::new(safe_vk::Device::try_new(
+            pdevice,
+            &request.features,
+            &request.extensions,
+        )?);
+        let queue = safe_vk::Queue::new(device.clone());
+        let allocator = Arc::new(safe_vk::Allocator::new(device.clone()));
+        let command_pool = Arc::new(safe_vk::CommandPool::new(device.clone()));
+
+        Ok(Self {
+            device,
+            allocator,
+            queue,
+            command_pool,
+        })
+    }
+}
+
+/// With validation, its messages are logged.
+fn create_instance(
+    entry: Arc<safe_vk::Entry>,
+    descriptor: &GpuDescriptor,
+    extensions: &[safe_vk::name::instance::Extension],
+) -> safe_vk::Instance {
+    let layers = layers(descriptor);
+    if descriptor.validation {
+        safe_vk::Instance::with_debug_messenger(
+            entry,
+            &layers,
+            extensions,
+            safe_vk::DebugMessengerDesc::log(),
+        )
+    } else {
+        safe_vk::Instance::new(entry, &layers, extensions)
+    }
+}
+
+fn layers(descriptor: &GpuDescriptor) -> Vec<safe_vk::name::instance::Layer> {
+    if descriptor.validation {
+        vec![
+            safe_vk::name::instance::Layer::KhronosValidation,
+            safe_vk::name::instance::Layer::LunargMonitor,
+        ]
+    } else {
+        vec![safe_vk::name::instance::Layer::LunargMonitor]
+    }
+}
+
+/// Watches the shaders of an engine in `dir` for hot reloading, resolving includes in
+/// `SHADER_DIR` like its build script. `None`, logged, if `dir` can't be watched.
+pub fn watch_shaders(dir: &str) -> Option<safe_vk::ShaderWatcher> {
+    safe_vk::ShaderWatcher::new(dir)
+        .and_then(|mut watcher| {
+            watcher.add_include_dir(SHADER_DIR)?;
+            Ok(watcher)
+        })
+        .map_err(|e| log::warn!("failed to watch {}: {}", dir, e))
+        .ok()
+}
+
+/// FIFO with vsync, which caps the frame rate at the display's, immediate otherwise.
+pub fn present_mode(vsync: bool) -> vk::PresentModeKHR {
+    if vsync {
+        vk::PresentModeKHR::FIFO
+    } else {
+        vk::PresentModeKHR::IMMEDIATE
+    }
+}
+
+fn swapchain_images(swapchain: &Arc<safe_vk::Swapchain>) -> Vec<Arc<safe_vk::Image>> {
+    safe_vk::Image::from_swapchain(swapchain.clone())
+        .into_iter()
+        .map(Arc::new)
+        .collect()
+}
+
+pub fn screen_descriptor(
+    size: winit::dpi::PhysicalSize<u32>,
+    scale_factor: f64,
+) -> egui_backend::ScreenDescriptor {
+    egui_backend::ScreenDescriptor {
+        physical_width: size.width,
+        physical_height: size.height,
+        scale_factor: scale_factor as f32,
+    }
+}
+
+/// The UI platform of a window of `size`, or of the offscreen target headless.
+pub fn ui_platform(
+    size: winit::dpi::PhysicalSize<u32>,
+    scale_factor: f64,
+) -> egui_backend::Platform {
+    egui_backend::Platform::new(egui_backend::PlatformDescriptor {
+        physical_width: size.width,
+        physical_height: size.height,
+        scale_factor,
+        font_definitions: Default::default(),
+        style: Default::default(),
+    })
+}
+
+/// The pass drawing the UI over the images of `target`, last before they're presented or read
+/// back.
+pub fn ui_pass(
+    allocator: Arc<safe_vk::Allocator>,
+    target: &FrameTarget,
+    size: winit::dpi::PhysicalSize<u32>,
+    scale_factor: f64,
+) -> egui_backend::UiPass {
+    egui_backend::UiPass::new(
+        allocator,
+        target.format(),
+        target.final_layout(),
+        screen_descriptor(size, scale_factor),
+        target.image_count(),
+    )
+}
+
+pub fn create_window(event_loop: &EventLoop<()>, title: &str, size: [u32; 2]) -> Window {
+    winit::window::WindowBuilder::new()
+        .with_inner_size(winit::dpi::PhysicalSize::new(size[0], size[1]))
+        .with_title(title)
+        .build(event_loop)
+        .unwrap()
+}
+
+/// Runs the event loop of `window` until it's closed or `renderer` has an exit code, then exits
+/// the process with that code, 0 if closed.
+///
+/// Call from within the tokio runtime the renderer spawns its tasks on.
+pub fn run<R: Renderer + 'static>(event_loop: EventLoop<()>, window: Window, mut renderer: R) -> ! {
+    let mut exit_code = 0;
+    event_loop.run(move |event, _, control_flow| {
+        renderer.handle_event(&event);
+        match event {
+            Event::WindowEvent {
+                event: WindowEvent::CloseRequested,
+                ..
+            } => {
+                *control_flow = ControlFlow::Exit;
+            }
+            Event::MainEventsCleared => {
+                window.request_redraw();
+            }
+            Event::RedrawRequested(_) => {
+                renderer.update();
+                renderer.render();
+                if let Some(code) = renderer.exit_code() {
+                    exit_code = code;
+                    *control_flow = ControlFlow::Exit;
+                }
+            }
+            Event::LoopDestroyed => {
+                renderer.exit();
+                std::process::exit(exit_code);
+            }
+            _ => {}
+        }
+    })
+}
+
+/// Renders frames of `renderer` one after another until it has an exit code, without a window or
+/// an event loop, then exits the process with that code. The renderer gets no events.
+///
+/// Call from within the tokio runtime the renderer spawns its tasks on.
+pub fn run_headless<R: Renderer>(mut renderer: R) -> ! {
+    let exit_code = loop {
+        renderer.update();
+        renderer.render();
+        if let Some(code) = renderer.exit_code() {
+            break code;
+        }
+    };
+    renderer.exit();
+    std::process::exit(exit_code)
+}
@@ -0,0 +1,212 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::capture::{read_image, save_png, timestamp};
+
+/// What an offline render does once it reaches its target sample count.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionAction {
+    /// Writes the render and keeps the viewer running.
+    Save,
+    /// Writes the render and exits, with code 0 if it was written and 1 otherwise.
+    SaveAndExit,
+}
+
+impl CompletionAction {
+    pub const ALL: [CompletionAction; 2] = [CompletionAction::Save, CompletionAction::SaveAndExit];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CompletionAction::Save => "Save",
+            CompletionAction::SaveAndExit => "Save and Exit",
+        }
+    }
+}
+
+/// Accumulates until a target sample count and then writes the accumulation buffer to disk.
+///
+/// While active, every frame traces `batch_sample_count` samples instead of the batch scaled to
+/// the frame rate, so that a render takes the same frames on any machine.
+pub struct OfflineRender {
+    /// Samples per pixel to accumulate before writing.
+    pub target_sample_count: u32,
+    /// Samples per pixel traced every frame.
+    pub batch_sample_count: u32,
+    pub completion_action: CompletionAction,
+    /// Output path without extension. `{samples}`, `{width}`, `{height}` and `{timestamp}` are
+    /// replaced when the render is written.
+    pub file_template: String,
+    /// Also write the tone mapped image as PNG next to the EXR.
+    pub write_png: bool,
+    active: bool,
+    status: Option<String>,
+    exit_code: Option<i32>,
+}
+
+impl OfflineRender {
+    pub fn new() -> Self {
+        Self {
+            target_sample_count: 1024,
+            batch_sample_count: 16,
+            completion_action: CompletionAction::Save,
+            file_template: "render-{samples}spp-{timestamp}".to_owned(),
+            write_png: true,
+            active: false,
+            status: None,
+            exit_code: None,
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, sample_count: u32) {
+        ui.add_enabled_ui(!self.active, |ui| {
+            ui.add(
+                egui::DragValue::new(&mut self.target_sample_count)
+                    .clamp_range(1..=1 << 20)
+                    .prefix("Target Samples: "),
+            );
+            ui.add(
+                egui::DragValue::new(&mut self.batch_sample_count)
+                    .clamp_range(1..=1024)
+                    .prefix("Samples per Frame: "),
+            );
+            ui.horizontal(|ui| {
+                ui.label("File");
+                ui.text_edit_singleline(&mut self.file_template);
+            });
+            ui.checkbox(&mut self.write_png, "Also write tone mapped PNG");
+            ui.horizontal(|ui| {
+                ui.label("When Done");
+                for action in CompletionAction::ALL.iter() {
+                    ui.radio_value(&mut self.completion_action, *action, action.name());
+                }
+            });
+        });
+        if self.active {
+            ui.add(
+                egui::ProgressBar::new(sample_count as f32 / self.target_sample_count as f32)
+                    .text(format!("{} / {}", sample_count, self.target_sample_count)),
+            );
+            if ui.button("Cancel").clicked() {
+                self.active = false;
+            }
+        } else if ui.button("Start Offline Render").clicked() {
+            self.start();
+        }
+        if let Some(status) = &self.status {
+            ui.label(status);
+        }
+    }
+
+    pub fn start(&mut self) {
+        self.active = true;
+        self.status = None;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Samples the next frame traces on top of `sample_count`, stopping at the target.
+    pub fn next_batch_sample_count(&self, sample_count: u32) -> u32 {
+        let remaining = self.target_sample_count.saturating_sub(sample_count);
+        self.batch_sample_count.min(remaining).max(1)
+    }
+
+    /// Set once a render whose completion action exits has been written or has failed.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    /// Whether the frame that brings the accumulation to `sample_count` should be written.
+    pub fn is_due(&self, sample_count: u32) -> bool {
+        self.active && sample_count >= self.target_sample_count
+    }
+
+    /// Writes the accumulation buffer as EXR, and the tone mapped image as PNG if enabled, then
+    /// leaves the offline mode and runs the completion action. Both images must be idle.
+    pub fn write(
+        &mut self,
+        queue: &mut safe_vk::Queue,
+        command_pool: Arc<safe_vk::CommandPool>,
+        allocator: Arc<safe_vk::Allocator>,
+        hdr_image: Arc<safe_vk::Image>,
+        tone_mapped_image: Arc<safe_vk::Image>,
+        sample_count: u32,
+    ) {
+        self.active = false;
+        let written = self.write_files(
+            queue,
+            command_pool,
+            allocator,
+            hdr_image,
+            tone_mapped_image,
+            sample_count,
+        );
+        if self.completion_action == CompletionAction::SaveAndExit {
+            self.exit_code = Some(if written { 0 } else { 1 });
+        }
+    }
+
+    /// Returns whether every file was written.
+    fn write_files(
+        &mut self,
+        queue: &mut safe_vk::Queue,
+        command_pool: Arc<safe_vk::CommandPool>,
+        allocator: Arc<safe_vk::Allocator>,
+        hdr_image: Arc<safe_vk::Image>,
+        tone_mapped_image: Arc<safe_vk::Image>,
+        sample_count: u32,
+    ) -> bool {
+        let width = hdr_image.width() as usize;
+        let height = hdr_image.height() as usize;
+        let path = self.file_path(width, height, sample_count);
+        if let Some(parent) = path.parent() {
+            if let Err(e) = std::fs::create_dir_all(parent) {
+                log::warn!("failed to create {}: {}", parent.display(), e);
+            }
+        }
+
+        let hdr = read_image(queue, command_pool.clone(), allocator.clone(), hdr_image);
+        let exr_path = path.with_extension("exr");
+        let result = exr::prelude::write_rgb_file(&exr_path, width, height, |x, y| {
+            let pixel = &hdr[(y * width + x) * 4..];
+            (pixel[0], pixel[1], pixel[2])
+        });
+        if let Err(e) = result {
+            log::warn!("failed to write {}: {}", exr_path.display(), e);
+            self.status = Some(format!("Failed to write {}: {}", exr_path.display(), e));
+            return false;
+        }
+        let mut written = vec![exr_path];
+
+        if self.write_png {
+            let ldr = read_image(queue, command_pool, allocator, tone_mapped_image);
+            let png_path = path.with_extension("png");
+            if let Err(e) = save_png(&png_path, width as u32, height as u32, &ldr) {
+                log::warn!("failed to write {}: {}", png_path.display(), e);
+                self.status = Some(format!("Failed to write {}: {}", png_path.display(), e));
+                return false;
+            }
+            written.push(png_path);
+        }
+
+        let written = written
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        log::info!("offline render written to {}", written);
+        self.status = Some(format!("Wrote {}", written));
+        true
+    }
+
+    fn file_path(&self, width: usize, height: usize, sample_count: u32) -> PathBuf {
+        PathBuf::from(
+            self.file_template
+                .replace("{samples}", &sample_count.to_string())
+                .replace("{width}", &width.to_string())
+                .replace("{height}", &height.to_string())
+                .replace("{timestamp}", &timestamp().to_string()),
+        )
+    }
+}
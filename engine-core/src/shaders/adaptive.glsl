@@ -0,0 +1,24 @@
+// Per-pixel statistics for adaptive sampling, see adaptive.rs of engine-core.
+
+struct PixelMoments {
+    float mean_luminance;
+    float mean_squared_luminance;
+    uint sample_count; // Samples accumulated in the result image so far.
+    uint active; // Whether the pixel was sampled by the last frame.
+};
+
+layout(binding = 14, set = 0, scalar) buffer Moments
+{
+    PixelMoments pixel_moments[];
+};
+
+// Whether the standard error of the pixel's mean is within threshold of the mean itself.
+bool pixel_converged(PixelMoments moments, float threshold, uint min_samples)
+{
+    if (moments.sample_count < max(min_samples, 2)) {
+        return false;
+    }
+    const float variance = max(moments.mean_squared_luminance - moments.mean_luminance * moments.mean_luminance, 0.0);
+    const float standard_error = sqrt(variance / moments.sample_count);
+    return standard_error <= threshold * max(moments.mean_luminance, 0.01);
+}
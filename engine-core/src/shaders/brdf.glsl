@@ -0,0 +1,112 @@
+// glTF metallic-roughness BRDF: Lambert diffuse plus GGX specular with the height-correlated
+// Smith visibility term, shared by the engines. Include after the common.glsl of the engine, for
+// k_pi and tangent_frame.
+
+float luminance(vec3 color)
+{
+    return dot(color, vec3(0.2126, 0.7152, 0.0722));
+}
+
+// Perfectly smooth surfaces would need a delta lobe, so roughness is clamped slightly above 0.
+float ggx_alpha(float roughness)
+{
+    const float clamped = max(roughness, 0.03);
+    return clamped * clamped;
+}
+
+float ggx_distribution(float n_dot_h, float alpha)
+{
+    const float alpha2 = alpha * alpha;
+    const float d = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    return alpha2 / (k_pi * d * d);
+}
+
+float smith_visibility(float n_dot_l, float n_dot_v, float alpha)
+{
+    const float alpha2 = alpha * alpha;
+    const float ggx_v = n_dot_l * sqrt(n_dot_v * n_dot_v * (1.0 - alpha2) + alpha2);
+    const float ggx_l = n_dot_v * sqrt(n_dot_l * n_dot_l * (1.0 - alpha2) + alpha2);
+    return 0.5 / (ggx_v + ggx_l);
+}
+
+vec3 fresnel_schlick(vec3 f0, float v_dot_h)
+{
+    return f0 + (vec3(1.0) - f0) * pow(1.0 - v_dot_h, 5.0);
+}
+
+// Chance of sampling the specular lobe instead of the diffuse one.
+float specular_probability(vec3 normal, vec3 view, vec3 base_color, float metallic)
+{
+    const vec3 f0 = mix(vec3(0.04), base_color, metallic);
+    const float specular = luminance(fresnel_schlick(f0, max(dot(normal, view), 0.0)));
+    const float diffuse = luminance(base_color) * (1.0 - metallic);
+    return clamp(specular / max(specular + diffuse, 1e-6), 0.1, 0.9);
+}
+
+// The BRDF times the cosine term for light arriving from light_dir and leaving towards view.
+vec3 brdf_eval(vec3 normal, vec3 view, vec3 light_dir, vec3 base_color, float metallic, float roughness)
+{
+    const float n_dot_l = dot(normal, light_dir);
+    const float n_dot_v = dot(normal, view);
+    if (n_dot_l <= 0.0 || n_dot_v <= 0.0) {
+        return vec3(0.0);
+    }
+    const vec3 half_vector = normalize(view + light_dir);
+    const float n_dot_h = max(dot(normal, half_vector), 0.0);
+    const float v_dot_h = max(dot(view, half_vector), 0.0);
+    const float alpha = ggx_alpha(roughness);
+
+    const vec3 fresnel = fresnel_schlick(mix(vec3(0.04), base_color, metallic), v_dot_h);
+    const vec3 specular = fresnel * ggx_distribution(n_dot_h, alpha) * smith_visibility(n_dot_l, n_dot_v, alpha);
+    const vec3 diffuse = (vec3(1.0) - fresnel) * base_color * (1.0 - metallic) / k_pi;
+    return (diffuse + specular) * n_dot_l;
+}
+
+// Solid angle density of brdf_sample returning light_dir.
+float brdf_pdf(vec3 normal, vec3 view, vec3 light_dir, vec3 base_color, float metallic, float roughness)
+{
+    const float n_dot_l = dot(normal, light_dir);
+    if (n_dot_l <= 0.0) {
+        return 0.0;
+    }
+    const vec3 half_vector = normalize(view + light_dir);
+    const float n_dot_h = max(dot(normal, half_vector), 0.0);
+    const float v_dot_h = max(dot(view, half_vector), 1e-6);
+    const float specular_pdf = ggx_distribution(n_dot_h, ggx_alpha(roughness)) * n_dot_h / (4.0 * v_dot_h);
+    return mix(n_dot_l / k_pi, specular_pdf, specular_probability(normal, view, base_color, metallic));
+}
+
+// Returns a random diffuse (Lambertian) reflection for a surface with the
+// given normal, using the given random number generator state. This is
+// cosine-weighted, so directions closer to the normal are more likely to
+// be chosen.
+vec3 diffuseReflection(vec3 normal, inout uint rngState)
+{
+    // For a random diffuse bounce direction, we follow the approach of
+    // Ray Tracing in One Weekend, and generate a random point on a sphere
+    // of radius 1 centered at the normal. This uses the random_unit_vector
+    // function from chapter 8.5:
+    const float theta = 2.0 * k_pi * stepAndOutputRNGFloat(rngState); // Random in [0, 2pi]
+    const float u = 2.0 * stepAndOutputRNGFloat(rngState) - 1.0; // Random in [-1, 1]
+    const float r = sqrt(1.0 - u * u);
+    const vec3 direction = normal + vec3(r * cos(theta), r * sin(theta), u);
+
+    // Then normalize the ray direction:
+    return normalize(direction);
+}
+
+// Picks a lobe, then samples a cosine-weighted diffuse direction or a GGX microfacet normal to
+// reflect about.
+vec3 brdf_sample(vec3 normal, vec3 view, vec3 base_color, float metallic, float roughness, inout uint rngState)
+{
+    if (stepAndOutputRNGFloat(rngState) >= specular_probability(normal, view, base_color, metallic)) {
+        return diffuseReflection(normal, rngState);
+    }
+    const float alpha = ggx_alpha(roughness);
+    const float u = stepAndOutputRNGFloat(rngState);
+    const float cos_theta = sqrt((1.0 - u) / (1.0 + (alpha * alpha - 1.0) * u));
+    const float sin_theta = sqrt(max(0.0, 1.0 - cos_theta * cos_theta));
+    const float phi = 2.0 * k_pi * stepAndOutputRNGFloat(rngState);
+    const vec3 half_vector = tangent_frame(normal) * vec3(sin_theta * cos(phi), sin_theta * sin(phi), cos_theta);
+    return reflect(-view, half_vector);
+}
@@ -0,0 +1,77 @@
+// Equirectangular environment map and its sampling tables, built by environment.rs of engine-core.
+// The alpha channel holds the density of sampling each texel per unit of uv area. Include after
+// the common.glsl of the engine, for k_pi.
+
+layout(binding = 9, set = 0, rgba32f) uniform readonly image2D environment_map;
+// The CDF over rows, followed by the CDF within each row.
+layout(binding = 10, set = 0, scalar) buffer EnvironmentCdf
+{
+    float environment_cdf[];
+};
+
+// u runs around the up axis starting at -Z, v from the zenith to the nadir.
+vec3 environment_direction(vec2 uv, float rotation)
+{
+    const float phi = 2.0 * k_pi * uv.x + rotation;
+    const float theta = k_pi * uv.y;
+    return vec3(sin(theta) * sin(phi), cos(theta), -sin(theta) * cos(phi));
+}
+
+vec2 environment_uv(vec3 direction, float rotation)
+{
+    const float phi = atan(direction.x, -direction.z) - rotation;
+    return vec2(fract(phi / (2.0 * k_pi)), acos(clamp(direction.y, -1.0, 1.0)) / k_pi);
+}
+
+vec4 environment_texel(vec2 uv)
+{
+    const ivec2 size = imageSize(environment_map);
+    return imageLoad(environment_map, clamp(ivec2(uv * size), ivec2(0), size - 1));
+}
+
+// Converts the uv density to a solid angle density.
+float environment_solid_angle_pdf(vec2 uv, float uv_pdf)
+{
+    const float sin_theta = sin(k_pi * uv.y);
+    return sin_theta > 0.0 ? uv_pdf / (2.0 * k_pi * k_pi * sin_theta) : 0.0;
+}
+
+vec3 environment_radiance(vec3 direction, float rotation)
+{
+    return environment_texel(environment_uv(direction, rotation)).rgb;
+}
+
+// Solid angle density of sample_environment returning direction.
+float environment_pdf(vec3 direction, float rotation)
+{
+    const vec2 uv = environment_uv(direction, rotation);
+    return environment_solid_angle_pdf(uv, environment_texel(uv).a);
+}
+
+// First index in environment_cdf[offset, offset + count) whose value exceeds u.
+uint sample_cdf(uint offset, uint count, float u)
+{
+    uint low = 0;
+    uint high = count - 1;
+    while (low < high) {
+        const uint middle = (low + high) / 2;
+        if (environment_cdf[offset + middle] <= u) {
+            low = middle + 1;
+        } else {
+            high = middle;
+        }
+    }
+    return low;
+}
+
+// Picks a texel proportionally to its weight and a uniform point inside it.
+vec3 sample_environment(float rotation, inout uint rngState, out float pdf)
+{
+    const uvec2 size = uvec2(imageSize(environment_map));
+    const uint row = sample_cdf(0, size.y, stepAndOutputRNGFloat(rngState));
+    const uint column = sample_cdf(size.y + row * size.x, size.x, stepAndOutputRNGFloat(rngState));
+    const vec2 jitter = vec2(stepAndOutputRNGFloat(rngState), stepAndOutputRNGFloat(rngState));
+    const vec2 uv = (vec2(column, row) + jitter) / vec2(size);
+    pdf = environment_solid_angle_pdf(uv, imageLoad(environment_map, ivec2(column, row)).a);
+    return environment_direction(uv, rotation);
+}
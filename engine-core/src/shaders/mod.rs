@@ -0,0 +1,6 @@
+use rust_embed::RustEmbed;
+
+/// The SPIR-V of the shaders every engine shares, like the `Shaders` of the engines.
+#[derive(RustEmbed)]
+#[folder = "./src/shaders/bin"]
+pub struct Shaders;
@@ -0,0 +1,16 @@
+use engine_core::{present_mode, screen_descriptor};
+use safe_vk::vk;
+
+#[test]
+fn test_present_mode() {
+    assert_eq!(present_mode(true), vk::PresentModeKHR::FIFO);
+    assert_eq!(present_mode(false), vk::PresentModeKHR::IMMEDIATE);
+}
+
+#[test]
+fn test_screen_descriptor() {
+    let descriptor = screen_descriptor(winit::dpi::PhysicalSize::new(1280, 720), 1.5);
+    assert_eq!(descriptor.physical_width, 1280);
+    assert_eq!(descriptor.physical_height, 720);
+    assert_eq!(descriptor.scale_factor, 1.5);
+}
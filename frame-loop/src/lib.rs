@@ -0,0 +1,142 @@
+use std::time::{Duration, Instant};
+
+/// Updates one frame may run at most, the time of any more is dropped. A long stall, like
+/// loading a scene, would otherwise be followed by a burst of updates that take long enough to
+/// need more updates.
+const DEFAULT_MAX_STEPS: u32 = 8;
+
+/// Sleeps end this long before the frame is due, then the limiter spins. Sleeps overshoot by up
+/// to a scheduler tick on some platforms.
+const SPIN_MARGIN: Duration = Duration::from_millis(1);
+
+/// Runs the simulation in steps of fixed length, however long the frames take, so the camera
+/// and anything else updated moves the same at any frame rate.
+///
+/// Each frame `tick` adds the time since the last frame and returns how many steps to run. The
+/// time left over is `alpha` of a step, renders interpolate between the state before the last
+/// step and after it by that much.
+#[derive(Debug, Clone)]
+pub struct FixedTimestep {
+    step: Duration,
+    max_steps: u32,
+    accumulator: Duration,
+    last_tick: Option<Instant>,
+}
+
+impl FixedTimestep {
+    /// `rate` steps per second.
+    pub fn new(rate: f64) -> Self {
+        assert!(rate > 0.0, "step rate must be positive");
+        Self {
+            step: Duration::from_secs_f64(1.0 / rate),
+            max_steps: DEFAULT_MAX_STEPS,
+            accumulator: Duration::ZERO,
+            last_tick: None,
+        }
+    }
+
+    pub fn with_max_steps(mut self, max_steps: u32) -> Self {
+        self.max_steps = max_steps.max(1);
+        self
+    }
+
+    pub fn step(&self) -> Duration {
+        self.step
+    }
+
+    /// The step length in seconds, the `dt` of each update.
+    pub fn step_secs(&self) -> f32 {
+        self.step.as_secs_f32()
+    }
+
+    /// Adds the time since the last call and returns the number of steps to run. The first call
+    /// runs none.
+    pub fn tick(&mut self) -> u32 {
+        let now = Instant::now();
+        let elapsed = self
+            .last_tick
+            .map_or(Duration::ZERO, |last_tick| now - last_tick);
+        self.last_tick = Some(now);
+        self.advance(elapsed)
+    }
+
+    /// Adds `elapsed` and returns the number of steps to run, `tick` with the time measured
+    /// elsewhere.
+    pub fn advance(&mut self, elapsed: Duration) -> u32 {
+        self.accumulator += elapsed;
+        let due = self.accumulator.as_nanos() / self.step.as_nanos();
+        let steps = due.min(self.max_steps as u128) as u32;
+        self.accumulator -= self.step * steps;
+        if due > steps as u128 {
+            let behind = self.accumulator.as_nanos() % self.step.as_nanos();
+            self.accumulator = Duration::from_nanos(behind as u64);
+        }
+        steps
+    }
+
+    /// How far the time left over is into the next step, from 0 to 1.
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator.as_secs_f64() / self.step.as_secs_f64()) as f32
+    }
+
+    /// Forgets the time since the last tick, so a pause isn't caught up.
+    pub fn reset(&mut self) {
+        self.accumulator = Duration::ZERO;
+        self.last_tick = None;
+    }
+}
+
+/// Caps the frame rate, whatever the present mode. With FIFO the display caps it too, the lower
+/// of the two wins.
+#[derive(Debug, Clone, Default)]
+pub struct FrameLimiter {
+    max_fps: Option<f64>,
+    /// When the current frame was allowed to start.
+    frame_start: Option<Instant>,
+}
+
+impl FrameLimiter {
+    /// `None` leaves the frame rate uncapped.
+    pub fn new(max_fps: Option<f64>) -> Self {
+        let mut limiter = Self::default();
+        limiter.set_max_fps(max_fps);
+        limiter
+    }
+
+    pub fn max_fps(&self) -> Option<f64> {
+        self.max_fps
+    }
+
+    pub fn set_max_fps(&mut self, max_fps: Option<f64>) {
+        self.max_fps = max_fps.filter(|max_fps| *max_fps > 0.0);
+        self.frame_start = None;
+    }
+
+    /// Schedules the next frame for a frame ending at `now` and returns when it may start,
+    /// `None` if the frame rate isn't capped. A late frame doesn't make the ones after it
+    /// hurry.
+    pub fn next_frame(&mut self, now: Instant) -> Option<Instant> {
+        let interval = Duration::from_secs_f64(1.0 / self.max_fps?);
+        let start = match self.frame_start {
+            Some(frame_start) if frame_start + interval > now => frame_start + interval,
+            _ => now,
+        };
+        self.frame_start = Some(start);
+        Some(start)
+    }
+
+    /// Blocks until the next frame may start. Call once per frame, after presenting.
+    pub fn wait(&mut self) {
+        let start = match self.next_frame(Instant::now()) {
+            Some(start) => start,
+            None => return,
+        };
+        let now = Instant::now();
+        if start > now + SPIN_MARGIN {
+            std::thread::sleep(start - now - SPIN_MARGIN);
+        }
+        while Instant::now() < start {
+            std::thread::yield_now();
+        }
+    }
+}
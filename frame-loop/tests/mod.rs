@@ -0,0 +1,51 @@
+use std::time::{Duration, Instant};
+
+use frame_loop::{FixedTimestep, FrameLimiter};
+
+#[test]
+fn test_steps_accumulate() {
+    let mut timestep = FixedTimestep::new(100.0);
+    assert_eq!(timestep.advance(Duration::from_millis(5)), 0);
+    assert!((timestep.alpha() - 0.5).abs() < 1e-4);
+    assert_eq!(timestep.advance(Duration::from_millis(25)), 3);
+    assert!(timestep.alpha().abs() < 1e-4);
+}
+
+#[test]
+fn test_stall_drops_steps() {
+    let mut timestep = FixedTimestep::new(100.0).with_max_steps(4);
+    assert_eq!(timestep.advance(Duration::from_millis(1005)), 4);
+    assert!((timestep.alpha() - 0.5).abs() < 1e-4);
+    assert_eq!(timestep.advance(Duration::from_millis(5)), 1);
+}
+
+#[test]
+fn test_reset_forgets_time() {
+    let mut timestep = FixedTimestep::new(60.0);
+    timestep.advance(Duration::from_millis(10));
+    timestep.reset();
+    assert_eq!(timestep.alpha(), 0.0);
+    assert_eq!(timestep.tick(), 0);
+}
+
+#[test]
+fn test_limiter_spaces_frames() {
+    let mut limiter = FrameLimiter::new(Some(100.0));
+    let start = Instant::now();
+    assert_eq!(limiter.next_frame(start), Some(start));
+    assert_eq!(
+        limiter.next_frame(start + Duration::from_millis(1)),
+        Some(start + Duration::from_millis(10))
+    );
+    // Late by more than a frame, the schedule starts over.
+    let late = start + Duration::from_millis(50);
+    assert_eq!(limiter.next_frame(late), Some(late));
+}
+
+#[test]
+fn test_uncapped_limiter_never_waits() {
+    let mut limiter = FrameLimiter::new(None);
+    assert_eq!(limiter.next_frame(Instant::now()), None);
+    limiter.set_max_fps(Some(0.0));
+    assert_eq!(limiter.max_fps(), None);
+}
@@ -1,162 +1,217 @@
 mod shaders;
 
-use std::path::{PathBuf};
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Instant;
 
+use safe_vk::vk;
+use settings::Settings;
 
-use safe_vk::{vk};
+use crate::Args;
 
 pub struct Engine {
-    ui_platform: egui_winit_platform::Platform,
+    ui_platform: egui_backend::Platform,
+    ui_textures_delta: egui::TexturesDelta,
     size: winit::dpi::PhysicalSize<u32>,
     scale_factor: f64,
-    swapchain: Arc<safe_vk::Swapchain>,
+    target: engine_core::FrameTarget,
     queue: safe_vk::Queue,
     ui_pass: egui_backend::UiPass,
     command_pool: Arc<safe_vk::CommandPool>,
     time: Instant,
-    swapchain_images: Vec<Arc<safe_vk::Image>>,
-    render_finish_semaphore: safe_vk::BinarySemaphore,
     render_finish_fence: Arc<safe_vk::Fence>,
     allocator: Arc<safe_vk::Allocator>,
+    /// Transient images of the render graphs of earlier frames.
+    transient_images: safe_vk::TransientImagePool,
     scene: Option<gltf_wrapper::Scene>,
+    /// The file `scene` was loaded from.
+    scene_path: Option<PathBuf>,
+    /// Written back on exit.
+    settings: Settings,
 }
 
 impl Engine {
-    pub fn new(window: &winit::window::Window) -> Self {
+    pub fn new(window: &winit::window::Window, args: &Args, settings: Settings) -> Self {
         let size = window.inner_size();
         let scale_factor = window.scale_factor();
-        let ui_platform =
-            egui_winit_platform::Platform::new(egui_winit_platform::PlatformDescriptor {
-                physical_width: size.width,
-                physical_height: size.height,
-                scale_factor,
-                font_definitions: Default::default(),
-                style: Default::default(),
-            });
-        let entry = Arc::new(safe_vk::Entry::new().unwrap());
-        let instance = Arc::new(safe_vk::Instance::new(
-            entry,
-            &[
-                safe_vk::name::instance::layer::khronos::VALIDATION,
-                safe_vk::name::instance::layer::lunarg::MONITOR,
-            ],
-            &[
-                safe_vk::name::instance::extension::khr::WIN32_SURFACE,
-                safe_vk::name::instance::extension::khr::SURFACE,
-                safe_vk::name::instance::extension::ext::DEBUG_UTILS,
-            ],
-        ));
-        let surface = Arc::new(safe_vk::Surface::new(instance.clone(), window));
-
-        let pdevice = Arc::new(safe_vk::PhysicalDevice::new(instance, Some(surface)));
-        let device = Arc::new(safe_vk::Device::new(
-            pdevice,
-            &vk::PhysicalDeviceFeatures::default(),
-            &[
-                safe_vk::name::device::extension::khr::SWAPCHAIN,
-                safe_vk::name::device::extension::khr::ACCELERATION_STRUCTURE,
-                safe_vk::name::device::extension::khr::DEFERRED_HOST_OPERATIONS,
-                safe_vk::name::device::extension::khr::BUFFER_DEVICE_ADDRESS,
-                safe_vk::name::device::extension::khr::RAY_TRACING_PIPELINE,
-            ],
-        ));
-        let swapchain = Arc::new(safe_vk::Swapchain::new(device.clone()));
-        let queue = safe_vk::Queue::new(device.clone());
-        let allocator = Arc::new(safe_vk::Allocator::new(device.clone()));
-        let ui_pass = egui_backend::UiPass::new(allocator.clone());
-        let command_pool = Arc::new(safe_vk::CommandPool::new(device.clone()));
+        let ui_platform = engine_core::ui_platform(size, scale_factor);
+        let engine_core::Gpu {
+            device,
+            allocator,
+            mut queue,
+            command_pool,
+            target,
+            render_finish_fence,
+        } = engine_core::Gpu::new(
+            window,
+            &engine_core::GpuDescriptor {
+                device_index: args.device,
+                validation: !args.no_validation,
+                present_mode: engine_core::present_mode(settings.vsync),
+            },
+            |_| engine_core::DeviceRequest {
+                extensions: vec![
+                    safe_vk::name::device::Extension::KhrAccelerationStructure,
+                    safe_vk::name::device::Extension::KhrDeferredHostOperations,
+                    safe_vk::name::device::Extension::KhrRayTracingPipeline,
+                ],
+                ..Default::default()
+            },
+        )
+        .unwrap_or_else(|e| panic!("failed to create the device: {}", e));
+        let mut ui_pass = engine_core::ui_pass(allocator.clone(), &target, size, scale_factor);
+        // Nothing is rendered beneath the UI yet.
+        ui_pass.set_load_op(
+            egui_backend::LoadOp::Clear(egui::Rgba::BLACK),
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        );
         let time = Instant::now();
-        let swapchain_images = safe_vk::Image::from_swapchain(swapchain.clone())
-            .into_iter()
-            .map(Arc::new)
-            .collect::<Vec<_>>();
-        let render_finish_semaphore = safe_vk::BinarySemaphore::new(device.clone());
-        let render_finish_fence = Arc::new(safe_vk::Fence::new(device.clone(), true));
-
-        let uniform_descriptor_set_layout = safe_vk::DescriptorSetLayout::new(
+
+        let image_descriptor_set_layout = safe_vk::DescriptorSetLayout::new(
             device.clone(),
-            Some("uniform descriptor set laytou"),
+            Some("image descriptor set layout"),
             &[
-                vk::DescriptorSetLayoutBinding::builder()
-                    .binding(0)
-                    .stage_flags(vk::ShaderStageFlags::RAYGEN_KHR)
-                    .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
-                    .descriptor_count(1)
-                    .build(),
-                vk::DescriptorSetLayoutBinding::builder()
-                    .binding(1)
-                    .stage_flags(vk::ShaderStageFlags::RAYGEN_KHR)
-                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
-                    .descriptor_count(1)
-                    .build(),
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 0,
+                    descriptor_type: safe_vk::DescriptorType::StorageImage,
+                    stage_flags: vk::ShaderStageFlags::RAYGEN_KHR,
+                },
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 1,
+                    descriptor_type: safe_vk::DescriptorType::UniformBuffer,
+                    stage_flags: vk::ShaderStageFlags::RAYGEN_KHR,
+                },
             ],
         );
         let as_descriptor_set_layout = safe_vk::DescriptorSetLayout::new(
             device.clone(),
-            Some("as descriptor set laytou"),
-            &[vk::DescriptorSetLayoutBinding::builder()
-                .binding(0)
-                .stage_flags(vk::ShaderStageFlags::RAYGEN_KHR)
-                .descriptor_type(vk::DescriptorType::ACCELERATION_STRUCTURE_KHR)
-                .descriptor_count(1)
-                .build()],
+            Some("as descriptor set layout"),
+            &[safe_vk::DescriptorSetLayoutBinding {
+                binding: 0,
+                descriptor_type: safe_vk::DescriptorType::AccelerationStructure,
+                stage_flags: vk::ShaderStageFlags::RAYGEN_KHR,
+            }],
         );
         let ray_tracing_pipeline_layout = Arc::new(safe_vk::PipelineLayout::new(
             device.clone(),
             Some("rt pipeline layout"),
-            &[&uniform_descriptor_set_layout, &as_descriptor_set_layout],
+            &[&image_descriptor_set_layout, &as_descriptor_set_layout],
+            &[],
         ));
         let stages = vec![
             Arc::new(safe_vk::ShaderStage::new(
-                safe_vk::ShaderModule::new(
+                Arc::new(safe_vk::ShaderModule::new(
                     device.clone(),
                     shaders::Shaders::get("ray_gen.rgen.spv").unwrap(),
-                ),
+                )),
                 vk::ShaderStageFlags::RAYGEN_KHR,
                 "main",
             )),
             Arc::new(safe_vk::ShaderStage::new(
-                safe_vk::ShaderModule::new(
+                Arc::new(safe_vk::ShaderModule::new(
                     device.clone(),
                     shaders::Shaders::get("closest_hit.rchit.spv").unwrap(),
-                ),
+                )),
                 vk::ShaderStageFlags::CLOSEST_HIT_KHR,
                 "main",
             )),
             Arc::new(safe_vk::ShaderStage::new(
-                safe_vk::ShaderModule::new(
+                Arc::new(safe_vk::ShaderModule::new(
                     device.clone(),
                     shaders::Shaders::get("miss.rmiss.spv").unwrap(),
-                ),
+                )),
                 vk::ShaderStageFlags::MISS_KHR,
                 "main",
             )),
         ];
-        let ray_tracing_pipeline =
-            safe_vk::RayTracingPipeline::new(ray_tracing_pipeline_layout.clone(), stages, 4);
+        // Nothing is traced with it yet, the viewer only draws the UI so far.
+        let _ray_tracing_pipeline = safe_vk::RayTracingPipeline::new(
+            Some("rt pipeline"),
+            allocator.clone(),
+            ray_tracing_pipeline_layout,
+            stages,
+            4,
+            &mut queue,
+        );
+        let transient_images = safe_vk::TransientImagePool::new(allocator.clone());
+
+        // Reopens the last scene unless it has been moved or deleted since.
+        let scene_path = args
+            .scene
+            .clone()
+            .or_else(|| settings.last_scene.clone().filter(|path| path.exists()));
+        let scene = scene_path
+            .as_ref()
+            .map(|path| gltf_wrapper::Scene::from_file(allocator.clone(), path));
 
         Self {
             ui_platform,
+            ui_textures_delta: Default::default(),
             size,
             scale_factor,
-            swapchain,
+            target,
             queue,
             ui_pass,
             command_pool,
             time,
-            swapchain_images,
-            render_finish_semaphore,
             render_finish_fence,
             allocator,
-            scene: None,
+            transient_images,
+            scene,
+            scene_path,
+            settings,
         }
     }
 
+    /// Writes the window size and the scene to the settings file.
+    pub fn save_settings(&mut self) {
+        // A minimized window reopens at its size before.
+        if self.size.width > 0 && self.size.height > 0 {
+            self.settings.window_size = [self.size.width, self.size.height];
+        }
+        self.settings.last_scene = self.scene_path.clone();
+        if let Err(e) = self.settings.save(crate::SETTINGS_APP) {
+            log::warn!("failed to save settings: {}", e);
+        }
+    }
+
+    fn resize(&mut self, new_size: &winit::dpi::PhysicalSize<u32>) {
+        self.size = *new_size;
+        self.ui_pass
+            .set_screen_descriptor(engine_core::screen_descriptor(self.size, self.scale_factor));
+        // Minimized, keep everything as is until the window comes back.
+        if !self.target.resize(self.size) {
+            return;
+        }
+        self.transient_images.clear();
+    }
+
     pub fn handle_event(&mut self, event: &winit::event::Event<()>) {
         self.ui_platform.handle_event(event);
+        if let winit::event::Event::WindowEvent { event, .. } = event {
+            match event {
+                winit::event::WindowEvent::Resized(size) => {
+                    self.resize(size);
+                }
+                winit::event::WindowEvent::ScaleFactorChanged {
+                    scale_factor,
+                    new_inner_size,
+                } => {
+                    self.scale_factor = *scale_factor;
+                    self.resize(new_inner_size);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn load_scene(&mut self, path: PathBuf) {
+        self.scene = Some(gltf_wrapper::Scene::from_file(
+            self.allocator.clone(),
+            &path,
+        ));
+        self.scene_path = Some(path);
     }
 
     pub fn update(&mut self) {
@@ -167,17 +222,14 @@ impl Engine {
             .update_time(self.time.elapsed().as_secs_f64());
         self.ui_platform.begin_frame();
 
-        egui::TopPanel::top(egui::Id::new("menu bar")).show(&self.ui_platform.context(), |ui| {
+        egui::TopBottomPanel::top("menu bar").show(&self.ui_platform.context(), |ui| {
             egui::menu::bar(ui, |ui| {
-                egui::menu::menu(ui, "File", |ui| {
-                    if ui.button("Open").clicked {
+                ui.menu_button("File", |ui| {
+                    if ui.button("Open").clicked() {
                         match nfd2::open_file_dialog(Some("gltf,glb"), Some(current_dir.as_ref()))
                             .unwrap()
                         {
-                            nfd2::Response::Okay(p) => {
-                                self.scene =
-                                    Some(gltf_wrapper::Scene::from_file(self.allocator.clone(), p));
-                            }
+                            nfd2::Response::Okay(p) => self.load_scene(p),
                             nfd2::Response::OkayMultiple(_) => {}
                             nfd2::Response::Cancel => {}
                         }
@@ -186,48 +238,36 @@ impl Engine {
             });
         });
 
-        let (_, shapes) = self.ui_platform.end_frame();
-        let paint_jobs = self.ui_platform.context().tessellate(shapes);
-        self.ui_pass.update_buffers(
-            &paint_jobs,
-            &egui_backend::ScreenDescriptor {
-                physical_width: self.size.width,
-                physical_height: self.size.height,
-                scale_factor: self.scale_factor as f32,
-            },
-        );
-        self.ui_pass
-            .update_texture(&self.ui_platform.context().texture());
+        let full_output = self.ui_platform.end_frame();
+        let paint_jobs = self.ui_platform.context().tessellate(full_output.shapes);
+        self.ui_pass.update_buffers(&paint_jobs);
+        // Frames skipped while minimized leave their texture changes for the next render.
+        self.ui_textures_delta.append(full_output.textures_delta);
     }
 
     pub fn render(&mut self) {
-        let (index, _) = self.swapchain.acquire_next_image();
+        if self.target.is_zero_sized() {
+            return;
+        }
+        let (index, target_image) = match self.target.acquire() {
+            Some(acquired) => acquired,
+            None => {
+                self.resize(&self.size.clone());
+                return;
+            }
+        };
         let mut command_buffer = safe_vk::CommandBuffer::new(self.command_pool.clone());
 
-        let target_image = self.swapchain_images[index as usize].clone();
-        command_buffer.encode(|recorder| {
-            recorder.set_image_layout(
-                target_image.clone(),
-                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-            );
-            self.ui_pass.execute(
-                recorder,
-                target_image,
-                &egui_backend::ScreenDescriptor {
-                    physical_width: self.size.width,
-                    physical_height: self.size.height,
-                    scale_factor: self.scale_factor as f32,
-                },
-            );
-        });
+        let mut graph = safe_vk::RenderGraph::new(&mut self.transient_images);
+        let target = graph.import_image(target_image, Some(vk::ImageLayout::UNDEFINED));
+        self.ui_pass
+            .add_to_graph(&mut graph, target, &[], &self.ui_textures_delta, |_, _| {});
+        command_buffer.encode(|recorder| graph.execute(recorder));
         self.render_finish_fence.wait();
-        self.render_finish_fence = self.queue.submit_binary(
-            command_buffer,
-            &[&self.swapchain.image_available_semaphore()],
-            &[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT],
-            &[&self.render_finish_semaphore],
-        );
-        self.queue
-            .present(&self.swapchain, index, &[&self.render_finish_semaphore])
+        self.ui_pass.frame_finished();
+        self.render_finish_fence = self.target.submit(&mut self.queue, command_buffer);
+        self.ui_pass
+            .free_textures(&std::mem::take(&mut self.ui_textures_delta));
+        self.target.present(&self.queue, index);
     }
 }
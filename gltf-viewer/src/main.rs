@@ -1,47 +1,73 @@
 mod engine;
+use std::path::PathBuf;
+
+use clap::Parser;
 use engine::Engine;
+use settings::Settings;
+
+/// Name of the settings file, see `Settings::path`.
+pub const SETTINGS_APP: &str = "gltf-viewer";
+
+/// Ray traced glTF viewer.
+#[derive(Debug, Parser)]
+pub struct Args {
+    /// glTF scene to load on startup. Scenes can also be opened from the File menu. Defaults to the
+    /// scene open on the last exit.
+    #[clap(long)]
+    pub scene: Option<PathBuf>,
+    /// Window width in pixels. Defaults to the width on the last exit.
+    #[clap(long)]
+    pub width: Option<u32>,
+    /// Window height in pixels. Defaults to the height on the last exit.
+    #[clap(long)]
+    pub height: Option<u32>,
+    /// Index of the Vulkan device to use, in enumeration order. Defaults to the first discrete
+    /// GPU.
+    #[clap(long)]
+    pub device: Option<usize>,
+    /// Disables the Vulkan validation layer.
+    #[clap(long)]
+    pub no_validation: bool,
+}
+
+/// The engine as `engine_core::run` drives it.
+struct Viewer {
+    engine: Engine,
+}
+
+impl engine_core::Renderer for Viewer {
+    fn handle_event(&mut self, event: &winit::event::Event<()>) {
+        self.engine.handle_event(event);
+    }
+
+    fn update(&mut self) {
+        self.engine.update();
+    }
+
+    fn render(&mut self) {
+        self.engine.render();
+    }
+
+    fn exit(&mut self) {
+        self.engine.save_settings();
+    }
+}
 
 fn main() {
+    egui_backend::init_logger();
+    let args = Args::parse();
+    let settings = Settings::load(SETTINGS_APP);
+    let [width, height] = settings.window_size;
     let rt = tokio::runtime::Runtime::new().unwrap();
     let event_loop = winit::event_loop::EventLoop::new();
-    let window = winit::window::Window::new(&event_loop).unwrap();
-    let mut engine = Engine::new(&window);
+    let window = engine_core::create_window(
+        &event_loop,
+        "gltf-viewer",
+        [args.width.unwrap_or(width), args.height.unwrap_or(height)],
+    );
 
     rt.block_on(async {
-        event_loop.run(move |event, _, control_flow| {
-            engine.handle_event(&event);
-            match event {
-                winit::event::Event::NewEvents(_) => {}
-                winit::event::Event::WindowEvent {
-                    window_id: _,
-                    event,
-                } => {
-                    match event {
-                        winit::event::WindowEvent::Resized(_) => {}
-                        winit::event::WindowEvent::Moved(_) => {}
-                        winit::event::WindowEvent::CloseRequested => {
-                            *control_flow = winit::event_loop::ControlFlow::Exit;
-                        }
-                        _ => {}
-                    }
-                }
-                winit::event::Event::DeviceEvent {
-                    device_id: _,
-                    event: _,
-                } => {}
-                winit::event::Event::UserEvent(_) => {}
-                winit::event::Event::Suspended => {}
-                winit::event::Event::Resumed => {}
-                winit::event::Event::MainEventsCleared => {
-                    window.request_redraw();
-                }
-                winit::event::Event::RedrawRequested(_) => {
-                    engine.update();
-                    engine.render();
-                }
-                winit::event::Event::RedrawEventsCleared => {}
-                winit::event::Event::LoopDestroyed => {}
-            }
-        });
+        let engine = Engine::new(&window, &args, settings);
+        engine_core::run(event_loop, window, Viewer { engine })
     });
 }
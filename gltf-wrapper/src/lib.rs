@@ -342,10 +342,10 @@ mod tests {
         let instance = Arc::new(safe_vk::Instance::new(
             entry.clone(),
             &[
-                safe_vk::name::instance::layer::khronos::VALIDATION,
-                safe_vk::name::instance::layer::lunarg::MONITOR,
+                safe_vk::name::instance::Layer::KhronosValidation,
+                safe_vk::name::instance::Layer::LunargMonitor,
             ],
-            &[safe_vk::name::instance::extension::ext::DEBUG_UTILS],
+            &[safe_vk::name::instance::Extension::ExtDebugUtils],
         ));
         let pdevice = Arc::new(safe_vk::PhysicalDevice::new(instance.clone(), None));
 
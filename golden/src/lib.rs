@@ -0,0 +1,202 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use image::RgbaImage;
+
+/// Set to any value to write the renders as the new references instead of comparing.
+pub const UPDATE_VAR: &str = "UPDATE_GOLDEN";
+
+/// Differences scaled by this in the diff image, so the noise level is visible.
+const DIFF_SCALE: f32 = 8.0;
+
+/// How different a render may be from its reference. Path traced images are noisy and GPUs
+/// don't round alike, so both images are blurred before they are compared.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tolerance {
+    /// Root mean square difference of the color channels, from 0 to 1.
+    pub rmse: f32,
+    /// A pixel differs if a channel differs by more than this, from 0 to 1.
+    pub pixel_threshold: f32,
+    /// Fraction of the pixels that may differ.
+    pub max_differing_fraction: f32,
+    /// Radius in pixels of the box blur applied to both images.
+    pub blur_radius: u32,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self {
+            rmse: 0.01,
+            pixel_threshold: 0.08,
+            max_differing_fraction: 0.001,
+            blur_radius: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Comparison {
+    pub rmse: f32,
+    /// Largest difference of a channel, from 0 to 1.
+    pub max_difference: f32,
+    /// Fraction of the pixels over `Tolerance::pixel_threshold`.
+    pub differing_fraction: f32,
+}
+
+impl Comparison {
+    pub fn passes(&self, tolerance: &Tolerance) -> bool {
+        self.rmse <= tolerance.rmse && self.differing_fraction <= tolerance.max_differing_fraction
+    }
+}
+
+impl std::fmt::Display for Comparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "RMSE {:.4}, max difference {:.3}, {:.3}% of the pixels differ",
+            self.rmse,
+            self.max_difference,
+            self.differing_fraction * 100.0
+        )
+    }
+}
+
+/// Compares the color channels of two images of the same size, alpha is ignored.
+pub fn compare(
+    actual: &RgbaImage,
+    reference: &RgbaImage,
+    tolerance: &Tolerance,
+) -> Result<Comparison> {
+    if actual.dimensions() != reference.dimensions() {
+        bail!(
+            "the render is {:?} but the reference is {:?}",
+            actual.dimensions(),
+            reference.dimensions()
+        );
+    }
+    let actual = blur(actual, tolerance.blur_radius);
+    let reference = blur(reference, tolerance.blur_radius);
+    let mut squared_sum = 0.0f64;
+    let mut max_difference = 0.0f32;
+    let mut differing = 0usize;
+    for (a, b) in actual.iter().zip(reference.iter()) {
+        let mut pixel_difference = 0.0f32;
+        for (a, b) in a.iter().zip(b.iter()) {
+            let difference = (a - b).abs();
+            squared_sum += (difference * difference) as f64;
+            pixel_difference = pixel_difference.max(difference);
+        }
+        max_difference = max_difference.max(pixel_difference);
+        if pixel_difference > tolerance.pixel_threshold {
+            differing += 1;
+        }
+    }
+    let pixels = actual.len().max(1);
+    Ok(Comparison {
+        rmse: (squared_sum / (pixels * 3) as f64).sqrt() as f32,
+        max_difference,
+        differing_fraction: differing as f32 / pixels as f32,
+    })
+}
+
+/// The absolute difference of the color channels, scaled up, on opaque black.
+pub fn diff_image(actual: &RgbaImage, reference: &RgbaImage) -> RgbaImage {
+    RgbaImage::from_fn(actual.width(), actual.height(), |x, y| {
+        let a = actual.get_pixel(x, y);
+        let b = reference.get_pixel(x, y);
+        let channel = |c: usize| {
+            let difference = (a[c] as f32 - b[c] as f32).abs() * DIFF_SCALE;
+            difference.min(255.0) as u8
+        };
+        image::Rgba([channel(0), channel(1), channel(2), 255])
+    })
+}
+
+/// Compares the image at `actual` with the reference at `reference`. On a mismatch the diff
+/// image is written next to `actual` as `<name>.diff.png` and the error says where.
+///
+/// With `UPDATE_GOLDEN` set, writes `actual` as the reference instead.
+pub fn check(actual: &Path, reference: &Path, tolerance: &Tolerance) -> Result<Comparison> {
+    let actual_image = image::open(actual)
+        .with_context(|| format!("failed to read {}", actual.display()))?
+        .to_rgba8();
+    if std::env::var_os(UPDATE_VAR).is_some() {
+        if let Some(parent) = reference.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        actual_image
+            .save(reference)
+            .with_context(|| format!("failed to write {}", reference.display()))?;
+        return compare(&actual_image, &actual_image, tolerance);
+    }
+    if !reference.exists() {
+        bail!(
+            "there is no reference at {}, run with {}=1 to write it",
+            reference.display(),
+            UPDATE_VAR
+        );
+    }
+    let reference_image = image::open(reference)
+        .with_context(|| format!("failed to read {}", reference.display()))?
+        .to_rgba8();
+
+    let comparison = compare(&actual_image, &reference_image, tolerance)?;
+    if !comparison.passes(tolerance) {
+        let diff_path = diff_path(actual);
+        diff_image(&actual_image, &reference_image)
+            .save(&diff_path)
+            .with_context(|| format!("failed to write {}", diff_path.display()))?;
+        bail!(
+            "{} doesn't match {}: {}, see {}",
+            actual.display(),
+            reference.display(),
+            comparison,
+            diff_path.display()
+        );
+    }
+    Ok(comparison)
+}
+
+fn diff_path(actual: &Path) -> PathBuf {
+    let stem = actual
+        .file_stem()
+        .map_or_else(|| "render".into(), |stem| stem.to_string_lossy());
+    actual.with_file_name(format!("{}.diff.png", stem))
+}
+
+/// The color channels from 0 to 1, averaged over a square of `2 * radius + 1` pixels clamped
+/// to the image.
+fn blur(image: &RgbaImage, radius: u32) -> Vec<[f32; 3]> {
+    let (width, height) = image.dimensions();
+    let pixels: Vec<[f32; 3]> = image
+        .pixels()
+        .map(|pixel| {
+            [
+                pixel[0] as f32 / 255.0,
+                pixel[1] as f32 / 255.0,
+                pixel[2] as f32 / 255.0,
+            ]
+        })
+        .collect();
+    if radius == 0 {
+        return pixels;
+    }
+    let mut blurred = Vec::with_capacity(pixels.len());
+    for y in 0..height {
+        for x in 0..width {
+            let mut sum = [0.0f32; 3];
+            let mut count = 0.0f32;
+            for sy in y.saturating_sub(radius)..(y + radius + 1).min(height) {
+                for sx in x.saturating_sub(radius)..(x + radius + 1).min(width) {
+                    let pixel = pixels[(sy * width + sx) as usize];
+                    for (sum, value) in sum.iter_mut().zip(pixel.iter()) {
+                        *sum += value;
+                    }
+                    count += 1.0;
+                }
+            }
+            blurred.push([sum[0] / count, sum[1] / count, sum[2] / count]);
+        }
+    }
+    blurred
+}
@@ -0,0 +1,88 @@
+use std::path::PathBuf;
+
+use golden::{Comparison, Tolerance};
+use image::{Rgba, RgbaImage};
+
+fn gradient(width: u32, height: u32) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, y| {
+        Rgba([(x * 255 / width) as u8, (y * 255 / height) as u8, 128, 255])
+    })
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("silly-cat-engine-golden-{}", name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_identical_images_match() {
+    let image = gradient(64, 64);
+    let comparison = golden::compare(&image, &image, &Tolerance::default()).unwrap();
+    assert_eq!(
+        comparison,
+        Comparison {
+            rmse: 0.0,
+            max_difference: 0.0,
+            differing_fraction: 0.0,
+        }
+    );
+}
+
+#[test]
+fn test_noise_within_tolerance() {
+    let reference = gradient(64, 64);
+    let mut noisy = reference.clone();
+    for (i, pixel) in noisy.pixels_mut().enumerate() {
+        let offset = if i % 2 == 0 { 3 } else { -3 };
+        pixel[0] = (pixel[0] as i32 + offset).clamp(0, 255) as u8;
+    }
+    let comparison = golden::compare(&noisy, &reference, &Tolerance::default()).unwrap();
+    assert!(comparison.passes(&Tolerance::default()), "{}", comparison);
+}
+
+#[test]
+fn test_changed_region_fails() {
+    let reference = gradient(64, 64);
+    let mut changed = reference.clone();
+    for y in 16..32 {
+        for x in 16..32 {
+            changed.put_pixel(x, y, Rgba([255, 255, 255, 255]));
+        }
+    }
+    let comparison = golden::compare(&changed, &reference, &Tolerance::default()).unwrap();
+    assert!(!comparison.passes(&Tolerance::default()), "{}", comparison);
+}
+
+#[test]
+fn test_size_mismatch_is_an_error() {
+    let result = golden::compare(&gradient(64, 64), &gradient(32, 64), &Tolerance::default());
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_check_writes_diff_on_mismatch() {
+    let dir = temp_dir("mismatch");
+    let actual = dir.join("render.png");
+    let reference = dir.join("reference.png");
+    gradient(32, 32).save(&reference).unwrap();
+    RgbaImage::from_pixel(32, 32, Rgba([0, 0, 0, 255]))
+        .save(&actual)
+        .unwrap();
+
+    assert!(golden::check(&actual, &reference, &Tolerance::default()).is_err());
+    assert!(dir.join("render.diff.png").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_missing_reference_is_an_error() {
+    let dir = temp_dir("missing");
+    let actual = dir.join("render.png");
+    gradient(32, 32).save(&actual).unwrap();
+
+    let result = golden::check(&actual, &dir.join("reference.png"), &Tolerance::default());
+    assert!(result.is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,201 @@
+use std::any::Any;
+use std::marker::PhantomData;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use crossbeam_deque::{Injector, Steal, Stealer, Worker};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// How long an idle worker sleeps before looking for jobs again, in case it missed a wake up.
+const IDLE_TIMEOUT: Duration = Duration::from_millis(10);
+
+struct Shared {
+    injector: Injector<Job>,
+    stealers: Vec<Stealer<Job>>,
+    shutdown: AtomicBool,
+    /// Idle workers wait on `wake` holding this.
+    idle: Mutex<()>,
+    wake: Condvar,
+}
+
+impl Shared {
+    /// A job from `local`, else a batch from the injector, else one stolen from another worker.
+    fn find_job(&self, local: Option<&Worker<Job>>) -> Option<Job> {
+        if let Some(job) = local.and_then(Worker::pop) {
+            return Some(job);
+        }
+        loop {
+            let steal = match local {
+                Some(local) => self.injector.steal_batch_and_pop(local),
+                None => self.injector.steal(),
+            };
+            let steal = steal.or_else(|| self.stealers.iter().map(Stealer::steal).collect());
+            match steal {
+                Steal::Success(job) => return Some(job),
+                Steal::Empty => return None,
+                Steal::Retry => {}
+            }
+        }
+    }
+
+    fn push(&self, job: Job) {
+        self.injector.push(job);
+        self.wake.notify_one();
+    }
+}
+
+/// A pool of worker threads running jobs, each stealing from the others when it runs out.
+///
+/// `scope` fans out jobs that borrow from the caller and joins them before returning, `map` is
+/// the common case of one job per item with the results in order.
+pub struct JobSystem {
+    shared: Arc<Shared>,
+    threads: Vec<std::thread::JoinHandle<()>>,
+}
+
+impl JobSystem {
+    /// A pool of `thread_count` workers, at least one.
+    pub fn new(thread_count: usize) -> Self {
+        let workers: Vec<_> = (0..thread_count.max(1))
+            .map(|_| Worker::new_fifo())
+            .collect();
+        let shared = Arc::new(Shared {
+            injector: Injector::new(),
+            stealers: workers.iter().map(Worker::stealer).collect(),
+            shutdown: AtomicBool::new(false),
+            idle: Mutex::new(()),
+            wake: Condvar::new(),
+        });
+        let threads = workers
+            .into_iter()
+            .enumerate()
+            .map(|(index, worker)| {
+                let shared = shared.clone();
+                std::thread::Builder::new()
+                    .name(format!("job worker {}", index))
+                    .spawn(move || run_worker(&shared, &worker))
+                    .unwrap()
+            })
+            .collect();
+        Self { shared, threads }
+    }
+
+    /// A worker for each core but the one of the calling thread, which helps while it joins.
+    pub fn with_default_threads() -> Self {
+        Self::new(num_cpus::get().saturating_sub(1))
+    }
+
+    pub fn thread_count(&self) -> usize {
+        self.threads.len()
+    }
+
+    /// Runs `job` on a worker, without waiting for it. A panic is logged, not propagated.
+    pub fn spawn<F: FnOnce() + Send + 'static>(&self, job: F) {
+        self.shared.push(Box::new(move || {
+            if std::panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+                log::error!("job panicked");
+            }
+        }));
+    }
+
+    /// Calls `f` with a scope whose jobs may borrow from the caller, and returns once they have
+    /// all finished. The calling thread runs jobs too while it waits. If `f` or a job panicked,
+    /// the panic is resumed here after the join.
+    pub fn scope<'env, F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&Scope<'env>) -> R,
+    {
+        let scope = Scope {
+            shared: self.shared.clone(),
+            pending: Arc::new(AtomicUsize::new(0)),
+            panic: Arc::new(Mutex::new(None)),
+            _env: PhantomData,
+        };
+        let result = std::panic::catch_unwind(AssertUnwindSafe(|| f(&scope)));
+        while scope.pending.load(Ordering::Acquire) > 0 {
+            match self.shared.find_job(None) {
+                Some(job) => job(),
+                None => std::thread::yield_now(),
+            }
+        }
+        if let Some(panic) = scope.panic.lock().unwrap().take() {
+            std::panic::resume_unwind(panic);
+        }
+        match result {
+            Ok(result) => result,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+
+    /// `f` applied to every item on the workers, the results in the order of `items`.
+    pub fn map<T, R, F>(&self, items: &[T], f: F) -> Vec<R>
+    where
+        T: Sync,
+        R: Send,
+        F: Fn(&T) -> R + Sync,
+    {
+        let mut results: Vec<Option<R>> = items.iter().map(|_| None).collect();
+        let f = &f;
+        self.scope(|scope| {
+            for (item, result) in items.iter().zip(results.iter_mut()) {
+                scope.spawn(move || *result = Some(f(item)));
+            }
+        });
+        results.into_iter().map(Option::unwrap).collect()
+    }
+}
+
+impl Drop for JobSystem {
+    /// Waits for the jobs already spawned.
+    fn drop(&mut self) {
+        self.shared.shutdown.store(true, Ordering::Release);
+        self.shared.wake.notify_all();
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Runs jobs until the system is dropped and none are left.
+fn run_worker(shared: &Shared, local: &Worker<Job>) {
+    loop {
+        match shared.find_job(Some(local)) {
+            Some(job) => job(),
+            None if shared.shutdown.load(Ordering::Acquire) => return,
+            None => {
+                let idle = shared.idle.lock().unwrap();
+                let _ = shared.wake.wait_timeout(idle, IDLE_TIMEOUT).unwrap();
+            }
+        }
+    }
+}
+
+/// Jobs spawned in a `JobSystem::scope`, joined before it returns.
+pub struct Scope<'env> {
+    shared: Arc<Shared>,
+    pending: Arc<AtomicUsize>,
+    /// The first panic of a job, resumed after the join.
+    panic: Arc<Mutex<Option<Box<dyn Any + Send>>>>,
+    /// Invariant, so jobs can't borrow anything shorter lived than the scope.
+    _env: PhantomData<&'env mut &'env ()>,
+}
+
+impl<'env> Scope<'env> {
+    pub fn spawn<F: FnOnce() + Send + 'env>(&self, job: F) {
+        self.pending.fetch_add(1, Ordering::AcqRel);
+        let pending = self.pending.clone();
+        let panic = self.panic.clone();
+        let job: Box<dyn FnOnce() + Send + 'env> = Box::new(move || {
+            if let Err(e) = std::panic::catch_unwind(AssertUnwindSafe(job)) {
+                panic.lock().unwrap().get_or_insert(e);
+            }
+            pending.fetch_sub(1, Ordering::AcqRel);
+        });
+        // The scope outlives the job: `JobSystem::scope` doesn't return before `pending` is zero.
+        let job: Job = unsafe { std::mem::transmute(job) };
+        self.shared.push(job);
+    }
+}
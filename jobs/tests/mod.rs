@@ -0,0 +1,64 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use jobs::JobSystem;
+
+#[test]
+fn test_map_keeps_order() {
+    let jobs = JobSystem::new(4);
+    let items: Vec<u32> = (0..1000).collect();
+    let squares = jobs.map(&items, |item| item * item);
+    assert_eq!(
+        squares,
+        items.iter().map(|item| item * item).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_scope_joins_borrowing_jobs() {
+    let jobs = JobSystem::new(2);
+    let counter = AtomicUsize::new(0);
+    jobs.scope(|scope| {
+        for _ in 0..100 {
+            scope.spawn(|| {
+                counter.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+    });
+    assert_eq!(counter.load(Ordering::Relaxed), 100);
+}
+
+#[test]
+fn test_nested_scopes() {
+    let jobs = JobSystem::new(1);
+    let sums = jobs.map(&[10u32, 20, 30], |&count| {
+        let items: Vec<u32> = (0..count).collect();
+        jobs.map(&items, |item| item + 1).iter().sum::<u32>()
+    });
+    assert_eq!(sums, vec![55, 210, 465]);
+}
+
+#[test]
+fn test_scope_resumes_job_panic() {
+    let jobs = JobSystem::new(2);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        jobs.scope(|scope| scope.spawn(|| panic!("job failed")));
+    }));
+    assert!(result.is_err());
+    // The workers survive the panic.
+    assert_eq!(jobs.map(&[1, 2], |item| item * 2), vec![2, 4]);
+}
+
+#[test]
+fn test_spawn_runs_detached() {
+    let jobs = JobSystem::new(2);
+    let counter = Arc::new(AtomicUsize::new(0));
+    for _ in 0..10 {
+        let counter = counter.clone();
+        jobs.spawn(move || {
+            counter.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+    drop(jobs);
+    assert_eq!(counter.load(Ordering::Relaxed), 10);
+}
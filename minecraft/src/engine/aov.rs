@@ -0,0 +1,115 @@
+use std::sync::Arc;
+
+use safe_vk::vk;
+
+/// Floating point so the HDR inspector can show any of them.
+const AOV_FORMAT: vk::Format = vk::Format::R32G32B32A32_SFLOAT;
+
+/// An auxiliary image raytrace.rgen writes alongside the radiance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aov {
+    /// Base color of the first hit, the environment clamped to 1 for the sky.
+    Albedo,
+    /// World space shading normal of the first hit, 0 for the sky.
+    Normal,
+    /// Distance along the view axis to the first hit, 0 for the sky.
+    Depth,
+    /// Instance custom index plus geometry index of the first hit, -1 for the sky.
+    ObjectId,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 4] = [Aov::Albedo, Aov::Normal, Aov::Depth, Aov::ObjectId];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Albedo => "Albedo",
+            Aov::Normal => "Normal",
+            Aov::Depth => "Depth",
+            Aov::ObjectId => "Object ID",
+        }
+    }
+}
+
+/// The AOV images at bindings 22 to 25 of the ray tracing set, the size of the result image.
+///
+/// Albedo and normal are averaged over the accumulated samples like the radiance, depth and
+/// object ID are taken from the first sample. That makes albedo and normal usable as the guide
+/// images of a denoiser.
+pub struct Aovs {
+    albedo: Arc<safe_vk::Image>,
+    normal: Arc<safe_vk::Image>,
+    depth: Arc<safe_vk::Image>,
+    object_id: Arc<safe_vk::Image>,
+    /// Writes the images when set. Only the path tracer does.
+    pub enabled: bool,
+}
+
+impl Aovs {
+    pub fn new(
+        allocator: &Arc<safe_vk::Allocator>,
+        queue: &mut safe_vk::Queue,
+        command_pool: Arc<safe_vk::CommandPool>,
+        result_image: &Arc<safe_vk::Image>,
+    ) -> Self {
+        let mut create_image = |name: &str| {
+            let mut image = safe_vk::Image::new(
+                Some(name),
+                allocator.clone(),
+                AOV_FORMAT,
+                result_image.width(),
+                result_image.height(),
+                vk::ImageTiling::OPTIMAL,
+                // Copied from by the HDR inspector.
+                vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::TRANSFER_SRC,
+                safe_vk::MemoryUsage::GpuOnly,
+            );
+            image.set_layout(vk::ImageLayout::GENERAL, queue, command_pool.clone());
+            Arc::new(image)
+        };
+        Self {
+            albedo: create_image("aov albedo"),
+            normal: create_image("aov normal"),
+            depth: create_image("aov depth"),
+            object_id: create_image("aov object id"),
+            enabled: false,
+        }
+    }
+
+    /// Recreates the images at the size of a new result image, keeping `enabled`.
+    pub fn resize(
+        &mut self,
+        allocator: &Arc<safe_vk::Allocator>,
+        queue: &mut safe_vk::Queue,
+        command_pool: Arc<safe_vk::CommandPool>,
+        result_image: &Arc<safe_vk::Image>,
+    ) {
+        let enabled = self.enabled;
+        *self = Self::new(allocator, queue, command_pool, result_image);
+        self.enabled = enabled;
+    }
+
+    /// The image of `aov`, in `GENERAL` layout.
+    pub fn image(&self, aov: Aov) -> &Arc<safe_vk::Image> {
+        match aov {
+            Aov::Albedo => &self.albedo,
+            Aov::Normal => &self.normal,
+            Aov::Depth => &self.depth,
+            Aov::ObjectId => &self.object_id,
+        }
+    }
+
+    /// Descriptor writes of the images in the order of `Aov::ALL`.
+    pub fn descriptor_updates(&self) -> Vec<safe_vk::DescriptorSetUpdateInfo> {
+        Aov::ALL
+            .iter()
+            .zip(22..)
+            .map(|(aov, binding)| safe_vk::DescriptorSetUpdateInfo {
+                binding,
+                detail: safe_vk::DescriptorSetUpdateDetail::Image(Arc::new(
+                    safe_vk::ImageView::new(self.image(*aov).clone()),
+                )),
+            })
+            .collect()
+    }
+}
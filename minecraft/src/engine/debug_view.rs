@@ -0,0 +1,518 @@
+use std::sync::Arc;
+
+use bytemuck::{Pod, Zeroable};
+use safe_vk::{vk, ComputePipelineRecorder, GraphicsPipelineRecorder, Pipeline, PipelineRecorder};
+
+use super::scene::{Draw, Scene};
+use super::shaders;
+
+const WORKGROUP_SIZE: u32 = 16;
+
+/// The format of the tone mapped image, which the views are drawn to.
+const TARGET_FORMAT: vk::Format = vk::Format::R32G32B32A32_SFLOAT;
+
+/// What the debug view pass shows in place of the render.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugView {
+    Off,
+    /// How many triangles the traversal of the primary ray tested.
+    TraversalCost,
+    /// A false color for every instance of the top level acceleration structure.
+    InstanceId,
+    /// A false color for every geometry of every mesh.
+    GeometryId,
+}
+
+impl DebugView {
+    pub const ALL: [DebugView; 4] = [
+        DebugView::Off,
+        DebugView::TraversalCost,
+        DebugView::InstanceId,
+        DebugView::GeometryId,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DebugView::Off => "Off",
+            DebugView::TraversalCost => "Traversal Cost",
+            DebugView::InstanceId => "Instance ID",
+            DebugView::GeometryId => "Geometry ID",
+        }
+    }
+}
+
+/// Matches `PushConstants` in debug_view.comp.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct ViewPushConstants {
+    view: u32,
+    max_triangle_tests: u32,
+}
+
+/// Matches `PushConsts` in debug_bounds.vert.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct BoundsPushConstants {
+    model: [f32; 16],
+    bounds_min: [f32; 3],
+    _padding: f32,
+    bounds_max: [f32; 3],
+    _padding_2: f32,
+    color: [f32; 4],
+}
+
+const BOUNDS_COLOR: [f32; 4] = [0.0, 1.0, 0.2, 1.0];
+const SELECTION_COLOR: [f32; 4] = [1.0, 0.8, 0.0, 1.0];
+
+/// Views of the acceleration structures for finding out why a scene traces slowly, drawn over
+/// the tone mapped image.
+///
+/// debug_view.comp replaces the image with a heatmap of the triangles the primary rays tested or
+/// false colors of what they hit. The bounding box of every geometry can be drawn over any of
+/// them, or the render, as lines. The boxes of the selected instance are always drawn, in another
+/// color, even if it's hidden.
+pub struct DebugViews {
+    /// `None` without ray tracing, which leaves only the bounding boxes.
+    view_pass: Option<ViewPass>,
+    render_pass: Arc<safe_vk::RenderPass>,
+    bounds_pipeline: Arc<safe_vk::GraphicsPipeline>,
+    bounds_descriptor_set: Arc<safe_vk::DescriptorSet>,
+    /// Draws to the tone mapped image, recreated with it.
+    framebuffer: Arc<safe_vk::Framebuffer>,
+    extent: (u32, u32),
+    pub view: DebugView,
+    /// Triangle tests shown in full red by the traversal cost heatmap, more turn white.
+    pub max_triangle_tests: u32,
+    pub show_bounds: bool,
+    /// Index of the instance of `Scene::instances` selected by the user.
+    pub selected: Option<usize>,
+}
+
+struct ViewPass {
+    pipeline: Arc<safe_vk::ComputePipeline>,
+    /// A new scene or target image gets a new set while the frame in flight uses the old one.
+    descriptor_allocator: safe_vk::DescriptorAllocator,
+    descriptor_set: Arc<safe_vk::DescriptorSet>,
+    uniform_buffer: Arc<safe_vk::Buffer>,
+}
+
+impl DebugViews {
+    /// The views other than `DebugView::Off` need `ray_tracing`, the scene must have been loaded
+    /// with it then.
+    pub fn new(
+        device: Arc<safe_vk::Device>,
+        target_image: &Arc<safe_vk::Image>,
+        scene: &Scene,
+        uniform_buffer: Arc<safe_vk::Buffer>,
+        ray_tracing: bool,
+    ) -> Self {
+        let view_pass = if ray_tracing {
+            Some(ViewPass::new(
+                device.clone(),
+                target_image,
+                scene,
+                uniform_buffer.clone(),
+            ))
+        } else {
+            None
+        };
+
+        let descriptor_set_layout = Arc::new(safe_vk::DescriptorSetLayout::new(
+            device.clone(),
+            Some("debug bounds set layout"),
+            &[safe_vk::DescriptorSetLayoutBinding {
+                binding: 5,
+                descriptor_type: safe_vk::DescriptorType::UniformBuffer,
+                stage_flags: vk::ShaderStageFlags::VERTEX,
+            }],
+        ));
+        let pipeline_layout = Arc::new(safe_vk::PipelineLayout::new(
+            device.clone(),
+            Some("debug bounds pipeline layout"),
+            &[&descriptor_set_layout],
+            &[vk::PushConstantRange::builder()
+                .stage_flags(vk::ShaderStageFlags::VERTEX)
+                .offset(0)
+                .size(std::mem::size_of::<BoundsPushConstants>() as u32)
+                .build()],
+        ));
+        let render_pass = create_render_pass(device.clone());
+        let shader_stage = |shader: &str, stage: vk::ShaderStageFlags| {
+            Arc::new(safe_vk::ShaderStage::new(
+                Arc::new(safe_vk::ShaderModule::new(
+                    device.clone(),
+                    shaders::Shaders::get(shader).unwrap(),
+                )),
+                stage,
+                "main",
+            ))
+        };
+        let bounds_pipeline = Arc::new(safe_vk::GraphicsPipeline::new(
+            Some("debug bounds pipeline"),
+            pipeline_layout,
+            vec![
+                shader_stage("debug_bounds.vert.spv", vk::ShaderStageFlags::VERTEX),
+                shader_stage("debug_bounds.frag.spv", vk::ShaderStageFlags::FRAGMENT),
+            ],
+            render_pass.clone(),
+            &vk::PipelineVertexInputStateCreateInfo::builder().build(),
+            &vk::PipelineInputAssemblyStateCreateInfo::builder()
+                .topology(vk::PrimitiveTopology::LINE_LIST)
+                .build(),
+            &vk::PipelineRasterizationStateCreateInfo::builder()
+                .cull_mode(vk::CullModeFlags::NONE)
+                .polygon_mode(vk::PolygonMode::FILL)
+                .line_width(1.0)
+                .build(),
+            &vk::PipelineMultisampleStateCreateInfo::builder()
+                .rasterization_samples(vk::SampleCountFlags::TYPE_1)
+                .build(),
+            // An overlay, there's no depth to test against.
+            &vk::PipelineDepthStencilStateCreateInfo::builder()
+                .depth_test_enable(false)
+                .depth_write_enable(false)
+                .build(),
+            &vk::PipelineColorBlendStateCreateInfo::builder()
+                .attachments(&[vk::PipelineColorBlendAttachmentState::builder()
+                    .blend_enable(false)
+                    .color_write_mask(vk::ColorComponentFlags::all())
+                    .build()])
+                .build(),
+            &vk::PipelineViewportStateCreateInfo::builder()
+                .viewport_count(1)
+                .scissor_count(1),
+            &vk::PipelineDynamicStateCreateInfo::builder()
+                .dynamic_states(&[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR])
+                .build(),
+        ));
+
+        // The camera buffer never changes, one set does.
+        let mut descriptor_allocator = safe_vk::DescriptorAllocator::new(descriptor_set_layout, 1);
+        let bounds_descriptor_set =
+            descriptor_allocator.allocate(Some("debug bounds descriptor set"));
+        bounds_descriptor_set.update(&[safe_vk::DescriptorSetUpdateInfo {
+            binding: 5,
+            detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
+                buffer: uniform_buffer,
+                offset: 0,
+            },
+        }]);
+
+        Self {
+            view_pass,
+            framebuffer: create_framebuffer(&render_pass, target_image),
+            render_pass,
+            bounds_pipeline,
+            bounds_descriptor_set: Arc::new(bounds_descriptor_set),
+            extent: (target_image.width(), target_image.height()),
+            view: DebugView::Off,
+            max_triangle_tests: 64,
+            show_bounds: false,
+            selected: None,
+        }
+    }
+
+    /// Points the passes at a new tone mapped image.
+    pub fn resize(&mut self, target_image: &Arc<safe_vk::Image>, scene: &Scene) {
+        self.framebuffer = create_framebuffer(&self.render_pass, target_image);
+        self.extent = (target_image.width(), target_image.height());
+        self.set_scene(target_image, scene);
+    }
+
+    /// Points the view pass at a new scene.
+    pub fn set_scene(&mut self, target_image: &Arc<safe_vk::Image>, scene: &Scene) {
+        if let Some(view_pass) = &mut self.view_pass {
+            view_pass.set_scene(target_image, scene);
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.add_enabled_ui(self.view_pass.is_some(), |ui| {
+            for view in DebugView::ALL.iter() {
+                ui.radio_value(&mut self.view, *view, view.name())
+                    .on_disabled_hover_text("The device doesn't support ray tracing");
+            }
+        });
+        ui.add_enabled_ui(self.view == DebugView::TraversalCost, |ui| {
+            ui.add(
+                egui::Slider::new(&mut self.max_triangle_tests, 1..=1024)
+                    .logarithmic(true)
+                    .text("Max Triangle Tests"),
+            );
+        });
+        ui.checkbox(&mut self.show_bounds, "Bounding Boxes");
+    }
+
+    /// Draws the selected view and the bounding boxes over the target image, which must be in
+    /// `GENERAL` layout and stays in it.
+    pub fn record(&self, recorder: &mut safe_vk::CommandRecorder, scene: &Scene) {
+        let (width, height) = self.extent;
+        if let Some(view_pass) = &self.view_pass {
+            if self.view != DebugView::Off {
+                view_pass.record(
+                    recorder,
+                    self.extent,
+                    &ViewPushConstants {
+                        view: self.view as u32,
+                        max_triangle_tests: self.max_triangle_tests,
+                    },
+                );
+            }
+        }
+        if !self.show_bounds && self.selected.is_none() {
+            return;
+        }
+        recorder.begin_render_pass(
+            self.render_pass.clone(),
+            self.framebuffer.clone(),
+            &[],
+            |recorder| {
+                recorder.bind_graphics_pipeline(
+                    self.bounds_pipeline.clone(),
+                    |recorder, pipeline| {
+                        recorder.set_viewport(
+                            vk::Viewport::builder()
+                                .width(width as f32)
+                                .height(height as f32)
+                                .min_depth(0.0)
+                                .max_depth(1.0)
+                                .build(),
+                        );
+                        recorder.set_scissor(&[vk::Rect2D::builder()
+                            .extent(vk::Extent2D { width, height })
+                            .build()]);
+                        recorder.bind_descriptor_sets(
+                            vec![self.bounds_descriptor_set.clone()],
+                            pipeline.layout(),
+                            0,
+                        );
+                        let mut draw_bounds = |draw: &Draw, color: [f32; 4]| {
+                            let push_constants = BoundsPushConstants {
+                                model: draw.transform.to_cols_array(),
+                                bounds_min: draw.bounds_min,
+                                _padding: 0.0,
+                                bounds_max: draw.bounds_max,
+                                _padding_2: 0.0,
+                                color,
+                            };
+                            recorder.push_constants(
+                                pipeline.layout(),
+                                vk::ShaderStageFlags::VERTEX,
+                                0,
+                                bytemuck::bytes_of(&push_constants),
+                            );
+                            recorder.draw(24, 1);
+                        };
+                        if self.show_bounds {
+                            for draw in scene.draws().iter().filter(|draw| draw.visible) {
+                                draw_bounds(draw, BOUNDS_COLOR);
+                            }
+                        }
+                        // Last, so that they're on top of the other boxes.
+                        if let Some(selected) = self.selected {
+                            for draw in scene.draws() {
+                                if draw.instance as usize == selected {
+                                    draw_bounds(draw, SELECTION_COLOR);
+                                }
+                            }
+                        }
+                    },
+                );
+            },
+        );
+    }
+}
+
+impl ViewPass {
+    fn new(
+        device: Arc<safe_vk::Device>,
+        target_image: &Arc<safe_vk::Image>,
+        scene: &Scene,
+        uniform_buffer: Arc<safe_vk::Buffer>,
+    ) -> Self {
+        let binding = |binding: u32, descriptor_type: safe_vk::DescriptorType| {
+            safe_vk::DescriptorSetLayoutBinding {
+                binding,
+                descriptor_type,
+                stage_flags: vk::ShaderStageFlags::COMPUTE,
+            }
+        };
+        let descriptor_set_layout = Arc::new(safe_vk::DescriptorSetLayout::new(
+            device.clone(),
+            Some("debug view set layout"),
+            &[
+                binding(0, safe_vk::DescriptorType::StorageImage),
+                binding(1, safe_vk::DescriptorType::AccelerationStructure),
+                binding(5, safe_vk::DescriptorType::UniformBuffer),
+            ],
+        ));
+        let pipeline_layout = Arc::new(safe_vk::PipelineLayout::new(
+            device.clone(),
+            Some("debug view pipeline layout"),
+            &[&descriptor_set_layout],
+            &[vk::PushConstantRange::builder()
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .offset(0)
+                .size(std::mem::size_of::<ViewPushConstants>() as u32)
+                .build()],
+        ));
+        let pipeline = Arc::new(safe_vk::ComputePipeline::new(
+            Some("debug view pipeline"),
+            pipeline_layout,
+            Arc::new(safe_vk::ShaderStage::new(
+                Arc::new(safe_vk::ShaderModule::new(
+                    device,
+                    shaders::Shaders::get("debug_view.comp.spv").unwrap(),
+                )),
+                vk::ShaderStageFlags::COMPUTE,
+                "main",
+            )),
+        ));
+        let mut descriptor_allocator = safe_vk::DescriptorAllocator::new(descriptor_set_layout, 2);
+        let descriptor_set = create_descriptor_set(
+            &mut descriptor_allocator,
+            target_image,
+            scene,
+            &uniform_buffer,
+        );
+        Self {
+            pipeline,
+            descriptor_allocator,
+            descriptor_set,
+            uniform_buffer,
+        }
+    }
+
+    fn set_scene(&mut self, target_image: &Arc<safe_vk::Image>, scene: &Scene) {
+        self.descriptor_set = create_descriptor_set(
+            &mut self.descriptor_allocator,
+            target_image,
+            scene,
+            &self.uniform_buffer,
+        );
+    }
+
+    fn record(
+        &self,
+        recorder: &mut safe_vk::CommandRecorder,
+        (width, height): (u32, u32),
+        push_constants: &ViewPushConstants,
+    ) {
+        // The view overwrites what the previous pass wrote to the image.
+        recorder.memory_barrier(
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::AccessFlags::SHADER_WRITE,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::SHADER_WRITE,
+        );
+        recorder.bind_compute_pipeline(self.pipeline.clone(), |recorder, pipeline| {
+            recorder.bind_descriptor_sets(vec![self.descriptor_set.clone()], pipeline.layout(), 0);
+            recorder.push_constants(
+                pipeline.layout(),
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                bytemuck::bytes_of(push_constants),
+            );
+            recorder.dispatch(
+                (width + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE,
+                (height + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE,
+                1,
+            );
+        });
+    }
+}
+
+fn create_descriptor_set(
+    descriptor_allocator: &mut safe_vk::DescriptorAllocator,
+    target_image: &Arc<safe_vk::Image>,
+    scene: &Scene,
+    uniform_buffer: &Arc<safe_vk::Buffer>,
+) -> Arc<safe_vk::DescriptorSet> {
+    let descriptor_set = descriptor_allocator.allocate(Some("debug view descriptor set"));
+    descriptor_set.update(&[
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 0,
+            detail: safe_vk::DescriptorSetUpdateDetail::Image(Arc::new(safe_vk::ImageView::new(
+                target_image.clone(),
+            ))),
+        },
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 1,
+            detail: safe_vk::DescriptorSetUpdateDetail::AccelerationStructure(scene.tlas().clone()),
+        },
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 5,
+            detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
+                buffer: uniform_buffer.clone(),
+                offset: 0,
+            },
+        },
+    ]);
+    Arc::new(descriptor_set)
+}
+
+fn create_framebuffer(
+    render_pass: &Arc<safe_vk::RenderPass>,
+    target_image: &Arc<safe_vk::Image>,
+) -> Arc<safe_vk::Framebuffer> {
+    Arc::new(safe_vk::Framebuffer::new(
+        render_pass.clone(),
+        target_image.width(),
+        target_image.height(),
+        vec![Arc::new(safe_vk::ImageView::new(target_image.clone()))],
+    ))
+}
+
+/// A single subpass drawing over the tone mapped image, which stays in `GENERAL` layout.
+fn create_render_pass(device: Arc<safe_vk::Device>) -> Arc<safe_vk::RenderPass> {
+    Arc::new(safe_vk::RenderPass::new(
+        device,
+        &vk::RenderPassCreateInfo::builder()
+            .attachments(&[vk::AttachmentDescription::builder()
+                .format(TARGET_FORMAT)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .load_op(vk::AttachmentLoadOp::LOAD)
+                .store_op(vk::AttachmentStoreOp::STORE)
+                .initial_layout(vk::ImageLayout::GENERAL)
+                .final_layout(vk::ImageLayout::GENERAL)
+                .build()])
+            .subpasses(&[vk::SubpassDescription::builder()
+                .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+                .color_attachments(&[vk::AttachmentReference::builder()
+                    .attachment(0)
+                    .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                    .build()])
+                .build()])
+            .dependencies(&[
+                // The image written by the tone map and the views, and the camera update.
+                vk::SubpassDependency::builder()
+                    .src_subpass(vk::SUBPASS_EXTERNAL)
+                    .dst_subpass(0)
+                    .src_stage_mask(
+                        vk::PipelineStageFlags::TRANSFER | vk::PipelineStageFlags::COMPUTE_SHADER,
+                    )
+                    .src_access_mask(
+                        vk::AccessFlags::TRANSFER_WRITE | vk::AccessFlags::SHADER_WRITE,
+                    )
+                    .dst_stage_mask(
+                        vk::PipelineStageFlags::VERTEX_SHADER
+                            | vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                    )
+                    .dst_access_mask(
+                        vk::AccessFlags::UNIFORM_READ
+                            | vk::AccessFlags::COLOR_ATTACHMENT_READ
+                            | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                    )
+                    .build(),
+                vk::SubpassDependency::builder()
+                    .src_subpass(0)
+                    .dst_subpass(vk::SUBPASS_EXTERNAL)
+                    .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+                    .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                    .dst_stage_mask(vk::PipelineStageFlags::ALL_COMMANDS)
+                    .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::TRANSFER_READ)
+                    .build(),
+            ])
+            .build(),
+    ))
+}
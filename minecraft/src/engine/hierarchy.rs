@@ -0,0 +1,151 @@
+use glam::{Mat3, Mat4, Quat, Vec3};
+
+use super::scene::Scene;
+
+/// The glTF node tree of the scene and an inspector of the selected instance.
+///
+/// Only top level nodes with a mesh are rendered, as the instances of `Scene::instances`, so only
+/// they can be selected. The selection is shared with the object picker.
+#[derive(Default)]
+pub struct SceneHierarchy {
+    /// Index of the selected instance of `Scene::instances`.
+    pub selected: Option<usize>,
+}
+
+/// A new transform or visibility of the selected instance, see `Scene::set_instance`.
+pub struct InstanceEdit {
+    pub instance: usize,
+    pub transform: Mat4,
+    pub visible: bool,
+}
+
+impl SceneHierarchy {
+    /// Lists the nodes of the scene, clicking an instance selects it.
+    pub fn tree_ui(&mut self, ui: &mut egui::Ui, scene: &Scene) {
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            let document = scene.document();
+            for node in document.scenes().flat_map(|gltf_scene| gltf_scene.nodes()) {
+                self.node_ui(ui, scene, node, true);
+            }
+        });
+    }
+
+    fn node_ui(&mut self, ui: &mut egui::Ui, scene: &Scene, node: gltf::Node, top_level: bool) {
+        let name = match node.name() {
+            Some(name) => format!("{} {}", node.index(), name),
+            None => format!("Node {}", node.index()),
+        };
+        let instance = if top_level {
+            scene
+                .instances()
+                .iter()
+                .position(|instance| instance.node == node.index())
+        } else {
+            None
+        };
+        match instance {
+            Some(instance) => {
+                let label = if scene.instances()[instance].visible {
+                    egui::RichText::new(name)
+                } else {
+                    egui::RichText::new(name).weak()
+                };
+                if ui
+                    .selectable_label(self.selected == Some(instance), label)
+                    .clicked()
+                {
+                    self.selected = Some(instance);
+                }
+            }
+            None => {
+                ui.add_enabled(false, egui::Label::new(name))
+                    .on_disabled_hover_text("Only top level nodes with a mesh are rendered");
+            }
+        }
+        if node.children().next().is_some() {
+            ui.indent(node.index(), |ui| {
+                for child in node.children() {
+                    self.node_ui(ui, scene, child, false);
+                }
+            });
+        }
+    }
+
+    /// Shows the mesh, the materials, the transform and the visibility of the selected instance.
+    /// Returns the edit to apply to the scene, if any.
+    pub fn inspector_ui(&mut self, ui: &mut egui::Ui, scene: &Scene) -> Option<InstanceEdit> {
+        let index = match self.selected {
+            Some(index) => index,
+            None => {
+                ui.label("Click an object or a node of the hierarchy to select it");
+                return None;
+            }
+        };
+        let instance = &scene.instances()[index];
+        let document = scene.document();
+        let node = document.nodes().nth(instance.node).unwrap();
+        let mesh = document.meshes().nth(instance.mesh).unwrap();
+        ui.label(format!("Node: {}", node.name().unwrap_or("unnamed")));
+        ui.label(format!(
+            "Mesh: {} {}",
+            mesh.index(),
+            mesh.name().unwrap_or("unnamed")
+        ));
+        for (i, primitive) in mesh.primitives().enumerate() {
+            let material = primitive.material();
+            let material = match material.index() {
+                Some(index) => format!("{} {}", index, material.name().unwrap_or("unnamed")),
+                None => "default".to_owned(),
+            };
+            ui.label(format!("Geometry {}: material {}", i, material));
+        }
+        ui.separator();
+
+        let (scale, rotation, translation) = instance.transform.to_scale_rotation_translation();
+        let mut translation: [f32; 3] = translation.into();
+        let mut scale: [f32; 3] = scale.into();
+        let mut angles = ypr_degrees(rotation);
+        let mut visible = instance.visible;
+        let mut changed = false;
+        let mut vec3_ui = |ui: &mut egui::Ui, label: &str, value: &mut [f32; 3], speed: f32| {
+            ui.horizontal(|ui| {
+                ui.label(label);
+                for component in value.iter_mut() {
+                    changed |= ui
+                        .add(egui::DragValue::new(component).speed(speed))
+                        .changed();
+                }
+            });
+        };
+        vec3_ui(ui, "Translation", &mut translation, 0.1);
+        vec3_ui(ui, "Yaw Pitch Roll", &mut angles, 1.0);
+        vec3_ui(ui, "Scale", &mut scale, 0.01);
+        changed |= ui.checkbox(&mut visible, "Visible").changed();
+        if ui.button("Clear Selection").clicked() {
+            self.selected = None;
+        }
+        if !changed {
+            return None;
+        }
+        let [yaw, pitch, roll] = angles;
+        Some(InstanceEdit {
+            instance: index,
+            transform: Mat4::from_scale_rotation_translation(
+                Vec3::from(scale),
+                Quat::from_rotation_ypr(yaw.to_radians(), pitch.to_radians(), roll.to_radians()),
+                Vec3::from(translation),
+            ),
+            visible,
+        })
+    }
+}
+
+/// The angles `Quat::from_rotation_ypr` takes, in degrees.
+fn ypr_degrees(rotation: Quat) -> [f32; 3] {
+    // The rotation is Ry(yaw) * Rx(pitch) * Rz(roll).
+    let m = Mat3::from_quat(rotation);
+    let pitch = (-m.z_axis.y).max(-1.0).min(1.0).asin();
+    let yaw = m.z_axis.x.atan2(m.z_axis.z);
+    let roll = m.x_axis.y.atan2(m.y_axis.y);
+    [yaw.to_degrees(), pitch.to_degrees(), roll.to_degrees()]
+}
@@ -0,0 +1,557 @@
+use std::sync::Arc;
+
+use bytemuck::{Pod, Zeroable};
+use engine_core::environment::Environment;
+use safe_vk::{vk, ComputePipelineRecorder, GraphicsPipelineRecorder, Pipeline, PipelineRecorder};
+
+use super::raster;
+use super::scene::Scene;
+use super::shaders;
+
+const WORKGROUP_SIZE: u32 = 16;
+
+/// World space positions and normals, see hybrid_gbuffer.frag.
+const GBUFFER_FORMAT: vk::Format = vk::Format::R32G32B32A32_SFLOAT;
+const DEPTH_FORMAT: vk::Format = vk::Format::D32_SFLOAT;
+
+/// Matches `PushConsts` in raster.vert, raster_pulled.vert and hybrid_gbuffer.glsl.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct DrawPushConstants {
+    model: [f32; 16],
+    /// Index of the geometry drawn, for its material or the material of each of its faces.
+    geometry: u32,
+}
+
+/// Matches `PushConstants` in hybrid_shade.comp.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct ShadePushConstants {
+    sample_count: u32,
+    batch_sample_count: u32,
+    environment_rotation: f32,
+    environment_intensity: f32,
+    focus_probe_x: u32,
+    focus_probe_y: u32,
+    reflection_roughness: f32,
+}
+
+/// Rasterizes the first hits into a G-buffer and traces rays only for shadows and mirror
+/// reflections, which keeps larger scenes interactive where the path tracer can't.
+///
+/// The G-buffer pass draws every geometry of the scene with its material. hybrid_shade.comp then
+/// shades the visible surfaces with every light and an environment sample, each tested with a
+/// shadow ray query, and follows smooth surfaces into one reflection. There is no diffuse
+/// interreflection and adaptive sampling has no effect.
+pub struct Hybrid {
+    render_pass: Arc<safe_vk::RenderPass>,
+    gbuffer_pipeline: Arc<safe_vk::GraphicsPipeline>,
+    /// Whether the G-buffer pipeline reads the vertices itself, see `raster::pull_vertices`.
+    pull_vertices: bool,
+    shade_pipeline: Arc<safe_vk::ComputePipeline>,
+    /// A new scene or result image gets a new set while the frame in flight uses the old one.
+    /// Both pipelines use the same set.
+    descriptor_allocator: safe_vk::DescriptorAllocator,
+    descriptor_set: Arc<safe_vk::DescriptorSet>,
+    gbuffer: GBuffer,
+    uniform_buffer: Arc<safe_vk::Buffer>,
+    focus_probe_buffer: Arc<safe_vk::Buffer>,
+    extent: (u32, u32),
+    /// Surfaces up to this roughness get a ray traced mirror reflection.
+    pub reflection_roughness: f32,
+}
+
+/// The render targets, recreated with the result image.
+struct GBuffer {
+    position: Arc<safe_vk::Image>,
+    normal: Arc<safe_vk::Image>,
+    framebuffer: Arc<safe_vk::Framebuffer>,
+}
+
+impl Hybrid {
+    pub fn new(
+        allocator: Arc<safe_vk::Allocator>,
+        queue: &mut safe_vk::Queue,
+        command_pool: Arc<safe_vk::CommandPool>,
+        result_image: &Arc<safe_vk::Image>,
+        scene: &Scene,
+        environment: &Environment,
+        uniform_buffer: Arc<safe_vk::Buffer>,
+        focus_probe_buffer: Arc<safe_vk::Buffer>,
+    ) -> Self {
+        let device = allocator.device().clone();
+        let binding = |binding: u32, descriptor_type: safe_vk::DescriptorType| {
+            safe_vk::DescriptorSetLayoutBinding {
+                binding,
+                descriptor_type,
+                stage_flags: vk::ShaderStageFlags::COMPUTE,
+            }
+        };
+        let descriptor_set_layout = Arc::new(safe_vk::DescriptorSetLayout::new(
+            device.clone(),
+            Some("hybrid set layout"),
+            &[
+                binding(0, safe_vk::DescriptorType::StorageImage),
+                binding(1, safe_vk::DescriptorType::AccelerationStructure),
+                // The G-buffer pass reads face materials too, and pulled vertices.
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 2,
+                    descriptor_type: safe_vk::DescriptorType::StorageBuffer,
+                    stage_flags: vk::ShaderStageFlags::COMPUTE
+                        | vk::ShaderStageFlags::VERTEX
+                        | vk::ShaderStageFlags::FRAGMENT,
+                },
+                binding(4, safe_vk::DescriptorType::StorageBuffer),
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 5,
+                    descriptor_type: safe_vk::DescriptorType::UniformBuffer,
+                    stage_flags: vk::ShaderStageFlags::COMPUTE
+                        | vk::ShaderStageFlags::VERTEX
+                        | vk::ShaderStageFlags::FRAGMENT,
+                },
+                binding(6, safe_vk::DescriptorType::StorageBuffer),
+                binding(7, safe_vk::DescriptorType::StorageBuffer),
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 8,
+                    descriptor_type: safe_vk::DescriptorType::StorageBuffer,
+                    stage_flags: vk::ShaderStageFlags::COMPUTE | vk::ShaderStageFlags::FRAGMENT,
+                },
+                binding(9, safe_vk::DescriptorType::StorageImage),
+                binding(10, safe_vk::DescriptorType::StorageBuffer),
+                binding(20, safe_vk::DescriptorType::StorageImage),
+                binding(21, safe_vk::DescriptorType::StorageImage),
+                binding(26, safe_vk::DescriptorType::StorageImage),
+            ],
+        ));
+
+        let render_pass = create_render_pass(device.clone());
+
+        let gbuffer_pipeline_layout = Arc::new(safe_vk::PipelineLayout::new(
+            device.clone(),
+            Some("hybrid gbuffer pipeline layout"),
+            &[&descriptor_set_layout],
+            &[vk::PushConstantRange::builder()
+                .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
+                .offset(0)
+                .size(std::mem::size_of::<DrawPushConstants>() as u32)
+                .build()],
+        ));
+        let shader_stage = |shader: &str, stage: vk::ShaderStageFlags| {
+            Arc::new(safe_vk::ShaderStage::new(
+                Arc::new(safe_vk::ShaderModule::new(
+                    device.clone(),
+                    shaders::Shaders::get(shader).unwrap(),
+                )),
+                stage,
+                "main",
+            ))
+        };
+        let color_blend_attachment = vk::PipelineColorBlendAttachmentState::builder()
+            .blend_enable(false)
+            .color_write_mask(vk::ColorComponentFlags::all())
+            .build();
+        let pull_vertices = raster::pull_vertices(&device);
+        let gbuffer_pipeline = Arc::new(safe_vk::GraphicsPipeline::new(
+            Some("hybrid gbuffer pipeline"),
+            gbuffer_pipeline_layout,
+            if pull_vertices {
+                vec![
+                    shader_stage("raster_pulled.vert.spv", vk::ShaderStageFlags::VERTEX),
+                    shader_stage(
+                        "hybrid_gbuffer_pulled.frag.spv",
+                        vk::ShaderStageFlags::FRAGMENT,
+                    ),
+                ]
+            } else {
+                vec![
+                    shader_stage("raster.vert.spv", vk::ShaderStageFlags::VERTEX),
+                    shader_stage("hybrid_gbuffer.frag.spv", vk::ShaderStageFlags::FRAGMENT),
+                ]
+            },
+            render_pass.clone(),
+            &raster::vertex_input(pull_vertices),
+            &vk::PipelineInputAssemblyStateCreateInfo::builder()
+                .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+                .build(),
+            // The acceleration structures don't cull either.
+            &vk::PipelineRasterizationStateCreateInfo::builder()
+                .cull_mode(vk::CullModeFlags::NONE)
+                .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+                .polygon_mode(vk::PolygonMode::FILL)
+                .line_width(1.0)
+                .build(),
+            &vk::PipelineMultisampleStateCreateInfo::builder()
+                .rasterization_samples(vk::SampleCountFlags::TYPE_1)
+                .build(),
+            &vk::PipelineDepthStencilStateCreateInfo::builder()
+                .depth_test_enable(true)
+                .depth_write_enable(true)
+                .depth_compare_op(vk::CompareOp::LESS)
+                .build(),
+            &vk::PipelineColorBlendStateCreateInfo::builder()
+                .attachments(&[color_blend_attachment, color_blend_attachment])
+                .build(),
+            &vk::PipelineViewportStateCreateInfo::builder()
+                .viewport_count(1)
+                .scissor_count(1),
+            &vk::PipelineDynamicStateCreateInfo::builder()
+                .dynamic_states(&[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR])
+                .build(),
+        ));
+
+        let shade_pipeline_layout = Arc::new(safe_vk::PipelineLayout::new(
+            device.clone(),
+            Some("hybrid shade pipeline layout"),
+            &[&descriptor_set_layout],
+            &[vk::PushConstantRange::builder()
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .offset(0)
+                .size(std::mem::size_of::<ShadePushConstants>() as u32)
+                .build()],
+        ));
+        let shade_pipeline = Arc::new(safe_vk::ComputePipeline::new(
+            Some("hybrid shade pipeline"),
+            shade_pipeline_layout,
+            shader_stage("hybrid_shade.comp.spv", vk::ShaderStageFlags::COMPUTE),
+        ));
+
+        let gbuffer = GBuffer::new(
+            &allocator,
+            queue,
+            command_pool,
+            &render_pass,
+            result_image.width(),
+            result_image.height(),
+        );
+        let mut descriptor_allocator = safe_vk::DescriptorAllocator::new(descriptor_set_layout, 2);
+        let descriptor_set = Arc::new(descriptor_allocator.allocate(Some("hybrid descriptor set")));
+        let hybrid = Self {
+            render_pass,
+            gbuffer_pipeline,
+            pull_vertices,
+            shade_pipeline,
+            descriptor_allocator,
+            descriptor_set,
+            gbuffer,
+            uniform_buffer,
+            focus_probe_buffer,
+            extent: (result_image.width(), result_image.height()),
+            reflection_roughness: 0.1,
+        };
+        hybrid.update_descriptor_set(result_image, scene, environment);
+        hybrid
+    }
+
+    /// Recreates the G-buffer for a new result image.
+    pub fn resize(
+        &mut self,
+        allocator: &Arc<safe_vk::Allocator>,
+        queue: &mut safe_vk::Queue,
+        command_pool: Arc<safe_vk::CommandPool>,
+        result_image: &Arc<safe_vk::Image>,
+        scene: &Scene,
+        environment: &Environment,
+    ) {
+        self.gbuffer = GBuffer::new(
+            allocator,
+            queue,
+            command_pool,
+            &self.render_pass,
+            result_image.width(),
+            result_image.height(),
+        );
+        self.extent = (result_image.width(), result_image.height());
+        self.set_scene(result_image, scene, environment);
+    }
+
+    /// Points the passes at a new scene or environment.
+    pub fn set_scene(
+        &mut self,
+        result_image: &Arc<safe_vk::Image>,
+        scene: &Scene,
+        environment: &Environment,
+    ) {
+        self.descriptor_set = Arc::new(
+            self.descriptor_allocator
+                .allocate(Some("hybrid descriptor set")),
+        );
+        self.update_descriptor_set(result_image, scene, environment);
+    }
+
+    /// Records `batch_sample_count` samples of every pixel in place of the ray tracing dispatch,
+    /// with the settings raytrace.rgen would have used. The result image must be in `GENERAL`
+    /// layout.
+    pub(super) fn record(
+        &self,
+        recorder: &mut safe_vk::CommandRecorder,
+        scene: &Scene,
+        settings: &super::PushConstants,
+    ) {
+        let (width, height) = self.extent;
+        // The camera update of the frame.
+        recorder.memory_barrier(
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::PipelineStageFlags::VERTEX_SHADER
+                | vk::PipelineStageFlags::FRAGMENT_SHADER
+                | vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::UNIFORM_READ,
+        );
+
+        // A cleared position has w = 0, which marks the sky.
+        let clear_values = [
+            vk::ClearValue {
+                color: vk::ClearColorValue { float32: [0.0; 4] },
+            },
+            vk::ClearValue {
+                color: vk::ClearColorValue { float32: [0.0; 4] },
+            },
+            vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    depth: 1.0,
+                    stencil: 0,
+                },
+            },
+        ];
+        recorder.begin_render_pass(
+            self.render_pass.clone(),
+            self.gbuffer.framebuffer.clone(),
+            &clear_values,
+            |recorder| {
+                recorder.bind_graphics_pipeline(
+                    self.gbuffer_pipeline.clone(),
+                    |recorder, pipeline| {
+                        recorder.set_viewport(
+                            vk::Viewport::builder()
+                                .width(width as f32)
+                                .height(height as f32)
+                                .min_depth(0.0)
+                                .max_depth(1.0)
+                                .build(),
+                        );
+                        recorder.set_scissor(&[vk::Rect2D::builder()
+                            .extent(vk::Extent2D { width, height })
+                            .build()]);
+                        recorder.bind_descriptor_sets(
+                            vec![self.descriptor_set.clone()],
+                            pipeline.layout(),
+                            0,
+                        );
+                        for draw in scene.draws().iter().filter(|draw| draw.visible) {
+                            let push_constants = DrawPushConstants {
+                                model: draw.transform.to_cols_array(),
+                                geometry: draw.geometry,
+                            };
+                            recorder.push_constants(
+                                pipeline.layout(),
+                                vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                                0,
+                                bytemuck::bytes_of(&push_constants),
+                            );
+                            raster::draw_geometry(recorder, draw, self.pull_vertices);
+                        }
+                    },
+                );
+            },
+        );
+
+        let push_constants = ShadePushConstants {
+            sample_count: settings.sample_count,
+            batch_sample_count: settings.batch_sample_count,
+            environment_rotation: settings.environment_rotation,
+            environment_intensity: settings.environment_intensity,
+            focus_probe_x: settings.focus_probe_x,
+            focus_probe_y: settings.focus_probe_y,
+            reflection_roughness: self.reflection_roughness,
+        };
+        recorder.bind_compute_pipeline(self.shade_pipeline.clone(), |recorder, pipeline| {
+            recorder.bind_descriptor_sets(vec![self.descriptor_set.clone()], pipeline.layout(), 0);
+            recorder.push_constants(
+                pipeline.layout(),
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                bytemuck::bytes_of(&push_constants),
+            );
+            recorder.dispatch(
+                (width + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE,
+                (height + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE,
+                1,
+            );
+        });
+        recorder.memory_barrier(
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::SHADER_WRITE,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::AccessFlags::SHADER_READ | vk::AccessFlags::TRANSFER_READ,
+        );
+    }
+
+    fn update_descriptor_set(
+        &self,
+        result_image: &Arc<safe_vk::Image>,
+        scene: &Scene,
+        environment: &Environment,
+    ) {
+        let buffer =
+            |binding: u32, buffer: &Arc<safe_vk::Buffer>| safe_vk::DescriptorSetUpdateInfo {
+                binding,
+                detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
+                    buffer: buffer.clone(),
+                    offset: 0,
+                },
+            };
+        let image = |binding: u32, image: &Arc<safe_vk::Image>| safe_vk::DescriptorSetUpdateInfo {
+            binding,
+            detail: safe_vk::DescriptorSetUpdateDetail::Image(Arc::new(safe_vk::ImageView::new(
+                image.clone(),
+            ))),
+        };
+        self.descriptor_set.update(&[
+            image(0, result_image),
+            safe_vk::DescriptorSetUpdateInfo {
+                binding: 1,
+                detail: safe_vk::DescriptorSetUpdateDetail::AccelerationStructure(
+                    scene.tlas().clone(),
+                ),
+            },
+            buffer(2, scene.geometry_buffer()),
+            buffer(4, &self.focus_probe_buffer),
+            buffer(5, &self.uniform_buffer),
+            buffer(6, scene.light_buffer()),
+            buffer(7, scene.material_buffer()),
+            buffer(8, scene.geometry_material_buffer()),
+            image(9, environment.image()),
+            buffer(10, environment.cdf_buffer()),
+            image(20, &self.gbuffer.position),
+            image(21, &self.gbuffer.normal),
+            image(26, scene.atlas()),
+        ]);
+    }
+}
+
+impl GBuffer {
+    fn new(
+        allocator: &Arc<safe_vk::Allocator>,
+        queue: &mut safe_vk::Queue,
+        command_pool: Arc<safe_vk::CommandPool>,
+        render_pass: &Arc<safe_vk::RenderPass>,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let create_image = |name: &str, format: vk::Format, usage: vk::ImageUsageFlags| {
+            safe_vk::Image::new(
+                Some(name),
+                allocator.clone(),
+                format,
+                width,
+                height,
+                vk::ImageTiling::OPTIMAL,
+                usage,
+                safe_vk::MemoryUsage::GpuOnly,
+            )
+        };
+        let usage = vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::STORAGE;
+        let mut position = create_image("hybrid gbuffer position", GBUFFER_FORMAT, usage);
+        let mut normal = create_image("hybrid gbuffer normal", GBUFFER_FORMAT, usage);
+        // The layout the render pass leaves them in, which the descriptors are written with.
+        position.set_layout(vk::ImageLayout::GENERAL, queue, command_pool.clone());
+        normal.set_layout(vk::ImageLayout::GENERAL, queue, command_pool);
+        let depth = create_image(
+            "hybrid gbuffer depth",
+            DEPTH_FORMAT,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+        );
+
+        let position = Arc::new(position);
+        let normal = Arc::new(normal);
+        let framebuffer = Arc::new(safe_vk::Framebuffer::new(
+            render_pass.clone(),
+            width,
+            height,
+            vec![
+                Arc::new(safe_vk::ImageView::new(position.clone())),
+                Arc::new(safe_vk::ImageView::new(normal.clone())),
+                Arc::new(safe_vk::ImageView::new(Arc::new(depth))),
+            ],
+        ));
+        Self {
+            position,
+            normal,
+            framebuffer,
+        }
+    }
+}
+
+/// A single subpass writing the two G-buffer images, which end up in `GENERAL` layout for the
+/// shade pass, and depth.
+fn create_render_pass(device: Arc<safe_vk::Device>) -> Arc<safe_vk::RenderPass> {
+    let color_attachment = vk::AttachmentDescription::builder()
+        .format(GBUFFER_FORMAT)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .load_op(vk::AttachmentLoadOp::CLEAR)
+        .store_op(vk::AttachmentStoreOp::STORE)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+        .final_layout(vk::ImageLayout::GENERAL)
+        .build();
+    let color_reference = |attachment: u32| {
+        vk::AttachmentReference::builder()
+            .attachment(attachment)
+            .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .build()
+    };
+    Arc::new(safe_vk::RenderPass::new(
+        device,
+        &vk::RenderPassCreateInfo::builder()
+            .attachments(&[
+                color_attachment,
+                color_attachment,
+                vk::AttachmentDescription::builder()
+                    .format(DEPTH_FORMAT)
+                    .samples(vk::SampleCountFlags::TYPE_1)
+                    .load_op(vk::AttachmentLoadOp::CLEAR)
+                    .store_op(vk::AttachmentStoreOp::DONT_CARE)
+                    .initial_layout(vk::ImageLayout::UNDEFINED)
+                    .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+                    .build(),
+            ])
+            .subpasses(&[vk::SubpassDescription::builder()
+                .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+                .color_attachments(&[color_reference(0), color_reference(1)])
+                .depth_stencil_attachment(
+                    &vk::AttachmentReference::builder()
+                        .attachment(2)
+                        .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+                        .build(),
+                )
+                .build()])
+            .dependencies(&[
+                // The shade pass of the last frame is done reading before the images are cleared.
+                vk::SubpassDependency::builder()
+                    .src_subpass(vk::SUBPASS_EXTERNAL)
+                    .dst_subpass(0)
+                    .src_stage_mask(
+                        vk::PipelineStageFlags::COMPUTE_SHADER
+                            | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                    )
+                    .src_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+                    .dst_stage_mask(
+                        vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                            | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+                    )
+                    .dst_access_mask(
+                        vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                            | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                    )
+                    .build(),
+                vk::SubpassDependency::builder()
+                    .src_subpass(0)
+                    .dst_subpass(vk::SUBPASS_EXTERNAL)
+                    .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+                    .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                    .dst_stage_mask(vk::PipelineStageFlags::COMPUTE_SHADER)
+                    .dst_access_mask(vk::AccessFlags::SHADER_READ)
+                    .build(),
+            ])
+            .build(),
+    ))
+}
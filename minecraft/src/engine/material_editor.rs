@@ -0,0 +1,91 @@
+use safe_vk::vk;
+
+use super::scene::Scene;
+
+/// Edits the factors of one material of the scene at a time. Edits are written to the material
+/// buffer at the start of the next frame, so the renderers pick them up without reloading.
+#[derive(Default)]
+pub struct MaterialEditor {
+    /// Index into `Scene::materials`, follows the picked geometry.
+    pub selected: usize,
+    /// Materials edited since the last `record`.
+    edited: Vec<usize>,
+}
+
+impl MaterialEditor {
+    /// Shows the material picker and the factors of the selected material. Returns whether any
+    /// of them changed, which invalidates the accumulated samples.
+    pub fn ui(&mut self, ui: &mut egui::Ui, scene: &mut Scene) -> bool {
+        let material_count = scene.materials().len();
+        self.selected = self.selected.min(material_count - 1);
+        egui::ComboBox::from_label("Material")
+            .selected_text(scene.material_name(self.selected))
+            .show_ui(ui, |ui| {
+                for index in 0..material_count {
+                    ui.selectable_value(&mut self.selected, index, scene.material_name(index));
+                }
+            });
+
+        let material = scene.material_mut(self.selected);
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            let [r, g, b, a] = material.base_color;
+            let mut rgb = [r, g, b];
+            changed |= ui.color_edit_button_rgb(&mut rgb).changed();
+            material.base_color = [rgb[0], rgb[1], rgb[2], a];
+            ui.label("Base Color");
+        });
+        changed |= ui
+            .add(egui::Slider::new(&mut material.metallic, 0.0..=1.0).text("Metallic"))
+            .changed();
+        changed |= ui
+            .add(egui::Slider::new(&mut material.roughness, 0.0..=1.0).text("Roughness"))
+            .changed();
+        // Emission isn't limited to 1 like a color.
+        ui.horizontal(|ui| {
+            for component in material.emissive.iter_mut() {
+                changed |= ui
+                    .add(
+                        egui::DragValue::new(component)
+                            .speed(0.01)
+                            .clamp_range(0.0..=1000.0),
+                    )
+                    .changed();
+            }
+            ui.label("Emissive");
+        });
+        if material.texture != 0 {
+            ui.label(format!(
+                "Base color textured by atlas tile {}",
+                material.texture - 1
+            ));
+        }
+        if changed && !self.edited.contains(&self.selected) {
+            self.edited.push(self.selected);
+        }
+        changed
+    }
+
+    /// Writes the edited materials to the material buffer, before anything of the frame reads
+    /// it.
+    pub fn record(&mut self, recorder: &mut safe_vk::CommandRecorder, scene: &Scene) {
+        if self.edited.is_empty() {
+            return;
+        }
+        for index in self.edited.drain(..) {
+            scene.write_material(recorder, index);
+        }
+        recorder.memory_barrier(
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::AccessFlags::SHADER_READ,
+        );
+    }
+
+    /// Forgets the edits of a replaced scene.
+    pub fn set_scene(&mut self) {
+        self.selected = 0;
+        self.edited.clear();
+    }
+}
@@ -6,17 +6,77 @@ use std::str::FromStr;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
+use asset_cache::AssetCache;
 use bytemuck::cast_slice;
-use camera::{Camera, CameraUniform};
+use camera::{
+    Action, Binding, Camera, CameraMode, CameraPath, CameraPresets, CameraState, CameraUniform,
+};
 use image::ImageBuffer;
-use safe_vk::{vk, PipelineRecorder};
+use render_pass::tone_map::{ToneMap, ToneMapOperator};
+use safe_vk::vk;
+use settings::Settings;
 use vk::CommandBuffer;
 
 use bytemuck::{Pod, Zeroable};
 
+mod aov;
+mod debug_view;
+mod hierarchy;
+mod hybrid;
+mod material_editor;
+mod picking;
+mod raster;
+mod ray_tracing;
+mod restir;
 mod scene;
+mod watchdog;
+mod wavefront;
+mod world;
 
+use aov::Aov;
+use debug_view::DebugViews;
+use engine_core::adaptive::AdaptiveSampling;
+use engine_core::benchmark::{Benchmark, DeviceInfo};
+use engine_core::capture;
+use engine_core::environment::Environment;
+use engine_core::offline::{CompletionAction, OfflineRender};
+use hierarchy::SceneHierarchy;
+use material_editor::MaterialEditor;
+use picking::ObjectPicker;
+use raster::Raster;
+use ray_tracing::RayTracing;
 use scene::Scene;
+use world::World;
+
+use crate::Args;
+
+// Matches the vertical slope of 1/5 the scenes were originally framed with.
+const CAMERA_FOV: f32 = 22.62;
+
+/// Camera updates per second, renders in between interpolate.
+const UPDATE_RATE: f64 = 120.0;
+
+/// Frame rate cap offered when the cap is turned on.
+const DEFAULT_MAX_FPS: u32 = 60;
+
+/// Where camera presets were saved before they moved to the settings. Imported when the settings
+/// have none.
+const CAMERA_PRESETS_PATH: &str = "./minecraft/camera-presets.json";
+
+const DEFAULT_SCENE: &str = "./minecraft/models/basic-blocks/basic-blocks.gltf";
+
+const SCREENSHOT_DIR: &str = "./minecraft/screenshots";
+
+const CAPTURE_DIR: &str = "./minecraft/captures";
+
+/// Where the Camera menu saves the camera path, for `--benchmark`.
+const CAMERA_PATH_FILE: &str = "./minecraft/camera-path.json";
+
+/// Where benchmark reports go unless `--benchmark-report` says otherwise.
+const BENCHMARK_DIR: &str = "./minecraft/benchmarks";
+
+/// The GLSL sources of `shaders::Shaders`, watched for changes in debug builds.
+const SHADER_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/engine/shaders");
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Pod, Zeroable)]
@@ -25,6 +85,64 @@ struct PushConstants {
     render_height: u32,
     sample_count: u32,
     batch_sample_count: u32,
+    /// Samples a light at every diffuse vertex when non-zero.
+    nee_enabled: u32,
+    environment_rotation: f32,
+    environment_intensity: f32,
+    /// Writes the G-buffer and leaves the direct light of the first hit to the ReSTIR passes
+    /// when non-zero.
+    restir_enabled: u32,
+    /// The pixel raytrace.rgen probes the focus distance at, `u32::MAX` when not picking.
+    focus_probe_x: u32,
+    focus_probe_y: u32,
+    adaptive_enabled: u32,
+    adaptive_threshold: f32,
+    adaptive_min_samples: u32,
+    /// Paths end after this many bounces off surfaces.
+    max_bounces: u32,
+    /// Bounce from which paths are terminated at random, based on the light they carry.
+    russian_roulette_start: u32,
+    /// Writes the `Aovs` images when non-zero.
+    aov_enabled: u32,
+    /// The row of the result image the launch IDs of raytrace.rgen start at, see
+    /// `GpuWatchdog::dispatches`.
+    first_row: u32,
+}
+
+/// How the result image is rendered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Renderer {
+    /// Whole paths traced by raytrace.rgen.
+    PathTracer,
+    /// Paths traced in compute stages, see `Wavefront`.
+    Wavefront,
+    /// Rasterized first hits with ray traced shadows and reflections, see `Hybrid`.
+    Hybrid,
+    /// Forward rendering without ray tracing, see `Raster`.
+    Raster,
+}
+
+impl Renderer {
+    pub const ALL: [Renderer; 4] = [
+        Renderer::PathTracer,
+        Renderer::Wavefront,
+        Renderer::Hybrid,
+        Renderer::Raster,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Renderer::PathTracer => "Path Tracer",
+            Renderer::Wavefront => "Wavefront Path Tracer",
+            Renderer::Hybrid => "Hybrid Raster",
+            Renderer::Raster => "Raster",
+        }
+    }
+
+    /// Whether the renderer needs a device with the ray tracing extensions.
+    pub fn needs_ray_tracing(&self) -> bool {
+        *self != Renderer::Raster
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -35,159 +153,176 @@ struct FpsCounter {
 }
 
 pub struct Engine {
-    ui_platform: egui_winit_platform::Platform,
+    ui_platform: egui_backend::Platform,
+    ui_textures_delta: egui::TexturesDelta,
     size: winit::dpi::PhysicalSize<u32>,
     scale_factor: f64,
-    swapchain: Arc<safe_vk::Swapchain>,
+    target: engine_core::FrameTarget,
     queue: safe_vk::Queue,
     ui_pass: egui_backend::UiPass,
+    hdr_inspector: egui_backend::HdrInspector,
+    show_hdr_inspector: bool,
+    /// What the HDR inspector shows, the result image if `None`.
+    inspected_aov: Option<Aov>,
+    show_render_settings: bool,
     command_pool: Arc<safe_vk::CommandPool>,
     time: Instant,
-    swapchain_images: Vec<Arc<safe_vk::Image>>,
-    render_finish_semaphore: safe_vk::BinarySemaphore,
+    timestep: frame_loop::FixedTimestep,
+    /// The camera before the last update, renders interpolate from it.
+    previous_camera_state: CameraState,
+    frame_limiter: frame_loop::FrameLimiter,
     render_finish_fence: Arc<safe_vk::Fence>,
     allocator: Arc<safe_vk::Allocator>,
-    pipeline: Arc<safe_vk::RayTracingPipeline>,
-    descriptor_set: Arc<safe_vk::DescriptorSet>,
     result_image: Arc<safe_vk::Image>,
     tone_mapped_image: Arc<safe_vk::Image>,
+    tone_map: ToneMap,
+    adaptive_sampling: AdaptiveSampling,
+    debug_views: DebugViews,
+    offline_render: OfflineRender,
+    screenshot_requested: bool,
+    toasts: egui_backend::Toasts,
+    frame_capture: capture::FrameCapture,
     uniform_buffer: Arc<safe_vk::Buffer>,
+    /// Distance written by raytrace.rgen at the probed pixel.
+    focus_probe_buffer: Arc<safe_vk::Buffer>,
+    /// The next click on the image sets the focus distance.
+    picking_focus: bool,
+    /// `None` without ray tracing.
+    object_picker: Option<ObjectPicker>,
+    /// Whether the pick in flight edits a block of the world instead of selecting an object.
+    picking_block: bool,
+    hierarchy: SceneHierarchy,
+    show_hierarchy: bool,
+    show_selection: bool,
+    material_editor: MaterialEditor,
+    show_material_editor: bool,
+    cursor_position: winit::dpi::PhysicalPosition<f64>,
     camera: Camera,
+    camera_presets: CameraPresets,
+    camera_path: CameraPath,
     scene: Scene,
+    /// The file `scene` was loaded from.
+    scene_path: PathBuf,
+    /// Voxel terrain streamed around the camera into `scene`, `None` while turned off.
+    world: Option<World>,
+    /// Plays the camera path of `--benchmark`, the UI is hidden while it runs.
+    benchmark: Option<Benchmark>,
+    /// Written back on exit, unless headless.
+    settings: Settings,
+    headless: bool,
+    /// Decodes the textures of the scenes loaded.
+    jobs: jobs::JobSystem,
+    /// Decoded textures of earlier runs, `None` with `--no-asset-cache`.
+    asset_cache: Option<AssetCache>,
+    environment: Environment,
+    renderer: Renderer,
+    /// `None` on devices without the ray tracing extensions, which only have `raster`.
+    ray_tracing: Option<RayTracing>,
+    raster: Raster,
+    /// Transient images of the render graphs of earlier frames.
+    transient_images: safe_vk::TransientImagePool,
+    /// GPU times of the passes of the render graphs.
+    gpu_profiler: safe_vk::GpuProfiler,
+    /// Replaced scenes, dropped once the frame that last used them has finished.
+    retired_scenes: Vec<(Arc<safe_vk::Fence>, Scene)>,
     push_constants: PushConstants,
     fps_counter: FpsCounter,
+    frame_stats: egui_backend::FrameStats,
+    show_frame_stats: bool,
+    memory_panel: egui_backend::MemoryPanel,
+    show_memory_panel: bool,
+    log_console: egui_backend::LogConsole,
+    show_log_console: bool,
+    bindings_editor: egui_backend::BindingsEditor,
+    show_input_bindings: bool,
+    /// Recompiles the shaders when their sources change. Only in debug builds, where
+    /// `shaders::Shaders` reads the SPIR-V from disk instead of embedding it.
+    shader_watcher: Option<safe_vk::ShaderWatcher>,
+    /// Compiler output of the last shaders that failed to reload, shown until they compile.
+    shader_errors: Vec<String>,
     sample_speed: f64,
-    old_camera_position: glam::Vec3A,
+    old_camera_uniform: CameraUniform,
 }
 
 impl Engine {
-    pub fn new(window: &winit::window::Window) -> Self {
-        let size = window.inner_size();
-        let scale_factor = window.scale_factor();
-        let ui_platform =
-            egui_winit_platform::Platform::new(egui_winit_platform::PlatformDescriptor {
-                physical_width: size.width,
-                physical_height: size.height,
-                scale_factor,
-                font_definitions: Default::default(),
-                style: Default::default(),
-            });
-        let entry = Arc::new(safe_vk::Entry::new().unwrap());
-        #[cfg(target_os = "linux")]
-        let extensions = vec![
-            safe_vk::name::instance::Extension::KhrSurface,
-            safe_vk::name::instance::Extension::ExtDebugUtils,
-            safe_vk::name::instance::Extension::KhrXcbSurface,
-            safe_vk::name::instance::Extension::KhrXlibSurface,
-        ];
-        #[cfg(target_os = "windows")]
-        let extensions = vec![
-            safe_vk::name::instance::Extension::KhrSurface,
-            safe_vk::name::instance::Extension::ExtDebugUtils,
-            safe_vk::name::instance::Extension::KhrWin32Surface,
-        ];
-        let instance = Arc::new(safe_vk::Instance::new(
-            entry,
-            &[
-                safe_vk::name::instance::Layer::KhronosValidation,
-                safe_vk::name::instance::Layer::LunargMonitor,
-            ],
-            extensions.as_slice(),
-        ));
-        let surface = Arc::new(safe_vk::Surface::new(instance.clone(), window));
-
-        let pdevice = Arc::new(safe_vk::PhysicalDevice::new(
-            instance,
-            Some(surface.as_ref()),
-        ));
-        let device = Arc::new(safe_vk::Device::new(
-            pdevice,
-            &vk::PhysicalDeviceFeatures {
-                fragment_stores_and_atomics: vk::TRUE,
-                vertex_pipeline_stores_and_atomics: vk::TRUE,
-                ..Default::default()
-            },
-            &[
-                safe_vk::name::device::Extension::KhrSwapchain,
+    /// Renders to `window`, or offscreen at `size` without one.
+    pub fn new(
+        window: Option<&winit::window::Window>,
+        size: winit::dpi::PhysicalSize<u32>,
+        args: &Args,
+        settings: Settings,
+    ) -> Self {
+        let scale_factor = window.map_or(1.0, |window| window.scale_factor());
+        let ui_platform = engine_core::ui_platform(size, scale_factor);
+        let mut ray_tracing = false;
+        let descriptor = engine_core::GpuDescriptor {
+            device_index: args.device,
+            validation: !args.no_validation,
+            // Benchmarks measure the frame rate the device reaches, not the display.
+            present_mode: engine_core::present_mode(settings.vsync && args.benchmark.is_none()),
+        };
+        let request = |pdevice: &safe_vk::PhysicalDevice| {
+            let mut extensions = Vec::new();
+            // Only used for debug printf.
+            if pdevice
+                .supports_extensions(&[safe_vk::name::device::Extension::KhrShaderNonSemanticInfo])
+            {
+                extensions.push(safe_vk::name::device::Extension::KhrShaderNonSemanticInfo);
+            }
+            let ray_tracing_extensions = vec![
                 safe_vk::name::device::Extension::KhrAccelerationStructure,
                 safe_vk::name::device::Extension::KhrDeferredHostOperations,
-                safe_vk::name::device::Extension::KhrShaderNonSemanticInfo,
                 safe_vk::name::device::Extension::KhrRayTracingPipeline,
-            ],
-        ));
-        let swapchain = Arc::new(safe_vk::Swapchain::new(
-            device.clone(),
-            surface.clone(),
-            vk::PresentModeKHR::IMMEDIATE,
-        ));
-        let mut queue = safe_vk::Queue::new(device.clone());
-        let allocator = Arc::new(safe_vk::Allocator::new(device.clone()));
-        let ui_pass = egui_backend::UiPass::new(allocator.clone());
-        let command_pool = Arc::new(safe_vk::CommandPool::new(device.clone()));
-        let time = Instant::now();
-        let swapchain_images = safe_vk::Image::from_swapchain(swapchain.clone())
-            .into_iter()
-            .map(Arc::new)
-            .collect::<Vec<_>>();
-        let render_finish_semaphore = safe_vk::BinarySemaphore::new(device.clone());
-        let render_finish_fence = Arc::new(safe_vk::Fence::new(device.clone(), true));
-
-        let descriptor_set_layout = Arc::new(safe_vk::DescriptorSetLayout::new(
-            device.clone(),
-            Some("descriptor set layout"),
-            &[
-                safe_vk::DescriptorSetLayoutBinding {
-                    binding: 0,
-                    descriptor_type: safe_vk::DescriptorType::StorageImage,
-                    stage_flags: vk::ShaderStageFlags::RAYGEN_KHR,
-                },
-                safe_vk::DescriptorSetLayoutBinding {
-                    binding: 1,
-                    descriptor_type: safe_vk::DescriptorType::AccelerationStructure,
-                    stage_flags: vk::ShaderStageFlags::RAYGEN_KHR,
-                },
-                safe_vk::DescriptorSetLayoutBinding {
-                    binding: 2,
-                    descriptor_type: safe_vk::DescriptorType::StorageBuffer,
-                    stage_flags: vk::ShaderStageFlags::CLOSEST_HIT_KHR,
-                },
-                safe_vk::DescriptorSetLayoutBinding {
-                    binding: 3,
-                    descriptor_type: safe_vk::DescriptorType::StorageBuffer,
-                    stage_flags: vk::ShaderStageFlags::CLOSEST_HIT_KHR,
-                },
-                safe_vk::DescriptorSetLayoutBinding {
-                    binding: 4,
-                    descriptor_type: safe_vk::DescriptorType::StorageImage,
-                    stage_flags: vk::ShaderStageFlags::RAYGEN_KHR,
-                },
-                safe_vk::DescriptorSetLayoutBinding {
-                    binding: 5,
-                    descriptor_type: safe_vk::DescriptorType::UniformBuffer,
-                    stage_flags: vk::ShaderStageFlags::RAYGEN_KHR,
+                safe_vk::name::device::Extension::KhrRayQuery,
+            ];
+            ray_tracing =
+                !args.no_ray_tracing && pdevice.supports_extensions(&ray_tracing_extensions);
+            if ray_tracing {
+                extensions.extend(ray_tracing_extensions);
+            } else {
+                log::warn!("ray tracing is unavailable, falling back to rasterization");
+            }
+            engine_core::DeviceRequest {
+                extensions,
+                features: vk::PhysicalDeviceFeatures {
+                    fragment_stores_and_atomics: vk::TRUE,
+                    vertex_pipeline_stores_and_atomics: vk::TRUE,
+                    // gl_PrimitiveID in fragment shaders, for the materials of faces. The
+                    // raster passes pull their vertices without it, see
+                    // `raster::pull_vertices`.
+                    geometry_shader: pdevice.features().geometry_shader,
+                    // The vertex, triangle and fragment counts of the raster passes, shown
+                    // where the device can count them.
+                    pipeline_statistics_query: pdevice.features().pipeline_statistics_query,
+                    ..Default::default()
                 },
-            ],
-        ));
-
-        let pipeline_layout = Arc::new(safe_vk::PipelineLayout::new(
-            device.clone(),
-            Some("rt pipeline layout"),
-            &[&descriptor_set_layout],
-            &[vk::PushConstantRange::builder()
-                .offset(0)
-                .size(std::mem::size_of::<PushConstants>() as u32)
-                .stage_flags(vk::ShaderStageFlags::RAYGEN_KHR)
-                .build()],
-        ));
+            }
+        };
+        let engine_core::Gpu {
+            device,
+            allocator,
+            mut queue,
+            command_pool,
+            target,
+            render_finish_fence,
+        } = match window {
+            Some(window) => engine_core::Gpu::new(window, &descriptor, request),
+            None => engine_core::Gpu::headless(&descriptor, size, request),
+        }
+        .unwrap_or_else(|e| panic!("failed to create the device: {}", e));
+        let ui_pass = engine_core::ui_pass(allocator.clone(), &target, size, scale_factor);
+        let time = Instant::now();
 
         let mut result_image = safe_vk::Image::new(
             Some("result image"),
             allocator.clone(),
             vk::Format::R32G32B32A32_SFLOAT,
-            swapchain.width(),
-            swapchain.height(),
+            target.width(),
+            target.height(),
             vk::ImageTiling::OPTIMAL,
+            // Rendered to by `Raster`.
             vk::ImageUsageFlags::STORAGE
+                | vk::ImageUsageFlags::COLOR_ATTACHMENT
                 | vk::ImageUsageFlags::TRANSFER_DST
                 | vk::ImageUsageFlags::TRANSFER_SRC,
             safe_vk::MemoryUsage::GpuOnly,
@@ -197,10 +332,12 @@ impl Engine {
             Some("tone mapped image"),
             allocator.clone(),
             vk::Format::R32G32B32A32_SFLOAT,
-            swapchain.width(),
-            swapchain.height(),
+            target.width(),
+            target.height(),
             vk::ImageTiling::OPTIMAL,
+            // The bounding boxes of `DebugViews` are drawn to it.
             vk::ImageUsageFlags::STORAGE
+                | vk::ImageUsageFlags::COLOR_ATTACHMENT
                 | vk::ImageUsageFlags::TRANSFER_DST
                 | vk::ImageUsageFlags::TRANSFER_SRC,
             safe_vk::MemoryUsage::GpuOnly,
@@ -210,155 +347,135 @@ impl Engine {
         tone_mapped_image.set_layout(vk::ImageLayout::GENERAL, &mut queue, command_pool.clone());
 
         let result_image = Arc::new(result_image);
+        let mut hdr_inspector = egui_backend::HdrInspector::new(allocator.clone());
+        hdr_inspector.set_source(result_image.clone());
         let tone_mapped_image = Arc::new(tone_mapped_image);
+        let mut tone_map = ToneMap::new(device.clone());
+        tone_map.set_images(result_image.clone(), tone_mapped_image.clone());
+        if let Some(operator) = settings
+            .tone_map_operator
+            .as_deref()
+            .and_then(ToneMapOperator::from_name)
+        {
+            tone_map.operator = operator;
+        }
+        let adaptive_sampling = AdaptiveSampling::new(allocator.clone(), tone_mapped_image.clone());
 
-        let result_image_view = Arc::new(safe_vk::ImageView::new(result_image.clone()));
-        let tone_mapped_image_view = Arc::new(safe_vk::ImageView::new(tone_mapped_image.clone()));
-
-        let mut descriptor_set = safe_vk::DescriptorSet::new(
-            Some("Main descriptor set"),
-            Arc::new(safe_vk::DescriptorPool::new(
-                device.clone(),
-                &[vk::DescriptorPoolSize::builder()
-                    .ty(vk::DescriptorType::STORAGE_IMAGE)
-                    .descriptor_count(1)
-                    .build()],
-                1,
-            )),
-            descriptor_set_layout.clone(),
-        );
-
+        let jobs = jobs::JobSystem::with_default_threads();
+        let asset_cache = if args.no_asset_cache {
+            None
+        } else {
+            AssetCache::open_default()
+        };
+        // A last scene that has been moved or deleted since falls back to the default.
+        let scene_path = args
+            .scene
+            .clone()
+            .or_else(|| settings.last_scene.clone().filter(|path| path.exists()))
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_SCENE));
         let scene = Scene::from_file(
             allocator.clone(),
-            "./minecraft/models/basic-blocks/basic-blocks.gltf",
-        );
+            &scene_path,
+            ray_tracing,
+            &jobs,
+            asset_cache.as_ref(),
+        )
+        .unwrap_or_else(|e| {
+            panic!("failed to load {}: {}", scene_path.display(), e);
+        });
+        let environment = match &args.environment {
+            Some(path) => {
+                Environment::from_hdr(allocator.clone(), &mut queue, command_pool.clone(), path)
+                    .unwrap_or_else(|e| panic!("failed to load {}: {}", path.display(), e))
+            }
+            None => Environment::gradient_sky(allocator.clone(), &mut queue, command_pool.clone()),
+        };
 
         let uniform_buffer = Arc::new(safe_vk::Buffer::new(
             Some("camera buffer"),
             allocator.clone(),
-            std::mem::size_of::<f32>() * 3,
+            std::mem::size_of::<CameraUniform>(),
             vk::BufferUsageFlags::UNIFORM_BUFFER,
             safe_vk::MemoryUsage::CpuToGpu,
         ));
-
-        descriptor_set.update(&[
-            safe_vk::DescriptorSetUpdateInfo {
-                binding: 0,
-                detail: safe_vk::DescriptorSetUpdateDetail::Image(result_image_view.clone()),
-            },
-            safe_vk::DescriptorSetUpdateInfo {
-                binding: 1,
-                detail: safe_vk::DescriptorSetUpdateDetail::AccelerationStructure(
-                    scene.tlas().clone(),
-                ),
-            },
-            safe_vk::DescriptorSetUpdateInfo {
-                binding: 2,
-                detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
-                    buffer: scene.sole_buffer().clone(),
-                    offset: scene.sole_geometry_index_buffer_offset(),
-                },
-            },
-            safe_vk::DescriptorSetUpdateInfo {
-                binding: 3,
-                detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
-                    buffer: scene.sole_buffer().clone(),
-                    offset: scene.sole_geometry_vertex_buffer_offset(),
-                },
-            },
-            safe_vk::DescriptorSetUpdateInfo {
-                binding: 4,
-                detail: safe_vk::DescriptorSetUpdateDetail::Image(tone_mapped_image_view.clone()),
-            },
-            safe_vk::DescriptorSetUpdateInfo {
-                binding: 5,
-                detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
-                    buffer: uniform_buffer.clone(),
-                    offset: 0,
-                },
-            },
-        ]);
-
-        let descriptor_set = Arc::new(descriptor_set);
-
-        let shader_stages = vec![
-            Arc::new(safe_vk::ShaderStage::new(
-                Arc::new(safe_vk::ShaderModule::new(
-                    device.clone(),
-                    shaders::Shaders::get("raytrace.rgen.spv").unwrap(),
-                )),
-                vk::ShaderStageFlags::RAYGEN_KHR,
-                "main",
-            )),
-            Arc::new(safe_vk::ShaderStage::new(
-                Arc::new(safe_vk::ShaderModule::new(
-                    device.clone(),
-                    shaders::Shaders::get("miss.rmiss.spv").unwrap(),
-                )),
-                vk::ShaderStageFlags::MISS_KHR,
-                "main",
-            )),
-            Arc::new(safe_vk::ShaderStage::new(
-                Arc::new(safe_vk::ShaderModule::new(
-                    device.clone(),
-                    shaders::Shaders::get("closest_hit_0.rchit.spv").unwrap(),
-                )),
-                vk::ShaderStageFlags::CLOSEST_HIT_KHR,
-                "main",
-            )),
-            Arc::new(safe_vk::ShaderStage::new(
-                Arc::new(safe_vk::ShaderModule::new(
-                    device.clone(),
-                    shaders::Shaders::get("closest_hit_1.rchit.spv").unwrap(),
-                )),
-                vk::ShaderStageFlags::CLOSEST_HIT_KHR,
-                "main",
-            )),
-            Arc::new(safe_vk::ShaderStage::new(
-                Arc::new(safe_vk::ShaderModule::new(
-                    device.clone(),
-                    shaders::Shaders::get("closest_hit_2.rchit.spv").unwrap(),
-                )),
-                vk::ShaderStageFlags::CLOSEST_HIT_KHR,
-                "main",
-            )),
-            Arc::new(safe_vk::ShaderStage::new(
-                Arc::new(safe_vk::ShaderModule::new(
-                    device.clone(),
-                    shaders::Shaders::get("closest_hit_3.rchit.spv").unwrap(),
-                )),
-                vk::ShaderStageFlags::CLOSEST_HIT_KHR,
-                "main",
-            )),
-            Arc::new(safe_vk::ShaderStage::new(
-                Arc::new(safe_vk::ShaderModule::new(
-                    device.clone(),
-                    shaders::Shaders::get("closest_hit_4.rchit.spv").unwrap(),
-                )),
-                vk::ShaderStageFlags::CLOSEST_HIT_KHR,
-                "main",
-            )),
-        ];
-
-        let pipeline = Arc::new(safe_vk::RayTracingPipeline::new(
-            Some("rt pipeline"),
+        let focus_probe_buffer = Arc::new(safe_vk::Buffer::new_typed::<f32>(
+            Some("focus probe buffer"),
             allocator.clone(),
-            pipeline_layout,
-            shader_stages,
-            31,
-            &mut queue,
+            1,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            safe_vk::MemoryUsage::GpuToCpu,
         ));
+        let ray_tracing = if ray_tracing {
+            Some(RayTracing::new(
+                allocator.clone(),
+                &mut queue,
+                command_pool.clone(),
+                &result_image,
+                &scene,
+                &environment,
+                uniform_buffer.clone(),
+                focus_probe_buffer.clone(),
+                adaptive_sampling.moments_buffer(),
+            ))
+        } else {
+            None
+        };
+        let raster = Raster::new(
+            allocator.clone(),
+            &result_image,
+            &scene,
+            &environment,
+            uniform_buffer.clone(),
+        );
+        let object_picker = if ray_tracing.is_some() {
+            Some(ObjectPicker::new(
+                &allocator,
+                &scene,
+                uniform_buffer.clone(),
+            ))
+        } else {
+            None
+        };
+        let debug_views = DebugViews::new(
+            device.clone(),
+            &tone_mapped_image,
+            &scene,
+            uniform_buffer.clone(),
+            ray_tracing.is_some(),
+        );
 
-        let camera = camera::Camera::new(
+        let mut camera = camera::Camera::new(
             glam::Vec3A::new(-0.001, 0.0, 53.0),
             glam::Vec3A::new(0.0, 0.0, 0.0),
         );
+        camera.set_fov(CAMERA_FOV);
+        camera.set_aspect_ratio(size.width as f32 / size.height as f32);
+        camera.set_bindings(settings.input_bindings.clone());
+
+        let camera_presets = if settings.camera_presets.names().next().is_some() {
+            settings.camera_presets.clone()
+        } else {
+            CameraPresets::load(CAMERA_PRESETS_PATH).unwrap_or_default()
+        };
 
         let push_constants = PushConstants {
             render_width: size.width,
             render_height: size.height,
             sample_count: 0,
-            batch_sample_count: 1,
+            batch_sample_count: settings.batch_samples.min.max(1),
+            nee_enabled: 1,
+            environment_rotation: environment.rotation,
+            environment_intensity: environment.intensity,
+            restir_enabled: 0,
+            focus_probe_x: u32::MAX,
+            focus_probe_y: u32::MAX,
+            adaptive_enabled: adaptive_sampling.enabled as u32,
+            adaptive_threshold: adaptive_sampling.threshold,
+            adaptive_min_samples: adaptive_sampling.min_samples,
+            max_bounces: 31,
+            russian_roulette_start: 3,
+            aov_enabled: 0,
+            first_row: 0,
         };
 
         log::info!("pipeline created");
@@ -369,32 +486,118 @@ impl Engine {
             sampled_frames: 0,
         };
 
-        let old_camera_position = camera.position();
+        let old_camera_uniform = camera.camera_uniform();
+        let previous_camera_state = camera.state();
+
+        let shader_watcher = if cfg!(debug_assertions) && !args.headless {
+            engine_core::watch_shaders(SHADER_DIR)
+        } else {
+            None
+        };
+
+        let benchmark = args.benchmark.as_ref().map(|camera_path| {
+            let report_path = args.benchmark_report.clone().unwrap_or_else(|| {
+                PathBuf::from(BENCHMARK_DIR)
+                    .join(format!("benchmark-{}.json", capture::timestamp()))
+            });
+            Benchmark::new(camera_path, args.benchmark_duration, report_path)
+                .unwrap_or_else(|e| panic!("failed to load {}: {}", camera_path.display(), e))
+        });
+
+        let mut offline_render = OfflineRender::new();
+        if let Some(samples) = args.samples {
+            offline_render.target_sample_count = samples;
+        }
+        if let Some(output) = &args.output {
+            offline_render.file_template = output.clone();
+        }
+        if args.headless {
+            offline_render.completion_action = CompletionAction::SaveAndExit;
+            offline_render.start();
+        }
+        let transient_images = safe_vk::TransientImagePool::new(allocator.clone());
 
         Self {
             ui_platform,
+            ui_textures_delta: Default::default(),
             size,
             scale_factor,
-            swapchain,
+            target,
             queue,
             ui_pass,
+            hdr_inspector,
+            show_hdr_inspector: settings.window_open("HDR Inspector"),
+            inspected_aov: None,
+            show_render_settings: settings.window_open("Render Settings"),
             command_pool,
             time,
-            swapchain_images,
-            render_finish_semaphore,
+            timestep: frame_loop::FixedTimestep::new(UPDATE_RATE),
+            previous_camera_state,
+            frame_limiter: frame_loop::FrameLimiter::new(
+                if args.headless || args.benchmark.is_some() {
+                    None
+                } else {
+                    settings.max_fps.map(f64::from)
+                },
+            ),
             render_finish_fence,
             allocator,
-            pipeline,
-            descriptor_set,
             result_image,
             tone_mapped_image,
+            tone_map,
+            adaptive_sampling,
+            debug_views,
+            offline_render,
+            screenshot_requested: false,
+            toasts: egui_backend::Toasts::new(),
+            frame_capture: capture::FrameCapture::new(CAPTURE_DIR),
             uniform_buffer,
+            focus_probe_buffer,
+            picking_focus: false,
+            object_picker,
+            picking_block: false,
+            hierarchy: SceneHierarchy::default(),
+            show_hierarchy: settings.window_open("Scene Hierarchy"),
+            show_selection: settings.window_open("Selection"),
+            material_editor: MaterialEditor::default(),
+            show_material_editor: settings.window_open("Material Editor"),
+            cursor_position: Default::default(),
             camera,
+            camera_presets,
+            camera_path: CameraPath::new(),
             scene,
+            scene_path,
+            world: if args.world { Some(World::new()) } else { None },
+            settings,
+            headless: args.headless,
+            benchmark,
+            jobs,
+            asset_cache,
+            environment,
+            renderer: if ray_tracing.is_some() {
+                Renderer::PathTracer
+            } else {
+                Renderer::Raster
+            },
+            ray_tracing,
+            raster,
+            transient_images,
+            gpu_profiler: safe_vk::GpuProfiler::new(device.clone(), 16),
+            retired_scenes: Vec::new(),
             push_constants,
             fps_counter,
+            frame_stats: egui_backend::FrameStats::new(),
+            show_frame_stats: settings.window_open("Frame Times"),
+            memory_panel: egui_backend::MemoryPanel::new(),
+            show_memory_panel: settings.window_open("GPU Memory"),
+            log_console: egui_backend::LogConsole::new(),
+            show_log_console: settings.window_open("Log Console"),
+            bindings_editor: egui_backend::BindingsEditor::new(),
+            show_input_bindings: settings.window_open("Input Bindings"),
+            shader_watcher,
+            shader_errors: Vec::new(),
             sample_speed: 0.0,
-            old_camera_position,
+            old_camera_uniform,
         }
     }
 
@@ -430,19 +633,22 @@ impl Engine {
     fn resize(&mut self, new_size: &winit::dpi::PhysicalSize<u32>) {
         log::debug!("resizing");
         self.size = new_size.clone();
-        self.swapchain.renew();
-        self.swapchain_images = safe_vk::Image::from_swapchain(self.swapchain.clone())
-            .into_iter()
-            .map(Arc::new)
-            .collect::<Vec<_>>();
+        self.ui_pass
+            .set_screen_descriptor(engine_core::screen_descriptor(self.size, self.scale_factor));
+        // Minimized, keep everything as is until the window comes back.
+        if !self.target.resize(self.size) {
+            return;
+        }
+        self.transient_images.clear();
         let mut result_image = safe_vk::Image::new(
             Some("result image"),
             self.allocator.clone(),
             vk::Format::R32G32B32A32_SFLOAT,
-            self.swapchain.width(),
-            self.swapchain.height(),
+            self.target.width(),
+            self.target.height(),
             vk::ImageTiling::OPTIMAL,
             vk::ImageUsageFlags::STORAGE
+                | vk::ImageUsageFlags::COLOR_ATTACHMENT
                 | vk::ImageUsageFlags::TRANSFER_DST
                 | vk::ImageUsageFlags::TRANSFER_SRC,
             safe_vk::MemoryUsage::GpuOnly,
@@ -452,10 +658,11 @@ impl Engine {
             Some("result image"),
             self.allocator.clone(),
             vk::Format::R32G32B32A32_SFLOAT,
-            self.swapchain.width(),
-            self.swapchain.height(),
+            self.target.width(),
+            self.target.height(),
             vk::ImageTiling::OPTIMAL,
             vk::ImageUsageFlags::STORAGE
+                | vk::ImageUsageFlags::COLOR_ATTACHMENT
                 | vk::ImageUsageFlags::TRANSFER_DST
                 | vk::ImageUsageFlags::TRANSFER_SRC,
             safe_vk::MemoryUsage::GpuOnly,
@@ -475,27 +682,348 @@ impl Engine {
 
         self.result_image = Arc::new(result_image);
         self.tone_mapped_image = Arc::new(tone_mapped_image);
+        self.tone_map
+            .set_images(self.result_image.clone(), self.tone_mapped_image.clone());
+        self.adaptive_sampling
+            .resize(&self.allocator, self.tone_mapped_image.clone());
 
-        let result_image_view = Arc::new(safe_vk::ImageView::new(self.result_image.clone()));
-        let tone_mapped_image_view =
-            Arc::new(safe_vk::ImageView::new(self.tone_mapped_image.clone()));
-        self.descriptor_set.update(&[
-            safe_vk::DescriptorSetUpdateInfo {
-                binding: 0,
-                detail: safe_vk::DescriptorSetUpdateDetail::Image(result_image_view.clone()),
-            },
-            safe_vk::DescriptorSetUpdateInfo {
-                binding: 4,
-                detail: safe_vk::DescriptorSetUpdateDetail::Image(tone_mapped_image_view.clone()),
-            },
-        ]);
+        self.raster.resize(&self.allocator, &self.result_image);
+        self.debug_views
+            .resize(&self.tone_mapped_image, &self.scene);
+        if let Some(ray_tracing) = &mut self.ray_tracing {
+            ray_tracing.resize(
+                &self.allocator,
+                &mut self.queue,
+                self.command_pool.clone(),
+                &self.result_image,
+                &self.scene,
+                &self.environment,
+                self.adaptive_sampling.moments_buffer(),
+            );
+        }
+        self.hdr_inspector.set_source(self.inspector_source());
+
+        self.camera
+            .set_aspect_ratio(new_size.width as f32 / new_size.height as f32);
+
+        self.push_constants.sample_count = 0;
+    }
+
+    /// Replaces the scene. The old one is kept until the frame in flight is done with it.
+    fn load_scene(&mut self, path: PathBuf) {
+        let ray_tracing = self.ray_tracing.is_some();
+        let scene = match Scene::from_file(
+            self.allocator.clone(),
+            &path,
+            ray_tracing,
+            &self.jobs,
+            self.asset_cache.as_ref(),
+        ) {
+            Ok(scene) => scene,
+            Err(e) => {
+                log::warn!("failed to load {}: {}", path.display(), e);
+                self.toasts.add(format!("Failed to load {}: {}", path.display(), e));
+                return;
+            }
+        };
+        self.raster.set_scene(&scene, &self.environment);
+        self.debug_views.set_scene(&self.tone_mapped_image, &scene);
+        if let Some(object_picker) = &mut self.object_picker {
+            object_picker.set_scene(&scene);
+            object_picker.selection = None;
+        }
+        self.hierarchy.selected = None;
+        self.material_editor.set_scene();
+        if let Some(ray_tracing) = &mut self.ray_tracing {
+            ray_tracing.set_scene(
+                &self.result_image,
+                &scene,
+                &self.environment,
+                self.adaptive_sampling.moments_buffer(),
+            );
+        }
+        let old_scene = std::mem::replace(&mut self.scene, scene);
+        self.retired_scenes
+            .push((self.render_finish_fence.clone(), old_scene));
+        self.push_constants.sample_count = 0;
+        self.toasts.add(format!("Loaded {}", path.display()));
+        self.scene_path = path;
+        if let Some(world) = &mut self.world {
+            world.reset();
+        }
+        // Loading stalled the frame, the camera doesn't catch up on it.
+        self.timestep.reset();
+    }
+
+    /// Rebuilds the pipelines from the shaders the watcher recompiled, between two frames. Shaders
+    /// that fail to compile keep their last SPIR-V and show their errors instead.
+    fn reload_shaders(&mut self) {
+        let reload = match self
+            .shader_watcher
+            .as_mut()
+            .and_then(|watcher| watcher.poll())
+        {
+            Some(reload) => reload,
+            None => return,
+        };
+        for error in reload.errors.iter() {
+            log::error!("{}", error);
+        }
+        if !reload.errors.is_empty() {
+            self.toasts
+                .add("Shaders failed to compile, see Shader Errors");
+        }
+        self.shader_errors = reload.errors;
+        if reload.compiled.is_empty() {
+            return;
+        }
+
+        self.render_finish_fence.wait();
+        if let Some(old) = &self.ray_tracing {
+            let mut ray_tracing = RayTracing::new(
+                self.allocator.clone(),
+                &mut self.queue,
+                self.command_pool.clone(),
+                &self.result_image,
+                &self.scene,
+                &self.environment,
+                self.uniform_buffer.clone(),
+                self.focus_probe_buffer.clone(),
+                self.adaptive_sampling.moments_buffer(),
+            );
+            ray_tracing.restir.enabled = old.restir.enabled;
+            ray_tracing.restir.temporal = old.restir.temporal;
+            ray_tracing.restir.spatial = old.restir.spatial;
+            ray_tracing.hybrid.reflection_roughness = old.hybrid.reflection_roughness;
+            ray_tracing.aovs.enabled = old.aovs.enabled;
+            self.ray_tracing = Some(ray_tracing);
+        }
+        self.raster = Raster::new(
+            self.allocator.clone(),
+            &self.result_image,
+            &self.scene,
+            &self.environment,
+            self.uniform_buffer.clone(),
+        );
+        if let Some(old) = &self.object_picker {
+            let mut object_picker =
+                ObjectPicker::new(&self.allocator, &self.scene, self.uniform_buffer.clone());
+            object_picker.selection = old.selection;
+            self.object_picker = Some(object_picker);
+        }
+        let mut debug_views = DebugViews::new(
+            self.allocator.device().clone(),
+            &self.tone_mapped_image,
+            &self.scene,
+            self.uniform_buffer.clone(),
+            self.ray_tracing.is_some(),
+        );
+        debug_views.view = self.debug_views.view;
+        debug_views.max_triangle_tests = self.debug_views.max_triangle_tests;
+        debug_views.show_bounds = self.debug_views.show_bounds;
+        debug_views.selected = self.debug_views.selected;
+        self.debug_views = debug_views;
+        self.hdr_inspector.set_source(self.inspector_source());
+
+        log::info!("reloaded {}", reload.compiled.join(", "));
+        self.toasts
+            .add(format!("Reloaded {}", reload.compiled.join(", ")));
+        self.push_constants.sample_count = 0;
+    }
+
+    /// Moves or hides an instance. The frame in flight is waited for, as the top level
+    /// acceleration structure it uses is replaced.
+    fn edit_instance(&mut self, edit: hierarchy::InstanceEdit) {
+        self.render_finish_fence.wait();
+        self.scene
+            .set_instance(edit.instance, edit.transform, edit.visible);
+        self.set_scene_buffers();
+    }
+
+    /// Streams the chunks of the voxel world around the camera. The frame in flight is waited
+    /// for when chunks come or go, as the buffers and the top level acceleration structure it
+    /// uses are replaced.
+    fn update_world(&mut self) {
+        let world = match &mut self.world {
+            Some(world) => world,
+            None => return,
+        };
+        if !world.update(&self.jobs, &self.scene, self.camera.position().into()) {
+            return;
+        }
+        self.render_finish_fence.wait();
+        world.apply(&mut self.scene);
+        self.set_scene_buffers();
+    }
+
+    /// Turns the voxel world on or off, removing its chunks from the scene when off.
+    fn set_world_enabled(&mut self, enabled: bool) {
+        if enabled {
+            self.world.get_or_insert_with(World::new);
+            return;
+        }
+        if let Some(mut world) = self.world.take() {
+            self.render_finish_fence.wait();
+            world.remove_from(&mut self.scene);
+            self.set_scene_buffers();
+        }
+    }
+
+    /// Points the passes at the materials, geometries and top level acceleration structure of
+    /// the scene after they were replaced, and restarts accumulation.
+    fn set_scene_buffers(&mut self) {
+        self.raster.set_scene(&self.scene, &self.environment);
+        if self.ray_tracing.is_some() {
+            self.debug_views
+                .set_scene(&self.tone_mapped_image, &self.scene);
+            if let Some(object_picker) = &mut self.object_picker {
+                object_picker.set_scene(&self.scene);
+            }
+        }
+        if let Some(ray_tracing) = &mut self.ray_tracing {
+            ray_tracing.set_scene(
+                &self.result_image,
+                &self.scene,
+                &self.environment,
+                self.adaptive_sampling.moments_buffer(),
+            );
+        }
+        self.push_constants.sample_count = 0;
+    }
 
+    /// Replaces the environment map, keeping the rotation and intensity.
+    fn load_environment(&mut self, path: PathBuf) {
+        let mut environment = match Environment::from_hdr(
+            self.allocator.clone(),
+            &mut self.queue,
+            self.command_pool.clone(),
+            &path,
+        ) {
+            Ok(environment) => environment,
+            Err(e) => {
+                log::warn!("failed to load {}: {}", path.display(), e);
+                self.toasts
+                    .add(format!("Failed to load {}: {}", path.display(), e));
+                return;
+            }
+        };
+        environment.rotation = self.environment.rotation;
+        environment.intensity = self.environment.intensity;
+        self.environment = environment;
+        // The sets of the frame in flight keep the old map alive.
+        self.raster.set_scene(&self.scene, &self.environment);
+        if let Some(ray_tracing) = &mut self.ray_tracing {
+            ray_tracing.set_scene(
+                &self.result_image,
+                &self.scene,
+                &self.environment,
+                self.adaptive_sampling.moments_buffer(),
+            );
+        }
         self.push_constants.sample_count = 0;
+        self.toasts.add(format!("Loaded {}", path.display()));
+    }
+
+    /// The image the HDR inspector shows, see `inspected_aov`.
+    fn inspector_source(&self) -> Arc<safe_vk::Image> {
+        match (self.inspected_aov, &self.ray_tracing) {
+            (Some(aov), Some(ray_tracing)) => ray_tracing.aovs.image(aov).clone(),
+            _ => self.result_image.clone(),
+        }
+    }
+
+    fn benchmark_device_info(&self) -> DeviceInfo {
+        let pdevice = self.allocator.device().pdevice();
+        let properties = pdevice.properties();
+        DeviceInfo {
+            name: pdevice.name(),
+            driver_version: properties.driver_version,
+            api_version: properties.api_version,
+            renderer: self.renderer.name(),
+            width: self.size.width,
+            height: self.size.height,
+        }
+    }
+
+    /// The code to exit with, once an offline render that exits when done has finished, or a
+    /// benchmark.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.offline_render
+            .exit_code()
+            .or_else(|| self.benchmark.as_ref().and_then(Benchmark::exit_code))
+    }
+
+    /// Writes the window size, the scene, the tone map operator, the camera presets, the input
+    /// bindings and the open windows to the settings file. Headless runs and benchmarks leave it
+    /// as is.
+    pub fn save_settings(&mut self) {
+        if self.headless || self.benchmark.is_some() {
+            return;
+        }
+        // A minimized window reopens at its size before.
+        if self.size.width > 0 && self.size.height > 0 {
+            self.settings.window_size = [self.size.width, self.size.height];
+        }
+        self.settings.last_scene = Some(self.scene_path.clone());
+        self.settings.tone_map_operator = Some(self.tone_map.operator.name().to_owned());
+        self.settings.camera_presets = self.camera_presets.clone();
+        self.settings.input_bindings = self.camera.bindings().clone();
+        let open_windows = [
+            ("HDR Inspector", self.show_hdr_inspector),
+            ("Render Settings", self.show_render_settings),
+            ("Scene Hierarchy", self.show_hierarchy),
+            ("Selection", self.show_selection),
+            ("Material Editor", self.show_material_editor),
+            ("Frame Times", self.show_frame_stats),
+            ("GPU Memory", self.show_memory_panel),
+            ("Log Console", self.show_log_console),
+            ("Input Bindings", self.show_input_bindings),
+        ];
+        for (title, open) in open_windows.iter() {
+            self.settings.set_window_open(title, *open);
+        }
+        if let Err(e) = self.settings.save(crate::SETTINGS_APP) {
+            log::warn!("failed to save settings: {}", e);
+        }
+    }
+
+    /// Focuses on the surface probed by the last frame. The sky leaves the focus as is.
+    fn read_focus_probe(&mut self) {
+        let distance = self.focus_probe_buffer.read::<f32>(0, 1)[0];
+        if distance > 0.0 {
+            self.camera.set_focus_distance(distance);
+        }
+        self.push_constants.focus_probe_x = u32::MAX;
+        self.push_constants.focus_probe_y = u32::MAX;
+    }
+
+    fn save_screenshot(&mut self) {
+        let result = capture::save_screenshot(
+            SCREENSHOT_DIR,
+            &mut self.queue,
+            self.command_pool.clone(),
+            self.allocator.clone(),
+            self.tone_mapped_image.clone(),
+        );
+        match result {
+            Ok(path) => self.toasts.add(format!("Saved {}", path.display())),
+            Err(e) => {
+                log::warn!("failed to save screenshot: {}", e);
+                self.toasts.add(format!("Failed to save screenshot: {}", e));
+            }
+        }
     }
 
     pub fn handle_event(&mut self, event: &winit::event::Event<()>) {
         self.ui_platform.handle_event(event);
-        self.camera.input(event);
+        // A key or button bound in the bindings editor does nothing else.
+        let captured = camera::CameraInput::from_winit(event).map_or(false, |input| {
+            self.bindings_editor
+                .capture(self.camera.bindings_mut(), &input)
+        });
+        if !captured {
+            self.camera.input(event);
+        }
         match event {
             winit::event::Event::NewEvents(_) => {}
             winit::event::Event::WindowEvent { window_id, event } => {
@@ -515,13 +1043,27 @@ impl Engine {
                         device_id,
                         input,
                         is_synthetic,
-                    } => {}
+                    } => {
+                        let key = input.virtual_keycode.and_then(camera::Key::from_winit);
+                        if input.state == winit::event::ElementState::Pressed
+                            && !self.bindings_editor.is_capturing()
+                            && key.map_or(false, |key| {
+                                self.camera
+                                    .bindings()
+                                    .is_bound(Action::Screenshot, Binding::Key(key))
+                            })
+                        {
+                            self.screenshot_requested = true;
+                        }
+                    }
                     winit::event::WindowEvent::ModifiersChanged(_) => {}
                     winit::event::WindowEvent::CursorMoved {
                         device_id,
                         position,
                         modifiers,
-                    } => {}
+                    } => {
+                        self.cursor_position = *position;
+                    }
                     winit::event::WindowEvent::CursorEntered { device_id } => {}
                     winit::event::WindowEvent::CursorLeft { device_id } => {}
                     winit::event::WindowEvent::MouseWheel {
@@ -535,7 +1077,27 @@ impl Engine {
                         state,
                         button,
                         modifiers,
-                    } => {}
+                    } => {
+                        if *state == winit::event::ElementState::Pressed
+                            && *button == winit::event::MouseButton::Left
+                            && !self.ui_platform.context().wants_pointer_input()
+                        {
+                            let x = self.cursor_position.x as u32;
+                            let y = self.cursor_position.y as u32;
+                            if self.picking_focus {
+                                self.picking_focus = false;
+                                self.push_constants.focus_probe_x = x;
+                                self.push_constants.focus_probe_y = y;
+                            } else if let Some(object_picker) = &mut self.object_picker {
+                                object_picker.request(x, y);
+                                if self.world.as_ref().map_or(false, World::edits_blocks) {
+                                    self.picking_block = true;
+                                } else {
+                                    self.show_selection = true;
+                                }
+                            }
+                        }
+                    }
                     winit::event::WindowEvent::TouchpadPressure {
                         device_id,
                         pressure,
@@ -550,7 +1112,10 @@ impl Engine {
                     winit::event::WindowEvent::ScaleFactorChanged {
                         scale_factor,
                         new_inner_size,
-                    } => {}
+                    } => {
+                        self.scale_factor = *scale_factor;
+                        self.resize(new_inner_size);
+                    }
                     winit::event::WindowEvent::ThemeChanged(_) => {}
                 }
             }
@@ -566,6 +1131,8 @@ impl Engine {
     }
 
     pub fn update(&mut self) {
+        self.frame_stats.begin_frame();
+        self.reload_shaders();
         let current_dir = PathBuf::from_str(std::env::current_dir().unwrap().to_str().unwrap())
             .unwrap()
             .join("models\\2.0\\Box\\glTF");
@@ -573,208 +1140,842 @@ impl Engine {
             .update_time(self.time.elapsed().as_secs_f64());
         self.ui_platform.begin_frame();
 
-        egui::TopPanel::top(egui::Id::new("menu bar")).show(&self.ui_platform.context(), |ui| {
+        egui::TopBottomPanel::top("menu bar").show(&self.ui_platform.context(), |ui| {
             egui::menu::bar(ui, |ui| {
-                egui::menu::menu(ui, "File", |ui| {
-                    if ui.button("Open").clicked {
+                ui.menu_button("File", |ui| {
+                    if ui.button("Open").clicked() {
                         match nfd2::open_file_dialog(Some("gltf,glb"), Some(current_dir.as_ref()))
                             .unwrap()
                         {
-                            nfd2::Response::Okay(p) => {}
+                            nfd2::Response::Okay(p) => self.load_scene(p),
                             nfd2::Response::OkayMultiple(_) => {}
                             nfd2::Response::Cancel => {}
                         }
                     }
+                    if ui.button("Screenshot").clicked() {
+                        self.screenshot_requested = true;
+                    }
                 });
-                ui.label(format!("FPS: {:.1}", self.fps_counter.fps));
-                ui.label(format!("Samples: {}", self.push_constants.sample_count));
+                ui.menu_button("View", |ui| {
+                    let mut orbit = self.camera.mode() == CameraMode::Orbit;
+                    if ui.checkbox(&mut orbit, "Orbit Camera").clicked() {
+                        self.camera.set_mode(if orbit {
+                            CameraMode::Orbit
+                        } else {
+                            CameraMode::Fly
+                        });
+                    }
+                    ui.checkbox(&mut self.show_hdr_inspector, "HDR Inspector");
+                    ui.checkbox(&mut self.show_memory_panel, "GPU Memory");
+                    ui.checkbox(&mut self.show_log_console, "Log Console");
+                    ui.checkbox(&mut self.show_input_bindings, "Input Bindings");
+                    ui.checkbox(&mut self.show_hierarchy, "Scene Hierarchy");
+                    ui.checkbox(&mut self.show_selection, "Selection");
+                    ui.checkbox(&mut self.show_material_editor, "Material Editor");
+                    ui.checkbox(&mut self.settings.vsync, "VSync")
+                        .on_hover_text("Applies on the next start");
+                    ui.horizontal(|ui| {
+                        let mut capped = self.settings.max_fps.is_some();
+                        ui.checkbox(&mut capped, "Frame Rate Cap");
+                        let mut max_fps = self.settings.max_fps.unwrap_or(DEFAULT_MAX_FPS);
+                        ui.add_enabled(
+                            capped,
+                            egui::DragValue::new(&mut max_fps)
+                                .clamp_range(10..=1000)
+                                .suffix(" FPS"),
+                        );
+                        let max_fps = Some(max_fps).filter(|_| capped);
+                        if max_fps != self.settings.max_fps {
+                            self.settings.max_fps = max_fps;
+                            self.frame_limiter.set_max_fps(max_fps.map(f64::from));
+                        }
+                    });
+                    if ui.button("Save Camera").clicked() {
+                        let name = format!("View {}", self.camera_presets.names().count() + 1);
+                        self.camera_presets.insert(&name, self.camera.state());
+                    }
+                    let mut aperture = self.camera.aperture();
+                    ui.add(egui::Slider::new(&mut aperture, 0.0..=5.0).text("Aperture"));
+                    self.camera.set_aperture(aperture);
+                    let mut focus_distance = self.camera.focus_distance();
+                    ui.add(
+                        egui::Slider::new(&mut focus_distance, 0.1..=200.0).text("Focus Distance"),
+                    );
+                    self.camera.set_focus_distance(focus_distance);
+                    // Raster has neither depth of field nor the focus probe.
+                    if ui
+                        .add_enabled(
+                            self.renderer.needs_ray_tracing(),
+                            egui::Button::new("Pick Focus"),
+                        )
+                        .clicked()
+                    {
+                        self.picking_focus = true;
+                        self.toasts.add("Click the image to focus on it");
+                    }
+                    ui.separator();
+                    if ui.button("Add Path Keyframe").clicked() {
+                        let time = if self.camera_path.keyframes().is_empty() {
+                            0.0
+                        } else {
+                            self.camera_path.duration() + 2.0
+                        };
+                        self.camera_path.add_keyframe(time, self.camera.state());
+                    }
+                    let play_label = if self.camera_path.is_playing() {
+                        "Pause Path"
+                    } else {
+                        "Play Path"
+                    };
+                    if ui.button(play_label).clicked() {
+                        if self.camera_path.is_playing() {
+                            self.camera_path.pause();
+                        } else {
+                            self.camera_path.play();
+                        }
+                    }
+                    if ui.button("Clear Path").clicked() {
+                        self.camera_path.clear();
+                    }
+                    if ui.button("Save Path").clicked() {
+                        match self.camera_path.save(CAMERA_PATH_FILE) {
+                            Ok(()) => self.toasts.add(format!("Saved {}", CAMERA_PATH_FILE)),
+                            Err(e) => {
+                                log::warn!("failed to save {}: {}", CAMERA_PATH_FILE, e);
+                                self.toasts
+                                    .add(format!("Failed to save {}: {}", CAMERA_PATH_FILE, e));
+                            }
+                        }
+                    }
+                    if ui.button("Load Path").clicked() {
+                        match CameraPath::load(CAMERA_PATH_FILE) {
+                            Ok(camera_path) => self.camera_path = camera_path,
+                            Err(e) => {
+                                log::warn!("failed to load {}: {}", CAMERA_PATH_FILE, e);
+                                self.toasts
+                                    .add(format!("Failed to load {}: {}", CAMERA_PATH_FILE, e));
+                            }
+                        }
+                    }
+                    ui.separator();
+                    let names: Vec<String> =
+                        self.camera_presets.names().map(str::to_owned).collect();
+                    for name in names {
+                        if ui.button(&name).clicked() {
+                            let state = *self.camera_presets.get(&name).unwrap();
+                            self.camera.set_state(&state);
+                        }
+                    }
+                });
+                ui.menu_button("Tone Mapping", |ui| {
+                    for operator in ToneMapOperator::ALL.iter() {
+                        ui.radio_value(&mut self.tone_map.operator, *operator, operator.name());
+                    }
+                    ui.separator();
+                    let mut exposure = self.camera.exposure();
+                    ui.add(egui::Slider::new(&mut exposure, -8.0..=8.0).text("Exposure"));
+                    self.camera.set_exposure(exposure);
+                    ui.add(
+                        egui::Slider::new(&mut self.tone_map.white_point, 1.0..=20.0)
+                            .text("White Point"),
+                    );
+                });
+                ui.menu_button("Environment", |ui| {
+                    if ui.button("Open").clicked() {
+                        match nfd2::open_file_dialog(Some("hdr"), None).unwrap() {
+                            nfd2::Response::Okay(p) => self.load_environment(p),
+                            nfd2::Response::OkayMultiple(_) => {}
+                            nfd2::Response::Cancel => {}
+                        }
+                    }
+                    let mut rotation = self.environment.rotation.to_degrees();
+                    let rotation_changed = ui
+                        .add(egui::Slider::new(&mut rotation, -180.0..=180.0).text("Rotation"))
+                        .changed();
+                    let intensity_changed = ui
+                        .add(
+                            egui::Slider::new(&mut self.environment.intensity, 0.0..=10.0)
+                                .text("Intensity"),
+                        )
+                        .changed();
+                    if rotation_changed || intensity_changed {
+                        self.environment.rotation = rotation.to_radians();
+                        self.push_constants.sample_count = 0;
+                    }
+                });
+                ui.menu_button("World", |ui| {
+                    let mut enabled = self.world.is_some();
+                    if ui.checkbox(&mut enabled, "Voxel World").changed() {
+                        self.set_world_enabled(enabled);
+                    }
+                    if let Some(world) = &mut self.world {
+                        world.ui(ui);
+                    }
+                });
+                ui.menu_button("Render", |ui| {
+                    ui.checkbox(&mut self.show_render_settings, "Render Settings");
+                    let mut nee_enabled = self.push_constants.nee_enabled != 0;
+                    if ui
+                        .checkbox(&mut nee_enabled, "Next-Event Estimation")
+                        .changed()
+                    {
+                        self.push_constants.nee_enabled = nee_enabled as u32;
+                        self.push_constants.sample_count = 0;
+                    }
+                    ui.separator();
+                    let mut changed = false;
+                    let ray_tracing = self.ray_tracing.is_some();
+                    for renderer in Renderer::ALL.iter() {
+                        ui.add_enabled_ui(ray_tracing || !renderer.needs_ray_tracing(), |ui| {
+                            changed |= ui
+                                .radio_value(&mut self.renderer, *renderer, renderer.name())
+                                .on_disabled_hover_text("The device doesn't support ray tracing")
+                                .changed();
+                        });
+                    }
+                    if let Some(ray_tracing) = &mut self.ray_tracing {
+                        // Only raytrace.rgen writes the G-buffer the ReSTIR passes read.
+                        let path_tracer = self.renderer == Renderer::PathTracer;
+                        if !path_tracer {
+                            ray_tracing.restir.enabled = false;
+                        }
+                        let restir = &mut ray_tracing.restir;
+                        ui.add_enabled_ui(path_tracer, |ui| {
+                            changed |= ui
+                                .checkbox(&mut restir.enabled, "ReSTIR Direct Lighting")
+                                .changed();
+                        });
+                        ui.add_enabled_ui(restir.enabled, |ui| {
+                            changed |= ui
+                                .checkbox(&mut restir.temporal, "Temporal Reuse")
+                                .changed();
+                            changed |= ui.checkbox(&mut restir.spatial, "Spatial Reuse").changed();
+                        });
+                        self.push_constants.restir_enabled = restir.enabled as u32;
+                        // Like the G-buffer, only raytrace.rgen writes the AOVs.
+                        let aovs = &mut ray_tracing.aovs;
+                        if !path_tracer {
+                            aovs.enabled = false;
+                        }
+                        ui.add_enabled_ui(path_tracer, |ui| {
+                            changed |= ui
+                                .checkbox(&mut aovs.enabled, "AOV Outputs")
+                                .on_hover_text(
+                                    "Albedo, normal, depth and object ID images for the HDR \
+                                     Inspector",
+                                )
+                                .changed();
+                        });
+                        self.push_constants.aov_enabled = aovs.enabled as u32;
+                    }
+                    if changed {
+                        self.push_constants.sample_count = 0;
+                    }
+                    ui.separator();
+                    ui.checkbox(&mut self.adaptive_sampling.enabled, "Adaptive Sampling");
+                    ui.add_enabled_ui(self.adaptive_sampling.enabled, |ui| {
+                        ui.add(
+                            egui::Slider::new(&mut self.adaptive_sampling.threshold, 0.001..=0.2)
+                                .logarithmic(true)
+                                .text("Error Threshold"),
+                        );
+                        ui.add(
+                            egui::Slider::new(&mut self.adaptive_sampling.min_samples, 2..=1024)
+                                .logarithmic(true)
+                                .text("Min Samples"),
+                        );
+                    });
+                    ui.checkbox(&mut self.adaptive_sampling.show_heatmap, "Sample Heatmap");
+                    ui.menu_button("Debug Views", |ui| self.debug_views.ui(ui));
+                    ui.separator();
+                    self.offline_render.ui(ui, self.push_constants.sample_count);
+                    ui.separator();
+                    self.frame_capture.ui(ui);
+                });
+                let fps = format!("FPS: {:.1}", self.fps_counter.fps);
+                if ui
+                    .selectable_label(self.show_frame_stats, fps)
+                    .on_hover_text("Frame Times")
+                    .clicked()
+                {
+                    self.show_frame_stats = !self.show_frame_stats;
+                }
+                if self.offline_render.is_active() {
+                    ui.label(format!(
+                        "Rendering: {} / {}",
+                        self.push_constants.sample_count, self.offline_render.target_sample_count
+                    ));
+                } else {
+                    ui.label(format!("Samples: {}", self.push_constants.sample_count));
+                }
                 ui.label(format!("Sample Speed: {:.1}", self.sample_speed));
+                ui.label(format!(
+                    "Batch: {} spp",
+                    self.push_constants.batch_sample_count
+                ));
+                let ui_stats = self.ui_pass.stats();
+                ui.label(format!(
+                    "UI: {} draws, {} vertices, {} texture uploads",
+                    ui_stats.draw_calls, ui_stats.vertices, ui_stats.texture_uploads
+                ));
+                if let Some(gpu_time) = ui_stats.gpu_time {
+                    ui.label(format!(
+                        "UI GPU time: {:.2} ms",
+                        gpu_time.as_secs_f64() * 1e3
+                    ));
+                }
             });
         });
 
-        let (_, shapes) = self.ui_platform.end_frame();
-        let paint_jobs = self.ui_platform.context().tessellate(shapes);
-        self.ui_pass.update_buffers(
-            &paint_jobs,
-            &egui_backend::ScreenDescriptor {
-                physical_width: self.size.width,
-                physical_height: self.size.height,
-                scale_factor: self.scale_factor as f32,
-            },
-        );
-        self.ui_pass
-            .update_texture(&self.ui_platform.context().texture());
+        // Without AOV outputs there is only the result image to inspect.
+        let aovs_enabled =
+            matches!(&self.ray_tracing, Some(ray_tracing) if ray_tracing.aovs.enabled);
+        if !aovs_enabled && self.inspected_aov.is_some() {
+            self.inspected_aov = None;
+            self.hdr_inspector.set_source(self.result_image.clone());
+        }
+        let mut inspected_aov = self.inspected_aov;
+        let hdr_inspector = &mut self.hdr_inspector;
+        egui::Window::new("HDR Inspector")
+            .open(&mut self.show_hdr_inspector)
+            .show(&self.ui_platform.context(), |ui| {
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut inspected_aov, None, "Radiance");
+                    ui.add_enabled_ui(aovs_enabled, |ui| {
+                        for aov in Aov::ALL.iter() {
+                            ui.radio_value(&mut inspected_aov, Some(*aov), aov.name())
+                                .on_disabled_hover_text("Enable AOV Outputs in the Render menu");
+                        }
+                    });
+                });
+                hdr_inspector.ui(ui);
+            });
+        if inspected_aov != self.inspected_aov {
+            self.inspected_aov = inspected_aov;
+            self.hdr_inspector.set_source(self.inspector_source());
+        }
+        let push_constants = &mut self.push_constants;
+        let ray_tracing = &mut self.ray_tracing;
+        let raster = &self.raster;
+        let batch_samples = &mut self.settings.batch_samples;
+        egui::Window::new("Render Settings")
+            .open(&mut self.show_render_settings)
+            .show(&self.ui_platform.context(), |ui| {
+                let mut changed = ui
+                    .add(
+                        egui::Slider::new(&mut push_constants.max_bounces, 0..=64)
+                            .text("Max Bounces"),
+                    )
+                    .changed();
+                changed |= ui
+                    .add(
+                        egui::Slider::new(&mut push_constants.russian_roulette_start, 1..=64)
+                            .text("Russian Roulette Start"),
+                    )
+                    .on_hover_text("The first bounce that may terminate paths at random")
+                    .changed();
+                if let Some(ray_tracing) = ray_tracing {
+                    changed |= ui
+                        .add(
+                            egui::Slider::new(
+                                &mut ray_tracing.hybrid.reflection_roughness,
+                                0.0..=1.0,
+                            )
+                            .text("Reflection Roughness"),
+                        )
+                        .on_hover_text("Hybrid Raster reflects surfaces up to this roughness")
+                        .changed();
+                    ray_tracing.watchdog.ui(ui);
+                }
+                ui.collapsing("Raster Statistics", |ui| raster.ui(ui));
+                if changed {
+                    push_constants.sample_count = 0;
+                }
+                ui.separator();
+                ui.label("Batch Samples").on_hover_text(
+                    "Samples per pixel traced each frame. Offline renders trace their own batches",
+                );
+                let mut fixed = batch_samples.fixed.is_some();
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut fixed, "Fixed").on_hover_text(
+                        "Traces the same batch every frame, whatever the frame rate",
+                    );
+                    let mut fixed_count = batch_samples
+                        .fixed
+                        .unwrap_or(push_constants.batch_sample_count);
+                    ui.add_enabled(
+                        fixed,
+                        egui::DragValue::new(&mut fixed_count)
+                            .clamp_range(1..=4096)
+                            .suffix(" spp"),
+                    );
+                    batch_samples.fixed = Some(fixed_count).filter(|_| fixed);
+                });
+                ui.add_enabled_ui(!fixed, |ui| {
+                    ui.add(
+                        egui::Slider::new(&mut batch_samples.target_fps, 10.0..=500.0)
+                            .text("Target FPS"),
+                    )
+                    .on_hover_text("Batches double well above this rate and halve well below");
+                    ui.add(
+                        egui::Slider::new(&mut batch_samples.min, 1..=4096)
+                            .logarithmic(true)
+                            .text("Min"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut batch_samples.max, 1..=4096)
+                            .logarithmic(true)
+                            .text("Max"),
+                    );
+                });
+                batch_samples.max = batch_samples.max.max(batch_samples.min);
+            });
+        let hierarchy = &mut self.hierarchy;
+        let scene = &self.scene;
+        egui::Window::new("Scene Hierarchy")
+            .open(&mut self.show_hierarchy)
+            .show(&self.ui_platform.context(), |ui| {
+                hierarchy.tree_ui(ui, scene)
+            });
+        let object_picker = &self.object_picker;
+        let mut instance_edit = None;
+        egui::Window::new("Selection")
+            .open(&mut self.show_selection)
+            .show(&self.ui_platform.context(), |ui| {
+                instance_edit = hierarchy.inspector_ui(ui, scene);
+                if let Some(object_picker) = object_picker {
+                    object_picker.ui(ui);
+                }
+            });
+        if let Some(edit) = instance_edit {
+            self.edit_instance(edit);
+        }
+        let material_editor = &mut self.material_editor;
+        let scene = &mut self.scene;
+        let mut material_changed = false;
+        egui::Window::new("Material Editor")
+            .open(&mut self.show_material_editor)
+            .show(&self.ui_platform.context(), |ui| {
+                material_changed = material_editor.ui(ui, scene);
+            });
+        if material_changed {
+            self.push_constants.sample_count = 0;
+        }
+        // The details of a pick only apply while its instance stays selected.
+        if let Some(object_picker) = &mut self.object_picker {
+            let picked = object_picker.selection.map(|pick| pick.instance as usize);
+            if picked != self.hierarchy.selected {
+                object_picker.selection = None;
+            }
+        }
+        self.debug_views.selected = self.hierarchy.selected;
+        let frame_stats = &self.frame_stats;
+        let gpu_profiler = &self.gpu_profiler;
+        egui::Window::new("Frame Times")
+            .open(&mut self.show_frame_stats)
+            .show(&self.ui_platform.context(), |ui| {
+                frame_stats.ui(ui);
+                ui.separator();
+                if !gpu_profiler.is_supported() {
+                    ui.label("The device can't time passes");
+                    return;
+                }
+                egui::Grid::new("gpu pass times").show(ui, |ui| {
+                    for (pass, milliseconds) in gpu_profiler.timings() {
+                        ui.label(pass.as_str());
+                        ui.label(format!("{:.2} ms", milliseconds));
+                        ui.end_row();
+                    }
+                });
+            });
+        let memory_panel = &mut self.memory_panel;
+        let allocator = &self.allocator;
+        egui::Window::new("GPU Memory")
+            .open(&mut self.show_memory_panel)
+            .show(&self.ui_platform.context(), |ui| {
+                memory_panel.ui(ui, allocator)
+            });
+        // Drained while hidden too, so it shows the latest records when opened.
+        self.log_console.update();
+        let log_console = &mut self.log_console;
+        egui::Window::new("Log Console")
+            .open(&mut self.show_log_console)
+            .default_size([600.0, 300.0])
+            .show(&self.ui_platform.context(), |ui| log_console.ui(ui));
+        let bindings_editor = &mut self.bindings_editor;
+        let bindings = self.camera.bindings_mut();
+        egui::Window::new("Input Bindings")
+            .open(&mut self.show_input_bindings)
+            .show(&self.ui_platform.context(), |ui| {
+                bindings_editor.ui(ui, bindings)
+            });
+        let mut show_shader_errors = !self.shader_errors.is_empty();
+        let shader_errors = &self.shader_errors;
+        egui::Window::new("Shader Errors")
+            .open(&mut show_shader_errors)
+            .default_size([600.0, 300.0])
+            .show(&self.ui_platform.context(), |ui| {
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    for error in shader_errors.iter() {
+                        ui.label(
+                            egui::RichText::new(error)
+                                .monospace()
+                                .color(egui::Color32::RED),
+                        );
+                    }
+                });
+            });
+        if !show_shader_errors {
+            self.shader_errors.clear();
+        }
+        self.toasts.show(&self.ui_platform.context());
+
+        let dt = self.timestep.step_secs();
+        for _ in 0..self.timestep.tick() {
+            self.previous_camera_state = self.camera.state();
+            if let Some(state) = self.camera_path.advance(dt) {
+                self.camera.set_state(&state);
+            }
+            self.camera.update(dt);
+        }
+        if let Some(state) = self.benchmark.as_mut().and_then(Benchmark::camera_state) {
+            self.camera.set_state(&state);
+            self.previous_camera_state = state;
+        }
+        self.tone_map.exposure = self.camera.exposure();
+        self.push_constants.environment_rotation = self.environment.rotation;
+        self.push_constants.environment_intensity = self.environment.intensity;
+        self.push_constants.adaptive_enabled = self.adaptive_sampling.enabled as u32;
+        self.push_constants.adaptive_threshold = self.adaptive_sampling.threshold;
+        self.push_constants.adaptive_min_samples = self.adaptive_sampling.min_samples;
+
+        let full_output = self.ui_platform.end_frame();
+        let paint_jobs = if self.benchmark.is_some() {
+            Vec::new()
+        } else {
+            self.ui_platform.context().tessellate(full_output.shapes)
+        };
+        self.ui_pass.update_buffers(&paint_jobs);
+        // Frames skipped while minimized leave their texture changes for the next render.
+        self.ui_textures_delta.append(full_output.textures_delta);
+        self.frame_stats.span("Update");
+        self.update_world();
+        self.frame_stats.span("World");
 
         // self.uniform_buffer.copy_from(bytemuck::cast_slice(
         //     self.camera.camera_uniform().origin.as_ref(),
         // ));
 
-        if !self
-            .old_camera_position
-            .abs_diff_eq(self.camera.position(), std::f32::EPSILON)
-        {
-            println!("here");
+        let camera_uniform = self.camera_uniform();
+        if bytemuck::bytes_of(&camera_uniform) != bytemuck::bytes_of(&self.old_camera_uniform) {
             self.push_constants.sample_count = 0;
-            self.old_camera_position = self.camera.position();
+            self.old_camera_uniform = camera_uniform;
         }
     }
 
+    /// The camera between the last two updates, as far as the time since the last one.
+    fn camera_uniform(&self) -> CameraUniform {
+        self.camera
+            .interpolated_uniform(&self.previous_camera_state, self.timestep.alpha())
+    }
+
     pub fn render(&mut self) {
-        let (index, _) = self.swapchain.acquire_next_image();
+        if self.target.is_zero_sized() {
+            return;
+        }
+        if self.offline_render.is_active() {
+            self.push_constants.batch_sample_count = self
+                .offline_render
+                .next_batch_sample_count(self.push_constants.sample_count);
+        } else if self.settings.batch_samples.fixed.is_some() {
+            // Fixed batches apply right away, adapted ones once the frame rate is measured.
+            self.push_constants.batch_sample_count = self
+                .settings
+                .batch_samples
+                .next(self.push_constants.batch_sample_count, self.fps_counter.fps);
+        }
+        let (index, target_image) = match self.target.acquire() {
+            Some(acquired) => acquired,
+            None => {
+                self.resize(&self.size.clone());
+                return;
+            }
+        };
+        self.frame_stats.span("Acquire");
         let mut command_buffer = safe_vk::CommandBuffer::new(self.command_pool.clone());
 
-        let target_image = self.swapchain_images[index as usize].clone();
-
-        let start_address = self.pipeline.sbt_buffer().device_address();
-        let stride = self.pipeline.sbt_stride() as u64;
-        let sbt_ray_gen_region = vk::StridedDeviceAddressRegionKHR::builder()
-            .device_address(start_address)
-            .stride(stride)
-            .size(stride)
-            .build();
-        let mut sbt_hit_region = sbt_ray_gen_region;
-        sbt_hit_region.size = stride;
-        sbt_hit_region.device_address = start_address + 2 * stride;
-        let mut sbt_miss_region = sbt_ray_gen_region;
-        sbt_miss_region.size = stride;
-        sbt_miss_region.device_address = start_address + stride;
-
-        let mut sbt_callable_region = sbt_ray_gen_region;
-        sbt_callable_region.size = 0;
-
-        command_buffer.encode(|recorder| {
-            recorder.update_buffer(
-                self.uniform_buffer.clone(),
-                0,
-                bytemuck::cast_slice(&[self.camera.camera_uniform()]),
-            );
-            // recorder.bind_compute_pipeline(self.pipeline.clone(), |rec, pipeline| {
-            //     rec.bind_descriptor_sets(vec![self.descriptor_set.clone()], pipeline.layout(), 0);
-
-            //     rec.dispatch(
-            //         (WIDTH as f32 / WORKGROUP_WIDTH as f32).ceil() as u32,
-            //         (HEIGHT as f32 / WORKGROUP_HEIGHT as f32).ceil() as u32,
-            //         1,
-            //     );
-            // });
-            recorder.set_image_layout(
-                self.result_image.clone(),
-                Some(vk::ImageLayout::UNDEFINED),
-                vk::ImageLayout::GENERAL,
-            );
-            recorder.bind_ray_tracing_pipeline(self.pipeline.clone(), |rec, pipeline| {
-                rec.bind_descriptor_sets(vec![self.descriptor_set.clone()], pipeline.layout(), 0);
-                rec.push_constants(
-                    pipeline.layout(),
-                    vk::ShaderStageFlags::RAYGEN_KHR,
+        let camera_uniform = self.camera_uniform();
+        let trace_stage = match &self.ray_tracing {
+            Some(_) if self.renderer.needs_ray_tracing() => {
+                vk::PipelineStageFlags::RAY_TRACING_SHADER_KHR
+                    | vk::PipelineStageFlags::COMPUTE_SHADER
+            }
+            _ => {
+                vk::PipelineStageFlags::FRAGMENT_SHADER
+                    | vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+            }
+        };
+        let Self {
+            ref mut ui_pass,
+            ref ui_textures_delta,
+            ref mut hdr_inspector,
+            show_hdr_inspector,
+            ref uniform_buffer,
+            ref mut material_editor,
+            ref scene,
+            ref result_image,
+            ref tone_mapped_image,
+            ref mut tone_map,
+            ref adaptive_sampling,
+            ref debug_views,
+            ref mut ray_tracing,
+            ref mut raster,
+            ref mut object_picker,
+            ref mut transient_images,
+            ref mut gpu_profiler,
+            renderer,
+            ref push_constants,
+            ..
+        } = *self;
+        let mut graph = safe_vk::RenderGraph::new(transient_images);
+        graph.profile(gpu_profiler);
+        // The accumulated samples live in their own buffer, the images are written anew.
+        let result = graph.import_image(result_image.clone(), Some(vk::ImageLayout::UNDEFINED));
+        let tone_mapped =
+            graph.import_image(tone_mapped_image.clone(), Some(vk::ImageLayout::UNDEFINED));
+        let target = graph.import_image(target_image, Some(vk::ImageLayout::UNDEFINED));
+
+        graph.add_pass(
+            "upload",
+            |_| {},
+            |recorder, _| {
+                recorder.update_buffer(
+                    uniform_buffer.clone(),
                     0,
-                    bytemuck::cast_slice(&[self.push_constants]),
+                    bytemuck::cast_slice(&[camera_uniform]),
+                );
+                material_editor.record(recorder, scene);
+            },
+        );
+        graph.add_pass(
+            "render",
+            |pass| {
+                pass.write(result, safe_vk::ImageAccess::Storage(trace_stage));
+            },
+            |recorder, images| {
+                let result_image = images.get(result);
+                match ray_tracing {
+                    Some(ray_tracing) if renderer.needs_ray_tracing() => ray_tracing.record(
+                        recorder,
+                        renderer,
+                        result_image,
+                        scene,
+                        &camera_uniform,
+                        push_constants,
+                    ),
+                    _ => raster.record(recorder, scene, push_constants),
+                }
+                if let Some(object_picker) = object_picker {
+                    object_picker.record(recorder, (result_image.width(), result_image.height()));
+                }
+            },
+        );
+        graph.add_pass(
+            "post",
+            |pass| {
+                pass.read(
+                    result,
+                    safe_vk::ImageAccess::Storage(vk::PipelineStageFlags::COMPUTE_SHADER),
+                )
+                .write(
+                    tone_mapped,
+                    safe_vk::ImageAccess::Storage(
+                        vk::PipelineStageFlags::COMPUTE_SHADER
+                            | vk::PipelineStageFlags::FRAGMENT_SHADER
+                            | vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                    ),
                 );
-                rec.trace_ray(
-                    &sbt_ray_gen_region,
-                    &sbt_miss_region,
-                    &sbt_hit_region,
-                    &sbt_callable_region,
-                    self.result_image.width(),
-                    self.result_image.height(),
-                    1,
+            },
+            |recorder, _| {
+                tone_map.record(recorder);
+                adaptive_sampling.record_heatmap(
+                    recorder,
+                    push_constants.sample_count + push_constants.batch_sample_count,
                 );
-            });
-            recorder.set_image_layout(
+                debug_views.record(recorder, scene);
+            },
+        );
+        graph.add_pass(
+            "blit",
+            |pass| {
+                pass.read(tone_mapped, safe_vk::ImageAccess::TransferSrc)
+                    .write(target, safe_vk::ImageAccess::TransferDst);
+            },
+            |recorder, images| {
+                let (tone_mapped_image, target_image) =
+                    (images.get(tone_mapped), images.get(target));
+                recorder.blit_image(
+                    tone_mapped_image.clone(),
+                    target_image.clone(),
+                    &[vk::ImageBlit::builder()
+                        .src_subresource(
+                            vk::ImageSubresourceLayers::builder()
+                                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                                .layer_count(1)
+                                .base_array_layer(0)
+                                .mip_level(0)
+                                .build(),
+                        )
+                        .src_offsets([
+                            vk::Offset3D { x: 0, y: 0, z: 0 },
+                            vk::Offset3D {
+                                x: tone_mapped_image.width() as i32,
+                                y: tone_mapped_image.height() as i32,
+                                z: 1,
+                            },
+                        ])
+                        .dst_offsets([
+                            vk::Offset3D { x: 0, y: 0, z: 0 },
+                            vk::Offset3D {
+                                x: target_image.width() as i32,
+                                y: target_image.height() as i32,
+                                z: 1,
+                            },
+                        ])
+                        .dst_subresource(
+                            vk::ImageSubresourceLayers::builder()
+                                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                                .layer_count(1)
+                                .base_array_layer(0)
+                                .mip_level(0)
+                                .build(),
+                        )
+                        .build()],
+                    vk::Filter::NEAREST,
+                );
+            },
+        );
+        // The HDR inspector converts the result for display before the UI draws it.
+        let ui_reads = if show_hdr_inspector {
+            vec![(
+                result,
+                safe_vk::ImageAccess::Storage(vk::PipelineStageFlags::COMPUTE_SHADER),
+            )]
+        } else {
+            Vec::new()
+        };
+        ui_pass.add_to_graph(
+            &mut graph,
+            target,
+            &ui_reads,
+            ui_textures_delta,
+            |recorder, ui_pass| {
+                if show_hdr_inspector {
+                    hdr_inspector.record(recorder, ui_pass);
+                }
+            },
+        );
+        command_buffer.encode(|recorder| graph.execute(recorder));
+        self.frame_stats.span("Record");
+        self.render_finish_fence.wait();
+        self.gpu_profiler.frame_finished();
+        self.ui_pass.frame_finished();
+        self.raster.frame_finished();
+        if let Some(ray_tracing) = &mut self.ray_tracing {
+            ray_tracing.watchdog.frame_finished();
+        }
+        self.frame_stats.span("GPU Wait");
+        self.render_finish_fence = self.target.submit(&mut self.queue, command_buffer);
+        self.ui_pass.free_textures(&std::mem::take(&mut self.ui_textures_delta));
+        self.retired_scenes
+            .retain(|(fence, _)| !fence.is_signaled());
+        self.target.present(&self.queue, index);
+        self.frame_stats.span("Submit");
+
+        self.push_constants.sample_count += self.push_constants.batch_sample_count;
+        let benchmark_done = match &mut self.benchmark {
+            Some(benchmark) if benchmark.exit_code().is_none() => {
+                benchmark.record_frame(self.push_constants.batch_sample_count, &self.allocator);
+                benchmark.is_done()
+            }
+            _ => false,
+        };
+        if benchmark_done {
+            let device = self.benchmark_device_info();
+            if let Some(benchmark) = &mut self.benchmark {
+                benchmark.finish(device, &self.allocator);
+            }
+        }
+        if self.offline_render.is_due(self.push_constants.sample_count) {
+            self.render_finish_fence.wait();
+            self.offline_render.write(
+                &mut self.queue,
+                self.command_pool.clone(),
+                self.allocator.clone(),
                 self.result_image.clone(),
-                Some(vk::ImageLayout::GENERAL),
-                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
-            );
-            recorder.set_image_layout(
-                target_image.clone(),
-                Some(vk::ImageLayout::UNDEFINED),
-                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-            );
-            // recorder.copy_buffer_to_image(
-            //     self.storage_buffer.clone(),
-            //     self.result_image.clone(),
-            //     &[vk::BufferImageCopy::builder()
-            //         .image_extent(vk::Extent3D {
-            //             width: self.result_image.width(),
-            //             height: self.result_image.height(),
-            //             depth: 1,
-            //         })
-            //         .image_subresource(
-            //             vk::ImageSubresourceLayers::builder()
-            //                 .aspect_mask(vk::ImageAspectFlags::COLOR)
-            //                 .layer_count(1)
-            //                 .base_array_layer(0)
-            //                 .mip_level(0)
-            //                 .build(),
-            //         )
-            //         .build()],
-            // );
-
-            recorder.blit_image(
                 self.tone_mapped_image.clone(),
-                target_image.clone(),
-                &[vk::ImageBlit::builder()
-                    .src_subresource(
-                        vk::ImageSubresourceLayers::builder()
-                            .aspect_mask(vk::ImageAspectFlags::COLOR)
-                            .layer_count(1)
-                            .base_array_layer(0)
-                            .mip_level(0)
-                            .build(),
-                    )
-                    .src_offsets([
-                        vk::Offset3D { x: 0, y: 0, z: 0 },
-                        vk::Offset3D {
-                            x: self.result_image.width() as i32,
-                            y: self.result_image.height() as i32,
-                            z: 1,
-                        },
-                    ])
-                    .dst_offsets([
-                        vk::Offset3D { x: 0, y: 0, z: 0 },
-                        vk::Offset3D {
-                            x: target_image.width() as i32,
-                            y: target_image.height() as i32,
-                            z: 1,
-                        },
-                    ])
-                    .dst_subresource(
-                        vk::ImageSubresourceLayers::builder()
-                            .aspect_mask(vk::ImageAspectFlags::COLOR)
-                            .layer_count(1)
-                            .base_array_layer(0)
-                            .mip_level(0)
-                            .build(),
-                    )
-                    .build()],
-                vk::Filter::NEAREST,
+                self.push_constants.sample_count,
             );
-            recorder.set_image_layout(
-                target_image.clone(),
-                None,
-                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        }
+        if self.push_constants.focus_probe_x != u32::MAX {
+            self.render_finish_fence.wait();
+            self.read_focus_probe();
+        }
+        if let Some(object_picker) = &mut self.object_picker {
+            if object_picker.in_flight() {
+                self.render_finish_fence.wait();
+                object_picker.read();
+                if std::mem::take(&mut self.picking_block) {
+                    let pick = object_picker.selection.take();
+                    if let (Some(pick), Some(world)) = (pick, &mut self.world) {
+                        if let Some(mesh) = self.scene.added_mesh(pick.instance as usize) {
+                            world.edit(mesh, pick.primitive);
+                        }
+                    }
+                } else {
+                    // Added meshes, like the chunks of the world, aren't in the hierarchy.
+                    let instance_count = self.scene.instances().len();
+                    self.hierarchy.selected = object_picker
+                        .selection
+                        .map(|pick| pick.instance as usize)
+                        .filter(|instance| *instance < instance_count);
+                }
+                // The draws of an instance are in the order of its geometries.
+                if let Some(pick) = object_picker.selection {
+                    let draw = self
+                        .scene
+                        .draws()
+                        .iter()
+                        .filter(|draw| draw.instance == pick.instance)
+                        .nth(pick.geometry as usize);
+                    if let Some(draw) = draw {
+                        self.material_editor.selected = draw.material as usize;
+                    }
+                }
+            }
+        }
+        if self.screenshot_requested {
+            self.screenshot_requested = false;
+            self.render_finish_fence.wait();
+            self.save_screenshot();
+        }
+        if self.frame_capture.should_capture() {
+            self.render_finish_fence.wait();
+            let pixels = capture::read_image(
+                &mut self.queue,
+                self.command_pool.clone(),
+                self.allocator.clone(),
+                self.tone_mapped_image.clone(),
             );
-            self.ui_pass.execute(
-                recorder,
-                target_image,
-                &egui_backend::ScreenDescriptor {
-                    physical_width: self.size.width,
-                    physical_height: self.size.height,
-                    scale_factor: self.scale_factor as f32,
-                },
+            self.frame_capture.write_frame(
+                self.tone_mapped_image.width(),
+                self.tone_mapped_image.height(),
+                &pixels,
             );
-        });
-        self.render_finish_fence.wait();
-        self.render_finish_fence = self.queue.submit_binary(
-            command_buffer,
-            &[&self.swapchain.image_available_semaphore()],
-            &[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT],
-            &[&self.render_finish_semaphore],
-        );
-        self.queue
-            .present(&self.swapchain, index, &[&self.render_finish_semaphore]);
+        }
 
-        self.push_constants.sample_count += self.push_constants.batch_sample_count;
+        // Offline renders, picks, screenshots and captures read back after the frame.
+        self.frame_stats.span("Readback");
 
         let now = Instant::now();
         let frame_time = now - self.fps_counter.update_time;
@@ -786,11 +1987,14 @@ impl Engine {
             self.fps_counter.sampled_frames = 0;
             self.sample_speed =
                 self.fps_counter.fps * self.push_constants.batch_sample_count as f64;
-            if self.fps_counter.fps > 140.0 {
-                self.push_constants.batch_sample_count *= 2;
-            } else if self.fps_counter.fps < 70.0 && self.push_constants.batch_sample_count > 1 {
-                self.push_constants.batch_sample_count /= 2;
+            // Offline renders trace a fixed batch.
+            if !self.offline_render.is_active() {
+                self.push_constants.batch_sample_count = self
+                    .settings
+                    .batch_samples
+                    .next(self.push_constants.batch_sample_count, self.fps_counter.fps);
             }
         }
+        self.frame_limiter.wait();
     }
 }
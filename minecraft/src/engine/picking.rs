@@ -0,0 +1,231 @@
+use std::sync::Arc;
+
+use bytemuck::{Pod, Zeroable};
+use safe_vk::{vk, ComputePipelineRecorder, Pipeline, PipelineRecorder};
+
+use super::scene::Scene;
+use super::shaders;
+
+/// Matches `PushConstants` in pick.comp.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct PushConstants {
+    pixel_x: u32,
+    pixel_y: u32,
+    width: u32,
+    height: u32,
+}
+
+/// Matches `PickResult` in pick.comp.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct PickResult {
+    hit: u32,
+    instance: u32,
+    geometry: u32,
+    primitive: u32,
+    distance: f32,
+}
+
+/// The triangle hit by the ray through a picked pixel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pick {
+    /// Index of the top level instance.
+    pub instance: u32,
+    /// Index of the geometry within the mesh of the instance.
+    pub geometry: u32,
+    pub primitive: u32,
+    /// Distance from the camera along the ray.
+    pub distance: f32,
+}
+
+/// Selects the object under the cursor with a single ray query, read back once the frame it was
+/// recorded in is done.
+pub struct ObjectPicker {
+    pipeline: Arc<safe_vk::ComputePipeline>,
+    /// A new scene gets a new set while the frame in flight still uses the old one.
+    descriptor_allocator: safe_vk::DescriptorAllocator,
+    descriptor_set: Arc<safe_vk::DescriptorSet>,
+    uniform_buffer: Arc<safe_vk::Buffer>,
+    result_buffer: Arc<safe_vk::Buffer>,
+    /// The pixel to pick at in the next frame.
+    requested: Option<(u32, u32)>,
+    /// Whether the last recorded frame picked, and the result buffer must be read after it.
+    in_flight: bool,
+    pub selection: Option<Pick>,
+}
+
+impl ObjectPicker {
+    /// The scene must have been loaded with ray tracing.
+    pub fn new(
+        allocator: &Arc<safe_vk::Allocator>,
+        scene: &Scene,
+        uniform_buffer: Arc<safe_vk::Buffer>,
+    ) -> Self {
+        let device = allocator.device().clone();
+        let binding = |binding: u32, descriptor_type: safe_vk::DescriptorType| {
+            safe_vk::DescriptorSetLayoutBinding {
+                binding,
+                descriptor_type,
+                stage_flags: vk::ShaderStageFlags::COMPUTE,
+            }
+        };
+        let descriptor_set_layout = Arc::new(safe_vk::DescriptorSetLayout::new(
+            device.clone(),
+            Some("pick set layout"),
+            &[
+                binding(0, safe_vk::DescriptorType::StorageBuffer),
+                binding(1, safe_vk::DescriptorType::AccelerationStructure),
+                binding(5, safe_vk::DescriptorType::UniformBuffer),
+            ],
+        ));
+        let pipeline_layout = Arc::new(safe_vk::PipelineLayout::new(
+            device.clone(),
+            Some("pick pipeline layout"),
+            &[&descriptor_set_layout],
+            &[vk::PushConstantRange::builder()
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .offset(0)
+                .size(std::mem::size_of::<PushConstants>() as u32)
+                .build()],
+        ));
+        let pipeline = Arc::new(safe_vk::ComputePipeline::new(
+            Some("pick pipeline"),
+            pipeline_layout,
+            Arc::new(safe_vk::ShaderStage::new(
+                Arc::new(safe_vk::ShaderModule::new(
+                    device,
+                    shaders::Shaders::get("pick.comp.spv").unwrap(),
+                )),
+                vk::ShaderStageFlags::COMPUTE,
+                "main",
+            )),
+        ));
+        let result_buffer = Arc::new(safe_vk::Buffer::new_typed::<PickResult>(
+            Some("pick result buffer"),
+            allocator.clone(),
+            1,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            safe_vk::MemoryUsage::GpuToCpu,
+        ));
+        let mut descriptor_allocator = safe_vk::DescriptorAllocator::new(descriptor_set_layout, 2);
+        let descriptor_set = create_descriptor_set(
+            &mut descriptor_allocator,
+            &result_buffer,
+            scene,
+            &uniform_buffer,
+        );
+        Self {
+            pipeline,
+            descriptor_allocator,
+            descriptor_set,
+            uniform_buffer,
+            result_buffer,
+            requested: None,
+            in_flight: false,
+            selection: None,
+        }
+    }
+
+    /// Points the picker at a new scene or top level acceleration structure.
+    pub fn set_scene(&mut self, scene: &Scene) {
+        self.descriptor_set = create_descriptor_set(
+            &mut self.descriptor_allocator,
+            &self.result_buffer,
+            scene,
+            &self.uniform_buffer,
+        );
+    }
+
+    /// Picks the object at a pixel of the result image in the next frame.
+    pub fn request(&mut self, x: u32, y: u32) {
+        self.requested = Some((x, y));
+    }
+
+    /// Whether `read` has to be called once the last recorded frame is done.
+    pub fn in_flight(&self) -> bool {
+        self.in_flight
+    }
+
+    /// Records the requested pick, if any, for a result image of the given size.
+    pub fn record(&mut self, recorder: &mut safe_vk::CommandRecorder, (width, height): (u32, u32)) {
+        let (pixel_x, pixel_y) = match self.requested.take() {
+            Some(pixel) => pixel,
+            None => return,
+        };
+        let push_constants = PushConstants {
+            pixel_x,
+            pixel_y,
+            width,
+            height,
+        };
+        recorder.bind_compute_pipeline(self.pipeline.clone(), |recorder, pipeline| {
+            recorder.bind_descriptor_sets(vec![self.descriptor_set.clone()], pipeline.layout(), 0);
+            recorder.push_constants(
+                pipeline.layout(),
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                bytemuck::bytes_of(&push_constants),
+            );
+            recorder.dispatch(1, 1, 1);
+        });
+        self.in_flight = true;
+    }
+
+    /// Selects what the last recorded pick hit, nothing if it was the sky. The frame must be
+    /// done.
+    pub fn read(&mut self) {
+        let result = self.result_buffer.read::<PickResult>(0, 1)[0];
+        self.in_flight = false;
+        self.selection = if result.hit != 0 {
+            Some(Pick {
+                instance: result.instance,
+                geometry: result.geometry,
+                primitive: result.primitive,
+                distance: result.distance,
+            })
+        } else {
+            None
+        };
+    }
+
+    /// Shows what the last pick hit, if anything.
+    pub fn ui(&self, ui: &mut egui::Ui) {
+        if let Some(pick) = self.selection {
+            ui.label(format!("Instance: {}", pick.instance));
+            ui.label(format!("Geometry: {}", pick.geometry));
+            ui.label(format!("Primitive: {}", pick.primitive));
+            ui.label(format!("Distance: {:.2}", pick.distance));
+        }
+    }
+}
+
+fn create_descriptor_set(
+    descriptor_allocator: &mut safe_vk::DescriptorAllocator,
+    result_buffer: &Arc<safe_vk::Buffer>,
+    scene: &Scene,
+    uniform_buffer: &Arc<safe_vk::Buffer>,
+) -> Arc<safe_vk::DescriptorSet> {
+    let descriptor_set = descriptor_allocator.allocate(Some("pick descriptor set"));
+    descriptor_set.update(&[
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 0,
+            detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
+                buffer: result_buffer.clone(),
+                offset: 0,
+            },
+        },
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 1,
+            detail: safe_vk::DescriptorSetUpdateDetail::AccelerationStructure(scene.tlas().clone()),
+        },
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 5,
+            detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
+                buffer: uniform_buffer.clone(),
+                offset: 0,
+            },
+        },
+    ]);
+    Arc::new(descriptor_set)
+}
@@ -0,0 +1,584 @@
+use std::sync::Arc;
+
+use bytemuck::{Pod, Zeroable};
+use engine_core::environment::Environment;
+use safe_vk::{vk, GraphicsPipelineRecorder, Pipeline, PipelineRecorder};
+
+use super::scene::{Draw, Scene};
+use super::shaders;
+
+/// The format of the result image, which the pass renders to.
+const COLOR_FORMAT: vk::Format = vk::Format::R32G32B32A32_SFLOAT;
+const DEPTH_FORMAT: vk::Format = vk::Format::D32_SFLOAT;
+
+/// The positions of raster.vert, tightly packed in the vertex buffer of a draw.
+const VERTEX_BINDINGS: [vk::VertexInputBindingDescription; 1] =
+    [vk::VertexInputBindingDescription {
+        binding: 0,
+        stride: 3 * 4,
+        input_rate: vk::VertexInputRate::VERTEX,
+    }];
+const VERTEX_ATTRIBUTES: [vk::VertexInputAttributeDescription; 1] =
+    [vk::VertexInputAttributeDescription {
+        location: 0,
+        binding: 0,
+        format: vk::Format::R32G32B32_SFLOAT,
+        offset: 0,
+    }];
+
+/// What the statistics query of the scene draws counts, in the order `ui` shows them.
+const STATISTICS: [(vk::QueryPipelineStatisticFlags, &str); 4] = [
+    (
+        vk::QueryPipelineStatisticFlags::VERTEX_SHADER_INVOCATIONS,
+        "Vertices",
+    ),
+    (
+        vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_PRIMITIVES,
+        "Triangles",
+    ),
+    (
+        vk::QueryPipelineStatisticFlags::CLIPPING_PRIMITIVES,
+        "Clipped Triangles",
+    ),
+    (
+        vk::QueryPipelineStatisticFlags::FRAGMENT_SHADER_INVOCATIONS,
+        "Fragments",
+    ),
+];
+
+/// Matches `PushConsts` in raster.vert, raster_pulled.vert, raster_forward.glsl and
+/// raster_sky.frag.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct PushConstants {
+    model: [f32; 16],
+    /// Index of the geometry drawn, for its material or the material of each of its faces.
+    geometry: u32,
+    environment_rotation: f32,
+    environment_intensity: f32,
+}
+
+/// Forward renders the scene with vertex and fragment shaders only, for devices without the ray
+/// tracing extensions.
+///
+/// The sky pass fills the background with the environment, then every geometry of the scene is
+/// drawn with its material, lit by every light without shadows and by the environment along the
+/// normal and the reflected view direction. The result is the same for every sample, so the
+/// sample settings, adaptive sampling and the focus probe have no effect.
+pub struct Raster {
+    render_pass: Arc<safe_vk::RenderPass>,
+    pipeline: Arc<safe_vk::GraphicsPipeline>,
+    sky_pipeline: Arc<safe_vk::GraphicsPipeline>,
+    /// A new scene or environment gets a new set while the frame in flight uses the old one.
+    /// Both pipelines use the same set.
+    descriptor_allocator: safe_vk::DescriptorAllocator,
+    descriptor_set: Arc<safe_vk::DescriptorSet>,
+    /// Whether the scene pipeline reads the vertices itself, see `pull_vertices`.
+    pull_vertices: bool,
+    /// Renders to the result image, recreated with it.
+    framebuffer: Arc<safe_vk::Framebuffer>,
+    uniform_buffer: Arc<safe_vk::Buffer>,
+    extent: (u32, u32),
+    /// Counts the work of the scene draws, `None` on devices without pipeline statistics.
+    statistics_query: Option<Arc<safe_vk::QueryPool>>,
+    /// Whether the frame being recorded and the one last submitted drew the scene.
+    recorded: bool,
+    submitted: bool,
+    /// Of the last frame that drew the scene, once it has finished.
+    statistics: Option<safe_vk::PipelineStatistics>,
+}
+
+impl Raster {
+    pub fn new(
+        allocator: Arc<safe_vk::Allocator>,
+        result_image: &Arc<safe_vk::Image>,
+        scene: &Scene,
+        environment: &Environment,
+        uniform_buffer: Arc<safe_vk::Buffer>,
+    ) -> Self {
+        let device = allocator.device().clone();
+        let statistics_query = if device.features().pipeline_statistics_query == vk::TRUE {
+            let statistics = STATISTICS.iter().fold(
+                vk::QueryPipelineStatisticFlags::empty(),
+                |flags, (flag, _)| flags | *flag,
+            );
+            Some(Arc::new(safe_vk::QueryPool::pipeline_statistics(
+                device.clone(),
+                1,
+                statistics,
+            )))
+        } else {
+            None
+        };
+        let binding = |binding: u32, descriptor_type: safe_vk::DescriptorType| {
+            safe_vk::DescriptorSetLayoutBinding {
+                binding,
+                descriptor_type,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            }
+        };
+        let descriptor_set_layout = Arc::new(safe_vk::DescriptorSetLayout::new(
+            device.clone(),
+            Some("raster set layout"),
+            &[
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 5,
+                    descriptor_type: safe_vk::DescriptorType::UniformBuffer,
+                    stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                },
+                // raster_pulled.vert reads the vertices too.
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 2,
+                    descriptor_type: safe_vk::DescriptorType::StorageBuffer,
+                    stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                },
+                binding(6, safe_vk::DescriptorType::StorageBuffer),
+                binding(7, safe_vk::DescriptorType::StorageBuffer),
+                binding(8, safe_vk::DescriptorType::StorageBuffer),
+                binding(9, safe_vk::DescriptorType::StorageImage),
+                binding(10, safe_vk::DescriptorType::StorageBuffer),
+                binding(26, safe_vk::DescriptorType::StorageImage),
+            ],
+        ));
+
+        let render_pass = create_render_pass(device.clone());
+
+        let pipeline_layout = Arc::new(safe_vk::PipelineLayout::new(
+            device.clone(),
+            Some("raster pipeline layout"),
+            &[&descriptor_set_layout],
+            &[vk::PushConstantRange::builder()
+                .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
+                .offset(0)
+                .size(std::mem::size_of::<PushConstants>() as u32)
+                .build()],
+        ));
+        let shader_stage = |shader: &str, stage: vk::ShaderStageFlags| {
+            Arc::new(safe_vk::ShaderStage::new(
+                Arc::new(safe_vk::ShaderModule::new(
+                    device.clone(),
+                    shaders::Shaders::get(shader).unwrap(),
+                )),
+                stage,
+                "main",
+            ))
+        };
+        let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::builder()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+            .build();
+        // The acceleration structures don't cull either.
+        let rasterization = vk::PipelineRasterizationStateCreateInfo::builder()
+            .cull_mode(vk::CullModeFlags::NONE)
+            .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+            .polygon_mode(vk::PolygonMode::FILL)
+            .line_width(1.0)
+            .build();
+        let multisample = vk::PipelineMultisampleStateCreateInfo::builder()
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1)
+            .build();
+        let color_blend_attachment = vk::PipelineColorBlendAttachmentState::builder()
+            .blend_enable(false)
+            .color_write_mask(vk::ColorComponentFlags::all())
+            .build();
+        let color_blend = vk::PipelineColorBlendStateCreateInfo::builder()
+            .attachments(&[color_blend_attachment])
+            .build();
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic = vk::PipelineDynamicStateCreateInfo::builder()
+            .dynamic_states(&dynamic_states)
+            .build();
+
+        let pull_vertices = pull_vertices(&device);
+        let pipeline = Arc::new(safe_vk::GraphicsPipeline::new(
+            Some("raster pipeline"),
+            pipeline_layout.clone(),
+            if pull_vertices {
+                vec![
+                    shader_stage("raster_pulled.vert.spv", vk::ShaderStageFlags::VERTEX),
+                    shader_stage(
+                        "raster_forward_pulled.frag.spv",
+                        vk::ShaderStageFlags::FRAGMENT,
+                    ),
+                ]
+            } else {
+                vec![
+                    shader_stage("raster.vert.spv", vk::ShaderStageFlags::VERTEX),
+                    shader_stage("raster_forward.frag.spv", vk::ShaderStageFlags::FRAGMENT),
+                ]
+            },
+            render_pass.clone(),
+            &vertex_input(pull_vertices),
+            &input_assembly,
+            &rasterization,
+            &multisample,
+            &vk::PipelineDepthStencilStateCreateInfo::builder()
+                .depth_test_enable(true)
+                .depth_write_enable(true)
+                .depth_compare_op(vk::CompareOp::LESS)
+                .build(),
+            &color_blend,
+            &vk::PipelineViewportStateCreateInfo::builder()
+                .viewport_count(1)
+                .scissor_count(1),
+            &dynamic,
+        ));
+        // Drawn first, behind everything.
+        let sky_pipeline = Arc::new(safe_vk::GraphicsPipeline::new(
+            Some("raster sky pipeline"),
+            pipeline_layout,
+            vec![
+                shader_stage("raster_sky.vert.spv", vk::ShaderStageFlags::VERTEX),
+                shader_stage("raster_sky.frag.spv", vk::ShaderStageFlags::FRAGMENT),
+            ],
+            render_pass.clone(),
+            &vk::PipelineVertexInputStateCreateInfo::builder().build(),
+            &input_assembly,
+            &rasterization,
+            &multisample,
+            &vk::PipelineDepthStencilStateCreateInfo::builder()
+                .depth_test_enable(false)
+                .depth_write_enable(false)
+                .build(),
+            &color_blend,
+            &vk::PipelineViewportStateCreateInfo::builder()
+                .viewport_count(1)
+                .scissor_count(1),
+            &dynamic,
+        ));
+
+        let framebuffer = create_framebuffer(&allocator, &render_pass, result_image);
+        let mut descriptor_allocator = safe_vk::DescriptorAllocator::new(descriptor_set_layout, 2);
+        let descriptor_set = Arc::new(descriptor_allocator.allocate(Some("raster descriptor set")));
+        let raster = Self {
+            render_pass,
+            pipeline,
+            sky_pipeline,
+            descriptor_allocator,
+            descriptor_set,
+            pull_vertices,
+            framebuffer,
+            uniform_buffer,
+            extent: (result_image.width(), result_image.height()),
+            statistics_query,
+            recorded: false,
+            submitted: false,
+            statistics: None,
+        };
+        raster.update_descriptor_set(scene, environment);
+        raster
+    }
+
+    /// Recreates the framebuffer for a new result image.
+    pub fn resize(
+        &mut self,
+        allocator: &Arc<safe_vk::Allocator>,
+        result_image: &Arc<safe_vk::Image>,
+    ) {
+        self.framebuffer = create_framebuffer(allocator, &self.render_pass, result_image);
+        self.extent = (result_image.width(), result_image.height());
+    }
+
+    /// Points the passes at a new scene or environment.
+    pub fn set_scene(&mut self, scene: &Scene, environment: &Environment) {
+        self.descriptor_set = Arc::new(
+            self.descriptor_allocator
+                .allocate(Some("raster descriptor set")),
+        );
+        self.update_descriptor_set(scene, environment);
+    }
+
+    /// Records the passes in place of the ray tracing dispatch, leaving the result image in
+    /// `GENERAL` layout.
+    pub(super) fn record(
+        &mut self,
+        recorder: &mut safe_vk::CommandRecorder,
+        scene: &Scene,
+        settings: &super::PushConstants,
+    ) {
+        let (width, height) = self.extent;
+        let mut push_constants = PushConstants {
+            model: glam::Mat4::IDENTITY.to_cols_array(),
+            geometry: 0,
+            environment_rotation: settings.environment_rotation,
+            environment_intensity: settings.environment_intensity,
+        };
+        let clear_values = [
+            vk::ClearValue {
+                color: vk::ClearColorValue { float32: [0.0; 4] },
+            },
+            vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    depth: 1.0,
+                    stencil: 0,
+                },
+            },
+        ];
+        let viewport = vk::Viewport::builder()
+            .width(width as f32)
+            .height(height as f32)
+            .min_depth(0.0)
+            .max_depth(1.0)
+            .build();
+        let scissor = vk::Rect2D::builder()
+            .extent(vk::Extent2D { width, height })
+            .build();
+        if let Some(statistics_query) = &self.statistics_query {
+            recorder.reset_query_pool(statistics_query.clone(), 0, 1);
+            self.recorded = true;
+        }
+        let statistics_query = self.statistics_query.clone();
+        recorder.begin_render_pass(
+            self.render_pass.clone(),
+            self.framebuffer.clone(),
+            &clear_values,
+            |recorder| {
+                recorder.bind_graphics_pipeline(self.sky_pipeline.clone(), |recorder, pipeline| {
+                    recorder.set_viewport(viewport);
+                    recorder.set_scissor(&[scissor]);
+                    recorder.bind_descriptor_sets(
+                        vec![self.descriptor_set.clone()],
+                        pipeline.layout(),
+                        0,
+                    );
+                    recorder.push_constants(
+                        pipeline.layout(),
+                        vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                        0,
+                        bytemuck::bytes_of(&push_constants),
+                    );
+                    recorder.draw(3, 1);
+                });
+                let mut draw_scene = |recorder: &mut safe_vk::CommandRecorder| {
+                    recorder.bind_graphics_pipeline(self.pipeline.clone(), |recorder, pipeline| {
+                        recorder.set_viewport(viewport);
+                        recorder.set_scissor(&[scissor]);
+                        recorder.bind_descriptor_sets(
+                            vec![self.descriptor_set.clone()],
+                            pipeline.layout(),
+                            0,
+                        );
+                        for draw in scene.draws().iter().filter(|draw| draw.visible) {
+                            push_constants.model = draw.transform.to_cols_array();
+                            push_constants.geometry = draw.geometry;
+                            recorder.push_constants(
+                                pipeline.layout(),
+                                vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                                0,
+                                bytemuck::bytes_of(&push_constants),
+                            );
+                            draw_geometry(recorder, draw, self.pull_vertices);
+                        }
+                    });
+                };
+                match statistics_query {
+                    Some(statistics_query) => recorder.begin_query(
+                        statistics_query,
+                        0,
+                        vk::QueryControlFlags::empty(),
+                        draw_scene,
+                    ),
+                    None => draw_scene(recorder),
+                }
+            },
+        );
+    }
+
+    /// Reads the statistics of the frame last submitted. Call once that frame has finished,
+    /// before the one recorded is submitted.
+    pub fn frame_finished(&mut self) {
+        let submitted = std::mem::replace(&mut self.submitted, std::mem::take(&mut self.recorded));
+        if let (Some(statistics_query), true) = (&self.statistics_query, submitted) {
+            if let [Some(statistics)] = &statistics_query.pipeline_statistics_results(0, 1)[..] {
+                self.statistics = Some(statistics.clone());
+            }
+        }
+    }
+
+    /// The statistics of the scene draws of the last frame that rasterized the scene.
+    pub fn ui(&self, ui: &mut egui::Ui) {
+        let statistics = match &self.statistics {
+            Some(statistics) => statistics,
+            None => return,
+        };
+        egui::Grid::new("raster statistics").show(ui, |ui| {
+            for (flag, label) in STATISTICS.iter() {
+                ui.label(*label);
+                ui.label(statistics.get(*flag).unwrap_or(0).to_string());
+                ui.end_row();
+            }
+        });
+    }
+
+    fn update_descriptor_set(&self, scene: &Scene, environment: &Environment) {
+        let buffer =
+            |binding: u32, buffer: &Arc<safe_vk::Buffer>| safe_vk::DescriptorSetUpdateInfo {
+                binding,
+                detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
+                    buffer: buffer.clone(),
+                    offset: 0,
+                },
+            };
+        self.descriptor_set.update(&[
+            buffer(2, scene.geometry_buffer()),
+            buffer(5, &self.uniform_buffer),
+            buffer(6, scene.light_buffer()),
+            buffer(7, scene.material_buffer()),
+            buffer(8, scene.geometry_material_buffer()),
+            safe_vk::DescriptorSetUpdateInfo {
+                binding: 9,
+                detail: safe_vk::DescriptorSetUpdateDetail::Image(Arc::new(
+                    safe_vk::ImageView::new(environment.image().clone()),
+                )),
+            },
+            buffer(10, environment.cdf_buffer()),
+            safe_vk::DescriptorSetUpdateInfo {
+                binding: 26,
+                detail: safe_vk::DescriptorSetUpdateDetail::Image(Arc::new(
+                    safe_vk::ImageView::new(scene.atlas().clone()),
+                )),
+            },
+        ]);
+    }
+}
+
+/// Whether the scene draws of `Raster` and `Hybrid` pull their vertices with raster_pulled.vert.
+/// Their fragment shaders read the triangle drawn for its material, which gl_PrimitiveID only
+/// tells with the geometry shader feature.
+pub(super) fn pull_vertices(device: &safe_vk::Device) -> bool {
+    device.features().geometry_shader != vk::TRUE
+}
+
+/// The vertex input of the scene pipelines, none for pulled vertices.
+pub(super) fn vertex_input(pull_vertices: bool) -> vk::PipelineVertexInputStateCreateInfo {
+    if pull_vertices {
+        vk::PipelineVertexInputStateCreateInfo::builder().build()
+    } else {
+        vk::PipelineVertexInputStateCreateInfo::builder()
+            .vertex_binding_descriptions(&VERTEX_BINDINGS)
+            .vertex_attribute_descriptions(&VERTEX_ATTRIBUTES)
+            .build()
+    }
+}
+
+/// Draws `draw` with the scene pipeline bound. Pulled vertices are read by index from the
+/// geometry buffer, one per index, without vertex and index buffers.
+pub(super) fn draw_geometry(
+    recorder: &mut dyn GraphicsPipelineRecorder,
+    draw: &Draw,
+    pull_vertices: bool,
+) {
+    if pull_vertices {
+        recorder.draw(draw.index_count, 1);
+        return;
+    }
+    recorder.bind_vertex_buffer(vec![draw.buffer.clone()], &[draw.vertex_buffer_offset]);
+    recorder.bind_index_buffer(
+        draw.buffer.clone(),
+        draw.index_buffer_offset,
+        draw.index_type,
+    );
+    recorder.draw_indexed(draw.index_count, 1);
+}
+
+fn create_framebuffer(
+    allocator: &Arc<safe_vk::Allocator>,
+    render_pass: &Arc<safe_vk::RenderPass>,
+    result_image: &Arc<safe_vk::Image>,
+) -> Arc<safe_vk::Framebuffer> {
+    let depth = safe_vk::Image::new(
+        Some("raster depth"),
+        allocator.clone(),
+        DEPTH_FORMAT,
+        result_image.width(),
+        result_image.height(),
+        vk::ImageTiling::OPTIMAL,
+        vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+        safe_vk::MemoryUsage::GpuOnly,
+    );
+    Arc::new(safe_vk::Framebuffer::new(
+        render_pass.clone(),
+        result_image.width(),
+        result_image.height(),
+        vec![
+            Arc::new(safe_vk::ImageView::new(result_image.clone())),
+            Arc::new(safe_vk::ImageView::new(Arc::new(depth))),
+        ],
+    ))
+}
+
+/// A single subpass drawing to the result image, which ends up in `GENERAL` layout like after
+/// the ray tracing dispatch, and depth.
+fn create_render_pass(device: Arc<safe_vk::Device>) -> Arc<safe_vk::RenderPass> {
+    Arc::new(safe_vk::RenderPass::new(
+        device,
+        &vk::RenderPassCreateInfo::builder()
+            .attachments(&[
+                vk::AttachmentDescription::builder()
+                    .format(COLOR_FORMAT)
+                    .samples(vk::SampleCountFlags::TYPE_1)
+                    .load_op(vk::AttachmentLoadOp::CLEAR)
+                    .store_op(vk::AttachmentStoreOp::STORE)
+                    .initial_layout(vk::ImageLayout::UNDEFINED)
+                    .final_layout(vk::ImageLayout::GENERAL)
+                    .build(),
+                vk::AttachmentDescription::builder()
+                    .format(DEPTH_FORMAT)
+                    .samples(vk::SampleCountFlags::TYPE_1)
+                    .load_op(vk::AttachmentLoadOp::CLEAR)
+                    .store_op(vk::AttachmentStoreOp::DONT_CARE)
+                    .initial_layout(vk::ImageLayout::UNDEFINED)
+                    .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+                    .build(),
+            ])
+            .subpasses(&[vk::SubpassDescription::builder()
+                .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+                .color_attachments(&[vk::AttachmentReference::builder()
+                    .attachment(0)
+                    .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                    .build()])
+                .depth_stencil_attachment(
+                    &vk::AttachmentReference::builder()
+                        .attachment(1)
+                        .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+                        .build(),
+                )
+                .build()])
+            .dependencies(&[
+                // The camera update of the frame is visible and the last frame is done reading
+                // the result image before it's cleared.
+                vk::SubpassDependency::builder()
+                    .src_subpass(vk::SUBPASS_EXTERNAL)
+                    .dst_subpass(0)
+                    .src_stage_mask(
+                        vk::PipelineStageFlags::TRANSFER
+                            | vk::PipelineStageFlags::COMPUTE_SHADER
+                            | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                    )
+                    .src_access_mask(
+                        vk::AccessFlags::TRANSFER_WRITE
+                            | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                    )
+                    .dst_stage_mask(
+                        vk::PipelineStageFlags::VERTEX_SHADER
+                            | vk::PipelineStageFlags::FRAGMENT_SHADER
+                            | vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                            | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+                    )
+                    .dst_access_mask(
+                        vk::AccessFlags::UNIFORM_READ
+                            | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                            | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                    )
+                    .build(),
+                vk::SubpassDependency::builder()
+                    .src_subpass(0)
+                    .dst_subpass(vk::SUBPASS_EXTERNAL)
+                    .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+                    .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                    .dst_stage_mask(vk::PipelineStageFlags::ALL_COMMANDS)
+                    .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::TRANSFER_READ)
+                    .build(),
+            ])
+            .build(),
+    ))
+}
@@ -0,0 +1,525 @@
+use std::sync::Arc;
+
+use camera::CameraUniform;
+use engine_core::environment::Environment;
+use safe_vk::{vk, PipelineRecorder};
+
+use super::aov::Aovs;
+use super::hybrid::Hybrid;
+use super::restir::Restir;
+use super::scene::Scene;
+use super::shaders;
+use super::watchdog::GpuWatchdog;
+use super::wavefront::Wavefront;
+use super::Renderer;
+
+/// The renderers that need the ray tracing extensions: raytrace.rgen with the ReSTIR passes,
+/// `Wavefront` and `Hybrid`. Only created on devices that support them, the others fall back to
+/// `Raster`.
+pub struct RayTracing {
+    pipeline: Arc<safe_vk::RayTracingPipeline>,
+    /// A new scene gets a new set while the frame in flight still uses the old one.
+    descriptor_allocator: safe_vk::DescriptorAllocator,
+    descriptor_set: Arc<safe_vk::DescriptorSet>,
+    uniform_buffer: Arc<safe_vk::Buffer>,
+    focus_probe_buffer: Arc<safe_vk::Buffer>,
+    pub restir: Restir,
+    pub wavefront: Wavefront,
+    pub hybrid: Hybrid,
+    pub aovs: Aovs,
+    /// Splits the dispatches of raytrace.rgen that would time out.
+    pub watchdog: GpuWatchdog,
+}
+
+impl RayTracing {
+    pub fn new(
+        allocator: Arc<safe_vk::Allocator>,
+        queue: &mut safe_vk::Queue,
+        command_pool: Arc<safe_vk::CommandPool>,
+        result_image: &Arc<safe_vk::Image>,
+        scene: &Scene,
+        environment: &Environment,
+        uniform_buffer: Arc<safe_vk::Buffer>,
+        focus_probe_buffer: Arc<safe_vk::Buffer>,
+        moments_buffer: &Arc<safe_vk::Buffer>,
+    ) -> Self {
+        let device = allocator.device().clone();
+        let descriptor_set_layout = Arc::new(safe_vk::DescriptorSetLayout::new(
+            device.clone(),
+            Some("descriptor set layout"),
+            &[
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 0,
+                    descriptor_type: safe_vk::DescriptorType::StorageImage,
+                    stage_flags: vk::ShaderStageFlags::RAYGEN_KHR,
+                },
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 1,
+                    descriptor_type: safe_vk::DescriptorType::AccelerationStructure,
+                    stage_flags: vk::ShaderStageFlags::RAYGEN_KHR,
+                },
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 2,
+                    descriptor_type: safe_vk::DescriptorType::StorageBuffer,
+                    stage_flags: vk::ShaderStageFlags::CLOSEST_HIT_KHR,
+                },
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 4,
+                    descriptor_type: safe_vk::DescriptorType::StorageBuffer,
+                    stage_flags: vk::ShaderStageFlags::RAYGEN_KHR,
+                },
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 5,
+                    descriptor_type: safe_vk::DescriptorType::UniformBuffer,
+                    stage_flags: vk::ShaderStageFlags::RAYGEN_KHR,
+                },
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 6,
+                    descriptor_type: safe_vk::DescriptorType::StorageBuffer,
+                    stage_flags: vk::ShaderStageFlags::RAYGEN_KHR,
+                },
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 7,
+                    descriptor_type: safe_vk::DescriptorType::StorageBuffer,
+                    stage_flags: vk::ShaderStageFlags::CLOSEST_HIT_KHR,
+                },
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 8,
+                    descriptor_type: safe_vk::DescriptorType::StorageBuffer,
+                    stage_flags: vk::ShaderStageFlags::CLOSEST_HIT_KHR,
+                },
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 9,
+                    descriptor_type: safe_vk::DescriptorType::StorageImage,
+                    stage_flags: vk::ShaderStageFlags::RAYGEN_KHR,
+                },
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 10,
+                    descriptor_type: safe_vk::DescriptorType::StorageBuffer,
+                    stage_flags: vk::ShaderStageFlags::RAYGEN_KHR,
+                },
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 11,
+                    descriptor_type: safe_vk::DescriptorType::StorageBuffer,
+                    stage_flags: vk::ShaderStageFlags::RAYGEN_KHR,
+                },
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 14,
+                    descriptor_type: safe_vk::DescriptorType::StorageBuffer,
+                    stage_flags: vk::ShaderStageFlags::RAYGEN_KHR,
+                },
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 22,
+                    descriptor_type: safe_vk::DescriptorType::StorageImage,
+                    stage_flags: vk::ShaderStageFlags::RAYGEN_KHR,
+                },
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 23,
+                    descriptor_type: safe_vk::DescriptorType::StorageImage,
+                    stage_flags: vk::ShaderStageFlags::RAYGEN_KHR,
+                },
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 24,
+                    descriptor_type: safe_vk::DescriptorType::StorageImage,
+                    stage_flags: vk::ShaderStageFlags::RAYGEN_KHR,
+                },
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 25,
+                    descriptor_type: safe_vk::DescriptorType::StorageImage,
+                    stage_flags: vk::ShaderStageFlags::RAYGEN_KHR,
+                },
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 26,
+                    descriptor_type: safe_vk::DescriptorType::StorageImage,
+                    stage_flags: vk::ShaderStageFlags::CLOSEST_HIT_KHR,
+                },
+            ],
+        ));
+
+        let pipeline_layout = Arc::new(safe_vk::PipelineLayout::new(
+            device.clone(),
+            Some("rt pipeline layout"),
+            &[&descriptor_set_layout],
+            &[vk::PushConstantRange::builder()
+                .offset(0)
+                .size(std::mem::size_of::<super::PushConstants>() as u32)
+                .stage_flags(vk::ShaderStageFlags::RAYGEN_KHR)
+                .build()],
+        ));
+
+        let restir = Restir::new(
+            allocator.clone(),
+            result_image,
+            scene,
+            moments_buffer.clone(),
+        );
+        let wavefront = Wavefront::new(
+            allocator.clone(),
+            result_image,
+            scene,
+            environment,
+            uniform_buffer.clone(),
+            focus_probe_buffer.clone(),
+            moments_buffer.clone(),
+        );
+        let hybrid = Hybrid::new(
+            allocator.clone(),
+            queue,
+            command_pool.clone(),
+            result_image,
+            scene,
+            environment,
+            uniform_buffer.clone(),
+            focus_probe_buffer.clone(),
+        );
+        let aovs = Aovs::new(&allocator, queue, command_pool, result_image);
+
+        let mut descriptor_allocator =
+            safe_vk::DescriptorAllocator::new(descriptor_set_layout.clone(), 2);
+        let descriptor_set = create_descriptor_set(
+            &mut descriptor_allocator,
+            result_image,
+            scene,
+            environment,
+            restir.gbuffer(),
+            &uniform_buffer,
+            &focus_probe_buffer,
+            moments_buffer,
+            &aovs,
+        );
+
+        let shader_stages = vec![
+            Arc::new(safe_vk::ShaderStage::new(
+                Arc::new(safe_vk::ShaderModule::new(
+                    device.clone(),
+                    shaders::Shaders::get("raytrace.rgen.spv").unwrap(),
+                )),
+                vk::ShaderStageFlags::RAYGEN_KHR,
+                "main",
+            )),
+            Arc::new(safe_vk::ShaderStage::new(
+                Arc::new(safe_vk::ShaderModule::new(
+                    device.clone(),
+                    shaders::Shaders::get("miss.rmiss.spv").unwrap(),
+                )),
+                vk::ShaderStageFlags::MISS_KHR,
+                "main",
+            )),
+            Arc::new(safe_vk::ShaderStage::new(
+                Arc::new(safe_vk::ShaderModule::new(
+                    device.clone(),
+                    engine_core::shaders::Shaders::get("shadow.rmiss.spv").unwrap(),
+                )),
+                vk::ShaderStageFlags::MISS_KHR,
+                "main",
+            )),
+            Arc::new(safe_vk::ShaderStage::new(
+                Arc::new(safe_vk::ShaderModule::new(
+                    device.clone(),
+                    shaders::Shaders::get("closest_hit.rchit.spv").unwrap(),
+                )),
+                vk::ShaderStageFlags::CLOSEST_HIT_KHR,
+                "main",
+            )),
+        ];
+
+        let pipeline = Arc::new(safe_vk::RayTracingPipeline::new(
+            Some("rt pipeline"),
+            allocator.clone(),
+            pipeline_layout,
+            shader_stages,
+            // Bounces are traced in a loop in the ray generation shader, not recursively.
+            1,
+            queue,
+        ));
+
+        Self {
+            pipeline,
+            descriptor_allocator,
+            descriptor_set,
+            uniform_buffer,
+            focus_probe_buffer,
+            restir,
+            wavefront,
+            hybrid,
+            aovs,
+            watchdog: GpuWatchdog::new(device),
+        }
+    }
+
+    /// Recreates the per-pixel resources of every renderer for a new result image.
+    pub fn resize(
+        &mut self,
+        allocator: &Arc<safe_vk::Allocator>,
+        queue: &mut safe_vk::Queue,
+        command_pool: Arc<safe_vk::CommandPool>,
+        result_image: &Arc<safe_vk::Image>,
+        scene: &Scene,
+        environment: &Environment,
+        moments_buffer: &Arc<safe_vk::Buffer>,
+    ) {
+        self.restir
+            .resize(allocator, result_image, scene, moments_buffer.clone());
+        self.wavefront.resize(
+            allocator,
+            result_image,
+            scene,
+            environment,
+            moments_buffer.clone(),
+        );
+        self.hybrid.resize(
+            allocator,
+            queue,
+            command_pool.clone(),
+            result_image,
+            scene,
+            environment,
+        );
+        self.aovs
+            .resize(allocator, queue, command_pool, result_image);
+
+        let result_image_view = Arc::new(safe_vk::ImageView::new(result_image.clone()));
+        self.descriptor_set.update(&[
+            safe_vk::DescriptorSetUpdateInfo {
+                binding: 0,
+                detail: safe_vk::DescriptorSetUpdateDetail::Image(result_image_view.clone()),
+            },
+            safe_vk::DescriptorSetUpdateInfo {
+                binding: 11,
+                detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
+                    buffer: self.restir.gbuffer().clone(),
+                    offset: 0,
+                },
+            },
+            safe_vk::DescriptorSetUpdateInfo {
+                binding: 14,
+                detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
+                    buffer: moments_buffer.clone(),
+                    offset: 0,
+                },
+            },
+        ]);
+        self.descriptor_set.update(&self.aovs.descriptor_updates());
+    }
+
+    /// Points every renderer at a new scene or environment. The set of the frame in flight keeps
+    /// the old ones alive.
+    pub fn set_scene(
+        &mut self,
+        result_image: &Arc<safe_vk::Image>,
+        scene: &Scene,
+        environment: &Environment,
+        moments_buffer: &Arc<safe_vk::Buffer>,
+    ) {
+        self.descriptor_set = create_descriptor_set(
+            &mut self.descriptor_allocator,
+            result_image,
+            scene,
+            environment,
+            self.restir.gbuffer(),
+            &self.uniform_buffer,
+            &self.focus_probe_buffer,
+            moments_buffer,
+            &self.aovs,
+        );
+        self.restir.set_scene(result_image, scene);
+        self.wavefront.set_scene(result_image, scene, environment);
+        self.hybrid.set_scene(result_image, scene, environment);
+    }
+
+    /// Records `batch_sample_count` samples of every pixel with `renderer`, which can't be
+    /// `Renderer::Raster`, followed by the ReSTIR passes. The result image must be in `GENERAL`
+    /// layout.
+    pub(super) fn record(
+        &mut self,
+        recorder: &mut safe_vk::CommandRecorder,
+        renderer: Renderer,
+        result_image: &Arc<safe_vk::Image>,
+        scene: &Scene,
+        camera_uniform: &CameraUniform,
+        settings: &super::PushConstants,
+    ) {
+        let start_address = self.pipeline.sbt_buffer().device_address();
+        let stride = self.pipeline.sbt_stride() as u64;
+        let sbt_ray_gen_region = vk::StridedDeviceAddressRegionKHR::builder()
+            .device_address(start_address)
+            .stride(stride)
+            .size(stride)
+            .build();
+        // Groups are in stage order: raygen, the sky and shadow misses, then the hit group.
+        let mut sbt_hit_region = sbt_ray_gen_region;
+        sbt_hit_region.size = stride;
+        sbt_hit_region.device_address = start_address + 3 * stride;
+        let mut sbt_miss_region = sbt_ray_gen_region;
+        sbt_miss_region.size = 2 * stride;
+        sbt_miss_region.device_address = start_address + stride;
+
+        let mut sbt_callable_region = sbt_ray_gen_region;
+        sbt_callable_region.size = 0;
+
+        match renderer {
+            Renderer::PathTracer => {
+                let dispatches = self
+                    .watchdog
+                    .dispatches(settings.batch_sample_count, result_image.height());
+                let pipeline = &self.pipeline;
+                let descriptor_set = &self.descriptor_set;
+                self.watchdog
+                    .record(recorder, dispatches.len() as u32, |recorder| {
+                        let mut sub_settings = *settings;
+                        for dispatch in &dispatches {
+                            if dispatch.first_sample > 0 && dispatch.first_row == 0 {
+                                // Each sub-batch adds to the samples of the one before.
+                                recorder.memory_barrier(
+                                    vk::PipelineStageFlags::RAY_TRACING_SHADER_KHR,
+                                    vk::AccessFlags::SHADER_WRITE,
+                                    vk::PipelineStageFlags::RAY_TRACING_SHADER_KHR,
+                                    vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+                                );
+                                // The first dispatch has probed the focus distance.
+                                sub_settings.focus_probe_x = u32::MAX;
+                                sub_settings.focus_probe_y = u32::MAX;
+                            }
+                            sub_settings.sample_count =
+                                settings.sample_count + dispatch.first_sample;
+                            sub_settings.batch_sample_count = dispatch.sample_count;
+                            sub_settings.first_row = dispatch.first_row;
+                            recorder.bind_ray_tracing_pipeline(
+                                pipeline.clone(),
+                                |rec, pipeline| {
+                                    rec.bind_descriptor_sets(
+                                        vec![descriptor_set.clone()],
+                                        pipeline.layout(),
+                                        0,
+                                    );
+                                    rec.push_constants(
+                                        pipeline.layout(),
+                                        vk::ShaderStageFlags::RAYGEN_KHR,
+                                        0,
+                                        bytemuck::cast_slice(&[sub_settings]),
+                                    );
+                                    rec.trace_ray(
+                                        &sbt_ray_gen_region,
+                                        &sbt_miss_region,
+                                        &sbt_hit_region,
+                                        &sbt_callable_region,
+                                        result_image.width(),
+                                        dispatch.row_count,
+                                        1,
+                                    );
+                                },
+                            );
+                        }
+                    });
+            }
+            Renderer::Wavefront => self.wavefront.record(recorder, settings),
+            Renderer::Hybrid => self.hybrid.record(recorder, scene, settings),
+            Renderer::Raster => unreachable!("rasterization is recorded by Raster"),
+        }
+        self.restir
+            .record(recorder, camera_uniform, settings.batch_sample_count);
+    }
+}
+
+fn create_descriptor_set(
+    descriptor_allocator: &mut safe_vk::DescriptorAllocator,
+    result_image: &Arc<safe_vk::Image>,
+    scene: &Scene,
+    environment: &Environment,
+    gbuffer: &Arc<safe_vk::Buffer>,
+    uniform_buffer: &Arc<safe_vk::Buffer>,
+    focus_probe_buffer: &Arc<safe_vk::Buffer>,
+    moments_buffer: &Arc<safe_vk::Buffer>,
+    aovs: &Aovs,
+) -> Arc<safe_vk::DescriptorSet> {
+    let descriptor_set = descriptor_allocator.allocate(Some("Main descriptor set"));
+    descriptor_set.update(&[
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 0,
+            detail: safe_vk::DescriptorSetUpdateDetail::Image(Arc::new(safe_vk::ImageView::new(
+                result_image.clone(),
+            ))),
+        },
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 1,
+            detail: safe_vk::DescriptorSetUpdateDetail::AccelerationStructure(scene.tlas().clone()),
+        },
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 2,
+            detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
+                buffer: scene.geometry_buffer().clone(),
+                offset: 0,
+            },
+        },
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 4,
+            detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
+                buffer: focus_probe_buffer.clone(),
+                offset: 0,
+            },
+        },
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 5,
+            detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
+                buffer: uniform_buffer.clone(),
+                offset: 0,
+            },
+        },
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 6,
+            detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
+                buffer: scene.light_buffer().clone(),
+                offset: 0,
+            },
+        },
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 7,
+            detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
+                buffer: scene.material_buffer().clone(),
+                offset: 0,
+            },
+        },
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 8,
+            detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
+                buffer: scene.geometry_material_buffer().clone(),
+                offset: 0,
+            },
+        },
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 9,
+            detail: safe_vk::DescriptorSetUpdateDetail::Image(Arc::new(safe_vk::ImageView::new(
+                environment.image().clone(),
+            ))),
+        },
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 10,
+            detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
+                buffer: environment.cdf_buffer().clone(),
+                offset: 0,
+            },
+        },
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 11,
+            detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
+                buffer: gbuffer.clone(),
+                offset: 0,
+            },
+        },
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 14,
+            detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
+                buffer: moments_buffer.clone(),
+                offset: 0,
+            },
+        },
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 26,
+            detail: safe_vk::DescriptorSetUpdateDetail::Image(Arc::new(safe_vk::ImageView::new(
+                scene.atlas().clone(),
+            ))),
+        },
+    ]);
+    descriptor_set.update(&aovs.descriptor_updates());
+    Arc::new(descriptor_set)
+}
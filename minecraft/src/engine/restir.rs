@@ -0,0 +1,369 @@
+use std::sync::Arc;
+
+use bytemuck::{Pod, Zeroable};
+use camera::CameraUniform;
+use glam::Mat4;
+use safe_vk::{vk, ComputePipelineRecorder, Pipeline, PipelineRecorder};
+
+use super::scene::Scene;
+use super::shaders;
+
+const WORKGROUP_SIZE: u32 = 16;
+
+/// Size of `GBufferTexel` in gbuffer.glsl.
+const GBUFFER_TEXEL_SIZE: u64 = 64;
+/// Size of `Reservoir` in restir.glsl.
+const RESERVOIR_SIZE: u64 = 32;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct PushConstants {
+    previous_view_projection: [f32; 16],
+    batch_sample_count: u32,
+    frame_index: u32,
+    spatial_enabled: u32,
+}
+
+/// ReSTIR direct illumination for the first hit of every pixel, so that scenes with many lights
+/// converge without sampling each of them.
+///
+/// raytrace.rgen writes the G-buffer and leaves the light buffer out at the first hit when
+/// `restir_enabled` is set. `record` then resamples light candidates into a reservoir per pixel,
+/// reuses the reservoirs of the last frame and of neighbouring pixels, and adds the shaded result
+/// to the result image.
+pub struct Restir {
+    initial_pipeline: Arc<safe_vk::ComputePipeline>,
+    temporal_pipeline: Arc<safe_vk::ComputePipeline>,
+    spatial_pipeline: Arc<safe_vk::ComputePipeline>,
+    /// A new scene or result image gets a new set while the frame in flight uses the old one.
+    descriptor_allocator: safe_vk::DescriptorAllocator,
+    descriptor_set: Arc<safe_vk::DescriptorSet>,
+    gbuffer: Arc<safe_vk::Buffer>,
+    current_reservoirs: Arc<safe_vk::Buffer>,
+    /// The final reservoirs of the last frame.
+    previous_reservoirs: Arc<safe_vk::Buffer>,
+    /// Tells the spatial pass which pixels were sampled and how many samples they have.
+    moments_buffer: Arc<safe_vk::Buffer>,
+    extent: (u32, u32),
+    previous_view_projection: Mat4,
+    frame_index: u32,
+    /// False until `previous_reservoirs` holds a frame of the current scene and resolution.
+    history_valid: bool,
+    pub enabled: bool,
+    /// Reuses the reservoirs of the last frame.
+    pub temporal: bool,
+    /// Reuses the reservoirs of neighbouring pixels.
+    pub spatial: bool,
+}
+
+impl Restir {
+    pub fn new(
+        allocator: Arc<safe_vk::Allocator>,
+        result_image: &Arc<safe_vk::Image>,
+        scene: &Scene,
+        moments_buffer: Arc<safe_vk::Buffer>,
+    ) -> Self {
+        let device = allocator.device().clone();
+        let descriptor_set_layout = Arc::new(safe_vk::DescriptorSetLayout::new(
+            device.clone(),
+            Some("restir set layout"),
+            &[
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 0,
+                    descriptor_type: safe_vk::DescriptorType::StorageImage,
+                    stage_flags: vk::ShaderStageFlags::COMPUTE,
+                },
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 1,
+                    descriptor_type: safe_vk::DescriptorType::AccelerationStructure,
+                    stage_flags: vk::ShaderStageFlags::COMPUTE,
+                },
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 6,
+                    descriptor_type: safe_vk::DescriptorType::StorageBuffer,
+                    stage_flags: vk::ShaderStageFlags::COMPUTE,
+                },
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 11,
+                    descriptor_type: safe_vk::DescriptorType::StorageBuffer,
+                    stage_flags: vk::ShaderStageFlags::COMPUTE,
+                },
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 12,
+                    descriptor_type: safe_vk::DescriptorType::StorageBuffer,
+                    stage_flags: vk::ShaderStageFlags::COMPUTE,
+                },
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 13,
+                    descriptor_type: safe_vk::DescriptorType::StorageBuffer,
+                    stage_flags: vk::ShaderStageFlags::COMPUTE,
+                },
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 14,
+                    descriptor_type: safe_vk::DescriptorType::StorageBuffer,
+                    stage_flags: vk::ShaderStageFlags::COMPUTE,
+                },
+            ],
+        ));
+
+        let pipeline_layout = Arc::new(safe_vk::PipelineLayout::new(
+            device.clone(),
+            Some("restir pipeline layout"),
+            &[&descriptor_set_layout],
+            &[vk::PushConstantRange::builder()
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .offset(0)
+                .size(std::mem::size_of::<PushConstants>() as u32)
+                .build()],
+        ));
+
+        let create_pipeline = |name: &str, shader: &str| {
+            Arc::new(safe_vk::ComputePipeline::new(
+                Some(name),
+                pipeline_layout.clone(),
+                Arc::new(safe_vk::ShaderStage::new(
+                    Arc::new(safe_vk::ShaderModule::new(
+                        device.clone(),
+                        shaders::Shaders::get(shader).unwrap(),
+                    )),
+                    vk::ShaderStageFlags::COMPUTE,
+                    "main",
+                )),
+            ))
+        };
+        let initial_pipeline =
+            create_pipeline("restir initial pipeline", "restir_initial.comp.spv");
+        let temporal_pipeline =
+            create_pipeline("restir temporal pipeline", "restir_temporal.comp.spv");
+        let spatial_pipeline =
+            create_pipeline("restir spatial pipeline", "restir_spatial.comp.spv");
+
+        let (gbuffer, current_reservoirs, previous_reservoirs) =
+            create_buffers(&allocator, result_image.width(), result_image.height());
+        let mut descriptor_allocator = safe_vk::DescriptorAllocator::new(descriptor_set_layout, 2);
+        let descriptor_set = create_descriptor_set(
+            &mut descriptor_allocator,
+            result_image,
+            scene,
+            &gbuffer,
+            &current_reservoirs,
+            &previous_reservoirs,
+            &moments_buffer,
+        );
+
+        Self {
+            initial_pipeline,
+            temporal_pipeline,
+            spatial_pipeline,
+            descriptor_allocator,
+            descriptor_set,
+            gbuffer,
+            current_reservoirs,
+            previous_reservoirs,
+            moments_buffer,
+            extent: (result_image.width(), result_image.height()),
+            previous_view_projection: Mat4::IDENTITY,
+            frame_index: 0,
+            history_valid: false,
+            enabled: false,
+            temporal: true,
+            spatial: true,
+        }
+    }
+
+    /// Recreates the per-pixel buffers for a new result image. Binding 11 of the ray tracing set
+    /// must be updated with the new `gbuffer`.
+    pub fn resize(
+        &mut self,
+        allocator: &Arc<safe_vk::Allocator>,
+        result_image: &Arc<safe_vk::Image>,
+        scene: &Scene,
+        moments_buffer: Arc<safe_vk::Buffer>,
+    ) {
+        let (gbuffer, current_reservoirs, previous_reservoirs) =
+            create_buffers(allocator, result_image.width(), result_image.height());
+        self.gbuffer = gbuffer;
+        self.current_reservoirs = current_reservoirs;
+        self.previous_reservoirs = previous_reservoirs;
+        self.moments_buffer = moments_buffer;
+        self.extent = (result_image.width(), result_image.height());
+        self.set_scene(result_image, scene);
+    }
+
+    /// Points the passes at a new scene. Reservoirs of the old one are dropped.
+    pub fn set_scene(&mut self, result_image: &Arc<safe_vk::Image>, scene: &Scene) {
+        self.descriptor_set = create_descriptor_set(
+            &mut self.descriptor_allocator,
+            result_image,
+            scene,
+            &self.gbuffer,
+            &self.current_reservoirs,
+            &self.previous_reservoirs,
+            &self.moments_buffer,
+        );
+        self.history_valid = false;
+    }
+
+    /// The first hits written by raytrace.rgen, binding 11 of gbuffer.glsl.
+    pub fn gbuffer(&self) -> &Arc<safe_vk::Buffer> {
+        &self.gbuffer
+    }
+
+    /// Records the passes after the ray tracing dispatch, adding the direct light of the first
+    /// hit to the `batch_sample_count` samples it just accumulated.
+    pub fn record(
+        &mut self,
+        recorder: &mut safe_vk::CommandRecorder,
+        camera_uniform: &CameraUniform,
+        batch_sample_count: u32,
+    ) {
+        if !self.enabled {
+            self.history_valid = false;
+            return;
+        }
+        let push_constants = PushConstants {
+            previous_view_projection: self.previous_view_projection.to_cols_array(),
+            batch_sample_count,
+            frame_index: self.frame_index,
+            spatial_enabled: self.spatial as u32,
+        };
+        let (width, height) = self.extent;
+        let run_temporal = self.temporal && self.history_valid;
+
+        recorder.memory_barrier(
+            vk::PipelineStageFlags::RAY_TRACING_SHADER_KHR,
+            vk::AccessFlags::SHADER_WRITE,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+        );
+        let mut pipelines = vec![self.initial_pipeline.clone()];
+        if run_temporal {
+            pipelines.push(self.temporal_pipeline.clone());
+        }
+        pipelines.push(self.spatial_pipeline.clone());
+        for (i, pipeline) in pipelines.into_iter().enumerate() {
+            if i > 0 {
+                recorder.memory_barrier(
+                    vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::AccessFlags::SHADER_WRITE,
+                    vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+                );
+            }
+            recorder.bind_compute_pipeline(pipeline, |recorder, pipeline| {
+                recorder.bind_descriptor_sets(
+                    vec![self.descriptor_set.clone()],
+                    pipeline.layout(),
+                    0,
+                );
+                recorder.push_constants(
+                    pipeline.layout(),
+                    vk::ShaderStageFlags::COMPUTE,
+                    0,
+                    bytemuck::bytes_of(&push_constants),
+                );
+                recorder.dispatch(
+                    (width + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE,
+                    (height + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE,
+                    1,
+                );
+            });
+        }
+        recorder.memory_barrier(
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::SHADER_WRITE,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::AccessFlags::SHADER_READ | vk::AccessFlags::TRANSFER_READ,
+        );
+
+        self.previous_view_projection = camera_uniform.projection * camera_uniform.view;
+        self.frame_index = self.frame_index.wrapping_add(1);
+        self.history_valid = true;
+    }
+}
+
+fn create_buffers(
+    allocator: &Arc<safe_vk::Allocator>,
+    width: u32,
+    height: u32,
+) -> (
+    Arc<safe_vk::Buffer>,
+    Arc<safe_vk::Buffer>,
+    Arc<safe_vk::Buffer>,
+) {
+    let texels = width as u64 * height as u64;
+    let create_buffer = |name: &str, size: u64| {
+        Arc::new(safe_vk::Buffer::new(
+            Some(name),
+            allocator.clone(),
+            size,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            safe_vk::MemoryUsage::GpuOnly,
+        ))
+    };
+    (
+        create_buffer("gbuffer", texels * GBUFFER_TEXEL_SIZE),
+        create_buffer("current reservoirs", texels * RESERVOIR_SIZE),
+        create_buffer("previous reservoirs", texels * RESERVOIR_SIZE),
+    )
+}
+
+fn create_descriptor_set(
+    descriptor_allocator: &mut safe_vk::DescriptorAllocator,
+    result_image: &Arc<safe_vk::Image>,
+    scene: &Scene,
+    gbuffer: &Arc<safe_vk::Buffer>,
+    current_reservoirs: &Arc<safe_vk::Buffer>,
+    previous_reservoirs: &Arc<safe_vk::Buffer>,
+    moments_buffer: &Arc<safe_vk::Buffer>,
+) -> Arc<safe_vk::DescriptorSet> {
+    let descriptor_set = descriptor_allocator.allocate(Some("restir descriptor set"));
+    descriptor_set.update(&[
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 0,
+            detail: safe_vk::DescriptorSetUpdateDetail::Image(Arc::new(safe_vk::ImageView::new(
+                result_image.clone(),
+            ))),
+        },
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 1,
+            detail: safe_vk::DescriptorSetUpdateDetail::AccelerationStructure(scene.tlas().clone()),
+        },
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 6,
+            detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
+                buffer: scene.light_buffer().clone(),
+                offset: 0,
+            },
+        },
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 11,
+            detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
+                buffer: gbuffer.clone(),
+                offset: 0,
+            },
+        },
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 12,
+            detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
+                buffer: current_reservoirs.clone(),
+                offset: 0,
+            },
+        },
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 13,
+            detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
+                buffer: previous_reservoirs.clone(),
+                offset: 0,
+            },
+        },
+        safe_vk::DescriptorSetUpdateInfo {
+            binding: 14,
+            detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
+                buffer: moments_buffer.clone(),
+                offset: 0,
+            },
+        },
+    ]);
+    Arc::new(descriptor_set)
+}
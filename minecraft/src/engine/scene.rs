@@ -4,8 +4,9 @@ use std::io::Read;
 use std::path::Path;
 use std::sync::Arc;
 
+use asset_cache::{AssetCache, KeyBuilder};
+use bytemuck::{Pod, Zeroable};
 use glam::{vec3, Mat4, Vec3};
-use rand::{Rng, SeedableRng};
 use safe_vk::{vk, MemoryUsage};
 
 struct Geometry {
@@ -17,40 +18,359 @@ struct Geometry {
     vertex_buffer_address: u64,
     vertex_stride: u64,
     triangle_count: u32,
+    /// Device address of the material index of each triangle, `None` for geometries of one
+    /// material.
+    face_materials_address: Option<u64>,
+    /// Object space bounds of the positions.
+    bounds: gltf::mesh::BoundingBox,
+}
+
+impl Geometry {
+    /// Bounds and triangles of `positions` and `indices`, at the given offsets of `buffer`, with
+    /// the face materials at `face_materials_offset`, if any.
+    fn from_triangles(
+        buffer: &safe_vk::Buffer,
+        index_buffer_offset: u64,
+        vertex_buffer_offset: u64,
+        face_materials_offset: Option<u64>,
+        positions: &[[f32; 3]],
+        indices: &[u16],
+    ) -> Self {
+        let mut bounds = gltf::mesh::BoundingBox {
+            min: [f32::MAX; 3],
+            max: [f32::MIN; 3],
+        };
+        for position in positions {
+            for axis in 0..3 {
+                bounds.min[axis] = bounds.min[axis].min(position[axis]);
+                bounds.max[axis] = bounds.max[axis].max(position[axis]);
+            }
+        }
+        Self {
+            index_type: vk::IndexType::UINT16,
+            index_buffer_offset,
+            index_buffer_address: buffer.device_address(),
+            vertex_format: vk::Format::R32G32B32_SFLOAT,
+            vertex_buffer_offset,
+            vertex_buffer_address: buffer.device_address(),
+            vertex_stride: std::mem::size_of::<[f32; 3]>() as u64,
+            triangle_count: indices.len() as u32 / 3,
+            face_materials_address: face_materials_offset
+                .map(|offset| buffer.device_address() + offset),
+            bounds,
+        }
+    }
+}
+
+/// Matches `GeometryAddresses` in geometry.glsl.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct GeometryAddresses {
+    indices: u64,
+    vertices: u64,
+    /// Zero for geometries of one material.
+    face_materials: u64,
+    /// Whether the indices are 32-bit rather than 16-bit.
+    wide_indices: u32,
+    padding: u32,
+}
+
+impl GeometryAddresses {
+    fn new(geometry: &Geometry) -> Self {
+        Self {
+            indices: geometry.index_buffer_address + geometry.index_buffer_offset,
+            vertices: geometry.vertex_buffer_address + geometry.vertex_buffer_offset,
+            face_materials: geometry.face_materials_address.unwrap_or(0),
+            wide_indices: (geometry.index_type == vk::IndexType::UINT32) as u32,
+            padding: 0,
+        }
+    }
 }
 
 struct Mesh {
+    /// Holds the indices and vertices of the geometries.
+    buffer: Arc<safe_vk::Buffer>,
     geometries: Vec<Geometry>,
-    blas: safe_vk::AccelerationStructure,
+    /// `None` for scenes loaded without ray tracing.
+    blas: Option<safe_vk::AccelerationStructure>,
+    /// Where the material indices of the geometries start in the geometry material buffer.
+    first_geometry_material: u32,
+}
+
+/// One geometry of one instance or added mesh, for rasterizing the scene.
+pub struct Draw {
+    /// Holds the indices and vertices, at the offsets below.
+    pub buffer: Arc<safe_vk::Buffer>,
+    pub transform: Mat4,
+    pub index_type: vk::IndexType,
+    pub index_buffer_offset: u64,
+    pub vertex_buffer_offset: u64,
+    pub index_count: u32,
+    /// Index into the material buffer. Geometries with face materials use it only where one
+    /// material stands for the whole geometry, like in the material editor.
+    pub material: u32,
+    /// Index into the geometry and geometry material buffers, which have the material of each
+    /// triangle.
+    pub geometry: u32,
+    /// Corners of the object space bounding box, see `transform`.
+    pub bounds_min: [f32; 3],
+    pub bounds_max: [f32; 3],
+    /// Index of the top level instance, as seen by rays. Added meshes come after
+    /// `Scene::instances`.
+    pub instance: u32,
+    /// Copied from the instance.
+    pub visible: bool,
+}
+
+/// A top level node with a mesh, in the order of the instances of the top level acceleration
+/// structure.
+pub struct Instance {
+    /// Index of the glTF node.
+    pub node: usize,
+    /// Index of the glTF mesh.
+    pub mesh: usize,
+    pub transform: Mat4,
+    /// Hidden instances aren't rasterized and get a zero mask in the top level acceleration
+    /// structure, which no ray matches. That keeps the indices of the others as they are.
+    pub visible: bool,
+}
+
+/// Identifies a mesh added with `Scene::add_mesh`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MeshId(u64);
+
+/// Triangles of a mesh added with `Scene::add_mesh`, of one material or of one for each.
+pub struct MeshGeometry<'a> {
+    pub positions: &'a [[f32; 3]],
+    pub indices: &'a [u16],
+    /// Index into the material buffer.
+    pub material: u32,
+    /// Index into the material buffer for each triangle, which rays and draws use in place of
+    /// `material`.
+    pub face_materials: Option<&'a [u32]>,
+}
+
+/// A mesh added at runtime rather than loaded with the glTF scene, instanced once.
+struct AddedMesh {
+    id: MeshId,
+    mesh: Mesh,
+    /// Material index of each geometry.
+    materials: Vec<u32>,
+    transform: Mat4,
+    /// New geometries from `Scene::replace_mesh`, swapped in once built.
+    replacement: Option<Replacement>,
+}
+
+/// The geometries replacing those of an added mesh, while their bottom level acceleration
+/// structure is built.
+struct Replacement {
+    mesh: Mesh,
+    materials: Vec<u32>,
+    /// `None` for scenes loaded without ray tracing.
+    blas: Option<safe_vk::PendingAccelerationStructure>,
+}
+
+impl Replacement {
+    fn is_built(&self) -> bool {
+        self.blas.as_ref().map_or(true, |blas| blas.is_built())
+    }
+}
+
+/// Matches `Material` in materials.glsl.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct Material {
+    pub base_color: [f32; 4],
+    pub emissive: [f32; 3],
+    pub metallic: f32,
+    pub roughness: f32,
+    /// One more than the index of the atlas tile multiplying the base color, 0 without a
+    /// texture. See `Scene::add_atlas_tiles`.
+    pub texture: u32,
+}
+
+impl Material {
+    fn from_gltf(material: gltf::Material) -> Self {
+        let pbr = material.pbr_metallic_roughness();
+        Self {
+            base_color: pbr.base_color_factor(),
+            emissive: material.emissive_factor(),
+            metallic: pbr.metallic_factor(),
+            roughness: pbr.roughness_factor(),
+            texture: 0,
+        }
+    }
+}
+
+/// Texels along each side of a tile of the texture atlas. Matches `ATLAS_TILE_SIZE` in
+/// materials.glsl.
+pub const ATLAS_TILE_SIZE: usize = 16;
+
+/// The RGBA texels of a tile of the texture atlas, in rows from the top. The shaders read them
+/// as linear, not sRGB.
+pub type AtlasTile = [[u8; 4]; ATLAS_TILE_SIZE * ATLAS_TILE_SIZE];
+
+const LIGHT_SUN: u32 = 0;
+const LIGHT_DIRECTIONAL: u32 = 1;
+const LIGHT_POINT: u32 = 2;
+const LIGHT_SPOT: u32 = 3;
+
+/// Sky light every scene gets, towards the camera side and above the horizon.
+const SUN_DIRECTION: Vec3 = glam::const_vec3!([0.3, 0.6, 1.0]);
+/// Larger than the real sun so that paths hitting it by chance converge in reasonable time.
+const SUN_ANGULAR_RADIUS: f32 = 0.02;
+const SUN_IRRADIANCE: f32 = 3.0;
+
+/// Names decoded textures in the asset cache. Bump the version when the decoding changes.
+const TEXTURE_CACHE_KIND: &str = "gltf-texture-rgba8-v1";
+
+/// Matches `Light` in raytrace.rgen.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct Light {
+    position: [f32; 3],
+    kind: u32,
+    /// The direction the light travels in.
+    direction: [f32; 3],
+    cos_outer: f32,
+    /// Radiance for suns, intensity for point and spot lights, illuminance for directional
+    /// lights.
+    radiance: [f32; 3],
+    cos_inner: f32,
+}
+
+impl Light {
+    fn sun(towards_sun: Vec3, angular_radius: f32, irradiance: f32) -> Self {
+        let cos_outer = angular_radius.cos();
+        let solid_angle = 2.0 * std::f32::consts::PI * (1.0 - cos_outer);
+        Self {
+            position: [0.0; 3],
+            kind: LIGHT_SUN,
+            direction: (-towards_sun.normalize()).into(),
+            cos_outer,
+            radiance: [irradiance / solid_angle; 3],
+            cos_inner: cos_outer,
+        }
+    }
+
+    fn from_gltf(light: gltf::khr_lights_punctual::Light, transform: Mat4) -> Self {
+        let radiance = Vec3::from(light.color()) * light.intensity();
+        let (kind, cos_outer, cos_inner) = match light.kind() {
+            gltf::khr_lights_punctual::Kind::Directional => (LIGHT_DIRECTIONAL, -1.0, -1.0),
+            gltf::khr_lights_punctual::Kind::Point => (LIGHT_POINT, -1.0, -1.0),
+            gltf::khr_lights_punctual::Kind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => (LIGHT_SPOT, outer_cone_angle.cos(), inner_cone_angle.cos()),
+        };
+        Self {
+            position: transform.transform_point3(Vec3::ZERO).into(),
+            kind,
+            // Punctual lights point down their local -Z axis.
+            direction: transform.transform_vector3(-Vec3::Z).normalize().into(),
+            cos_outer,
+            radiance: radiance.into(),
+            cos_inner,
+        }
+    }
 }
 
 pub struct Scene {
     doc: gltf::Document,
-    buffers: Vec<Arc<safe_vk::Buffer>>,
+    /// The glTF buffers, which the geometry buffer points into.
+    _buffers: Vec<Arc<safe_vk::Buffer>>,
     // images: Vec<safe_vk::Image>,
-    top_level_acceleration_structure: Arc<safe_vk::AccelerationStructure>,
-    instance_buffers: Vec<safe_vk::Buffer>,
+    /// `None` for scenes loaded without ray tracing.
+    acceleration_structures: Option<AccelerationStructures>,
     allocator: Arc<safe_vk::Allocator>,
     queue: safe_vk::Queue,
     command_pool: Arc<safe_vk::CommandPool>,
-    pointer_buffer: safe_vk::Buffer,
     meshes: Vec<Mesh>,
+    light_buffer: Arc<safe_vk::Buffer>,
+    /// What the material buffer holds, unless edited since the last `write_material`.
+    materials: Vec<Material>,
+    /// Names of the materials added with `add_materials`, which follow the default one.
+    added_material_names: Vec<String>,
+    material_buffer: Arc<safe_vk::Buffer>,
+    /// Tiles of the texture atlas, starting with a white one so that it is never empty.
+    atlas_tiles: Vec<AtlasTile>,
+    atlas: Arc<safe_vk::Image>,
+    /// Material index of every geometry of `meshes`, in order. The geometry material buffer
+    /// has those of the added meshes after them.
+    geometry_materials: Vec<u32>,
+    geometry_material_buffer: Arc<safe_vk::Buffer>,
+    geometry_buffer: Arc<safe_vk::Buffer>,
+    instances: Vec<Instance>,
+    /// Instanced after `instances`, in order.
+    added_meshes: Vec<AddedMesh>,
+    next_mesh_id: u64,
+    /// The draws of `instances`, followed by those of `added_meshes`.
+    draws: Vec<Draw>,
+    instance_draw_count: usize,
+}
+
+/// The top level acceleration structure and the instances it was built from.
+struct AccelerationStructures {
+    top_level: Arc<safe_vk::AccelerationStructure>,
+    /// One for each of `Scene::instances`.
+    instance_buffers: Vec<safe_vk::Buffer>,
+    /// The instances of the added meshes, `None` without any.
+    added_instance_buffer: Option<safe_vk::Buffer>,
+    added_instance_count: usize,
+    pointer_buffer: safe_vk::Buffer,
+}
+
+impl AccelerationStructures {
+    /// Rewrites the instances of the same added meshes and refits the top level acceleration
+    /// structure to them, which is cheaper than rebuilding it when only their bottom level ones
+    /// changed. No frame may still be using it.
+    fn refit(&self, added_meshes: &[AddedMesh]) {
+        assert_eq!(added_meshes.len(), self.added_instance_count);
+        let added_instances = added_instances(added_meshes);
+        if let Some(buffer) = &self.added_instance_buffer {
+            buffer.copy_from(instance_bytes(&added_instances));
+        }
+        self.top_level.update(
+            &[top_level_geometry(&self.pointer_buffer)],
+            &[(self.instance_buffers.len() + self.added_instance_count) as u32],
+        );
+    }
 }
 
 impl Scene {
-    pub fn from_file<I: AsRef<Path>>(allocator: Arc<safe_vk::Allocator>, path: I) -> Self {
+    /// Loads a glTF scene. Without `ray_tracing`, no acceleration structures are built and the
+    /// scene can only be rasterized, for devices lacking the ray tracing extensions. Textures are
+    /// decoded on `jobs`, or loaded from `cache` if they were decoded before.
+    pub fn from_file<I: AsRef<Path>>(
+        allocator: Arc<safe_vk::Allocator>,
+        path: I,
+        ray_tracing: bool,
+        jobs: &jobs::JobSystem,
+        cache: Option<&AssetCache>,
+    ) -> Result<Self, gltf::Error> {
         let mut queue = safe_vk::Queue::new(allocator.device().clone());
         let command_pool = Arc::new(safe_vk::CommandPool::new(allocator.device().clone()));
-        let (doc, gltf_buffers, gltf_images) = gltf::import(path).unwrap();
+        let path = path.as_ref();
+        let gltf::Gltf {
+            document: doc,
+            blob,
+        } = gltf::Gltf::open(path)?;
+        let gltf_buffers = gltf::import_buffers(&doc, path.parent(), blob)?;
+        let gltf_images = import_images(jobs, cache, &doc, path.parent(), &gltf_buffers)?;
 
+        let mut buffer_usage = vk::BufferUsageFlags::STORAGE_BUFFER
+            | vk::BufferUsageFlags::VERTEX_BUFFER
+            | vk::BufferUsageFlags::INDEX_BUFFER;
+        if ray_tracing {
+            buffer_usage |= vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR;
+        }
         let buffers = gltf_buffers
             .iter()
             .map(|data| {
                 Arc::new(safe_vk::Buffer::new_init_host(
                     Some("gltf buffer"),
                     allocator.clone(),
-                    vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR
-                        | vk::BufferUsageFlags::STORAGE_BUFFER,
+                    buffer_usage,
                     safe_vk::MemoryUsage::CpuToGpu,
                     data.as_ref(),
                 ))
@@ -60,49 +380,16 @@ impl Scene {
         let images = gltf_images
             .iter()
             .map(|image| {
-                match image.format {
-                    gltf::image::Format::R8G8B8 => {
-                        let mut rgba_data: Vec<u8> =
-                            Vec::with_capacity((image.width * image.height * 4) as usize);
-                        for i in 0..image.pixels.len() {
-                            rgba_data.push(image.pixels[i]);
-                            if (i + 1) % 3 == 0 {
-                                rgba_data.push(std::u8::MAX);
-                            }
-                        }
-                        safe_vk::Image::new_init_host(
-                            Some("gltf texture"),
-                            allocator.clone(),
-                            vk::Format::R8G8B8A8_UNORM,
-                            image.width,
-                            image.height,
-                            vk::ImageTiling::LINEAR,
-                            vk::ImageUsageFlags::SAMPLED,
-                            safe_vk::MemoryUsage::CpuToGpu,
-                            &mut queue,
-                            command_pool.clone(),
-                            &rgba_data,
-                        )
-                    }
-                    gltf::image::Format::R8G8B8A8 => {
-                        safe_vk::Image::new_init_host(
-                            Some("gltf texture"),
-                            allocator.clone(),
-                            vk::Format::R8G8B8A8_UNORM,
-                            image.width,
-                            image.height,
-                            vk::ImageTiling::OPTIMAL,
-                            vk::ImageUsageFlags::SAMPLED,
-                            safe_vk::MemoryUsage::CpuToGpu,
-                            &mut queue,
-                            command_pool.clone(),
-                            &image.pixels,
-                        )
-                    }
-                    _ => {
-                        unimplemented!()
-                    }
-                };
+                safe_vk::Image::new_init_host_mipmapped(
+                    Some("gltf texture"),
+                    allocator.clone(),
+                    vk::Format::R8G8B8A8_UNORM,
+                    image.width(),
+                    image.height(),
+                    &mut queue,
+                    command_pool.clone(),
+                    image.as_raw(),
+                )
             })
             .collect::<Vec<_>>();
 
@@ -110,10 +397,29 @@ impl Scene {
 
         let scene = doc.scenes().next().unwrap();
 
+        let mut materials = doc.materials().map(Material::from_gltf).collect::<Vec<_>>();
+        // Primitives without a material get the glTF default one at the end.
+        let default_material = materials.len() as u32;
+        materials.push(Material {
+            base_color: [1.0; 4],
+            emissive: [0.0; 3],
+            metallic: 1.0,
+            roughness: 1.0,
+            texture: 0,
+        });
+        let mut geometry_materials = Vec::new();
+
         let mut meshes = Vec::with_capacity(doc.meshes().count());
         for mesh in doc.meshes() {
             let mut geometries = Vec::with_capacity(mesh.primitives().count());
+            let first_geometry_material = geometry_materials.len() as u32;
             for primitive in mesh.primitives() {
+                geometry_materials.push(
+                    primitive
+                        .material()
+                        .index()
+                        .map_or(default_material, |index| index as u32),
+                );
                 let index_accessor = primitive.indices().expect("unsupported");
                 let index_type = match index_accessor.data_type() {
                     gltf::accessor::DataType::U16 => vk::IndexType::UINT16,
@@ -165,68 +471,304 @@ impl Scene {
                     vertex_buffer_address,
                     vertex_stride,
                     triangle_count,
+                    face_materials_address: None,
+                    bounds: primitive.bounding_box(),
                 });
             }
-            let blas = safe_vk::AccelerationStructure::new(
-                Some("bottom level - mesh"),
-                allocator.clone(),
-                geometries
-                    .iter()
-                    .map(|geometry| {
-                        vk::AccelerationStructureGeometryKHR::builder()
-                            .geometry_type(vk::GeometryTypeKHR::TRIANGLES)
-                            .flags(
-                                vk::GeometryFlagsKHR::OPAQUE
-                                    | vk::GeometryFlagsKHR::NO_DUPLICATE_ANY_HIT_INVOCATION,
-                            )
-                            .geometry(vk::AccelerationStructureGeometryDataKHR {
-                                triangles:
-                                    vk::AccelerationStructureGeometryTrianglesDataKHR::builder()
-                                        .index_type(geometry.index_type)
-                                        .index_data(vk::DeviceOrHostAddressConstKHR {
-                                            device_address: buffers[0].device_address()
-                                                + geometry.index_buffer_offset,
-                                        })
-                                        .vertex_data(vk::DeviceOrHostAddressConstKHR {
-                                            device_address: buffers[0].device_address()
-                                                + geometry.vertex_buffer_offset,
-                                        })
-                                        .vertex_format(geometry.vertex_format)
-                                        .vertex_stride(geometry.vertex_stride)
-                                        .max_vertex(std::u32::MAX)
-                                        .build(),
-                            })
-                            .build()
-                    })
-                    .collect::<Vec<_>>()
-                    .as_slice(),
-                geometries
-                    .iter()
-                    .map(|geometry| geometry.triangle_count)
-                    .collect::<Vec<_>>()
-                    .as_slice(),
-                vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL,
-            );
-            meshes.push(Mesh { geometries, blas });
+            // Rasterization only needs the geometries.
+            let blas = if ray_tracing {
+                Some(Self::build_bottom_level(&allocator, &buffers[0], &geometries).wait())
+            } else {
+                None
+            };
+            meshes.push(Mesh {
+                buffer: buffers[0].clone(),
+                geometries,
+                blas,
+                first_geometry_material,
+            });
         }
 
-        let instance_buffers: Vec<safe_vk::Buffer> = scene
+        // Only top level nodes are instanced.
+        let instances = scene
             .nodes()
-            .map(|node| {
-                Self::process_node(
-                    node,
-                    meshes.as_slice(),
+            .filter_map(|node| {
+                Some(Instance {
+                    node: node.index(),
+                    mesh: node.mesh()?.index(),
+                    transform: Mat4::from_cols_array_2d(&node.transform().matrix()),
+                    visible: true,
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut draws = Vec::new();
+        for (index, instance) in instances.iter().enumerate() {
+            let mesh = &meshes[instance.mesh];
+            for (i, geometry) in mesh.geometries.iter().enumerate() {
+                draws.push(Draw {
+                    buffer: mesh.buffer.clone(),
+                    transform: instance.transform,
+                    index_type: geometry.index_type,
+                    index_buffer_offset: geometry.index_buffer_offset,
+                    vertex_buffer_offset: geometry.vertex_buffer_offset,
+                    index_count: geometry.triangle_count * 3,
+                    material: geometry_materials[mesh.first_geometry_material as usize + i],
+                    geometry: mesh.first_geometry_material + i as u32,
+                    bounds_min: geometry.bounds.min,
+                    bounds_max: geometry.bounds.max,
+                    instance: index as u32,
+                    visible: instance.visible,
+                });
+            }
+        }
+
+        let instance_draw_count = draws.len();
+
+        let acceleration_structures = if ray_tracing {
+            let instance_buffers = Self::create_instance_buffers(
+                &instances,
+                &meshes,
+                &allocator,
+                &mut queue,
+                &command_pool,
+            );
+            Some(Self::build_top_level(
+                instance_buffers,
+                &[],
+                &allocator,
+                &mut queue,
+                &command_pool,
+            ))
+        } else {
+            None
+        };
+
+        // Lights use their glTF transforms, there's always at least the sun.
+        let mut lights = vec![Light::sun(
+            SUN_DIRECTION,
+            SUN_ANGULAR_RADIUS,
+            SUN_IRRADIANCE,
+        )];
+        for node in scene.nodes() {
+            Self::collect_lights(node, Mat4::IDENTITY, &mut lights);
+        }
+        log::info!("{} lights", lights.len());
+        let light_buffer = Arc::new(safe_vk::Buffer::new_init_host(
+            Some("light buffer"),
+            allocator.clone(),
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            safe_vk::MemoryUsage::CpuToGpu,
+            bytemuck::cast_slice(&lights),
+        ));
+        let material_buffer = Self::create_material_buffer(&allocator, &materials);
+        let atlas_tiles = vec![[[u8::MAX; 4]; ATLAS_TILE_SIZE * ATLAS_TILE_SIZE]];
+        let atlas = Self::create_atlas(&allocator, &mut queue, &command_pool, &atlas_tiles);
+        let geometry_material_buffer =
+            Self::create_geometry_material_buffer(&allocator, &geometry_materials);
+        let geometry_addresses = meshes
+            .iter()
+            .flat_map(|mesh| mesh.geometries.iter().map(GeometryAddresses::new))
+            .collect::<Vec<_>>();
+        let geometry_buffer = Self::create_geometry_buffer(&allocator, &geometry_addresses);
+
+        Ok(Self {
+            doc,
+            _buffers: buffers,
+            // images,
+            acceleration_structures,
+            allocator,
+            queue,
+            command_pool,
+            meshes,
+            light_buffer,
+            materials,
+            added_material_names: Vec::new(),
+            material_buffer,
+            atlas_tiles,
+            atlas,
+            geometry_materials,
+            geometry_material_buffer,
+            geometry_buffer,
+            instances,
+            added_meshes: Vec::new(),
+            next_mesh_id: 0,
+            draws,
+            instance_draw_count,
+        })
+    }
+
+    fn create_material_buffer(
+        allocator: &Arc<safe_vk::Allocator>,
+        materials: &[Material],
+    ) -> Arc<safe_vk::Buffer> {
+        Arc::new(safe_vk::Buffer::new_init_host(
+            Some("material buffer"),
+            allocator.clone(),
+            // Written by `write_material` as they are edited.
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+            safe_vk::MemoryUsage::CpuToGpu,
+            bytemuck::cast_slice(materials),
+        ))
+    }
+
+    /// The tiles side by side in one row.
+    fn create_atlas(
+        allocator: &Arc<safe_vk::Allocator>,
+        queue: &mut safe_vk::Queue,
+        command_pool: &Arc<safe_vk::CommandPool>,
+        tiles: &[AtlasTile],
+    ) -> Arc<safe_vk::Image> {
+        let mut texels = Vec::with_capacity(tiles.len() * ATLAS_TILE_SIZE * ATLAS_TILE_SIZE);
+        for row in 0..ATLAS_TILE_SIZE {
+            let start = row * ATLAS_TILE_SIZE;
+            for tile in tiles {
+                texels.extend_from_slice(&tile[start..start + ATLAS_TILE_SIZE]);
+            }
+        }
+        let mut image = safe_vk::Image::new_init_host(
+            Some("texture atlas"),
+            allocator.clone(),
+            vk::Format::R8G8B8A8_UNORM,
+            (tiles.len() * ATLAS_TILE_SIZE) as u32,
+            ATLAS_TILE_SIZE as u32,
+            vk::ImageTiling::OPTIMAL,
+            vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::TRANSFER_DST,
+            safe_vk::MemoryUsage::GpuOnly,
+            queue,
+            command_pool.clone(),
+            bytemuck::cast_slice(&texels),
+        );
+        image.set_layout(vk::ImageLayout::GENERAL, queue, command_pool.clone());
+        Arc::new(image)
+    }
+
+    fn create_geometry_material_buffer(
+        allocator: &Arc<safe_vk::Allocator>,
+        geometry_materials: &[u32],
+    ) -> Arc<safe_vk::Buffer> {
+        Arc::new(safe_vk::Buffer::new_init_host(
+            Some("geometry material buffer"),
+            allocator.clone(),
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            safe_vk::MemoryUsage::CpuToGpu,
+            bytemuck::cast_slice(geometry_materials),
+        ))
+    }
+
+    fn create_geometry_buffer(
+        allocator: &Arc<safe_vk::Allocator>,
+        geometry_addresses: &[GeometryAddresses],
+    ) -> Arc<safe_vk::Buffer> {
+        Arc::new(safe_vk::Buffer::new_init_host(
+            Some("geometry buffer"),
+            allocator.clone(),
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            safe_vk::MemoryUsage::CpuToGpu,
+            bytemuck::cast_slice(geometry_addresses),
+        ))
+    }
+
+    fn build_bottom_level(
+        allocator: &Arc<safe_vk::Allocator>,
+        buffer: &safe_vk::Buffer,
+        geometries: &[Geometry],
+    ) -> safe_vk::PendingAccelerationStructure {
+        safe_vk::AccelerationStructure::build(
+            Some("bottom level - mesh"),
+            allocator.clone(),
+            geometries
+                .iter()
+                .map(|geometry| {
+                    vk::AccelerationStructureGeometryKHR::builder()
+                        .geometry_type(vk::GeometryTypeKHR::TRIANGLES)
+                        .flags(
+                            vk::GeometryFlagsKHR::OPAQUE
+                                | vk::GeometryFlagsKHR::NO_DUPLICATE_ANY_HIT_INVOCATION,
+                        )
+                        .geometry(vk::AccelerationStructureGeometryDataKHR {
+                            triangles: vk::AccelerationStructureGeometryTrianglesDataKHR::builder()
+                                .index_type(geometry.index_type)
+                                .index_data(vk::DeviceOrHostAddressConstKHR {
+                                    device_address: buffer.device_address()
+                                        + geometry.index_buffer_offset,
+                                })
+                                .vertex_data(vk::DeviceOrHostAddressConstKHR {
+                                    device_address: buffer.device_address()
+                                        + geometry.vertex_buffer_offset,
+                                })
+                                .vertex_format(geometry.vertex_format)
+                                .vertex_stride(geometry.vertex_stride)
+                                .max_vertex(std::u32::MAX)
+                                .build(),
+                        })
+                        .build()
+                })
+                .collect::<Vec<_>>()
+                .as_slice(),
+            geometries
+                .iter()
+                .map(|geometry| geometry.triangle_count)
+                .collect::<Vec<_>>()
+                .as_slice(),
+            vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL,
+            vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE,
+        )
+    }
+
+    /// An instance buffer for each of `instances`, for `build_top_level`.
+    fn create_instance_buffers(
+        instances: &[Instance],
+        meshes: &[Mesh],
+        allocator: &Arc<safe_vk::Allocator>,
+        queue: &mut safe_vk::Queue,
+        command_pool: &Arc<safe_vk::CommandPool>,
+    ) -> Vec<safe_vk::Buffer> {
+        instances
+            .iter()
+            .map(|instance| {
+                Self::create_instance_buffer(
+                    instance,
+                    meshes,
                     allocator.clone(),
-                    &mut queue,
+                    queue,
                     command_pool.clone(),
                 )
             })
-            .flatten()
-            .collect();
+            .collect()
+    }
 
+    /// Builds the top level acceleration structure over the instances of `instance_buffers`,
+    /// followed by one of each added mesh. It can be refit to added meshes with new geometries.
+    fn build_top_level(
+        instance_buffers: Vec<safe_vk::Buffer>,
+        added_meshes: &[AddedMesh],
+        allocator: &Arc<safe_vk::Allocator>,
+        queue: &mut safe_vk::Queue,
+        command_pool: &Arc<safe_vk::CommandPool>,
+    ) -> AccelerationStructures {
+        // Host visible, they change with every mesh streamed in, out or replaced.
+        let added_instances = added_instances(added_meshes);
+        let added_instance_buffer = if added_instances.is_empty() {
+            None
+        } else {
+            Some(safe_vk::Buffer::new_init_host(
+                Some("added mesh instance buffer"),
+                allocator.clone(),
+                vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
+                    | vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR,
+                safe_vk::MemoryUsage::CpuToGpu,
+                instance_bytes(&added_instances),
+            ))
+        };
+
+        let instance_size = std::mem::size_of::<vk::AccelerationStructureInstanceKHR>() as u64;
         let instance_buffer_addresses = instance_buffers
             .iter()
             .map(|buffer| buffer.device_address())
+            .chain(added_instance_buffer.iter().flat_map(|buffer| {
+                (0..added_instances.len() as u64)
+                    .map(move |i| buffer.device_address() + i * instance_size)
+            }))
             .collect::<Vec<_>>();
 
         let pointer_buffer = safe_vk::Buffer::new_init_device(
@@ -235,107 +777,581 @@ impl Scene {
             vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR
                 | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
             safe_vk::MemoryUsage::GpuOnly,
-            &mut queue,
+            queue,
             command_pool.clone(),
             bytemuck::cast_slice(&instance_buffer_addresses),
         );
 
-        let instance_geometry = vk::AccelerationStructureGeometryKHR::builder()
-            .geometry_type(vk::GeometryTypeKHR::INSTANCES)
-            .flags(vk::GeometryFlagsKHR::OPAQUE)
-            .geometry(vk::AccelerationStructureGeometryDataKHR {
-                instances: vk::AccelerationStructureGeometryInstancesDataKHR::builder()
-                    .array_of_pointers(true)
-                    .data(vk::DeviceOrHostAddressConstKHR {
-                        device_address: pointer_buffer.device_address(),
-                    })
-                    .build(),
-            })
-            .build();
-
-        let top_level_acceleration_structure = Arc::new(safe_vk::AccelerationStructure::new(
-            Some("top level - mesh"),
-            allocator.clone(),
-            &[instance_geometry],
-            &[instance_buffer_addresses.len() as u32],
-            vk::AccelerationStructureTypeKHR::TOP_LEVEL,
-        ));
-
-        Self {
-            doc,
-            buffers,
-            // images,
+        let top_level_acceleration_structure = Arc::new(
+            safe_vk::AccelerationStructure::build(
+                Some("top level - mesh"),
+                allocator.clone(),
+                &[top_level_geometry(&pointer_buffer)],
+                &[instance_buffer_addresses.len() as u32],
+                vk::AccelerationStructureTypeKHR::TOP_LEVEL,
+                vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE
+                    | vk::BuildAccelerationStructureFlagsKHR::ALLOW_UPDATE,
+            )
+            .wait(),
+        );
+        AccelerationStructures {
+            top_level: top_level_acceleration_structure,
             instance_buffers,
-            allocator,
-            queue,
-            command_pool,
-            top_level_acceleration_structure,
+            added_instance_buffer,
+            added_instance_count: added_instances.len(),
             pointer_buffer,
-            meshes,
         }
     }
 
-    fn process_node(
-        node: gltf::Node,
+    fn collect_lights(node: gltf::Node, parent_transform: Mat4, lights: &mut Vec<Light>) {
+        let transform = parent_transform * Mat4::from_cols_array_2d(&node.transform().matrix());
+        if let Some(light) = node.light() {
+            lights.push(Light::from_gltf(light, transform));
+        }
+        for child in node.children() {
+            Self::collect_lights(child, transform, lights);
+        }
+    }
+
+    fn create_instance_buffer(
+        instance: &Instance,
         meshes: &[Mesh],
         allocator: Arc<safe_vk::Allocator>,
         queue: &mut safe_vk::Queue,
         command_pool: Arc<safe_vk::CommandPool>,
-    ) -> Vec<safe_vk::Buffer> {
-        let orig_transform = Mat4::from_cols_array_2d(&node.transform().matrix());
+    ) -> safe_vk::Buffer {
+        let mesh = &meshes[instance.mesh];
+        let instance = acceleration_structure_instance(
+            instance.transform,
+            mesh.first_geometry_material,
+            instance.visible,
+            mesh.blas.as_ref().unwrap(),
+        );
+        safe_vk::Buffer::new_init_device(
+            Some("instance buffer"),
+            allocator,
+            vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
+                | vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR,
+            safe_vk::MemoryUsage::GpuOnly,
+            queue,
+            command_pool,
+            instance_bytes(std::slice::from_ref(&instance)),
+        )
+    }
+
+    /// Moves or hides an instance, rebuilding the top level acceleration structure. The old one
+    /// is dropped, so no frame may still be using it, and every descriptor set holding it has to
+    /// be recreated.
+    pub fn set_instance(&mut self, index: usize, transform: Mat4, visible: bool) {
+        let instance = &mut self.instances[index];
+        instance.transform = transform;
+        instance.visible = visible;
+        for draw in self.draws.iter_mut() {
+            if draw.instance as usize == index {
+                draw.transform = transform;
+                draw.visible = visible;
+            }
+        }
+        if self.acceleration_structures.is_some() {
+            let instance_buffers = Self::create_instance_buffers(
+                &self.instances,
+                &self.meshes,
+                &self.allocator,
+                &mut self.queue,
+                &self.command_pool,
+            );
+            self.acceleration_structures = Some(Self::build_top_level(
+                instance_buffers,
+                &self.added_meshes,
+                &self.allocator,
+                &mut self.queue,
+                &self.command_pool,
+            ));
+        }
+    }
 
-        let mut rng = rand::rngs::SmallRng::from_entropy();
+    /// Adds a mesh of one or more geometries, instanced once with `transform`. Its bottom level
+    /// acceleration structure is built right away, if the scene was loaded with ray tracing, but
+    /// rays and draws only see it from the next `update_meshes`.
+    pub fn add_mesh(&mut self, name: &str, geometries: &[MeshGeometry], transform: Mat4) -> MeshId {
+        let (mut mesh, materials) = self.create_added_mesh(name, geometries);
+        mesh.blas = self.acceleration_structures.as_ref().map(|_| {
+            Self::build_bottom_level(&self.allocator, &mesh.buffer, &mesh.geometries).wait()
+        });
+        let id = MeshId(self.next_mesh_id);
+        self.next_mesh_id += 1;
+        self.added_meshes.push(AddedMesh {
+            id,
+            mesh,
+            materials,
+            transform,
+            replacement: None,
+        });
+        id
+    }
 
-        let mut arr = Vec::new();
+    /// Replaces the geometries of a mesh added with `add_mesh`, keeping its place among the
+    /// instances. Its new bottom level acceleration structure is built in the background, until
+    /// then rays and draws keep seeing the old geometries. See `finish_replacements`. A
+    /// replacement still being built is dropped, waiting for it.
+    pub fn replace_mesh(&mut self, id: MeshId, name: &str, geometries: &[MeshGeometry]) {
+        let (mesh, materials) = self.create_added_mesh(name, geometries);
+        let blas = self
+            .acceleration_structures
+            .as_ref()
+            .map(|_| Self::build_bottom_level(&self.allocator, &mesh.buffer, &mesh.geometries));
+        let added = self
+            .added_meshes
+            .iter_mut()
+            .find(|added| added.id == id)
+            .expect("no such mesh");
+        added.replacement = Some(Replacement {
+            mesh,
+            materials,
+            blas,
+        });
+    }
 
-        if let Some(mesh) = node.mesh() {
-            let instance = vk::AccelerationStructureInstanceKHR {
-                transform: vk::TransformMatrixKHR {
-                    matrix: orig_transform.transpose().as_ref()[..12]
-                        .try_into()
-                        .unwrap(),
-                },
-                instance_custom_index_and_mask: 0 | (0xFF << 24),
-                instance_shader_binding_table_record_offset_and_flags: rng.gen_range(0..=4)
-                    | (vk::GeometryInstanceFlagsKHR::TRIANGLE_FACING_CULL_DISABLE.as_raw() << 24),
-                acceleration_structure_reference: vk::AccelerationStructureReferenceKHR {
-                    device_handle: meshes[mesh.index()].blas.device_address(),
-                },
-            };
-            let data = unsafe {
-                std::slice::from_raw_parts(
-                    std::mem::transmute(&instance),
-                    std::mem::size_of::<vk::AccelerationStructureInstanceKHR>(),
-                )
+    /// Whether the bottom level acceleration structure of any replacement is built.
+    pub fn replacements_built(&self) -> bool {
+        self.added_meshes
+            .iter()
+            .filter_map(|added| added.replacement.as_ref())
+            .any(Replacement::is_built)
+    }
+
+    /// Swaps in the replaced geometries whose bottom level acceleration structures are built,
+    /// returning the ids of their meshes. The old ones are dropped right away, so no frame may
+    /// still be using them, and the scene has to be updated with `update_meshes` before the next
+    /// one.
+    pub fn finish_replacements(&mut self) -> Vec<MeshId> {
+        let mut finished = Vec::new();
+        for added in self.added_meshes.iter_mut() {
+            let built = added
+                .replacement
+                .as_ref()
+                .map_or(false, Replacement::is_built);
+            if !built {
+                continue;
+            }
+            let replacement = added.replacement.take().unwrap();
+            added.mesh = Mesh {
+                blas: replacement.blas.map(|blas| blas.wait()),
+                ..replacement.mesh
             };
-            let instance_buffer = safe_vk::Buffer::new_init_device(
-                Some("instance buffer"),
-                allocator.clone(),
-                vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
-                    | vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR,
-                safe_vk::MemoryUsage::GpuOnly,
-                queue,
-                command_pool.clone(),
-                data,
-            );
-            arr.push(instance_buffer);
+            added.materials = replacement.materials;
+            finished.push(added.id);
+        }
+        finished
+    }
+
+    /// A mesh for `add_mesh` or `replace_mesh`, without its bottom level acceleration structure,
+    /// and the material of each of its geometries.
+    fn create_added_mesh(&self, name: &str, geometries: &[MeshGeometry]) -> (Mesh, Vec<u32>) {
+        assert!(!geometries.is_empty());
+        // The indices of every geometry, each padded to 4 bytes, then their vertices, then their
+        // face materials.
+        let mut data = Vec::new();
+        let mut index_offsets = Vec::with_capacity(geometries.len());
+        for geometry in geometries {
+            index_offsets.push(data.len() as u64);
+            data.extend_from_slice(bytemuck::cast_slice(geometry.indices));
+            data.resize((data.len() + 3) & !3, 0);
+        }
+        let mut vertex_offsets = Vec::with_capacity(geometries.len());
+        for geometry in geometries {
+            vertex_offsets.push(data.len() as u64);
+            data.extend_from_slice(bytemuck::cast_slice(geometry.positions));
+        }
+        let mut face_material_offsets = Vec::with_capacity(geometries.len());
+        for geometry in geometries {
+            face_material_offsets.push(geometry.face_materials.map(|face_materials| {
+                assert_eq!(face_materials.len(), geometry.indices.len() / 3);
+                let offset = data.len() as u64;
+                data.extend_from_slice(bytemuck::cast_slice(face_materials));
+                offset
+            }));
+        }
+
+        let ray_tracing = self.acceleration_structures.is_some();
+        let mut buffer_usage = vk::BufferUsageFlags::STORAGE_BUFFER
+            | vk::BufferUsageFlags::VERTEX_BUFFER
+            | vk::BufferUsageFlags::INDEX_BUFFER;
+        if ray_tracing {
+            buffer_usage |= vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR;
         }
-        arr
+        let buffer = Arc::new(safe_vk::Buffer::new_init_host(
+            Some(name),
+            self.allocator.clone(),
+            buffer_usage,
+            safe_vk::MemoryUsage::CpuToGpu,
+            &data,
+        ));
+        let mesh_geometries = geometries
+            .iter()
+            .zip(index_offsets.iter().zip(vertex_offsets.iter()))
+            .zip(face_material_offsets.iter())
+            .map(
+                |((geometry, (index_offset, vertex_offset)), face_material_offset)| {
+                    Geometry::from_triangles(
+                        &buffer,
+                        *index_offset,
+                        *vertex_offset,
+                        *face_material_offset,
+                        geometry.positions,
+                        geometry.indices,
+                    )
+                },
+            )
+            .collect::<Vec<_>>();
+        let mesh = Mesh {
+            buffer,
+            geometries: mesh_geometries,
+            blas: None,
+            // Assigned by `update_meshes`.
+            first_geometry_material: 0,
+        };
+        let materials = geometries
+            .iter()
+            .map(|geometry| geometry.material)
+            .collect();
+        (mesh, materials)
+    }
+
+    /// Removes a mesh added with `add_mesh`. It is dropped right away, so no frame may still be
+    /// using it, and the scene has to be updated with `update_meshes` before the next one.
+    pub fn remove_mesh(&mut self, id: MeshId) {
+        self.added_meshes.retain(|added| added.id != id);
     }
 
+    /// The added mesh rays see as a top level instance, `None` for the instances of the glTF
+    /// scene. Matches the instances of the last `update_meshes` until meshes are added or
+    /// removed.
+    pub fn added_mesh(&self, instance: usize) -> Option<MeshId> {
+        instance
+            .checked_sub(self.instances.len())
+            .and_then(|index| self.added_meshes.get(index))
+            .map(|added| added.id)
+    }
+
+    /// Number of meshes added with `add_mesh` and not removed since.
+    pub fn added_mesh_count(&self) -> usize {
+        self.added_meshes.len()
+    }
+
+    /// Makes the meshes added, removed and replaced since the last call visible to rays and
+    /// draws, by replacing the geometry buffers, the draws and the top level acceleration
+    /// structure, which is only refit if the meshes are the same. The old ones are dropped, so no
+    /// frame may still be using them, and every descriptor set holding them has to be recreated.
+    pub fn update_meshes(&mut self) {
+        let mut geometry_materials = self.geometry_materials.clone();
+        let mut geometry_addresses = self
+            .meshes
+            .iter()
+            .flat_map(|mesh| mesh.geometries.iter().map(GeometryAddresses::new))
+            .collect::<Vec<_>>();
+        self.draws.truncate(self.instance_draw_count);
+        for (index, added) in self.added_meshes.iter_mut().enumerate() {
+            added.mesh.first_geometry_material = geometry_materials.len() as u32;
+            geometry_materials.extend_from_slice(&added.materials);
+            let geometries = added.mesh.geometries.iter().zip(added.materials.iter());
+            for (i, (geometry, material)) in geometries.enumerate() {
+                geometry_addresses.push(GeometryAddresses::new(geometry));
+                self.draws.push(Draw {
+                    buffer: added.mesh.buffer.clone(),
+                    transform: added.transform,
+                    index_type: geometry.index_type,
+                    index_buffer_offset: geometry.index_buffer_offset,
+                    vertex_buffer_offset: geometry.vertex_buffer_offset,
+                    index_count: geometry.triangle_count * 3,
+                    material: *material,
+                    geometry: added.mesh.first_geometry_material + i as u32,
+                    bounds_min: geometry.bounds.min,
+                    bounds_max: geometry.bounds.max,
+                    instance: (self.instances.len() + index) as u32,
+                    visible: true,
+                });
+            }
+        }
+        self.geometry_material_buffer =
+            Self::create_geometry_material_buffer(&self.allocator, &geometry_materials);
+        self.geometry_buffer = Self::create_geometry_buffer(&self.allocator, &geometry_addresses);
+
+        match self.acceleration_structures.take() {
+            Some(acceleration_structures)
+                if acceleration_structures.added_instance_count == self.added_meshes.len() =>
+            {
+                acceleration_structures.refit(&self.added_meshes);
+                self.acceleration_structures = Some(acceleration_structures);
+            }
+            Some(acceleration_structures) => {
+                self.acceleration_structures = Some(Self::build_top_level(
+                    acceleration_structures.instance_buffers,
+                    &self.added_meshes,
+                    &self.allocator,
+                    &mut self.queue,
+                    &self.command_pool,
+                ));
+            }
+            None => {}
+        }
+    }
+
+    /// The top level nodes with meshes, indexed like the instances seen by rays.
+    pub fn instances(&self) -> &[Instance] {
+        &self.instances
+    }
+
+    pub fn document(&self) -> &gltf::Document {
+        &self.doc
+    }
+
+    /// Panics if the scene was loaded without ray tracing.
     pub fn tlas(&self) -> &Arc<safe_vk::AccelerationStructure> {
-        &self.top_level_acceleration_structure
+        &self
+            .acceleration_structures
+            .as_ref()
+            .expect("scene loaded without ray tracing")
+            .top_level
+    }
+
+    /// Lights for next-event estimation, see `Light` in raytrace.rgen.
+    pub fn light_buffer(&self) -> &Arc<safe_vk::Buffer> {
+        &self.light_buffer
+    }
+
+    /// Material factors, see `Material` in materials.glsl.
+    pub fn material_buffer(&self) -> &Arc<safe_vk::Buffer> {
+        &self.material_buffer
+    }
+
+    /// The materials of the glTF document followed by the default one and those added with
+    /// `add_materials`.
+    pub fn materials(&self) -> &[Material] {
+        &self.materials
     }
 
-    pub fn sole_buffer(&self) -> &Arc<safe_vk::Buffer> {
-        assert_eq!(self.buffers.len(), 1);
-        &self.buffers[0]
+    pub fn material_name(&self, index: usize) -> String {
+        let default_material = self.doc.materials().count();
+        if index > default_material {
+            return format!(
+                "{} {}",
+                index,
+                self.added_material_names[index - default_material - 1]
+            );
+        }
+        match self.doc.materials().nth(index) {
+            Some(material) => format!("{} {}", index, material.name().unwrap_or("unnamed")),
+            None => "default".to_owned(),
+        }
+    }
+
+    /// Appends named materials, for added meshes. Returns the index of the first one. The
+    /// material buffer is replaced, so every descriptor set holding it has to be recreated.
+    pub fn add_materials(&mut self, materials: &[(&str, Material)]) -> u32 {
+        let first = self.materials.len() as u32;
+        for (name, material) in materials {
+            self.added_material_names.push((*name).to_owned());
+            self.materials.push(*material);
+        }
+        self.material_buffer = Self::create_material_buffer(&self.allocator, &self.materials);
+        first
     }
 
-    pub fn sole_geometry_index_buffer_offset(&self) -> u64 {
-        self.meshes[0].geometries[0].index_buffer_offset
+    /// Appends tiles to the texture atlas, for the textures of added materials. Returns the
+    /// index of the first one. The atlas is replaced, so no frame may still be using it, and
+    /// every descriptor set holding it has to be recreated.
+    pub fn add_atlas_tiles(&mut self, tiles: &[AtlasTile]) -> u32 {
+        let first = self.atlas_tiles.len() as u32;
+        self.atlas_tiles.extend_from_slice(tiles);
+        self.atlas = Self::create_atlas(
+            &self.allocator,
+            &mut self.queue,
+            &self.command_pool,
+            &self.atlas_tiles,
+        );
+        first
     }
-    pub fn sole_geometry_vertex_buffer_offset(&self) -> u64 {
-        self.meshes[0].geometries[0].vertex_buffer_offset
+
+    /// The tiles textured materials multiply their base color by, see `surface_material` in
+    /// materials.glsl.
+    pub fn atlas(&self) -> &Arc<safe_vk::Image> {
+        &self.atlas
     }
+
+    /// Edits a material, which takes effect from the next `write_material` of it.
+    pub fn material_mut(&mut self, index: usize) -> &mut Material {
+        &mut self.materials[index]
+    }
+
+    /// Records writing a material to the material buffer, ordered with the frames reading it.
+    pub fn write_material(&self, recorder: &mut safe_vk::CommandRecorder, index: usize) {
+        let size = std::mem::size_of::<Material>();
+        recorder.update_buffer(
+            self.material_buffer.clone(),
+            (index * size) as u64,
+            bytemuck::bytes_of(&self.materials[index]),
+        );
+    }
+
+    /// Material index of every geometry, indexed by instance custom index plus geometry index.
+    pub fn geometry_material_buffer(&self) -> &Arc<safe_vk::Buffer> {
+        &self.geometry_material_buffer
+    }
+
+    /// Addresses of the indices and vertices of every geometry, see `GeometryAddresses` in
+    /// geometry.glsl. Indexed like the geometry material buffer.
+    pub fn geometry_buffer(&self) -> &Arc<safe_vk::Buffer> {
+        &self.geometry_buffer
+    }
+
+    /// Every geometry of the scene with its transform and material.
+    pub fn draws(&self) -> &[Draw] {
+        &self.draws
+    }
+}
+
+/// An instance of `blas` for the top level acceleration structure. Hidden instances get a zero
+/// mask, which no ray matches.
+fn acceleration_structure_instance(
+    transform: Mat4,
+    custom_index: u32,
+    visible: bool,
+    blas: &safe_vk::AccelerationStructure,
+) -> vk::AccelerationStructureInstanceKHR {
+    let mask: u32 = if visible { 0xFF } else { 0 };
+    vk::AccelerationStructureInstanceKHR {
+        transform: vk::TransformMatrixKHR {
+            matrix: transform.transpose().as_ref()[..12].try_into().unwrap(),
+        },
+        instance_custom_index_and_mask: custom_index | (mask << 24),
+        instance_shader_binding_table_record_offset_and_flags:
+            vk::GeometryInstanceFlagsKHR::TRIANGLE_FACING_CULL_DISABLE.as_raw() << 24,
+        acceleration_structure_reference: vk::AccelerationStructureReferenceKHR {
+            device_handle: blas.device_address(),
+        },
+    }
+}
+
+/// An instance of each added mesh, in order.
+fn added_instances(added_meshes: &[AddedMesh]) -> Vec<vk::AccelerationStructureInstanceKHR> {
+    added_meshes
+        .iter()
+        .map(|added| {
+            acceleration_structure_instance(
+                added.transform,
+                added.mesh.first_geometry_material,
+                true,
+                added.mesh.blas.as_ref().unwrap(),
+            )
+        })
+        .collect()
+}
+
+/// The instances of the top level acceleration structure, through the pointers in
+/// `pointer_buffer`.
+fn top_level_geometry(pointer_buffer: &safe_vk::Buffer) -> vk::AccelerationStructureGeometryKHR {
+    vk::AccelerationStructureGeometryKHR::builder()
+        .geometry_type(vk::GeometryTypeKHR::INSTANCES)
+        .flags(vk::GeometryFlagsKHR::OPAQUE)
+        .geometry(vk::AccelerationStructureGeometryDataKHR {
+            instances: vk::AccelerationStructureGeometryInstancesDataKHR::builder()
+                .array_of_pointers(true)
+                .data(vk::DeviceOrHostAddressConstKHR {
+                    device_address: pointer_buffer.device_address(),
+                })
+                .build(),
+        })
+        .build()
+}
+
+fn instance_bytes(instances: &[vk::AccelerationStructureInstanceKHR]) -> &[u8] {
+    unsafe {
+        std::slice::from_raw_parts(
+            instances.as_ptr() as *const u8,
+            std::mem::size_of_val(instances),
+        )
+    }
+}
+
+/// Decodes the images of `doc` to RGBA on the job system, in order. Images embedded as data URIs
+/// are left to `gltf::import_images`, which decodes them one after the other, uncached.
+fn import_images(
+    jobs: &jobs::JobSystem,
+    cache: Option<&AssetCache>,
+    doc: &gltf::Document,
+    base: Option<&Path>,
+    buffers: &[gltf::buffer::Data],
+) -> Result<Vec<image::RgbaImage>, gltf::Error> {
+    let images = doc.images().collect::<Vec<_>>();
+    let embedded = images.iter().any(|image| match image.source() {
+        gltf::image::Source::Uri { uri, .. } => uri.starts_with("data:"),
+        gltf::image::Source::View { .. } => false,
+    });
+    if embedded {
+        return Ok(gltf::import_images(doc, base, buffers)?
+            .into_iter()
+            .map(|data| match data.format {
+                gltf::image::Format::R8G8B8 => {
+                    image::RgbImage::from_raw(data.width, data.height, data.pixels)
+                        .map(|rgb| image::DynamicImage::ImageRgb8(rgb).to_rgba8())
+                        .unwrap()
+                }
+                gltf::image::Format::R8G8B8A8 => {
+                    image::RgbaImage::from_raw(data.width, data.height, data.pixels).unwrap()
+                }
+                _ => {
+                    unimplemented!()
+                }
+            })
+            .collect());
+    }
+
+    jobs.map(&images, |source| decode_image(source, cache, base, buffers))
+        .into_iter()
+        .collect()
+}
+
+fn decode_image(
+    source: &gltf::Image,
+    cache: Option<&AssetCache>,
+    base: Option<&Path>,
+    buffers: &[gltf::buffer::Data],
+) -> Result<image::RgbaImage, gltf::Error> {
+    let file;
+    let encoded = match source.source() {
+        gltf::image::Source::View { view, .. } => {
+            let data = &buffers[view.buffer().index()];
+            &data[view.offset()..view.offset() + view.length()]
+        }
+        gltf::image::Source::Uri { uri, .. } => {
+            file = std::fs::read(base.unwrap_or_else(|| Path::new("./")).join(uri))
+                .map_err(gltf::Error::Io)?;
+            &file[..]
+        }
+    };
+    let cache = match cache {
+        Some(cache) => cache,
+        None => {
+            return Ok(image::load_from_memory(encoded)
+                .map_err(gltf::Error::Image)?
+                .to_rgba8())
+        }
+    };
+
+    let key = KeyBuilder::new(TEXTURE_CACHE_KIND).bytes(encoded).finish();
+    let artifact = cache.get_or_insert_with(key, || -> Result<_, gltf::Error> {
+        let decoded = image::load_from_memory(encoded)
+            .map_err(gltf::Error::Image)?
+            .to_rgba8();
+        // The width and height, then the pixels.
+        let mut artifact = Vec::with_capacity(8 + decoded.len());
+        artifact.extend_from_slice(&decoded.width().to_le_bytes());
+        artifact.extend_from_slice(&decoded.height().to_le_bytes());
+        artifact.extend_from_slice(&decoded);
+        Ok(artifact)
+    })?;
+    let width = u32::from_le_bytes(artifact[0..4].try_into().unwrap());
+    let height = u32::from_le_bytes(artifact[4..8].try_into().unwrap());
+    Ok(image::RgbaImage::from_raw(width, height, artifact[8..].to_vec()).unwrap())
 }
@@ -1,18 +1,12 @@
 #include "common.glsl"
+#include "brdf.glsl"
+#include "materials.glsl"
+#include "geometry.glsl"
 
 layout(location = 0) rayPayloadInEXT PassableInfo payload;
 
 hitAttributeEXT vec2 attributes;
 
-layout(binding = 2, set = 0, scalar) buffer Indices
-{
-    uint16_t indices[];
-};
-layout(binding = 3, set = 0, scalar) buffer Vertices
-{
-    vec3 vertices[];
-};
-
 struct HitInfo {
     vec3 object_position;
     vec3 world_normal;
@@ -27,15 +21,9 @@ HitInfo get_object_hit_info()
     // Get the ID of the triangle
     const int primitiveID = gl_PrimitiveID;
 
-    // Get the indices of the vertices of the triangle
-    const uint i0 = uint(indices[3 * primitiveID + 0]);
-    const uint i1 = uint(indices[3 * primitiveID + 1]);
-    const uint i2 = uint(indices[3 * primitiveID + 2]);
-
     // Get the vertices of the triangle
-    const vec3 v0 = vertices[i0];
-    const vec3 v1 = vertices[i1];
-    const vec3 v2 = vertices[i2];
+    vec3 v0, v1, v2;
+    triangle_vertices(uint(gl_InstanceCustomIndexEXT + gl_GeometryIndexEXT), primitiveID, v0, v1, v2);
 
     // Get the barycentric coordinates of the intersection
     vec3 barycentrics = vec3(0.0, attributes.x, attributes.y);
@@ -66,22 +54,3 @@ HitInfo get_object_hit_info()
 
     return result;
 }
-
-// Returns a random diffuse (Lambertian) reflection for a surface with the
-// given normal, using the given random number generator state. This is
-// cosine-weighted, so directions closer to the normal are more likely to
-// be chosen.
-vec3 diffuseReflection(vec3 normal, inout uint rngState)
-{
-    // For a random diffuse bounce direction, we follow the approach of
-    // Ray Tracing in One Weekend, and generate a random point on a sphere
-    // of radius 1 centered at the normal. This uses the random_unit_vector
-    // function from chapter 8.5:
-    const float theta = 2.0 * k_pi * stepAndOutputRNGFloat(rngState); // Random in [0, 2pi]
-    const float u = 2.0 * stepAndOutputRNGFloat(rngState) - 1.0; // Random in [-1, 1]
-    const float r = sqrt(1.0 - u * u);
-    const vec3 direction = normal + vec3(r * cos(theta), r * sin(theta), u);
-
-    // Then normalize the ray direction:
-    return normalize(direction);
-}
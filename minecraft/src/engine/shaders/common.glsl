@@ -3,7 +3,14 @@ struct PassableInfo {
     vec3 color; // The reflectivity of the surface.
     vec3 rayOrigin; // The new ray origin in world-space.
     vec3 rayDirection; // The new ray direction in world-space.
+    vec3 normal; // The surface normal facing the incoming ray.
+    vec3 baseColor; // Material inputs of the BRDF at the hit.
+    float metallic;
+    float roughness;
+    vec3 emission; // Radiance emitted by the surface.
+    float pdf; // Solid angle density the new ray direction was sampled with.
     uint rngState; // State of the random number generator.
+    uint objectId; // Instance custom index plus geometry index of the hit.
     bool rayHitSky; // True if the ray hit the sky.
 };
 
@@ -18,3 +25,10 @@ float stepAndOutputRNGFloat(inout uint rngState)
 }
 
 const float k_pi = 3.14159265;
+
+// Rotates directions given around +Z to be around axis.
+mat3 tangent_frame(vec3 axis)
+{
+    const vec3 tangent = normalize(cross(abs(axis.x) > 0.5 ? vec3(0, 1, 0) : vec3(1, 0, 0), axis));
+    return mat3(tangent, cross(axis, tangent), axis);
+}
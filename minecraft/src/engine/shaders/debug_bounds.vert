@@ -0,0 +1,43 @@
+#version 460
+
+layout(binding = 5, set = 0) uniform Camera
+{
+    mat4 view;
+    mat4 projection;
+    mat4 inverse_view_projection;
+    vec3 origin;
+    float fov;
+    float aperture;
+    float focus_distance;
+    float exposure;
+}
+camera;
+
+layout(push_constant) uniform PushConsts
+{
+    mat4 model;
+    vec3 bounds_min;
+    float _padding;
+    vec3 bounds_max;
+    float _padding_2;
+    vec4 color;
+}
+push_constants;
+
+layout(location = 0) out vec4 out_color;
+
+// The 12 edges of the bounding box as a line list of 24 vertices, without a vertex buffer.
+void main()
+{
+    const uint edge = uint(gl_VertexIndex) / 2;
+    // 4 edges run along every axis, between corners that differ only in that axis.
+    const uint axis = edge / 4;
+    const uint others = edge % 4;
+    const uint low_mask = (1u << axis) - 1u;
+    const uint corner = ((uint(gl_VertexIndex) % 2) << axis) | (others & low_mask) | ((others & ~low_mask) << 1);
+    const vec3 position = mix(push_constants.bounds_min, push_constants.bounds_max, vec3(corner & 1, (corner >> 1) & 1, (corner >> 2) & 1));
+    gl_Position = camera.projection * camera.view * push_constants.model * vec4(position, 1.0);
+    // The projection has y pointing up, Vulkan framebuffers have it pointing down.
+    gl_Position.y = -gl_Position.y;
+    out_color = push_constants.color;
+}
@@ -0,0 +1,17 @@
+// The first hit of the first sample of every pixel, written by raytrace.rgen for the ReSTIR
+// passes. hit is 0 where the ray escaped to the sky.
+struct GBufferTexel {
+    vec3 position;
+    float hit;
+    vec3 normal;
+    float metallic;
+    vec3 base_color;
+    float roughness;
+    vec3 view;
+    float _padding;
+};
+
+layout(binding = 11, set = 0, scalar) buffer GBuffer
+{
+    GBufferTexel gbuffer[];
+};
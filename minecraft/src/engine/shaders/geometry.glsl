@@ -0,0 +1,63 @@
+// The triangles of every geometry of the scene, through the device addresses of their index,
+// vertex and face material data, indexed like geometry_materials in materials.glsl. Include
+// after materials.glsl and after enabling GL_EXT_buffer_reference, GL_EXT_buffer_reference_uvec2
+// and GL_EXT_shader_16bit_storage.
+
+layout(buffer_reference, scalar, buffer_reference_align = 2) readonly buffer Indices
+{
+    uint16_t indices[];
+};
+layout(buffer_reference, scalar, buffer_reference_align = 4) readonly buffer WideIndices
+{
+    uint indices[];
+};
+layout(buffer_reference, scalar, buffer_reference_align = 4) readonly buffer Vertices
+{
+    vec3 vertices[];
+};
+layout(buffer_reference, scalar, buffer_reference_align = 4) readonly buffer FaceMaterials
+{
+    uint face_materials[];
+};
+
+// Matches `GeometryAddresses` in scene.rs.
+struct GeometryAddresses
+{
+    uvec2 indices;
+    uvec2 vertices;
+    uvec2 face_materials; // Zero for geometries of one material.
+    uint wide_indices; // Whether the indices are 32-bit rather than 16-bit.
+    uint padding;
+};
+
+layout(binding = 2, set = 0, scalar) buffer Geometries
+{
+    GeometryAddresses geometries[];
+};
+
+// The object space position of the vertex at `index` in the index buffer of a geometry.
+vec3 geometry_vertex(uint geometry, int index)
+{
+    const GeometryAddresses addresses = geometries[geometry];
+    const uint vertex = addresses.wide_indices != 0 ? WideIndices(addresses.indices).indices[index]
+                                                    : uint(Indices(addresses.indices).indices[index]);
+    return Vertices(addresses.vertices).vertices[vertex];
+}
+
+// The object space corners of a triangle of a geometry.
+void triangle_vertices(uint geometry, int primitive, out vec3 v0, out vec3 v1, out vec3 v2)
+{
+    v0 = geometry_vertex(geometry, 3 * primitive + 0);
+    v1 = geometry_vertex(geometry, 3 * primitive + 1);
+    v2 = geometry_vertex(geometry, 3 * primitive + 2);
+}
+
+// The material index of a triangle of a geometry, its own one for geometries with face materials.
+uint triangle_material(uint geometry, int primitive)
+{
+    const uvec2 address = geometries[geometry].face_materials;
+    if (address == uvec2(0)) {
+        return geometry_materials[geometry];
+    }
+    return FaceMaterials(address).face_materials[primitive];
+}
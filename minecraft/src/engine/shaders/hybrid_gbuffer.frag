@@ -0,0 +1,13 @@
+#version 460
+#extension GL_EXT_buffer_reference : require
+#extension GL_EXT_buffer_reference_uvec2 : require
+#extension GL_EXT_scalar_block_layout : require
+#extension GL_EXT_shader_16bit_storage : require
+#extension GL_GOOGLE_include_directive : require
+
+#include "hybrid_gbuffer.glsl"
+
+void main()
+{
+    write_gbuffer(gl_PrimitiveID);
+}
@@ -0,0 +1,43 @@
+// The G-buffer writes of hybrid_gbuffer.frag and hybrid_gbuffer_pulled.frag, which only differ in
+// where the primitive comes from. Include after enabling GL_EXT_buffer_reference,
+// GL_EXT_buffer_reference_uvec2, GL_EXT_scalar_block_layout and GL_EXT_shader_16bit_storage.
+
+#include "materials.glsl"
+#include "geometry.glsl"
+
+layout(location = 0) in vec3 world_position;
+
+layout(location = 0) out vec4 out_position;
+layout(location = 1) out vec4 out_normal;
+
+layout(binding = 5, set = 0) uniform Camera
+{
+    mat4 view;
+    mat4 projection;
+    mat4 inverse_view_projection;
+    vec3 origin;
+    float fov;
+    float aperture;
+    float focus_distance;
+    float exposure;
+}
+camera;
+
+layout(push_constant) uniform PushConsts
+{
+    mat4 model;
+    uint geometry;
+}
+push_constants;
+
+// Writes the first hit on triangle `primitive` of the geometry drawn for hybrid_shade.comp. Meshes
+// only have positions, so the normal is the flat one of the triangle, facing the camera like the
+// ones of closest_hit_common.glsl.
+void write_gbuffer(int primitive)
+{
+    vec3 normal = normalize(cross(dFdx(world_position), dFdy(world_position)));
+    normal = faceforward(normal, world_position - camera.origin, normal);
+    out_position = vec4(world_position, 1.0);
+    // Bit cast so that the index survives the float attachment exactly.
+    out_normal = vec4(normal, uintBitsToFloat(triangle_material(push_constants.geometry, primitive)));
+}
@@ -0,0 +1,60 @@
+// The light buffer built by scene.rs. Include after common.glsl.
+
+const uint LIGHT_SUN = 0;
+const uint LIGHT_DIRECTIONAL = 1;
+const uint LIGHT_POINT = 2;
+const uint LIGHT_SPOT = 3;
+
+// `direction` is where the light travels. Suns cover a disk of angular radius acos(cos_outer)
+// around -direction in the sky, the other types are points or directions that can't be hit.
+struct Light {
+    vec3 position;
+    uint type;
+    vec3 direction;
+    float cos_outer;
+    vec3 radiance;
+    float cos_inner;
+};
+
+layout(binding = 6, set = 0, scalar) buffer Lights
+{
+    Light lights[];
+};
+
+// Uniformly samples a direction within acos(cos_max) of axis.
+vec3 sample_cone(vec3 axis, float cos_max, inout uint rngState)
+{
+    const float cos_theta = mix(1.0, cos_max, stepAndOutputRNGFloat(rngState));
+    const float sin_theta = sqrt(max(0.0, 1.0 - cos_theta * cos_theta));
+    const float phi = 2.0 * k_pi * stepAndOutputRNGFloat(rngState);
+    return normalize(tangent_frame(axis) * vec3(sin_theta * cos(phi), sin_theta * sin(phi), cos_theta));
+}
+
+float sun_solid_angle(Light sun)
+{
+    return 2.0 * k_pi * (1.0 - sun.cos_outer);
+}
+
+// Light arriving at position: the radiance along sun_direction for suns, which must lie on the
+// disk, and the irradiance for the other types, which are points or directions.
+vec3 light_incidence(Light light, vec3 position, vec3 sun_direction, out vec3 direction, out float distance)
+{
+    distance = 10000.0;
+    if (light.type == LIGHT_SUN) {
+        direction = sun_direction;
+        return light.radiance;
+    }
+    if (light.type == LIGHT_DIRECTIONAL) {
+        direction = -light.direction;
+        return light.radiance;
+    }
+    const vec3 to_light = light.position - position;
+    distance = length(to_light);
+    direction = to_light / distance;
+    vec3 irradiance = light.radiance / (distance * distance);
+    if (light.type == LIGHT_SPOT) {
+        const float cone = clamp((dot(-direction, light.direction) - light.cos_outer) / (light.cos_inner - light.cos_outer), 0.0, 1.0);
+        irradiance *= cone * cone;
+    }
+    return irradiance;
+}
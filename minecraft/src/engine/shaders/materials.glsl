@@ -0,0 +1,49 @@
+// The material buffers and the texture atlas built by scene.rs.
+
+// glTF material factors, glTF textures aren't loaded. Materials added at runtime can multiply
+// the base color by a tile of the texture atlas.
+struct Material {
+    vec4 base_color;
+    vec3 emissive;
+    float metallic;
+    float roughness;
+    uint texture; // One more than the index of the atlas tile, 0 without a texture.
+};
+
+layout(binding = 7, set = 0, scalar) buffer Materials
+{
+    Material materials[];
+};
+// Material index of every geometry, starting at the instance custom index for each mesh.
+layout(binding = 8, set = 0, scalar) buffer GeometryMaterials
+{
+    uint geometry_materials[];
+};
+
+// Square tiles side by side in one row, see `ATLAS_TILE_SIZE` in scene.rs.
+layout(binding = 26, set = 0, rgba8) uniform readonly image2D atlas;
+#define ATLAS_TILE_SIZE 16
+
+// The material at a world space point of a surface, with the base color of textured materials
+// multiplied by their tile. Tiles are projected onto the axis aligned plane the normal is
+// closest to, one per unit, the right way up on vertical planes, which fits the faces of blocks.
+Material surface_material(uint index, vec3 position, vec3 normal)
+{
+    Material material = materials[index];
+    if (material.texture == 0) {
+        return material;
+    }
+    const vec3 weights = abs(normal);
+    vec2 uv;
+    if (weights.y >= weights.x && weights.y >= weights.z) {
+        uv = position.xz;
+    } else if (weights.x >= weights.z) {
+        uv = vec2(position.z, -position.y);
+    } else {
+        uv = vec2(position.x, -position.y);
+    }
+    const ivec2 texel = min(ivec2(fract(uv) * ATLAS_TILE_SIZE), ivec2(ATLAS_TILE_SIZE - 1));
+    const int tile = int(material.texture) - 1;
+    material.base_color.rgb *= imageLoad(atlas, ivec2(tile * ATLAS_TILE_SIZE + texel.x, texel.y)).rgb;
+    return material;
+}
@@ -0,0 +1,28 @@
+// Surface lookups for the closest hits of ray queries, the counterpart of
+// closest_hit_common.glsl for compute shaders. Include after materials.glsl and enable
+// GL_EXT_buffer_reference, GL_EXT_buffer_reference_uvec2 and GL_EXT_shader_16bit_storage.
+
+#include "geometry.glsl"
+
+// The world space position, the normal facing the ray and the material index at the committed
+// intersection of query, which must have one.
+void committed_surface(rayQueryEXT query, vec3 direction, out vec3 position, out vec3 normal, out uint material)
+{
+    const uint geometry = uint(rayQueryGetIntersectionInstanceCustomIndexEXT(query, true)
+        + rayQueryGetIntersectionGeometryIndexEXT(query, true));
+    const int primitive = rayQueryGetIntersectionPrimitiveIndexEXT(query, true);
+    vec3 v0, v1, v2;
+    triangle_vertices(geometry, primitive, v0, v1, v2);
+
+    vec3 barycentrics = vec3(0.0, rayQueryGetIntersectionBarycentricsEXT(query, true));
+    barycentrics.x = 1.0 - barycentrics.y - barycentrics.z;
+    const vec3 object_position = v0 * barycentrics.x + v1 * barycentrics.y + v2 * barycentrics.z;
+    position = rayQueryGetIntersectionObjectToWorldEXT(query, true) * vec4(object_position, 1.0);
+
+    // Normals use the transpose of the inverse matrix, see closest_hit_common.glsl.
+    const vec3 object_normal = cross(v1 - v0, v2 - v0);
+    normal = normalize((object_normal * rayQueryGetIntersectionWorldToObjectEXT(query, true)).xyz);
+    normal = faceforward(normal, direction, normal);
+
+    material = triangle_material(geometry, primitive);
+}
@@ -0,0 +1,35 @@
+#version 460
+
+layout(location = 0) in vec3 position;
+
+layout(location = 0) out vec3 world_position;
+
+layout(binding = 5, set = 0) uniform Camera
+{
+    mat4 view;
+    mat4 projection;
+    mat4 inverse_view_projection;
+    vec3 origin;
+    float fov;
+    float aperture;
+    float focus_distance;
+    float exposure;
+}
+camera;
+
+layout(push_constant) uniform PushConsts
+{
+    mat4 model;
+    uint geometry;
+}
+push_constants;
+
+// Transforms the geometries for the G-buffer pass of hybrid.rs and the forward pass of raster.rs.
+void main()
+{
+    const vec4 world = push_constants.model * vec4(position, 1.0);
+    world_position = world.xyz;
+    gl_Position = camera.projection * camera.view * world;
+    // The projection has y pointing up, Vulkan framebuffers have it pointing down.
+    gl_Position.y = -gl_Position.y;
+}
@@ -0,0 +1,72 @@
+// The forward shading of raster_forward.frag and raster_forward_pulled.frag, which only differ in
+// where the primitive comes from. Include after enabling GL_EXT_buffer_reference,
+// GL_EXT_buffer_reference_uvec2, GL_EXT_scalar_block_layout and GL_EXT_shader_16bit_storage.
+
+#include "common.glsl"
+#include "brdf.glsl"
+#include "environment.glsl"
+#include "lights.glsl"
+#include "materials.glsl"
+#include "geometry.glsl"
+
+layout(location = 0) in vec3 world_position;
+
+layout(location = 0) out vec4 out_color;
+
+layout(binding = 5, set = 0) uniform Camera
+{
+    mat4 view;
+    mat4 projection;
+    mat4 inverse_view_projection;
+    vec3 origin;
+    float fov;
+    float aperture;
+    float focus_distance;
+    float exposure;
+}
+camera;
+
+layout(push_constant) uniform PushConsts
+{
+    mat4 model;
+    uint geometry;
+    float environment_rotation;
+    float environment_intensity;
+}
+push_constants;
+
+vec3 sky_radiance(vec3 direction)
+{
+    return push_constants.environment_intensity * environment_radiance(direction, push_constants.environment_rotation);
+}
+
+// Shades the surface of triangle `primitive` of the geometry drawn without rays: emission, every
+// light unshadowed, and the sky as seen along the normal and the mirror direction in place of the
+// diffuse and specular light it reflects.
+void shade(int primitive)
+{
+    vec3 normal = normalize(cross(dFdx(world_position), dFdy(world_position)));
+    normal = faceforward(normal, world_position - camera.origin, normal);
+    const vec3 view = normalize(camera.origin - world_position);
+    const Material material = surface_material(triangle_material(push_constants.geometry, primitive), world_position, normal);
+    const vec3 base_color = material.base_color.rgb;
+
+    vec3 radiance = material.emissive;
+    for (uint i = 0; i < lights.length(); i++) {
+        const Light light = lights[i];
+        vec3 direction;
+        float distance;
+        // Suns are lit from the center of their disk.
+        vec3 irradiance = light_incidence(light, world_position, -light.direction, direction, distance);
+        if (light.type == LIGHT_SUN) {
+            irradiance *= sun_solid_angle(light);
+        }
+        radiance += brdf_eval(normal, view, direction, base_color, material.metallic, material.roughness) * irradiance;
+    }
+
+    const vec3 f0 = mix(vec3(0.04), base_color, material.metallic);
+    const vec3 fresnel = fresnel_schlick(f0, max(dot(normal, view), 0.0));
+    const vec3 diffuse = (1.0 - fresnel) * (1.0 - material.metallic) * base_color;
+    radiance += diffuse * sky_radiance(normal) + fresnel * sky_radiance(reflect(-view, normal));
+    out_color = vec4(radiance, 1.0);
+}
@@ -0,0 +1,46 @@
+#version 460
+#extension GL_EXT_buffer_reference : require
+#extension GL_EXT_buffer_reference_uvec2 : require
+#extension GL_EXT_scalar_block_layout : require
+#extension GL_EXT_shader_16bit_storage : require
+#extension GL_GOOGLE_include_directive : require
+
+#include "materials.glsl"
+#include "geometry.glsl"
+
+layout(location = 0) out vec3 world_position;
+layout(location = 1) flat out int primitive;
+
+layout(binding = 5, set = 0) uniform Camera
+{
+    mat4 view;
+    mat4 projection;
+    mat4 inverse_view_projection;
+    vec3 origin;
+    float fov;
+    float aperture;
+    float focus_distance;
+    float exposure;
+}
+camera;
+
+layout(push_constant) uniform PushConsts
+{
+    mat4 model;
+    uint geometry;
+}
+push_constants;
+
+// raster.vert for devices without the geometry shader feature, whose fragment shaders can't read
+// gl_PrimitiveID. Drawn without vertex or index buffers, one vertex per index of the geometry, so
+// the vertex index tells the triangle, which is passed on to raster_forward_pulled.frag and
+// hybrid_gbuffer_pulled.frag.
+void main()
+{
+    const vec4 world = push_constants.model * vec4(geometry_vertex(push_constants.geometry, gl_VertexIndex), 1.0);
+    world_position = world.xyz;
+    primitive = gl_VertexIndex / 3;
+    gl_Position = camera.projection * camera.view * world;
+    // The projection has y pointing up, Vulkan framebuffers have it pointing down.
+    gl_Position.y = -gl_Position.y;
+}
@@ -0,0 +1,47 @@
+#version 460
+#extension GL_EXT_scalar_block_layout : require
+#extension GL_GOOGLE_include_directive : require
+
+#include "common.glsl"
+#include "environment.glsl"
+#include "lights.glsl"
+
+layout(location = 0) in vec2 ndc;
+
+layout(location = 0) out vec4 out_color;
+
+layout(binding = 5, set = 0) uniform Camera
+{
+    mat4 view;
+    mat4 projection;
+    mat4 inverse_view_projection;
+    vec3 origin;
+    float fov;
+    float aperture;
+    float focus_distance;
+    float exposure;
+}
+camera;
+
+layout(push_constant) uniform PushConsts
+{
+    mat4 model;
+    uint geometry;
+    float environment_rotation;
+    float environment_intensity;
+}
+push_constants;
+
+// Fills the background with the environment and the sun disks, before the geometry is drawn.
+void main()
+{
+    const vec4 target = camera.inverse_view_projection * vec4(ndc, 1.0, 1.0);
+    const vec3 direction = normalize(target.xyz / target.w - camera.origin);
+    vec3 radiance = push_constants.environment_intensity * environment_radiance(direction, push_constants.environment_rotation);
+    for (uint i = 0; i < lights.length(); i++) {
+        if (lights[i].type == LIGHT_SUN && dot(direction, -lights[i].direction) >= lights[i].cos_outer) {
+            radiance += lights[i].radiance;
+        }
+    }
+    out_color = vec4(radiance, 1.0);
+}
@@ -0,0 +1,12 @@
+#version 460
+
+layout(location = 0) out vec2 ndc;
+
+// A triangle covering the screen, drawn with 3 vertices and no vertex buffer.
+void main()
+{
+    const vec2 position = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2) * 2.0 - 1.0;
+    gl_Position = vec4(position, 1.0, 1.0);
+    // The projection has y pointing up, Vulkan framebuffers have it pointing down.
+    ndc = vec2(position.x, -position.y);
+}
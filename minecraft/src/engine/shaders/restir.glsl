@@ -0,0 +1,111 @@
+// Shared by the ReSTIR DI passes, see restir.rs. Include after common.glsl, brdf.glsl,
+// lights.glsl and gbuffer.glsl.
+
+layout(binding = 0, set = 0, rgba32f) uniform image2D storage_image;
+layout(binding = 1, set = 0) uniform accelerationStructureEXT tlas;
+
+// A light sample picked out of all the candidates seen so far.
+struct Reservoir {
+    uint light_index;
+    vec3 sun_direction; // The point on the disk when the light is a sun.
+    float weight_sum;
+    float candidate_count;
+    float weight; // Contribution weight of the sample, 0 if it's occluded.
+    float target_pdf; // Of the sample at the pixel owning the reservoir.
+};
+
+layout(binding = 12, set = 0, scalar) buffer CurrentReservoirs
+{
+    Reservoir current_reservoirs[];
+};
+// Written at the end of the frame and read by the next temporal pass.
+layout(binding = 13, set = 0, scalar) buffer PreviousReservoirs
+{
+    Reservoir previous_reservoirs[];
+};
+
+struct PushConstants {
+    mat4 previous_view_projection;
+    uint batch_sample_count;
+    uint frame_index;
+    uint spatial_enabled;
+};
+
+layout(push_constant) uniform PushConsts
+{
+    PushConstants push_constants;
+};
+
+const uint INITIAL_CANDIDATES = 32;
+// Caps the temporal history relative to the new candidates so that stale samples fade out.
+const float TEMPORAL_HISTORY = 20.0;
+const uint SPATIAL_NEIGHBORS = 5;
+const float SPATIAL_RADIUS = 30.0;
+
+Reservoir empty_reservoir()
+{
+    Reservoir reservoir;
+    reservoir.light_index = 0;
+    reservoir.sun_direction = vec3(0.0);
+    reservoir.weight_sum = 0.0;
+    reservoir.candidate_count = 0.0;
+    reservoir.weight = 0.0;
+    reservoir.target_pdf = 0.0;
+    return reservoir;
+}
+
+// Unshadowed light from the sample, weighted by the BRDF and cosine term of surface.
+vec3 sample_contribution(GBufferTexel surface, uint light_index, vec3 sun_direction)
+{
+    vec3 direction;
+    float distance;
+    const vec3 incidence = light_incidence(lights[light_index], surface.position, sun_direction, direction, distance);
+    return incidence * brdf_eval(surface.normal, surface.view, direction, surface.base_color, surface.metallic, surface.roughness);
+}
+
+float target_pdf(GBufferTexel surface, uint light_index, vec3 sun_direction)
+{
+    return luminance(sample_contribution(surface, light_index, sun_direction));
+}
+
+void update_reservoir(inout Reservoir reservoir, uint light_index, vec3 sun_direction, float target, float weight, float count, inout uint rngState)
+{
+    reservoir.weight_sum += weight;
+    reservoir.candidate_count += count;
+    if (stepAndOutputRNGFloat(rngState) * reservoir.weight_sum < weight) {
+        reservoir.light_index = light_index;
+        reservoir.sun_direction = sun_direction;
+        reservoir.target_pdf = target;
+    }
+}
+
+void finalize_reservoir(inout Reservoir reservoir)
+{
+    const float denominator = reservoir.candidate_count * reservoir.target_pdf;
+    reservoir.weight = denominator > 0.0 ? reservoir.weight_sum / denominator : 0.0;
+}
+
+// Adds the sample of a reservoir from another pixel or frame, re-evaluated at surface.
+void combine_reservoir(inout Reservoir reservoir, Reservoir other, GBufferTexel surface, inout uint rngState)
+{
+    const float target = other.candidate_count > 0.0 ? target_pdf(surface, other.light_index, other.sun_direction) : 0.0;
+    update_reservoir(reservoir, other.light_index, other.sun_direction, target, target * other.weight * other.candidate_count, other.candidate_count, rngState);
+}
+
+bool sample_visible(GBufferTexel surface, Reservoir reservoir)
+{
+    vec3 direction;
+    float distance;
+    light_incidence(lights[reservoir.light_index], surface.position, reservoir.sun_direction, direction, distance);
+
+    rayQueryEXT query;
+    rayQueryInitializeEXT(query, tlas, gl_RayFlagsOpaqueEXT | gl_RayFlagsTerminateOnFirstHitEXT, 0xFF, surface.position, 0.001, direction, distance - 0.001);
+    while (rayQueryProceedEXT(query)) {
+    }
+    return rayQueryGetIntersectionTypeEXT(query, true) == gl_RayQueryCommittedIntersectionNoneEXT;
+}
+
+uint restir_seed(uvec2 pixel, uvec2 resolution, uint pass_index)
+{
+    return ((push_constants.frame_index * 3 + pass_index) * resolution.y + pixel.y) * resolution.x + pixel.x;
+}
@@ -0,0 +1,178 @@
+// Shared by the wavefront path tracing stages, see wavefront.rs. Include after common.glsl.
+//
+// Every pixel owns the path at its texel index. Stages run over queues of path indices, and
+// append to the queues of the next stage along with the group count it's dispatched with.
+
+layout(binding = 0, set = 0, rgba32f) uniform image2D storage_image;
+layout(binding = 1, set = 0) uniform accelerationStructureEXT tlas;
+
+layout(binding = 5, set = 0) uniform Camera
+{
+    mat4 view;
+    mat4 projection;
+    mat4 inverse_view_projection;
+    vec3 origin;
+    float fov;
+    float aperture;
+    float focus_distance;
+    float exposure;
+}
+camera;
+
+struct PathState {
+    vec3 origin; // Of the next ray.
+    uint rng_state;
+    vec3 direction; // Of the next ray.
+    float last_brdf_pdf; // Density of direction when the last surface also sampled the lights.
+    vec3 throughput;
+    float last_environment_probability;
+    vec3 radiance; // Gathered by the current sample so far.
+    float _padding;
+};
+
+const uint HIT_MISS = 0xFFFFFFFF;
+
+// Where the ray of a path ended, written by the intersect stage.
+struct Hit {
+    vec3 position;
+    uint material; // HIT_MISS if the ray escaped to the sky.
+    vec3 normal; // Facing the ray.
+    float _padding;
+};
+
+// A light sample of the shade stage, added to the radiance of path unless it's occluded.
+struct ShadowRay {
+    vec3 origin;
+    float distance;
+    vec3 direction;
+    uint path;
+    vec3 contribution;
+    float _padding;
+};
+
+struct DispatchIndirectCommand {
+    uint x;
+    uint y;
+    uint z;
+};
+
+// The extension queues of even and odd bounces, then the shadow queue.
+const uint SHADOW_QUEUE = 2;
+
+layout(binding = 15, set = 0, scalar) buffer Paths
+{
+    PathState paths[];
+};
+
+layout(binding = 16, set = 0, scalar) buffer Queues
+{
+    DispatchIndirectCommand queue_dispatches[3];
+    uint queue_lengths[3];
+};
+
+// Both extension queues, one after the other.
+layout(binding = 17, set = 0, scalar) buffer PathQueues
+{
+    uint path_queues[];
+};
+
+layout(binding = 18, set = 0, scalar) buffer ShadowRays
+{
+    ShadowRay shadow_rays[];
+};
+
+layout(binding = 19, set = 0, scalar) buffer Hits
+{
+    Hit hits[];
+};
+
+struct PushConstants {
+    uint sample_count;
+    uint nee_enabled;
+    float environment_rotation;
+    float environment_intensity;
+    // Writes focus_probe_distance at this pixel, never if it's out of the image.
+    uint focus_probe_x;
+    uint focus_probe_y;
+    uint adaptive_enabled;
+    float adaptive_threshold;
+    uint adaptive_min_samples;
+    uint max_bounces;
+    uint russian_roulette_start;
+    uint bounce; // The queue of the stage is bounce % 2, the next bounce goes to the other one.
+};
+
+layout(push_constant) uniform PushConsts
+{
+    PushConstants push_constants;
+};
+
+const uint QUEUE_GROUP_SIZE = 64;
+
+// Chance of a light sample going to the environment instead of the light buffer.
+const float ENVIRONMENT_SAMPLE_PROBABILITY = 0.5;
+
+uint queue_capacity()
+{
+    const uvec2 resolution = imageSize(storage_image);
+    return resolution.x * resolution.y;
+}
+
+// Grows the dispatch of the queue to cover slot.
+void cover_slot(uint queue, uint slot)
+{
+    atomicMax(queue_dispatches[queue].x, slot / QUEUE_GROUP_SIZE + 1);
+}
+
+void push_path(uint queue, uint path)
+{
+    const uint slot = atomicAdd(queue_lengths[queue], 1);
+    path_queues[queue * queue_capacity() + slot] = path;
+    cover_slot(queue, slot);
+}
+
+void push_shadow_ray(ShadowRay ray)
+{
+    const uint slot = atomicAdd(queue_lengths[SHADOW_QUEUE], 1);
+    shadow_rays[slot] = ray;
+    cover_slot(SHADOW_QUEUE, slot);
+}
+
+uint current_queue()
+{
+    return push_constants.bounce % 2;
+}
+
+float max3(vec3 v)
+{
+    return max(max(v.x, v.y), v.z);
+}
+
+vec3 camera_forward()
+{
+    return -vec3(camera.view[0][2], camera.view[1][2], camera.view[2][2]);
+}
+
+// Direction of the ray through a point of the image given in NDC, from the center of the lens.
+vec3 primary_ray_direction(vec2 ndc)
+{
+    // Unproject a point on the far plane and shoot the ray towards it.
+    const vec4 target = camera.inverse_view_projection * vec4(ndc, 1.0, 1.0);
+    return normalize(target.xyz / target.w - camera.origin);
+}
+
+// Thin lens model: moves the ray origin to a random point of the aperture, aiming at where the
+// pinhole ray crosses the focus plane.
+void sample_lens(inout vec3 origin, inout vec3 direction, inout uint rngState)
+{
+    if (camera.aperture <= 0.0) {
+        return;
+    }
+    const vec3 focus_point = origin + direction * camera.focus_distance / dot(direction, camera_forward());
+    const float radius = camera.aperture * sqrt(stepAndOutputRNGFloat(rngState));
+    const float angle = 2.0 * k_pi * stepAndOutputRNGFloat(rngState);
+    const vec3 right = vec3(camera.view[0][0], camera.view[1][0], camera.view[2][0]);
+    const vec3 up = vec3(camera.view[0][1], camera.view[1][1], camera.view[2][1]);
+    origin += (right * cos(angle) + up * sin(angle)) * radius;
+    direction = normalize(focus_point - origin);
+}
@@ -0,0 +1,186 @@
+use std::sync::Arc;
+
+use safe_vk::vk;
+
+/// Windows resets the GPU once a single dispatch runs for longer than this many milliseconds
+/// (TDR), other platforms have similar limits.
+const TIMEOUT_MS: f64 = 2000.0;
+
+/// Dispatches taking more than this part of the timeout are split in two.
+const SPLIT_FRACTION: f64 = 0.25;
+
+/// Dispatches taking less than this part of the timeout are merged back in pairs. Far enough
+/// below `SPLIT_FRACTION` that a merge doesn't split again right away.
+const MERGE_FRACTION: f64 = 0.05;
+
+const MAX_DISPATCHES: u32 = 256;
+
+/// One dispatch of the trace of a frame: `sample_count` samples of the rows from `first_row`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dispatch {
+    /// Samples of the frame traced by the dispatches before, of the same rows.
+    pub first_sample: u32,
+    pub sample_count: u32,
+    pub first_row: u32,
+    pub row_count: u32,
+}
+
+/// Splits the samples raytrace.rgen traces in a frame over several dispatches once one would run
+/// long enough for the OS to reset the GPU, e.g. at the batch sample counts of offline renders.
+///
+/// The trace is timed with timestamps around it. Each dispatch traces a sub-batch of the samples
+/// and adds to those of the one before, so the image is the same however many there are. Once
+/// every sub-batch is a single sample, the dispatches of a sub-batch split the image into bands
+/// of rows instead.
+pub struct GpuWatchdog {
+    pub enabled: bool,
+    /// `None` on devices that can't write timestamps on the graphics queue.
+    query_pool: Option<Arc<safe_vk::QueryPool>>,
+    dispatches: u32,
+    /// The batch sample count and the image height of the trace last planned by `dispatches`,
+    /// which limit how far it splits.
+    planned: (u32, u32),
+    /// Dispatches of the trace of the frame being recorded.
+    recorded_dispatches: Option<u32>,
+    /// Dispatches of the trace of the frame last submitted, timed once it has finished.
+    submitted_dispatches: Option<u32>,
+    /// GPU time in milliseconds of the last trace timed, and how many dispatches it took.
+    last_trace: Option<(f64, u32)>,
+}
+
+impl GpuWatchdog {
+    pub fn new(device: Arc<safe_vk::Device>) -> Self {
+        let timestamps = device
+            .pdevice()
+            .properties()
+            .limits
+            .timestamp_compute_and_graphics
+            == vk::TRUE;
+        if !timestamps {
+            log::warn!("the device can't time dispatches, long traces may time out");
+        }
+        let query_pool = if timestamps {
+            Some(Arc::new(safe_vk::QueryPool::timestamps(device, 2)))
+        } else {
+            None
+        };
+        Self {
+            enabled: timestamps,
+            query_pool,
+            dispatches: 1,
+            planned: (1, 1),
+            recorded_dispatches: None,
+            submitted_dispatches: None,
+            last_trace: None,
+        }
+    }
+
+    /// The dispatches `batch_sample_count` samples of an image `height` rows high are traced
+    /// in, the sub-batches of samples in order, each in bands of rows from the top.
+    pub fn dispatches(&mut self, batch_sample_count: u32, height: u32) -> Vec<Dispatch> {
+        self.planned = (batch_sample_count, height);
+        let dispatches = if self.enabled { self.dispatches } else { 1 };
+        let (sub_batches, bands) = split(dispatches, batch_sample_count, height);
+        let mut result = Vec::new();
+        let mut first_sample = 0;
+        for i in 0..sub_batches {
+            let sample_count =
+                batch_sample_count / sub_batches + (i < batch_sample_count % sub_batches) as u32;
+            for band in 0..bands {
+                let first_row = height * band / bands;
+                result.push(Dispatch {
+                    first_sample,
+                    sample_count,
+                    first_row,
+                    row_count: height * (band + 1) / bands - first_row,
+                });
+            }
+            first_sample += sample_count;
+        }
+        result
+    }
+
+    /// Records the trace with `f`, timed as a whole. `dispatches` is how many it's split into,
+    /// as many as `dispatches` returned.
+    pub fn record<F>(&mut self, recorder: &mut safe_vk::CommandRecorder, dispatches: u32, f: F)
+    where
+        F: FnOnce(&mut safe_vk::CommandRecorder),
+    {
+        let query_pool = match &self.query_pool {
+            Some(query_pool) => query_pool.clone(),
+            None => return f(recorder),
+        };
+        recorder.reset_query_pool(query_pool.clone(), 0, 2);
+        recorder.write_timestamp(query_pool.clone(), vk::PipelineStageFlags::TOP_OF_PIPE, 0);
+        f(recorder);
+        recorder.write_timestamp(
+            query_pool,
+            vk::PipelineStageFlags::RAY_TRACING_SHADER_KHR,
+            1,
+        );
+        self.recorded_dispatches = Some(dispatches);
+    }
+
+    /// Times the trace of the frame last submitted and splits or merges the dispatches of the
+    /// next ones. Call once that frame has finished, before the one recorded is submitted.
+    pub fn frame_finished(&mut self) {
+        let submitted = std::mem::replace(
+            &mut self.submitted_dispatches,
+            self.recorded_dispatches.take(),
+        );
+        let (query_pool, dispatches) = match (&self.query_pool, submitted) {
+            (Some(query_pool), Some(dispatches)) => (query_pool, dispatches),
+            _ => return,
+        };
+        let (start, end) = match query_pool.results(0, 2)[..] {
+            [Some(start), Some(end)] => (start, end),
+            _ => return,
+        };
+        let trace_time =
+            end.wrapping_sub(start) as f64 * query_pool.timestamp_period() as f64 / 1e6;
+        self.last_trace = Some((trace_time, dispatches));
+        if !self.enabled {
+            return;
+        }
+        let dispatch_time = trace_time / dispatches as f64;
+        let (batch_sample_count, height) = self.planned;
+        let (sub_batches, bands) = split(
+            (dispatches * 2).min(MAX_DISPATCHES),
+            batch_sample_count,
+            height,
+        );
+        if dispatch_time > TIMEOUT_MS * SPLIT_FRACTION && sub_batches * bands > dispatches {
+            self.dispatches = sub_batches * bands;
+            log::info!(
+                "dispatches took {:.0} ms, tracing in {} dispatches",
+                dispatch_time,
+                self.dispatches
+            );
+        } else if dispatch_time < TIMEOUT_MS * MERGE_FRACTION && self.dispatches > 1 {
+            self.dispatches = (dispatches / 2).max(1);
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.add_enabled_ui(self.query_pool.is_some(), |ui| {
+            ui.checkbox(&mut self.enabled, "GPU Watchdog")
+                .on_hover_text("Splits traces long enough to time out over several dispatches")
+                .on_disabled_hover_text("The device can't time dispatches");
+        });
+        if let Some((trace_time, dispatches)) = self.last_trace {
+            ui.label(format!(
+                "Trace: {:.1} ms in {} dispatches",
+                trace_time, dispatches
+            ));
+        }
+    }
+}
+
+/// How many sub-batches of samples and bands of rows `dispatches` dispatches split a trace
+/// into, at most one per sample and row. Bands only split single sample sub-batches, so there
+/// may be fewer dispatches than asked for.
+fn split(dispatches: u32, batch_sample_count: u32, height: u32) -> (u32, u32) {
+    let sub_batches = dispatches.min(batch_sample_count).max(1);
+    let bands = (dispatches / sub_batches).min(height).max(1);
+    (sub_batches, bands)
+}
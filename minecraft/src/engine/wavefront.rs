@@ -0,0 +1,447 @@
+use std::sync::Arc;
+
+use bytemuck::{Pod, Zeroable};
+use engine_core::environment::Environment;
+use safe_vk::{vk, ComputePipelineRecorder, Pipeline, PipelineRecorder};
+
+use super::scene::Scene;
+use super::shaders;
+
+const WORKGROUP_SIZE: u32 = 16;
+
+/// Size of `PathState` in wavefront.glsl.
+const PATH_STATE_SIZE: u64 = 64;
+/// Size of `Hit` in wavefront.glsl.
+const HIT_SIZE: u64 = 32;
+/// Size of `ShadowRay` in wavefront.glsl.
+const SHADOW_RAY_SIZE: u64 = 48;
+
+/// Index of the shadow queue, after the extension queues of even and odd bounces.
+const SHADOW_QUEUE: u64 = 2;
+/// Size of the `vk::DispatchIndirectCommand` of every queue.
+const DISPATCH_SIZE: u64 = 12;
+/// Offset of the queue lengths, which follow the dispatches of all three queues.
+const QUEUE_LENGTHS_OFFSET: u64 = 3 * DISPATCH_SIZE;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct PushConstants {
+    sample_count: u32,
+    nee_enabled: u32,
+    environment_rotation: f32,
+    environment_intensity: f32,
+    focus_probe_x: u32,
+    focus_probe_y: u32,
+    adaptive_enabled: u32,
+    adaptive_threshold: f32,
+    adaptive_min_samples: u32,
+    max_bounces: u32,
+    russian_roulette_start: u32,
+    bounce: u32,
+}
+
+/// Path tracing split into compute stages that pass paths along through GPU queues, instead of
+/// tracing whole paths in raytrace.rgen.
+///
+/// For every sample, the generate stage starts a path per pixel. Every bounce then runs the
+/// intersect stage over the queued paths with ray queries, the shade stage, which queues a shadow
+/// ray and the continuing paths, and the shadow stage. The queued stages are dispatched
+/// indirectly with group counts the shaders grow as they append, so terminated paths cost
+/// nothing. The accumulate stage finally adds the samples to the result image.
+pub struct Wavefront {
+    generate_pipeline: Arc<safe_vk::ComputePipeline>,
+    intersect_pipeline: Arc<safe_vk::ComputePipeline>,
+    shade_pipeline: Arc<safe_vk::ComputePipeline>,
+    shadow_pipeline: Arc<safe_vk::ComputePipeline>,
+    accumulate_pipeline: Arc<safe_vk::ComputePipeline>,
+    /// A new scene or result image gets a new set while the frame in flight uses the old one.
+    descriptor_allocator: safe_vk::DescriptorAllocator,
+    descriptor_set: Arc<safe_vk::DescriptorSet>,
+    buffers: Buffers,
+    /// The dispatches and lengths of the queues, reset before they're filled.
+    queues: Arc<safe_vk::Buffer>,
+    uniform_buffer: Arc<safe_vk::Buffer>,
+    focus_probe_buffer: Arc<safe_vk::Buffer>,
+    moments_buffer: Arc<safe_vk::Buffer>,
+    extent: (u32, u32),
+}
+
+/// The per-pixel buffers, recreated with the result image.
+struct Buffers {
+    paths: Arc<safe_vk::Buffer>,
+    path_queues: Arc<safe_vk::Buffer>,
+    shadow_rays: Arc<safe_vk::Buffer>,
+    hits: Arc<safe_vk::Buffer>,
+}
+
+impl Wavefront {
+    pub fn new(
+        allocator: Arc<safe_vk::Allocator>,
+        result_image: &Arc<safe_vk::Image>,
+        scene: &Scene,
+        environment: &Environment,
+        uniform_buffer: Arc<safe_vk::Buffer>,
+        focus_probe_buffer: Arc<safe_vk::Buffer>,
+        moments_buffer: Arc<safe_vk::Buffer>,
+    ) -> Self {
+        let device = allocator.device().clone();
+        let binding = |binding: u32, descriptor_type: safe_vk::DescriptorType| {
+            safe_vk::DescriptorSetLayoutBinding {
+                binding,
+                descriptor_type,
+                stage_flags: vk::ShaderStageFlags::COMPUTE,
+            }
+        };
+        let descriptor_set_layout = Arc::new(safe_vk::DescriptorSetLayout::new(
+            device.clone(),
+            Some("wavefront set layout"),
+            &[
+                binding(0, safe_vk::DescriptorType::StorageImage),
+                binding(1, safe_vk::DescriptorType::AccelerationStructure),
+                binding(2, safe_vk::DescriptorType::StorageBuffer),
+                binding(4, safe_vk::DescriptorType::StorageBuffer),
+                binding(5, safe_vk::DescriptorType::UniformBuffer),
+                binding(6, safe_vk::DescriptorType::StorageBuffer),
+                binding(7, safe_vk::DescriptorType::StorageBuffer),
+                binding(8, safe_vk::DescriptorType::StorageBuffer),
+                binding(9, safe_vk::DescriptorType::StorageImage),
+                binding(10, safe_vk::DescriptorType::StorageBuffer),
+                binding(14, safe_vk::DescriptorType::StorageBuffer),
+                binding(15, safe_vk::DescriptorType::StorageBuffer),
+                binding(16, safe_vk::DescriptorType::StorageBuffer),
+                binding(17, safe_vk::DescriptorType::StorageBuffer),
+                binding(18, safe_vk::DescriptorType::StorageBuffer),
+                binding(19, safe_vk::DescriptorType::StorageBuffer),
+                binding(26, safe_vk::DescriptorType::StorageImage),
+            ],
+        ));
+
+        let pipeline_layout = Arc::new(safe_vk::PipelineLayout::new(
+            device.clone(),
+            Some("wavefront pipeline layout"),
+            &[&descriptor_set_layout],
+            &[vk::PushConstantRange::builder()
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .offset(0)
+                .size(std::mem::size_of::<PushConstants>() as u32)
+                .build()],
+        ));
+
+        let create_pipeline = |name: &str, shader: &str| {
+            Arc::new(safe_vk::ComputePipeline::new(
+                Some(name),
+                pipeline_layout.clone(),
+                Arc::new(safe_vk::ShaderStage::new(
+                    Arc::new(safe_vk::ShaderModule::new(
+                        device.clone(),
+                        shaders::Shaders::get(shader).unwrap(),
+                    )),
+                    vk::ShaderStageFlags::COMPUTE,
+                    "main",
+                )),
+            ))
+        };
+        let generate_pipeline =
+            create_pipeline("wavefront generate pipeline", "wavefront_generate.comp.spv");
+        let intersect_pipeline = create_pipeline(
+            "wavefront intersect pipeline",
+            "wavefront_intersect.comp.spv",
+        );
+        let shade_pipeline =
+            create_pipeline("wavefront shade pipeline", "wavefront_shade.comp.spv");
+        let shadow_pipeline =
+            create_pipeline("wavefront shadow pipeline", "wavefront_shadow.comp.spv");
+        let accumulate_pipeline = create_pipeline(
+            "wavefront accumulate pipeline",
+            "wavefront_accumulate.comp.spv",
+        );
+
+        let buffers = Buffers::new(&allocator, result_image.width(), result_image.height());
+        let queues = Arc::new(safe_vk::Buffer::new(
+            Some("wavefront queues"),
+            allocator.clone(),
+            QUEUE_LENGTHS_OFFSET + 3 * std::mem::size_of::<u32>() as u64,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::INDIRECT_BUFFER,
+            safe_vk::MemoryUsage::GpuOnly,
+        ));
+        let mut descriptor_allocator = safe_vk::DescriptorAllocator::new(descriptor_set_layout, 2);
+        let descriptor_set =
+            Arc::new(descriptor_allocator.allocate(Some("wavefront descriptor set")));
+        let wavefront = Self {
+            generate_pipeline,
+            intersect_pipeline,
+            shade_pipeline,
+            shadow_pipeline,
+            accumulate_pipeline,
+            descriptor_allocator,
+            descriptor_set,
+            buffers,
+            queues,
+            uniform_buffer,
+            focus_probe_buffer,
+            moments_buffer,
+            extent: (result_image.width(), result_image.height()),
+        };
+        wavefront.update_descriptor_set(result_image, scene, environment);
+        wavefront
+    }
+
+    /// Recreates the per-pixel buffers for a new result image.
+    pub fn resize(
+        &mut self,
+        allocator: &Arc<safe_vk::Allocator>,
+        result_image: &Arc<safe_vk::Image>,
+        scene: &Scene,
+        environment: &Environment,
+        moments_buffer: Arc<safe_vk::Buffer>,
+    ) {
+        self.buffers = Buffers::new(allocator, result_image.width(), result_image.height());
+        self.moments_buffer = moments_buffer;
+        self.extent = (result_image.width(), result_image.height());
+        self.set_scene(result_image, scene, environment);
+    }
+
+    /// Points the stages at a new scene or environment.
+    pub fn set_scene(
+        &mut self,
+        result_image: &Arc<safe_vk::Image>,
+        scene: &Scene,
+        environment: &Environment,
+    ) {
+        self.descriptor_set = Arc::new(
+            self.descriptor_allocator
+                .allocate(Some("wavefront descriptor set")),
+        );
+        self.update_descriptor_set(result_image, scene, environment);
+    }
+
+    /// Records `batch_sample_count` samples of every pixel in place of the ray tracing dispatch,
+    /// with the settings raytrace.rgen would have used. The result image must be in `GENERAL`
+    /// layout.
+    pub(super) fn record(
+        &self,
+        recorder: &mut safe_vk::CommandRecorder,
+        settings: &super::PushConstants,
+    ) {
+        let mut push_constants = PushConstants {
+            sample_count: settings.sample_count,
+            nee_enabled: settings.nee_enabled,
+            environment_rotation: settings.environment_rotation,
+            environment_intensity: settings.environment_intensity,
+            focus_probe_x: settings.focus_probe_x,
+            focus_probe_y: settings.focus_probe_y,
+            adaptive_enabled: settings.adaptive_enabled,
+            adaptive_threshold: settings.adaptive_threshold,
+            adaptive_min_samples: settings.adaptive_min_samples,
+            max_bounces: settings.max_bounces,
+            russian_roulette_start: settings.russian_roulette_start,
+            bounce: 0,
+        };
+        let (width, height) = self.extent;
+
+        for sample in 0..settings.batch_sample_count {
+            push_constants.sample_count = settings.sample_count + sample;
+            push_constants.bounce = 0;
+            if sample > 0 {
+                push_constants.focus_probe_x = u32::MAX;
+                push_constants.focus_probe_y = u32::MAX;
+            }
+            self.reset_queue(recorder, 0);
+            stage_barrier(recorder);
+            self.dispatch(
+                recorder,
+                &self.generate_pipeline,
+                &push_constants,
+                Dispatch::Pixels(width, height),
+            );
+            for bounce in 0..=settings.max_bounces {
+                push_constants.bounce = bounce;
+                let queue = bounce as u64 % 2;
+                // The next bounce reuses the queue the last one was read from.
+                stage_barrier(recorder);
+                self.reset_queue(recorder, 1 - queue);
+                self.reset_queue(recorder, SHADOW_QUEUE);
+                stage_barrier(recorder);
+                self.dispatch(
+                    recorder,
+                    &self.intersect_pipeline,
+                    &push_constants,
+                    Dispatch::Queue(queue),
+                );
+                stage_barrier(recorder);
+                self.dispatch(
+                    recorder,
+                    &self.shade_pipeline,
+                    &push_constants,
+                    Dispatch::Queue(queue),
+                );
+                stage_barrier(recorder);
+                self.dispatch(
+                    recorder,
+                    &self.shadow_pipeline,
+                    &push_constants,
+                    Dispatch::Queue(SHADOW_QUEUE),
+                );
+            }
+            stage_barrier(recorder);
+            self.dispatch(
+                recorder,
+                &self.accumulate_pipeline,
+                &push_constants,
+                Dispatch::Pixels(width, height),
+            );
+            stage_barrier(recorder);
+        }
+        recorder.memory_barrier(
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::SHADER_WRITE,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::AccessFlags::SHADER_READ | vk::AccessFlags::TRANSFER_READ,
+        );
+    }
+
+    fn update_descriptor_set(
+        &self,
+        result_image: &Arc<safe_vk::Image>,
+        scene: &Scene,
+        environment: &Environment,
+    ) {
+        let buffer =
+            |binding: u32, buffer: &Arc<safe_vk::Buffer>| safe_vk::DescriptorSetUpdateInfo {
+                binding,
+                detail: safe_vk::DescriptorSetUpdateDetail::Buffer {
+                    buffer: buffer.clone(),
+                    offset: 0,
+                },
+            };
+        self.descriptor_set.update(&[
+            safe_vk::DescriptorSetUpdateInfo {
+                binding: 0,
+                detail: safe_vk::DescriptorSetUpdateDetail::Image(Arc::new(
+                    safe_vk::ImageView::new(result_image.clone()),
+                )),
+            },
+            safe_vk::DescriptorSetUpdateInfo {
+                binding: 1,
+                detail: safe_vk::DescriptorSetUpdateDetail::AccelerationStructure(
+                    scene.tlas().clone(),
+                ),
+            },
+            buffer(2, scene.geometry_buffer()),
+            buffer(4, &self.focus_probe_buffer),
+            buffer(5, &self.uniform_buffer),
+            buffer(6, scene.light_buffer()),
+            buffer(7, scene.material_buffer()),
+            buffer(8, scene.geometry_material_buffer()),
+            safe_vk::DescriptorSetUpdateInfo {
+                binding: 9,
+                detail: safe_vk::DescriptorSetUpdateDetail::Image(Arc::new(
+                    safe_vk::ImageView::new(environment.image().clone()),
+                )),
+            },
+            buffer(10, environment.cdf_buffer()),
+            buffer(14, &self.moments_buffer),
+            buffer(15, &self.buffers.paths),
+            buffer(16, &self.queues),
+            buffer(17, &self.buffers.path_queues),
+            buffer(18, &self.buffers.shadow_rays),
+            buffer(19, &self.buffers.hits),
+            safe_vk::DescriptorSetUpdateInfo {
+                binding: 26,
+                detail: safe_vk::DescriptorSetUpdateDetail::Image(Arc::new(
+                    safe_vk::ImageView::new(scene.atlas().clone()),
+                )),
+            },
+        ]);
+    }
+
+    /// Empties a queue and its dispatch, which keeps one group in y and z.
+    fn reset_queue(&self, recorder: &mut safe_vk::CommandRecorder, queue: u64) {
+        recorder.update_buffer(
+            self.queues.clone(),
+            queue * DISPATCH_SIZE,
+            bytemuck::cast_slice(&[0u32, 1, 1]),
+        );
+        recorder.update_buffer(
+            self.queues.clone(),
+            QUEUE_LENGTHS_OFFSET + queue * std::mem::size_of::<u32>() as u64,
+            bytemuck::bytes_of(&0u32),
+        );
+    }
+
+    fn dispatch(
+        &self,
+        recorder: &mut safe_vk::CommandRecorder,
+        pipeline: &Arc<safe_vk::ComputePipeline>,
+        push_constants: &PushConstants,
+        dispatch: Dispatch,
+    ) {
+        recorder.bind_compute_pipeline(pipeline.clone(), |recorder, pipeline| {
+            recorder.bind_descriptor_sets(vec![self.descriptor_set.clone()], pipeline.layout(), 0);
+            recorder.push_constants(
+                pipeline.layout(),
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                bytemuck::bytes_of(push_constants),
+            );
+            match dispatch {
+                Dispatch::Pixels(width, height) => recorder.dispatch(
+                    (width + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE,
+                    (height + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE,
+                    1,
+                ),
+                Dispatch::Queue(queue) => {
+                    recorder.dispatch_indirect(self.queues.clone(), queue * DISPATCH_SIZE)
+                }
+            }
+        });
+    }
+}
+
+enum Dispatch {
+    /// A thread per pixel.
+    Pixels(u32, u32),
+    /// A thread per entry of the queue, as many as the stages filling it asked for.
+    Queue(u64),
+}
+
+/// Makes the queues, their dispatches and the path buffers written by a stage or a reset visible
+/// to whatever comes next. The first one of a frame also covers the camera update.
+fn stage_barrier(recorder: &mut safe_vk::CommandRecorder) {
+    recorder.memory_barrier(
+        vk::PipelineStageFlags::COMPUTE_SHADER | vk::PipelineStageFlags::TRANSFER,
+        vk::AccessFlags::SHADER_WRITE | vk::AccessFlags::TRANSFER_WRITE,
+        vk::PipelineStageFlags::COMPUTE_SHADER
+            | vk::PipelineStageFlags::DRAW_INDIRECT
+            | vk::PipelineStageFlags::TRANSFER,
+        vk::AccessFlags::SHADER_READ
+            | vk::AccessFlags::UNIFORM_READ
+            | vk::AccessFlags::SHADER_WRITE
+            | vk::AccessFlags::INDIRECT_COMMAND_READ
+            | vk::AccessFlags::TRANSFER_WRITE,
+    );
+}
+
+impl Buffers {
+    fn new(allocator: &Arc<safe_vk::Allocator>, width: u32, height: u32) -> Self {
+        let texels = width as u64 * height as u64;
+        let create_buffer = |name: &str, size: u64| {
+            Arc::new(safe_vk::Buffer::new(
+                Some(name),
+                allocator.clone(),
+                size,
+                vk::BufferUsageFlags::STORAGE_BUFFER,
+                safe_vk::MemoryUsage::GpuOnly,
+            ))
+        };
+        Self {
+            paths: create_buffer("wavefront paths", texels * PATH_STATE_SIZE),
+            path_queues: create_buffer(
+                "wavefront path queues",
+                2 * texels * std::mem::size_of::<u32>() as u64,
+            ),
+            shadow_rays: create_buffer("wavefront shadow rays", texels * SHADOW_RAY_SIZE),
+            hits: create_buffer("wavefront hits", texels * HIT_SIZE),
+        }
+    }
+}
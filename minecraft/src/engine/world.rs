@@ -0,0 +1,863 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::Arc;
+
+use glam::{Mat4, Vec3};
+
+use super::scene::{AtlasTile, Material, MeshGeometry, MeshId, Scene, ATLAS_TILE_SIZE};
+
+/// Blocks along each side of a chunk. Small enough that the vertices of a chunk fit 16 bit
+/// indices, the hit shaders read no others.
+const CHUNK_SIZE: i32 = 16;
+
+/// The chunk and the layer of blocks around it.
+const PADDED_SIZE: i32 = CHUNK_SIZE + 2;
+
+/// Chunks stacked in each column, the world has no chunks below or above them.
+const WORLD_HEIGHT: i32 = 4;
+
+/// Where the corner of chunk (0, 0, 0) is, so that the terrain lies below the glTF scene.
+const WORLD_ORIGIN: Vec3 = glam::const_vec3!([0.0, -56.0, 0.0]);
+
+/// Height of the terrain around which the noise goes up and down, in blocks.
+const BASE_HEIGHT: i32 = 32;
+
+/// Columns this high or lower are topped with sand instead of grass.
+const SAND_HEIGHT: i32 = 24;
+
+const TERRAIN_SEED: u32 = 0x5eed;
+
+/// Seed of the noise of the textures of the tiles.
+const TEXTURE_SEED: u32 = 0x7e47;
+
+/// Chunks generated on the job system at a time.
+const MAX_PENDING: usize = 16;
+
+/// Chunks whose meshes `World::apply` adds per call, nearest first, so that building their
+/// bottom level acceleration structures doesn't stall a frame for long.
+const MAX_BUILDS_PER_APPLY: usize = 4;
+
+/// Chunks within this many chunks of the camera, horizontally, are loaded at first.
+const DEFAULT_VIEW_DISTANCE: i32 = 6;
+
+/// What fills a cell of the world.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Block {
+    Air,
+    Grass,
+    Dirt,
+    Stone,
+    Sand,
+}
+
+impl Block {
+    /// The blocks with faces, which can be placed.
+    const SOLID: [Block; 4] = [Block::Grass, Block::Dirt, Block::Stone, Block::Sand];
+
+    fn is_solid(&self) -> bool {
+        *self != Block::Air
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Block::Air => "Air",
+            Block::Grass => "Grass",
+            Block::Dirt => "Dirt",
+            Block::Stone => "Stone",
+            Block::Sand => "Sand",
+        }
+    }
+
+    /// The tile of the face looking `sign` along `axis`. Grass is only green on top.
+    fn face_tile(&self, axis: usize, sign: i32) -> Tile {
+        match self {
+            Block::Air => unreachable!("air has no faces"),
+            Block::Grass if axis == 1 && sign > 0 => Tile::GrassTop,
+            Block::Grass if axis == 1 => Tile::Dirt,
+            Block::Grass => Tile::GrassSide,
+            Block::Dirt => Tile::Dirt,
+            Block::Stone => Tile::Stone,
+            Block::Sand => Tile::Sand,
+        }
+    }
+}
+
+/// A texture of the faces of blocks, a tile of the texture atlas with a material of its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tile {
+    GrassTop,
+    GrassSide,
+    Dirt,
+    Stone,
+    Sand,
+}
+
+impl Tile {
+    /// In the order `World` adds them to the atlas and their materials to the scene.
+    const ALL: [Tile; 5] = [
+        Tile::GrassTop,
+        Tile::GrassSide,
+        Tile::Dirt,
+        Tile::Stone,
+        Tile::Sand,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Tile::GrassTop => "Grass Top",
+            Tile::GrassSide => "Grass Side",
+            Tile::Dirt => "Dirt",
+            Tile::Stone => "Stone",
+            Tile::Sand => "Sand",
+        }
+    }
+
+    /// Offset of the tile and its material from the first ones `World` added.
+    fn offset(&self) -> u32 {
+        Self::ALL.iter().position(|tile| tile == self).unwrap() as u32
+    }
+
+    /// A white material textured by the tile, which is added to the atlas at `first_tile`.
+    fn material(&self, first_tile: u32) -> Material {
+        let roughness = match self {
+            Tile::GrassTop | Tile::GrassSide => 0.9,
+            Tile::Dirt => 1.0,
+            Tile::Stone => 0.8,
+            Tile::Sand => 0.95,
+        };
+        Material {
+            base_color: [1.0; 4],
+            emissive: [0.0; 3],
+            metallic: 0.0,
+            roughness,
+            texture: first_tile + self.offset() + 1,
+        }
+    }
+
+    /// The texels of the tile, generated: a color speckled by noise, grass sides with dirt below
+    /// a ragged strip of grass.
+    fn texels(&self) -> AtlasTile {
+        const GRASS: [f32; 3] = [0.25, 0.5, 0.15];
+        const DIRT: [f32; 3] = [0.4, 0.28, 0.18];
+        const STONE: [f32; 3] = [0.45, 0.45, 0.45];
+        const SAND: [f32; 3] = [0.8, 0.75, 0.55];
+        let seed = TEXTURE_SEED.wrapping_add(self.offset());
+        let mut texels = [[u8::MAX; 4]; ATLAS_TILE_SIZE * ATLAS_TILE_SIZE];
+        for (i, texel) in texels.iter_mut().enumerate() {
+            let (x, y) = ((i % ATLAS_TILE_SIZE) as i32, (i / ATLAS_TILE_SIZE) as i32);
+            let speckle = lattice(seed, x, y);
+            let (color, noise) = match self {
+                Tile::GrassTop => (GRASS, 0.2 * speckle),
+                Tile::GrassSide if y < 3 + (2.0 * lattice(seed, x, -1)).abs().round() as i32 => {
+                    (GRASS, 0.2 * speckle)
+                }
+                Tile::GrassSide | Tile::Dirt => (DIRT, 0.15 * speckle),
+                // Blotches of 2 by 2 texels too, which tile as the blotches divide the tile.
+                Tile::Stone => (STONE, 0.12 * lattice(!seed, x / 2, y / 2) + 0.05 * speckle),
+                Tile::Sand => (SAND, 0.08 * speckle),
+            };
+            let brightness = 1.0 + noise;
+            for (channel, component) in texel.iter_mut().zip(color.iter()) {
+                *channel = (component * brightness * 255.0).round().clamp(0.0, 255.0) as u8;
+            }
+        }
+        texels
+    }
+}
+
+/// What a click on the world does, picked in the World menu.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tool {
+    /// Selects objects as usual.
+    Select,
+    Remove,
+    Place(Block),
+}
+
+/// Position of a chunk, in chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct ChunkCoord {
+    x: i32,
+    y: i32,
+    z: i32,
+}
+
+impl ChunkCoord {
+    /// The chunk holding the block at `position`, in blocks.
+    fn containing(position: [i32; 3]) -> Self {
+        Self {
+            x: position[0].div_euclid(CHUNK_SIZE),
+            y: position[1].div_euclid(CHUNK_SIZE),
+            z: position[2].div_euclid(CHUNK_SIZE),
+        }
+    }
+
+    /// Position of the first block of the chunk, in blocks.
+    fn corner(&self) -> [i32; 3] {
+        [
+            self.x * CHUNK_SIZE,
+            self.y * CHUNK_SIZE,
+            self.z * CHUNK_SIZE,
+        ]
+    }
+
+    /// From the blocks of the chunk to the world.
+    fn transform(&self) -> Mat4 {
+        let corner = Vec3::new(self.x as f32, self.y as f32, self.z as f32) * CHUNK_SIZE as f32;
+        Mat4::from_translation(WORLD_ORIGIN + corner)
+    }
+
+    /// Squared horizontal distance to a column of chunks, in chunks.
+    fn distance_squared(&self, (x, z): (i32, i32)) -> i32 {
+        (self.x - x).pow(2) + (self.z - z).pow(2)
+    }
+}
+
+/// The chunks whose meshes show the faces next to the block at `position`, in blocks: its own
+/// and those it borders on.
+fn chunks_around(position: [i32; 3]) -> Vec<ChunkCoord> {
+    let coord = ChunkCoord::containing(position);
+    let mut coords = vec![coord];
+    for axis in 0..3 {
+        let local = position[axis].rem_euclid(CHUNK_SIZE);
+        for &(border, sign) in [(0, -1), (CHUNK_SIZE - 1, 1)].iter() {
+            if local == border {
+                let mut offset = [0; 3];
+                offset[axis] = sign;
+                coords.push(ChunkCoord {
+                    x: coord.x + offset[0],
+                    y: coord.y + offset[1],
+                    z: coord.z + offset[2],
+                });
+            }
+        }
+    }
+    coords.retain(|coord| (0..WORLD_HEIGHT).contains(&coord.y));
+    coords
+}
+
+/// The column of chunks `position` is in.
+fn column_of(position: Vec3) -> (i32, i32) {
+    let blocks = position - WORLD_ORIGIN;
+    (
+        (blocks.x / CHUNK_SIZE as f32).floor() as i32,
+        (blocks.z / CHUNK_SIZE as f32).floor() as i32,
+    )
+}
+
+/// Rolling hills from a few octaves of value noise, the same on every run.
+struct Terrain {
+    seed: u32,
+}
+
+impl Terrain {
+    /// Height of the top block of a column, in blocks above the bottom of the world.
+    fn height(&self, x: i32, z: i32) -> i32 {
+        let mut height = 0.0;
+        let mut amplitude = 10.0;
+        let mut frequency = 1.0 / 64.0;
+        for octave in 0..4 {
+            height += amplitude
+                * value_noise(
+                    self.seed.wrapping_add(octave),
+                    x as f32 * frequency,
+                    z as f32 * frequency,
+                );
+            amplitude *= 0.5;
+            frequency *= 2.0;
+        }
+        BASE_HEIGHT + height.round() as i32
+    }
+
+    /// The block at height `y` of a column whose top block is at `height`. Below the bottom of
+    /// the world is stone, so the bottom faces of the lowest chunks are never seen.
+    fn block(&self, y: i32, height: i32) -> Block {
+        if y > height {
+            Block::Air
+        } else if y == height {
+            if height <= SAND_HEIGHT {
+                Block::Sand
+            } else {
+                Block::Grass
+            }
+        } else if y > height - 4 {
+            Block::Dirt
+        } else {
+            Block::Stone
+        }
+    }
+}
+
+/// Noise in [-1, 1] interpolating random values at the integer lattice points.
+fn value_noise(seed: u32, x: f32, z: f32) -> f32 {
+    let (x0, z0) = (x.floor(), z.floor());
+    let smooth = |t: f32| t * t * (3.0 - 2.0 * t);
+    let (tx, tz) = (smooth(x - x0), smooth(z - z0));
+    let (x0, z0) = (x0 as i32, z0 as i32);
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+    let near = lerp(lattice(seed, x0, z0), lattice(seed, x0 + 1, z0), tx);
+    let far = lerp(lattice(seed, x0, z0 + 1), lattice(seed, x0 + 1, z0 + 1), tx);
+    lerp(near, far, tz)
+}
+
+/// A random value in [-1, 1] for a lattice point.
+fn lattice(seed: u32, x: i32, z: i32) -> f32 {
+    let mut hash =
+        seed ^ (x as u32).wrapping_mul(0x27d4_eb2d) ^ (z as u32).wrapping_mul(0x1656_67b1);
+    hash = (hash ^ (hash >> 15)).wrapping_mul(0x2c1b_3c6d);
+    hash = (hash ^ (hash >> 12)).wrapping_mul(0x297a_2d39);
+    hash ^= hash >> 15;
+    hash as f32 / u32::MAX as f32 * 2.0 - 1.0
+}
+
+/// The blocks of a chunk and of the layer around it, which decides which faces of the chunk
+/// are visible.
+struct PaddedBlocks {
+    /// X fastest, then z, then y.
+    blocks: Vec<Block>,
+}
+
+impl PaddedBlocks {
+    /// The terrain of a chunk, with the edited blocks of `edits` in their place.
+    fn generate(terrain: &Terrain, edits: &[([i32; 3], Block)], coord: ChunkCoord) -> Self {
+        let corner = coord.corner();
+        let mut heights = Vec::with_capacity((PADDED_SIZE * PADDED_SIZE) as usize);
+        for z in -1..=CHUNK_SIZE {
+            for x in -1..=CHUNK_SIZE {
+                heights.push(terrain.height(corner[0] + x, corner[2] + z));
+            }
+        }
+        let mut blocks = Vec::with_capacity(heights.len() * PADDED_SIZE as usize);
+        for y in -1..=CHUNK_SIZE {
+            for height in heights.iter() {
+                blocks.push(terrain.block(corner[1] + y, *height));
+            }
+        }
+        let mut padded = Self { blocks };
+        for (position, block) in edits {
+            let (x, y, z) = (
+                position[0] - corner[0],
+                position[1] - corner[1],
+                position[2] - corner[2],
+            );
+            if [x, y, z]
+                .iter()
+                .all(|local| (-1..=CHUNK_SIZE).contains(local))
+            {
+                padded.blocks[Self::index(x, y, z)] = *block;
+            }
+        }
+        padded
+    }
+
+    /// The block at a position relative to the corner of the chunk, -1 to `CHUNK_SIZE`.
+    fn get(&self, x: i32, y: i32, z: i32) -> Block {
+        self.blocks[Self::index(x, y, z)]
+    }
+
+    fn index(x: i32, y: i32, z: i32) -> usize {
+        (((y + 1) * PADDED_SIZE + z + 1) * PADDED_SIZE + x + 1) as usize
+    }
+}
+
+/// A visible side of a block.
+#[derive(Debug, Clone, Copy)]
+struct Face {
+    /// Of the block, relative to the corner of the chunk.
+    position: [i32; 3],
+    axis: usize,
+    /// Whether the face looks along `axis` or against it.
+    sign: i32,
+}
+
+/// The faces of the solid blocks of a chunk that border on air, in one geometry whose triangles
+/// each have the material of the tile of their face.
+struct ChunkMesh {
+    /// Relative to the corner of the chunk, in blocks.
+    positions: Vec<[f32; 3]>,
+    indices: Vec<u16>,
+    /// Two triangles each, in order.
+    faces: Vec<Face>,
+    /// The tile of each face.
+    tiles: Vec<Tile>,
+}
+
+impl ChunkMesh {
+    fn new(blocks: &PaddedBlocks) -> Self {
+        let mut mesh = Self {
+            positions: Vec::new(),
+            indices: Vec::new(),
+            faces: Vec::new(),
+            tiles: Vec::new(),
+        };
+        for y in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                for x in 0..CHUNK_SIZE {
+                    let block = blocks.get(x, y, z);
+                    if !block.is_solid() {
+                        continue;
+                    }
+                    for axis in 0..3 {
+                        for &sign in [-1, 1].iter() {
+                            let mut neighbor = [x, y, z];
+                            neighbor[axis] += sign;
+                            if blocks.get(neighbor[0], neighbor[1], neighbor[2]).is_solid() {
+                                continue;
+                            }
+                            mesh.push_face([x, y, z], axis, sign);
+                            mesh.tiles.push(block.face_tile(axis, sign));
+                        }
+                    }
+                }
+            }
+        }
+        mesh
+    }
+
+    /// The material of each triangle, whose tiles' materials follow the first of `Tile::ALL`.
+    fn face_materials(&self, first_material: u32) -> Vec<u32> {
+        let mut face_materials = Vec::with_capacity(2 * self.tiles.len());
+        for tile in self.tiles.iter() {
+            let material = first_material + tile.offset();
+            face_materials.extend_from_slice(&[material, material]);
+        }
+        face_materials
+    }
+
+    /// The geometry for the scene, with the materials of `face_materials`.
+    fn mesh_geometry<'a>(&'a self, face_materials: &'a [u32]) -> MeshGeometry<'a> {
+        MeshGeometry {
+            positions: &self.positions,
+            indices: &self.indices,
+            material: face_materials[0],
+            face_materials: Some(face_materials),
+        }
+    }
+
+    /// Adds the face of the block at `position` facing `sign` along `axis`, counter-clockwise
+    /// seen from outside.
+    fn push_face(&mut self, position: [i32; 3], axis: usize, sign: i32) {
+        // The cross product of the unit vectors along u and v is the one along the axis.
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+        let corners: [(f32, f32); 4] = if sign > 0 {
+            [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)]
+        } else {
+            [(0.0, 0.0), (0.0, 1.0), (1.0, 1.0), (1.0, 0.0)]
+        };
+        let first = self.positions.len() as u16;
+        for (du, dv) in corners.iter() {
+            let mut corner = [position[0] as f32, position[1] as f32, position[2] as f32];
+            if sign > 0 {
+                corner[axis] += 1.0;
+            }
+            corner[u] += du;
+            corner[v] += dv;
+            self.positions.push(corner);
+        }
+        self.indices
+            .extend_from_slice(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+        self.faces.push(Face {
+            position,
+            axis,
+            sign,
+        });
+    }
+}
+
+/// A chunk generated and meshed on the job system.
+struct GeneratedChunk {
+    coord: ChunkCoord,
+    mesh: ChunkMesh,
+}
+
+/// A chunk in the scene.
+struct Chunk {
+    /// `None` for chunks without visible faces, buried or all air.
+    mesh: Option<MeshId>,
+    /// The faces of the mesh, as rays see it.
+    faces: Vec<Face>,
+    /// The faces of the mesh replacing it after an edit, while its bottom level acceleration
+    /// structure is built.
+    replacement: Option<Vec<Face>>,
+}
+
+impl Chunk {
+    fn triangle_count(&self) -> usize {
+        2 * self.faces.len()
+    }
+}
+
+/// An endless voxel terrain, streamed in chunks around the camera.
+///
+/// Chunks are generated and meshed on the job system. Each is added to the scene as a mesh of
+/// its own, one geometry whose faces take the material of their tile of the texture atlas, so
+/// only the bottom level acceleration structures of the chunks coming into view are
+/// built, and the top level one is rebuilt over them. Chunks with edited blocks are meshed again
+/// and their meshes replaced, with the top level acceleration structure only refit.
+pub struct World {
+    terrain: Arc<Terrain>,
+    /// Chunks within this many chunks of the camera, horizontally, are loaded. They are
+    /// unloaded one chunk further, so moving back and forth over a border doesn't reload them.
+    pub view_distance: i32,
+    chunks: HashMap<ChunkCoord, Chunk>,
+    /// Chunks being generated on the job system.
+    pending: HashSet<ChunkCoord>,
+    /// Pending chunks whose blocks were edited since they started, dropped when they are done.
+    outdated: HashSet<ChunkCoord>,
+    /// Loaded chunks whose blocks were edited, to be generated again.
+    remesh: HashSet<ChunkCoord>,
+    /// Placed and removed blocks by position, in blocks, which replace those of the terrain
+    /// whenever their chunks are generated.
+    edits: HashMap<[i32; 3], Block>,
+    tool: Tool,
+    sender: Sender<GeneratedChunk>,
+    receiver: Receiver<GeneratedChunk>,
+    /// Generated chunks waiting for `apply`.
+    generated: Vec<GeneratedChunk>,
+    /// Meshes of unloaded chunks, removed from the scene by the next `apply`.
+    removed: Vec<MeshId>,
+    /// Index of the material of the first of `Tile::ALL`, `None` until `apply` adds the tiles
+    /// and their materials to the scene.
+    first_material: Option<u32>,
+    /// The column of chunks the camera was in at the last `update`.
+    center: (i32, i32),
+}
+
+impl Default for World {
+    fn default() -> Self {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        Self {
+            terrain: Arc::new(Terrain { seed: TERRAIN_SEED }),
+            view_distance: DEFAULT_VIEW_DISTANCE,
+            chunks: HashMap::new(),
+            pending: HashSet::new(),
+            outdated: HashSet::new(),
+            remesh: HashSet::new(),
+            edits: HashMap::new(),
+            tool: Tool::Select,
+            sender,
+            receiver,
+            generated: Vec::new(),
+            removed: Vec::new(),
+            first_material: None,
+            center: (0, 0),
+        }
+    }
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Unloads the chunks out of view, starts generating those with edited blocks and those
+    /// coming into view, nearest first, and collects the ones generated since the last call.
+    /// Returns whether `apply` has anything to do.
+    pub fn update(&mut self, jobs: &jobs::JobSystem, scene: &Scene, camera_position: Vec3) -> bool {
+        let center = column_of(camera_position);
+        self.center = center;
+        let unload_distance = (self.view_distance + 1).pow(2);
+        let removed = &mut self.removed;
+        self.chunks.retain(|coord, chunk| {
+            let keep = coord.distance_squared(center) <= unload_distance;
+            if !keep {
+                removed.extend(chunk.mesh);
+            }
+            keep
+        });
+        let chunks = &self.chunks;
+        self.remesh.retain(|coord| chunks.contains_key(coord));
+
+        for chunk in self.receiver.try_iter() {
+            self.pending.remove(&chunk.coord);
+            if self.outdated.remove(&chunk.coord) {
+                if self.chunks.contains_key(&chunk.coord) {
+                    self.remesh.insert(chunk.coord);
+                }
+                continue;
+            }
+            if chunk.coord.distance_squared(center) <= unload_distance {
+                self.generated.push(chunk);
+            }
+        }
+        self.generated
+            .retain(|chunk| chunk.coord.distance_squared(center) <= unload_distance);
+
+        // Edits first, they are waited for.
+        let remesh = self
+            .remesh
+            .iter()
+            .filter(|coord| !self.pending.contains(coord))
+            .copied()
+            .collect::<Vec<_>>();
+        for coord in remesh {
+            self.remesh.remove(&coord);
+            self.spawn(jobs, coord);
+        }
+
+        let view_distance = self.view_distance;
+        let mut missing = Vec::new();
+        for z in center.1 - view_distance..=center.1 + view_distance {
+            for x in center.0 - view_distance..=center.0 + view_distance {
+                for y in 0..WORLD_HEIGHT {
+                    let coord = ChunkCoord { x, y, z };
+                    if coord.distance_squared(center) <= view_distance.pow(2)
+                        && !self.chunks.contains_key(&coord)
+                        && !self.pending.contains(&coord)
+                        && !self.generated.iter().any(|chunk| chunk.coord == coord)
+                    {
+                        missing.push(coord);
+                    }
+                }
+            }
+        }
+        // The top chunks of a column first, they are the ones seen.
+        missing.sort_by_key(|coord| (coord.distance_squared(center), -coord.y));
+        for coord in missing
+            .into_iter()
+            .take(MAX_PENDING.saturating_sub(self.pending.len()))
+        {
+            self.spawn(jobs, coord);
+        }
+
+        !self.removed.is_empty() || !self.generated.is_empty() || scene.replacements_built()
+    }
+
+    /// Starts generating and meshing a chunk on the job system.
+    fn spawn(&mut self, jobs: &jobs::JobSystem, coord: ChunkCoord) {
+        self.pending.insert(coord);
+        let corner = coord.corner();
+        let edits = self
+            .edits
+            .iter()
+            .filter(|(position, _)| {
+                (0..3).all(|axis| {
+                    (corner[axis] - 1..=corner[axis] + CHUNK_SIZE).contains(&position[axis])
+                })
+            })
+            .map(|(position, block)| (*position, *block))
+            .collect::<Vec<_>>();
+        let terrain = self.terrain.clone();
+        let sender = self.sender.clone();
+        jobs.spawn(move || {
+            let mesh = ChunkMesh::new(&PaddedBlocks::generate(&terrain, &edits, coord));
+            // The world may have been dropped since.
+            let _ = sender.send(GeneratedChunk { coord, mesh });
+        });
+    }
+
+    /// Adds the tiles and materials of the blocks to the scene the first time, swaps in the
+    /// meshes of edited chunks that are built, removes the meshes of the chunks
+    /// unloaded by `update` from the scene, replaces those of chunks generated again after an
+    /// edit, adds those of up to `MAX_BUILDS_PER_APPLY` generated chunks and updates the meshes
+    /// of the scene. No frame may still be using the scene, see `Scene::update_meshes`.
+    pub fn apply(&mut self, scene: &mut Scene) {
+        let first_material = match self.first_material {
+            Some(first_material) => first_material,
+            None => {
+                let tiles = Tile::ALL.iter().map(Tile::texels).collect::<Vec<_>>();
+                let first_tile = scene.add_atlas_tiles(&tiles);
+                let materials = Tile::ALL
+                    .iter()
+                    .map(|tile| (tile.name(), tile.material(first_tile)))
+                    .collect::<Vec<_>>();
+                let first_material = scene.add_materials(&materials);
+                self.first_material = Some(first_material);
+                first_material
+            }
+        };
+        for id in scene.finish_replacements() {
+            let chunk = self
+                .chunks
+                .values_mut()
+                .find(|chunk| chunk.mesh == Some(id));
+            if let Some(chunk) = chunk {
+                chunk.faces = chunk.replacement.take().unwrap();
+            }
+        }
+        for id in self.removed.drain(..) {
+            scene.remove_mesh(id);
+        }
+
+        let chunks = &self.chunks;
+        let (remeshed, generated) = self
+            .generated
+            .drain(..)
+            .partition::<Vec<_>, _>(|chunk| chunks.contains_key(&chunk.coord));
+        self.generated = generated;
+        for generated in remeshed {
+            let chunk = self.chunks.get_mut(&generated.coord).unwrap();
+            Self::replace_mesh(scene, chunk, generated, first_material);
+        }
+
+        // Nearest last, to pop them first.
+        let center = self.center;
+        self.generated
+            .sort_by_key(|chunk| std::cmp::Reverse(chunk.coord.distance_squared(center)));
+        for _ in 0..MAX_BUILDS_PER_APPLY {
+            let generated = match self.generated.pop() {
+                Some(generated) => generated,
+                None => break,
+            };
+            let mut chunk = Chunk {
+                mesh: None,
+                faces: Vec::new(),
+                replacement: None,
+            };
+            let coord = generated.coord;
+            Self::replace_mesh(scene, &mut chunk, generated, first_material);
+            self.chunks.insert(coord, chunk);
+        }
+        scene.update_meshes();
+    }
+
+    /// Gives a chunk the mesh of `generated`. An existing mesh is replaced in the background, see
+    /// `Scene::replace_mesh`, or removed if there are no faces left.
+    fn replace_mesh(
+        scene: &mut Scene,
+        chunk: &mut Chunk,
+        generated: GeneratedChunk,
+        first_material: u32,
+    ) {
+        let coord = generated.coord;
+        let name = format!("chunk {} {} {}", coord.x, coord.y, coord.z);
+        let face_materials = generated.mesh.face_materials(first_material);
+        let geometries = if generated.mesh.faces.is_empty() {
+            Vec::new()
+        } else {
+            vec![generated.mesh.mesh_geometry(&face_materials)]
+        };
+        match chunk.mesh {
+            Some(id) if geometries.is_empty() => {
+                scene.remove_mesh(id);
+                chunk.mesh = None;
+                chunk.faces.clear();
+                chunk.replacement = None;
+            }
+            Some(id) => {
+                scene.replace_mesh(id, &name, &geometries);
+                chunk.replacement = Some(generated.mesh.faces);
+            }
+            None if geometries.is_empty() => {}
+            None => {
+                chunk.mesh = Some(scene.add_mesh(&name, &geometries, coord.transform()));
+                chunk.faces = generated.mesh.faces;
+            }
+        }
+    }
+
+    /// Whether clicks edit blocks rather than select objects.
+    pub fn edits_blocks(&self) -> bool {
+        self.tool != Tool::Select
+    }
+
+    /// Removes the block whose face a ray hit, or places one in front of it, as the tool picked
+    /// in the menu does. The ray hit triangle `primitive` of `mesh`, which does nothing unless it
+    /// is the mesh of a chunk. The chunks around the block are generated
+    /// again by the next `update`. The bottom layer of blocks can't be removed, the world has no
+    /// faces below it.
+    pub fn edit(&mut self, mesh: MeshId, primitive: u32) {
+        let (coord, chunk) = match self
+            .chunks
+            .iter()
+            .find(|(_, chunk)| chunk.mesh == Some(mesh))
+        {
+            Some(found) => found,
+            None => return,
+        };
+        let face = match chunk.faces.get(primitive as usize / 2) {
+            Some(face) => *face,
+            None => return,
+        };
+        let corner = coord.corner();
+        let mut position = [
+            corner[0] + face.position[0],
+            corner[1] + face.position[1],
+            corner[2] + face.position[2],
+        ];
+        let block = match self.tool {
+            Tool::Select => return,
+            Tool::Remove => Block::Air,
+            Tool::Place(block) => {
+                position[face.axis] += face.sign;
+                block
+            }
+        };
+        if (position[1] < 1 && block == Block::Air)
+            || position[1] >= WORLD_HEIGHT * CHUNK_SIZE
+            || self.block(position).is_solid() == block.is_solid()
+        {
+            return;
+        }
+        self.edits.insert(position, block);
+        for coord in chunks_around(position) {
+            if self.pending.contains(&coord) {
+                self.outdated.insert(coord);
+            }
+            self.generated.retain(|chunk| chunk.coord != coord);
+            if self.chunks.contains_key(&coord) {
+                self.remesh.insert(coord);
+            }
+        }
+    }
+
+    /// The block at `position`, in blocks, edited or not.
+    fn block(&self, position: [i32; 3]) -> Block {
+        match self.edits.get(&position) {
+            Some(block) => *block,
+            None => self
+                .terrain
+                .block(position[1], self.terrain.height(position[0], position[2])),
+        }
+    }
+
+    /// Forgets the chunks of a replaced scene, they are added to the new one as they are
+    /// generated again.
+    pub fn reset(&mut self) {
+        self.chunks.clear();
+        self.remesh.clear();
+        self.removed.clear();
+        self.first_material = None;
+    }
+
+    /// Removes every chunk from the scene, before the world is dropped. No frame may still be
+    /// using the scene.
+    pub fn remove_from(&mut self, scene: &mut Scene) {
+        for chunk in self.chunks.drain().map(|(_, chunk)| chunk) {
+            self.removed.extend(chunk.mesh);
+        }
+        for id in self.removed.drain(..) {
+            scene.remove_mesh(id);
+        }
+        scene.update_meshes();
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.add(egui::Slider::new(&mut self.view_distance, 1..=16).text("View Distance"));
+        ui.label("Click");
+        ui.radio_value(&mut self.tool, Tool::Select, "Selects");
+        ui.radio_value(&mut self.tool, Tool::Remove, "Removes Blocks");
+        for block in Block::SOLID.iter() {
+            let text = format!("Places {}", block.name());
+            ui.radio_value(&mut self.tool, Tool::Place(*block), text);
+        }
+        let meshes = self
+            .chunks
+            .values()
+            .filter(|chunk| chunk.mesh.is_some())
+            .count();
+        let triangles: usize = self.chunks.values().map(Chunk::triangle_count).sum();
+        ui.label(format!(
+            "{} chunks, {} with faces, {} triangles",
+            self.chunks.len(),
+            meshes,
+            triangles
+        ));
+        ui.label(format!(
+            "{} generating, {} waiting to be built",
+            self.pending.len(),
+            self.generated.len()
+        ));
+        ui.label(format!("{} blocks edited", self.edits.len()));
+    }
+}
@@ -1,54 +1,140 @@
 mod engine;
+use std::path::PathBuf;
 use std::time::Instant;
 
+use clap::Parser;
 use engine::Engine;
+use settings::Settings;
+
+/// Name of the settings file, see `Settings::path`.
+pub const SETTINGS_APP: &str = "minecraft";
+
+/// Path traced block scene viewer.
+#[derive(Debug, Parser)]
+pub struct Args {
+    /// glTF scene to load. Defaults to the scene open on the last exit, or the basic blocks.
+    #[clap(long)]
+    pub scene: Option<PathBuf>,
+    /// Equirectangular Radiance HDR lighting the scene. Defaults to a gradient sky.
+    #[clap(long)]
+    pub environment: Option<PathBuf>,
+    /// Window width in pixels. Defaults to the width on the last exit.
+    #[clap(long)]
+    pub width: Option<u32>,
+    /// Window height in pixels. Defaults to the height on the last exit.
+    #[clap(long)]
+    pub height: Option<u32>,
+    /// Samples per pixel of offline renders.
+    #[clap(long)]
+    pub samples: Option<u32>,
+    /// Index of the Vulkan device to use, in enumeration order. Defaults to the first discrete
+    /// GPU.
+    #[clap(long)]
+    pub device: Option<usize>,
+    /// Disables the Vulkan validation layer.
+    #[clap(long)]
+    pub no_validation: bool,
+    /// Uses the raster renderer even if the device supports ray tracing.
+    #[clap(long)]
+    pub no_ray_tracing: bool,
+    /// Decodes the textures again instead of loading them from the asset cache.
+    #[clap(long)]
+    pub no_asset_cache: bool,
+    /// Renders `--samples` samples offscreen, without a window, writes them like an offline render
+    /// and exits, with code 1 if they couldn't be written. Runs on machines without a display.
+    #[clap(long, requires = "samples")]
+    pub headless: bool,
+    /// Path of offline renders without the extension. Defaults to the file template of the
+    /// Offline Render window.
+    #[clap(long)]
+    pub output: Option<String>,
+    /// Starts from the default settings instead of the saved ones and leaves them as they are,
+    /// so renders don't depend on what was last used.
+    #[clap(long)]
+    pub default_settings: bool,
+    /// Streams a voxel terrain around the camera into the scene, see the World menu.
+    #[clap(long)]
+    pub world: bool,
+    /// Plays the camera path saved at this file without the UI, writes a report of the frame
+    /// times, sample throughput and memory usage and exits, with code 1 if it couldn't be
+    /// written. The Camera menu saves paths to `./minecraft/camera-path.json`.
+    #[clap(long, conflicts_with = "headless")]
+    pub benchmark: Option<PathBuf>,
+    /// Seconds the benchmark plays the camera path over. Defaults to the length of the path.
+    #[clap(long, requires = "benchmark")]
+    pub benchmark_duration: Option<f32>,
+    /// Where the benchmark report goes, CSV if the extension is `csv` and JSON otherwise.
+    /// Defaults to `./minecraft/benchmarks/benchmark-<timestamp>.json`.
+    #[clap(long, requires = "benchmark")]
+    pub benchmark_report: Option<PathBuf>,
+}
+
+/// The engine as `engine_core::run` or `engine_core::run_headless` drives it.
+struct Viewer {
+    engine: Engine,
+    /// Whether the settings are written back on exit, not with `--default-settings`.
+    save_settings: bool,
+}
+
+impl engine_core::Renderer for Viewer {
+    fn handle_event(&mut self, event: &winit::event::Event<()>) {
+        self.engine.handle_event(event);
+    }
+
+    fn update(&mut self) {
+        self.engine.update();
+    }
+
+    fn render(&mut self) {
+        self.engine.render();
+    }
+
+    fn exit_code(&self) -> Option<i32> {
+        self.engine.exit_code()
+    }
+
+    fn exit(&mut self) {
+        if self.save_settings {
+            self.engine.save_settings();
+        }
+    }
+}
 
 fn main() {
-    env_logger::init();
+    egui_backend::init_logger();
+    let args = Args::parse();
+    let settings = if args.default_settings {
+        Settings::default()
+    } else {
+        Settings::load(SETTINGS_APP)
+    };
+    let save_settings = !args.default_settings;
+    let [width, height] = settings.window_size;
+    let size = [args.width.unwrap_or(width), args.height.unwrap_or(height)];
     let rt = tokio::runtime::Runtime::new().unwrap();
-    let event_loop = winit::event_loop::EventLoop::new();
-    let window = winit::window::WindowBuilder::new()
-        .with_inner_size(winit::dpi::PhysicalSize::new(800, 600))
-        .with_title("hello")
-        .build(&event_loop)
-        .unwrap();
-
-    rt.block_on(async {
-        let mut engine = Engine::new(&window);
-        event_loop.run(move |event, _, control_flow| {
-            engine.handle_event(&event);
-            match event {
-                winit::event::Event::NewEvents(_) => {}
-                winit::event::Event::WindowEvent {
-                    window_id: _,
-                    event,
-                } => {
-                    match event {
-                        winit::event::WindowEvent::Resized(_) => {}
-                        winit::event::WindowEvent::Moved(_) => {}
-                        winit::event::WindowEvent::CloseRequested => {
-                            *control_flow = winit::event_loop::ControlFlow::Exit;
-                        }
-                        _ => {}
-                    }
-                }
-                winit::event::Event::DeviceEvent {
-                    device_id: _,
-                    event: _,
-                } => {}
-                winit::event::Event::UserEvent(_) => {}
-                winit::event::Event::Suspended => {}
-                winit::event::Event::Resumed => {}
-                winit::event::Event::MainEventsCleared => {
-                    window.request_redraw();
-                }
-                winit::event::Event::RedrawRequested(_) => {
-                    engine.update();
-                    engine.render();
-                }
-                winit::event::Event::RedrawEventsCleared => {}
-                winit::event::Event::LoopDestroyed => {}
-            }
+
+    if args.headless {
+        rt.block_on(async {
+            let engine = Engine::new(None, size.into(), &args, settings);
+            engine_core::run_headless(Viewer {
+                engine,
+                save_settings,
+            })
+        });
+    } else {
+        let event_loop = winit::event_loop::EventLoop::new();
+        let window = engine_core::create_window(&event_loop, "minecraft", size);
+
+        rt.block_on(async {
+            let engine = Engine::new(Some(&window), window.inner_size(), &args, settings);
+            engine_core::run(
+                event_loop,
+                window,
+                Viewer {
+                    engine,
+                    save_settings,
+                },
+            )
         });
-    });
+    }
 }
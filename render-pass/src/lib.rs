@@ -1 +1,2 @@
 pub mod quad;
+pub mod tone_map;
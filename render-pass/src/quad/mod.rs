@@ -31,6 +31,7 @@ impl Quad {
             device.clone(),
             Some("quad pipeline layout"),
             &[&set_layout],
+            &[],
         ));
         let vs_module = safe_vk::ShaderModule::new(
             device.clone(),
@@ -153,10 +154,15 @@ impl Quad {
             vec![color_attachment.clone()],
         ));
 
-        recorder.begin_render_pass(self.render_pass.clone(), framebuffer, |recorder| {
-            recorder.bind_graphics_pipeline(self.pipeline.clone(), |recorder, pipeline| {
-                recorder.draw(3, 1);
-            });
-        });
+        recorder.begin_render_pass(
+            self.render_pass.clone(),
+            framebuffer,
+            &[vk::ClearValue::default()],
+            |recorder| {
+                recorder.bind_graphics_pipeline(self.pipeline.clone(), |recorder, pipeline| {
+                    recorder.draw(3, 1);
+                });
+            },
+        );
     }
 }
@@ -0,0 +1,175 @@
+use std::sync::Arc;
+
+use safe_vk::{vk, ComputePipelineRecorder, Pipeline, PipelineRecorder};
+
+const WORKGROUP_SIZE: u32 = 16;
+
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct PushConstants {
+    operator: u32,
+    exposure: f32,
+    white_point: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToneMapOperator {
+    Aces,
+    Reinhard,
+    Uncharted2,
+}
+
+impl ToneMapOperator {
+    pub const ALL: [ToneMapOperator; 3] = [
+        ToneMapOperator::Aces,
+        ToneMapOperator::Reinhard,
+        ToneMapOperator::Uncharted2,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ToneMapOperator::Aces => "ACES",
+            ToneMapOperator::Reinhard => "Reinhard",
+            ToneMapOperator::Uncharted2 => "Uncharted 2",
+        }
+    }
+
+    /// The operator `name` returns `name` for.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|operator| operator.name() == name)
+    }
+}
+
+/// Maps a floating point image to displayable colors with a compute shader.
+///
+/// Both images must have `STORAGE` usage and be in `GENERAL` layout when `record` is called.
+pub struct ToneMap {
+    pipeline: Arc<safe_vk::ComputePipeline>,
+    descriptor_set: Arc<safe_vk::DescriptorSet>,
+    extent: Option<(u32, u32)>,
+    pub operator: ToneMapOperator,
+    /// Exposure in stops applied before the operator.
+    pub exposure: f32,
+    /// Exposed luminance mapped to white by Reinhard and Uncharted 2.
+    pub white_point: f32,
+}
+
+impl ToneMap {
+    pub fn new(device: Arc<safe_vk::Device>) -> Self {
+        let module = safe_vk::ShaderModule::new(
+            device.clone(),
+            shader::Shaders::get("tone_map.comp.spv").unwrap(),
+        );
+
+        let descriptor_set_layout = Arc::new(safe_vk::DescriptorSetLayout::new(
+            device.clone(),
+            Some("tone map set layout"),
+            &[
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 0,
+                    descriptor_type: safe_vk::DescriptorType::StorageImage,
+                    stage_flags: vk::ShaderStageFlags::COMPUTE,
+                },
+                safe_vk::DescriptorSetLayoutBinding {
+                    binding: 1,
+                    descriptor_type: safe_vk::DescriptorType::StorageImage,
+                    stage_flags: vk::ShaderStageFlags::COMPUTE,
+                },
+            ],
+        ));
+
+        let pipeline_layout = Arc::new(safe_vk::PipelineLayout::new(
+            device.clone(),
+            Some("tone map pipeline layout"),
+            &[&descriptor_set_layout],
+            &[vk::PushConstantRange::builder()
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .offset(0)
+                .size(std::mem::size_of::<PushConstants>() as u32)
+                .build()],
+        ));
+
+        let pipeline = Arc::new(safe_vk::ComputePipeline::new(
+            Some("tone map pipeline"),
+            pipeline_layout,
+            Arc::new(safe_vk::ShaderStage::new(
+                Arc::new(module),
+                vk::ShaderStageFlags::COMPUTE,
+                "main",
+            )),
+        ));
+
+        let descriptor_pool = Arc::new(safe_vk::DescriptorPool::new(
+            device,
+            &[vk::DescriptorPoolSize::builder()
+                .ty(vk::DescriptorType::STORAGE_IMAGE)
+                .descriptor_count(2)
+                .build()],
+            1,
+        ));
+
+        let descriptor_set = Arc::new(safe_vk::DescriptorSet::new(
+            Some("tone map descriptor set"),
+            descriptor_pool,
+            descriptor_set_layout,
+        ));
+
+        Self {
+            pipeline,
+            descriptor_set,
+            extent: None,
+            operator: ToneMapOperator::Aces,
+            exposure: 0.0,
+            white_point: 11.2,
+        }
+    }
+
+    /// Sets the image to read and the image to write. Call again whenever either is recreated,
+    /// e.g. on resize.
+    pub fn set_images(&mut self, source: Arc<safe_vk::Image>, target: Arc<safe_vk::Image>) {
+        self.extent = Some((target.width(), target.height()));
+        self.descriptor_set.update(&[
+            safe_vk::DescriptorSetUpdateInfo {
+                binding: 0,
+                detail: safe_vk::DescriptorSetUpdateDetail::Image(Arc::new(
+                    safe_vk::ImageView::new(source),
+                )),
+            },
+            safe_vk::DescriptorSetUpdateInfo {
+                binding: 1,
+                detail: safe_vk::DescriptorSetUpdateDetail::Image(Arc::new(
+                    safe_vk::ImageView::new(target),
+                )),
+            },
+        ]);
+    }
+
+    pub fn record(&self, recorder: &mut safe_vk::CommandRecorder) {
+        let (width, height) = match self.extent {
+            Some(extent) => extent,
+            None => return,
+        };
+        let push_constants = PushConstants {
+            operator: self.operator as u32,
+            exposure: self.exposure,
+            white_point: self.white_point,
+        };
+        recorder.bind_compute_pipeline(self.pipeline.clone(), |recorder, pipeline| {
+            recorder.bind_descriptor_sets(vec![self.descriptor_set.clone()], pipeline.layout(), 0);
+            recorder.push_constants(
+                pipeline.layout(),
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                bytemuck::bytes_of(&push_constants),
+            );
+            recorder.dispatch(
+                (width + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE,
+                (height + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE,
+                1,
+            );
+        });
+    }
+}
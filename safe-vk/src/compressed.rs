@@ -0,0 +1,463 @@
+use std::convert::TryInto;
+use std::sync::Arc;
+
+use crate::{
+    aspect_mask, vk, Allocator, Buffer, CommandBuffer, CommandPool, Error, Image, MemoryUsage,
+    Queue, TimelineSemaphore,
+};
+
+const KTX2_IDENTIFIER: [u8; 12] = [
+    0xab, b'K', b'T', b'X', b' ', b'2', b'0', 0xbb, b'\r', b'\n', 0x1a, b'\n',
+];
+const DDS_MAGIC: &[u8; 4] = b"DDS ";
+
+/// A block compressed texture read from a KTX2 or DDS file, with its mip levels.
+pub struct CompressedTexture {
+    format: vk::Format,
+    width: u32,
+    height: u32,
+    /// 6 for cubemaps.
+    layers: u32,
+    cube: bool,
+    /// The blocks of every layer of each level, from the largest.
+    levels: Vec<Vec<u8>>,
+}
+
+impl CompressedTexture {
+    /// Reads a KTX2 or a DDS file, told apart by their magic numbers.
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.starts_with(&KTX2_IDENTIFIER) {
+            Self::from_ktx2(bytes)
+        } else if bytes.starts_with(DDS_MAGIC) {
+            Self::from_dds(bytes)
+        } else {
+            Err(invalid("neither a KTX2 nor a DDS file"))
+        }
+    }
+
+    /// Reads a 2D or cubemap KTX2 file of a BC1, BC5 or BC7 format without supercompression.
+    pub fn from_ktx2(bytes: &[u8]) -> Result<Self, Error> {
+        if !bytes.starts_with(&KTX2_IDENTIFIER) {
+            return Err(invalid("not a KTX2 file"));
+        }
+        let format = vk::Format::from_raw(read_u32(bytes, 12)? as i32);
+        let width = read_u32(bytes, 20)?;
+        let height = read_u32(bytes, 24)?;
+        let depth = read_u32(bytes, 28)?;
+        let layer_count = read_u32(bytes, 32)?;
+        let face_count = read_u32(bytes, 36)?;
+        let level_count = read_u32(bytes, 40)?.max(1);
+        let supercompression = read_u32(bytes, 44)?;
+        if depth > 1 || layer_count > 1 || !(face_count == 1 || face_count == 6) {
+            return Err(Error::Unsupported(
+                "KTX2 files other than 2D textures and cubemaps".to_owned(),
+            ));
+        }
+        if supercompression != 0 {
+            return Err(Error::Unsupported("KTX2 supercompression".to_owned()));
+        }
+        block_size(format)?;
+        check_level_count(width, height, level_count)?;
+
+        // The level index follows the 80 bytes of the header and the data format, key/value
+        // and supercompression indices.
+        let levels = (0..level_count as usize)
+            .map(|level| {
+                let entry = 80 + level * 24;
+                let offset = read_u64(bytes, entry)? as usize;
+                let length = read_u64(bytes, entry + 8)? as usize;
+                let end = offset
+                    .checked_add(length)
+                    .ok_or_else(|| invalid("KTX2 level out of bounds"))?;
+                bytes
+                    .get(offset..end)
+                    .map(<[u8]>::to_vec)
+                    .ok_or_else(|| invalid("KTX2 level out of bounds"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::new(format, width, height, face_count, face_count == 6, levels)
+    }
+
+    /// Reads a 2D DDS file of a BC1, BC5 or BC7 format, with a DX10 header for BC7.
+    pub fn from_dds(bytes: &[u8]) -> Result<Self, Error> {
+        if !bytes.starts_with(DDS_MAGIC) {
+            return Err(invalid("not a DDS file"));
+        }
+        // The header follows the magic number, the pixel format is 72 bytes into it.
+        let height = read_u32(bytes, 12)?;
+        let width = read_u32(bytes, 16)?;
+        let level_count = read_u32(bytes, 28)?.max(1);
+        let four_cc = bytes
+            .get(84..88)
+            .ok_or_else(|| invalid("truncated DDS header"))?;
+        let caps2 = read_u32(bytes, 112)?;
+        if caps2 & 0x200 != 0 {
+            return Err(Error::Unsupported("DDS cubemaps".to_owned()));
+        }
+        let (format, mut offset) = match four_cc {
+            b"DXT1" => (vk::Format::BC1_RGBA_UNORM_BLOCK, 128),
+            b"ATI2" | b"BC5U" => (vk::Format::BC5_UNORM_BLOCK, 128),
+            b"BC5S" => (vk::Format::BC5_SNORM_BLOCK, 128),
+            b"DX10" => {
+                let format = match read_u32(bytes, 128)? {
+                    71 => vk::Format::BC1_RGBA_UNORM_BLOCK,
+                    72 => vk::Format::BC1_RGBA_SRGB_BLOCK,
+                    83 => vk::Format::BC5_UNORM_BLOCK,
+                    84 => vk::Format::BC5_SNORM_BLOCK,
+                    98 => vk::Format::BC7_UNORM_BLOCK,
+                    99 => vk::Format::BC7_SRGB_BLOCK,
+                    dxgi_format => {
+                        return Err(Error::Unsupported(format!(
+                            "DXGI format {} in DDS files",
+                            dxgi_format
+                        )))
+                    }
+                };
+                if read_u32(bytes, 140)? > 1 {
+                    return Err(Error::Unsupported("DDS texture arrays".to_owned()));
+                }
+                (format, 148)
+            }
+            four_cc => {
+                return Err(Error::Unsupported(format!(
+                    "{:?} DDS files",
+                    String::from_utf8_lossy(four_cc)
+                )))
+            }
+        };
+        check_level_count(width, height, level_count)?;
+
+        // The levels follow the headers one after the other, from the largest.
+        let levels = (0..level_count)
+            .map(|level| {
+                let length = level_size(format, width, height, level)?;
+                let end = offset
+                    .checked_add(length)
+                    .ok_or_else(|| invalid("DDS level out of bounds"))?;
+                let data = bytes
+                    .get(offset..end)
+                    .ok_or_else(|| invalid("DDS level out of bounds"))?;
+                offset = end;
+                Ok(data.to_vec())
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        Self::new(format, width, height, 1, false, levels)
+    }
+
+    fn new(
+        format: vk::Format,
+        width: u32,
+        height: u32,
+        layers: u32,
+        cube: bool,
+        levels: Vec<Vec<u8>>,
+    ) -> Result<Self, Error> {
+        if width == 0 || height == 0 {
+            return Err(invalid("empty texture"));
+        }
+        check_level_count(width, height, levels.len() as u32)?;
+        for (level, data) in levels.iter().enumerate() {
+            let length = level_size(format, width, height, level as u32)?
+                .checked_mul(layers as usize)
+                .ok_or_else(|| invalid("level too large"))?;
+            if data.len() < length {
+                return Err(invalid("level shorter than its blocks"));
+            }
+        }
+        Ok(Self {
+            format,
+            width,
+            height,
+            layers,
+            cube,
+            levels,
+        })
+    }
+
+    pub fn format(&self) -> vk::Format {
+        self.format
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn mip_levels(&self) -> u32 {
+        self.levels.len() as u32
+    }
+
+    /// Creates a sampled image of the texture and uploads every level of it, waiting for the
+    /// upload. Left in `SHADER_READ_ONLY_OPTIMAL`. Fails with `Error::Unsupported` if the device
+    /// can't sample the format.
+    pub fn upload(
+        &self,
+        name: Option<&str>,
+        allocator: Arc<Allocator>,
+        queue: &mut Queue,
+        command_pool: Arc<CommandPool>,
+    ) -> Result<Arc<Image>, Error> {
+        let sampled = allocator.device().pdevice.supported_format(
+            &[self.format],
+            vk::ImageTiling::OPTIMAL,
+            vk::FormatFeatureFlags::SAMPLED_IMAGE | vk::FormatFeatureFlags::TRANSFER_DST,
+        );
+        if sampled.is_none() {
+            return Err(Error::Unsupported(format!("format {:?}", self.format)));
+        }
+        let flags = if self.cube {
+            vk::ImageCreateFlags::CUBE_COMPATIBLE
+        } else {
+            vk::ImageCreateFlags::empty()
+        };
+        let image = Arc::new(Image::allocate(
+            name,
+            allocator.clone(),
+            self.format,
+            self.width,
+            self.height,
+            self.mip_levels(),
+            self.layers,
+            flags,
+            vk::ImageTiling::OPTIMAL,
+            vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+            MemoryUsage::GpuOnly,
+        )?);
+
+        // Each level is a multiple of the block size, which keeps the offsets of the ones after
+        // aligned to it.
+        let mut regions = Vec::with_capacity(self.levels.len());
+        let mut offset = 0;
+        for (level, data) in self.levels.iter().enumerate() {
+            regions.push(
+                vk::BufferImageCopy::builder()
+                    .buffer_offset(offset as u64)
+                    .image_subresource(
+                        vk::ImageSubresourceLayers::builder()
+                            .aspect_mask(aspect_mask(self.format))
+                            .mip_level(level as u32)
+                            .layer_count(self.layers)
+                            .build(),
+                    )
+                    .image_extent(vk::Extent3D {
+                        width: (self.width >> level).max(1),
+                        height: (self.height >> level).max(1),
+                        depth: 1,
+                    })
+                    .build(),
+            );
+            offset += data.len();
+        }
+        let staging_buffer = Arc::new(Buffer::new_init_host(
+            Some("staging buffer"),
+            allocator,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            MemoryUsage::CpuToGpu,
+            self.levels.concat(),
+        ));
+
+        let mut command_buffer = CommandBuffer::new(command_pool);
+        command_buffer.encode(|recorder| {
+            recorder.copy_buffer_to_image(staging_buffer, image.clone(), &regions);
+            recorder.access_image(
+                image.clone(),
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::AccessFlags::SHADER_READ,
+            );
+        });
+        let semaphore = TimelineSemaphore::new(image.device().clone());
+        queue.submit_timeline(
+            command_buffer,
+            &[&semaphore],
+            &[0],
+            &[vk::PipelineStageFlags::ALL_COMMANDS],
+            &[1],
+        );
+        semaphore.wait_for(1);
+        Ok(image)
+    }
+}
+
+/// Bytes per 4x4 block of `format`, for the formats textures can be read in.
+fn block_size(format: vk::Format) -> Result<usize, Error> {
+    match format {
+        vk::Format::BC1_RGB_UNORM_BLOCK
+        | vk::Format::BC1_RGB_SRGB_BLOCK
+        | vk::Format::BC1_RGBA_UNORM_BLOCK
+        | vk::Format::BC1_RGBA_SRGB_BLOCK => Ok(8),
+        vk::Format::BC5_UNORM_BLOCK
+        | vk::Format::BC5_SNORM_BLOCK
+        | vk::Format::BC7_UNORM_BLOCK
+        | vk::Format::BC7_SRGB_BLOCK => Ok(16),
+        format => Err(Error::Unsupported(format!(
+            "format {:?} in compressed textures",
+            format
+        ))),
+    }
+}
+
+/// Fails unless `level_count` is at most the length of the mip chain of a `width` by `height`
+/// texture, which keeps `extent >> level` from shifting out of range.
+fn check_level_count(width: u32, height: u32, level_count: u32) -> Result<(), Error> {
+    if level_count > 32 - width.max(height).leading_zeros() {
+        return Err(invalid("more mip levels than the texture has"));
+    }
+    Ok(())
+}
+
+/// Bytes of a single layer of mip level `level` of a texture of `format`.
+fn level_size(format: vk::Format, width: u32, height: u32, level: u32) -> Result<usize, Error> {
+    let blocks = |extent: u32| ((extent >> level).max(1) as usize + 3) / 4;
+    blocks(width)
+        .checked_mul(blocks(height))
+        .and_then(|blocks| blocks.checked_mul(block_size(format).ok()?))
+        .ok_or_else(|| invalid("level too large"))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, Error> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .ok_or_else(|| invalid("truncated header"))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64, Error> {
+    bytes
+        .get(offset..offset + 8)
+        .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+        .ok_or_else(|| invalid("truncated header"))
+}
+
+fn invalid(message: &str) -> Error {
+    Error::InvalidData(message.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn put_u32(bytes: &mut [u8], offset: usize, value: u32) {
+        bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_u64(bytes: &mut [u8], offset: usize, value: u64) {
+        bytes[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn dds(width: u32, height: u32, level_count: u32, four_cc: &[u8; 4]) -> Vec<u8> {
+        let mut bytes = vec![0; 128];
+        bytes[..4].copy_from_slice(DDS_MAGIC);
+        put_u32(&mut bytes, 12, height);
+        put_u32(&mut bytes, 16, width);
+        put_u32(&mut bytes, 28, level_count);
+        bytes[84..88].copy_from_slice(four_cc);
+        bytes
+    }
+
+    /// A 2D KTX2 header of `format` with a level index of `levels` `(offset, length)` pairs.
+    fn ktx2(format: vk::Format, width: u32, height: u32, levels: &[(u64, u64)]) -> Vec<u8> {
+        let mut bytes = vec![0; 80 + levels.len() * 24];
+        bytes[..12].copy_from_slice(&KTX2_IDENTIFIER);
+        put_u32(&mut bytes, 12, format.as_raw() as u32);
+        put_u32(&mut bytes, 20, width);
+        put_u32(&mut bytes, 24, height);
+        put_u32(&mut bytes, 36, 1);
+        put_u32(&mut bytes, 40, levels.len() as u32);
+        for (level, &(offset, length)) in levels.iter().enumerate() {
+            put_u64(&mut bytes, 80 + level * 24, offset);
+            put_u64(&mut bytes, 80 + level * 24 + 8, length);
+            put_u64(&mut bytes, 80 + level * 24 + 16, length);
+        }
+        bytes
+    }
+
+    fn is_invalid(result: Result<CompressedTexture, Error>) -> bool {
+        matches!(result, Err(Error::InvalidData(_)))
+    }
+
+    #[test]
+    fn test_bc1_dds() {
+        let mut bytes = dds(4, 4, 1, b"DXT1");
+        bytes.extend_from_slice(&[1; 8]);
+        let texture = CompressedTexture::parse(&bytes).unwrap();
+        assert_eq!(texture.format(), vk::Format::BC1_RGBA_UNORM_BLOCK);
+        assert_eq!((texture.width(), texture.height()), (4, 4));
+        assert_eq!(texture.mip_levels(), 1);
+        assert_eq!(texture.levels, vec![vec![1; 8]]);
+    }
+
+    #[test]
+    fn test_dx10_dds() {
+        let mut bytes = dds(8, 8, 2, b"DX10");
+        bytes.resize(148, 0);
+        put_u32(&mut bytes, 128, 98);
+        put_u32(&mut bytes, 140, 1);
+        // 2x2 blocks of 16 bytes, then a single block.
+        bytes.extend_from_slice(&[1; 64]);
+        bytes.extend_from_slice(&[2; 16]);
+        let texture = CompressedTexture::parse(&bytes).unwrap();
+        assert_eq!(texture.format(), vk::Format::BC7_UNORM_BLOCK);
+        assert_eq!(texture.mip_levels(), 2);
+        assert_eq!(texture.levels, vec![vec![1; 64], vec![2; 16]]);
+    }
+
+    #[test]
+    fn test_ktx2_mips() {
+        let format = vk::Format::BC1_RGBA_UNORM_BLOCK;
+        // 8x8, 4x4 and 2x2, the last two a single block each, stored smallest first.
+        let mut bytes = ktx2(format, 8, 8, &[(168, 32), (160, 8), (152, 8)]);
+        bytes.extend_from_slice(&[3; 8]);
+        bytes.extend_from_slice(&[2; 8]);
+        bytes.extend_from_slice(&[1; 32]);
+        let texture = CompressedTexture::parse(&bytes).unwrap();
+        assert_eq!(texture.format(), format);
+        assert_eq!((texture.width(), texture.height()), (8, 8));
+        assert_eq!(texture.mip_levels(), 3);
+        assert_eq!(texture.levels, vec![vec![1; 32], vec![2; 8], vec![3; 8]]);
+    }
+
+    #[test]
+    fn test_truncated() {
+        let mut bytes = dds(4, 4, 1, b"DXT1");
+        bytes.extend_from_slice(&[1; 8]);
+        for length in [0, 4, 100, 130] {
+            assert!(CompressedTexture::parse(&bytes[..length]).is_err());
+        }
+        assert!(is_invalid(CompressedTexture::from_dds(&bytes[..130])));
+        assert!(is_invalid(CompressedTexture::from_dds(&bytes[..100])));
+
+        let bytes = ktx2(vk::Format::BC1_RGBA_UNORM_BLOCK, 4, 4, &[(104, 8)]);
+        assert!(is_invalid(CompressedTexture::from_ktx2(&bytes)));
+        assert!(is_invalid(CompressedTexture::from_ktx2(&bytes[..60])));
+    }
+
+    #[test]
+    fn test_oversized_mip_count() {
+        // A 4x4 texture has 3 levels, 4x4, 2x2 and 1x1.
+        let mut bytes = dds(4, 4, 4, b"DXT1");
+        bytes.extend_from_slice(&[0; 32]);
+        assert!(is_invalid(CompressedTexture::from_dds(&bytes)));
+        let mut bytes = dds(4, 4, 3, b"DXT1");
+        bytes.extend_from_slice(&[0; 24]);
+        assert!(CompressedTexture::from_dds(&bytes).is_ok());
+
+        let bytes = dds(4, 4, 100, b"DXT1");
+        assert!(is_invalid(CompressedTexture::from_dds(&bytes)));
+        let levels = vec![(0, 8); 40];
+        let bytes = ktx2(vk::Format::BC1_RGBA_UNORM_BLOCK, 4, 4, &levels);
+        assert!(is_invalid(CompressedTexture::from_ktx2(&bytes)));
+    }
+
+    #[test]
+    fn test_overflowing_level_offset() {
+        let format = vk::Format::BC1_RGBA_UNORM_BLOCK;
+        let bytes = ktx2(format, 4, 4, &[(u64::MAX - 4, 8)]);
+        assert!(is_invalid(CompressedTexture::from_ktx2(&bytes)));
+        let bytes = ktx2(format, 4, 4, &[(8, u64::MAX)]);
+        assert!(is_invalid(CompressedTexture::from_ktx2(&bytes)));
+    }
+}
@@ -0,0 +1,125 @@
+use std::ffi::CStr;
+use std::os::raw::c_void;
+use std::sync::{Arc, Mutex};
+
+use crate::vk;
+
+/// A message of the validation layer or the driver, passed to the callback of a
+/// `DebugMessengerDesc`.
+#[derive(Debug, Clone)]
+pub struct DebugMessage {
+    pub severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    pub message_type: vk::DebugUtilsMessageTypeFlagsEXT,
+    /// The name of the validation check, e.g. `VUID-vkCmdDraw-None-02859`.
+    pub id_name: Option<String>,
+    pub message: String,
+}
+
+pub type DebugCallback = Box<dyn Fn(&DebugMessage) + Send + Sync>;
+
+/// Which messages `Instance::with_debug_messenger` passes to `callback`.
+pub struct DebugMessengerDesc {
+    pub severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    pub message_type: vk::DebugUtilsMessageTypeFlagsEXT,
+    pub callback: DebugCallback,
+}
+
+impl DebugMessengerDesc {
+    /// Warnings and errors of every type, passed to `callback`.
+    pub fn new<F>(callback: F) -> Self
+    where
+        F: Fn(&DebugMessage) + Send + Sync + 'static,
+    {
+        Self {
+            severity: vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
+                | vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
+            message_type: vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
+                | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
+                | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE,
+            callback: Box::new(callback),
+        }
+    }
+
+    /// Logs warnings and errors at the `log` level of their severity.
+    pub fn log() -> Self {
+        Self::new(log_message)
+    }
+
+    /// Appends warnings and errors to `messages`, e.g. for a test to check there were none.
+    pub fn collect(messages: Arc<Mutex<Vec<DebugMessage>>>) -> Self {
+        Self::new(move |message| messages.lock().unwrap().push(message.clone()))
+    }
+
+    /// Logs warnings and aborts on the first error, for tests. The panic can't unwind through
+    /// the driver calling back, so the process aborts once it's printed.
+    pub fn panic_on_error() -> Self {
+        Self::new(|message| {
+            log_message(message);
+            if message
+                .severity
+                .contains(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR)
+            {
+                panic!("Vulkan error: {}", message.message);
+            }
+        })
+    }
+
+    /// Passes verbose and info messages as well.
+    pub fn verbose(mut self) -> Self {
+        self.severity |= vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE
+            | vk::DebugUtilsMessageSeverityFlagsEXT::INFO;
+        self
+    }
+}
+
+fn log_message(message: &DebugMessage) {
+    let level = if message
+        .severity
+        .contains(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR)
+    {
+        log::Level::Error
+    } else if message
+        .severity
+        .contains(vk::DebugUtilsMessageSeverityFlagsEXT::WARNING)
+    {
+        log::Level::Warn
+    } else if message
+        .severity
+        .contains(vk::DebugUtilsMessageSeverityFlagsEXT::INFO)
+    {
+        log::Level::Info
+    } else {
+        log::Level::Debug
+    };
+    log::log!(level, "[{:?}] {}", message.message_type, message.message);
+}
+
+/// Called by the messenger with the `DebugCallback` boxed by the instance as `user_data`.
+pub(crate) unsafe extern "system" fn debug_callback(
+    severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    message_type: vk::DebugUtilsMessageTypeFlagsEXT,
+    data: *const vk::DebugUtilsMessengerCallbackDataEXT,
+    user_data: *mut c_void,
+) -> vk::Bool32 {
+    let callback = &*(user_data as *const DebugCallback);
+    let data = &*data;
+    let string = |pointer: *const std::os::raw::c_char| {
+        if pointer.is_null() {
+            None
+        } else {
+            Some(CStr::from_ptr(pointer).to_string_lossy().into_owned())
+        }
+    };
+    let message = DebugMessage {
+        severity,
+        message_type,
+        id_name: string(data.p_message_id_name),
+        message: string(data.p_message).unwrap_or_default(),
+    };
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| callback(&message)));
+    if result.is_err() {
+        std::process::abort();
+    }
+    // VK_TRUE, aborting the call that triggered the message, is reserved for layers.
+    vk::FALSE
+}
@@ -0,0 +1,215 @@
+//! VK_KHR_dynamic_rendering, which the ash version used predates, declared by hand from the
+//! Vulkan headers.
+
+use std::os::raw::{c_char, c_void};
+use std::sync::Arc;
+
+use ash::version::InstanceV1_0;
+
+use crate::{vk, ImageView};
+
+const STRUCTURE_TYPE_RENDERING_INFO: i32 = 1_000_044_000;
+const STRUCTURE_TYPE_RENDERING_ATTACHMENT_INFO: i32 = 1_000_044_001;
+const STRUCTURE_TYPE_PIPELINE_RENDERING_CREATE_INFO: i32 = 1_000_044_002;
+const STRUCTURE_TYPE_PHYSICAL_DEVICE_DYNAMIC_RENDERING_FEATURES: i32 = 1_000_044_003;
+
+/// An attachment `CommandRecorder::begin_rendering` renders to.
+#[derive(Clone)]
+pub struct RenderingAttachment {
+    pub view: Arc<ImageView>,
+    pub load_op: vk::AttachmentLoadOp,
+    pub store_op: vk::AttachmentStoreOp,
+    /// Used if `load_op` is `CLEAR`.
+    pub clear_value: vk::ClearValue,
+}
+
+impl RenderingAttachment {
+    /// An attachment cleared to `clear_value` and stored.
+    pub fn clear(view: Arc<ImageView>, clear_value: vk::ClearValue) -> Self {
+        Self {
+            view,
+            load_op: vk::AttachmentLoadOp::CLEAR,
+            store_op: vk::AttachmentStoreOp::STORE,
+            clear_value,
+        }
+    }
+
+    /// An attachment rendered on top of its contents.
+    pub fn load(view: Arc<ImageView>) -> Self {
+        Self {
+            view,
+            load_op: vk::AttachmentLoadOp::LOAD,
+            store_op: vk::AttachmentStoreOp::STORE,
+            clear_value: vk::ClearValue::default(),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct RenderingAttachmentInfo {
+    s_type: vk::StructureType,
+    p_next: *const c_void,
+    image_view: vk::ImageView,
+    image_layout: vk::ImageLayout,
+    resolve_mode: vk::ResolveModeFlags,
+    resolve_image_view: vk::ImageView,
+    resolve_image_layout: vk::ImageLayout,
+    load_op: vk::AttachmentLoadOp,
+    store_op: vk::AttachmentStoreOp,
+    clear_value: vk::ClearValue,
+}
+
+impl RenderingAttachmentInfo {
+    pub(crate) fn new(
+        attachment: &RenderingAttachment,
+        image_view: vk::ImageView,
+        image_layout: vk::ImageLayout,
+    ) -> Self {
+        Self {
+            s_type: vk::StructureType::from_raw(STRUCTURE_TYPE_RENDERING_ATTACHMENT_INFO),
+            p_next: std::ptr::null(),
+            image_view,
+            image_layout,
+            resolve_mode: vk::ResolveModeFlags::NONE,
+            resolve_image_view: vk::ImageView::null(),
+            resolve_image_layout: vk::ImageLayout::UNDEFINED,
+            load_op: attachment.load_op,
+            store_op: attachment.store_op,
+            clear_value: attachment.clear_value,
+        }
+    }
+}
+
+#[repr(C)]
+pub(crate) struct RenderingInfo {
+    s_type: vk::StructureType,
+    p_next: *const c_void,
+    flags: vk::Flags,
+    render_area: vk::Rect2D,
+    layer_count: u32,
+    view_mask: u32,
+    color_attachment_count: u32,
+    p_color_attachments: *const RenderingAttachmentInfo,
+    p_depth_attachment: *const RenderingAttachmentInfo,
+    p_stencil_attachment: *const RenderingAttachmentInfo,
+}
+
+impl RenderingInfo {
+    /// Refers to the attachments, which must outlive it.
+    pub(crate) fn new(
+        render_area: vk::Rect2D,
+        color_attachments: &[RenderingAttachmentInfo],
+        depth_attachment: Option<&RenderingAttachmentInfo>,
+        stencil_attachment: Option<&RenderingAttachmentInfo>,
+    ) -> Self {
+        let pointer = |attachment: Option<&RenderingAttachmentInfo>| {
+            attachment.map_or(std::ptr::null(), |a| a as *const _)
+        };
+        Self {
+            s_type: vk::StructureType::from_raw(STRUCTURE_TYPE_RENDERING_INFO),
+            p_next: std::ptr::null(),
+            flags: 0,
+            render_area,
+            layer_count: 1,
+            view_mask: 0,
+            color_attachment_count: color_attachments.len() as u32,
+            p_color_attachments: color_attachments.as_ptr(),
+            p_depth_attachment: pointer(depth_attachment),
+            p_stencil_attachment: pointer(stencil_attachment),
+        }
+    }
+}
+
+/// Chained to `vk::GraphicsPipelineCreateInfo` to create a pipeline without a render pass.
+#[repr(C)]
+pub(crate) struct PipelineRenderingCreateInfo {
+    s_type: vk::StructureType,
+    p_next: *mut c_void,
+    view_mask: u32,
+    color_attachment_count: u32,
+    p_color_attachment_formats: *const vk::Format,
+    depth_attachment_format: vk::Format,
+    stencil_attachment_format: vk::Format,
+}
+
+unsafe impl vk::ExtendsGraphicsPipelineCreateInfo for PipelineRenderingCreateInfo {}
+
+impl PipelineRenderingCreateInfo {
+    /// Refers to `color_formats`, which must outlive it.
+    pub(crate) fn new(
+        color_formats: &[vk::Format],
+        depth_format: vk::Format,
+        stencil_format: vk::Format,
+    ) -> Self {
+        Self {
+            s_type: vk::StructureType::from_raw(STRUCTURE_TYPE_PIPELINE_RENDERING_CREATE_INFO),
+            p_next: std::ptr::null_mut(),
+            view_mask: 0,
+            color_attachment_count: color_formats.len() as u32,
+            p_color_attachment_formats: color_formats.as_ptr(),
+            depth_attachment_format: depth_format,
+            stencil_attachment_format: stencil_format,
+        }
+    }
+}
+
+/// Chained to `vk::DeviceCreateInfo` to enable dynamic rendering, or to
+/// `vk::PhysicalDeviceFeatures2` to query its support.
+#[repr(C)]
+pub(crate) struct PhysicalDeviceDynamicRenderingFeatures {
+    s_type: vk::StructureType,
+    p_next: *mut c_void,
+    pub(crate) dynamic_rendering: vk::Bool32,
+}
+
+unsafe impl vk::ExtendsDeviceCreateInfo for PhysicalDeviceDynamicRenderingFeatures {}
+unsafe impl vk::ExtendsPhysicalDeviceFeatures2 for PhysicalDeviceDynamicRenderingFeatures {}
+
+impl PhysicalDeviceDynamicRenderingFeatures {
+    pub(crate) fn new(dynamic_rendering: bool) -> Self {
+        Self {
+            s_type: vk::StructureType::from_raw(
+                STRUCTURE_TYPE_PHYSICAL_DEVICE_DYNAMIC_RENDERING_FEATURES,
+            ),
+            p_next: std::ptr::null_mut(),
+            dynamic_rendering: dynamic_rendering as vk::Bool32,
+        }
+    }
+}
+
+type CmdBeginRendering = unsafe extern "system" fn(vk::CommandBuffer, *const RenderingInfo);
+type CmdEndRendering = unsafe extern "system" fn(vk::CommandBuffer);
+
+/// The commands of the extension, loaded like those of the ash extension loaders.
+pub(crate) struct DynamicRenderingFn {
+    cmd_begin_rendering: CmdBeginRendering,
+    cmd_end_rendering: CmdEndRendering,
+}
+
+impl DynamicRenderingFn {
+    pub(crate) unsafe fn load(instance: &ash::Instance, device: vk::Device) -> Self {
+        let load = |name: &[u8]| {
+            instance
+                .fp_v1_0()
+                .get_device_proc_addr(device, name.as_ptr() as *const c_char)
+                .expect("VK_KHR_dynamic_rendering enabled without its commands")
+        };
+        Self {
+            cmd_begin_rendering: std::mem::transmute(load(b"vkCmdBeginRenderingKHR\0")),
+            cmd_end_rendering: std::mem::transmute(load(b"vkCmdEndRenderingKHR\0")),
+        }
+    }
+
+    pub(crate) unsafe fn cmd_begin_rendering(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        info: &RenderingInfo,
+    ) {
+        (self.cmd_begin_rendering)(command_buffer, info);
+    }
+
+    pub(crate) unsafe fn cmd_end_rendering(&self, command_buffer: vk::CommandBuffer) {
+        (self.cmd_end_rendering)(command_buffer);
+    }
+}
@@ -1,7 +1,7 @@
 #![feature(negative_impls)]
 #![allow(unused)]
 
-use ash::version::{DeviceV1_0, DeviceV1_2, EntryV1_0, InstanceV1_0, InstanceV1_1};
+use ash::version::{DeviceV1_0, DeviceV1_1, DeviceV1_2, EntryV1_0, InstanceV1_0, InstanceV1_1};
 
 use anyhow::Result;
 
@@ -18,6 +18,25 @@ use std::sync::{Arc, Mutex};
 pub use ash::vk;
 pub use vk_mem::MemoryUsage;
 
+mod compressed;
+mod debug_messenger;
+mod dynamic_rendering;
+mod offscreen;
+mod reflect;
+mod render_graph;
+mod uniform_ring;
+
+pub use compressed::CompressedTexture;
+pub use debug_messenger::{DebugCallback, DebugMessage, DebugMessengerDesc};
+pub use dynamic_rendering::RenderingAttachment;
+pub use offscreen::{OffscreenTarget, Pixels};
+pub use reflect::{ReflectedBinding, ShaderReflection};
+pub use render_graph::{
+    ImageAccess, ImageHandle, PassBuilder, PassImages, RenderGraph, TransientImageDesc,
+    TransientImagePool,
+};
+pub use uniform_ring::DynamicUniformRing;
+
 pub mod name {
     pub mod instance {
         pub enum Layer {
@@ -68,6 +87,8 @@ pub mod name {
             KhrAccelerationStructure,
             KhrShaderNonSemanticInfo,
             KhrRayQuery,
+            KhrDynamicRendering,
+            ExtMemoryBudget,
         }
 
         impl Into<&'static str> for &Extension {
@@ -79,12 +100,57 @@ pub mod name {
                     Extension::KhrAccelerationStructure => "VK_KHR_acceleration_structure",
                     Extension::KhrShaderNonSemanticInfo => "VK_KHR_shader_non_semantic_info",
                     Extension::KhrRayQuery => "VK_KHR_ray_query",
+                    Extension::KhrDynamicRendering => "VK_KHR_dynamic_rendering",
+                    Extension::ExtMemoryBudget => "VK_EXT_memory_budget",
                 }
             }
         }
     }
 }
 
+/// Why a Vulkan object couldn't be created, returned by the `try_` constructors. The
+/// constructors without the prefix panic instead.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// A Vulkan call failed, e.g. with `ERROR_OUT_OF_DEVICE_MEMORY` or `ERROR_DEVICE_LOST`.
+    Vulkan(vk::Result),
+    /// The memory allocator failed, with its message.
+    Allocation(String),
+    /// The instance or device lacks a layer, an extension or a format asked for, by name.
+    Unsupported(String),
+    /// A file failed to parse, e.g. a compressed texture, or an argument is out of range, with
+    /// why.
+    InvalidData(String),
+    /// A shader failed to compile, with the compiler output.
+    Compilation(String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Vulkan(result) => write!(f, "Vulkan error {}", result),
+            Error::Allocation(message) => write!(f, "allocation failed: {}", message),
+            Error::Unsupported(name) => write!(f, "{} is not supported", name),
+            Error::InvalidData(message) => write!(f, "invalid data: {}", message),
+            Error::Compilation(output) => write!(f, "shader compilation failed: {}", output),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<vk::Result> for Error {
+    fn from(result: vk::Result) -> Self {
+        Error::Vulkan(result)
+    }
+}
+
+impl From<vk_mem::Error> for Error {
+    fn from(error: vk_mem::Error) -> Self {
+        Error::Allocation(format!("{:?}", error))
+    }
+}
+
 pub struct Entry {
     handle: ash::Entry,
 }
@@ -150,6 +216,8 @@ pub struct Instance {
     surface_loader: ash::extensions::khr::Surface,
     debug_utils_loader: ash::extensions::ext::DebugUtils,
     display_loader: ash::extensions::khr::Display,
+    /// The messenger and the callback it's passed, which has to outlive it.
+    debug_messenger: Option<(vk::DebugUtilsMessengerEXT, Box<DebugCallback>)>,
 }
 
 impl Instance {
@@ -158,6 +226,54 @@ impl Instance {
         layers: &[name::instance::Layer],
         extensions: &[name::instance::Extension],
     ) -> Self {
+        Self::try_new(entry, layers, extensions).unwrap()
+    }
+
+    /// Fails with `Error::Unsupported` if a layer or an extension is missing.
+    pub fn try_new(
+        entry: Arc<Entry>,
+        layers: &[name::instance::Layer],
+        extensions: &[name::instance::Extension],
+    ) -> Result<Self, Error> {
+        Self::create(entry, layers, extensions, None)
+    }
+
+    /// An instance passing the validation messages `messenger` selects to its callback,
+    /// including those of creating and destroying the instance. `extensions` must include
+    /// `ExtDebugUtils`.
+    pub fn with_debug_messenger(
+        entry: Arc<Entry>,
+        layers: &[name::instance::Layer],
+        extensions: &[name::instance::Extension],
+        messenger: DebugMessengerDesc,
+    ) -> Self {
+        Self::try_with_debug_messenger(entry, layers, extensions, messenger).unwrap()
+    }
+
+    /// Fails with `Error::Unsupported` if a layer or an extension is missing.
+    pub fn try_with_debug_messenger(
+        entry: Arc<Entry>,
+        layers: &[name::instance::Layer],
+        extensions: &[name::instance::Extension],
+        messenger: DebugMessengerDesc,
+    ) -> Result<Self, Error> {
+        if !extensions
+            .iter()
+            .any(|extension| matches!(extension, name::instance::Extension::ExtDebugUtils))
+        {
+            return Err(Error::Unsupported(
+                "debug messengers without VK_EXT_debug_utils".to_owned(),
+            ));
+        }
+        Self::create(entry, layers, extensions, Some(messenger))
+    }
+
+    fn create(
+        entry: Arc<Entry>,
+        layers: &[name::instance::Layer],
+        extensions: &[name::instance::Extension],
+        messenger: Option<DebugMessengerDesc>,
+    ) -> Result<Self, Error> {
         let app_name = CString::new(env!("CARGO_PKG_NAME")).unwrap();
         let engine_name = CString::new("Silly Cat Engine").unwrap();
 
@@ -181,7 +297,7 @@ impl Instance {
         for layer in layers {
             let name: &str = layer.into();
             if !supported_layers.contains(&name.to_owned()) {
-                panic!("not support layer {}", &name);
+                return Err(Error::Unsupported(name.to_owned()));
             }
         }
 
@@ -198,15 +314,36 @@ impl Instance {
         for extension in extensions {
             let name: &str = extension.into();
             if !supported_extensions.contains(&name.to_owned()) {
-                panic!("not support extension {}", &name);
+                return Err(Error::Unsupported(name.to_owned()));
             }
         }
 
-        let create_info = vk::InstanceCreateInfo::builder()
+        // The callback is boxed once more for a thin pointer to pass as the user data.
+        let messenger = messenger.map(|messenger| {
+            let callback = Box::new(messenger.callback);
+            let info = vk::DebugUtilsMessengerCreateInfoEXT::builder()
+                .message_severity(messenger.severity)
+                .message_type(messenger.message_type)
+                .pfn_user_callback(Some(debug_messenger::debug_callback))
+                .user_data(callback.as_ref() as *const DebugCallback as *mut std::os::raw::c_void)
+                .build();
+            (info, callback)
+        });
+        // Chained to report the creation of the instance as well.
+        let mut messenger_info = messenger.as_ref().map(|(info, _)| *info);
+
+        let mut create_info = vk::InstanceCreateInfo::builder()
             .application_info(&appinfo)
             .enabled_layer_names(&layers_names_raw)
             .enabled_extension_names(&extension_names_raw);
-        let handle = unsafe { entry.handle.create_instance(&create_info, None).unwrap() };
+        if let Some(messenger_info) = messenger_info.as_mut() {
+            create_info = create_info.push_next(messenger_info);
+        }
+        let handle =
+            unsafe { entry.handle.create_instance(&create_info, None) }.map_err(|e| match e {
+                ash::InstanceError::VkError(result) => Error::Vulkan(result),
+                ash::InstanceError::LoadError(names) => Error::Unsupported(names.join(", ")),
+            })?;
 
         let surface_loader = ash::extensions::khr::Surface::new(&entry.handle, &handle);
 
@@ -214,21 +351,85 @@ impl Instance {
 
         let display_loader = ash::extensions::khr::Display::new(&entry.handle, &handle);
 
+        let debug_messenger = match messenger {
+            Some((info, callback)) => {
+                let messenger =
+                    unsafe { debug_utils_loader.create_debug_utils_messenger(&info, None) };
+                match messenger {
+                    Ok(messenger) => Some((messenger, callback)),
+                    Err(result) => {
+                        unsafe { handle.destroy_instance(None) };
+                        return Err(Error::Vulkan(result));
+                    }
+                }
+            }
+            None => None,
+        };
+
         let result = Self {
             handle,
             entry,
             surface_loader,
             debug_utils_loader,
             display_loader,
+            debug_messenger,
         };
 
-        result
+        Ok(result)
+    }
+
+    /// The device groups of the instance, every physical device being in exactly one, most of
+    /// them alone.
+    pub fn device_groups(&self) -> Vec<PhysicalDeviceGroup> {
+        self.device_group_properties()
+            .iter()
+            .map(|group| PhysicalDeviceGroup {
+                device_names: group.physical_devices[..group.physical_device_count as usize]
+                    .iter()
+                    .map(|pdevice| {
+                        let properties =
+                            unsafe { self.handle.get_physical_device_properties(*pdevice) };
+                        unsafe { CStr::from_ptr(properties.device_name.as_ptr()) }
+                            .to_string_lossy()
+                            .into_owned()
+                    })
+                    .collect(),
+                subset_allocation: group.subset_allocation == vk::TRUE,
+            })
+            .collect()
+    }
+
+    fn device_group_properties(&self) -> Vec<vk::PhysicalDeviceGroupProperties> {
+        unsafe {
+            let mut count = 0;
+            let result = self.handle.fp_v1_1().enumerate_physical_device_groups(
+                self.handle.handle(),
+                &mut count,
+                std::ptr::null_mut(),
+            );
+            assert_eq!(result, vk::Result::SUCCESS);
+            let mut groups = vec![vk::PhysicalDeviceGroupProperties::default(); count as usize];
+            let result = self.handle.fp_v1_1().enumerate_physical_device_groups(
+                self.handle.handle(),
+                &mut count,
+                groups.as_mut_ptr(),
+            );
+            assert_eq!(result, vk::Result::SUCCESS);
+            groups.truncate(count as usize);
+            groups
+        }
     }
 }
 
 impl Drop for Instance {
     fn drop(&mut self) {
+        // The callback is dropped last, destroying the instance reports through it as well.
+        let debug_messenger = self.debug_messenger.take();
         unsafe {
+            if let Some((messenger, _)) = &debug_messenger {
+                self.debug_utils_loader
+                    .destroy_debug_utils_messenger(*messenger, None);
+            }
             self.handle.destroy_instance(None);
         }
     }
@@ -244,28 +445,64 @@ pub struct PhysicalDeviceRayTracingPipelineProperties {
     pub max_ray_hit_attribute_size: u32,
 }
 
+/// Physical devices that can be driven as one logical device, see `Device::new_device_group`.
+#[derive(Debug, Clone)]
+pub struct PhysicalDeviceGroup {
+    /// In device index order.
+    pub device_names: Vec<String>,
+    /// Whether memory can be allocated on some of the devices only, rather than replicated on
+    /// all of them.
+    pub subset_allocation: bool,
+}
+
 pub struct PhysicalDevice {
     handle: vk::PhysicalDevice,
     instance: Arc<Instance>,
     queue_family_index: u32,
+    /// A family of compute queues that can't do graphics, for async compute.
+    compute_queue_family_index: Option<u32>,
+    /// A family of queues that can only transfer, usually backed by DMA engines.
+    transfer_queue_family_index: Option<u32>,
     ray_tracing_pipeline_properties: PhysicalDeviceRayTracingPipelineProperties,
 }
 
 impl PhysicalDevice {
     pub fn new(instance: Arc<Instance>, surface: Option<&Surface>) -> Self {
+        Self::with_index(instance, surface, None)
+    }
+
+    /// Selects the device at `device_index` in enumeration order, or the first discrete device
+    /// if `None`.
+    pub fn with_index(
+        instance: Arc<Instance>,
+        surface: Option<&Surface>,
+        device_index: Option<usize>,
+    ) -> Self {
         let surface_loader = &instance.surface_loader;
         let pdevices =
             unsafe { instance.handle.enumerate_physical_devices() }.expect("Physical device error");
+        if let Some(index) = device_index {
+            assert!(
+                index < pdevices.len(),
+                "device index {} out of range, {} devices found",
+                index,
+                pdevices.len()
+            );
+        }
 
         unsafe {
             let (pdevice, queue_family_index) = pdevices
                 .iter()
-                .filter_map(|pdevice| {
+                .enumerate()
+                .filter(|(index, _)| device_index.map_or(true, |selected| selected == *index))
+                .filter_map(|(_, pdevice)| {
                     let prop = instance.handle.get_physical_device_properties(*pdevice);
                     let queue_families_props = instance
                         .handle
                         .get_physical_device_queue_family_properties(*pdevice);
-                    if prop.device_type == vk::PhysicalDeviceType::INTEGRATED_GPU {
+                    if device_index.is_none()
+                        && prop.device_type == vk::PhysicalDeviceType::INTEGRATED_GPU
+                    {
                         return None;
                     }
 
@@ -337,14 +574,227 @@ impl PhysicalDevice {
                 max_ray_hit_attribute_size: props.max_ray_hit_attribute_size,
             };
 
+            let queue_families_props = instance
+                .handle
+                .get_physical_device_queue_family_properties(pdevice);
+            let dedicated_family = |flags: vk::QueueFlags, excluded: vk::QueueFlags| {
+                queue_families_props
+                    .iter()
+                    .position(|info| {
+                        info.queue_flags.contains(flags) && !info.queue_flags.intersects(excluded)
+                    })
+                    .map(|index| index as u32)
+            };
+            let compute_queue_family_index =
+                dedicated_family(vk::QueueFlags::COMPUTE, vk::QueueFlags::GRAPHICS);
+            let transfer_queue_family_index = dedicated_family(
+                vk::QueueFlags::TRANSFER,
+                vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE,
+            );
+
             Self {
                 handle: pdevice,
                 instance,
                 queue_family_index: queue_family_index as u32,
+                compute_queue_family_index,
+                transfer_queue_family_index,
                 ray_tracing_pipeline_properties,
             }
         }
     }
+
+    /// The features a device of it can be created with.
+    pub fn features(&self) -> vk::PhysicalDeviceFeatures {
+        unsafe {
+            self.instance
+                .handle
+                .get_physical_device_features(self.handle)
+        }
+    }
+
+    /// Whether a device of it can be created with `feature` and the extension it needs.
+    pub fn supports(&self, feature: DeviceFeature) -> bool {
+        if let Some(extension) = feature.extension() {
+            let name: &str = (&extension).into();
+            if !self.supported_extensions().contains(&name.to_owned()) {
+                return false;
+            }
+        }
+        match feature {
+            DeviceFeature::BufferDeviceAddress => {
+                let features =
+                    self.query_features(vk::PhysicalDeviceBufferDeviceAddressFeatures::default());
+                features.buffer_device_address == vk::TRUE
+            }
+            DeviceFeature::Storage16Bit => {
+                let features =
+                    self.query_features(vk::PhysicalDevice16BitStorageFeatures::default());
+                features.uniform_and_storage_buffer16_bit_access == vk::TRUE
+                    && features.storage_buffer16_bit_access == vk::TRUE
+                    && features.storage_push_constant16 == vk::TRUE
+            }
+            DeviceFeature::ScalarBlockLayout => {
+                let features =
+                    self.query_features(vk::PhysicalDeviceScalarBlockLayoutFeatures::default());
+                features.scalar_block_layout == vk::TRUE
+            }
+            DeviceFeature::RayTracingPipeline => {
+                let features =
+                    self.query_features(vk::PhysicalDeviceRayTracingPipelineFeaturesKHR::default());
+                features.ray_tracing_pipeline == vk::TRUE
+            }
+            DeviceFeature::AccelerationStructure => {
+                let features = self
+                    .query_features(vk::PhysicalDeviceAccelerationStructureFeaturesKHR::default());
+                features.acceleration_structure == vk::TRUE
+            }
+            DeviceFeature::RayQuery => {
+                let features =
+                    self.query_features(vk::PhysicalDeviceRayQueryFeaturesKHR::default());
+                features.ray_query == vk::TRUE
+            }
+            DeviceFeature::DynamicRendering => {
+                let features = self.query_features(
+                    dynamic_rendering::PhysicalDeviceDynamicRenderingFeatures::new(false),
+                );
+                features.dynamic_rendering == vk::TRUE
+            }
+        }
+    }
+
+    /// Fills in the feature struct `features` with what the physical device supports.
+    fn query_features<T>(&self, mut features: T) -> T
+    where
+        T: vk::ExtendsPhysicalDeviceFeatures2,
+    {
+        unsafe {
+            self.instance.handle.get_physical_device_features2(
+                self.handle,
+                &mut vk::PhysicalDeviceFeatures2::builder().push_next(&mut features),
+            );
+        }
+        features
+    }
+
+    /// The first of `candidates` that images of `tiling` can have for `features`, e.g. the
+    /// depth format of depth attachments.
+    pub fn supported_format(
+        &self,
+        candidates: &[vk::Format],
+        tiling: vk::ImageTiling,
+        features: vk::FormatFeatureFlags,
+    ) -> Option<vk::Format> {
+        candidates.iter().copied().find(|&format| {
+            let properties = unsafe {
+                self.instance
+                    .handle
+                    .get_physical_device_format_properties(self.handle, format)
+            };
+            match tiling {
+                vk::ImageTiling::LINEAR => properties.linear_tiling_features.contains(features),
+                _ => properties.optimal_tiling_features.contains(features),
+            }
+        })
+    }
+
+    /// The most precise depth format depth attachments of optimal tiling can have, with a
+    /// stencil aspect if `stencil`.
+    pub fn depth_format(&self, stencil: bool) -> vk::Format {
+        let candidates: &[vk::Format] = if stencil {
+            &[
+                vk::Format::D32_SFLOAT_S8_UINT,
+                vk::Format::D24_UNORM_S8_UINT,
+                vk::Format::D16_UNORM_S8_UINT,
+            ]
+        } else {
+            &[
+                vk::Format::D32_SFLOAT,
+                vk::Format::X8_D24_UNORM_PACK32,
+                vk::Format::D16_UNORM,
+            ]
+        };
+        // Every device supports one of each.
+        self.supported_format(
+            candidates,
+            vk::ImageTiling::OPTIMAL,
+            vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT,
+        )
+        .unwrap()
+    }
+
+    pub fn properties(&self) -> vk::PhysicalDeviceProperties {
+        unsafe {
+            self.instance
+                .handle
+                .get_physical_device_properties(self.handle)
+        }
+    }
+
+    /// The family of the graphics queue, which can present to the surface the device was
+    /// selected for.
+    pub fn queue_family_index(&self) -> u32 {
+        self.queue_family_index
+    }
+
+    /// The family of compute queues that can't do graphics, if the device has one.
+    pub fn compute_queue_family_index(&self) -> Option<u32> {
+        self.compute_queue_family_index
+    }
+
+    /// The family of queues that can only transfer, if the device has one.
+    pub fn transfer_queue_family_index(&self) -> Option<u32> {
+        self.transfer_queue_family_index
+    }
+
+    pub fn name(&self) -> String {
+        let properties = self.properties();
+        unsafe { CStr::from_ptr(properties.device_name.as_ptr()) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    pub fn supported_extensions(&self) -> Vec<String> {
+        unsafe {
+            self.instance
+                .handle
+                .enumerate_device_extension_properties(self.handle)
+                .unwrap()
+                .iter()
+                .map(|ext| {
+                    CStr::from_ptr(ext.extension_name.as_ptr() as *const std::os::raw::c_char)
+                        .to_str()
+                        .unwrap()
+                        .to_owned()
+                })
+                .collect::<Vec<_>>()
+        }
+    }
+
+    /// Whether every one of `extensions` can be enabled on the device, e.g. to fall back to
+    /// rasterization when the ray tracing extensions are missing.
+    pub fn supports_extensions(&self, extensions: &[name::device::Extension]) -> bool {
+        let supported_extensions = self.supported_extensions();
+        extensions.iter().all(|extension| {
+            let name: &str = extension.into();
+            supported_extensions.contains(&name.to_owned())
+        })
+    }
+
+    /// Physical devices `Device::new_device_group` drives, 1 if this one isn't in a group with
+    /// others.
+    pub fn device_group_size(&self) -> u32 {
+        self.device_group().len() as u32
+    }
+
+    /// The physical devices of the device group of this one, in device index order.
+    fn device_group(&self) -> Vec<vk::PhysicalDevice> {
+        self.instance
+            .device_group_properties()
+            .iter()
+            .map(|group| group.physical_devices[..group.physical_device_count as usize].to_vec())
+            .find(|pdevices| pdevices.contains(&self.handle))
+            .unwrap_or_else(|| vec![self.handle])
+    }
 }
 
 pub struct Surface {
@@ -393,12 +843,200 @@ struct PhysicalDeviceFeatureEnablement {
     ray_query: vk::PhysicalDeviceRayQueryFeaturesKHR,
 }
 
+/// A feature struct chained to the creation of a device, each enabling the features of its kind
+/// the engine uses.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeviceFeature {
+    BufferDeviceAddress,
+    /// 16-bit types in uniform and storage buffers and in push constants.
+    Storage16Bit,
+    ScalarBlockLayout,
+    RayTracingPipeline,
+    AccelerationStructure,
+    RayQuery,
+    DynamicRendering,
+}
+
+impl DeviceFeature {
+    pub const ALL: [DeviceFeature; 7] = [
+        DeviceFeature::BufferDeviceAddress,
+        DeviceFeature::Storage16Bit,
+        DeviceFeature::ScalarBlockLayout,
+        DeviceFeature::RayTracingPipeline,
+        DeviceFeature::AccelerationStructure,
+        DeviceFeature::RayQuery,
+        DeviceFeature::DynamicRendering,
+    ];
+
+    /// The extension the feature comes with, `None` for those of Vulkan 1.2.
+    pub fn extension(self) -> Option<name::device::Extension> {
+        match self {
+            DeviceFeature::BufferDeviceAddress
+            | DeviceFeature::Storage16Bit
+            | DeviceFeature::ScalarBlockLayout => None,
+            DeviceFeature::RayTracingPipeline => {
+                Some(name::device::Extension::KhrRayTracingPipeline)
+            }
+            DeviceFeature::AccelerationStructure => {
+                Some(name::device::Extension::KhrAccelerationStructure)
+            }
+            DeviceFeature::RayQuery => Some(name::device::Extension::KhrRayQuery),
+            DeviceFeature::DynamicRendering => Some(name::device::Extension::KhrDynamicRendering),
+        }
+    }
+}
+
+/// Creates a `Device` with only the extensions and feature structs asked for, unlike
+/// `Device::new` which always enables some. Everything asked for is checked against what the
+/// physical device supports first, so a missing feature fails with `Error::Unsupported` rather
+/// than in the driver, e.g. to fall back to rasterization without ray tracing.
+pub struct DeviceBuilder {
+    pdevice: Arc<PhysicalDevice>,
+    features: vk::PhysicalDeviceFeatures,
+    extensions: Vec<name::device::Extension>,
+    feature_structs: Vec<DeviceFeature>,
+    device_group: bool,
+}
+
+impl DeviceBuilder {
+    /// The core features to enable.
+    pub fn features(mut self, features: vk::PhysicalDeviceFeatures) -> Self {
+        self.features = features;
+        self
+    }
+
+    pub fn extension(mut self, extension: name::device::Extension) -> Self {
+        if !self.extensions.contains(&extension) {
+            self.extensions.push(extension);
+        }
+        self
+    }
+
+    /// Enables `feature`, and the extension it comes with.
+    pub fn feature(mut self, feature: DeviceFeature) -> Self {
+        if let Some(extension) = feature.extension() {
+            self = self.extension(extension);
+        }
+        if !self.feature_structs.contains(&feature) {
+            self.feature_structs.push(feature);
+        }
+        self
+    }
+
+    /// Drives the device group of the physical device, see `Device::new_device_group`.
+    pub fn device_group(mut self) -> Self {
+        self.device_group = true;
+        self
+    }
+
+    pub fn build(self) -> Result<Device, Error> {
+        let group = if self.device_group {
+            self.pdevice.device_group()
+        } else {
+            Vec::new()
+        };
+        let group = if group.len() > 1 { group } else { Vec::new() };
+        Device::create(
+            self.pdevice,
+            &self.features,
+            &self.extensions,
+            &self.feature_structs,
+            &group,
+        )
+    }
+}
+
+/// The fields of `vk::PhysicalDeviceFeatures`, in order.
+const CORE_FEATURE_NAMES: [&str; 55] = [
+    "robust_buffer_access",
+    "full_draw_index_uint32",
+    "image_cube_array",
+    "independent_blend",
+    "geometry_shader",
+    "tessellation_shader",
+    "sample_rate_shading",
+    "dual_src_blend",
+    "logic_op",
+    "multi_draw_indirect",
+    "draw_indirect_first_instance",
+    "depth_clamp",
+    "depth_bias_clamp",
+    "fill_mode_non_solid",
+    "depth_bounds",
+    "wide_lines",
+    "large_points",
+    "alpha_to_one",
+    "multi_viewport",
+    "sampler_anisotropy",
+    "texture_compression_etc2",
+    "texture_compression_astc_ldr",
+    "texture_compression_bc",
+    "occlusion_query_precise",
+    "pipeline_statistics_query",
+    "vertex_pipeline_stores_and_atomics",
+    "fragment_stores_and_atomics",
+    "shader_tessellation_and_geometry_point_size",
+    "shader_image_gather_extended",
+    "shader_storage_image_extended_formats",
+    "shader_storage_image_multisample",
+    "shader_storage_image_read_without_format",
+    "shader_storage_image_write_without_format",
+    "shader_uniform_buffer_array_dynamic_indexing",
+    "shader_sampled_image_array_dynamic_indexing",
+    "shader_storage_buffer_array_dynamic_indexing",
+    "shader_storage_image_array_dynamic_indexing",
+    "shader_clip_distance",
+    "shader_cull_distance",
+    "shader_float64",
+    "shader_int64",
+    "shader_int16",
+    "shader_resource_residency",
+    "shader_resource_min_lod",
+    "sparse_binding",
+    "sparse_residency_buffer",
+    "sparse_residency_image2_d",
+    "sparse_residency_image3_d",
+    "sparse_residency2_samples",
+    "sparse_residency4_samples",
+    "sparse_residency8_samples",
+    "sparse_residency16_samples",
+    "sparse_residency_aliased",
+    "variable_multisample_rate",
+    "inherited_queries",
+];
+
+/// The names of the features of `requested` that `supported` lacks.
+fn missing_features(
+    requested: &vk::PhysicalDeviceFeatures,
+    supported: &vk::PhysicalDeviceFeatures,
+) -> Vec<&'static str> {
+    // The struct is nothing but `vk::Bool32`s.
+    let as_slice = |features: &vk::PhysicalDeviceFeatures| unsafe {
+        std::slice::from_raw_parts(
+            features as *const _ as *const vk::Bool32,
+            CORE_FEATURE_NAMES.len(),
+        )
+    };
+    as_slice(requested)
+        .iter()
+        .zip(as_slice(supported))
+        .zip(CORE_FEATURE_NAMES.iter())
+        .filter(|((&requested, &supported), _)| requested == vk::TRUE && supported != vk::TRUE)
+        .map(|(_, name)| *name)
+        .collect()
+}
+
 pub struct Device {
     handle: ash::Device,
     pdevice: Arc<PhysicalDevice>,
+    /// Physical devices driven by the device, more than 1 for those of `new_device_group`.
+    device_count: u32,
+    features: vk::PhysicalDeviceFeatures,
     acceleration_structure_loader: ash::extensions::khr::AccelerationStructure,
     swapchain_loader: ash::extensions::khr::Swapchain,
     ray_tracing_pipeline_loader: ash::extensions::khr::RayTracingPipeline,
+    /// `None` unless created with `KhrDynamicRendering`.
+    dynamic_rendering_fn: Option<dynamic_rendering::DynamicRenderingFn>,
 }
 
 impl Device {
@@ -407,13 +1045,146 @@ impl Device {
         device_features: &vk::PhysicalDeviceFeatures,
         device_extensions: &[name::device::Extension],
     ) -> Self {
+        Self::try_new(pdevice, device_features, device_extensions).unwrap()
+    }
+
+    /// Fails with `Error::Unsupported` if an extension is missing, e.g. so the ray tracing ones
+    /// can be left out and rendering falls back to rasterization.
+    pub fn try_new(
+        pdevice: Arc<PhysicalDevice>,
+        device_features: &vk::PhysicalDeviceFeatures,
+        device_extensions: &[name::device::Extension],
+    ) -> Result<Self, Error> {
+        Self::with_physical_devices(pdevice, device_features, device_extensions, &[])
+    }
+
+    /// Drives every physical device of the device group of `pdevice` as one device. Like `new`
+    /// if `pdevice` isn't in a group with others, or if the group can't take buffer device
+    /// addresses on every device.
+    ///
+    /// Every allocation has an instance on each physical device, and commands run on all of
+    /// them unless restricted with `CommandRecorder::set_device_mask` or
+    /// `Queue::submit_device_group`. Nothing synchronizes the devices with each other: barriers
+    /// only order the commands of each device against its own, and a write is only seen by the
+    /// device that made it until it's copied to the instance of another, see
+    /// `peer_memory_features`. A device waits for another with a timeline semaphore value the
+    /// other signals, each with its own device index in `Queue::submit_device_group`. Fences
+    /// signal once every device of the submission is done.
+    pub fn new_device_group(
+        pdevice: Arc<PhysicalDevice>,
+        device_features: &vk::PhysicalDeviceFeatures,
+        device_extensions: &[name::device::Extension],
+    ) -> Self {
+        let pdevices = pdevice.device_group();
+        if pdevices.len() > 1 {
+            match Self::with_physical_devices(
+                pdevice.clone(),
+                device_features,
+                device_extensions,
+                &pdevices,
+            ) {
+                Ok(device) => {
+                    log::info!("Driving a device group of {} devices", pdevices.len());
+                    return device;
+                }
+                Err(Error::Unsupported(feature)) => {
+                    log::warn!("Not driving the device group, {} is unsupported", feature);
+                }
+                Err(error) => panic!("{:?}", error),
+            }
+        }
+        Self::new(pdevice, device_features, device_extensions)
+    }
+
+    /// Chooses the extensions and feature structs of the device, see `DeviceBuilder`.
+    pub fn builder(pdevice: Arc<PhysicalDevice>) -> DeviceBuilder {
+        DeviceBuilder {
+            pdevice,
+            features: vk::PhysicalDeviceFeatures::default(),
+            extensions: Vec::new(),
+            feature_structs: Vec::new(),
+            device_group: false,
+        }
+    }
+
+    /// `group` is empty for a device of `pdevice` alone. Enables buffer device address, 16-bit
+    /// storage and scalar block layout, and the features of the extensions that have some.
+    fn with_physical_devices(
+        pdevice: Arc<PhysicalDevice>,
+        device_features: &vk::PhysicalDeviceFeatures,
+        device_extensions: &[name::device::Extension],
+        group: &[vk::PhysicalDevice],
+    ) -> Result<Self, Error> {
+        let mut feature_structs = vec![
+            DeviceFeature::BufferDeviceAddress,
+            DeviceFeature::Storage16Bit,
+            DeviceFeature::ScalarBlockLayout,
+        ];
+        feature_structs.extend(DeviceFeature::ALL.iter().copied().filter(|feature| {
+            feature
+                .extension()
+                .map_or(false, |extension| device_extensions.contains(&extension))
+        }));
+        Self::create(
+            pdevice,
+            device_features,
+            device_extensions,
+            &feature_structs,
+            group,
+        )
+    }
+
+    /// Fails with `Error::Unsupported` if `pdevice` lacks an extension or a feature.
+    fn create(
+        pdevice: Arc<PhysicalDevice>,
+        device_features: &vk::PhysicalDeviceFeatures,
+        device_extensions: &[name::device::Extension],
+        feature_structs: &[DeviceFeature],
+        group: &[vk::PhysicalDevice],
+    ) -> Result<Self, Error> {
+        let supported_extensions = pdevice.supported_extensions();
+        for extension in device_extensions {
+            let name: &str = extension.into();
+            if !supported_extensions.contains(&name.to_owned()) {
+                return Err(Error::Unsupported(name.to_owned()));
+            }
+        }
+        if let Some(feature) = missing_features(device_features, &pdevice.features()).first() {
+            return Err(Error::Unsupported(format!("feature {}", feature)));
+        }
+        for feature in feature_structs {
+            if !pdevice.supports(*feature) {
+                return Err(Error::Unsupported(format!("feature {:?}", feature)));
+            }
+        }
+        let enabled = |feature| feature_structs.contains(&feature);
+        // Every buffer has a device address, which a device of several physical devices can
+        // only take with the multi-device feature.
+        let multi_device_address = !group.is_empty() && enabled(DeviceFeature::BufferDeviceAddress);
+        if multi_device_address
+            && pdevice
+                .query_features(vk::PhysicalDeviceBufferDeviceAddressFeatures::default())
+                .buffer_device_address_multi_device
+                != vk::TRUE
+        {
+            return Err(Error::Unsupported(
+                "feature bufferDeviceAddressMultiDevice".to_owned(),
+            ));
+        }
         unsafe {
             let priorities = [1.0];
 
-            let queue_info = [vk::DeviceQueueCreateInfo::builder()
-                .queue_family_index(pdevice.queue_family_index)
-                .queue_priorities(&priorities)
-                .build()];
+            // A queue of the graphics family, and one of each dedicated family besides.
+            let queue_info = std::iter::once(pdevice.queue_family_index)
+                .chain(pdevice.compute_queue_family_index)
+                .chain(pdevice.transfer_queue_family_index)
+                .map(|family_index| {
+                    vk::DeviceQueueCreateInfo::builder()
+                        .queue_family_index(family_index)
+                        .queue_priorities(&priorities)
+                        .build()
+                })
+                .collect::<Vec<_>>();
 
             let device_extension_names = device_extensions
                 .iter()
@@ -438,6 +1209,7 @@ impl Device {
             let mut device_buffer_address_pnext =
                 vk::PhysicalDeviceBufferDeviceAddressFeatures::builder()
                     .buffer_device_address(true)
+                    .buffer_device_address_multi_device(multi_device_address)
                     .build();
             let mut fea_16_bit_storage_pnext = vk::PhysicalDevice16BitStorageFeatures::builder()
                 .uniform_and_storage_buffer16_bit_access(true)
@@ -455,35 +1227,48 @@ impl Device {
                 .enabled_extension_names(&device_extension_names_raw)
                 .enabled_features(&device_features);
 
-            device_create_info =
-                if device_extensions.contains(&name::device::Extension::KhrRayTracingPipeline) {
-                    device_create_info.push_next(&mut ray_tracing_pipeline_pnext)
-                } else {
-                    device_create_info
-                };
-            device_create_info =
-                if device_extensions.contains(&name::device::Extension::KhrRayQuery) {
-                    device_create_info.push_next(&mut ray_query_pnext)
-                } else {
-                    device_create_info
-                };
-            device_create_info =
-                if device_extensions.contains(&name::device::Extension::KhrAccelerationStructure) {
-                    device_create_info.push_next(&mut acceleration_structure_pnext)
-                } else {
-                    device_create_info
-                };
+            device_create_info = if enabled(DeviceFeature::RayTracingPipeline) {
+                device_create_info.push_next(&mut ray_tracing_pipeline_pnext)
+            } else {
+                device_create_info
+            };
+            device_create_info = if enabled(DeviceFeature::RayQuery) {
+                device_create_info.push_next(&mut ray_query_pnext)
+            } else {
+                device_create_info
+            };
+            device_create_info = if enabled(DeviceFeature::AccelerationStructure) {
+                device_create_info.push_next(&mut acceleration_structure_pnext)
+            } else {
+                device_create_info
+            };
 
-            device_create_info = device_create_info
-                .push_next(&mut device_buffer_address_pnext)
-                .push_next(&mut fea_16_bit_storage_pnext)
-                .push_next(&mut scalar_block_layout_pnext);
+            let mut dynamic_rendering_pnext =
+                dynamic_rendering::PhysicalDeviceDynamicRenderingFeatures::new(true);
+            let dynamic_rendering = enabled(DeviceFeature::DynamicRendering);
+            if dynamic_rendering {
+                device_create_info = device_create_info.push_next(&mut dynamic_rendering_pnext);
+            }
 
-            let handle = pdevice
-                .instance
-                .handle
-                .create_device(pdevice.handle, &device_create_info, None)
-                .unwrap();
+            let mut device_group_pnext = vk::DeviceGroupDeviceCreateInfo::builder()
+                .physical_devices(group)
+                .build();
+            if !group.is_empty() {
+                device_create_info = device_create_info.push_next(&mut device_group_pnext);
+            }
+
+            if enabled(DeviceFeature::BufferDeviceAddress) {
+                device_create_info = device_create_info.push_next(&mut device_buffer_address_pnext);
+            }
+            if enabled(DeviceFeature::Storage16Bit) {
+                device_create_info = device_create_info.push_next(&mut fea_16_bit_storage_pnext);
+            }
+            if enabled(DeviceFeature::ScalarBlockLayout) {
+                device_create_info = device_create_info.push_next(&mut scalar_block_layout_pnext);
+            }
+
+            let instance = &pdevice.instance.handle;
+            let handle = instance.create_device(pdevice.handle, &device_create_info, None)?;
 
             let acceleration_structure_loader =
                 ash::extensions::khr::AccelerationStructure::new(&pdevice.instance.handle, &handle);
@@ -494,32 +1279,258 @@ impl Device {
             let ray_tracing_pipeline_loader =
                 ash::extensions::khr::RayTracingPipeline::new(&pdevice.instance.handle, &handle);
 
-            Self {
+            let dynamic_rendering_fn = if dynamic_rendering {
+                Some(dynamic_rendering::DynamicRenderingFn::load(
+                    &pdevice.instance.handle,
+                    handle.handle(),
+                ))
+            } else {
+                None
+            };
+
+            Ok(Self {
                 handle,
                 pdevice,
+                device_count: group.len().max(1) as u32,
+                features: *device_features,
                 acceleration_structure_loader,
                 swapchain_loader,
                 ray_tracing_pipeline_loader,
-            }
+                dynamic_rendering_fn,
+            })
         }
     }
 
     pub fn pdevice(&self) -> &PhysicalDevice {
         &self.pdevice
     }
-}
 
-impl Drop for Device {
-    fn drop(&mut self) {
+    /// The features the device was created with.
+    pub fn features(&self) -> &vk::PhysicalDeviceFeatures {
+        &self.features
+    }
+
+    /// A queue of the dedicated compute family, to run compute work alongside graphics. The
+    /// graphics queue if the device has no such family.
+    pub fn compute_queue(self: &Arc<Self>) -> Queue {
+        let family_index = self
+            .pdevice
+            .compute_queue_family_index
+            .unwrap_or(self.pdevice.queue_family_index);
+        Queue::with_family_index(self.clone(), family_index)
+    }
+
+    /// A queue of the transfer-only family, to upload while the other queues render. Falls
+    /// back to the compute queue, then to the graphics one.
+    pub fn transfer_queue(self: &Arc<Self>) -> Queue {
+        let family_index = self
+            .pdevice
+            .transfer_queue_family_index
+            .or(self.pdevice.compute_queue_family_index)
+            .unwrap_or(self.pdevice.queue_family_index);
+        Queue::with_family_index(self.clone(), family_index)
+    }
+
+    /// Physical devices the device drives, see `new_device_group`.
+    pub fn device_count(&self) -> u32 {
+        self.device_count
+    }
+
+    /// The device mask of every physical device the device drives.
+    pub fn all_devices_mask(&self) -> u32 {
+        (1 << self.device_count) - 1
+    }
+
+    /// How the physical device at `local_device_index` can access the instance of memory of
+    /// heap `heap_index` on the one at `remote_device_index`, e.g. whether it can copy a band
+    /// traced there to its own instance of an image. Empty for devices of one physical device.
+    pub fn peer_memory_features(
+        &self,
+        heap_index: u32,
+        local_device_index: u32,
+        remote_device_index: u32,
+    ) -> vk::PeerMemoryFeatureFlags {
+        if self.device_count == 1 {
+            return vk::PeerMemoryFeatureFlags::empty();
+        }
         unsafe {
-            self.handle.destroy_device(None);
+            self.handle.get_device_group_peer_memory_features(
+                heap_index,
+                local_device_index,
+                remote_device_index,
+            )
         }
     }
 }
 
-pub struct Allocator {
-    handle: vk_mem::Allocator,
-    device: Arc<Device>,
+/// How the ray tracing of frames is spread over the physical devices of a device group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceGroupSplit {
+    /// Each frame is traced by one device, the devices taking turns.
+    AlternateFrame,
+    /// Each device traces a band of rows of every frame.
+    SplitScreen,
+}
+
+/// The part of a frame one physical device traces, see `DeviceGroupSplit::regions`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceRegion {
+    pub device_index: u32,
+    /// Top left pixel, for the ray generation shader to add to the launch ID.
+    pub offset: [u32; 2],
+    pub extent: [u32; 2],
+}
+
+impl DeviceRegion {
+    pub fn device_mask(&self) -> u32 {
+        1 << self.device_index
+    }
+}
+
+impl DeviceGroupSplit {
+    /// The regions of frame `frame_index` of a `width` by `height` image for `device_count`
+    /// devices, each traced after `CommandRecorder::set_device_mask(region.device_mask())`.
+    /// Devices without a region have nothing to trace. The results end up in the instances of
+    /// the image on the tracing devices, which have to copy them to the presenting one, and
+    /// signal it with `Queue::submit_device_group` that they did, see
+    /// `Device::new_device_group`.
+    pub fn regions(
+        &self,
+        device_count: u32,
+        frame_index: u64,
+        width: u32,
+        height: u32,
+    ) -> Vec<DeviceRegion> {
+        assert!(device_count > 0);
+        match self {
+            DeviceGroupSplit::AlternateFrame => vec![DeviceRegion {
+                device_index: (frame_index % device_count as u64) as u32,
+                offset: [0, 0],
+                extent: [width, height],
+            }],
+            DeviceGroupSplit::SplitScreen => {
+                // The first devices take a row more each if the rows don't divide evenly.
+                let (rows, remainder) = (height / device_count, height % device_count);
+                let mut regions = Vec::with_capacity(device_count as usize);
+                let mut y = 0;
+                for device_index in 0..device_count {
+                    let band = rows + (device_index < remainder) as u32;
+                    if band > 0 {
+                        regions.push(DeviceRegion {
+                            device_index,
+                            offset: [0, y],
+                            extent: [width, band],
+                        });
+                    }
+                    y += band;
+                }
+                regions
+            }
+        }
+    }
+}
+
+impl Drop for Device {
+    fn drop(&mut self) {
+        unsafe {
+            self.handle.destroy_device(None);
+        }
+    }
+}
+
+/// A live buffer or image of an `Allocator`.
+#[derive(Debug, Clone)]
+pub struct AllocationRecord {
+    pub name: Option<String>,
+    /// `BUFFER` or `IMAGE`.
+    pub object_type: vk::ObjectType,
+    /// Bytes of device memory bound to the object.
+    pub size: u64,
+    /// Where the object was created, if `Allocator::set_tracking` was on then.
+    pub backtrace: Option<Arc<std::backtrace::Backtrace>>,
+}
+
+/// The live buffers and images of an `Allocator`, see `Allocator::allocation_report`.
+#[derive(Debug, Clone)]
+pub struct AllocationReport {
+    /// Per object type and name, largest first.
+    pub totals: Vec<AllocationTotal>,
+    /// Largest first.
+    pub largest: Vec<AllocationRecord>,
+}
+
+/// The live buffers or images of the same name.
+#[derive(Debug, Clone)]
+pub struct AllocationTotal {
+    pub name: Option<String>,
+    pub object_type: vk::ObjectType,
+    pub count: usize,
+    pub size: u64,
+}
+
+impl std::fmt::Display for AllocationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = |object_type: vk::ObjectType| {
+            if object_type == vk::ObjectType::IMAGE {
+                "image"
+            } else {
+                "buffer"
+            }
+        };
+        let name = |name: &Option<String>| name.clone().unwrap_or_else(|| "unnamed".to_owned());
+        writeln!(f, "Totals:")?;
+        for total in &self.totals {
+            writeln!(
+                f,
+                "{:>14} bytes in {:>5} {}s {:?}",
+                total.size,
+                total.count,
+                kind(total.object_type),
+                name(&total.name)
+            )?;
+        }
+        writeln!(f, "Largest:")?;
+        for record in &self.largest {
+            writeln!(
+                f,
+                "{:>14} bytes, {} {:?}",
+                record.size,
+                kind(record.object_type),
+                name(&record.name)
+            )?;
+            if let Some(backtrace) = &record.backtrace {
+                writeln!(f, "{}", backtrace)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How full one memory heap is, see `Allocator::heap_usage`.
+#[derive(Debug, Clone, Copy)]
+pub struct HeapUsage {
+    pub flags: vk::MemoryHeapFlags,
+    pub size: u64,
+    /// Bytes the process can use before allocations fail or slow down. The heap size on devices
+    /// without `VK_EXT_memory_budget`.
+    pub budget: u64,
+    /// Bytes the process uses, `block_bytes` on devices without `VK_EXT_memory_budget`.
+    pub usage: u64,
+    /// Bytes of the memory blocks of the allocator.
+    pub block_bytes: u64,
+    /// Bytes of those blocks taken by allocations.
+    pub allocation_bytes: u64,
+    pub block_count: u32,
+    pub allocation_count: u32,
+}
+
+pub struct Allocator {
+    handle: vk_mem::Allocator,
+    device: Arc<Device>,
+    /// Live buffers and images by object type and raw handle.
+    allocations: Mutex<HashMap<(vk::ObjectType, u64), AllocationRecord>>,
+    /// Whether creation backtraces are captured, see `set_tracking`.
+    tracking: std::sync::atomic::AtomicBool,
 }
 
 impl Allocator {
@@ -534,7 +1545,12 @@ impl Allocator {
             })
             .unwrap();
 
-            Self { handle, device }
+            Self {
+                handle,
+                device,
+                allocations: Mutex::new(HashMap::new()),
+                tracking: std::sync::atomic::AtomicBool::new(false),
+            }
         }
     }
 
@@ -542,6 +1558,122 @@ impl Allocator {
         self.handle.calculate_stats().unwrap()
     }
 
+    /// Usage and budget of every memory heap of the device, in heap index order.
+    pub fn heap_usage(&self) -> Vec<HeapUsage> {
+        let stats = self.stats();
+        let pdevice = &self.device.pdevice;
+        let supports_budget =
+            pdevice.supports_extensions(&[name::device::Extension::ExtMemoryBudget]);
+        let mut budget_properties = vk::PhysicalDeviceMemoryBudgetPropertiesEXT::default();
+        let mut properties = vk::PhysicalDeviceMemoryProperties2::builder();
+        if supports_budget {
+            properties = properties.push_next(&mut budget_properties);
+        }
+        let mut properties = properties.build();
+        unsafe {
+            pdevice
+                .instance
+                .handle
+                .get_physical_device_memory_properties2(pdevice.handle, &mut properties);
+        }
+        let memory_properties = properties.memory_properties;
+        memory_properties.memory_heaps[..memory_properties.memory_heap_count as usize]
+            .iter()
+            .enumerate()
+            .map(|(index, heap)| {
+                let info = &stats.memoryHeap[index];
+                let block_bytes = info.usedBytes + info.unusedBytes;
+                let (budget, usage) = if supports_budget {
+                    (
+                        budget_properties.heap_budget[index],
+                        budget_properties.heap_usage[index],
+                    )
+                } else {
+                    (heap.size, block_bytes)
+                };
+                HeapUsage {
+                    flags: heap.flags,
+                    size: heap.size,
+                    budget,
+                    usage,
+                    block_bytes,
+                    allocation_bytes: info.usedBytes,
+                    block_count: info.blockCount,
+                    allocation_count: info.allocationCount,
+                }
+            })
+            .collect()
+    }
+
+    /// The `count` largest live buffers and images, largest first.
+    pub fn largest_allocations(&self, count: usize) -> Vec<AllocationRecord> {
+        let mut allocations: Vec<AllocationRecord> =
+            self.allocations.lock().unwrap().values().cloned().collect();
+        allocations.sort_by(|a, b| b.size.cmp(&a.size));
+        allocations.truncate(count);
+        allocations
+    }
+
+    /// Captures the backtrace of every buffer and image created from now on, for
+    /// `allocation_report`, e.g. to find what leaks staging buffers. Off by default, capturing
+    /// slows allocations down.
+    pub fn set_tracking(&self, enabled: bool) {
+        self.tracking
+            .store(enabled, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    pub fn is_tracking(&self) -> bool {
+        self.tracking.load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    /// The live buffers and images totaled per type and name, and the `count` largest of them
+    /// with their backtraces if created while tracking.
+    pub fn allocation_report(&self, count: usize) -> AllocationReport {
+        let mut totals = HashMap::<(vk::ObjectType, Option<String>), AllocationTotal>::new();
+        for record in self.allocations.lock().unwrap().values() {
+            let total = totals
+                .entry((record.object_type, record.name.clone()))
+                .or_insert_with(|| AllocationTotal {
+                    name: record.name.clone(),
+                    object_type: record.object_type,
+                    count: 0,
+                    size: 0,
+                });
+            total.count += 1;
+            total.size += record.size;
+        }
+        let mut totals = totals.into_values().collect::<Vec<_>>();
+        totals.sort_by(|a, b| b.size.cmp(&a.size));
+        AllocationReport {
+            totals,
+            largest: self.largest_allocations(count),
+        }
+    }
+
+    fn track(&self, object_type: vk::ObjectType, handle: u64, name: Option<&str>, size: u64) {
+        let backtrace = if self.is_tracking() {
+            Some(Arc::new(std::backtrace::Backtrace::force_capture()))
+        } else {
+            None
+        };
+        self.allocations.lock().unwrap().insert(
+            (object_type, handle),
+            AllocationRecord {
+                name: name.map(str::to_owned),
+                object_type,
+                size,
+                backtrace,
+            },
+        );
+    }
+
+    fn untrack(&self, object_type: vk::ObjectType, handle: u64) {
+        self.allocations
+            .lock()
+            .unwrap()
+            .remove(&(object_type, handle));
+    }
+
     pub fn device(&self) -> &Arc<Device> {
         &self.device
     }
@@ -564,14 +1696,22 @@ impl DescriptorPool {
         descriptor_pool_size: &[vk::DescriptorPoolSize],
         max_sets: u32,
     ) -> Self {
+        Self::try_new(device, descriptor_pool_size, max_sets).unwrap()
+    }
+
+    pub fn try_new(
+        device: Arc<Device>,
+        descriptor_pool_size: &[vk::DescriptorPoolSize],
+        max_sets: u32,
+    ) -> Result<Self, Error> {
         unsafe {
             let info = vk::DescriptorPoolCreateInfo::builder()
                 .pool_sizes(descriptor_pool_size)
                 .max_sets(max_sets)
                 .flags(vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET)
                 .build();
-            let handle = device.handle.create_descriptor_pool(&info, None).unwrap();
-            Self { handle, device }
+            let handle = device.handle.create_descriptor_pool(&info, None)?;
+            Ok(Self { handle, device })
         }
     }
 }
@@ -586,6 +1726,65 @@ impl Drop for DescriptorPool {
     }
 }
 
+/// Allocates descriptor sets of one layout, adding a pool whenever the existing ones are full.
+pub struct DescriptorAllocator {
+    descriptor_set_layout: Arc<DescriptorSetLayout>,
+    sets_per_pool: u32,
+    pools: Vec<(Arc<DescriptorPool>, u32)>,
+}
+
+impl DescriptorAllocator {
+    pub fn new(descriptor_set_layout: Arc<DescriptorSetLayout>, sets_per_pool: u32) -> Self {
+        Self {
+            descriptor_set_layout,
+            sets_per_pool,
+            pools: Vec::new(),
+        }
+    }
+
+    /// Sets the capacity of the pools created from now on.
+    pub fn set_sets_per_pool(&mut self, sets_per_pool: u32) {
+        self.sets_per_pool = sets_per_pool;
+    }
+
+    pub fn allocate(&mut self, name: Option<&str>) -> DescriptorSet {
+        self.try_allocate(name).unwrap()
+    }
+
+    /// Fails with `Error::Vulkan` when a new pool can't be created.
+    pub fn try_allocate(&mut self, name: Option<&str>) -> Result<DescriptorSet, Error> {
+        // Every live set holds on to its pool, so the strong count tells how full a pool is.
+        let pool = match self
+            .pools
+            .iter()
+            .find(|(pool, capacity)| (Arc::strong_count(pool) as u32) <= *capacity)
+        {
+            Some((pool, _)) => pool.clone(),
+            None => {
+                let pool_sizes = self
+                    .descriptor_set_layout
+                    .vk_bindings
+                    .iter()
+                    .map(|binding| {
+                        vk::DescriptorPoolSize::builder()
+                            .ty(binding.descriptor_type)
+                            .descriptor_count(binding.descriptor_count * self.sets_per_pool)
+                            .build()
+                    })
+                    .collect::<Vec<_>>();
+                let pool = Arc::new(DescriptorPool::try_new(
+                    self.descriptor_set_layout.device.clone(),
+                    &pool_sizes,
+                    self.sets_per_pool,
+                )?);
+                self.pools.push((pool.clone(), self.sets_per_pool));
+                pool
+            }
+        };
+        DescriptorSet::try_new(name, pool, self.descriptor_set_layout.clone())
+    }
+}
+
 pub struct Buffer {
     allocator: Arc<Allocator>,
     handle: vk::Buffer,
@@ -618,53 +1817,82 @@ impl Buffer {
     where
         I: num_traits::PrimInt,
     {
-        let (handle, allocation, allocation_info) = allocator
-            .handle
-            .create_buffer(
-                &vk::BufferCreateInfo::builder()
-                    .usage(
-                        buffer_usage
-                            | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
-                            | vk::BufferUsageFlags::TRANSFER_DST,
-                    )
-                    .size(size.to_u64().unwrap())
-                    .build(),
-                &vk_mem::AllocationCreateInfo {
-                    usage: memory_usage,
-                    ..Default::default()
-                },
-            )
-            .unwrap();
+        Self::try_new(name, allocator, size, buffer_usage, memory_usage).unwrap()
+    }
+
+    /// Fails with `Error::Allocation` when the memory runs out.
+    pub fn try_new<I>(
+        name: Option<&str>,
+        allocator: Arc<Allocator>,
+        size: I,
+        buffer_usage: vk::BufferUsageFlags,
+        memory_usage: vk_mem::MemoryUsage,
+    ) -> Result<Self, Error>
+    where
+        I: num_traits::PrimInt,
+    {
+        let (handle, allocation, allocation_info) = allocator.handle.create_buffer(
+            &vk::BufferCreateInfo::builder()
+                .usage(
+                    buffer_usage
+                        | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
+                        | vk::BufferUsageFlags::TRANSFER_DST,
+                )
+                .size(size.to_u64().unwrap())
+                .build(),
+            &vk_mem::AllocationCreateInfo {
+                usage: memory_usage,
+                ..Default::default()
+            },
+        )?;
 
+        // Until `Self` owns the buffer, it's destroyed by hand if naming it or querying its
+        // memory fails.
         let device = &allocator.device;
-        unsafe {
+        let property_flags = || -> Result<vk::MemoryPropertyFlags, Error> {
             if let Some(name) = name {
-                device
-                    .pdevice
-                    .instance
-                    .debug_utils_loader
-                    .debug_utils_set_object_name(
-                        device.handle.handle(),
-                        &vk::DebugUtilsObjectNameInfoEXT::builder()
-                            .object_handle(handle.as_raw())
-                            .object_type(vk::ObjectType::BUFFER)
-                            .object_name(CString::new(name).unwrap().as_ref())
-                            .build(),
-                    )
-                    .unwrap();
+                unsafe {
+                    device
+                        .pdevice
+                        .instance
+                        .debug_utils_loader
+                        .debug_utils_set_object_name(
+                            device.handle.handle(),
+                            &vk::DebugUtilsObjectNameInfoEXT::builder()
+                                .object_handle(handle.as_raw())
+                                .object_type(vk::ObjectType::BUFFER)
+                                .object_name(CString::new(name).unwrap().as_ref())
+                                .build(),
+                        )?;
+                }
             }
+            Ok(allocator
+                .handle
+                .get_memory_type_properties(allocation_info.get_memory_type())?)
+        };
+        let property_flags = match property_flags() {
+            Ok(property_flags) => property_flags,
+            Err(error) => {
+                allocator.handle.destroy_buffer(handle, &allocation);
+                return Err(error);
+            }
+        };
+
+        unsafe {
             let device_address = allocator.device.handle.get_buffer_device_address(
                 &vk::BufferDeviceAddressInfo::builder()
                     .buffer(handle)
                     .build(),
             );
 
-            let property_flags = allocator
-                .handle
-                .get_memory_type_properties(allocation_info.get_memory_type())
-                .unwrap();
+            allocator.track(
+                vk::ObjectType::BUFFER,
+                handle.as_raw(),
+                name,
+                allocation_info.get_size() as u64,
+            );
 
-            Self {
+            Ok(Self {
                 handle,
                 allocation,
                 mapped: std::sync::atomic::AtomicBool::new(false),
@@ -673,10 +1901,35 @@ impl Buffer {
                 allocator,
                 allocation_info,
                 property_flags,
-            }
+            })
         }
     }
 
+    /// A buffer of `count` values of `T`, read and written with `read` and `write`.
+    pub fn new_typed<T: bytemuck::Pod>(
+        name: Option<&str>,
+        allocator: Arc<Allocator>,
+        count: usize,
+        buffer_usage: vk::BufferUsageFlags,
+        memory_usage: vk_mem::MemoryUsage,
+    ) -> Self {
+        Self::try_new_typed::<T>(name, allocator, count, buffer_usage, memory_usage).unwrap()
+    }
+
+    /// Fails with `Error::Allocation` when the memory runs out.
+    pub fn try_new_typed<T: bytemuck::Pod>(
+        name: Option<&str>,
+        allocator: Arc<Allocator>,
+        count: usize,
+        buffer_usage: vk::BufferUsageFlags,
+        memory_usage: vk_mem::MemoryUsage,
+    ) -> Result<Self, Error> {
+        let size = count
+            .checked_mul(std::mem::size_of::<T>())
+            .ok_or_else(|| Error::Allocation("buffer size overflows".to_owned()))?;
+        Self::try_new(name, allocator, size, buffer_usage, memory_usage)
+    }
+
     pub fn new_init_host<I: AsRef<[u8]>>(
         name: Option<&str>,
         allocator: Arc<Allocator>,
@@ -798,6 +2051,50 @@ impl Buffer {
         self.unmap();
     }
 
+    pub fn copy_from_offset<I: AsRef<[u8]>>(&self, offset: usize, data: I) {
+        let data = data.as_ref();
+        assert!(offset + data.len() <= self.size, "write out of buffer bounds");
+        let mapped = self.map();
+        let mapped_bytes =
+            unsafe { std::slice::from_raw_parts_mut(mapped.add(offset), data.len()) };
+        mapped_bytes.copy_from_slice(data);
+        self.unmap();
+    }
+
+    /// Writes `data` `offset` bytes into the buffer. Panics unless `offset` is a multiple of the
+    /// alignment of `T` and the values fit in the buffer.
+    pub fn write<T: bytemuck::Pod>(&self, offset: usize, data: &[T]) {
+        assert_eq!(
+            offset % std::mem::align_of::<T>(),
+            0,
+            "write misaligned for its type"
+        );
+        self.copy_from_offset(offset, cast_slice(data));
+    }
+
+    /// Reads `count` values of `T` from `offset` bytes into the buffer, once written by the
+    /// device. Panics unless `offset` is a multiple of the alignment of `T` and the values fit in
+    /// the buffer.
+    pub fn read<T: bytemuck::Pod>(&self, offset: usize, count: usize) -> Vec<T> {
+        assert_eq!(
+            offset % std::mem::align_of::<T>(),
+            0,
+            "read misaligned for its type"
+        );
+        let mut values = vec![T::zeroed(); count];
+        let bytes = bytemuck::cast_slice_mut::<T, u8>(&mut values);
+        assert!(
+            offset + bytes.len() <= self.size,
+            "read out of buffer bounds"
+        );
+        let mapped = self.map();
+        unsafe {
+            std::ptr::copy_nonoverlapping(mapped.add(offset), bytes.as_mut_ptr(), bytes.len());
+        }
+        self.unmap();
+        values
+    }
+
     pub fn size(&self) -> usize {
         self.size
     }
@@ -824,6 +2121,8 @@ impl Drop for Buffer {
         if self.mapped.load(std::sync::atomic::Ordering::SeqCst) {
             self.unmap();
         }
+        self.allocator
+            .untrack(vk::ObjectType::BUFFER, self.handle.as_raw());
         self.allocator
             .handle
             .destroy_buffer(self.handle, &self.allocation);
@@ -833,24 +2132,44 @@ impl Drop for Buffer {
 pub struct Queue {
     handle: vk::Queue,
     device: Arc<Device>,
+    family_index: u32,
     command_buffers:
         HashMap<vk::CommandBuffer, (Arc<std::sync::atomic::AtomicBool>, CommandBuffer)>,
 }
 
 impl Queue {
+    /// The graphics queue, see `Device::compute_queue` and `Device::transfer_queue` for the
+    /// others.
     pub fn new(device: Arc<Device>) -> Self {
+        let family_index = device.pdevice.queue_family_index;
+        Self::with_family_index(device, family_index)
+    }
+
+    fn with_family_index(device: Arc<Device>, family_index: u32) -> Self {
         unsafe {
-            let handle = device
-                .handle
-                .get_device_queue(device.pdevice.queue_family_index, 0);
+            let handle = device.handle.get_device_queue(family_index, 0);
             Self {
                 handle,
                 device,
+                family_index,
                 command_buffers: HashMap::new(),
             }
         }
     }
 
+    pub fn family_index(&self) -> u32 {
+        self.family_index
+    }
+
+    /// Command buffers can only be submitted to queues of the family of their pool.
+    fn assert_family(&self, command_buffer: &CommandBuffer) {
+        assert_eq!(
+            command_buffer.pool.family_index, self.family_index,
+            "command buffer of queue family {} submitted to a queue of family {}",
+            command_buffer.pool.family_index, self.family_index
+        );
+    }
+
     pub fn clean_command_buffers(&mut self) {
         let mut removal_list = Vec::with_capacity(self.command_buffers.len());
         for (handle, (in_use, _)) in self.command_buffers.iter() {
@@ -870,6 +2189,24 @@ impl Queue {
         wait_stages: &[vk::PipelineStageFlags],
         signal_semaphore: &[&BinarySemaphore],
     ) -> Arc<Fence> {
+        self.try_submit_binary(
+            command_buffer,
+            wait_semaphore,
+            wait_stages,
+            signal_semaphore,
+        )
+        .unwrap()
+    }
+
+    /// Fails with `Error::Vulkan`, e.g. with `ERROR_DEVICE_LOST`.
+    pub fn try_submit_binary(
+        &mut self,
+        command_buffer: CommandBuffer,
+        wait_semaphore: &[&BinarySemaphore],
+        wait_stages: &[vk::PipelineStageFlags],
+        signal_semaphore: &[&BinarySemaphore],
+    ) -> Result<Arc<Fence>, Error> {
+        self.assert_family(&command_buffer);
         self.clean_command_buffers();
 
         let wait_handles = wait_semaphore.iter().map(|s| s.handle).collect::<Vec<_>>();
@@ -885,7 +2222,7 @@ impl Queue {
             .signal_semaphores(signal_handles.as_slice())
             .build();
 
-        let fence = Arc::new(Fence::new(self.device.clone(), false));
+        let fence = Arc::new(Fence::try_new(self.device.clone(), false)?);
 
         let in_use = Arc::new(std::sync::atomic::AtomicBool::new(true));
         let in_use_signaler = in_use.clone();
@@ -893,9 +2230,9 @@ impl Queue {
         unsafe {
             self.device
                 .handle
-                .queue_submit(self.handle, &[submit_info], fence.handle)
-                .unwrap();
+                .queue_submit(self.handle, &[submit_info], fence.handle)?;
         }
+        command_buffer.commit_image_layouts();
         let fence_cloned = fence.clone();
         let _task = tokio::task::spawn(async move {
             fence_cloned.wait();
@@ -905,7 +2242,7 @@ impl Queue {
         self.command_buffers
             .insert(command_buffer.handle, (in_use, command_buffer));
 
-        fence
+        Ok(fence)
     }
 
     pub fn submit_timeline(
@@ -916,6 +2253,26 @@ impl Queue {
         wait_stages: &[vk::PipelineStageFlags],
         signal_values: &[u64],
     ) {
+        self.try_submit_timeline(
+            command_buffer,
+            timeline_semaphores,
+            wait_values,
+            wait_stages,
+            signal_values,
+        )
+        .unwrap()
+    }
+
+    /// Fails with `Error::Vulkan`, e.g. with `ERROR_DEVICE_LOST`.
+    pub fn try_submit_timeline(
+        &mut self,
+        command_buffer: CommandBuffer,
+        timeline_semaphores: &[&TimelineSemaphore],
+        wait_values: &[u64],
+        wait_stages: &[vk::PipelineStageFlags],
+        signal_values: &[u64],
+    ) -> Result<(), Error> {
+        self.assert_family(&command_buffer);
         self.clean_command_buffers();
         unsafe {
             let semaphore_handles = timeline_semaphores
@@ -923,26 +2280,105 @@ impl Queue {
                 .map(|s| s.handle)
                 .collect::<Vec<vk::Semaphore>>();
 
-            let fence = Fence::new(self.device.clone(), false);
-            self.device
-                .handle
-                .queue_submit(
-                    self.handle,
-                    &[vk::SubmitInfo::builder()
-                        .command_buffers(&[command_buffer.handle])
-                        .wait_semaphores(&semaphore_handles)
-                        .wait_dst_stage_mask(wait_stages)
-                        .signal_semaphores(&semaphore_handles)
-                        .push_next(
-                            &mut vk::TimelineSemaphoreSubmitInfo::builder()
-                                .wait_semaphore_values(wait_values)
-                                .signal_semaphore_values(signal_values)
-                                .build(),
-                        )
-                        .build()],
-                    fence.handle,
-                )
-                .unwrap();
+            let fence = Fence::try_new(self.device.clone(), false)?;
+            self.device.handle.queue_submit(
+                self.handle,
+                &[vk::SubmitInfo::builder()
+                    .command_buffers(&[command_buffer.handle])
+                    .wait_semaphores(&semaphore_handles)
+                    .wait_dst_stage_mask(wait_stages)
+                    .signal_semaphores(&semaphore_handles)
+                    .push_next(
+                        &mut vk::TimelineSemaphoreSubmitInfo::builder()
+                            .wait_semaphore_values(wait_values)
+                            .signal_semaphore_values(signal_values)
+                            .build(),
+                    )
+                    .build()],
+                fence.handle,
+            )?;
+            command_buffer.commit_image_layouts();
+
+            let in_use = Arc::new(std::sync::atomic::AtomicBool::new(true));
+            let in_use_signaler = in_use.clone();
+
+            self.command_buffers
+                .insert(command_buffer.handle, (in_use, command_buffer));
+
+            tokio::task::spawn(async move {
+                fence.wait();
+                in_use_signaler.store(false, std::sync::atomic::Ordering::SeqCst);
+            });
+        }
+        Ok(())
+    }
+
+    /// Like `submit_timeline`, on the physical devices of `device_mask` only, with each
+    /// semaphore operation on one device. A device waits for another by waiting, with its own
+    /// index, for a value the other signals.
+    pub fn submit_device_group(
+        &mut self,
+        command_buffer: CommandBuffer,
+        device_mask: u32,
+        waits: &[DeviceSemaphoreValue],
+        wait_stages: &[vk::PipelineStageFlags],
+        signals: &[DeviceSemaphoreValue],
+    ) {
+        self.try_submit_device_group(command_buffer, device_mask, waits, wait_stages, signals)
+            .unwrap()
+    }
+
+    /// Fails with `Error::Vulkan`, e.g. with `ERROR_DEVICE_LOST`.
+    pub fn try_submit_device_group(
+        &mut self,
+        command_buffer: CommandBuffer,
+        device_mask: u32,
+        waits: &[DeviceSemaphoreValue],
+        wait_stages: &[vk::PipelineStageFlags],
+        signals: &[DeviceSemaphoreValue],
+    ) -> Result<(), Error> {
+        assert!(device_mask != 0 && device_mask & !self.device.all_devices_mask() == 0);
+        self.assert_family(&command_buffer);
+        self.clean_command_buffers();
+        // The handles, values and device indices.
+        let split = |values: &[DeviceSemaphoreValue]| {
+            let mut split = (Vec::new(), Vec::new(), Vec::new());
+            for value in values {
+                split.0.push(value.semaphore.handle);
+                split.1.push(value.value);
+                split.2.push(value.device_index);
+            }
+            split
+        };
+        let (wait_handles, wait_values, wait_indices) = split(waits);
+        let (signal_handles, signal_values, signal_indices) = split(signals);
+        let device_masks = [device_mask];
+        unsafe {
+            let fence = Fence::try_new(self.device.clone(), false)?;
+            self.device.handle.queue_submit(
+                self.handle,
+                &[vk::SubmitInfo::builder()
+                    .command_buffers(&[command_buffer.handle])
+                    .wait_semaphores(&wait_handles)
+                    .wait_dst_stage_mask(wait_stages)
+                    .signal_semaphores(&signal_handles)
+                    .push_next(
+                        &mut vk::TimelineSemaphoreSubmitInfo::builder()
+                            .wait_semaphore_values(&wait_values)
+                            .signal_semaphore_values(&signal_values)
+                            .build(),
+                    )
+                    .push_next(
+                        &mut vk::DeviceGroupSubmitInfo::builder()
+                            .wait_semaphore_device_indices(&wait_indices)
+                            .command_buffer_device_masks(&device_masks)
+                            .signal_semaphore_device_indices(&signal_indices)
+                            .build(),
+                    )
+                    .build()],
+                fence.handle,
+            )?;
+            command_buffer.commit_image_layouts();
 
             let in_use = Arc::new(std::sync::atomic::AtomicBool::new(true));
             let in_use_signaler = in_use.clone();
@@ -955,9 +2391,23 @@ impl Queue {
                 in_use_signaler.store(false, std::sync::atomic::Ordering::SeqCst);
             });
         }
+        Ok(())
     }
 
     pub fn present(&self, swapchain: &Swapchain, index: u32, wait_semaphore: &[&BinarySemaphore]) {
+        if let Err(e) = self.try_present(swapchain, index, wait_semaphore) {
+            log::warn!("{:?}", e);
+        }
+    }
+
+    /// Whether the swapchain is suboptimal for the surface. Fails with `Error::Vulkan`, e.g.
+    /// with `ERROR_OUT_OF_DATE_KHR` once the window was resized.
+    pub fn try_present(
+        &self,
+        swapchain: &Swapchain,
+        index: u32,
+        wait_semaphore: &[&BinarySemaphore],
+    ) -> Result<bool, Error> {
         let wait_handles = wait_semaphore.iter().map(|s| s.handle).collect::<Vec<_>>();
 
         let info = vk::PresentInfoKHR::builder()
@@ -965,18 +2415,23 @@ impl Queue {
             .wait_semaphores(wait_handles.as_slice())
             .image_indices(&[index])
             .build();
-        unsafe {
-            if let Err(e) = self
-                .device
+        let suboptimal = unsafe {
+            self.device
                 .swapchain_loader
-                .queue_present(self.handle, &info)
-            {
-                log::warn!("{:?}", e);
-            }
-        }
+                .queue_present(self.handle, &info)?
+        };
+        Ok(suboptimal)
     }
 }
 
+/// A timeline semaphore value one physical device of a device group waits for or signals, see
+/// `Queue::submit_device_group`.
+pub struct DeviceSemaphoreValue<'a> {
+    pub semaphore: &'a TimelineSemaphore,
+    pub value: u64,
+    pub device_index: u32,
+}
+
 pub struct Fence {
     handle: vk::Fence,
     device: Arc<Device>,
@@ -984,6 +2439,11 @@ pub struct Fence {
 
 impl Fence {
     pub fn new(device: Arc<Device>, signaled: bool) -> Self {
+        Self::try_new(device, signaled).unwrap()
+    }
+
+    /// Fails with `Error::Vulkan` when the memory runs out.
+    pub fn try_new(device: Arc<Device>, signaled: bool) -> Result<Self, Error> {
         let handle = unsafe {
             device.handle.create_fence(
                 &vk::FenceCreateInfo::builder()
@@ -994,18 +2454,22 @@ impl Fence {
                     .build(),
                 None,
             )
-        }
-        .unwrap();
-        Self { handle, device }
+        }?;
+        Ok(Self { handle, device })
     }
 
     pub fn wait(&self) {
+        self.try_wait().unwrap()
+    }
+
+    /// Fails with `Error::Vulkan`, e.g. with `ERROR_DEVICE_LOST`.
+    pub fn try_wait(&self) -> Result<(), Error> {
         unsafe {
             self.device
                 .handle
-                .wait_for_fences(&[self.handle], true, std::u64::MAX)
-                .unwrap();
+                .wait_for_fences(&[self.handle], true, std::u64::MAX)?;
         }
+        Ok(())
     }
 
     pub fn reset(&self) {
@@ -1013,6 +2477,11 @@ impl Fence {
             self.device.handle.reset_fences(&[self.handle]).unwrap();
         }
     }
+
+    /// Whether the fence is signaled, without blocking.
+    pub fn is_signaled(&self) -> bool {
+        unsafe { self.device.handle.get_fence_status(self.handle) }.unwrap()
+    }
 }
 
 impl Drop for Fence {
@@ -1021,6 +2490,285 @@ impl Drop for Fence {
     }
 }
 
+/// Statistics of `QueryPool::pipeline_statistics` queries, one value per flag of the pool.
+const MAX_QUERY_VALUES: usize = 11;
+
+/// Timestamps written by `CommandRecorder::write_timestamp`, or occlusion or pipeline
+/// statistics queries of `CommandRecorder::begin_query`. Queries have to be reset with
+/// `CommandRecorder::reset_query_pool` before each write, and before their results are read.
+pub struct QueryPool {
+    handle: vk::QueryPool,
+    device: Arc<Device>,
+    query_type: vk::QueryType,
+    /// Empty but for pipeline statistics queries.
+    statistics: vk::QueryPipelineStatisticFlags,
+    query_count: u32,
+}
+
+impl QueryPool {
+    pub fn timestamps(device: Arc<Device>, query_count: u32) -> Self {
+        Self::with_type(
+            device,
+            vk::QueryType::TIMESTAMP,
+            vk::QueryPipelineStatisticFlags::empty(),
+            query_count,
+        )
+    }
+
+    /// Queries counting the samples that pass the depth and stencil tests, e.g. to tell whether
+    /// an object is visible at all. Counts are exact only with `vk::QueryControlFlags::PRECISE`
+    /// and the `occlusion_query_precise` feature, non-zero if any sample passed otherwise.
+    pub fn occlusion(device: Arc<Device>, query_count: u32) -> Self {
+        Self::with_type(
+            device,
+            vk::QueryType::OCCLUSION,
+            vk::QueryPipelineStatisticFlags::empty(),
+            query_count,
+        )
+    }
+
+    /// Queries counting the invocations and primitives of `statistics`, which needs the
+    /// `pipeline_statistics_query` feature.
+    pub fn pipeline_statistics(
+        device: Arc<Device>,
+        query_count: u32,
+        statistics: vk::QueryPipelineStatisticFlags,
+    ) -> Self {
+        assert!(!statistics.is_empty(), "no statistics to query");
+        assert_eq!(
+            device.features.pipeline_statistics_query,
+            vk::TRUE,
+            "pipeline_statistics_query isn't enabled"
+        );
+        Self::with_type(
+            device,
+            vk::QueryType::PIPELINE_STATISTICS,
+            statistics,
+            query_count,
+        )
+    }
+
+    fn with_type(
+        device: Arc<Device>,
+        query_type: vk::QueryType,
+        statistics: vk::QueryPipelineStatisticFlags,
+        query_count: u32,
+    ) -> Self {
+        let handle = unsafe {
+            device.handle.create_query_pool(
+                &vk::QueryPoolCreateInfo::builder()
+                    .query_type(query_type)
+                    .pipeline_statistics(statistics)
+                    .query_count(query_count)
+                    .build(),
+                None,
+            )
+        }
+        .unwrap();
+        Self {
+            handle,
+            device,
+            query_type,
+            statistics,
+            query_count,
+        }
+    }
+
+    pub fn query_count(&self) -> u32 {
+        self.query_count
+    }
+
+    pub fn query_type(&self) -> vk::QueryType {
+        self.query_type
+    }
+
+    /// Nanoseconds per tick of the timestamps.
+    pub fn timestamp_period(&self) -> f32 {
+        self.device.pdevice.properties().limits.timestamp_period
+    }
+
+    /// The timestamps or sample counts of `query_count` queries from `first_query`, `None` for
+    /// those whose commands haven't finished. Doesn't block.
+    pub fn results(&self, first_query: u32, query_count: u32) -> Vec<Option<u64>> {
+        assert_ne!(
+            self.query_type,
+            vk::QueryType::PIPELINE_STATISTICS,
+            "use pipeline_statistics_results"
+        );
+        self.raw_results(first_query, query_count)
+            .into_iter()
+            .map(|values| values.map(|values| values[0]))
+            .collect()
+    }
+
+    /// The statistics of `query_count` queries from `first_query`, `None` for those whose
+    /// commands haven't finished. Doesn't block.
+    pub fn pipeline_statistics_results(
+        &self,
+        first_query: u32,
+        query_count: u32,
+    ) -> Vec<Option<PipelineStatistics>> {
+        assert_eq!(self.query_type, vk::QueryType::PIPELINE_STATISTICS);
+        self.raw_results(first_query, query_count)
+            .into_iter()
+            .map(|values| {
+                values.map(|values| PipelineStatistics {
+                    flags: self.statistics,
+                    values,
+                })
+            })
+            .collect()
+    }
+
+    /// The values of each query, one per statistic of pipeline statistics queries.
+    fn raw_results(&self, first_query: u32, query_count: u32) -> Vec<Option<Vec<u64>>> {
+        assert!(first_query + query_count <= self.query_count);
+        let value_count = if self.query_type == vk::QueryType::PIPELINE_STATISTICS {
+            self.statistics.as_raw().count_ones() as usize
+        } else {
+            1
+        };
+        // The values of each query are followed by whether they're available.
+        let mut data = vec![[0u64; MAX_QUERY_VALUES + 1]; query_count as usize];
+        let result = unsafe {
+            self.device.handle.get_query_pool_results(
+                self.handle,
+                first_query,
+                query_count,
+                &mut data,
+                vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WITH_AVAILABILITY,
+            )
+        };
+        match result {
+            Ok(()) | Err(vk::Result::NOT_READY) => {}
+            Err(e) => panic!("failed to get query results: {}", e),
+        }
+        data.iter()
+            .map(|values| Some(values[..value_count].to_vec()).filter(|_| values[value_count] != 0))
+            .collect()
+    }
+}
+
+/// The results of a pipeline statistics query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PipelineStatistics {
+    flags: vk::QueryPipelineStatisticFlags,
+    /// One per flag, in the order of their bits.
+    values: Vec<u64>,
+}
+
+impl PipelineStatistics {
+    /// The count of `statistic`, a single flag, `None` if the pool didn't query it.
+    pub fn get(&self, statistic: vk::QueryPipelineStatisticFlags) -> Option<u64> {
+        let bit = statistic.as_raw();
+        assert!(bit.is_power_of_two(), "query one statistic at a time");
+        if !self.flags.contains(statistic) {
+            return None;
+        }
+        let index = (self.flags.as_raw() & (bit - 1)).count_ones() as usize;
+        Some(self.values[index])
+    }
+}
+
+impl Drop for QueryPool {
+    fn drop(&mut self) {
+        unsafe { self.device.handle.destroy_query_pool(self.handle, None) };
+    }
+}
+
+/// GPU times of the scopes a frame records with `CommandRecorder::gpu_scope`, in milliseconds.
+///
+/// Each scope takes two timestamps of one query pool, reset as the scope is recorded. The
+/// scopes of a frame are timed by `frame_finished` once it has finished, so at most one frame
+/// may be recorded between two calls.
+pub struct GpuProfiler {
+    /// `None` on devices that can't write timestamps on the graphics and compute queues.
+    query_pool: Option<Arc<QueryPool>>,
+    /// Scopes of the frame being recorded and their first query.
+    recorded: Vec<(String, u32)>,
+    /// Scopes of the frame last submitted, timed once it has finished.
+    submitted: Vec<(String, u32)>,
+    timings: Vec<(String, f64)>,
+}
+
+impl GpuProfiler {
+    /// Times up to `max_scopes` scopes a frame, those recorded after them aren't timed.
+    pub fn new(device: Arc<Device>, max_scopes: u32) -> Self {
+        let timestamps = device
+            .pdevice
+            .properties()
+            .limits
+            .timestamp_compute_and_graphics
+            == vk::TRUE;
+        let query_pool = if timestamps {
+            Some(Arc::new(QueryPool::timestamps(device, max_scopes * 2)))
+        } else {
+            None
+        };
+        Self {
+            query_pool,
+            recorded: Vec::new(),
+            submitted: Vec::new(),
+            timings: Vec::new(),
+        }
+    }
+
+    pub fn is_supported(&self) -> bool {
+        self.query_pool.is_some()
+    }
+
+    /// The scopes of the last frame timed in the order they were recorded, with their GPU time
+    /// in milliseconds.
+    pub fn timings(&self) -> &[(String, f64)] {
+        &self.timings
+    }
+
+    /// Times the scopes of the frame last submitted. Call once that frame has finished, before
+    /// the one recorded is submitted.
+    pub fn frame_finished(&mut self) {
+        let submitted = std::mem::replace(&mut self.submitted, std::mem::take(&mut self.recorded));
+        let query_pool = match &self.query_pool {
+            Some(query_pool) if !submitted.is_empty() => query_pool,
+            _ => return,
+        };
+        let results = query_pool.results(0, submitted.len() as u32 * 2);
+        let period = query_pool.timestamp_period() as f64;
+        self.timings = submitted
+            .into_iter()
+            .filter_map(
+                |(name, query)| match results[query as usize..query as usize + 2] {
+                    [Some(start), Some(end)] => {
+                        Some((name, end.wrapping_sub(start) as f64 * period / 1e6))
+                    }
+                    _ => None,
+                },
+            )
+            .collect();
+    }
+
+    fn begin_scope(&mut self, recorder: &mut CommandRecorder, name: &str) -> Option<u32> {
+        let query_pool = self.query_pool.clone()?;
+        let query = self.recorded.len() as u32 * 2;
+        if query + 2 > query_pool.query_count() {
+            return None;
+        }
+        self.recorded.push((name.to_owned(), query));
+        recorder.reset_query_pool(query_pool.clone(), query, 2);
+        recorder.write_timestamp(query_pool, vk::PipelineStageFlags::TOP_OF_PIPE, query);
+        Some(query)
+    }
+
+    fn end_scope(&mut self, recorder: &mut CommandRecorder, query: Option<u32>) {
+        if let (Some(query_pool), Some(query)) = (&self.query_pool, query) {
+            recorder.write_timestamp(
+                query_pool.clone(),
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                query + 1,
+            );
+        }
+    }
+}
+
 pub struct TimelineSemaphore {
     handle: vk::Semaphore,
     device: Arc<Device>,
@@ -1048,18 +2796,21 @@ impl TimelineSemaphore {
     }
 
     pub fn wait_for(&self, value: u64) {
+        self.try_wait_for(value).unwrap()
+    }
+
+    /// Fails with `Error::Vulkan`, e.g. with `ERROR_DEVICE_LOST`.
+    pub fn try_wait_for(&self, value: u64) -> Result<(), Error> {
         unsafe {
-            self.device
-                .handle
-                .wait_semaphores(
-                    &vk::SemaphoreWaitInfo::builder()
-                        .semaphores(&[self.handle])
-                        .values(&[value])
-                        .build(),
-                    std::u64::MAX,
-                )
-                .unwrap();
+            self.device.handle.wait_semaphores(
+                &vk::SemaphoreWaitInfo::builder()
+                    .semaphores(&[self.handle])
+                    .values(&[value])
+                    .build(),
+                std::u64::MAX,
+            )?;
         }
+        Ok(())
     }
 
     pub fn signal(&self, value: u64) {
@@ -1075,6 +2826,16 @@ impl TimelineSemaphore {
                 .unwrap();
         }
     }
+
+    /// The value last signaled.
+    pub fn value(&self) -> u64 {
+        unsafe {
+            self.device
+                .handle
+                .get_semaphore_counter_value(self.handle)
+                .unwrap()
+        }
+    }
 }
 
 impl Drop for TimelineSemaphore {
@@ -1085,6 +2846,297 @@ impl Drop for TimelineSemaphore {
     }
 }
 
+/// Signaled once the uploads of a `StagingBelt::finish` have run.
+#[derive(Clone)]
+pub struct UploadToken {
+    semaphore: Arc<TimelineSemaphore>,
+    value: u64,
+    /// Queue family of the belt, if the destinations have to be acquired from it.
+    src_family_index: Option<u32>,
+    buffers: Vec<Arc<Buffer>>,
+    /// With the layout they're uploaded to.
+    images: Vec<(Arc<Image>, vk::ImageLayout)>,
+}
+
+impl UploadToken {
+    pub fn is_complete(&self) -> bool {
+        self.semaphore.value() >= self.value
+    }
+
+    pub fn wait(&self) {
+        self.semaphore.wait_for(self.value);
+    }
+
+    /// Acquires the destinations of the uploads for the queue family of `recorder`, when the
+    /// belt uploaded them on a queue of another family. Record before their first use there,
+    /// once the uploads are complete.
+    pub fn acquire(
+        &self,
+        recorder: &mut CommandRecorder,
+        dst_stage: vk::PipelineStageFlags,
+        dst_access: vk::AccessFlags,
+    ) {
+        let src_family_index = match self.src_family_index {
+            Some(src_family_index) => src_family_index,
+            None => return,
+        };
+        for buffer in &self.buffers {
+            recorder.acquire_buffer(buffer.clone(), src_family_index, dst_stage, dst_access);
+        }
+        for (image, layout) in &self.images {
+            recorder.acquire_image(image.clone(), src_family_index, *layout);
+        }
+    }
+}
+
+enum StagedCopy {
+    Buffer {
+        src: Arc<Buffer>,
+        src_offset: u64,
+        dst: Arc<Buffer>,
+        dst_offset: u64,
+        size: u64,
+    },
+    Image {
+        src: Arc<Buffer>,
+        src_offset: u64,
+        dst: Arc<Image>,
+        layout: vk::ImageLayout,
+    },
+}
+
+/// Uploads to device local buffers and images through host visible chunks of staging memory,
+/// recording every upload up to a `finish` in one command buffer, submitted without waiting for
+/// it. Chunks are reused once the uploads reading from them have run.
+///
+/// Destinations uploaded on a queue of another family than the graphics one, e.g. the one of
+/// `Device::transfer_queue`, are released to the graphics family, see `UploadToken::acquire`.
+pub struct StagingBelt {
+    allocator: Arc<Allocator>,
+    queue: Queue,
+    command_pool: Arc<CommandPool>,
+    semaphore: Arc<TimelineSemaphore>,
+    /// Value the last batch submitted signals.
+    submitted_value: u64,
+    chunk_size: usize,
+    /// The chunk being written and how much of it is used.
+    active_chunk: Option<(Arc<Buffer>, usize)>,
+    /// Chunks written for the batch being recorded, besides the active one.
+    used_chunks: Vec<Arc<Buffer>>,
+    /// Chunks of submitted batches and the value each batch signals once done with them.
+    in_flight_chunks: Vec<(u64, Arc<Buffer>)>,
+    free_chunks: Vec<Arc<Buffer>>,
+    copies: Vec<StagedCopy>,
+}
+
+impl StagingBelt {
+    /// Uploads bigger than `chunk_size` bytes get a chunk of their own, freed once done.
+    pub fn new(allocator: Arc<Allocator>, queue: Queue, chunk_size: usize) -> Self {
+        let command_pool = Arc::new(CommandPool::for_queue(&queue));
+        let semaphore = Arc::new(TimelineSemaphore::new(allocator.device().clone()));
+        Self {
+            allocator,
+            queue,
+            command_pool,
+            semaphore,
+            submitted_value: 0,
+            chunk_size,
+            active_chunk: None,
+            used_chunks: Vec::new(),
+            in_flight_chunks: Vec::new(),
+            free_chunks: Vec::new(),
+            copies: Vec::new(),
+        }
+    }
+
+    /// Uploads `data` to `dst` at `dst_offset`.
+    pub fn upload_buffer(&mut self, dst: Arc<Buffer>, dst_offset: u64, data: &[u8]) {
+        assert!(
+            dst_offset as usize + data.len() <= dst.size(),
+            "write out of buffer bounds"
+        );
+        let (src, src_offset) = self.stage(data);
+        self.copies.push(StagedCopy::Buffer {
+            src,
+            src_offset,
+            dst,
+            dst_offset,
+            size: data.len() as u64,
+        });
+    }
+
+    /// Uploads `data`, tightly packed texels of each layer after the other, to the first mip
+    /// level of every layer of `dst` and leaves it in `layout`.
+    pub fn upload_image(&mut self, dst: Arc<Image>, data: &[u8], layout: vk::ImageLayout) {
+        let (src, src_offset) = self.stage(data);
+        self.copies.push(StagedCopy::Image {
+            src,
+            src_offset,
+            dst,
+            layout,
+        });
+    }
+
+    /// Submits the uploads recorded since the last call.
+    pub fn finish(&mut self) -> UploadToken {
+        self.recall();
+        let graphics_family_index = self.allocator.device().pdevice.queue_family_index;
+        let src_family_index = if self.queue.family_index() != graphics_family_index {
+            Some(self.queue.family_index())
+        } else {
+            None
+        };
+        let mut token = UploadToken {
+            semaphore: self.semaphore.clone(),
+            value: self.submitted_value,
+            src_family_index,
+            buffers: Vec::new(),
+            images: Vec::new(),
+        };
+        if self.copies.is_empty() {
+            return token;
+        }
+
+        let copies = std::mem::take(&mut self.copies);
+        let mut command_buffer = CommandBuffer::new(self.command_pool.clone());
+        command_buffer.encode(|recorder| {
+            for copy in copies {
+                match copy {
+                    StagedCopy::Buffer {
+                        src,
+                        src_offset,
+                        dst,
+                        dst_offset,
+                        size,
+                    } => {
+                        recorder.copy_buffer(
+                            src,
+                            dst.clone(),
+                            &[vk::BufferCopy::builder()
+                                .src_offset(src_offset)
+                                .dst_offset(dst_offset)
+                                .size(size)
+                                .build()],
+                        );
+                        if src_family_index.is_some() {
+                            recorder.release_buffer(
+                                dst.clone(),
+                                graphics_family_index,
+                                vk::PipelineStageFlags::TRANSFER,
+                                vk::AccessFlags::TRANSFER_WRITE,
+                            );
+                        }
+                        token.buffers.push(dst);
+                    }
+                    StagedCopy::Image {
+                        src,
+                        src_offset,
+                        dst,
+                        layout,
+                    } => {
+                        recorder.set_image_layout(
+                            dst.clone(),
+                            Some(vk::ImageLayout::UNDEFINED),
+                            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        );
+                        recorder.copy_buffer_to_image(
+                            src,
+                            dst.clone(),
+                            &[vk::BufferImageCopy::builder()
+                                .buffer_offset(src_offset)
+                                .image_extent(vk::Extent3D {
+                                    width: dst.width(),
+                                    height: dst.height(),
+                                    depth: 1,
+                                })
+                                .image_subresource(
+                                    vk::ImageSubresourceLayers::builder()
+                                        .aspect_mask(aspect_mask(dst.format))
+                                        .layer_count(dst.array_layers)
+                                        .build(),
+                                )
+                                .build()],
+                        );
+                        if src_family_index.is_some() {
+                            recorder.release_image(dst.clone(), graphics_family_index, layout);
+                        } else {
+                            recorder.set_image_layout(dst.clone(), None, layout);
+                        }
+                        token.images.push((dst, layout));
+                    }
+                }
+            }
+        });
+
+        let value = self.submitted_value + 1;
+        self.queue.submit_timeline(
+            command_buffer,
+            &[&self.semaphore],
+            &[self.submitted_value],
+            &[vk::PipelineStageFlags::ALL_COMMANDS],
+            &[value],
+        );
+        self.submitted_value = value;
+        token.value = value;
+
+        let used_chunks = self
+            .used_chunks
+            .drain(..)
+            .chain(self.active_chunk.take().map(|(chunk, _)| chunk));
+        self.in_flight_chunks
+            .extend(used_chunks.map(|chunk| (value, chunk)));
+        token
+    }
+
+    /// Copies `data` to staging memory, returning the chunk and the offset it's at.
+    fn stage(&mut self, data: &[u8]) -> (Arc<Buffer>, u64) {
+        // Buffer to image copies need offsets aligned to the texel size.
+        const ALIGNMENT: usize = 16;
+        let fits = |(chunk, used): &(Arc<Buffer>, usize)| {
+            (used + ALIGNMENT - 1) / ALIGNMENT * ALIGNMENT + data.len() <= chunk.size()
+        };
+        if !self.active_chunk.as_ref().map_or(false, fits) {
+            if let Some((chunk, _)) = self.active_chunk.take() {
+                self.used_chunks.push(chunk);
+            }
+            self.recall();
+            let chunk = if data.len() <= self.chunk_size && !self.free_chunks.is_empty() {
+                self.free_chunks.pop().unwrap()
+            } else {
+                Arc::new(Buffer::new(
+                    Some("staging belt chunk"),
+                    self.allocator.clone(),
+                    data.len().max(self.chunk_size),
+                    vk::BufferUsageFlags::TRANSFER_SRC,
+                    vk_mem::MemoryUsage::CpuToGpu,
+                ))
+            };
+            self.active_chunk = Some((chunk, 0));
+        }
+        let (chunk, used) = self.active_chunk.as_mut().unwrap();
+        let offset = (*used + ALIGNMENT - 1) / ALIGNMENT * ALIGNMENT;
+        chunk.copy_from_offset(offset, data);
+        *used = offset + data.len();
+        (chunk.clone(), offset as u64)
+    }
+
+    /// Frees the chunks of the batches that have run, keeping those of the usual size.
+    fn recall(&mut self) {
+        let completed = self.semaphore.value();
+        let chunk_size = self.chunk_size;
+        let free_chunks = &mut self.free_chunks;
+        self.in_flight_chunks.retain(|(value, chunk)| {
+            if *value > completed {
+                return true;
+            }
+            if chunk.size() == chunk_size {
+                free_chunks.push(chunk.clone());
+            }
+            false
+        });
+    }
+}
+
 pub struct BinarySemaphore {
     handle: vk::Semaphore,
     device: Arc<Device>,
@@ -1113,25 +3165,45 @@ impl Drop for BinarySemaphore {
 pub struct CommandPool {
     handle: vk::CommandPool,
     device: Arc<Device>,
+    family_index: u32,
 }
 
 impl CommandPool {
+    /// A pool of command buffers for the graphics queue.
     pub fn new(device: Arc<Device>) -> Self {
+        let family_index = device.pdevice.queue_family_index;
+        Self::with_family_index(device, family_index)
+    }
+
+    /// A pool of command buffers for `queue`, and the other queues of its family.
+    pub fn for_queue(queue: &Queue) -> Self {
+        Self::with_family_index(queue.device.clone(), queue.family_index)
+    }
+
+    fn with_family_index(device: Arc<Device>, family_index: u32) -> Self {
         unsafe {
             let handle = device
                 .handle
                 .create_command_pool(
                     &vk::CommandPoolCreateInfo::builder()
                         .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
-                        .queue_family_index(device.pdevice.queue_family_index)
+                        .queue_family_index(family_index)
                         .build(),
                     None,
                 )
                 .unwrap();
 
-            Self { handle, device }
+            Self {
+                handle,
+                device,
+                family_index,
+            }
         }
     }
+
+    pub fn family_index(&self) -> u32 {
+        self.family_index
+    }
 }
 
 impl Drop for CommandPool {
@@ -1153,6 +3225,9 @@ pub trait GraphicsPipelineRecorder: PipelineRecorder {
 
 pub trait ComputePipelineRecorder: PipelineRecorder {
     fn dispatch(&self, group_count_x: u32, group_count_y: u32, group_count_z: u32);
+    /// Dispatches the group counts found in `buffer` at `offset` as a
+    /// `vk::DispatchIndirectCommand`, which must have been created with `INDIRECT_BUFFER` usage.
+    fn dispatch_indirect(&mut self, buffer: Arc<Buffer>, offset: u64);
 }
 
 pub trait RayTracingPipelineRecorder: PipelineRecorder {
@@ -1175,6 +3250,15 @@ pub trait PipelineRecorder {
         layout: &PipelineLayout,
         first_set: u32,
     );
+    /// Binds sets with dynamic uniform buffers, `dynamic_offsets` holding an offset for each of
+    /// them, in set and binding order.
+    fn bind_descriptor_sets_with_offsets(
+        &mut self,
+        descriptor_sets: Vec<Arc<DescriptorSet>>,
+        layout: &PipelineLayout,
+        first_set: u32,
+        dynamic_offsets: &[u32],
+    );
     fn push_constants(
         &mut self,
         layout: &PipelineLayout,
@@ -1192,6 +3276,16 @@ impl<'a> PipelineRecorder for CommandRecorder<'a> {
         descriptor_sets: Vec<Arc<DescriptorSet>>,
         layout: &PipelineLayout,
         first_set: u32,
+    ) {
+        self.bind_descriptor_sets_with_offsets(descriptor_sets, layout, first_set, &[]);
+    }
+
+    fn bind_descriptor_sets_with_offsets(
+        &mut self,
+        descriptor_sets: Vec<Arc<DescriptorSet>>,
+        layout: &PipelineLayout,
+        first_set: u32,
+        dynamic_offsets: &[u32],
     ) {
         unsafe {
             let descriptor_set_handles = descriptor_sets
@@ -1204,7 +3298,7 @@ impl<'a> PipelineRecorder for CommandRecorder<'a> {
                 layout.handle,
                 first_set,
                 descriptor_set_handles.as_slice(),
-                &[],
+                dynamic_offsets,
             );
         }
 
@@ -1268,6 +3362,17 @@ impl<'a> ComputePipelineRecorder for CommandRecorder<'a> {
             );
         }
     }
+
+    fn dispatch_indirect(&mut self, buffer: Arc<Buffer>, offset: u64) {
+        unsafe {
+            self.device().handle.cmd_dispatch_indirect(
+                self.command_buffer.handle,
+                buffer.handle,
+                offset,
+            );
+        }
+        self.command_buffer.resources.push(buffer);
+    }
 }
 
 impl<'a> GraphicsPipelineRecorder for CommandRecorder<'a> {
@@ -1380,18 +3485,88 @@ impl<'a> CommandRecorder<'a> {
         }
     }
 
+    /// Fills `size` bytes of `buffer` from `offset` with repeats of `data`, up to the end of the
+    /// buffer if `None`, e.g. to zero a storage buffer. `offset` and `size` are multiples of 4.
+    /// Buffer uses aren't tracked like those of images, the reads and writes of the commands
+    /// around it need a `memory_barrier` against the `TRANSFER_WRITE` at `TRANSFER`.
+    pub fn fill_buffer(&mut self, buffer: Arc<Buffer>, offset: u64, size: Option<u64>, data: u32) {
+        unsafe {
+            self.device().handle.cmd_fill_buffer(
+                self.command_buffer.handle,
+                buffer.handle,
+                offset,
+                size.unwrap_or(vk::WHOLE_SIZE),
+                data,
+            );
+        }
+        self.command_buffer.resources.push(buffer);
+    }
+
+    /// Clears every level and layer of `image` to `color`, e.g. to reset an accumulation image,
+    /// after the commands before that used it. Outside of render passes, see
+    /// `clear_attachments` for inside.
+    pub fn clear_color_image(&mut self, image: Arc<Image>, color: vk::ClearColorValue) {
+        let layout = self.transfer_layout(&image, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+        self.access_image(
+            image.clone(),
+            layout,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::TRANSFER_WRITE,
+        );
+        unsafe {
+            self.device().handle.cmd_clear_color_image(
+                self.command_buffer.handle,
+                image.handle,
+                layout,
+                &color,
+                &[image.subresource_range()],
+            );
+        }
+        self.command_buffer.resources.push(image);
+    }
+
+    /// Clears regions of the attachments of the render pass or dynamic rendering being
+    /// recorded, e.g. between subpasses. Only inside `begin_render_pass` or `begin_rendering`.
+    pub fn clear_attachments(
+        &mut self,
+        attachments: &[vk::ClearAttachment],
+        rects: &[vk::ClearRect],
+    ) {
+        unsafe {
+            self.device().handle.cmd_clear_attachments(
+                self.command_buffer.handle,
+                attachments,
+                rects,
+            );
+        }
+    }
+
     pub fn begin_render_pass<I>(
         &mut self,
         render_pass: Arc<RenderPass>,
         framebuffer: Arc<Framebuffer>,
+        clear_values: &[vk::ClearValue],
         f: I,
     ) where
         I: FnOnce(&mut CommandRecorder),
     {
+        // Attachments the render pass keeps the contents of are transitioned to the layouts it
+        // expects them in.
+        for (view, &layout) in framebuffer
+            .attachments
+            .iter()
+            .zip(&render_pass.initial_layouts)
+        {
+            if layout != vk::ImageLayout::UNDEFINED {
+                let (stage, access) = layout_usage(layout);
+                self.access_image(view.image.clone(), layout, stage, access);
+            }
+        }
         unsafe {
             let info = vk::RenderPassBeginInfo::builder()
                 .render_pass(render_pass.handle)
                 .framebuffer(framebuffer.handle)
+                .clear_values(clear_values)
                 .render_area(
                     vk::Rect2D::builder()
                         .extent(vk::Extent2D {
@@ -1412,11 +3587,97 @@ impl<'a> CommandRecorder<'a> {
             self.device()
                 .handle
                 .cmd_end_render_pass(self.command_buffer.handle);
+            // The render pass transitions its attachments to their final layouts.
+            for (view, &layout) in framebuffer
+                .attachments
+                .iter()
+                .zip(&render_pass.final_layouts)
+            {
+                let sync = ImageSync {
+                    layout,
+                    write_stage: vk::PipelineStageFlags::ALL_GRAPHICS,
+                    write_access: vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                    read_stages: vk::PipelineStageFlags::empty(),
+                    visible_stages: vk::PipelineStageFlags::empty(),
+                };
+                self.command_buffer
+                    .image_syncs
+                    .insert(view.image.handle, (view.image.clone(), sync));
+            }
             self.command_buffer.resources.push(render_pass);
             self.command_buffer.resources.push(framebuffer);
         }
     }
 
+    /// Renders to `color_attachments` and `depth_attachment` without a render pass or
+    /// framebuffer, with pipelines created by `GraphicsPipeline::with_formats` for their formats.
+    /// The attachments are transitioned to attachment layouts first, and left in them. A depth
+    /// attachment with stencil is the stencil attachment too. Needs a device created with
+    /// `KhrDynamicRendering`.
+    pub fn begin_rendering<I>(
+        &mut self,
+        extent: vk::Extent2D,
+        color_attachments: &[RenderingAttachment],
+        depth_attachment: Option<&RenderingAttachment>,
+        f: I,
+    ) where
+        I: FnOnce(&mut CommandRecorder),
+    {
+        let attach = |recorder: &mut Self, attachment: &RenderingAttachment, layout| {
+            let (stage, access) = layout_usage(layout);
+            recorder.access_image(attachment.view.image.clone(), layout, stage, access);
+            recorder
+                .command_buffer
+                .resources
+                .push(attachment.view.clone());
+            dynamic_rendering::RenderingAttachmentInfo::new(
+                attachment,
+                attachment.view.handle,
+                layout,
+            )
+        };
+        let color_infos = color_attachments
+            .iter()
+            .map(|attachment| attach(self, attachment, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL))
+            .collect::<Vec<_>>();
+        let depth_info = depth_attachment.map(|attachment| {
+            attach(
+                self,
+                attachment,
+                vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            )
+        });
+        let stencil_info = depth_attachment
+            .filter(|attachment| {
+                aspect_mask(attachment.view.image.format).contains(vk::ImageAspectFlags::STENCIL)
+            })
+            .and(depth_info);
+        let info = dynamic_rendering::RenderingInfo::new(
+            vk::Rect2D::builder().extent(extent).build(),
+            &color_infos,
+            depth_info.as_ref(),
+            stencil_info.as_ref(),
+        );
+
+        let dynamic_rendering_fn = self
+            .device()
+            .dynamic_rendering_fn
+            .as_ref()
+            .expect("device created without KhrDynamicRendering");
+        unsafe {
+            dynamic_rendering_fn.cmd_begin_rendering(self.command_buffer.handle, &info);
+        }
+        f(self);
+        unsafe {
+            self.device()
+                .dynamic_rendering_fn
+                .as_ref()
+                .unwrap()
+                .cmd_end_rendering(self.command_buffer.handle);
+        }
+    }
+
     pub fn bind_graphics_pipeline<I>(&mut self, pipeline: Arc<GraphicsPipeline>, f: I)
     where
         I: FnOnce(&mut dyn GraphicsPipelineRecorder, &dyn Pipeline),
@@ -1475,15 +3736,50 @@ impl<'a> CommandRecorder<'a> {
         dst: Arc<Image>,
         regions: &[vk::BufferImageCopy],
     ) {
+        let dst_layout = self.transfer_layout(&dst, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+        self.access_image(
+            dst.clone(),
+            dst_layout,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::TRANSFER_WRITE,
+        );
         unsafe {
             self.device().handle.cmd_copy_buffer_to_image(
                 self.command_buffer.handle,
                 src.handle,
                 dst.handle,
-                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                dst_layout,
+                regions,
+            );
+        }
+        self.command_buffer.resources.push(src);
+        self.command_buffer.resources.push(dst);
+    }
+
+    pub fn copy_image_to_buffer(
+        &mut self,
+        src: Arc<Image>,
+        dst: Arc<Buffer>,
+        regions: &[vk::BufferImageCopy],
+    ) {
+        let src_layout = self.transfer_layout(&src, vk::ImageLayout::TRANSFER_SRC_OPTIMAL);
+        self.access_image(
+            src.clone(),
+            src_layout,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::TRANSFER_READ,
+        );
+        unsafe {
+            self.device().handle.cmd_copy_image_to_buffer(
+                self.command_buffer.handle,
+                src.handle,
+                src_layout,
+                dst.handle,
                 regions,
             );
         }
+        self.command_buffer.resources.push(src);
+        self.command_buffer.resources.push(dst);
     }
 
     unsafe fn copy_buffer_to_image_raw(
@@ -1508,13 +3804,27 @@ impl<'a> CommandRecorder<'a> {
         regions: &[vk::ImageBlit],
         filter: vk::Filter,
     ) {
+        let src_layout = self.transfer_layout(&src, vk::ImageLayout::TRANSFER_SRC_OPTIMAL);
+        self.access_image(
+            src.clone(),
+            src_layout,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::TRANSFER_READ,
+        );
+        let dst_layout = self.transfer_layout(&dst, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+        self.access_image(
+            dst.clone(),
+            dst_layout,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::TRANSFER_WRITE,
+        );
         unsafe {
             self.device().handle.cmd_blit_image(
                 self.command_buffer.handle,
                 src.handle,
-                src.layout(),
+                src_layout,
                 dst.handle,
-                dst.layout(),
+                dst_layout,
                 regions,
                 filter,
             );
@@ -1523,30 +3833,478 @@ impl<'a> CommandRecorder<'a> {
         self.command_buffer.resources.push(dst);
     }
 
+    /// Fills every mip level of `image` but the first by blitting each from the one before,
+    /// then transitions every level to `final_layout`. The format of the image has to support
+    /// linear blits, as those of `Image::new_mipmapped` usually do.
+    pub fn generate_mipmaps(&mut self, image: Arc<Image>, final_layout: vk::ImageLayout) {
+        self.access_image(
+            image.clone(),
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::TRANSFER_WRITE,
+        );
+        let aspect_mask = aspect_mask(image.format);
+        // Each level is blitted from once the blit to it has finished.
+        let level_done = |recorder: &mut CommandRecorder, level: u32| unsafe {
+            recorder.device().handle.cmd_pipeline_barrier(
+                recorder.command_buffer.handle,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[vk::ImageMemoryBarrier::builder()
+                    .image(image.handle)
+                    .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                    .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                    .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                    .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .subresource_range(
+                        vk::ImageSubresourceRange::builder()
+                            .aspect_mask(aspect_mask)
+                            .base_mip_level(level)
+                            .level_count(1)
+                            .layer_count(image.array_layers)
+                            .build(),
+                    )
+                    .build()],
+            );
+        };
+        let extent = |level: u32| vk::Offset3D {
+            x: (image.width >> level).max(1) as i32,
+            y: (image.height >> level).max(1) as i32,
+            z: 1,
+        };
+        let subresource = |level: u32| {
+            vk::ImageSubresourceLayers::builder()
+                .aspect_mask(aspect_mask)
+                .mip_level(level)
+                .layer_count(image.array_layers)
+                .build()
+        };
+        for level in 1..image.mip_levels {
+            level_done(self, level - 1);
+            unsafe {
+                self.device().handle.cmd_blit_image(
+                    self.command_buffer.handle,
+                    image.handle,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    image.handle,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &[vk::ImageBlit::builder()
+                        .src_subresource(subresource(level - 1))
+                        .src_offsets([vk::Offset3D::default(), extent(level - 1)])
+                        .dst_subresource(subresource(level))
+                        .dst_offsets([vk::Offset3D::default(), extent(level)])
+                        .build()],
+                    vk::Filter::LINEAR,
+                );
+            }
+        }
+        level_done(self, image.mip_levels - 1);
+
+        // Every level is in `TRANSFER_SRC_OPTIMAL` now, written by the blits.
+        let sync = ImageSync {
+            layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            write_stage: vk::PipelineStageFlags::TRANSFER,
+            write_access: vk::AccessFlags::TRANSFER_WRITE,
+            read_stages: vk::PipelineStageFlags::empty(),
+            visible_stages: vk::PipelineStageFlags::empty(),
+        };
+        self.command_buffer
+            .image_syncs
+            .insert(image.handle, (image.clone(), sync));
+        let (stage, access) = layout_usage(final_layout);
+        self.access_image(image, final_layout, stage, access);
+    }
+
+    /// Transitions `image` by hand, from `old_layout`, `UNDEFINED` to discard the contents, or
+    /// from the layout the commands before left it in if `None`. The transition waits for the
+    /// commands before that used the image, and is made visible to every use of `new_layout`.
     pub fn set_image_layout(
         &mut self,
         image: Arc<Image>,
         old_layout: Option<vk::ImageLayout>,
         new_layout: vk::ImageLayout,
     ) {
-        let old = match old_layout {
-            Some(l) => l,
-            None => {
-                vk::ImageLayout::from_raw(image.layout.load(std::sync::atomic::Ordering::SeqCst))
-            }
+        let old_layout = old_layout.unwrap_or_else(|| self.image_layout(&image));
+        let (stage, access) = layout_usage(new_layout);
+        self.image_barrier(&image, old_layout, new_layout, stage, access);
+    }
+
+    /// The layout the commands recorded so far leave `image` in. Images are only left in it
+    /// once the command buffer is submitted.
+    pub fn image_layout(&self, image: &Image) -> vk::ImageLayout {
+        match self.command_buffer.image_syncs.get(&image.handle) {
+            Some((_, sync)) => sync.layout,
+            None => image.layout(),
+        }
+    }
+
+    /// Declares that the next commands use `image` in `layout`, at `stage` with `access`, e.g.
+    /// as a storage image bound for a dispatch, and records the transition and the barrier
+    /// they need after the commands before that used the image, if any.
+    pub fn access_image(
+        &mut self,
+        image: Arc<Image>,
+        layout: vk::ImageLayout,
+        stage: vk::PipelineStageFlags,
+        access: vk::AccessFlags,
+    ) {
+        let mut sync = self.image_sync(&image);
+        if let Some(barrier) = sync.access(layout, stage, access) {
+            self.record_image_barrier(&image, &barrier);
+        }
+        self.command_buffer
+            .image_syncs
+            .insert(image.handle, (image, sync));
+    }
+
+    /// Tells the recorder that `image` is in `layout` after commands it doesn't know of, e.g.
+    /// barriers recorded through raw handles, without recording anything. The uses after wait
+    /// for everything before.
+    pub fn assume_image_layout(&mut self, image: Arc<Image>, layout: vk::ImageLayout) {
+        self.command_buffer
+            .image_syncs
+            .insert(image.handle, (image, ImageSync::untracked(layout)));
+    }
+
+    fn image_sync(&mut self, image: &Arc<Image>) -> ImageSync {
+        let layout = image.layout();
+        self.command_buffer
+            .image_syncs
+            .entry(image.handle)
+            .or_insert_with(|| (image.clone(), ImageSync::untracked(layout)))
+            .1
+    }
+
+    /// Transitions `image` from `old_layout` to `new_layout` after the commands before that
+    /// used it, for those of `dst_stage` with `dst_access`.
+    fn image_barrier(
+        &mut self,
+        image: &Arc<Image>,
+        old_layout: vk::ImageLayout,
+        new_layout: vk::ImageLayout,
+        dst_stage: vk::PipelineStageFlags,
+        dst_access: vk::AccessFlags,
+    ) {
+        let mut sync = self.image_sync(image);
+        let barrier = sync.transition(old_layout, new_layout, dst_stage, dst_access);
+        self.record_image_barrier(image, &barrier);
+        self.command_buffer
+            .image_syncs
+            .insert(image.handle, (image.clone(), sync));
+    }
+
+    fn record_image_barrier(&mut self, image: &Arc<Image>, barrier: &ImageBarrier) {
+        unsafe {
+            self.device().handle.cmd_pipeline_barrier(
+                self.command_buffer.handle,
+                barrier.src_stage,
+                barrier.dst_stage,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[vk::ImageMemoryBarrier::builder()
+                    .image(image.handle)
+                    .old_layout(barrier.old_layout)
+                    .new_layout(barrier.new_layout)
+                    .src_access_mask(barrier.src_access)
+                    .dst_access_mask(barrier.dst_access)
+                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .subresource_range(image.subresource_range())
+                    .build()],
+            );
+        }
+        self.command_buffer.resources.push(image.clone());
+    }
+
+    /// `GENERAL` if `image` is in it, which transfers can use as well, `layout` otherwise.
+    fn transfer_layout(&self, image: &Image, layout: vk::ImageLayout) -> vk::ImageLayout {
+        match self.image_layout(image) {
+            vk::ImageLayout::GENERAL => vk::ImageLayout::GENERAL,
+            _ => layout,
+        }
+    }
+
+    /// Restricts the commands recorded after it to the physical devices of `device_mask`, for
+    /// devices of `Device::new_device_group`. They start out running on every device the
+    /// submission includes.
+    pub fn set_device_mask(&mut self, device_mask: u32) {
+        assert!(device_mask != 0 && device_mask & !self.device().all_devices_mask() == 0);
+        unsafe {
+            self.device()
+                .handle
+                .cmd_set_device_mask(self.command_buffer.handle, device_mask);
+        }
+    }
+
+    /// Makes queries `first_query..first_query + query_count` of `pool` unavailable, so they can
+    /// be written again.
+    pub fn reset_query_pool(&mut self, pool: Arc<QueryPool>, first_query: u32, query_count: u32) {
+        unsafe {
+            self.device().handle.cmd_reset_query_pool(
+                self.command_buffer.handle,
+                pool.handle,
+                first_query,
+                query_count,
+            );
+        }
+        self.command_buffer.resources.push(pool);
+    }
+
+    /// Writes to query `query` of `pool` the time at which the commands before it have finished
+    /// `stage`.
+    pub fn write_timestamp(
+        &mut self,
+        pool: Arc<QueryPool>,
+        stage: vk::PipelineStageFlags,
+        query: u32,
+    ) {
+        unsafe {
+            self.device().handle.cmd_write_timestamp(
+                self.command_buffer.handle,
+                stage,
+                pool.handle,
+                query,
+            );
+        }
+        self.command_buffer.resources.push(pool);
+    }
+
+    /// Records the commands of `f` counted by query `query` of `pool`, an occlusion or pipeline
+    /// statistics query. The query has to be reset first.
+    pub fn begin_query<F>(
+        &mut self,
+        pool: Arc<QueryPool>,
+        query: u32,
+        flags: vk::QueryControlFlags,
+        f: F,
+    ) where
+        F: FnOnce(&mut CommandRecorder),
+    {
+        assert_ne!(
+            pool.query_type,
+            vk::QueryType::TIMESTAMP,
+            "use write_timestamp"
+        );
+        unsafe {
+            self.device().handle.cmd_begin_query(
+                self.command_buffer.handle,
+                pool.handle,
+                query,
+                flags,
+            );
+        }
+        f(self);
+        unsafe {
+            self.device()
+                .handle
+                .cmd_end_query(self.command_buffer.handle, pool.handle, query);
+        }
+        self.command_buffer.resources.push(pool);
+    }
+
+    /// Records the commands of `f` timed as `name` by `profiler`, nested scopes included.
+    pub fn gpu_scope<F>(&mut self, profiler: &mut GpuProfiler, name: &str, f: F)
+    where
+        F: FnOnce(&mut CommandRecorder),
+    {
+        let query = profiler.begin_scope(self, name);
+        f(self);
+        profiler.end_scope(self, query);
+    }
+
+    /// Makes the writes of `src_stage` in `src_access` visible to `dst_access` in `dst_stage`,
+    /// for every resource.
+    pub fn memory_barrier(
+        &mut self,
+        src_stage: vk::PipelineStageFlags,
+        src_access: vk::AccessFlags,
+        dst_stage: vk::PipelineStageFlags,
+        dst_access: vk::AccessFlags,
+    ) {
+        unsafe {
+            self.device().handle.cmd_pipeline_barrier(
+                self.command_buffer.handle,
+                src_stage,
+                dst_stage,
+                vk::DependencyFlags::empty(),
+                &[vk::MemoryBarrier::builder()
+                    .src_access_mask(src_access)
+                    .dst_access_mask(dst_access)
+                    .build()],
+                &[],
+                &[],
+            );
+        }
+    }
+
+    /// Releases `buffer` from the queue family of the command buffer to `dst_family_index`,
+    /// making the writes of `src_stage` in `src_access` available. A matching `acquire_buffer`
+    /// must be recorded for a queue of that family, waiting on a semaphore this one signals.
+    pub fn release_buffer(
+        &mut self,
+        buffer: Arc<Buffer>,
+        dst_family_index: u32,
+        src_stage: vk::PipelineStageFlags,
+        src_access: vk::AccessFlags,
+    ) {
+        let src_family_index = self.command_buffer.pool.family_index;
+        self.buffer_ownership_barrier(
+            buffer,
+            src_family_index,
+            dst_family_index,
+            (src_stage, src_access),
+            (
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::AccessFlags::empty(),
+            ),
+        );
+    }
+
+    /// Acquires `buffer`, released by a `release_buffer` from `src_family_index`, for the queue
+    /// family of the command buffer, and makes it visible to `dst_access` in `dst_stage`.
+    pub fn acquire_buffer(
+        &mut self,
+        buffer: Arc<Buffer>,
+        src_family_index: u32,
+        dst_stage: vk::PipelineStageFlags,
+        dst_access: vk::AccessFlags,
+    ) {
+        let dst_family_index = self.command_buffer.pool.family_index;
+        self.buffer_ownership_barrier(
+            buffer,
+            src_family_index,
+            dst_family_index,
+            (
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::AccessFlags::empty(),
+            ),
+            (dst_stage, dst_access),
+        );
+    }
+
+    /// Releases `image` from the queue family of the command buffer to `dst_family_index`,
+    /// transitioning it to `new_layout`. A matching `acquire_image` must be recorded for a queue
+    /// of that family, after which the image is in `new_layout`.
+    pub fn release_image(
+        &mut self,
+        image: Arc<Image>,
+        dst_family_index: u32,
+        new_layout: vk::ImageLayout,
+    ) {
+        let src_family_index = self.command_buffer.pool.family_index;
+        self.image_ownership_barrier(image, src_family_index, dst_family_index, new_layout);
+    }
+
+    /// Acquires `image`, released by a `release_image` from `src_family_index` with the same
+    /// `new_layout`, for the queue family of the command buffer.
+    pub fn acquire_image(
+        &mut self,
+        image: Arc<Image>,
+        src_family_index: u32,
+        new_layout: vk::ImageLayout,
+    ) {
+        let dst_family_index = self.command_buffer.pool.family_index;
+        self.image_ownership_barrier(
+            image.clone(),
+            src_family_index,
+            dst_family_index,
+            new_layout,
+        );
+        let sync = ImageSync {
+            layout: new_layout,
+            write_stage: vk::PipelineStageFlags::ALL_COMMANDS,
+            write_access: vk::AccessFlags::empty(),
+            read_stages: vk::PipelineStageFlags::empty(),
+            visible_stages: vk::PipelineStageFlags::ALL_COMMANDS,
         };
-        cmd_set_image_layout(old, &self.command_buffer, image.handle, new_layout);
-        image
-            .layout
-            .store(new_layout.as_raw(), std::sync::atomic::Ordering::SeqCst);
+        self.command_buffer
+            .image_syncs
+            .insert(image.handle, (image, sync));
+    }
+
+    /// The layout of `image` only changes once acquired, so both halves of the transfer record
+    /// the same transition.
+    fn image_ownership_barrier(
+        &mut self,
+        image: Arc<Image>,
+        src_family_index: u32,
+        dst_family_index: u32,
+        new_layout: vk::ImageLayout,
+    ) {
+        assert_ne!(
+            src_family_index, dst_family_index,
+            "ownership of an image transferred within queue family {}",
+            src_family_index
+        );
+        unsafe {
+            self.device().handle.cmd_pipeline_barrier(
+                self.command_buffer.handle,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[vk::ImageMemoryBarrier::builder()
+                    .image(image.handle)
+                    .old_layout(self.image_layout(&image))
+                    .new_layout(new_layout)
+                    .src_access_mask(vk::AccessFlags::MEMORY_WRITE)
+                    .dst_access_mask(vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE)
+                    .src_queue_family_index(src_family_index)
+                    .dst_queue_family_index(dst_family_index)
+                    .subresource_range(image.subresource_range())
+                    .build()],
+            );
+        }
         self.command_buffer.resources.push(image);
     }
 
+    fn buffer_ownership_barrier(
+        &mut self,
+        buffer: Arc<Buffer>,
+        src_family_index: u32,
+        dst_family_index: u32,
+        src: (vk::PipelineStageFlags, vk::AccessFlags),
+        dst: (vk::PipelineStageFlags, vk::AccessFlags),
+    ) {
+        assert_ne!(
+            src_family_index, dst_family_index,
+            "ownership of a buffer transferred within queue family {}",
+            src_family_index
+        );
+        unsafe {
+            self.device().handle.cmd_pipeline_barrier(
+                self.command_buffer.handle,
+                src.0,
+                dst.0,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[vk::BufferMemoryBarrier::builder()
+                    .src_access_mask(src.1)
+                    .dst_access_mask(dst.1)
+                    .src_queue_family_index(src_family_index)
+                    .dst_queue_family_index(dst_family_index)
+                    .buffer(buffer.handle)
+                    .offset(0)
+                    .size(vk::WHOLE_SIZE)
+                    .build()],
+                &[],
+            );
+        }
+        self.command_buffer.resources.push(buffer);
+    }
+
     unsafe fn set_image_layout_raw(&mut self, image: &Image, new_layout: vk::ImageLayout) {
         cmd_set_image_layout(
             vk::ImageLayout::from_raw(image.layout.load(std::sync::atomic::Ordering::SeqCst)),
             &self.command_buffer,
-            image.handle,
+            image,
             new_layout,
         );
     }
@@ -1582,12 +4340,15 @@ impl Resource for RayTracingPipeline {}
 impl Resource for DescriptorSet {}
 impl Resource for PipelineLayout {}
 impl Resource for AccelerationStructure {}
+impl Resource for QueryPool {}
 
 pub struct CommandBuffer {
     handle: vk::CommandBuffer,
     pool: Arc<CommandPool>,
     in_use: bool,
     resources: Vec<Arc<dyn Resource>>,
+    /// The images the commands recorded use, with where they leave each.
+    image_syncs: HashMap<vk::Image, (Arc<Image>, ImageSync)>,
 }
 impl !Send for CommandBuffer {}
 impl !Sync for CommandBuffer {}
@@ -1628,31 +4389,160 @@ impl CommandBuffer {
                 pool,
                 in_use: false,
                 resources: Vec::new(),
+                image_syncs: HashMap::new(),
             }
         }
     }
 
     pub fn encode<F>(&mut self, func: F)
+    where
+        F: FnOnce(&mut CommandRecorder),
+    {
+        self.try_encode(func).unwrap()
+    }
+
+    /// Fails with `Error::Vulkan` when the memory runs out. The commands recorded by `func`
+    /// can't fail themselves, errors in them are reported when the command buffer ends.
+    pub fn try_encode<F>(&mut self, func: F) -> Result<(), Error>
     where
         F: FnOnce(&mut CommandRecorder),
     {
         unsafe {
             let device = self.pool.device.handle.clone();
-            device
-                .begin_command_buffer(self.handle, &vk::CommandBufferBeginInfo::default())
-                .unwrap();
+            device.begin_command_buffer(self.handle, &vk::CommandBufferBeginInfo::default())?;
+            self.image_syncs.clear();
             let mut manager = CommandRecorder {
                 command_buffer: self,
                 bind_point: None,
             };
             func(&mut manager);
-            device.end_command_buffer(self.handle).unwrap();
+            device.end_command_buffer(self.handle)?;
         }
+        Ok(())
     }
 
     fn free_resources(&mut self) {
         self.resources.clear();
     }
+
+    /// Stores the layouts the command buffer leaves its images in, once submitted, so the
+    /// layouts of command buffers recorded but never submitted don't stick.
+    fn commit_image_layouts(&self) {
+        for (image, sync) in self.image_syncs.values() {
+            image
+                .layout
+                .store(sync.layout.as_raw(), std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+}
+
+/// Accesses that write, which later ones have to wait for.
+const WRITE_ACCESS: vk::AccessFlags = vk::AccessFlags::from_raw(
+    vk::AccessFlags::SHADER_WRITE.as_raw()
+        | vk::AccessFlags::COLOR_ATTACHMENT_WRITE.as_raw()
+        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE.as_raw()
+        | vk::AccessFlags::TRANSFER_WRITE.as_raw()
+        | vk::AccessFlags::HOST_WRITE.as_raw()
+        | vk::AccessFlags::MEMORY_WRITE.as_raw()
+        | vk::AccessFlags::ACCELERATION_STRUCTURE_WRITE_KHR.as_raw(),
+);
+
+/// Where the commands recorded so far leave an image, see `CommandRecorder::access_image`.
+#[derive(Debug, Clone, Copy)]
+struct ImageSync {
+    layout: vk::ImageLayout,
+    /// Stages of the last write or layout transition, which later uses wait for.
+    write_stage: vk::PipelineStageFlags,
+    /// Accesses of the last write, made visible to later uses. Empty after transitions.
+    write_access: vk::AccessFlags,
+    /// Stages that read the image since, which writes wait for.
+    read_stages: vk::PipelineStageFlags,
+    /// Stages the last write or transition has been made visible to.
+    visible_stages: vk::PipelineStageFlags,
+}
+
+impl ImageSync {
+    /// An image the command buffer hasn't used yet, written by anything before it.
+    fn untracked(layout: vk::ImageLayout) -> Self {
+        Self {
+            layout,
+            write_stage: vk::PipelineStageFlags::ALL_COMMANDS,
+            write_access: vk::AccessFlags::MEMORY_WRITE,
+            read_stages: vk::PipelineStageFlags::empty(),
+            visible_stages: vk::PipelineStageFlags::empty(),
+        }
+    }
+
+    /// Records a use of the image in `layout` at `stage` with `access`, returning the barrier
+    /// it needs after the uses before, if any.
+    fn access(
+        &mut self,
+        layout: vk::ImageLayout,
+        stage: vk::PipelineStageFlags,
+        access: vk::AccessFlags,
+    ) -> Option<ImageBarrier> {
+        let write = access.intersects(WRITE_ACCESS);
+        let barrier = if self.layout != layout
+            || !self.visible_stages.contains(stage)
+            || write && (!self.write_access.is_empty() || !self.read_stages.is_empty())
+        {
+            Some(self.transition(self.layout, layout, stage, access))
+        } else {
+            None
+        };
+        if write {
+            self.write_stage = stage;
+            self.write_access = access & WRITE_ACCESS;
+            self.read_stages = vk::PipelineStageFlags::empty();
+            self.visible_stages = vk::PipelineStageFlags::empty();
+        } else {
+            self.read_stages |= stage;
+        }
+        barrier
+    }
+
+    /// The barrier transitioning the image from `old_layout` to `new_layout` after the uses
+    /// before, made visible to `dst_stage` with `dst_access`.
+    fn transition(
+        &mut self,
+        old_layout: vk::ImageLayout,
+        new_layout: vk::ImageLayout,
+        dst_stage: vk::PipelineStageFlags,
+        dst_access: vk::AccessFlags,
+    ) -> ImageBarrier {
+        let src_stage = match self.write_stage | self.read_stages {
+            stage if stage.is_empty() => vk::PipelineStageFlags::TOP_OF_PIPE,
+            stage => stage,
+        };
+        let barrier = ImageBarrier {
+            old_layout,
+            new_layout,
+            src_stage,
+            src_access: self.write_access,
+            dst_stage,
+            dst_access,
+        };
+        *self = Self {
+            layout: new_layout,
+            write_stage: dst_stage,
+            write_access: vk::AccessFlags::empty(),
+            read_stages: vk::PipelineStageFlags::empty(),
+            visible_stages: dst_stage,
+        };
+        barrier
+    }
+}
+
+/// A layout transition of an image and the dependency on its uses before, see
+/// `ImageSync::access`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ImageBarrier {
+    old_layout: vk::ImageLayout,
+    new_layout: vk::ImageLayout,
+    src_stage: vk::PipelineStageFlags,
+    src_access: vk::AccessFlags,
+    dst_stage: vk::PipelineStageFlags,
+    dst_access: vk::AccessFlags,
 }
 
 impl Drop for CommandBuffer {
@@ -1670,6 +4560,110 @@ impl Drop for CommandBuffer {
     }
 }
 
+/// What `Swapchain::acquire_next_image` got.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AcquireResult {
+    Ok(u32),
+    /// The image can be rendered to and presented, but the swapchain no longer matches the
+    /// surface exactly and should be renewed.
+    Suboptimal(u32),
+    /// The swapchain no longer matches the surface and has to be renewed before acquiring again.
+    OutOfDate,
+}
+
+impl AcquireResult {
+    /// The index of the image acquired, `None` if out of date.
+    pub fn index(self) -> Option<u32> {
+        match self {
+            AcquireResult::Ok(index) | AcquireResult::Suboptimal(index) => Some(index),
+            AcquireResult::OutOfDate => None,
+        }
+    }
+}
+
+/// How `Swapchain::with_desc` creates the swapchain. What the surface doesn't support is
+/// clamped to what it does.
+#[derive(Debug, Clone)]
+pub struct SwapchainDesc {
+    /// The first of these the surface supports is used, its first format if none is, e.g.
+    /// `B8G8R8A8_SRGB` first for sRGB output.
+    pub preferred_formats: Vec<vk::SurfaceFormatKHR>,
+    /// FIFO if the surface doesn't support it, which every surface does.
+    pub present_mode: vk::PresentModeKHR,
+    /// Clamped to the minimum and maximum of the surface.
+    pub image_count: u32,
+    /// The usages the surface doesn't support are left out.
+    pub image_usage: vk::ImageUsageFlags,
+}
+
+impl Default for SwapchainDesc {
+    fn default() -> Self {
+        Self {
+            preferred_formats: Vec::new(),
+            present_mode: vk::PresentModeKHR::FIFO,
+            image_count: 2,
+            image_usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_DST,
+        }
+    }
+}
+
+impl SwapchainDesc {
+    /// The format, present mode, image count and usage of a swapchain of `surface`.
+    fn resolve(
+        &self,
+        device: &Device,
+        surface: &Surface,
+        capabilities: &vk::SurfaceCapabilitiesKHR,
+    ) -> Result<
+        (
+            vk::SurfaceFormatKHR,
+            vk::PresentModeKHR,
+            u32,
+            vk::ImageUsageFlags,
+        ),
+        Error,
+    > {
+        let surface_loader = &device.pdevice.instance.surface_loader;
+        let (formats, present_modes) = unsafe {
+            (
+                surface_loader
+                    .get_physical_device_surface_formats(device.pdevice.handle, surface.handle)?,
+                surface_loader.get_physical_device_surface_present_modes(
+                    device.pdevice.handle,
+                    surface.handle,
+                )?,
+            )
+        };
+        let format = self
+            .preferred_formats
+            .iter()
+            .find(|preferred| {
+                formats.iter().any(|format| {
+                    format.format == preferred.format && format.color_space == preferred.color_space
+                })
+            })
+            .copied()
+            .unwrap_or(formats[0]);
+        let present_mode = if present_modes.contains(&self.present_mode) {
+            self.present_mode
+        } else {
+            log::warn!(
+                "present mode {:?} not supported, falling back to FIFO",
+                self.present_mode
+            );
+            vk::PresentModeKHR::FIFO
+        };
+        // A maximum of 0 means there is none.
+        let mut image_count = self.image_count.max(capabilities.min_image_count);
+        if capabilities.max_image_count > 0 {
+            image_count = image_count.min(capabilities.max_image_count);
+        }
+        let image_usage = self.image_usage & capabilities.supported_usage_flags
+            | vk::ImageUsageFlags::COLOR_ATTACHMENT;
+        Ok((format, present_mode, image_count, image_usage))
+    }
+}
+
 pub struct Swapchain {
     handle: std::sync::atomic::AtomicU64,
     device: Arc<Device>,
@@ -1678,7 +4672,11 @@ pub struct Swapchain {
     height: std::sync::atomic::AtomicU32,
     format: vk::Format,
     image_available_semaphore: BinarySemaphore,
+    desc: SwapchainDesc,
+    /// What `desc` resolved to for the surface.
     present_mode: vk::PresentModeKHR,
+    image_count: u32,
+    image_usage: vk::ImageUsageFlags,
 }
 
 impl Swapchain {
@@ -1687,27 +4685,49 @@ impl Swapchain {
         surface: Arc<Surface>,
         present_mode: vk::PresentModeKHR,
     ) -> Self {
+        Self::try_new(device, surface, present_mode).unwrap()
+    }
+
+    /// Fails with `Error::Vulkan` e.g. when the surface has been lost.
+    pub fn try_new(
+        device: Arc<Device>,
+        surface: Arc<Surface>,
+        present_mode: vk::PresentModeKHR,
+    ) -> Result<Self, Error> {
+        let desc = SwapchainDesc {
+            present_mode,
+            ..Default::default()
+        };
+        Self::try_with_desc(device, surface, desc)
+    }
+
+    pub fn with_desc(device: Arc<Device>, surface: Arc<Surface>, desc: SwapchainDesc) -> Self {
+        Self::try_with_desc(device, surface, desc).unwrap()
+    }
+
+    /// Fails with `Error::Vulkan` e.g. when the surface has been lost.
+    pub fn try_with_desc(
+        device: Arc<Device>,
+        surface: Arc<Surface>,
+        desc: SwapchainDesc,
+    ) -> Result<Self, Error> {
         unsafe {
             let surface_loader = &device.pdevice.instance.surface_loader;
             let surface_capabilities = surface_loader
-                .get_physical_device_surface_capabilities(device.pdevice.handle, surface.handle)
-                .unwrap();
+                .get_physical_device_surface_capabilities(device.pdevice.handle, surface.handle)?;
 
-            let surface_format = surface_loader
-                .get_physical_device_surface_formats(device.pdevice.handle, surface.handle)
-                .unwrap()[0];
+            let (surface_format, present_mode, image_count, image_usage) =
+                desc.resolve(&device, &surface, &surface_capabilities)?;
 
             let format = surface_format.format;
 
             let swapchain_create_info = vk::SwapchainCreateInfoKHR::builder()
                 .surface(surface.handle)
-                .min_image_count(2)
+                .min_image_count(image_count)
                 .image_color_space(surface_format.color_space)
                 .image_format(format)
                 .image_extent(surface_capabilities.current_extent)
-                .image_usage(
-                    vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_DST,
-                )
+                .image_usage(image_usage)
                 .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
                 .pre_transform(vk::SurfaceTransformFlagsKHR::IDENTITY)
                 .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
@@ -1716,12 +4736,11 @@ impl Swapchain {
                 .image_array_layers(1);
             let handle = device
                 .swapchain_loader
-                .create_swapchain(&swapchain_create_info, None)
-                .unwrap()
+                .create_swapchain(&swapchain_create_info, None)?
                 .as_raw();
             let image_available_semaphore = BinarySemaphore::new(device.clone());
 
-            Self {
+            Ok(Self {
                 handle: std::sync::atomic::AtomicU64::new(handle),
                 device,
                 surface,
@@ -1731,30 +4750,69 @@ impl Swapchain {
                 ),
                 format,
                 image_available_semaphore,
+                desc,
                 present_mode,
-            }
+                image_count,
+                image_usage,
+            })
         }
     }
 
-    pub fn acquire_next_image(&self) -> (u32, bool) {
-        unsafe {
-            let (index, sub) = self
-                .device
-                .swapchain_loader
-                .acquire_next_image(
-                    vk::SwapchainKHR::from_raw(
-                        self.handle.load(std::sync::atomic::Ordering::SeqCst),
-                    ),
-                    0,
-                    self.image_available_semaphore.handle,
-                    vk::Fence::null(),
-                )
-                .unwrap();
-            (index, sub)
+    /// Acquires the next image, signaling `image_available_semaphore` once it can be rendered
+    /// to. Blocks until the presentation engine has an image to give. Fails with `Error::Vulkan`
+    /// e.g. when the surface or the device has been lost.
+    pub fn acquire_next_image(&self) -> Result<AcquireResult, Error> {
+        let result = unsafe {
+            self.device.swapchain_loader.acquire_next_image(
+                self.vk_handle(),
+                u64::MAX,
+                self.image_available_semaphore.handle,
+                vk::Fence::null(),
+            )
+        };
+        match result {
+            Ok((index, false)) => Ok(AcquireResult::Ok(index)),
+            Ok((index, true)) => Ok(AcquireResult::Suboptimal(index)),
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => Ok(AcquireResult::OutOfDate),
+            Err(result) => Err(Error::Vulkan(result)),
         }
     }
 
+    /// Acquires the next image like `acquire_next_image`, renewing the swapchain with `renew_to`
+    /// and retrying if it's out of date, e.g. after a resize or leaving exclusive fullscreen.
+    /// Returns the index and whether the swapchain was renewed, in which case its images and
+    /// everything sized after them must be recreated before rendering. `None` if the surface has
+    /// no area after the renew.
+    pub fn acquire_next_image_renewing(
+        &self,
+        extent: vk::Extent2D,
+    ) -> Result<Option<(u32, bool)>, Error> {
+        if let Some(index) = self.acquire_next_image()?.index() {
+            return Ok(Some((index, false)));
+        }
+        self.renew_to(extent);
+        if self.is_zero_sized() {
+            return Ok(None);
+        }
+        match self.acquire_next_image()? {
+            AcquireResult::Ok(index) | AcquireResult::Suboptimal(index) => Ok(Some((index, true))),
+            AcquireResult::OutOfDate => Err(Error::Vulkan(vk::Result::ERROR_OUT_OF_DATE_KHR)),
+        }
+    }
+
+    /// Recreates the swapchain for the current surface extent. A minimized window has a zero
+    /// extent, the old swapchain is kept then and `is_zero_sized` returns `true` until a renew
+    /// with a visible surface.
     pub fn renew(&self) {
+        self.renew_to(vk::Extent2D {
+            width: self.width(),
+            height: self.height(),
+        });
+    }
+
+    /// Like `renew`, with `extent` for surfaces whose extent is up to the swapchain, like those of
+    /// Wayland windows. Pass the new size of the window.
+    pub fn renew_to(&self, extent: vk::Extent2D) {
         let swapchain_loader = &self.device.swapchain_loader;
         let surface_loader = &self.device.pdevice.instance.surface_loader;
         let pdevice = &self.device.pdevice;
@@ -1763,20 +4821,40 @@ impl Swapchain {
                 .get_physical_device_surface_capabilities(pdevice.handle, self.surface.handle)
                 .unwrap();
 
-            let surface_format = surface_loader
-                .get_physical_device_surface_formats(pdevice.handle, self.surface.handle)
-                .unwrap()[0];
+            // A current extent of u32::MAX means the surface takes that of the swapchain.
+            let extent = if surface_capabilities.current_extent.width == u32::MAX {
+                vk::Extent2D {
+                    width: extent
+                        .width
+                        .max(surface_capabilities.min_image_extent.width)
+                        .min(surface_capabilities.max_image_extent.width),
+                    height: extent
+                        .height
+                        .max(surface_capabilities.min_image_extent.height)
+                        .min(surface_capabilities.max_image_extent.height),
+                }
+            } else {
+                surface_capabilities.current_extent
+            };
+            if extent.width == 0 || extent.height == 0 {
+                self.width.store(0, std::sync::atomic::Ordering::SeqCst);
+                self.height.store(0, std::sync::atomic::Ordering::SeqCst);
+                return;
+            }
+
+            let (surface_format, _, _, _) = self
+                .desc
+                .resolve(&self.device, &self.surface, &surface_capabilities)
+                .unwrap();
 
             let old_swapchain = self.vk_handle();
             let swapchain_create_info = vk::SwapchainCreateInfoKHR::builder()
                 .surface(self.surface.handle)
-                .min_image_count(2)
+                .min_image_count(self.image_count)
                 .image_color_space(surface_format.color_space)
                 .image_format(surface_format.format)
-                .image_extent(surface_capabilities.current_extent)
-                .image_usage(
-                    vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_DST,
-                )
+                .image_extent(extent)
+                .image_usage(self.image_usage)
                 .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
                 .pre_transform(vk::SurfaceTransformFlagsKHR::IDENTITY)
                 .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
@@ -1795,14 +4873,10 @@ impl Swapchain {
             self.device
                 .swapchain_loader
                 .destroy_swapchain(old_swapchain, None);
-            self.width.store(
-                surface_capabilities.current_extent.width,
-                std::sync::atomic::Ordering::SeqCst,
-            );
-            self.height.store(
-                surface_capabilities.current_extent.height,
-                std::sync::atomic::Ordering::SeqCst,
-            );
+            self.width
+                .store(extent.width, std::sync::atomic::Ordering::SeqCst);
+            self.height
+                .store(extent.height, std::sync::atomic::Ordering::SeqCst);
         }
     }
 
@@ -1821,6 +4895,30 @@ impl Swapchain {
     pub fn height(&self) -> u32 {
         self.height.load(std::sync::atomic::Ordering::SeqCst)
     }
+
+    /// Whether the surface had no area at the last `renew`, e.g. because the window is minimized.
+    /// Nothing can be acquired or presented then.
+    pub fn is_zero_sized(&self) -> bool {
+        self.width() == 0 || self.height() == 0
+    }
+
+    pub fn format(&self) -> vk::Format {
+        self.format
+    }
+
+    /// The present mode the surface supports that the swapchain was created with.
+    pub fn present_mode(&self) -> vk::PresentModeKHR {
+        self.present_mode
+    }
+
+    /// The minimum image count the swapchain was created with, the driver may create more.
+    pub fn image_count(&self) -> u32 {
+        self.image_count
+    }
+
+    pub fn image_usage(&self) -> vk::ImageUsageFlags {
+        self.image_usage
+    }
 }
 
 impl Drop for Swapchain {
@@ -1850,6 +4948,10 @@ pub struct Image {
     image_type: ImageType,
     width: u32,
     height: u32,
+    mip_levels: u32,
+    array_layers: u32,
+    /// Viewed as a cube, or an array of cubes.
+    cube: bool,
     layout: std::sync::atomic::AtomicI32,
     format: vk::Format,
 }
@@ -1865,35 +4967,166 @@ impl Image {
         image_usage: vk::ImageUsageFlags,
         memory_usage: vk_mem::MemoryUsage,
     ) -> Self {
-        let (handle, allocation, allocation_info) = allocator
-            .handle
-            .create_image(
-                &vk::ImageCreateInfo::builder()
-                    .image_type(vk::ImageType::TYPE_2D)
-                    .format(format)
-                    .extent(vk::Extent3D {
-                        width,
-                        height,
-                        depth: 1,
-                    })
-                    .samples(vk::SampleCountFlags::TYPE_1)
-                    .mip_levels(1)
-                    .array_layers(1)
-                    .tiling(tiling)
-                    .usage(image_usage)
-                    .sharing_mode(vk::SharingMode::EXCLUSIVE)
-                    .initial_layout(vk::ImageLayout::UNDEFINED)
-                    .build(),
-                &vk_mem::AllocationCreateInfo {
-                    usage: memory_usage,
-                    ..Default::default()
-                },
-            )
-            .unwrap();
+        Self::try_new(
+            name,
+            allocator,
+            format,
+            width,
+            height,
+            tiling,
+            image_usage,
+            memory_usage,
+        )
+        .unwrap()
+    }
+
+    /// Fails with `Error::Allocation` when the memory runs out, or with `Error::Vulkan` if the
+    /// format doesn't support the usage.
+    pub fn try_new(
+        name: Option<&str>,
+        allocator: Arc<Allocator>,
+        format: vk::Format,
+        width: u32,
+        height: u32,
+        tiling: vk::ImageTiling,
+        image_usage: vk::ImageUsageFlags,
+        memory_usage: vk_mem::MemoryUsage,
+    ) -> Result<Self, Error> {
+        Self::allocate(
+            name,
+            allocator,
+            format,
+            width,
+            height,
+            1,
+            1,
+            vk::ImageCreateFlags::empty(),
+            tiling,
+            image_usage,
+            memory_usage,
+        )
+    }
+
+    /// An image with every mip level down to 1x1, for `CommandRecorder::generate_mipmaps` to
+    /// fill from the first. Can be copied from and to on top of `image_usage`.
+    pub fn new_mipmapped(
+        name: Option<&str>,
+        allocator: Arc<Allocator>,
+        format: vk::Format,
+        width: u32,
+        height: u32,
+        image_usage: vk::ImageUsageFlags,
+        memory_usage: vk_mem::MemoryUsage,
+    ) -> Self {
+        Self::allocate(
+            name,
+            allocator,
+            format,
+            width,
+            height,
+            32 - width.max(height).leading_zeros(),
+            1,
+            vk::ImageCreateFlags::empty(),
+            vk::ImageTiling::OPTIMAL,
+            image_usage | vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST,
+            memory_usage,
+        )
+        .unwrap()
+    }
+
+    /// An array of `array_layers` 2D images of the same size, viewed as a 2D array.
+    pub fn new_array(
+        name: Option<&str>,
+        allocator: Arc<Allocator>,
+        format: vk::Format,
+        width: u32,
+        height: u32,
+        array_layers: u32,
+        image_usage: vk::ImageUsageFlags,
+        memory_usage: vk_mem::MemoryUsage,
+    ) -> Self {
+        Self::allocate(
+            name,
+            allocator,
+            format,
+            width,
+            height,
+            1,
+            array_layers,
+            vk::ImageCreateFlags::empty(),
+            vk::ImageTiling::OPTIMAL,
+            image_usage,
+            memory_usage,
+        )
+        .unwrap()
+    }
+
+    /// A cubemap of `size` by `size` faces, the layers in the order +X, -X, +Y, -Y, +Z, -Z.
+    /// Its views are cube views, sampled by direction.
+    pub fn new_cube(
+        name: Option<&str>,
+        allocator: Arc<Allocator>,
+        format: vk::Format,
+        size: u32,
+        image_usage: vk::ImageUsageFlags,
+        memory_usage: vk_mem::MemoryUsage,
+    ) -> Self {
+        Self::allocate(
+            name,
+            allocator,
+            format,
+            size,
+            size,
+            1,
+            6,
+            vk::ImageCreateFlags::CUBE_COMPATIBLE,
+            vk::ImageTiling::OPTIMAL,
+            image_usage,
+            memory_usage,
+        )
+        .unwrap()
+    }
+
+    fn allocate(
+        name: Option<&str>,
+        allocator: Arc<Allocator>,
+        format: vk::Format,
+        width: u32,
+        height: u32,
+        mip_levels: u32,
+        array_layers: u32,
+        flags: vk::ImageCreateFlags,
+        tiling: vk::ImageTiling,
+        image_usage: vk::ImageUsageFlags,
+        memory_usage: vk_mem::MemoryUsage,
+    ) -> Result<Self, Error> {
+        let (handle, allocation, allocation_info) = allocator.handle.create_image(
+            &vk::ImageCreateInfo::builder()
+                .flags(flags)
+                .image_type(vk::ImageType::TYPE_2D)
+                .format(format)
+                .extent(vk::Extent3D {
+                    width,
+                    height,
+                    depth: 1,
+                })
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .mip_levels(mip_levels)
+                .array_layers(array_layers)
+                .tiling(tiling)
+                .usage(image_usage)
+                .sharing_mode(vk::SharingMode::EXCLUSIVE)
+                .initial_layout(vk::ImageLayout::UNDEFINED)
+                .build(),
+            &vk_mem::AllocationCreateInfo {
+                usage: memory_usage,
+                ..Default::default()
+            },
+        )?;
 
         let device = allocator.device();
-        unsafe {
-            if let Some(name) = name {
+        if let Some(name) = name {
+            let named = unsafe {
                 device
                     .pdevice
                     .instance
@@ -1906,9 +5139,19 @@ impl Image {
                             .object_name(CString::new(name).unwrap().as_ref())
                             .build(),
                     )
-                    .unwrap();
+            };
+            // Until `Self` owns the image, it's destroyed by hand.
+            if let Err(error) = named {
+                allocator.handle.destroy_image(handle, &allocation);
+                return Err(error.into());
             }
         }
+        allocator.track(
+            vk::ObjectType::IMAGE,
+            handle.as_raw(),
+            name,
+            allocation_info.get_size() as u64,
+        );
 
         let image_type = ImageType::Allocated {
             allocator,
@@ -1918,20 +5161,55 @@ impl Image {
 
         let layout = std::sync::atomic::AtomicI32::new(vk::ImageLayout::UNDEFINED.as_raw());
 
-        Self {
+        Ok(Self {
             handle,
             width,
             height,
+            mip_levels,
+            array_layers,
+            cube: flags.contains(vk::ImageCreateFlags::CUBE_COMPATIBLE),
             layout,
             image_type,
             format,
-        }
+        })
     }
 
+    /// The layout the command buffers submitted last left the image in, see
+    /// `CommandRecorder::image_layout` for the one while recording.
     pub fn layout(&self) -> vk::ImageLayout {
         vk::ImageLayout::from_raw(self.layout.load(std::sync::atomic::Ordering::SeqCst))
     }
 
+    /// The copy of the first mip level of layer `layer` from or to tightly packed texels at
+    /// `buffer_offset`, e.g. for each face of a cube with `CommandRecorder::copy_buffer_to_image`.
+    pub fn layer_copy(&self, layer: u32, buffer_offset: u64) -> vk::BufferImageCopy {
+        assert!(layer < self.array_layers);
+        vk::BufferImageCopy::builder()
+            .buffer_offset(buffer_offset)
+            .image_subresource(
+                vk::ImageSubresourceLayers::builder()
+                    .aspect_mask(aspect_mask(self.format))
+                    .base_array_layer(layer)
+                    .layer_count(1)
+                    .build(),
+            )
+            .image_extent(vk::Extent3D {
+                width: self.width,
+                height: self.height,
+                depth: 1,
+            })
+            .build()
+    }
+
+    /// Every level and layer of the image, of the aspects of its format.
+    fn subresource_range(&self) -> vk::ImageSubresourceRange {
+        vk::ImageSubresourceRange::builder()
+            .aspect_mask(aspect_mask(self.format))
+            .level_count(vk::REMAINING_MIP_LEVELS)
+            .layer_count(vk::REMAINING_ARRAY_LAYERS)
+            .build()
+    }
+
     pub fn new_init_host<I: AsRef<[u8]>>(
         name: Option<&str>,
         allocator: Arc<Allocator>,
@@ -1970,6 +5248,69 @@ impl Image {
         image
     }
 
+    /// A sampled texture of `data`, the texels of the first mip level, with the others generated
+    /// from it. Left in `SHADER_READ_ONLY_OPTIMAL`. Shared with the command buffer that uploaded
+    /// it until the queue submits another.
+    pub fn new_init_host_mipmapped<I: AsRef<[u8]>>(
+        name: Option<&str>,
+        allocator: Arc<Allocator>,
+        format: vk::Format,
+        width: u32,
+        height: u32,
+        queue: &mut Queue,
+        command_pool: Arc<CommandPool>,
+        data: I,
+    ) -> Arc<Self> {
+        let image = Arc::new(Self::new_mipmapped(
+            name,
+            allocator.clone(),
+            format,
+            width,
+            height,
+            vk::ImageUsageFlags::SAMPLED,
+            MemoryUsage::GpuOnly,
+        ));
+        let staging_buffer = Arc::new(Buffer::new_init_host(
+            Some("staging buffer"),
+            allocator,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            MemoryUsage::CpuToGpu,
+            data.as_ref(),
+        ));
+
+        let mut command_buffer = CommandBuffer::new(command_pool);
+        command_buffer.encode(|recorder| {
+            recorder.copy_buffer_to_image(
+                staging_buffer,
+                image.clone(),
+                &[vk::BufferImageCopy::builder()
+                    .image_extent(vk::Extent3D {
+                        width,
+                        height,
+                        depth: 1,
+                    })
+                    .image_subresource(
+                        vk::ImageSubresourceLayers::builder()
+                            .aspect_mask(aspect_mask(format))
+                            .layer_count(1)
+                            .build(),
+                    )
+                    .build()],
+            );
+            recorder.generate_mipmaps(image.clone(), vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        });
+        let semaphore = TimelineSemaphore::new(image.device().clone());
+        queue.submit_timeline(
+            command_buffer,
+            &[&semaphore],
+            &[0],
+            &[vk::PipelineStageFlags::ALL_COMMANDS],
+            &[1],
+        );
+        semaphore.wait_for(1);
+        image
+    }
+
     pub fn copy_from_buffer(
         &self,
         buffer: &Buffer,
@@ -1993,9 +5334,9 @@ impl Image {
                         .image_offset(vk::Offset3D::default())
                         .image_subresource(
                             vk::ImageSubresourceLayers::builder()
-                                .layer_count(1)
+                                .layer_count(self.array_layers)
                                 .base_array_layer(0)
-                                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                                .aspect_mask(aspect_mask(self.format))
                                 .mip_level(0)
                                 .build(),
                         )
@@ -2048,6 +5389,20 @@ impl Image {
         semaphore.wait_for(1);
     }
 
+    /// Copies the first level and layer back to the host and converts it to RGBA, e.g. for
+    /// screenshots. Runs after what `queue` was submitted before and waits for the copy. Swapchain
+    /// images need `TRANSFER_SRC` in `SwapchainDesc::image_usage`. Fails with
+    /// `Error::Unsupported` for formats `Pixels` has no conversion from.
+    pub fn read_to_vec(
+        self: &Arc<Self>,
+        allocator: Arc<Allocator>,
+        queue: &mut Queue,
+        command_pool: Arc<CommandPool>,
+    ) -> Result<Pixels, Error> {
+        let texels = offscreen::read_back(self.clone(), allocator, queue, command_pool)?;
+        Pixels::from_texels(self.format, texels)
+    }
+
     pub fn from_swapchain(swapchain: Arc<Swapchain>) -> Vec<Self> {
         unsafe {
             let device = swapchain.device.as_ref();
@@ -2066,6 +5421,9 @@ impl Image {
                         },
                         width: swapchain.width(),
                         height: swapchain.height(),
+                        mip_levels: 1,
+                        array_layers: 1,
+                        cube: false,
                         layout: std::sync::atomic::AtomicI32::new(
                             vk::ImageLayout::UNDEFINED.as_raw(),
                         ),
@@ -2113,7 +5471,7 @@ impl Image {
             }
             false => vk::ImageLayout::UNDEFINED,
         };
-        cmd_set_image_layout(old_layout, command_buffer, self.handle, layout);
+        cmd_set_image_layout(old_layout, command_buffer, self, layout);
         self.layout
             .store(layout.as_raw(), std::sync::atomic::Ordering::SeqCst);
     }
@@ -2125,6 +5483,23 @@ impl Image {
     pub fn height(&self) -> u32 {
         self.height
     }
+
+    pub fn mip_levels(&self) -> u32 {
+        self.mip_levels
+    }
+
+    pub fn array_layers(&self) -> u32 {
+        self.array_layers
+    }
+
+    pub fn format(&self) -> vk::Format {
+        self.format
+    }
+
+    /// Whether the format has a depth or stencil aspect, e.g. to tell depth attachments apart.
+    pub fn is_depth_stencil(&self) -> bool {
+        !aspect_mask(self.format).contains(vk::ImageAspectFlags::COLOR)
+    }
 }
 
 impl Drop for Image {
@@ -2135,6 +5510,7 @@ impl Drop for Image {
                 allocation,
                 ..
             } => {
+                allocator.untrack(vk::ObjectType::IMAGE, self.handle.as_raw());
                 allocator.handle.destroy_image(self.handle, &allocation);
             }
             ImageType::Swapchain { .. } => {}
@@ -2149,6 +5525,37 @@ pub struct ImageView {
 
 impl ImageView {
     pub fn new(image: Arc<Image>) -> Self {
+        let aspect_mask = aspect_mask(image.format);
+        Self::with_aspect(image, aspect_mask)
+    }
+
+    /// A view of some aspects of the image only, e.g. the depth of a depth/stencil image to
+    /// sample it.
+    pub fn with_aspect(image: Arc<Image>, aspect_mask: vk::ImageAspectFlags) -> Self {
+        let view_type = match (image.cube, image.array_layers) {
+            (true, 6) => vk::ImageViewType::CUBE,
+            (true, _) => vk::ImageViewType::CUBE_ARRAY,
+            (false, 1) => vk::ImageViewType::TYPE_2D,
+            (false, _) => vk::ImageViewType::TYPE_2D_ARRAY,
+        };
+        let layer_count = image.array_layers;
+        Self::with_layers(image, view_type, aspect_mask, 0, layer_count)
+    }
+
+    /// A 2D view of layer `layer` only, e.g. to render to a face of a cube.
+    pub fn layer(image: Arc<Image>, layer: u32) -> Self {
+        assert!(layer < image.array_layers);
+        let aspect_mask = aspect_mask(image.format);
+        Self::with_layers(image, vk::ImageViewType::TYPE_2D, aspect_mask, layer, 1)
+    }
+
+    fn with_layers(
+        image: Arc<Image>,
+        view_type: vk::ImageViewType,
+        aspect_mask: vk::ImageAspectFlags,
+        base_layer: u32,
+        layer_count: u32,
+    ) -> Self {
         unsafe {
             let device = match &image.image_type {
                 ImageType::Allocated { allocator, .. } => &allocator.device,
@@ -2166,15 +5573,15 @@ impl ImageView {
                                 .a(vk::ComponentSwizzle::IDENTITY)
                                 .build(),
                         )
-                        .view_type(vk::ImageViewType::TYPE_2D)
+                        .view_type(view_type)
                         .format(image.format)
                         .subresource_range(
                             vk::ImageSubresourceRange::builder()
-                                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                                .aspect_mask(aspect_mask)
                                 .base_mip_level(0)
-                                .level_count(1)
-                                .base_array_layer(0)
-                                .layer_count(1)
+                                .level_count(image.mip_levels)
+                                .base_array_layer(base_layer)
+                                .layer_count(layer_count)
                                 .build(),
                         )
                         .image(image.handle)
@@ -2203,39 +5610,30 @@ impl Drop for ImageView {
     }
 }
 
+/// The aspects of an image of `format`, so that depth attachments get depth views.
+fn aspect_mask(format: vk::Format) -> vk::ImageAspectFlags {
+    match format {
+        vk::Format::D16_UNORM | vk::Format::X8_D24_UNORM_PACK32 | vk::Format::D32_SFLOAT => {
+            vk::ImageAspectFlags::DEPTH
+        }
+        vk::Format::D16_UNORM_S8_UINT
+        | vk::Format::D24_UNORM_S8_UINT
+        | vk::Format::D32_SFLOAT_S8_UINT => {
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        }
+        vk::Format::S8_UINT => vk::ImageAspectFlags::STENCIL,
+        _ => vk::ImageAspectFlags::COLOR,
+    }
+}
+
 fn cmd_set_image_layout(
     old_layout: vk::ImageLayout,
     command_buffer: &CommandBuffer,
-    image: vk::Image,
+    image: &Image,
     new_layout: vk::ImageLayout,
 ) {
-    use vk::AccessFlags;
-    use vk::ImageLayout;
-
     let device = &command_buffer.pool.device.handle;
     unsafe {
-        let src_access_mask = match old_layout {
-            ImageLayout::UNDEFINED => AccessFlags::default(),
-            ImageLayout::GENERAL => AccessFlags::default(),
-            ImageLayout::COLOR_ATTACHMENT_OPTIMAL => AccessFlags::COLOR_ATTACHMENT_WRITE,
-            ImageLayout::TRANSFER_DST_OPTIMAL => AccessFlags::TRANSFER_WRITE,
-            ImageLayout::TRANSFER_SRC_OPTIMAL => AccessFlags::TRANSFER_READ,
-            ImageLayout::PRESENT_SRC_KHR => AccessFlags::COLOR_ATTACHMENT_READ,
-            _ => {
-                unimplemented!("unknown old layout {:?}", old_layout);
-            }
-        };
-        let dst_access_mask = match new_layout {
-            ImageLayout::COLOR_ATTACHMENT_OPTIMAL => AccessFlags::COLOR_ATTACHMENT_WRITE,
-            ImageLayout::GENERAL => AccessFlags::default(),
-            ImageLayout::TRANSFER_SRC_OPTIMAL => AccessFlags::TRANSFER_READ,
-            ImageLayout::TRANSFER_DST_OPTIMAL => AccessFlags::TRANSFER_WRITE,
-            ImageLayout::PRESENT_SRC_KHR => AccessFlags::COLOR_ATTACHMENT_READ,
-            ImageLayout::SHADER_READ_ONLY_OPTIMAL => AccessFlags::SHADER_READ,
-            _ => {
-                unimplemented!("unknown new layout {:?}", new_layout);
-            }
-        };
         device.cmd_pipeline_barrier(
             command_buffer.handle,
             vk::PipelineStageFlags::ALL_COMMANDS,
@@ -2244,25 +5642,58 @@ fn cmd_set_image_layout(
             &[],
             &[],
             &[vk::ImageMemoryBarrier::builder()
-                .image(image)
+                .image(image.handle)
                 .old_layout(old_layout)
                 .new_layout(new_layout)
-                .src_access_mask(src_access_mask)
-                .dst_access_mask(dst_access_mask)
-                .subresource_range(
-                    vk::ImageSubresourceRange::builder()
-                        .aspect_mask(vk::ImageAspectFlags::COLOR)
-                        .base_mip_level(0)
-                        .level_count(1)
-                        .base_array_layer(0)
-                        .layer_count(1)
-                        .build(),
-                )
+                .src_access_mask(layout_usage(old_layout).1 & WRITE_ACCESS)
+                .dst_access_mask(layout_usage(new_layout).1)
+                .subresource_range(image.subresource_range())
                 .build()],
         );
     }
 }
 
+/// The stages and accesses that use an image in `layout`, for transitions that don't know
+/// better. Every stage and access for layouts of any use, like `GENERAL`.
+fn layout_usage(layout: vk::ImageLayout) -> (vk::PipelineStageFlags, vk::AccessFlags) {
+    use vk::{AccessFlags, ImageLayout, PipelineStageFlags};
+
+    match layout {
+        ImageLayout::UNDEFINED | ImageLayout::PREINITIALIZED => {
+            (PipelineStageFlags::TOP_OF_PIPE, AccessFlags::empty())
+        }
+        ImageLayout::TRANSFER_SRC_OPTIMAL => {
+            (PipelineStageFlags::TRANSFER, AccessFlags::TRANSFER_READ)
+        }
+        ImageLayout::TRANSFER_DST_OPTIMAL => {
+            (PipelineStageFlags::TRANSFER, AccessFlags::TRANSFER_WRITE)
+        }
+        ImageLayout::COLOR_ATTACHMENT_OPTIMAL => (
+            PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            AccessFlags::COLOR_ATTACHMENT_READ | AccessFlags::COLOR_ATTACHMENT_WRITE,
+        ),
+        ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL => (
+            PipelineStageFlags::EARLY_FRAGMENT_TESTS | PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                | AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        ),
+        ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL => (
+            PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                | PipelineStageFlags::LATE_FRAGMENT_TESTS
+                | PipelineStageFlags::ALL_COMMANDS,
+            AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | AccessFlags::SHADER_READ,
+        ),
+        ImageLayout::SHADER_READ_ONLY_OPTIMAL => {
+            (PipelineStageFlags::ALL_COMMANDS, AccessFlags::SHADER_READ)
+        }
+        ImageLayout::PRESENT_SRC_KHR => (PipelineStageFlags::BOTTOM_OF_PIPE, AccessFlags::empty()),
+        _ => (
+            PipelineStageFlags::ALL_COMMANDS,
+            AccessFlags::MEMORY_READ | AccessFlags::MEMORY_WRITE,
+        ),
+    }
+}
+
 pub struct Framebuffer {
     handle: vk::Framebuffer,
     render_pass: Arc<RenderPass>,
@@ -2324,13 +5755,24 @@ impl Drop for Framebuffer {
 pub struct RenderPass {
     handle: vk::RenderPass,
     device: Arc<Device>,
+    initial_layouts: Vec<vk::ImageLayout>,
+    final_layouts: Vec<vk::ImageLayout>,
 }
 
 impl RenderPass {
     pub fn new(device: Arc<Device>, info: &vk::RenderPassCreateInfo) -> Self {
         unsafe {
             let handle = device.handle.create_render_pass(&info, None).unwrap();
-            Self { handle, device }
+            let attachments = match info.attachment_count {
+                0 => &[][..],
+                count => std::slice::from_raw_parts(info.p_attachments, count as usize),
+            };
+            Self {
+                handle,
+                device,
+                initial_layouts: attachments.iter().map(|a| a.initial_layout).collect(),
+                final_layouts: attachments.iter().map(|a| a.final_layout).collect(),
+            }
         }
     }
 
@@ -2352,6 +5794,9 @@ pub enum DescriptorType {
     Sampler(Option<Arc<Sampler>>),
     SampledImage,
     UniformBuffer,
+    /// A uniform buffer read at an offset given when binding the set, see
+    /// `PipelineRecorder::bind_descriptor_sets_with_offsets` and `DynamicUniformRing`.
+    UniformBufferDynamic,
     StorageBuffer,
     AccelerationStructure,
     StorageImage,
@@ -2415,6 +5860,14 @@ impl DescriptorSetLayout {
                             .stage_flags(binding.stage_flags)
                             .build()
                     }
+                    DescriptorType::UniformBufferDynamic => {
+                        vk::DescriptorSetLayoutBinding::builder()
+                            .binding(binding.binding)
+                            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC)
+                            .descriptor_count(1)
+                            .stage_flags(binding.stage_flags)
+                            .build()
+                    }
                     DescriptorType::StorageBuffer => {
                         vk::DescriptorSetLayoutBinding::builder()
                             .binding(binding.binding)
@@ -2489,6 +5942,8 @@ impl Drop for DescriptorSetLayout {
 pub struct PipelineLayout {
     handle: vk::PipelineLayout,
     device: Arc<Device>,
+    /// The set layouts `from_shaders` created, empty for layouts made with `new`.
+    set_layouts: Vec<Arc<DescriptorSetLayout>>,
 }
 
 impl PipelineLayout {
@@ -2523,8 +5978,82 @@ impl PipelineLayout {
                     )
                     .unwrap();
             }
-            Self { handle, device }
+            Self {
+                handle,
+                device,
+                set_layouts: Vec::new(),
+            }
+        }
+    }
+
+    /// Creates the layout of a pipeline of `stages` from the descriptors and push constants they
+    /// declare, with a set layout for every set up to the last one used, empty for the gaps.
+    /// Bindings several stages declare are visible to all of them, and must agree on their type.
+    pub fn from_shaders(
+        device: Arc<Device>,
+        name: Option<&str>,
+        stages: &[&ShaderStage],
+    ) -> Result<Self, Error> {
+        let mut sets: Vec<Vec<DescriptorSetLayoutBinding>> = Vec::new();
+        let mut push_constant_range = vk::PushConstantRange::default();
+        for stage in stages {
+            let reflection = stage.module.reflect()?;
+            for reflected in reflection.bindings {
+                let set = reflected.set as usize;
+                if sets.len() <= set {
+                    sets.resize(set + 1, Vec::new());
+                }
+                match sets[set]
+                    .iter_mut()
+                    .find(|b| b.binding == reflected.binding)
+                {
+                    Some(binding) => {
+                        if std::mem::discriminant(&binding.descriptor_type)
+                            != std::mem::discriminant(&reflected.descriptor_type)
+                        {
+                            return Err(Error::InvalidData(format!(
+                                "stages disagree on the type of binding {} of set {}",
+                                reflected.binding, reflected.set
+                            )));
+                        }
+                        binding.stage_flags |= stage.stage;
+                    }
+                    None => sets[set].push(DescriptorSetLayoutBinding {
+                        binding: reflected.binding,
+                        descriptor_type: reflected.descriptor_type,
+                        stage_flags: stage.stage,
+                    }),
+                }
+            }
+            if reflection.push_constant_size > 0 {
+                push_constant_range.stage_flags |= stage.stage;
+                push_constant_range.size =
+                    push_constant_range.size.max(reflection.push_constant_size);
+            }
         }
+
+        let set_layouts = sets
+            .iter()
+            .map(|bindings| Arc::new(DescriptorSetLayout::new(device.clone(), name, bindings)))
+            .collect::<Vec<_>>();
+        let push_constant_ranges = if push_constant_range.size > 0 {
+            vec![push_constant_range]
+        } else {
+            Vec::new()
+        };
+        let mut layout = Self::new(
+            device,
+            name,
+            &set_layouts.iter().map(|l| l.as_ref()).collect::<Vec<_>>(),
+            &push_constant_ranges,
+        );
+        layout.set_layouts = set_layouts;
+        Ok(layout)
+    }
+
+    /// The set layouts `from_shaders` created, to allocate the sets of the pipeline from.
+    pub fn set_layouts(&self) -> &[Arc<DescriptorSetLayout>] {
+        &self.set_layouts
     }
 }
 
@@ -2546,7 +6075,8 @@ pub struct GraphicsPipeline {
     handle: vk::Pipeline,
     layout: Arc<PipelineLayout>,
     stages: Vec<Arc<ShaderStage>>,
-    render_pass: Arc<RenderPass>,
+    /// `None` for pipelines of `with_formats`.
+    render_pass: Option<Arc<RenderPass>>,
 }
 
 impl GraphicsPipeline {
@@ -2564,12 +6094,159 @@ impl GraphicsPipeline {
         viewport_state: &vk::PipelineViewportStateCreateInfo,
         dynamic_state: &vk::PipelineDynamicStateCreateInfo,
     ) -> Self {
+        Self::try_new(
+            name,
+            layout,
+            stages,
+            render_pass,
+            vertex_input_state,
+            input_assembly_state,
+            rasterization_state,
+            multisample_state,
+            depth_stencil_state,
+            color_blend_state,
+            viewport_state,
+            dynamic_state,
+        )
+        .unwrap()
+    }
+
+    pub fn try_new(
+        name: Option<&str>,
+        layout: Arc<PipelineLayout>,
+        stages: Vec<Arc<ShaderStage>>,
+        render_pass: Arc<RenderPass>,
+        vertex_input_state: &vk::PipelineVertexInputStateCreateInfo,
+        input_assembly_state: &vk::PipelineInputAssemblyStateCreateInfo,
+        rasterization_state: &vk::PipelineRasterizationStateCreateInfo,
+        multisample_state: &vk::PipelineMultisampleStateCreateInfo,
+        depth_stencil_state: &vk::PipelineDepthStencilStateCreateInfo,
+        color_blend_state: &vk::PipelineColorBlendStateCreateInfo,
+        viewport_state: &vk::PipelineViewportStateCreateInfo,
+        dynamic_state: &vk::PipelineDynamicStateCreateInfo,
+    ) -> Result<Self, Error> {
+        Self::create(
+            name,
+            layout,
+            stages,
+            Some(render_pass),
+            None,
+            vertex_input_state,
+            input_assembly_state,
+            rasterization_state,
+            multisample_state,
+            depth_stencil_state,
+            color_blend_state,
+            viewport_state,
+            dynamic_state,
+        )
+    }
+
+    /// Creates a pipeline for `CommandRecorder::begin_rendering` to attachments of
+    /// `color_formats` and `depth_format`, instead of for a subpass of a render pass. The stencil
+    /// format is the depth format if it has stencil.
+    pub fn with_formats(
+        name: Option<&str>,
+        layout: Arc<PipelineLayout>,
+        stages: Vec<Arc<ShaderStage>>,
+        color_formats: &[vk::Format],
+        depth_format: Option<vk::Format>,
+        vertex_input_state: &vk::PipelineVertexInputStateCreateInfo,
+        input_assembly_state: &vk::PipelineInputAssemblyStateCreateInfo,
+        rasterization_state: &vk::PipelineRasterizationStateCreateInfo,
+        multisample_state: &vk::PipelineMultisampleStateCreateInfo,
+        depth_stencil_state: &vk::PipelineDepthStencilStateCreateInfo,
+        color_blend_state: &vk::PipelineColorBlendStateCreateInfo,
+        viewport_state: &vk::PipelineViewportStateCreateInfo,
+        dynamic_state: &vk::PipelineDynamicStateCreateInfo,
+    ) -> Self {
+        Self::try_with_formats(
+            name,
+            layout,
+            stages,
+            color_formats,
+            depth_format,
+            vertex_input_state,
+            input_assembly_state,
+            rasterization_state,
+            multisample_state,
+            depth_stencil_state,
+            color_blend_state,
+            viewport_state,
+            dynamic_state,
+        )
+        .unwrap()
+    }
+
+    /// Fails with `Error::Unsupported` if the device wasn't created with `KhrDynamicRendering`.
+    pub fn try_with_formats(
+        name: Option<&str>,
+        layout: Arc<PipelineLayout>,
+        stages: Vec<Arc<ShaderStage>>,
+        color_formats: &[vk::Format],
+        depth_format: Option<vk::Format>,
+        vertex_input_state: &vk::PipelineVertexInputStateCreateInfo,
+        input_assembly_state: &vk::PipelineInputAssemblyStateCreateInfo,
+        rasterization_state: &vk::PipelineRasterizationStateCreateInfo,
+        multisample_state: &vk::PipelineMultisampleStateCreateInfo,
+        depth_stencil_state: &vk::PipelineDepthStencilStateCreateInfo,
+        color_blend_state: &vk::PipelineColorBlendStateCreateInfo,
+        viewport_state: &vk::PipelineViewportStateCreateInfo,
+        dynamic_state: &vk::PipelineDynamicStateCreateInfo,
+    ) -> Result<Self, Error> {
+        if layout.device.dynamic_rendering_fn.is_none() {
+            return Err(Error::Unsupported("VK_KHR_dynamic_rendering".to_owned()));
+        }
+        let depth_format = depth_format.unwrap_or(vk::Format::UNDEFINED);
+        let stencil_format = if aspect_mask(depth_format).contains(vk::ImageAspectFlags::STENCIL) {
+            depth_format
+        } else {
+            vk::Format::UNDEFINED
+        };
+        let mut rendering_info = dynamic_rendering::PipelineRenderingCreateInfo::new(
+            color_formats,
+            depth_format,
+            stencil_format,
+        );
+        Self::create(
+            name,
+            layout,
+            stages,
+            None,
+            Some(&mut rendering_info),
+            vertex_input_state,
+            input_assembly_state,
+            rasterization_state,
+            multisample_state,
+            depth_stencil_state,
+            color_blend_state,
+            viewport_state,
+            dynamic_state,
+        )
+    }
+
+    /// With either a render pass or the formats of dynamic rendering.
+    fn create(
+        name: Option<&str>,
+        layout: Arc<PipelineLayout>,
+        stages: Vec<Arc<ShaderStage>>,
+        render_pass: Option<Arc<RenderPass>>,
+        rendering_info: Option<&mut dynamic_rendering::PipelineRenderingCreateInfo>,
+        vertex_input_state: &vk::PipelineVertexInputStateCreateInfo,
+        input_assembly_state: &vk::PipelineInputAssemblyStateCreateInfo,
+        rasterization_state: &vk::PipelineRasterizationStateCreateInfo,
+        multisample_state: &vk::PipelineMultisampleStateCreateInfo,
+        depth_stencil_state: &vk::PipelineDepthStencilStateCreateInfo,
+        color_blend_state: &vk::PipelineColorBlendStateCreateInfo,
+        viewport_state: &vk::PipelineViewportStateCreateInfo,
+        dynamic_state: &vk::PipelineDynamicStateCreateInfo,
+    ) -> Result<Self, Error> {
         let device = &layout.device;
         let stage_create_infos = stages
             .iter()
             .map(|s| s.shader_stage_create_info())
             .collect::<Vec<_>>();
-        let info = vk::GraphicsPipelineCreateInfo::builder()
+        let mut info = vk::GraphicsPipelineCreateInfo::builder()
             .layout(layout.handle)
             .stages(&stage_create_infos)
             .vertex_input_state(vertex_input_state)
@@ -2579,14 +6256,19 @@ impl GraphicsPipeline {
             .depth_stencil_state(depth_stencil_state)
             .color_blend_state(color_blend_state)
             .viewport_state(viewport_state)
-            .dynamic_state(dynamic_state)
-            .render_pass(render_pass.handle)
-            .build();
+            .dynamic_state(dynamic_state);
+        if let Some(render_pass) = &render_pass {
+            info = info.render_pass(render_pass.handle);
+        }
+        if let Some(rendering_info) = rendering_info {
+            info = info.push_next(rendering_info);
+        }
+        let info = info.build();
         unsafe {
             let handle = device
                 .handle
                 .create_graphics_pipelines(vk::PipelineCache::null(), &[info], None)
-                .unwrap()
+                .map_err(|(_, result)| result)?
                 .first()
                 .unwrap()
                 .to_owned();
@@ -2602,15 +6284,14 @@ impl GraphicsPipeline {
                             .object_type(vk::ObjectType::PIPELINE)
                             .object_name(CString::new(name).unwrap().as_ref())
                             .build(),
-                    )
-                    .unwrap();
+                    )?;
             }
-            Self {
+            Ok(Self {
                 handle,
                 layout,
                 stages,
                 render_pass,
-            }
+            })
         }
     }
 }
@@ -2640,6 +6321,14 @@ pub struct ComputePipeline {
 
 impl ComputePipeline {
     pub fn new(name: Option<&str>, layout: Arc<PipelineLayout>, stage: Arc<ShaderStage>) -> Self {
+        Self::try_new(name, layout, stage).unwrap()
+    }
+
+    pub fn try_new(
+        name: Option<&str>,
+        layout: Arc<PipelineLayout>,
+        stage: Arc<ShaderStage>,
+    ) -> Result<Self, Error> {
         unsafe {
             let device = layout.device.as_ref();
             let handle = device
@@ -2652,7 +6341,7 @@ impl ComputePipeline {
                         .build()],
                     None,
                 )
-                .unwrap()
+                .map_err(|(_, result)| result)?
                 .first()
                 .unwrap()
                 .to_owned();
@@ -2669,15 +6358,14 @@ impl ComputePipeline {
                             .object_type(vk::ObjectType::PIPELINE)
                             .object_name(CString::new(name).unwrap().as_ref())
                             .build(),
-                    )
-                    .unwrap();
+                    )?;
             }
 
-            Self {
+            Ok(Self {
                 handle,
                 layout,
                 stage,
-            }
+            })
         }
     }
 }
@@ -2716,6 +6404,17 @@ impl RayTracingPipeline {
         recursion_depth: u32,
         queue: &mut Queue,
     ) -> Self {
+        Self::try_new(name, allocator, layout, stages, recursion_depth, queue).unwrap()
+    }
+
+    pub fn try_new(
+        name: Option<&str>,
+        allocator: Arc<Allocator>,
+        layout: Arc<PipelineLayout>,
+        stages: Vec<Arc<ShaderStage>>,
+        recursion_depth: u32,
+        queue: &mut Queue,
+    ) -> Result<Self, Error> {
         let device = &layout.device;
         let stage_create_infos = stages
             .iter()
@@ -2772,8 +6471,7 @@ impl RayTracingPipeline {
                         .max_pipeline_ray_recursion_depth(recursion_depth)
                         .build()],
                     None,
-                )
-                .unwrap()
+                )?
                 .first()
                 .unwrap()
                 .to_owned();
@@ -2790,8 +6488,7 @@ impl RayTracingPipeline {
                             .object_type(vk::ObjectType::PIPELINE)
                             .object_name(CString::new(name).unwrap().as_ref())
                             .build(),
-                    )
-                    .unwrap();
+                    )?;
             }
 
             let rt_p = &device.pdevice.ray_tracing_pipeline_properties;
@@ -2803,8 +6500,7 @@ impl RayTracingPipeline {
                     0,
                     group_create_infos.len() as u32,
                     rt_p.shader_group_handle_size as usize * group_create_infos.len(),
-                )
-                .unwrap();
+                )?;
             assert!(rt_p.shader_group_base_alignment % rt_p.shader_group_handle_alignment == 0);
             let sbt_stride = rt_p.shader_group_base_alignment
                 * ((rt_p.shader_group_handle_size + rt_p.shader_group_base_alignment - 1)
@@ -2836,13 +6532,13 @@ impl RayTracingPipeline {
                 temp,
             );
 
-            Self {
+            Ok(Self {
                 handle,
                 layout,
                 stages,
                 sbt_buffer,
                 sbt_stride,
-            }
+            })
         }
     }
 
@@ -2875,11 +6571,8 @@ impl Pipeline for RayTracingPipeline {
 pub struct ShaderModule {
     handle: vk::ShaderModule,
     device: Arc<Device>,
-}
-
-#[repr(C, align(32))]
-struct AlignedSpirv {
-    pub code: Vec<u8>,
+    /// The SPIR-V words, kept for `reflect`.
+    code: Vec<u32>,
 }
 
 impl ShaderModule {
@@ -2887,17 +6580,63 @@ impl ShaderModule {
     where
         P: AsRef<[u8]>,
     {
-        let aligned = AlignedSpirv {
-            code: spv.as_ref().to_vec(),
-        };
-        let info = vk::ShaderModuleCreateInfo::builder()
-            .code(bytemuck::cast_slice(aligned.code.as_slice()))
-            .build();
+        Self::try_new(device, spv).unwrap()
+    }
+
+    pub fn try_new<P>(device: Arc<Device>, spv: P) -> Result<Self, Error>
+    where
+        P: AsRef<[u8]>,
+    {
+        let spv = spv.as_ref();
+        if spv.len() % 4 != 0 {
+            return Err(Error::InvalidData(
+                "SPIR-V not a whole number of words".to_owned(),
+            ));
+        }
+        let code = spv
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+            .collect::<Vec<_>>();
+        let info = vk::ShaderModuleCreateInfo::builder().code(&code).build();
         unsafe {
-            let handle = device.handle.create_shader_module(&info, None).unwrap();
-            Self { handle, device }
+            let handle = device.handle.create_shader_module(&info, None)?;
+            Ok(Self {
+                handle,
+                device,
+                code,
+            })
         }
     }
+
+    /// Compiles GLSL `source` of `stage` at runtime, like the build scripts compile the shaders
+    /// of a crate. `defines` are set as by `#define name value`, `#include <file>` isn't
+    /// resolved. The entry point is `main`.
+    pub fn from_glsl(
+        device: Arc<Device>,
+        source: &str,
+        stage: vk::ShaderStageFlags,
+        defines: &[(&str, Option<&str>)],
+    ) -> Result<Self, Error> {
+        let kind = match stage {
+            vk::ShaderStageFlags::VERTEX => shader_compiler::ShaderKind::Vertex,
+            vk::ShaderStageFlags::FRAGMENT => shader_compiler::ShaderKind::Fragment,
+            vk::ShaderStageFlags::COMPUTE => shader_compiler::ShaderKind::Compute,
+            vk::ShaderStageFlags::RAYGEN_KHR => shader_compiler::ShaderKind::RayGeneration,
+            vk::ShaderStageFlags::CLOSEST_HIT_KHR => shader_compiler::ShaderKind::ClosestHit,
+            vk::ShaderStageFlags::MISS_KHR => shader_compiler::ShaderKind::Miss,
+            stage => return Err(Error::Unsupported(format!("GLSL of stage {:?}", stage))),
+        };
+        let compiled = shader_compiler::ShaderCompiler::new()
+            .and_then(|mut compiler| compiler.compile_source(source, "shader", kind, defines))
+            .map_err(|e| Error::Compilation(e.to_string()))?;
+        Self::try_new(device, compiled.spirv)
+    }
+
+    /// Reads the descriptors, push constants and vertex inputs the module declares. Fails with
+    /// `Error::Unsupported` for descriptors `DescriptorType` has no variant for, like arrays.
+    pub fn reflect(&self) -> Result<ShaderReflection, Error> {
+        reflect::reflect(&self.code)
+    }
 }
 
 impl Drop for ShaderModule {
@@ -2908,6 +6647,146 @@ impl Drop for ShaderModule {
     }
 }
 
+/// Seconds between two scans of a watched directory.
+const SHADER_POLL_INTERVAL: f64 = 0.5;
+
+/// The shaders a `ShaderWatcher` compiled after a change.
+#[derive(Debug, Default)]
+pub struct ShaderReload {
+    /// Names of the SPIR-V files written, like `raytrace.rgen.spv`.
+    pub compiled: Vec<String>,
+    /// Compiler output of the shaders that failed, their SPIR-V files are left as they were.
+    pub errors: Vec<String>,
+}
+
+/// Watches a directory of GLSL shaders and compiles those that change into its `bin` directory,
+/// like the build scripts do. A shader is recompiled when it or a file it includes changes, in
+/// the directory or in an include directory.
+///
+/// It only writes the SPIR-V, rebuilding the pipelines that use it is up to the caller.
+pub struct ShaderWatcher {
+    directory: std::path::PathBuf,
+    /// Scanned for changes to includes, the shaders in them aren't compiled.
+    include_dirs: Vec<std::path::PathBuf>,
+    /// Modification time of every file of `directory` and the include directories at the last
+    /// scan.
+    modified: HashMap<std::path::PathBuf, std::time::SystemTime>,
+    /// The files each shader was last compiled from. Unknown before its first reload, any change
+    /// to a file that isn't a shader recompiles it then.
+    dependencies: HashMap<std::path::PathBuf, Vec<std::path::PathBuf>>,
+    last_poll: std::time::Instant,
+    compiler: shader_compiler::ShaderCompiler,
+}
+
+impl ShaderWatcher {
+    pub fn new<P: Into<std::path::PathBuf>>(directory: P) -> Result<Self> {
+        let directory = directory.into();
+        let modified = scan_directory(&directory)?;
+        Ok(Self {
+            directory,
+            include_dirs: Vec::new(),
+            modified,
+            dependencies: HashMap::new(),
+            last_poll: std::time::Instant::now(),
+            compiler: shader_compiler::ShaderCompiler::new()?,
+        })
+    }
+
+    /// Resolves includes in `dir` too, like the build script given it, see
+    /// `shader_compiler::build_with_include_dirs`.
+    pub fn add_include_dir<P: Into<std::path::PathBuf>>(&mut self, dir: P) -> Result<()> {
+        let dir = dir.into();
+        self.modified.extend(scan_directory(&dir)?);
+        self.compiler.add_include_dir(dir.clone());
+        self.include_dirs.push(dir);
+        Ok(())
+    }
+
+    /// Compiles the shaders changed since the last call, `None` if there are none. Cheap to call
+    /// every frame, the directory is scanned at most every `SHADER_POLL_INTERVAL` seconds.
+    pub fn poll(&mut self) -> Option<ShaderReload> {
+        if self.last_poll.elapsed().as_secs_f64() < SHADER_POLL_INTERVAL {
+            return None;
+        }
+        self.last_poll = std::time::Instant::now();
+
+        let mut modified = HashMap::new();
+        for directory in std::iter::once(&self.directory).chain(self.include_dirs.iter()) {
+            match scan_directory(directory) {
+                Ok(files) => modified.extend(files),
+                Err(e) => {
+                    log::warn!("failed to scan {}: {}", directory.display(), e);
+                    return None;
+                }
+            }
+        }
+        let changed: Vec<_> = modified
+            .iter()
+            .filter(|(path, time)| self.modified.get(*path) != Some(time))
+            .map(|(path, _)| path.clone())
+            .collect();
+        self.modified = modified;
+
+        let dependencies = &self.dependencies;
+        let directory = &self.directory;
+        let mut sources: Vec<_> = self
+            .modified
+            .keys()
+            .filter(|path| path.parent() == Some(directory.as_path()))
+            .filter(|path| shader_compiler::shader_kind(path).is_some())
+            .filter(|source| match dependencies.get(*source) {
+                Some(dependencies) => dependencies.iter().any(|path| changed.contains(path)),
+                None => changed
+                    .iter()
+                    .any(|path| path == *source || shader_compiler::shader_kind(path).is_none()),
+            })
+            .cloned()
+            .collect();
+        if sources.is_empty() {
+            return None;
+        }
+        sources.sort();
+
+        let mut reload = ShaderReload::default();
+        for source in sources {
+            match self.compile(&source) {
+                Ok(name) => reload.compiled.push(name),
+                Err(e) => reload.errors.push(e.to_string()),
+            }
+        }
+        Some(reload)
+    }
+
+    /// Compiles `source` to where the build script puts it, returns the name of the SPIR-V file.
+    fn compile(&mut self, source: &std::path::Path) -> Result<String> {
+        let compiled = self.compiler.compile(source)?;
+        let spirv_path = shader_compiler::spirv_path(source);
+        std::fs::create_dir_all(spirv_path.parent().unwrap())?;
+        std::fs::write(&spirv_path, &compiled.spirv)?;
+        self.dependencies
+            .insert(source.to_owned(), compiled.dependencies);
+        Ok(spirv_path
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .into_owned())
+    }
+}
+
+fn scan_directory(
+    directory: &std::path::Path,
+) -> Result<HashMap<std::path::PathBuf, std::time::SystemTime>> {
+    let mut modified = HashMap::new();
+    for entry in std::fs::read_dir(directory)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_file() {
+            modified.insert(entry.path(), metadata.modified()?);
+        }
+    }
+    Ok(modified)
+}
+
 impl std::fmt::Debug for DescriptorSet {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("DescriptorSet")
@@ -2929,6 +6808,15 @@ impl DescriptorSet {
         descriptor_pool: Arc<DescriptorPool>,
         descriptor_set_layout: Arc<DescriptorSetLayout>,
     ) -> Self {
+        Self::try_new(name, descriptor_pool, descriptor_set_layout).unwrap()
+    }
+
+    /// Fails with `Error::Vulkan` when the pool is out of memory.
+    pub fn try_new(
+        name: Option<&str>,
+        descriptor_pool: Arc<DescriptorPool>,
+        descriptor_set_layout: Arc<DescriptorSetLayout>,
+    ) -> Result<Self, Error> {
         let device = &descriptor_pool.device;
         let info = vk::DescriptorSetAllocateInfo::builder()
             .set_layouts(&[descriptor_set_layout.handle])
@@ -2936,7 +6824,7 @@ impl DescriptorSet {
             .build();
 
         unsafe {
-            let handles = device.handle.allocate_descriptor_sets(&info).unwrap();
+            let handles = device.handle.allocate_descriptor_sets(&info)?;
             assert_eq!(handles.len(), 1);
             let handle = handles.first().unwrap().to_owned();
             if let Some(name) = name {
@@ -2951,16 +6839,15 @@ impl DescriptorSet {
                             .object_type(vk::ObjectType::DESCRIPTOR_SET)
                             .object_name(CString::new(name).unwrap().as_ref())
                             .build(),
-                    )
-                    .unwrap();
+                    )?;
             }
 
-            Self {
+            Ok(Self {
                 handle,
                 descriptor_pool,
                 descriptor_set_layout,
                 resources: RefCell::new(BTreeMap::new()),
-            }
+            })
         }
     }
 
@@ -3005,6 +6892,27 @@ impl DescriptorSet {
                             .buffer_info(&buffer_infos.as_slice()[buffer_infos.len() - 1..])
                             .build()
                     }
+                    DescriptorSetUpdateDetail::BufferRange {
+                        buffer,
+                        offset,
+                        range,
+                    } => {
+                        self.resources
+                            .try_borrow_mut()
+                            .unwrap()
+                            .insert(info.binding, buffer.clone());
+                        buffer_infos.push(
+                            vk::DescriptorBufferInfo::builder()
+                                .buffer(buffer.handle)
+                                .offset(*offset)
+                                .range(*range)
+                                .build(),
+                        );
+
+                        write_builder
+                            .buffer_info(&buffer_infos.as_slice()[buffer_infos.len() - 1..])
+                            .build()
+                    }
                     DescriptorSetUpdateDetail::Image(image_view) => {
                         self.resources
                             .try_borrow_mut()
@@ -3066,6 +6974,13 @@ impl DescriptorSet {
 
 pub enum DescriptorSetUpdateDetail {
     Buffer { buffer: Arc<Buffer>, offset: u64 },
+    /// `range` bytes of `buffer` from `offset`, e.g. for dynamic uniform buffers, whose offset
+    /// is added to that given when binding.
+    BufferRange {
+        buffer: Arc<Buffer>,
+        offset: u64,
+        range: u64,
+    },
     Image(Arc<ImageView>),
     Sampler(Arc<Sampler>),
     AccelerationStructure(Arc<AccelerationStructure>),
@@ -3095,9 +7010,20 @@ pub struct Sampler {
 
 impl Sampler {
     pub fn new(device: Arc<Device>) -> Self {
+        Self::new_with_filter(device, vk::Filter::LINEAR)
+    }
+
+    pub fn new_with_filter(device: Arc<Device>, filter: vk::Filter) -> Self {
+        let mipmap_mode = match filter {
+            vk::Filter::NEAREST => vk::SamplerMipmapMode::NEAREST,
+            _ => vk::SamplerMipmapMode::LINEAR,
+        };
+        // Samples every mip level the image has.
         let info = vk::SamplerCreateInfo::builder()
-            .mag_filter(vk::Filter::LINEAR)
-            .min_filter(vk::Filter::LINEAR)
+            .mag_filter(filter)
+            .min_filter(filter)
+            .mipmap_mode(mipmap_mode)
+            .max_lod(vk::LOD_CLAMP_NONE)
             .build();
         unsafe {
             let handle = device.handle.create_sampler(&info, None).unwrap();
@@ -3145,10 +7071,16 @@ pub struct AccelerationStructure {
     handle: vk::AccelerationStructureKHR,
     as_buffer: Buffer,
     device_address: u64,
+    ty: vk::AccelerationStructureTypeKHR,
+    flags: vk::BuildAccelerationStructureFlagsKHR,
+    /// Scratch space `update` needs, from the sizes queried at build time.
+    update_scratch_size: u64,
+    allocator: Arc<Allocator>,
     device: Arc<Device>,
 }
 
 impl AccelerationStructure {
+    /// Builds an acceleration structure optimized for tracing, waiting for the build.
     pub fn new(
         name: Option<&str>,
         allocator: Arc<Allocator>,
@@ -3156,6 +7088,28 @@ impl AccelerationStructure {
         primitive_counts: &[u32],
         as_type: vk::AccelerationStructureTypeKHR,
     ) -> Self {
+        Self::build(
+            name,
+            allocator,
+            geometries,
+            primitive_counts,
+            as_type,
+            vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE,
+        )
+        .wait()
+    }
+
+    /// Submits the build of an acceleration structure without waiting for it. The geometry data
+    /// must stay alive until the build is done. Pass `ALLOW_UPDATE` in `flags` to `update` it
+    /// later.
+    pub fn build(
+        name: Option<&str>,
+        allocator: Arc<Allocator>,
+        geometries: &[vk::AccelerationStructureGeometryKHR],
+        primitive_counts: &[u32],
+        as_type: vk::AccelerationStructureTypeKHR,
+        flags: vk::BuildAccelerationStructureFlagsKHR,
+    ) -> PendingAccelerationStructure {
         assert_eq!(geometries.len(), primitive_counts.len());
         let device = &allocator.device;
         let mut queue = Queue::new(device.clone());
@@ -3167,7 +7121,7 @@ impl AccelerationStructure {
                 .get_acceleration_structure_build_sizes(
                     vk::AccelerationStructureBuildTypeKHR::DEVICE,
                     &vk::AccelerationStructureBuildGeometryInfoKHR::builder()
-                        .flags(vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE)
+                        .flags(flags)
                         .ty(as_type)
                         .geometries(geometries)
                         .build(),
@@ -3229,7 +7183,7 @@ impl AccelerationStructure {
             );
 
             let build_geometry_info = vk::AccelerationStructureBuildGeometryInfoKHR::builder()
-                .flags(vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE)
+                .flags(flags)
                 .ty(as_type)
                 .geometries(geometries)
                 .dst_acceleration_structure(handle)
@@ -3239,18 +7193,6 @@ impl AccelerationStructure {
                 })
                 .build();
 
-            let build_range_infos = primitive_counts
-                .iter()
-                .map(|count| {
-                    vk::AccelerationStructureBuildRangeInfoKHR::builder()
-                        .first_vertex(0)
-                        .primitive_offset(0)
-                        .transform_offset(0)
-                        .primitive_count(*count)
-                        .build()
-                })
-                .collect::<Vec<_>>();
-
             let device_address = device
                 .acceleration_structure_loader
                 .get_acceleration_structure_device_address(
@@ -3262,6 +7204,10 @@ impl AccelerationStructure {
                 handle,
                 as_buffer,
                 device_address,
+                ty: as_type,
+                flags,
+                update_scratch_size: size_info.update_scratch_size,
+                allocator: allocator.clone(),
                 device,
             };
 
@@ -3269,16 +7215,65 @@ impl AccelerationStructure {
             command_buffer.encode(|recorder| {
                 recorder.build_acceleration_structure_raw(
                     build_geometry_info,
-                    build_range_infos.as_ref(),
+                    build_range_infos(primitive_counts).as_ref(),
                 )
             });
 
-            queue.submit_binary(command_buffer, &[], &[], &[]).wait();
+            let fence = queue.submit_binary(command_buffer, &[], &[], &[]);
 
-            result
+            PendingAccelerationStructure {
+                acceleration_structure: Some(result),
+                fence,
+                _scratch_buffer: scratch_buffer,
+                _queue: queue,
+            }
         }
     }
 
+    /// Refits the acceleration structure in place to moved geometry data, or to changed
+    /// instances of a top level one, waiting for it. The geometries and primitive counts must
+    /// match those it was built with, and it must have been built with `ALLOW_UPDATE`. No
+    /// command buffer may be using it.
+    pub fn update(
+        &self,
+        geometries: &[vk::AccelerationStructureGeometryKHR],
+        primitive_counts: &[u32],
+    ) {
+        assert!(self
+            .flags
+            .contains(vk::BuildAccelerationStructureFlagsKHR::ALLOW_UPDATE));
+        assert_eq!(geometries.len(), primitive_counts.len());
+        let mut queue = Queue::new(self.device.clone());
+        let command_pool = Arc::new(CommandPool::new(self.device.clone()));
+        let scratch_buffer = Buffer::new(
+            Some("acceleration structure update scratch buffer"),
+            self.allocator.clone(),
+            self.update_scratch_size,
+            vk::BufferUsageFlags::ACCELERATION_STRUCTURE_STORAGE_KHR
+                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            vk_mem::MemoryUsage::GpuOnly,
+        );
+        let build_geometry_info = vk::AccelerationStructureBuildGeometryInfoKHR::builder()
+            .flags(self.flags)
+            .ty(self.ty)
+            .geometries(geometries)
+            .src_acceleration_structure(self.handle)
+            .dst_acceleration_structure(self.handle)
+            .mode(vk::BuildAccelerationStructureModeKHR::UPDATE)
+            .scratch_data(vk::DeviceOrHostAddressKHR {
+                device_address: scratch_buffer.device_address(),
+            })
+            .build();
+        let mut command_buffer = CommandBuffer::new(command_pool);
+        command_buffer.encode(|recorder| {
+            recorder.build_acceleration_structure_raw(
+                build_geometry_info,
+                build_range_infos(primitive_counts).as_ref(),
+            )
+        });
+        queue.submit_binary(command_buffer, &[], &[], &[]).wait();
+    }
+
     pub fn device_address(&self) -> u64 {
         self.device_address
     }
@@ -3293,3 +7288,96 @@ impl Drop for AccelerationStructure {
         }
     }
 }
+
+/// Builds the acceleration structure whose build was submitted by `AccelerationStructure::build`,
+/// holding what the build uses until it is done. Dropping it waits for the build.
+pub struct PendingAccelerationStructure {
+    acceleration_structure: Option<AccelerationStructure>,
+    fence: Arc<Fence>,
+    _scratch_buffer: Buffer,
+    /// Holds the command buffer.
+    _queue: Queue,
+}
+
+impl PendingAccelerationStructure {
+    /// Whether the build is done, without blocking.
+    pub fn is_built(&self) -> bool {
+        self.fence.is_signaled()
+    }
+
+    /// Waits for the build, if it isn't done yet.
+    pub fn wait(mut self) -> AccelerationStructure {
+        self.fence.wait();
+        self.acceleration_structure.take().unwrap()
+    }
+}
+
+impl Drop for PendingAccelerationStructure {
+    fn drop(&mut self) {
+        self.fence.wait();
+    }
+}
+
+/// One range of all the primitives of each geometry.
+fn build_range_infos(primitive_counts: &[u32]) -> Vec<vk::AccelerationStructureBuildRangeInfoKHR> {
+    primitive_counts
+        .iter()
+        .map(|count| {
+            vk::AccelerationStructureBuildRangeInfoKHR::builder()
+                .first_vertex(0)
+                .primitive_offset(0)
+                .transform_offset(0)
+                .primitive_count(*count)
+                .build()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alternate_frame_regions() {
+        let split = DeviceGroupSplit::AlternateFrame;
+        for frame_index in 0..6 {
+            let regions = split.regions(3, frame_index, 640, 480);
+            assert_eq!(
+                regions,
+                vec![DeviceRegion {
+                    device_index: (frame_index % 3) as u32,
+                    offset: [0, 0],
+                    extent: [640, 480],
+                }]
+            );
+        }
+        assert_eq!(split.regions(1, 7, 640, 480)[0].device_mask(), 1);
+    }
+
+    #[test]
+    fn test_split_screen_regions() {
+        let split = DeviceGroupSplit::SplitScreen;
+        // 10 rows over 3 devices, the first taking the one left over.
+        let regions = split.regions(3, 0, 64, 10);
+        let bands = regions
+            .iter()
+            .map(|region| (region.device_index, region.offset, region.extent))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            bands,
+            vec![
+                (0, [0, 0], [64, 4]),
+                (1, [0, 4], [64, 3]),
+                (2, [0, 7], [64, 3]),
+            ]
+        );
+        assert_eq!(split.regions(3, 5, 64, 10), regions);
+        assert_eq!(regions[2].device_mask(), 0b100);
+
+        // Fewer rows than devices leave the last devices without a band.
+        let regions = split.regions(4, 0, 64, 2);
+        assert_eq!(regions.len(), 2);
+        assert_eq!(regions[1].offset, [0, 1]);
+        assert_eq!(regions[1].extent, [64, 1]);
+    }
+}
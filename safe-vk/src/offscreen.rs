@@ -0,0 +1,240 @@
+use std::sync::Arc;
+
+use crate::{
+    aspect_mask, vk, Allocator, Buffer, CommandBuffer, CommandPool, Error, Image, MemoryUsage,
+    Queue, TimelineSemaphore,
+};
+
+/// Images frames are rendered to in place of those of a swapchain, to render without a window
+/// or a surface, e.g. batch renders on a machine without a display. Frames cycle through the
+/// images like through swapchain images, and an image can be read back once rendered.
+pub struct OffscreenTarget {
+    allocator: Arc<Allocator>,
+    images: Vec<Arc<Image>>,
+    next_image: usize,
+}
+
+impl OffscreenTarget {
+    /// `image_count` images of `format`, usable as color attachments, storage images and
+    /// transfer sources and destinations.
+    pub fn new(
+        allocator: Arc<Allocator>,
+        format: vk::Format,
+        width: u32,
+        height: u32,
+        image_count: u32,
+    ) -> Self {
+        Self::try_new(allocator, format, width, height, image_count).unwrap()
+    }
+
+    /// Fails with `Error::Allocation` when the memory runs out.
+    pub fn try_new(
+        allocator: Arc<Allocator>,
+        format: vk::Format,
+        width: u32,
+        height: u32,
+        image_count: u32,
+    ) -> Result<Self, Error> {
+        let images = (0..image_count)
+            .map(|index| {
+                Image::try_new(
+                    Some(format!("offscreen image {}", index).as_str()),
+                    allocator.clone(),
+                    format,
+                    width,
+                    height,
+                    vk::ImageTiling::OPTIMAL,
+                    vk::ImageUsageFlags::COLOR_ATTACHMENT
+                        | vk::ImageUsageFlags::STORAGE
+                        | vk::ImageUsageFlags::TRANSFER_SRC
+                        | vk::ImageUsageFlags::TRANSFER_DST,
+                    MemoryUsage::GpuOnly,
+                )
+                .map(Arc::new)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            allocator,
+            images,
+            next_image: 0,
+        })
+    }
+
+    pub fn images(&self) -> &[Arc<Image>] {
+        &self.images
+    }
+
+    pub fn format(&self) -> vk::Format {
+        self.images[0].format()
+    }
+
+    pub fn width(&self) -> u32 {
+        self.images[0].width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.images[0].height()
+    }
+
+    /// The index of the image to render the next frame to, the least recently used. Unlike
+    /// with a swapchain there's no semaphore to wait for, the barriers of the recorders order
+    /// the frames using an image.
+    pub fn acquire_next_image(&mut self) -> u32 {
+        let index = self.next_image;
+        self.next_image = (self.next_image + 1) % self.images.len();
+        index as u32
+    }
+
+    /// Copies image `index` back to the host once the frames rendering to it have finished, see
+    /// `read_back`.
+    pub fn read_back(
+        &self,
+        index: u32,
+        queue: &mut Queue,
+        command_pool: Arc<CommandPool>,
+    ) -> Result<Vec<u8>, Error> {
+        read_back(
+            self.images[index as usize].clone(),
+            self.allocator.clone(),
+            queue,
+            command_pool,
+        )
+    }
+}
+
+/// Copies the first level and layer of `image` back to the host, its texels row after row
+/// without padding. Runs after what `queue` was submitted before and waits for the copy. Fails
+/// with `Error::Unsupported` for block compressed and multi-planar formats.
+pub(crate) fn read_back(
+    image: Arc<Image>,
+    allocator: Arc<Allocator>,
+    queue: &mut Queue,
+    command_pool: Arc<CommandPool>,
+) -> Result<Vec<u8>, Error> {
+    let texel_size = texel_size(image.format())
+        .ok_or_else(|| Error::Unsupported(format!("reading back {:?}", image.format())))?;
+    let size = image.width() as usize * image.height() as usize * texel_size;
+    let readback_buffer = Arc::new(Buffer::try_new(
+        Some("readback buffer"),
+        allocator,
+        size,
+        vk::BufferUsageFlags::TRANSFER_DST,
+        MemoryUsage::GpuToCpu,
+    )?);
+
+    let mut command_buffer = CommandBuffer::new(command_pool);
+    command_buffer.encode(|recorder| {
+        recorder.copy_image_to_buffer(
+            image.clone(),
+            readback_buffer.clone(),
+            &[vk::BufferImageCopy::builder()
+                .image_subresource(
+                    vk::ImageSubresourceLayers::builder()
+                        .aspect_mask(aspect_mask(image.format()))
+                        .layer_count(1)
+                        .build(),
+                )
+                .image_extent(vk::Extent3D {
+                    width: image.width(),
+                    height: image.height(),
+                    depth: 1,
+                })
+                .build()],
+        );
+    });
+    let semaphore = TimelineSemaphore::new(image.device().clone());
+    queue.submit_timeline(
+        command_buffer,
+        &[&semaphore],
+        &[0],
+        &[vk::PipelineStageFlags::ALL_COMMANDS],
+        &[1],
+    );
+    semaphore.wait_for(1);
+
+    let mut bytes = vec![0; size];
+    unsafe {
+        std::ptr::copy_nonoverlapping(readback_buffer.map(), bytes.as_mut_ptr(), size);
+    }
+    readback_buffer.unmap();
+    Ok(bytes)
+}
+
+/// Pixels `Image::read_to_vec` read back, row after row from the top left without padding.
+#[derive(Debug, Clone)]
+pub enum Pixels {
+    /// 4 bytes per pixel, read from 8-bit RGBA and BGRA formats as stored, sRGB encoded or not.
+    Rgba8(Vec<u8>),
+    /// 4 floats per pixel, read from 16 and 32-bit float RGBA formats.
+    Rgba32F(Vec<f32>),
+}
+
+impl Pixels {
+    /// Converts texels of `format` as `read_back` returns them.
+    pub(crate) fn from_texels(format: vk::Format, mut texels: Vec<u8>) -> Result<Self, Error> {
+        match format {
+            vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SRGB => Ok(Pixels::Rgba8(texels)),
+            vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB => {
+                for texel in texels.chunks_exact_mut(4) {
+                    texel.swap(0, 2);
+                }
+                Ok(Pixels::Rgba8(texels))
+            }
+            vk::Format::R16G16B16A16_SFLOAT => Ok(Pixels::Rgba32F(
+                texels
+                    .chunks_exact(2)
+                    .map(|half| f32_from_f16(u16::from_le_bytes([half[0], half[1]])))
+                    .collect(),
+            )),
+            vk::Format::R32G32B32A32_SFLOAT => Ok(Pixels::Rgba32F(
+                texels
+                    .chunks_exact(4)
+                    .map(|float| f32::from_le_bytes([float[0], float[1], float[2], float[3]]))
+                    .collect(),
+            )),
+            format => Err(Error::Unsupported(format!(
+                "converting {:?} to RGBA pixels",
+                format
+            ))),
+        }
+    }
+}
+
+fn f32_from_f16(half: u16) -> f32 {
+    let sign = ((half >> 15) as u32) << 31;
+    let exponent = ((half >> 10) & 0x1f) as u32;
+    let mantissa = (half & 0x3ff) as u32;
+    let bits = match exponent {
+        0 if mantissa == 0 => sign,
+        // Subnormal, normalized by moving the highest set bit of the mantissa to the implicit 1.
+        0 => {
+            let shift = mantissa.leading_zeros() - 8;
+            sign | (134 - mantissa.leading_zeros()) << 23 | (mantissa << shift) & 0x7f_ffff
+        }
+        // Infinity or NaN.
+        0x1f => sign | 0x7f80_0000 | mantissa << 13,
+        _ => sign | (exponent + 112) << 23 | mantissa << 13,
+    };
+    f32::from_bits(bits)
+}
+
+/// Bytes per texel of `format`, for the uncompressed formats images are rendered in.
+pub(crate) fn texel_size(format: vk::Format) -> Option<usize> {
+    match format {
+        vk::Format::R8_UNORM | vk::Format::R8_UINT | vk::Format::S8_UINT => Some(1),
+        vk::Format::R8G8_UNORM | vk::Format::R16_SFLOAT | vk::Format::D16_UNORM => Some(2),
+        vk::Format::R8G8B8A8_UNORM
+        | vk::Format::R8G8B8A8_SRGB
+        | vk::Format::B8G8R8A8_UNORM
+        | vk::Format::B8G8R8A8_SRGB
+        | vk::Format::A2B10G10R10_UNORM_PACK32
+        | vk::Format::B10G11R11_UFLOAT_PACK32
+        | vk::Format::R16G16_SFLOAT
+        | vk::Format::R32_SFLOAT
+        | vk::Format::R32_UINT
+        | vk::Format::D32_SFLOAT => Some(4),
+        vk::Format::R16G16B16A16_SFLOAT | vk::Format::R32G32_SFLOAT => Some(8),
+        vk::Format::R32G32B32A32_SFLOAT => Some(16),
+        _ => None,
+    }
+}
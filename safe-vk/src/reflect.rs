@@ -0,0 +1,514 @@
+use std::collections::HashMap;
+
+use crate::{vk, DescriptorType, Error};
+
+const MAGIC: u32 = 0x0723_0203;
+
+const OP_ENTRY_POINT: u32 = 15;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+const OP_TYPE_ACCELERATION_STRUCTURE: u32 = 5341;
+
+const DECORATION_BLOCK: u32 = 2;
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_BUILT_IN: u32 = 11;
+const DECORATION_LOCATION: u32 = 30;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+const STORAGE_CLASS_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_CLASS_INPUT: u32 = 1;
+const STORAGE_CLASS_UNIFORM: u32 = 2;
+const STORAGE_CLASS_PUSH_CONSTANT: u32 = 9;
+const STORAGE_CLASS_STORAGE_BUFFER: u32 = 12;
+
+const EXECUTION_MODEL_VERTEX: u32 = 0;
+
+/// A descriptor a shader declares.
+#[derive(Clone)]
+pub struct ReflectedBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub descriptor_type: DescriptorType,
+}
+
+/// The resources a shader module declares, read from its SPIR-V by `ShaderModule::reflect`.
+#[derive(Clone, Default)]
+pub struct ShaderReflection {
+    pub bindings: Vec<ReflectedBinding>,
+    /// Bytes of the push constant block up to the end of its last member, 0 without one.
+    pub push_constant_size: u32,
+    /// The locations and formats of the inputs of a vertex shader, in location order.
+    pub vertex_inputs: Vec<(u32, vk::Format)>,
+}
+
+impl ShaderReflection {
+    /// The inputs of the vertex shader read from vertex buffer binding `binding`, packed one
+    /// after the other in location order.
+    pub fn vertex_input(
+        &self,
+        binding: u32,
+    ) -> (
+        vk::VertexInputBindingDescription,
+        Vec<vk::VertexInputAttributeDescription>,
+    ) {
+        let mut offset = 0;
+        let attributes = self
+            .vertex_inputs
+            .iter()
+            .map(|&(location, format)| {
+                let attribute = vk::VertexInputAttributeDescription {
+                    location,
+                    binding,
+                    format,
+                    offset,
+                };
+                offset += format_size(format);
+                attribute
+            })
+            .collect();
+        let binding = vk::VertexInputBindingDescription {
+            binding,
+            stride: offset,
+            input_rate: vk::VertexInputRate::VERTEX,
+        };
+        (binding, attributes)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Type {
+    Int { width: u32, signed: bool },
+    Float { width: u32 },
+    Vector { component: u32, count: u32 },
+    Matrix { column: u32, count: u32 },
+    Image { dim: u32, sampled: u32 },
+    Sampler,
+    SampledImage,
+    AccelerationStructure,
+    Array { element: u32, length: u32 },
+    RuntimeArray,
+    Struct { members: Vec<u32> },
+    Pointer { storage_class: u32, pointee: u32 },
+}
+
+#[derive(Default)]
+struct Module {
+    types: HashMap<u32, Type>,
+    constants: HashMap<u32, u32>,
+    /// The decorations of each id, with their first literal.
+    decorations: HashMap<(u32, u32), u32>,
+    /// The decorations of each struct member, with their first literal.
+    member_decorations: HashMap<(u32, u32, u32), u32>,
+    /// Id, type and storage class of each global variable.
+    variables: Vec<(u32, u32, u32)>,
+    vertex_interface: Vec<u32>,
+}
+
+impl Module {
+    fn parse(code: &[u32]) -> Result<Self, Error> {
+        if code.len() < 5 || code[0] != MAGIC {
+            return Err(Error::InvalidData("not SPIR-V".to_owned()));
+        }
+        let mut module = Self::default();
+        let mut words = &code[5..];
+        while !words.is_empty() {
+            let word_count = (words[0] >> 16) as usize;
+            if word_count == 0 || word_count > words.len() {
+                return Err(Error::InvalidData(
+                    "truncated SPIR-V instruction".to_owned(),
+                ));
+            }
+            let (instruction, rest) = words.split_at(word_count);
+            module.add(instruction[0] & 0xffff, &instruction[1..]);
+            words = rest;
+        }
+        Ok(module)
+    }
+
+    fn add(&mut self, opcode: u32, operands: &[u32]) {
+        let operand = |index: usize| operands.get(index).copied().unwrap_or(0);
+        let id = operand(0);
+        let ty = match opcode {
+            OP_ENTRY_POINT if operand(0) == EXECUTION_MODEL_VERTEX => {
+                // The name is a nul-terminated string of words after the id.
+                let name_words = operands
+                    .get(2..)
+                    .unwrap_or(&[])
+                    .iter()
+                    .position(|word| word.to_le_bytes().contains(&0))
+                    .map_or(operands.len(), |index| index + 3);
+                self.vertex_interface
+                    .extend_from_slice(&operands[name_words.min(operands.len())..]);
+                None
+            }
+            OP_TYPE_INT => Some(Type::Int {
+                width: operand(1),
+                signed: operand(2) != 0,
+            }),
+            OP_TYPE_FLOAT => Some(Type::Float { width: operand(1) }),
+            OP_TYPE_VECTOR => Some(Type::Vector {
+                component: operand(1),
+                count: operand(2),
+            }),
+            OP_TYPE_MATRIX => Some(Type::Matrix {
+                column: operand(1),
+                count: operand(2),
+            }),
+            OP_TYPE_IMAGE => Some(Type::Image {
+                dim: operand(2),
+                sampled: operand(6),
+            }),
+            OP_TYPE_SAMPLER => Some(Type::Sampler),
+            OP_TYPE_SAMPLED_IMAGE => Some(Type::SampledImage),
+            OP_TYPE_ACCELERATION_STRUCTURE => Some(Type::AccelerationStructure),
+            OP_TYPE_ARRAY => Some(Type::Array {
+                element: operand(1),
+                length: operand(2),
+            }),
+            OP_TYPE_RUNTIME_ARRAY => Some(Type::RuntimeArray),
+            OP_TYPE_STRUCT => Some(Type::Struct {
+                members: operands[1..].to_vec(),
+            }),
+            OP_TYPE_POINTER => Some(Type::Pointer {
+                storage_class: operand(1),
+                pointee: operand(2),
+            }),
+            OP_CONSTANT => {
+                self.constants.insert(operand(1), operand(2));
+                None
+            }
+            OP_VARIABLE => {
+                self.variables.push((operand(1), operand(0), operand(2)));
+                None
+            }
+            OP_DECORATE => {
+                self.decorations.insert((id, operand(1)), operand(2));
+                None
+            }
+            OP_MEMBER_DECORATE => {
+                self.member_decorations
+                    .insert((id, operand(1), operand(2)), operand(3));
+                None
+            }
+            _ => None,
+        };
+        if let Some(ty) = ty {
+            self.types.insert(id, ty);
+        }
+    }
+
+    fn ty(&self, id: u32) -> Result<&Type, Error> {
+        self.types
+            .get(&id)
+            .ok_or_else(|| Error::InvalidData(format!("SPIR-V type {} not declared", id)))
+    }
+
+    fn decoration(&self, id: u32, decoration: u32) -> Option<u32> {
+        self.decorations.get(&(id, decoration)).copied()
+    }
+
+    /// The pointee of pointer type `pointer`.
+    fn pointee(&self, pointer: u32) -> Result<u32, Error> {
+        match self.ty(pointer)? {
+            Type::Pointer { pointee, .. } => Ok(*pointee),
+            _ => Err(Error::InvalidData(
+                "variable of a non-pointer type".to_owned(),
+            )),
+        }
+    }
+
+    fn descriptor_type(&self, ty: u32, storage_class: u32) -> Result<DescriptorType, Error> {
+        match (self.ty(ty)?, storage_class) {
+            (Type::Array { .. }, _) | (Type::RuntimeArray, _) => {
+                Err(Error::Unsupported("descriptor arrays".to_owned()))
+            }
+            (Type::Struct { .. }, STORAGE_CLASS_STORAGE_BUFFER) => {
+                Ok(DescriptorType::StorageBuffer)
+            }
+            (Type::Struct { .. }, STORAGE_CLASS_UNIFORM)
+                if self.decoration(ty, DECORATION_BUFFER_BLOCK).is_some() =>
+            {
+                Ok(DescriptorType::StorageBuffer)
+            }
+            (Type::Struct { .. }, STORAGE_CLASS_UNIFORM) => Ok(DescriptorType::UniformBuffer),
+            // Texel buffers have the dimension `Buffer`.
+            (Type::Image { dim, .. }, _) if *dim == 5 => {
+                Err(Error::Unsupported("texel buffers".to_owned()))
+            }
+            (Type::Image { sampled: 2, .. }, _) => Ok(DescriptorType::StorageImage),
+            (Type::Image { .. }, _) => Ok(DescriptorType::SampledImage),
+            (Type::Sampler, _) => Ok(DescriptorType::Sampler(None)),
+            (Type::AccelerationStructure, _) => Ok(DescriptorType::AccelerationStructure),
+            (Type::SampledImage, _) => {
+                Err(Error::Unsupported("combined image samplers".to_owned()))
+            }
+            _ => Err(Error::InvalidData(format!(
+                "SPIR-V type {} is no descriptor",
+                ty
+            ))),
+        }
+    }
+
+    /// Bytes of a value of type `ty` in a block, for the types push constants can have.
+    /// `matrix_stride` is that of the member the type is of, if a matrix.
+    fn size(&self, ty: u32, matrix_stride: Option<u32>) -> Result<u32, Error> {
+        Ok(match self.ty(ty)? {
+            Type::Int { width, .. } | Type::Float { width } => width / 8,
+            Type::Vector { component, count } => self.size(*component, None)? * count,
+            Type::Matrix { column, count } => match matrix_stride {
+                Some(stride) => stride * count,
+                None => self.size(*column, None)? * count,
+            },
+            Type::Array { element, length } => {
+                let length = self.constants.get(length).copied().unwrap_or(0);
+                let stride = match self.decoration(ty, DECORATION_ARRAY_STRIDE) {
+                    Some(stride) => stride,
+                    None => self.size(*element, None)?,
+                };
+                stride * length
+            }
+            Type::Struct { members } => {
+                let mut size = 0;
+                for (index, member) in members.iter().enumerate() {
+                    let member_decoration = |decoration| {
+                        self.member_decorations
+                            .get(&(ty, index as u32, decoration))
+                            .copied()
+                    };
+                    let offset = member_decoration(DECORATION_OFFSET).unwrap_or(size);
+                    let member_size =
+                        self.size(*member, member_decoration(DECORATION_MATRIX_STRIDE))?;
+                    size = size.max(offset + member_size);
+                }
+                size
+            }
+            _ => return Err(Error::Unsupported("opaque types in blocks".to_owned())),
+        })
+    }
+
+    fn vertex_format(&self, ty: u32) -> Result<vk::Format, Error> {
+        let (component, count) = match self.ty(ty)? {
+            Type::Vector { component, count } => (*component, *count),
+            _ => (ty, 1),
+        };
+        let formats = match self.ty(component)? {
+            Type::Float { width: 32 } => [
+                vk::Format::R32_SFLOAT,
+                vk::Format::R32G32_SFLOAT,
+                vk::Format::R32G32B32_SFLOAT,
+                vk::Format::R32G32B32A32_SFLOAT,
+            ],
+            Type::Int {
+                width: 32,
+                signed: true,
+            } => [
+                vk::Format::R32_SINT,
+                vk::Format::R32G32_SINT,
+                vk::Format::R32G32B32_SINT,
+                vk::Format::R32G32B32A32_SINT,
+            ],
+            Type::Int {
+                width: 32,
+                signed: false,
+            } => [
+                vk::Format::R32_UINT,
+                vk::Format::R32G32_UINT,
+                vk::Format::R32G32B32_UINT,
+                vk::Format::R32G32B32A32_UINT,
+            ],
+            _ => {
+                return Err(Error::Unsupported(
+                    "vertex inputs other than 32-bit".to_owned(),
+                ))
+            }
+        };
+        (count as usize)
+            .checked_sub(1)
+            .and_then(|index| formats.get(index))
+            .copied()
+            .ok_or_else(|| Error::InvalidData("vector of 0 or more than 4 components".to_owned()))
+    }
+}
+
+/// Reads the descriptors, push constants and vertex inputs `code` declares.
+pub(crate) fn reflect(code: &[u32]) -> Result<ShaderReflection, Error> {
+    let module = Module::parse(code)?;
+    let mut reflection = ShaderReflection::default();
+    for &(id, pointer, storage_class) in &module.variables {
+        let ty = module.pointee(pointer)?;
+        match storage_class {
+            STORAGE_CLASS_UNIFORM_CONSTANT
+            | STORAGE_CLASS_UNIFORM
+            | STORAGE_CLASS_STORAGE_BUFFER => {
+                let (set, binding) = match (
+                    module.decoration(id, DECORATION_DESCRIPTOR_SET),
+                    module.decoration(id, DECORATION_BINDING),
+                ) {
+                    (Some(set), Some(binding)) => (set, binding),
+                    _ => continue,
+                };
+                reflection.bindings.push(ReflectedBinding {
+                    set,
+                    binding,
+                    descriptor_type: module.descriptor_type(ty, storage_class)?,
+                });
+            }
+            STORAGE_CLASS_PUSH_CONSTANT => {
+                reflection.push_constant_size = module.size(ty, None)?;
+            }
+            STORAGE_CLASS_INPUT if module.vertex_interface.contains(&id) => {
+                if module.decoration(id, DECORATION_BUILT_IN).is_some() {
+                    continue;
+                }
+                if let Some(location) = module.decoration(id, DECORATION_LOCATION) {
+                    reflection
+                        .vertex_inputs
+                        .push((location, module.vertex_format(ty)?));
+                }
+            }
+            _ => {}
+        }
+    }
+    reflection.bindings.sort_by_key(|b| (b.set, b.binding));
+    reflection
+        .vertex_inputs
+        .sort_by_key(|&(location, _)| location);
+    Ok(reflection)
+}
+
+fn format_size(format: vk::Format) -> u32 {
+    match format {
+        vk::Format::R32_SFLOAT | vk::Format::R32_SINT | vk::Format::R32_UINT => 4,
+        vk::Format::R32G32_SFLOAT | vk::Format::R32G32_SINT | vk::Format::R32G32_UINT => 8,
+        vk::Format::R32G32B32_SFLOAT | vk::Format::R32G32B32_SINT | vk::Format::R32G32B32_UINT => {
+            12
+        }
+        _ => 16,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::discriminant;
+    use std::path::Path;
+
+    use super::*;
+
+    /// Compiles a shader of the workspace like the build scripts do and reflects it.
+    fn reflect_shader(path: &str) -> ShaderReflection {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("..").join(path);
+        let spirv = shader_compiler::ShaderCompiler::new()
+            .unwrap()
+            .compile(&path)
+            .unwrap()
+            .spirv;
+        let code = spirv
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+            .collect::<Vec<_>>();
+        reflect(&code).unwrap()
+    }
+
+    fn assert_bindings(reflection: &ShaderReflection, expected: &[(u32, u32, DescriptorType)]) {
+        assert_eq!(reflection.bindings.len(), expected.len());
+        for (binding, (set, index, descriptor_type)) in reflection.bindings.iter().zip(expected) {
+            assert_eq!((binding.set, binding.binding), (*set, *index));
+            assert!(discriminant(&binding.descriptor_type) == discriminant(descriptor_type));
+        }
+    }
+
+    #[test]
+    fn test_egui_vertex_shader() {
+        let reflection = reflect_shader("egui-backend/src/shaders/egui.vert");
+        assert_bindings(&reflection, &[(0, 0, DescriptorType::UniformBuffer)]);
+        assert_eq!(reflection.push_constant_size, 0);
+        assert_eq!(
+            reflection.vertex_inputs,
+            vec![
+                (0, vk::Format::R32G32_SFLOAT),
+                (1, vk::Format::R32G32_SFLOAT),
+                (2, vk::Format::R32_UINT),
+            ]
+        );
+        // The position, texture coordinate and color of `egui::epaint::Vertex`.
+        let (binding, attributes) = reflection.vertex_input(0);
+        assert_eq!(binding.stride, 20);
+        let offsets = attributes.iter().map(|a| a.offset).collect::<Vec<_>>();
+        assert_eq!(offsets, vec![0, 8, 16]);
+    }
+
+    #[test]
+    fn test_egui_fragment_shader() {
+        let reflection = reflect_shader("egui-backend/src/shaders/egui.frag");
+        assert_bindings(
+            &reflection,
+            &[
+                (0, 0, DescriptorType::UniformBuffer),
+                (0, 1, DescriptorType::Sampler(None)),
+                (0, 2, DescriptorType::Sampler(None)),
+                (1, 0, DescriptorType::SampledImage),
+            ],
+        );
+        assert!(reflection.vertex_inputs.is_empty());
+    }
+
+    #[test]
+    fn test_compute_shaders() {
+        let reflection = reflect_shader("egui-backend/src/shaders/hdr_inspector.comp");
+        assert_bindings(
+            &reflection,
+            &[
+                (0, 0, DescriptorType::StorageImage),
+                (0, 1, DescriptorType::StorageImage),
+            ],
+        );
+        assert_eq!(reflection.push_constant_size, 8);
+
+        let reflection = reflect_shader("minecraft/src/engine/shaders/pick.comp");
+        assert_bindings(
+            &reflection,
+            &[
+                (0, 0, DescriptorType::StorageBuffer),
+                (0, 1, DescriptorType::AccelerationStructure),
+                (0, 5, DescriptorType::UniformBuffer),
+            ],
+        );
+        assert_eq!(reflection.push_constant_size, 16);
+    }
+
+    #[test]
+    fn test_malformed_instructions() {
+        let header = [MAGIC, 0x0001_0500, 0, 100, 0];
+        // An entry point of only an execution model.
+        let mut code = header.to_vec();
+        code.extend_from_slice(&[(2 << 16) | OP_ENTRY_POINT, EXECUTION_MODEL_VERTEX]);
+        assert!(reflect(&code).is_ok());
+
+        // An input of a vector of no components.
+        let mut code = header.to_vec();
+        code.extend_from_slice(&[(3 << 16) | OP_TYPE_FLOAT, 1, 32]);
+        code.extend_from_slice(&[(4 << 16) | OP_TYPE_VECTOR, 2, 1, 0]);
+        code.extend_from_slice(&[(4 << 16) | OP_TYPE_POINTER, 3, STORAGE_CLASS_INPUT, 2]);
+        code.extend_from_slice(&[(4 << 16) | OP_VARIABLE, 3, 4, STORAGE_CLASS_INPUT]);
+        code.extend_from_slice(&[(4 << 16) | OP_DECORATE, 4, DECORATION_LOCATION, 0]);
+        code.extend_from_slice(&[(5 << 16) | OP_ENTRY_POINT, EXECUTION_MODEL_VERTEX, 5, 0, 4]);
+        assert!(matches!(reflect(&code), Err(Error::InvalidData(_))));
+    }
+}
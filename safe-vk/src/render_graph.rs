@@ -0,0 +1,634 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::{vk, Allocator, CommandRecorder, GpuProfiler, Image, ImageBarrier, ImageSync};
+
+/// The stages that test and write depth/stencil attachments.
+const FRAGMENT_TESTS: vk::PipelineStageFlags = vk::PipelineStageFlags::from_raw(
+    vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS.as_raw()
+        | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS.as_raw(),
+);
+
+/// An image of a `RenderGraph`, valid for the graph it was created with only.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ImageHandle(usize);
+
+/// How a pass uses an image, which decides the layout it's in and what the barriers before and
+/// after the pass wait for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageAccess {
+    /// In `GENERAL`, as a storage image of the shaders of the stages, or as an attachment of a
+    /// render pass keeping it in `GENERAL` if they include `COLOR_ATTACHMENT_OUTPUT`.
+    Storage(vk::PipelineStageFlags),
+    /// Sampled by the shaders of the stages.
+    Sampled(vk::PipelineStageFlags),
+    ColorAttachment,
+    /// The depth/stencil attachment of a render pass, tested and written if the pass writes it.
+    DepthStencilAttachment,
+    /// In `DEPTH_STENCIL_READ_ONLY_OPTIMAL`, tested by a render pass that doesn't write it and
+    /// sampled by the shaders of the stages.
+    DepthStencilReadOnly(vk::PipelineStageFlags),
+    TransferSrc,
+    TransferDst,
+}
+
+impl ImageAccess {
+    fn layout(self) -> vk::ImageLayout {
+        match self {
+            ImageAccess::Storage(_) => vk::ImageLayout::GENERAL,
+            ImageAccess::Sampled(_) => vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            ImageAccess::ColorAttachment => vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            ImageAccess::DepthStencilAttachment => {
+                vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+            }
+            ImageAccess::DepthStencilReadOnly(_) => {
+                vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL
+            }
+            ImageAccess::TransferSrc => vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            ImageAccess::TransferDst => vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        }
+    }
+
+    fn stage(self) -> vk::PipelineStageFlags {
+        match self {
+            ImageAccess::Storage(stage) | ImageAccess::Sampled(stage) => stage,
+            ImageAccess::ColorAttachment => vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            ImageAccess::DepthStencilAttachment => FRAGMENT_TESTS,
+            ImageAccess::DepthStencilReadOnly(stage) => FRAGMENT_TESTS | stage,
+            ImageAccess::TransferSrc | ImageAccess::TransferDst => vk::PipelineStageFlags::TRANSFER,
+        }
+    }
+
+    fn access(self, write: bool) -> vk::AccessFlags {
+        match self {
+            ImageAccess::Storage(stage) => {
+                let mut access = vk::AccessFlags::SHADER_READ;
+                if write {
+                    access |= vk::AccessFlags::SHADER_WRITE;
+                }
+                if stage.contains(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT) {
+                    access |= ImageAccess::ColorAttachment.access(write);
+                }
+                access
+            }
+            ImageAccess::Sampled(_) => vk::AccessFlags::SHADER_READ,
+            ImageAccess::ColorAttachment if write => {
+                vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+            }
+            ImageAccess::ColorAttachment => vk::AccessFlags::COLOR_ATTACHMENT_READ,
+            ImageAccess::DepthStencilAttachment if write => {
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE
+            }
+            ImageAccess::DepthStencilAttachment => vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ,
+            ImageAccess::DepthStencilReadOnly(stage) if stage.is_empty() => {
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+            }
+            ImageAccess::DepthStencilReadOnly(_) => {
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags::SHADER_READ
+            }
+            ImageAccess::TransferSrc => vk::AccessFlags::TRANSFER_READ,
+            ImageAccess::TransferDst => vk::AccessFlags::TRANSFER_WRITE,
+        }
+    }
+
+    /// What transient images used this way are created with.
+    fn usage(self) -> vk::ImageUsageFlags {
+        match self {
+            ImageAccess::Storage(stage)
+                if stage.contains(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT) =>
+            {
+                vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::COLOR_ATTACHMENT
+            }
+            ImageAccess::Storage(_) => vk::ImageUsageFlags::STORAGE,
+            ImageAccess::Sampled(_) => vk::ImageUsageFlags::SAMPLED,
+            ImageAccess::ColorAttachment => vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            ImageAccess::DepthStencilAttachment => vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            ImageAccess::DepthStencilReadOnly(stage) if stage.is_empty() => {
+                vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
+            }
+            ImageAccess::DepthStencilReadOnly(_) => {
+                vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED
+            }
+            ImageAccess::TransferSrc => vk::ImageUsageFlags::TRANSFER_SRC,
+            ImageAccess::TransferDst => vk::ImageUsageFlags::TRANSFER_DST,
+        }
+    }
+}
+
+/// A transient image of a `RenderGraph`, created by the graph for the passes between its first
+/// and last use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TransientImageDesc {
+    pub format: vk::Format,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Transient images of earlier graphs, for those of later frames to reuse.
+pub struct TransientImagePool {
+    allocator: Arc<Allocator>,
+    images: HashMap<(TransientImageDesc, vk::ImageUsageFlags), Vec<Arc<Image>>>,
+}
+
+impl TransientImagePool {
+    pub fn new(allocator: Arc<Allocator>) -> Self {
+        Self {
+            allocator,
+            images: HashMap::new(),
+        }
+    }
+
+    /// Drops the images kept, e.g. once the frame is resized and they won't be used again.
+    pub fn clear(&mut self) {
+        self.images.clear();
+    }
+
+    fn take(&mut self, desc: TransientImageDesc, usage: vk::ImageUsageFlags) -> Arc<Image> {
+        match self.images.get_mut(&(desc, usage)).and_then(Vec::pop) {
+            Some(image) => image,
+            None => Arc::new(Image::new(
+                Some("transient image"),
+                self.allocator.clone(),
+                desc.format,
+                desc.width,
+                desc.height,
+                vk::ImageTiling::OPTIMAL,
+                usage,
+                vk_mem::MemoryUsage::GpuOnly,
+            )),
+        }
+    }
+
+    fn give_back(
+        &mut self,
+        desc: TransientImageDesc,
+        usage: vk::ImageUsageFlags,
+        image: Arc<Image>,
+    ) {
+        self.images.entry((desc, usage)).or_default().push(image);
+    }
+}
+
+enum ImageSource {
+    /// With the layout it's in at the start of the graph, the one it was last left in if
+    /// `None`.
+    Imported(Arc<Image>, Option<vk::ImageLayout>),
+    Transient(TransientImageDesc),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ImageUse {
+    image: ImageHandle,
+    access: ImageAccess,
+    write: bool,
+}
+
+/// The images a pass reads and writes, declared in `RenderGraph::add_pass`.
+#[derive(Default)]
+pub struct PassBuilder {
+    uses: Vec<ImageUse>,
+}
+
+impl PassBuilder {
+    pub fn read(&mut self, image: ImageHandle, access: ImageAccess) -> &mut Self {
+        self.add_use(image, access, false)
+    }
+
+    pub fn write(&mut self, image: ImageHandle, access: ImageAccess) -> &mut Self {
+        assert!(
+            !matches!(
+                access,
+                ImageAccess::Sampled(_)
+                    | ImageAccess::DepthStencilReadOnly(_)
+                    | ImageAccess::TransferSrc
+            ),
+            "{:?} is read only",
+            access
+        );
+        self.add_use(image, access, true)
+    }
+
+    fn add_use(&mut self, image: ImageHandle, access: ImageAccess, write: bool) -> &mut Self {
+        assert!(
+            self.uses.iter().all(|u| u.image != image),
+            "image used twice by a pass"
+        );
+        self.uses.push(ImageUse {
+            image,
+            access,
+            write,
+        });
+        self
+    }
+}
+
+/// The images of the graph as a pass records, transient ones included.
+pub struct PassImages {
+    images: Vec<Option<Arc<Image>>>,
+}
+
+impl PassImages {
+    /// Panics for transient images not declared by the pass.
+    pub fn get(&self, image: ImageHandle) -> &Arc<Image> {
+        self.images[image.0]
+            .as_ref()
+            .expect("image not declared by the pass")
+    }
+}
+
+struct Pass<'a> {
+    name: String,
+    uses: Vec<ImageUse>,
+    record: Box<dyn FnOnce(&mut CommandRecorder, &PassImages) + 'a>,
+}
+
+/// The passes of a frame and the images they read and write. `execute` records the passes in
+/// the order they were added with the layout transitions and barriers between them, creates
+/// the transient images and drops the passes whose writes nothing reads.
+///
+/// Imported images are those that outlive the graph, e.g. swapchain images and accumulation
+/// targets: passes writing them are always kept. Transient images whose uses don't overlap
+/// share memory. Passes that change the layout of an image themselves, e.g. with the final
+/// layout of a render pass, leave it in that layout for the next one.
+pub struct RenderGraph<'a> {
+    pool: &'a mut TransientImagePool,
+    profiler: Option<&'a mut GpuProfiler>,
+    images: Vec<ImageSource>,
+    passes: Vec<Pass<'a>>,
+}
+
+impl<'a> RenderGraph<'a> {
+    pub fn new(pool: &'a mut TransientImagePool) -> Self {
+        Self {
+            pool,
+            profiler: None,
+            images: Vec::new(),
+            passes: Vec::new(),
+        }
+    }
+
+    /// Times each pass recorded as a scope of `profiler` named after the pass.
+    pub fn profile(&mut self, profiler: &'a mut GpuProfiler) {
+        self.profiler = Some(profiler);
+    }
+
+    /// `initial_layout` is `UNDEFINED` for images whose contents the graph doesn't need, `None`
+    /// for the layout the image was last left in.
+    pub fn import_image(
+        &mut self,
+        image: Arc<Image>,
+        initial_layout: Option<vk::ImageLayout>,
+    ) -> ImageHandle {
+        self.images
+            .push(ImageSource::Imported(image, initial_layout));
+        ImageHandle(self.images.len() - 1)
+    }
+
+    pub fn create_image(&mut self, desc: TransientImageDesc) -> ImageHandle {
+        self.images.push(ImageSource::Transient(desc));
+        ImageHandle(self.images.len() - 1)
+    }
+
+    /// `declare` declares the images the pass reads and writes, `record` records it.
+    pub fn add_pass<D, F>(&mut self, name: &str, declare: D, record: F)
+    where
+        D: FnOnce(&mut PassBuilder),
+        F: FnOnce(&mut CommandRecorder, &PassImages) + 'a,
+    {
+        let mut builder = PassBuilder::default();
+        declare(&mut builder);
+        self.passes.push(Pass {
+            name: name.to_owned(),
+            uses: builder.uses,
+            record: Box::new(record),
+        });
+    }
+
+    pub fn execute(self, recorder: &mut CommandRecorder) {
+        let RenderGraph {
+            pool,
+            mut profiler,
+            images,
+            passes,
+        } = self;
+        let initial_layouts = images
+            .iter()
+            .map(|source| match source {
+                ImageSource::Imported(_, Some(layout)) => *layout,
+                ImageSource::Imported(image, None) => recorder.image_layout(image),
+                ImageSource::Transient(_) => vk::ImageLayout::UNDEFINED,
+            })
+            .collect::<Vec<_>>();
+        let schedule = Schedule::new(&images, &initial_layouts, &passes);
+
+        let mut resolved = PassImages {
+            images: images
+                .iter()
+                .map(|source| match source {
+                    ImageSource::Imported(image, _) => Some(image.clone()),
+                    ImageSource::Transient(_) => None,
+                })
+                .collect(),
+        };
+        for (image, source) in resolved.images.iter().zip(&images) {
+            if let (Some(image), ImageSource::Imported(_, Some(layout))) = (image, source) {
+                recorder.assume_image_layout(image.clone(), *layout);
+            }
+        }
+        let mut slot_images: Vec<Option<Arc<Image>>> = vec![None; schedule.slots.len()];
+
+        for (index, pass) in passes.into_iter().enumerate() {
+            if !schedule.live[index] {
+                continue;
+            }
+            for image_use in &pass.uses {
+                let handle = image_use.image.0;
+                if let (None, Some(slot)) = (&resolved.images[handle], schedule.aliases[handle]) {
+                    // The contents of a transient image are discarded, but the uses of the
+                    // image by an earlier one or an earlier frame still have to finish.
+                    let (desc, usage) = schedule.slots[slot].key;
+                    let image = slot_images[slot]
+                        .get_or_insert_with(|| pool.take(desc, usage))
+                        .clone();
+                    recorder.assume_image_layout(image.clone(), vk::ImageLayout::UNDEFINED);
+                    resolved.images[handle] = Some(image);
+                }
+            }
+            for (image, barrier) in &schedule.barriers[index] {
+                log::trace!(
+                    "render graph: {} waits for {:?}: {:?}",
+                    pass.name,
+                    image,
+                    barrier
+                );
+            }
+            for image_use in &pass.uses {
+                recorder.access_image(
+                    resolved.get(image_use.image).clone(),
+                    image_use.access.layout(),
+                    image_use.access.stage(),
+                    image_use.access.access(image_use.write),
+                );
+            }
+
+            let record = pass.record;
+            match &mut profiler {
+                Some(profiler) => {
+                    recorder.gpu_scope(profiler, &pass.name, |recorder| record(recorder, &resolved))
+                }
+                None => record(recorder, &resolved),
+            }
+
+            for (slot, image) in slot_images.iter_mut().enumerate() {
+                if schedule.slots[slot].last_use == index {
+                    if let Some(image) = image.take() {
+                        let (desc, usage) = schedule.slots[slot].key;
+                        pool.give_back(desc, usage, image);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Transient images whose uses don't overlap share one, created with the same description and
+/// usage.
+#[derive(Debug)]
+struct Slot {
+    key: (TransientImageDesc, vk::ImageUsageFlags),
+    /// The last pass using an image of the slot.
+    last_use: usize,
+}
+
+/// What `RenderGraph::execute` records, worked out from the uses the passes declare alone.
+struct Schedule {
+    /// Whether each pass is kept.
+    live: Vec<bool>,
+    /// The slot of each transient image used by a pass kept.
+    aliases: Vec<Option<usize>>,
+    slots: Vec<Slot>,
+    /// The images each pass waits for and the barriers it needs, between the uses it
+    /// declares and those before. A pass changing the layout of an image itself, e.g. with a
+    /// render pass, changes what's recorded after it.
+    barriers: Vec<Vec<(ImageHandle, ImageBarrier)>>,
+}
+
+impl Schedule {
+    /// `initial_layouts` is the layout of each image at the start of the graph.
+    fn new(images: &[ImageSource], initial_layouts: &[vk::ImageLayout], passes: &[Pass]) -> Self {
+        let is_transient =
+            |image: ImageHandle| matches!(images[image.0], ImageSource::Transient(_));
+
+        // Walks back from the last pass, keeping those that write no transient image or one a
+        // later pass kept uses.
+        let mut needed = vec![false; images.len()];
+        let mut live = vec![false; passes.len()];
+        for (index, pass) in passes.iter().enumerate().rev() {
+            let writes = pass.uses.iter().filter(|u| u.write);
+            live[index] = writes.clone().count() == 0
+                || writes
+                    .clone()
+                    .any(|u| !is_transient(u.image) || needed[u.image.0]);
+            if live[index] {
+                for image_use in &pass.uses {
+                    needed[image_use.image.0] = true;
+                }
+            } else {
+                log::debug!("render graph: culled pass {}", pass.name);
+            }
+        }
+        let live_passes = || passes.iter().enumerate().filter(|(index, _)| live[*index]);
+
+        // The usage of the transient images and the last pass that uses each.
+        let mut usages = vec![vk::ImageUsageFlags::empty(); images.len()];
+        let mut last_uses = vec![0; images.len()];
+        for (index, pass) in live_passes() {
+            for image_use in &pass.uses {
+                usages[image_use.image.0] |= image_use.access.usage();
+                last_uses[image_use.image.0] = index;
+            }
+        }
+
+        let mut aliases = vec![None; images.len()];
+        let mut slots: Vec<Slot> = Vec::new();
+        let mut syncs = initial_layouts
+            .iter()
+            .map(|layout| ImageSync::untracked(*layout))
+            .collect::<Vec<_>>();
+        let mut barriers = vec![Vec::new(); passes.len()];
+        for (index, pass) in live_passes() {
+            for image_use in &pass.uses {
+                let handle = image_use.image.0;
+                if let (None, ImageSource::Transient(desc)) = (aliases[handle], &images[handle]) {
+                    // The first slot of the image's kind free by its first use.
+                    let key = (*desc, usages[handle]);
+                    let slot = match slots
+                        .iter()
+                        .position(|slot| slot.key == key && slot.last_use < index)
+                    {
+                        Some(slot) => slot,
+                        None => {
+                            slots.push(Slot { key, last_use: 0 });
+                            slots.len() - 1
+                        }
+                    };
+                    slots[slot].last_use = last_uses[handle];
+                    aliases[handle] = Some(slot);
+                }
+                let access = image_use.access;
+                if let Some(barrier) = syncs[handle].access(
+                    access.layout(),
+                    access.stage(),
+                    access.access(image_use.write),
+                ) {
+                    barriers[index].push((image_use.image, barrier));
+                }
+            }
+        }
+
+        Self {
+            live,
+            aliases,
+            slots,
+            barriers,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DESC: TransientImageDesc = TransientImageDesc {
+        format: vk::Format::R16G16B16A16_SFLOAT,
+        width: 640,
+        height: 480,
+    };
+
+    fn pass<D>(name: &str, declare: D) -> Pass<'static>
+    where
+        D: FnOnce(&mut PassBuilder),
+    {
+        let mut builder = PassBuilder::default();
+        declare(&mut builder);
+        Pass {
+            name: name.to_owned(),
+            uses: builder.uses,
+            record: Box::new(|_: &mut CommandRecorder, _: &PassImages| {}),
+        }
+    }
+
+    fn schedule(image_count: usize, passes: &[Pass]) -> Schedule {
+        let images = (0..image_count)
+            .map(|_| ImageSource::Transient(DESC))
+            .collect::<Vec<_>>();
+        let layouts = vec![vk::ImageLayout::UNDEFINED; image_count];
+        Schedule::new(&images, &layouts, passes)
+    }
+
+    fn compute() -> ImageAccess {
+        ImageAccess::Storage(vk::PipelineStageFlags::COMPUTE_SHADER)
+    }
+
+    fn fragment() -> ImageAccess {
+        ImageAccess::Sampled(vk::PipelineStageFlags::FRAGMENT_SHADER)
+    }
+
+    #[test]
+    fn test_unused_pass_culled() {
+        let (traced, unused) = (ImageHandle(0), ImageHandle(1));
+        let passes = [
+            pass("trace", |pass| {
+                pass.write(traced, compute());
+            }),
+            pass("unused", |pass| {
+                pass.read(traced, fragment()).write(unused, compute());
+            }),
+            pass("display", |pass| {
+                pass.read(traced, fragment());
+            }),
+        ];
+        let schedule = schedule(2, &passes);
+        assert_eq!(schedule.live, vec![true, false, true]);
+        assert_eq!(schedule.aliases, vec![Some(0), None]);
+    }
+
+    #[test]
+    fn test_write_read_barrier() {
+        let image = ImageHandle(0);
+        let passes = [
+            pass("trace", |pass| {
+                pass.write(image, compute());
+            }),
+            pass("display", |pass| {
+                pass.read(image, fragment());
+            }),
+        ];
+        let schedule = schedule(1, &passes);
+        // The first use discards the contents after whatever used the image before.
+        let (handle, barrier) = schedule.barriers[0][0];
+        assert_eq!(handle, image);
+        assert_eq!(barrier.old_layout, vk::ImageLayout::UNDEFINED);
+        assert_eq!(barrier.new_layout, vk::ImageLayout::GENERAL);
+        assert_eq!(
+            schedule.barriers[1],
+            vec![(
+                image,
+                ImageBarrier {
+                    old_layout: vk::ImageLayout::GENERAL,
+                    new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    src_stage: vk::PipelineStageFlags::COMPUTE_SHADER,
+                    src_access: vk::AccessFlags::SHADER_WRITE,
+                    dst_stage: vk::PipelineStageFlags::FRAGMENT_SHADER,
+                    dst_access: vk::AccessFlags::SHADER_READ,
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn test_read_after_read_needs_no_barrier() {
+        let image = ImageHandle(0);
+        let passes = [
+            pass("trace", |pass| {
+                pass.write(image, compute());
+            }),
+            pass("display", |pass| {
+                pass.read(image, fragment());
+            }),
+            pass("display again", |pass| {
+                pass.read(image, fragment());
+            }),
+        ];
+        let schedule = schedule(1, &passes);
+        assert_eq!(schedule.barriers[1].len(), 1);
+        assert!(schedule.barriers[2].is_empty());
+    }
+
+    #[test]
+    fn test_disjoint_lifetimes_alias() {
+        let images = [ImageHandle(0), ImageHandle(1), ImageHandle(2)];
+        // Each image lives from the pass writing it to the one after, which reads it.
+        let passes = [
+            pass("first", |pass| {
+                pass.write(images[0], compute());
+            }),
+            pass("second", |pass| {
+                pass.read(images[0], fragment()).write(images[1], compute());
+            }),
+            pass("third", |pass| {
+                pass.read(images[1], fragment()).write(images[2], compute());
+            }),
+            pass("last", |pass| {
+                pass.read(images[2], fragment());
+            }),
+        ];
+        let schedule = schedule(3, &passes);
+        // The first and the last don't overlap, the second overlaps both.
+        assert_eq!(schedule.aliases, vec![Some(0), Some(1), Some(0)]);
+        assert_eq!(schedule.slots.len(), 2);
+        assert_eq!(schedule.slots[0].last_use, 3);
+        assert_eq!(schedule.slots[1].last_use, 2);
+    }
+}
@@ -0,0 +1,209 @@
+use std::sync::Arc;
+
+use crate::{vk, Allocator, Buffer, DescriptorSetUpdateDetail, Error, Fence, MemoryUsage};
+
+/// Hands out slices of a persistently mapped uniform buffer for the constants of each frame,
+/// bound as a `DescriptorType::UniformBufferDynamic` at the offset `push` returns. The buffer is
+/// split in a part per frame in flight, reused once the fence of the frame that last used it
+/// signals.
+pub struct DynamicUniformRing {
+    buffer: Arc<Buffer>,
+    mapped: *mut u8,
+    cursor: RingCursor,
+    /// Signaled once the GPU is done with each part, `None` for parts not submitted yet.
+    fences: Vec<Option<Arc<Fence>>>,
+}
+
+impl DynamicUniformRing {
+    /// `frame_size` bytes per frame for `frame_count` frames in flight, in slices of at most
+    /// `range` bytes.
+    pub fn new(
+        allocator: Arc<Allocator>,
+        range: usize,
+        frame_size: usize,
+        frame_count: usize,
+    ) -> Self {
+        Self::try_new(allocator, range, frame_size, frame_count).unwrap()
+    }
+
+    /// Fails with `Error::InvalidData` for 0 frames, or with `Error::Allocation` when the memory
+    /// runs out.
+    pub fn try_new(
+        allocator: Arc<Allocator>,
+        range: usize,
+        frame_size: usize,
+        frame_count: usize,
+    ) -> Result<Self, Error> {
+        if frame_count == 0 {
+            return Err(Error::InvalidData(
+                "dynamic uniform ring of 0 frames".to_owned(),
+            ));
+        }
+        let alignment = allocator
+            .device()
+            .pdevice
+            .properties()
+            .limits
+            .min_uniform_buffer_offset_alignment
+            .max(1) as usize;
+        let cursor = RingCursor::new(alignment, range, frame_size, frame_count);
+        let buffer = Arc::new(Buffer::try_new(
+            Some("dynamic uniform ring"),
+            allocator,
+            cursor.frame_size * frame_count,
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            MemoryUsage::CpuToGpu,
+        )?);
+        // Unmapped when the buffer is dropped.
+        let mapped = buffer.map();
+        Ok(Self {
+            buffer,
+            mapped,
+            cursor,
+            fences: vec![None; frame_count],
+        })
+    }
+
+    pub fn buffer(&self) -> &Arc<Buffer> {
+        &self.buffer
+    }
+
+    /// What to update the dynamic uniform buffer binding of a set with, once.
+    pub fn descriptor(&self) -> DescriptorSetUpdateDetail {
+        DescriptorSetUpdateDetail::BufferRange {
+            buffer: self.buffer.clone(),
+            offset: 0,
+            range: self.cursor.range as u64,
+        }
+    }
+
+    /// Moves on to the part of the next frame, waiting for the frame that last used it.
+    pub fn begin_frame(&mut self) {
+        let frame = self.cursor.begin_frame();
+        if let Some(fence) = self.fences[frame].take() {
+            fence.wait();
+        }
+    }
+
+    /// Marks the part of the current frame in use until `fence`, that of the submit of the
+    /// frame, signals.
+    pub fn end_frame(&mut self, fence: Arc<Fence>) {
+        self.fences[self.cursor.frame] = Some(fence);
+    }
+
+    /// Writes `value` to the next slice of the frame and returns the dynamic offset to bind it
+    /// at. Panics if the value is larger than the range, or the part of the frame has less than
+    /// the range left, which the descriptor reads from the offset.
+    pub fn push<T: bytemuck::Pod>(&mut self, value: &T) -> u32 {
+        let bytes = bytemuck::bytes_of(value);
+        let offset = self.cursor.push(bytes.len());
+        unsafe {
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), self.mapped.add(offset), bytes.len());
+        }
+        // Coherent memory ignores the flush.
+        self.buffer
+            .allocator
+            .handle
+            .flush_allocation(&self.buffer.allocation, offset, bytes.len());
+        offset as u32
+    }
+}
+
+/// Where the slices of a `DynamicUniformRing` go, from the start of its buffer.
+#[derive(Debug)]
+struct RingCursor {
+    /// Of the offsets of the slices, the minimum uniform buffer offset alignment of the device.
+    alignment: usize,
+    /// Bytes of the largest slice, the range of the descriptor.
+    range: usize,
+    /// Bytes of the part of each frame, a multiple of the alignment.
+    frame_size: usize,
+    frame_count: usize,
+    frame: usize,
+    /// Of the next slice.
+    offset: usize,
+}
+
+impl RingCursor {
+    fn new(alignment: usize, range: usize, frame_size: usize, frame_count: usize) -> Self {
+        Self {
+            alignment,
+            range,
+            frame_size: align_up(frame_size.max(range), alignment),
+            frame_count,
+            frame: 0,
+            offset: 0,
+        }
+    }
+
+    /// Moves on to the part of the next frame and returns its index.
+    fn begin_frame(&mut self) -> usize {
+        self.frame = (self.frame + 1) % self.frame_count;
+        self.offset = self.frame * self.frame_size;
+        self.frame
+    }
+
+    /// The offset of the next slice, of `size` bytes, see `DynamicUniformRing::push`.
+    fn push(&mut self, size: usize) -> usize {
+        assert!(
+            size <= self.range,
+            "uniform of {} bytes larger than the range of the ring",
+            size
+        );
+        let frame_end = (self.frame + 1) * self.frame_size;
+        assert!(
+            self.offset + self.range <= frame_end,
+            "dynamic uniform ring full for the frame"
+        );
+        let offset = self.offset;
+        self.offset = align_up(offset + size, self.alignment);
+        offset
+    }
+}
+
+fn align_up(value: usize, alignment: usize) -> usize {
+    (value + alignment - 1) / alignment * alignment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aligned_offsets() {
+        // Room for 4 slices of 64 bytes a frame.
+        let mut cursor = RingCursor::new(256, 64, 1000, 2);
+        assert_eq!(cursor.frame_size, 1024);
+        assert_eq!(cursor.push(16), 0);
+        assert_eq!(cursor.push(64), 256);
+        assert_eq!(cursor.push(1), 512);
+    }
+
+    #[test]
+    fn test_frame_wrap() {
+        let mut cursor = RingCursor::new(256, 64, 512, 3);
+        cursor.push(64);
+        assert_eq!(cursor.begin_frame(), 1);
+        assert_eq!(cursor.push(64), 512);
+        assert_eq!(cursor.begin_frame(), 2);
+        assert_eq!(cursor.push(64), 1024);
+        // Back to the start of the buffer, whatever the frame before pushed.
+        assert_eq!(cursor.begin_frame(), 0);
+        assert_eq!(cursor.push(64), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "dynamic uniform ring full for the frame")]
+    fn test_ring_full() {
+        let mut cursor = RingCursor::new(256, 64, 512, 2);
+        cursor.push(64);
+        cursor.push(64);
+        cursor.push(64);
+    }
+
+    #[test]
+    #[should_panic(expected = "larger than the range")]
+    fn test_larger_than_range() {
+        RingCursor::new(256, 64, 512, 2).push(65);
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use camera::{CameraPresets, InputBindings};
+use serde::{Deserialize, Serialize};
+
+/// Directory under the platform config directory holding the settings of every viewer.
+const CONFIG_DIR: &str = "silly-cat-engine";
+
+/// What a viewer remembers between runs. Loaded at startup, saved on exit.
+///
+/// Missing fields take their default, so files written by older versions still load.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Inner size of the window in physical pixels.
+    pub window_size: [u32; 2],
+    /// The scene open when the viewer exited.
+    pub last_scene: Option<PathBuf>,
+    /// Presents in FIFO mode instead of immediate. Applied when the swapchain is created.
+    pub vsync: bool,
+    /// Frame rate cap, whatever the present mode. `None` leaves it uncapped.
+    pub max_fps: Option<u32>,
+    /// `ToneMapOperator::name` of the tone map operator.
+    pub tone_map_operator: Option<String>,
+    pub batch_samples: BatchSamples,
+    pub camera_presets: CameraPresets,
+    pub input_bindings: InputBindings,
+    /// Whether each window of the UI is open, by title.
+    pub open_windows: BTreeMap<String, bool>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            window_size: [800, 600],
+            last_scene: None,
+            vsync: false,
+            max_fps: None,
+            tone_map_operator: None,
+            batch_samples: BatchSamples::default(),
+            camera_presets: CameraPresets::default(),
+            input_bindings: InputBindings::default(),
+            open_windows: BTreeMap::new(),
+        }
+    }
+}
+
+/// How many samples per pixel interactive frames trace. Adapted to the frame rate unless `fixed`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BatchSamples {
+    /// The batch doubles while frames are well above this rate and halves while well below.
+    pub target_fps: f64,
+    pub min: u32,
+    pub max: u32,
+    /// Traced every frame instead of adapting.
+    pub fixed: Option<u32>,
+}
+
+impl Default for BatchSamples {
+    fn default() -> Self {
+        Self {
+            target_fps: 100.0,
+            min: 1,
+            max: 1024,
+            fixed: None,
+        }
+    }
+}
+
+impl BatchSamples {
+    /// Frame rates above `target_fps` by this factor double the batch.
+    const RAISE_FACTOR: f64 = 1.4;
+    /// Frame rates below `target_fps` by this factor halve the batch.
+    const LOWER_FACTOR: f64 = 0.7;
+
+    /// The batch to trace after `current` ran at `fps`.
+    pub fn next(&self, current: u32, fps: f64) -> u32 {
+        if let Some(fixed) = self.fixed {
+            return fixed.max(1);
+        }
+        let next = if fps > self.target_fps * Self::RAISE_FACTOR {
+            current.saturating_mul(2)
+        } else if fps < self.target_fps * Self::LOWER_FACTOR {
+            current / 2
+        } else {
+            current
+        };
+        next.min(self.max).max(self.min).max(1)
+    }
+}
+
+impl Settings {
+    /// `<config dir>/silly-cat-engine/<app>.ron`, `None` on platforms without a config directory.
+    pub fn path(app: &str) -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join(CONFIG_DIR).join(format!("{}.ron", app)))
+    }
+
+    /// The settings `app` saved last, the defaults if there are none or they can't be read.
+    pub fn load(app: &str) -> Self {
+        let path = match Self::path(app) {
+            Some(path) => path,
+            None => return Self::default(),
+        };
+        match Self::load_from(&path) {
+            Ok(settings) => settings,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(e) => {
+                log::warn!("failed to load {}: {}", path.display(), e);
+                Self::default()
+            }
+        }
+    }
+
+    /// Saves the settings of `app` where `load` finds them.
+    pub fn save(&self, app: &str) -> std::io::Result<()> {
+        let path = Self::path(app).ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "no config directory")
+        })?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        self.save_to(path)
+    }
+
+    pub fn save_to<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        let ron = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        std::fs::write(path, ron)
+    }
+
+    pub fn load_from<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let ron = std::fs::read_to_string(path)?;
+        ron::de::from_str(&ron).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    /// Whether the window titled `title` was open, `false` if it was never shown.
+    pub fn window_open(&self, title: &str) -> bool {
+        self.open_windows.get(title).copied().unwrap_or(false)
+    }
+
+    pub fn set_window_open(&mut self, title: &str, open: bool) {
+        self.open_windows.insert(title.to_owned(), open);
+    }
+}
@@ -0,0 +1,100 @@
+use std::path::PathBuf;
+
+use settings::{BatchSamples, Settings};
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("silly-cat-engine-settings-{}.ron", name))
+}
+
+#[test]
+fn test_save_load_round_trip() {
+    let mut settings = Settings::default();
+    settings.window_size = [1280, 720];
+    settings.last_scene = Some(PathBuf::from("./models/scene.gltf"));
+    settings.vsync = true;
+    settings.tone_map_operator = Some("Reinhard".to_owned());
+    settings.set_window_open("HDR Inspector", true);
+
+    let path = temp_path("round-trip");
+    settings.save_to(&path).unwrap();
+    let loaded = Settings::load_from(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(loaded.window_size, [1280, 720]);
+    assert_eq!(loaded.last_scene, settings.last_scene);
+    assert!(loaded.vsync);
+    assert_eq!(loaded.tone_map_operator.as_deref(), Some("Reinhard"));
+    assert!(loaded.window_open("HDR Inspector"));
+    assert!(!loaded.window_open("Selection"));
+}
+
+#[test]
+fn test_missing_fields_take_defaults() {
+    let path = temp_path("missing-fields");
+    std::fs::write(&path, "(vsync: true)").unwrap();
+    let loaded = Settings::load_from(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(loaded.vsync);
+    assert_eq!(loaded.window_size, Settings::default().window_size);
+    assert_eq!(loaded.last_scene, None);
+}
+
+#[test]
+fn test_invalid_file_is_an_error() {
+    let path = temp_path("invalid");
+    std::fs::write(&path, "not settings").unwrap();
+    let result = Settings::load_from(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_input_bindings_round_trip() {
+    use camera::{Action, Binding, Key};
+
+    let mut settings = Settings::default();
+    settings
+        .input_bindings
+        .bind(Action::MoveForward, Binding::Key(Key::Up));
+    settings
+        .input_bindings
+        .unbind(Action::Screenshot, Binding::Key(Key::Function(12)));
+
+    let path = temp_path("input-bindings");
+    settings.save_to(&path).unwrap();
+    let loaded = Settings::load_from(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(loaded.input_bindings, settings.input_bindings);
+    assert!(loaded
+        .input_bindings
+        .bindings(Action::Screenshot)
+        .is_empty());
+}
+
+#[test]
+fn test_batch_samples_follow_frame_rate() {
+    let batch_samples = BatchSamples {
+        target_fps: 100.0,
+        min: 2,
+        max: 16,
+        fixed: None,
+    };
+    assert_eq!(batch_samples.next(4, 150.0), 8);
+    assert_eq!(batch_samples.next(4, 100.0), 4);
+    assert_eq!(batch_samples.next(4, 60.0), 2);
+    assert_eq!(batch_samples.next(2, 10.0), 2);
+    assert_eq!(batch_samples.next(16, 1000.0), 16);
+}
+
+#[test]
+fn test_fixed_batch_samples() {
+    let batch_samples = BatchSamples {
+        fixed: Some(32),
+        ..BatchSamples::default()
+    };
+    assert_eq!(batch_samples.next(4, 1000.0), 32);
+    assert_eq!(batch_samples.next(64, 1.0), 32);
+}
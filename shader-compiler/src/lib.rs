@@ -0,0 +1,196 @@
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+pub use shaderc::ShaderKind;
+
+/// The SPIR-V of a shader and the files it was compiled from.
+pub struct Compiled {
+    pub spirv: Vec<u8>,
+    /// The source and every file it includes, directly or not.
+    pub dependencies: Vec<PathBuf>,
+}
+
+/// Compiles GLSL to SPIR-V for Vulkan 1.2, the same way for the build scripts and
+/// `safe_vk::ShaderWatcher`.
+///
+/// `#include "file"` is resolved relative to the including file, `#include <file>` in the
+/// include directories.
+pub struct ShaderCompiler {
+    compiler: shaderc::Compiler,
+    include_dirs: Vec<PathBuf>,
+}
+
+impl ShaderCompiler {
+    pub fn new() -> Result<Self> {
+        let compiler = shaderc::Compiler::new().context("Unable to create shader compiler")?;
+        Ok(Self {
+            compiler,
+            include_dirs: Vec::new(),
+        })
+    }
+
+    /// Searched for `#include <file>`, and for `#include "file"` not next to the including file.
+    pub fn add_include_dir<P: Into<PathBuf>>(&mut self, dir: P) {
+        self.include_dirs.push(dir.into());
+    }
+
+    /// Compiles the entry point `main` of `source`, its stage taken from the extension.
+    pub fn compile(&mut self, source: &Path) -> Result<Compiled> {
+        let kind = shader_kind(source)
+            .ok_or_else(|| anyhow!("Unsupported shader: {}", source.display()))?;
+        let text = std::fs::read_to_string(source)
+            .with_context(|| format!("Failed to read {}", source.display()))?;
+        let mut compiled = self.compile_source(&text, &source.to_string_lossy(), kind, &[])?;
+        compiled.dependencies.insert(0, source.to_owned());
+        Ok(compiled)
+    }
+
+    /// Compiles the entry point `main` of GLSL `text` of stage `kind`, with `defines` set as by
+    /// `#define name value`. `name` is the path errors refer to, relative includes are resolved
+    /// next to it. The dependencies are the files included.
+    pub fn compile_source(
+        &mut self,
+        text: &str,
+        name: &str,
+        kind: ShaderKind,
+        defines: &[(&str, Option<&str>)],
+    ) -> Result<Compiled> {
+        let dependencies = RefCell::new(Vec::new());
+        let include_dirs = &self.include_dirs;
+        let mut options =
+            shaderc::CompileOptions::new().context("Unable to create compile options")?;
+        options.set_target_env(
+            shaderc::TargetEnv::Vulkan,
+            shaderc::EnvVersion::Vulkan1_2 as u32,
+        );
+        options.set_target_spirv(shaderc::SpirvVersion::V1_5);
+        options.set_generate_debug_info();
+        options.set_optimization_level(shaderc::OptimizationLevel::Performance);
+        for (macro_name, value) in defines {
+            options.add_macro_definition(macro_name, *value);
+        }
+        options.set_include_callback(|requested, include_type, including, _| {
+            let relative = match include_type {
+                shaderc::IncludeType::Relative => Path::new(including).parent(),
+                shaderc::IncludeType::Standard => None,
+            };
+            let candidates: Vec<PathBuf> = relative
+                .into_iter()
+                .chain(include_dirs.iter().map(PathBuf::as_path))
+                .map(|dir| dir.join(requested))
+                .collect();
+            let path = candidates
+                .iter()
+                .find(|path| path.is_file())
+                .ok_or_else(|| {
+                    let tried: Vec<_> = candidates
+                        .iter()
+                        .map(|path| path.display().to_string())
+                        .collect();
+                    format!("{} not found, tried {}", requested, tried.join(", "))
+                })?;
+            let content = std::fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            let mut dependencies = dependencies.borrow_mut();
+            if !dependencies.contains(path) {
+                dependencies.push(path.clone());
+            }
+            Ok(shaderc::ResolvedInclude {
+                resolved_name: path.to_string_lossy().into_owned(),
+                content,
+            })
+        });
+
+        let artifact = self
+            .compiler
+            .compile_into_spirv(text, kind, name, "main", Some(&options))
+            .map_err(|e| anyhow!("{}", e))?;
+        drop(options);
+        Ok(Compiled {
+            spirv: artifact.as_binary_u8().to_vec(),
+            dependencies: dependencies.into_inner(),
+        })
+    }
+}
+
+/// The stage of a shader from its extension, `None` for includes and other files.
+pub fn shader_kind(path: &Path) -> Option<shaderc::ShaderKind> {
+    match path.extension()?.to_str()? {
+        "vert" => Some(shaderc::ShaderKind::Vertex),
+        "frag" => Some(shaderc::ShaderKind::Fragment),
+        "comp" => Some(shaderc::ShaderKind::Compute),
+        "rgen" => Some(shaderc::ShaderKind::RayGeneration),
+        "rchit" => Some(shaderc::ShaderKind::ClosestHit),
+        "rmiss" => Some(shaderc::ShaderKind::Miss),
+        _ => None,
+    }
+}
+
+/// Where the SPIR-V of `source` goes: `bin/<name>.<stage>.spv` next to it, the name
+/// `shaders::Shaders::get` takes.
+pub fn spirv_path(source: &Path) -> PathBuf {
+    let name = format!(
+        "{}.{}.spv",
+        source.file_stem().unwrap().to_string_lossy(),
+        source.extension().unwrap().to_string_lossy()
+    );
+    source.parent().unwrap().join("bin").join(name)
+}
+
+/// Compiles every shader under `root` into the `bin` directory next to it, for build scripts.
+///
+/// Each shader file is one entry point, compiled on its own. Cargo reruns the build script when a
+/// shader or a file it includes changes. Every shader is compiled before failing, so all the
+/// errors are printed at once.
+pub fn build<P: AsRef<Path>>(root: P) -> Result<()> {
+    build_with_include_dirs(root, &[] as &[&str])
+}
+
+/// Like `build`, resolving includes in `include_dirs` too, e.g. the shared shaders of
+/// engine-core. A change to a file included from there reruns the build script as well.
+pub fn build_with_include_dirs<P, I>(root: P, include_dirs: &[I]) -> Result<()>
+where
+    P: AsRef<Path>,
+    I: AsRef<Path>,
+{
+    let root = root.as_ref();
+    let mut sources = Vec::new();
+    for path in glob::glob(&root.join("**").join("*").to_string_lossy())? {
+        let path = path?;
+        if shader_kind(&path).is_some() {
+            sources.push(path);
+        }
+    }
+    sources.sort();
+
+    let mut compiler = ShaderCompiler::new()?;
+    for dir in include_dirs {
+        compiler.add_include_dir(dir.as_ref());
+    }
+    let mut failed = 0;
+    for source in sources.iter() {
+        match compiler.compile(source) {
+            Ok(compiled) => {
+                for dependency in compiled.dependencies.iter() {
+                    println!("cargo:rerun-if-changed={}", dependency.display());
+                }
+                let spirv_path = spirv_path(source);
+                std::fs::create_dir_all(spirv_path.parent().unwrap())?;
+                std::fs::write(&spirv_path, compiled.spirv)
+                    .with_context(|| format!("Failed to write {}", spirv_path.display()))?;
+            }
+            Err(e) => {
+                // Still rerun once the broken shader is fixed.
+                println!("cargo:rerun-if-changed={}", source.display());
+                eprintln!("{}\n", e);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        bail!("{} of {} shaders failed to compile", failed, sources.len());
+    }
+    Ok(())
+}
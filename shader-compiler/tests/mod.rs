@@ -0,0 +1,87 @@
+use std::path::PathBuf;
+
+use shader_compiler::{ShaderCompiler, ShaderKind};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("silly-cat-engine-shader-compiler-{}", name));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_compile_with_includes() {
+    let dir = temp_dir("includes");
+    std::fs::write(dir.join("common.glsl"), "#include \"constants.glsl\"\n").unwrap();
+    std::fs::write(dir.join("constants.glsl"), "const uint SIZE = 8;\n").unwrap();
+    let source = dir.join("fill.comp");
+    std::fs::write(
+        &source,
+        "#version 460\n\
+         #extension GL_GOOGLE_include_directive : require\n\
+         #include \"common.glsl\"\n\
+         layout(local_size_x = SIZE) in;\n\
+         void main() {}\n",
+    )
+    .unwrap();
+
+    let compiled = ShaderCompiler::new().unwrap().compile(&source).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(&compiled.spirv[..4], &0x0723_0203u32.to_le_bytes());
+    assert_eq!(
+        compiled.dependencies,
+        vec![source, dir.join("common.glsl"), dir.join("constants.glsl")]
+    );
+}
+
+#[test]
+fn test_missing_include_names_the_file() {
+    let dir = temp_dir("missing-include");
+    let source = dir.join("broken.frag");
+    std::fs::write(
+        &source,
+        "#version 460\n\
+         #extension GL_GOOGLE_include_directive : require\n\
+         #include \"missing.glsl\"\n\
+         void main() {}\n",
+    )
+    .unwrap();
+
+    let error = ShaderCompiler::new().unwrap().compile(&source).unwrap_err();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(error.to_string().contains("missing.glsl"));
+}
+
+#[test]
+fn test_compile_source_with_defines() {
+    let text = "#version 460\n\
+                layout(local_size_x = SIZE) in;\n\
+                void main() {}\n";
+    let mut compiler = ShaderCompiler::new().unwrap();
+
+    let compiled = compiler
+        .compile_source(
+            text,
+            "fill.comp",
+            ShaderKind::Compute,
+            &[("SIZE", Some("8"))],
+        )
+        .unwrap();
+    assert_eq!(&compiled.spirv[..4], &0x0723_0203u32.to_le_bytes());
+    assert!(compiled.dependencies.is_empty());
+
+    let error = compiler
+        .compile_source(text, "fill.comp", ShaderKind::Compute, &[])
+        .unwrap_err();
+    assert!(error.to_string().contains("SIZE"));
+}
+
+#[test]
+fn test_spirv_path() {
+    let source = PathBuf::from("src/engine/shaders/raytrace.rgen");
+    assert_eq!(
+        shader_compiler::spirv_path(&source),
+        PathBuf::from("src/engine/shaders/bin/raytrace.rgen.spv")
+    );
+}